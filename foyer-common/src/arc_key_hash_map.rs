@@ -139,6 +139,16 @@ where
             .map(|entry| &entry.value)
     }
 
+    pub fn get_key_value_with_hash<Q>(&self, hash: u64, key: &Q) -> Option<(&Arc<K>, &V)>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.inner
+            .find(hash, |entry| entry.key.as_ref().borrow() == key)
+            .map(|entry| (&entry.key, &entry.value))
+    }
+
     pub fn remove_with_hash<Q>(&mut self, hash: u64, key: &Q) -> Option<V>
     where
         K: Borrow<Q>,
@@ -266,6 +276,15 @@ where
         self.raw.get_with_hash(hash, key)
     }
 
+    pub fn get_key_value<Q>(&self, key: &Q) -> Option<(&Arc<K>, &V)>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let hash = self.build_hasher.hash_one(key);
+        self.raw.get_key_value_with_hash(hash, key)
+    }
+
     pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
     where
         K: Borrow<Q>,
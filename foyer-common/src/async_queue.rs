@@ -102,6 +102,21 @@ impl<T: Debug> AsyncQueue<T> {
     }
 }
 
+impl<T: Debug + PartialEq> AsyncQueue<T> {
+    /// Remove the given item from the queue, return `true` if it was queued.
+    pub fn remove(&self, item: &T) -> bool {
+        let mut guard = self.queue.lock();
+        match guard.iter().position(|queued| queued == item) {
+            Some(index) => {
+                guard.remove(index);
+                self.watch_tx.send(guard.len()).unwrap();
+                true
+            }
+            None => false,
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::{
@@ -131,4 +146,17 @@ mod tests {
         assert_eq!(1, read_future1.await);
         assert_eq!(2, read_future2.await);
     }
+
+    #[tokio::test]
+    async fn test_remove() {
+        let queue = AsyncQueue::new();
+        queue.release(1);
+        queue.release(2);
+        queue.release(3);
+        assert!(queue.remove(&2));
+        assert!(!queue.remove(&2));
+        assert_eq!(queue.len(), 2);
+        assert_eq!(1, queue.acquire().await);
+        assert_eq!(3, queue.acquire().await);
+    }
 }
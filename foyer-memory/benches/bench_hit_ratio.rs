@@ -118,6 +118,8 @@ fn new_s3fifo_cache(capacity: usize) -> Cache<CacheKey, CacheValue> {
         .with_shards(SHARDS)
         .with_eviction_config(S3FifoConfig {
             small_queue_capacity_ratio: 0.1,
+            ghost_queue_capacity_ratio: 0.9,
+            promotion_threshold: 1,
         })
         .with_object_pool_capacity(OBJECT_POOL_CAPACITY)
         .build()
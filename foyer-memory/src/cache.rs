@@ -18,6 +18,7 @@ use std::{
     hash::{BuildHasher, Hash},
     ops::Deref,
     sync::Arc,
+    time::Duration,
 };
 
 use ahash::RandomState;
@@ -27,14 +28,17 @@ use tokio::sync::oneshot;
 use foyer_common::code::{Key, Value};
 
 use crate::{
-    context::CacheContext,
+    context::{CacheContext, CachePriority},
     eviction::{
         fifo::{Fifo, FifoHandle},
         lfu::{Lfu, LfuHandle},
         lru::{Lru, LruHandle},
         s3fifo::{S3Fifo, S3FifoHandle},
     },
-    generic::{GenericCache, GenericCacheConfig, GenericCacheEntry, GenericEntry, Weighter},
+    generic::{
+        CacheFull, GenericCache, GenericCacheConfig, GenericCacheEntry, GenericEntry, InsertMode, ReleaseBatchConfig,
+        ShouldEvict, Weighter,
+    },
     indexer::ArcKeyHashMapIndexer,
     listener::{CacheEventListener, DefaultCacheEventListener},
     metrics::Metrics,
@@ -217,6 +221,15 @@ where
             CacheEntry::S3Fifo(entry) => entry.refs(),
         }
     }
+
+    pub fn is_stale(&self) -> bool {
+        match self {
+            CacheEntry::Fifo(entry) => entry.is_stale(),
+            CacheEntry::Lru(entry) => entry.is_stale(),
+            CacheEntry::Lfu(entry) => entry.is_stale(),
+            CacheEntry::S3Fifo(entry) => entry.is_stale(),
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -265,6 +278,11 @@ where
     event_listener: L,
     hash_builder: S,
     weighter: Arc<dyn Weighter<K, V>>,
+    should_evict: Option<Arc<dyn ShouldEvict<K, V>>>,
+    insert_mode: InsertMode,
+    release_batch: Option<ReleaseBatchConfig>,
+    ttl_jitter: f64,
+    serve_stale_on_error: Option<Duration>,
 }
 
 impl<K, V> CacheBuilder<K, V, DefaultCacheEventListener<K, V>, RandomState>
@@ -287,6 +305,11 @@ where
             event_listener: DefaultCacheEventListener::default(),
             hash_builder: RandomState::default(),
             weighter: Arc::new(|_, _| 1),
+            should_evict: None,
+            insert_mode: InsertMode::default(),
+            release_batch: None,
+            ttl_jitter: 0.0,
+            serve_stale_on_error: None,
         }
     }
 }
@@ -336,6 +359,11 @@ where
             event_listener,
             hash_builder: self.hash_builder,
             weighter: self.weighter,
+            should_evict: self.should_evict,
+            insert_mode: self.insert_mode,
+            release_batch: self.release_batch,
+            ttl_jitter: self.ttl_jitter,
+            serve_stale_on_error: self.serve_stale_on_error,
         }
     }
 
@@ -352,6 +380,11 @@ where
             event_listener: self.event_listener,
             hash_builder,
             weighter: self.weighter,
+            should_evict: self.should_evict,
+            insert_mode: self.insert_mode,
+            release_batch: self.release_batch,
+            ttl_jitter: self.ttl_jitter,
+            serve_stale_on_error: self.serve_stale_on_error,
         }
     }
 
@@ -361,6 +394,57 @@ where
         self
     }
 
+    /// Set in-memory cache eviction veto.
+    ///
+    /// If `should_evict` returns `false` for an eviction candidate, the candidate is skipped and the next one is
+    /// evicted. If all candidates are vetoed, they will be evicted anyway to release space.
+    pub fn with_should_evict(mut self, should_evict: impl ShouldEvict<K, V>) -> Self {
+        self.should_evict = Some(Arc::new(should_evict));
+        self
+    }
+
+    /// Set in-memory cache insert mode.
+    ///
+    /// With [`InsertMode::NoEvict`], inserting a new key into a full cache is rejected instead of evicting other
+    /// entries. The default value is [`InsertMode::Evict`].
+    pub fn with_insert_mode(mut self, insert_mode: InsertMode) -> Self {
+        self.insert_mode = insert_mode;
+        self
+    }
+
+    /// Deliver the released entries to the event listener in batches of `count` entries, or after `interval` since the
+    /// batch is started, whichever comes first.
+    ///
+    /// See [`CacheEventListener::on_release_batch`].
+    pub fn with_release_batch(mut self, count: usize, interval: Duration) -> Self {
+        self.release_batch = Some(ReleaseBatchConfig { count, interval });
+        self
+    }
+
+    /// Spread the expiry of the entries inserted with the same ttl over `[ttl, ttl * (1 + ttl_jitter)]`, to avoid the
+    /// miss storm when the entries inserted at once expire at once.
+    ///
+    /// The jitter of an entry is derived from its key hash, so the expiry of a key is deterministic.
+    ///
+    /// The default value is `0.0`, which disables the jitter.
+    pub fn with_ttl_jitter(mut self, ttl_jitter: f64) -> Self {
+        assert!(
+            (0.0..=1.0).contains(&ttl_jitter),
+            "ttl jitter must be in [0, 1], given: {ttl_jitter}"
+        );
+        self.ttl_jitter = ttl_jitter;
+        self
+    }
+
+    /// Serve an expired entry if refetching it with [`Cache::entry`] fails, until it has been expired for
+    /// `max_staleness`. Then the entry is removed and the fetch error is returned.
+    ///
+    /// The served entry is flagged as stale, see [`CacheEntry::is_stale`].
+    pub fn with_serve_stale_on_error(mut self, max_staleness: Duration) -> Self {
+        self.serve_stale_on_error = Some(max_staleness);
+        self
+    }
+
     /// Build in-memory cache with the given configuration.
     pub fn build(self) -> Cache<K, V, L, S> {
         match self.eviction_config {
@@ -372,6 +456,11 @@ where
                 hash_builder: self.hash_builder,
                 event_listener: self.event_listener,
                 weighter: self.weighter,
+                should_evict: self.should_evict,
+                insert_mode: self.insert_mode,
+                release_batch: self.release_batch,
+                ttl_jitter: self.ttl_jitter,
+                serve_stale_on_error: self.serve_stale_on_error,
             }))),
             EvictionConfig::Lru(eviction_config) => Cache::Lru(Arc::new(GenericCache::new(GenericCacheConfig {
                 capacity: self.capacity,
@@ -381,6 +470,11 @@ where
                 hash_builder: self.hash_builder,
                 event_listener: self.event_listener,
                 weighter: self.weighter,
+                should_evict: self.should_evict,
+                insert_mode: self.insert_mode,
+                release_batch: self.release_batch,
+                ttl_jitter: self.ttl_jitter,
+                serve_stale_on_error: self.serve_stale_on_error,
             }))),
             EvictionConfig::Lfu(eviction_config) => Cache::Lfu(Arc::new(GenericCache::new(GenericCacheConfig {
                 capacity: self.capacity,
@@ -390,6 +484,11 @@ where
                 hash_builder: self.hash_builder,
                 event_listener: self.event_listener,
                 weighter: self.weighter,
+                should_evict: self.should_evict,
+                insert_mode: self.insert_mode,
+                release_batch: self.release_batch,
+                ttl_jitter: self.ttl_jitter,
+                serve_stale_on_error: self.serve_stale_on_error,
             }))),
             EvictionConfig::S3Fifo(eviction_config) => Cache::S3Fifo(Arc::new(GenericCache::new(GenericCacheConfig {
                 capacity: self.capacity,
@@ -399,6 +498,11 @@ where
                 hash_builder: self.hash_builder,
                 event_listener: self.event_listener,
                 weighter: self.weighter,
+                should_evict: self.should_evict,
+                insert_mode: self.insert_mode,
+                release_batch: self.release_batch,
+                ttl_jitter: self.ttl_jitter,
+                serve_stale_on_error: self.serve_stale_on_error,
             }))),
         }
     }
@@ -484,6 +588,87 @@ where
         }
     }
 
+    /// Insert a new entry into the cache with the given priority.
+    ///
+    /// See [`CachePriority`] for how the priority affects the eviction.
+    pub fn insert_with_priority<AK, AV>(&self, key: AK, value: AV, priority: CachePriority) -> CacheEntry<K, V, L, S>
+    where
+        AK: Into<Arc<K>> + Send + 'static,
+        AV: Into<Arc<V>> + Send + 'static,
+    {
+        match self {
+            Cache::Fifo(cache) => cache.insert_with_priority(key, value, priority).into(),
+            Cache::Lru(cache) => cache.insert_with_priority(key, value, priority).into(),
+            Cache::Lfu(cache) => cache.insert_with_priority(key, value, priority).into(),
+            Cache::S3Fifo(cache) => cache.insert_with_priority(key, value, priority).into(),
+        }
+    }
+
+    /// Insert a new entry into the cache, which expires after `ttl`.
+    ///
+    /// An expired entry is treated as a miss and removed from the cache lazily on get.
+    pub fn insert_with_ttl<AK, AV>(&self, key: AK, value: AV, ttl: Duration) -> CacheEntry<K, V, L, S>
+    where
+        AK: Into<Arc<K>> + Send + 'static,
+        AV: Into<Arc<V>> + Send + 'static,
+    {
+        match self {
+            Cache::Fifo(cache) => cache.insert_with_ttl(key, value, ttl).into(),
+            Cache::Lru(cache) => cache.insert_with_ttl(key, value, ttl).into(),
+            Cache::Lfu(cache) => cache.insert_with_ttl(key, value, ttl).into(),
+            Cache::S3Fifo(cache) => cache.insert_with_ttl(key, value, ttl).into(),
+        }
+    }
+
+    /// Insert a new entry into the cache.
+    ///
+    /// Return [`CacheFull`] if the entry is rejected in [`InsertMode::NoEvict`] mode.
+    pub fn try_insert<AK, AV>(&self, key: AK, value: AV) -> std::result::Result<CacheEntry<K, V, L, S>, CacheFull>
+    where
+        AK: Into<Arc<K>> + Send + 'static,
+        AV: Into<Arc<V>> + Send + 'static,
+    {
+        let entry = match self {
+            Cache::Fifo(cache) => cache.try_insert(key, value)?.into(),
+            Cache::Lru(cache) => cache.try_insert(key, value)?.into(),
+            Cache::Lfu(cache) => cache.try_insert(key, value)?.into(),
+            Cache::S3Fifo(cache) => cache.try_insert(key, value)?.into(),
+        };
+        Ok(entry)
+    }
+
+    /// Create an entry that is not cached, e.g. to hand out a value without evicting the cached entries for it.
+    ///
+    /// See [`GenericCache::deposit`] for details.
+    pub fn deposit<AK, AV>(&self, key: AK, value: AV) -> CacheEntry<K, V, L, S>
+    where
+        AK: Into<Arc<K>> + Send + 'static,
+        AV: Into<Arc<V>> + Send + 'static,
+    {
+        match self {
+            Cache::Fifo(cache) => cache.deposit(key, value).into(),
+            Cache::Lru(cache) => cache.deposit(key, value).into(),
+            Cache::Lfu(cache) => cache.deposit(key, value).into(),
+            Cache::S3Fifo(cache) => cache.deposit(key, value).into(),
+        }
+    }
+
+    /// Insert a new entry into the cache, and return the entry evicted by the eviction algorithm to make room for it.
+    ///
+    /// See [`GenericCache::insert_and_get_evicted`] for details.
+    pub fn insert_and_get_evicted<AK, AV>(&self, key: AK, value: AV) -> Option<(Arc<K>, Arc<V>)>
+    where
+        AK: Into<Arc<K>> + Send + 'static,
+        AV: Into<Arc<V>> + Send + 'static,
+    {
+        match self {
+            Cache::Fifo(cache) => cache.insert_and_get_evicted(key, value),
+            Cache::Lru(cache) => cache.insert_and_get_evicted(key, value),
+            Cache::Lfu(cache) => cache.insert_and_get_evicted(key, value),
+            Cache::S3Fifo(cache) => cache.insert_and_get_evicted(key, value),
+        }
+    }
+
     pub fn remove<Q>(&self, key: &Q) -> Option<CacheEntry<K, V, L, S>>
     where
         K: Borrow<Q>,
@@ -589,6 +774,16 @@ where
             Cache::S3Fifo(cache) => cache.metrics(),
         }
     }
+
+    /// Deliver the pending batch of released entries to the event listener if release batching is enabled.
+    pub fn flush_release_batch(&self) {
+        match self {
+            Cache::Fifo(cache) => cache.flush_release_batch(),
+            Cache::Lru(cache) => cache.flush_release_batch(),
+            Cache::Lfu(cache) => cache.flush_release_batch(),
+            Cache::S3Fifo(cache) => cache.flush_release_batch(),
+        }
+    }
 }
 
 pub enum Entry<K, V, ER, L = DefaultCacheEventListener<K, V>, S = RandomState>
@@ -786,11 +981,46 @@ mod tests {
             .with_shards(SHARDS)
             .with_eviction_config(S3FifoConfig {
                 small_queue_capacity_ratio: 0.1,
+                ghost_queue_capacity_ratio: 0.9,
+                promotion_threshold: 1,
             })
             .with_object_pool_capacity(OBJECT_POOL_CAPACITY)
             .build()
     }
 
+    fn s3fifo_hit_ratio(config: S3FifoConfig) -> f64 {
+        const KEYS: usize = 10000;
+        const CAPACITY: usize = KEYS / 20;
+        const OPS: usize = 100000;
+
+        let cache: Cache<u64, u64> = CacheBuilder::new(CAPACITY)
+            .with_shards(1)
+            .with_eviction_config(config)
+            .with_object_pool_capacity(OBJECT_POOL_CAPACITY)
+            .build();
+
+        // Draw the keys from a zipf distribution with exponent 0.9 by searching its cumulative weights.
+        let weights = (1..=KEYS)
+            .scan(0.0, |sum, k| {
+                *sum += 1.0 / (k as f64).powf(0.9);
+                Some(*sum)
+            })
+            .collect_vec();
+        let mut rng = StdRng::seed_from_u64(42);
+
+        let mut hits = 0;
+        for _ in 0..OPS {
+            let x = rng.gen::<f64>() * weights[KEYS - 1];
+            let key = weights.partition_point(|&sum| sum < x) as u64;
+            if cache.get(&key).is_some() {
+                hits += 1;
+            } else {
+                cache.insert(key, key);
+            }
+        }
+        hits as f64 / OPS as f64
+    }
+
     fn init_cache(cache: &Cache<u64, u64>, rng: &mut StdRng) {
         let mut v = RANGE.collect_vec();
         v.shuffle(rng);
@@ -851,6 +1081,59 @@ mod tests {
         join_all(handles).await;
     }
 
+    fn priority_case(cache: Cache<u64, u64>) {
+        const KEYS: u64 = CAPACITY as u64 * 4;
+
+        // Insert interleaved low and high priority entries under eviction pressure.
+        for key in 0..KEYS {
+            let priority = if key % 2 == 0 {
+                CachePriority::Low
+            } else {
+                CachePriority::High
+            };
+            cache.insert_with_priority(key, key, priority);
+        }
+
+        let (high, low): (Vec<_>, Vec<_>) = (0..KEYS)
+            .filter(|key| cache.get(key).is_some())
+            .partition(|key| key % 2 == 1);
+        assert!(high.len() > low.len(), "high: {}, low: {}", high.len(), low.len());
+    }
+
+    #[test]
+    fn test_priority() {
+        priority_case(fifo());
+        priority_case(lru());
+        priority_case(lfu());
+        priority_case(s3fifo());
+    }
+
+    #[test]
+    fn test_s3fifo_hit_ratio() {
+        let hit_ratio = |small_queue_capacity_ratio, ghost_queue_capacity_ratio, promotion_threshold| {
+            s3fifo_hit_ratio(S3FifoConfig {
+                small_queue_capacity_ratio,
+                ghost_queue_capacity_ratio,
+                promotion_threshold,
+            })
+        };
+
+        let default = s3fifo_hit_ratio(S3FifoConfig::default());
+        let large_small_queue = hit_ratio(0.9, 0.9, 1);
+        let no_ghost = hit_ratio(0.1, 0.0, 1);
+        let no_ghost_no_promotion = hit_ratio(0.1, 0.0, 3);
+
+        // A small `small` queue filters out the one-hit wonders of the skewed workload.
+        assert!(default > large_small_queue, "{default} <= {large_small_queue}");
+        // The `ghost` queue readmits the evicted hot entries into the `main` queue.
+        assert!(default > no_ghost, "{default} <= {no_ghost}");
+        // Promoting the frequently accessed entries protects them from the following one-hit wonders.
+        assert!(
+            no_ghost > no_ghost_no_promotion,
+            "{no_ghost} <= {no_ghost_no_promotion}"
+        );
+    }
+
     #[tokio::test]
     async fn test_fifo_cache() {
         case(fifo()).await
@@ -25,6 +25,21 @@ impl Default for CacheContext {
     }
 }
 
+/// Priority of a cache entry, independent from the eviction algorithm.
+///
+/// High priority entries survive eviction pressure longer than normal ones, and low priority entries are evicted
+/// earlier than normal ones.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum CachePriority {
+    /// The entry is not promoted by the eviction algorithm on access.
+    Low,
+    /// The default priority, the entry is handled by the eviction algorithm as is.
+    #[default]
+    Normal,
+    /// The entry is given a second chance when it is picked to be evicted, the chance is renewed on access.
+    High,
+}
+
 /// The overhead of `Context` itself and the conversion should be light.
 pub trait Context: From<CacheContext> + Into<CacheContext> + Send + Sync + 'static + Clone {}
 
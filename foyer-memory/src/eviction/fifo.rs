@@ -123,6 +123,11 @@ where
         })
     }
 
+    unsafe fn reinsert(&mut self, mut ptr: NonNull<Self::Handle>) {
+        self.queue.push_front(ptr);
+        ptr.as_mut().base_mut().set_in_eviction(true);
+    }
+
     unsafe fn release(&mut self, _: NonNull<Self::Handle>) {}
 
     unsafe fn acquire(&mut self, _: NonNull<Self::Handle>) {}
@@ -75,6 +75,8 @@ where
     link: DlistLink,
     base: BaseHandle<T, LfuContext>,
     queue: Queue,
+    /// The queue the handle is popped from, for reinsertion.
+    popped_from: Queue,
 }
 
 impl<T> Debug for LfuHandle<T>
@@ -97,6 +99,7 @@ where
             link: DlistLink::default(),
             base: BaseHandle::new(),
             queue: Queue::None,
+            popped_from: Queue::None,
         }
     }
 }
@@ -290,12 +293,31 @@ where
         debug_assert_ne!(handle.queue, Queue::None);
 
         self.decrease_queue_weight(handle);
-        handle.queue = Queue::None;
+        handle.popped_from = std::mem::replace(&mut handle.queue, Queue::None);
         handle.base_mut().set_in_eviction(false);
 
         Some(ptr)
     }
 
+    unsafe fn reinsert(&mut self, mut ptr: NonNull<Self::Handle>) {
+        let handle = ptr.as_mut();
+
+        debug_assert!(!handle.link.is_linked());
+        debug_assert!(!handle.base().is_in_eviction());
+        debug_assert_eq!(handle.queue, Queue::None);
+
+        match handle.popped_from {
+            Queue::None => unreachable!(),
+            Queue::Window => self.window.push_front(ptr),
+            Queue::Probation => self.probation.push_front(ptr),
+            Queue::Protected => self.protected.push_front(ptr),
+        }
+        handle.queue = std::mem::replace(&mut handle.popped_from, Queue::None);
+        handle.base_mut().set_in_eviction(true);
+
+        self.increase_queue_weight(handle);
+    }
+
     unsafe fn release(&mut self, mut ptr: NonNull<Self::Handle>) {
         let handle = ptr.as_mut();
 
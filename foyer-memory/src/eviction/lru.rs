@@ -207,6 +207,22 @@ where
         Some(ptr)
     }
 
+    unsafe fn reinsert(&mut self, mut ptr: NonNull<Self::Handle>) {
+        let handle = ptr.as_mut();
+
+        debug_assert!(!handle.link.is_linked());
+
+        // `in_high_priority_pool` is kept by `pop`.
+        if handle.in_high_priority_pool {
+            self.high_priority_weight += handle.base().weight();
+            self.high_priority_list.push_front(ptr);
+        } else {
+            self.list.push_front(ptr);
+        }
+
+        handle.base_mut().set_in_eviction(true);
+    }
+
     unsafe fn acquire(&mut self, _: NonNull<Self::Handle>) {}
 
     unsafe fn release(&mut self, mut ptr: NonNull<Self::Handle>) {
@@ -49,6 +49,17 @@ pub trait Eviction: Send + Sync + 'static {
     /// The base handle associated to the `ptr` must be set NOT in cache.
     unsafe fn pop(&mut self) -> Option<NonNull<Self::Handle>>;
 
+    /// Push a handle `ptr` popped by `pop` back to the position it is popped from, e.g. if its eviction is vetoed.
+    ///
+    /// The handles popped in a row must be reinserted in the reverse order to keep their relative order.
+    ///
+    /// # Safety
+    ///
+    /// The `ptr` must be popped from the eviction container by `pop`, and not pushed back yet.
+    ///
+    /// The base handle associated to the `ptr` must be set in cache.
+    unsafe fn reinsert(&mut self, ptr: NonNull<Self::Handle>);
+
     /// Notify the eviciton container that the `ptr` is acquired by **AN** external user.
     ///
     /// # Safety
@@ -12,12 +12,13 @@
 //  See the License for the specific language governing permissions and
 //  limitations under the License.
 
-use std::{fmt::Debug, ptr::NonNull};
+use std::{collections::VecDeque, fmt::Debug, ptr::NonNull};
 
 use foyer_intrusive::{
     dlist::{Dlist, DlistLink},
     intrusive_adapter,
 };
+use hashbrown::HashSet;
 
 use crate::{
     eviction::Eviction,
@@ -40,6 +41,7 @@ impl From<S3FifoContext> for CacheContext {
     }
 }
 
+#[derive(Debug, PartialEq, Eq)]
 enum Queue {
     None,
     Main,
@@ -54,6 +56,8 @@ where
     base: BaseHandle<T, S3FifoContext>,
     freq: u8,
     queue: Queue,
+    /// The queue the handle is popped from, for reinsertion.
+    popped_from: Queue,
 }
 
 impl<T> Debug for S3FifoHandle<T>
@@ -73,7 +77,7 @@ where
 {
     #[inline(always)]
     pub fn inc(&mut self) {
-        self.freq = std::cmp::min(self.freq + 1, 3);
+        self.freq = std::cmp::min(self.freq + 1, MAX_FREQ);
     }
 
     #[inline(always)]
@@ -97,6 +101,7 @@ where
             freq: 0,
             base: BaseHandle::new(),
             queue: Queue::None,
+            popped_from: Queue::None,
         }
     }
 }
@@ -117,9 +122,95 @@ where
     }
 }
 
+/// Max access frequency recorded by a handle.
+const MAX_FREQ: u8 = 3;
+
 #[derive(Debug, Clone)]
 pub struct S3FifoConfig {
+    /// `small` queue capacity ratio of the total cache capacity.
+    ///
+    /// New entries are inserted into the `small` queue, which filters out the one-hit wonders.
+    ///
+    /// Must be in (0, 1). Default: `0.1`.
     pub small_queue_capacity_ratio: f64,
+    /// `ghost` queue capacity ratio of the total cache capacity.
+    ///
+    /// The `ghost` queue records the key hashes of the entries evicted from the `small` queue. A missed entry that is
+    /// found in the `ghost` queue is inserted into the `main` queue directly.
+    ///
+    /// `0` means the `ghost` queue is disabled. Default: `0.9`.
+    pub ghost_queue_capacity_ratio: f64,
+    /// An entry in the `small` queue is promoted to the `main` queue on eviction if its access frequency is larger
+    /// than the threshold.
+    ///
+    /// The access frequency is capped at 3, so the threshold must be in [0, 3], `3` means never promote.
+    /// Default: `1`.
+    pub promotion_threshold: u8,
+}
+
+impl Default for S3FifoConfig {
+    fn default() -> Self {
+        Self {
+            small_queue_capacity_ratio: 0.1,
+            ghost_queue_capacity_ratio: 0.9,
+            promotion_threshold: 1,
+        }
+    }
+}
+
+/// FIFO queue of the key hashes evicted from the `small` queue, bounded by the total weight.
+#[derive(Debug, Default)]
+struct GhostQueue {
+    queue: VecDeque<(u64, usize)>,
+    hashes: HashSet<u64>,
+    capacity: usize,
+    weight: usize,
+}
+
+impl GhostQueue {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            ..Default::default()
+        }
+    }
+
+    fn push(&mut self, hash: u64, weight: usize) {
+        if self.capacity == 0 || self.hashes.contains(&hash) {
+            return;
+        }
+        while self.weight + weight > self.capacity {
+            let Some((hash, weight)) = self.queue.pop_front() else {
+                return;
+            };
+            self.hashes.remove(&hash);
+            self.weight -= weight;
+        }
+        self.queue.push_back((hash, weight));
+        self.hashes.insert(hash);
+        self.weight += weight;
+    }
+
+    /// Remove the latest record of `hash`, if any.
+    fn remove(&mut self, hash: u64) {
+        if !self.hashes.remove(&hash) {
+            return;
+        }
+        if let Some(index) = self.queue.iter().rposition(|(h, _)| *h == hash) {
+            let (_, weight) = self.queue.remove(index).unwrap();
+            self.weight -= weight;
+        }
+    }
+
+    fn contains(&self, hash: u64) -> bool {
+        self.hashes.contains(&hash)
+    }
+
+    fn clear(&mut self) {
+        self.queue.clear();
+        self.hashes.clear();
+        self.weight = 0;
+    }
 }
 
 pub struct S3Fifo<T>
@@ -128,8 +219,10 @@ where
 {
     small_queue: Dlist<S3FifoHandleDlistAdapter<T>>,
     main_queue: Dlist<S3FifoHandleDlistAdapter<T>>,
+    ghost_queue: GhostQueue,
 
     small_capacity: usize,
+    promotion_threshold: u8,
 
     small_weight: usize,
     main_weight: usize,
@@ -152,15 +245,17 @@ where
     unsafe fn evict_small(&mut self) -> Option<NonNull<S3FifoHandle<T>>> {
         while let Some(mut ptr) = self.small_queue.pop_front() {
             let handle = ptr.as_mut();
-            if handle.freq > 1 {
+            if handle.freq > self.promotion_threshold {
                 self.main_queue.push_back(ptr);
                 handle.queue = Queue::Main;
                 self.small_weight -= handle.base().weight();
                 self.main_weight += handle.base().weight();
             } else {
                 handle.queue = Queue::None;
+                handle.popped_from = Queue::Small;
                 handle.reset();
                 self.small_weight -= handle.base().weight();
+                self.ghost_queue.push(handle.base().hash(), handle.base().weight());
                 return Some(ptr);
             }
         }
@@ -175,6 +270,7 @@ where
                 handle.dec();
             } else {
                 handle.queue = Queue::None;
+                handle.popped_from = Queue::Main;
                 self.main_weight -= handle.base.weight();
                 return Some(ptr);
             }
@@ -194,11 +290,21 @@ where
     where
         Self: Sized,
     {
+        assert!(
+            config.promotion_threshold <= MAX_FREQ,
+            "promotion threshold must be in [0, {}], given: {}",
+            MAX_FREQ,
+            config.promotion_threshold
+        );
+
         let small_capacity = (capacity as f64 * config.small_queue_capacity_ratio) as usize;
+        let ghost_capacity = (capacity as f64 * config.ghost_queue_capacity_ratio) as usize;
         Self {
             small_queue: Dlist::new(),
             main_queue: Dlist::new(),
+            ghost_queue: GhostQueue::new(ghost_capacity),
             small_capacity,
+            promotion_threshold: config.promotion_threshold,
             small_weight: 0,
             main_weight: 0,
         }
@@ -207,9 +313,16 @@ where
     unsafe fn push(&mut self, mut ptr: NonNull<Self::Handle>) {
         let handle = ptr.as_mut();
 
-        self.small_queue.push_back(ptr);
-        handle.queue = Queue::Small;
-        self.small_weight += handle.base().weight();
+        // Entries recently evicted from the `small` queue are inserted into the `main` queue directly.
+        if self.ghost_queue.contains(handle.base().hash()) {
+            self.main_queue.push_back(ptr);
+            handle.queue = Queue::Main;
+            self.main_weight += handle.base().weight();
+        } else {
+            self.small_queue.push_back(ptr);
+            handle.queue = Queue::Small;
+            self.small_weight += handle.base().weight();
+        }
 
         handle.base_mut().set_in_eviction(true);
     }
@@ -226,6 +339,30 @@ where
         }
     }
 
+    /// The access frequency of a handle popped from the `small` queue is not restored, which is under the promotion
+    /// threshold anyway.
+    unsafe fn reinsert(&mut self, mut ptr: NonNull<Self::Handle>) {
+        let handle = ptr.as_mut();
+
+        match std::mem::replace(&mut handle.popped_from, Queue::None) {
+            Queue::None => unreachable!(),
+            Queue::Main => {
+                self.main_queue.push_front(ptr);
+                handle.queue = Queue::Main;
+                self.main_weight += handle.base().weight();
+            }
+            Queue::Small => {
+                // The handle is not evicted, forget it in the ghost queue.
+                self.ghost_queue.remove(handle.base().hash());
+                self.small_queue.push_front(ptr);
+                handle.queue = Queue::Small;
+                self.small_weight += handle.base().weight();
+            }
+        }
+
+        handle.base_mut().set_in_eviction(true);
+    }
+
     unsafe fn release(&mut self, _: NonNull<Self::Handle>) {}
 
     unsafe fn acquire(&mut self, ptr: NonNull<Self::Handle>) {
@@ -281,6 +418,7 @@ where
             handle.queue = Queue::None;
             res.push(ptr);
         }
+        self.ghost_queue.clear();
         res
     }
 
@@ -349,6 +487,8 @@ mod tests {
             // window: 2, probation: 2, protected: 6
             let config = S3FifoConfig {
                 small_queue_capacity_ratio: 0.25,
+                ghost_queue_capacity_ratio: 0.0,
+                promotion_threshold: 1,
             };
             let mut s3fifo = TestS3Fifo::new(8, &config);
 
@@ -392,4 +532,97 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn test_s3fifo_ghost() {
+        unsafe {
+            let ptrs = (0..100)
+                .map(|i| {
+                    let mut handle = Box::<TestS3FifoHandle>::default();
+                    handle.init(i, i, 1, S3FifoContext);
+                    NonNull::new_unchecked(Box::into_raw(handle))
+                })
+                .collect_vec();
+
+            // small: 2, ghost: 2
+            let config = S3FifoConfig {
+                small_queue_capacity_ratio: 0.25,
+                ghost_queue_capacity_ratio: 0.25,
+                promotion_threshold: 1,
+            };
+            let mut s3fifo = TestS3Fifo::new(8, &config);
+
+            (0..4).for_each(|i| s3fifo.push(ptrs[i]));
+            assert_test_s3fifo(&s3fifo, vec![0, 1, 2, 3], vec![]);
+
+            // Entries evicted from the small queue are recorded by the ghost queue.
+            assert_eq!(s3fifo.pop().unwrap(), ptrs[0]);
+            assert_eq!(s3fifo.pop().unwrap(), ptrs[1]);
+            assert!(s3fifo.ghost_queue.contains(0));
+            assert!(s3fifo.ghost_queue.contains(1));
+
+            // Entries found in the ghost queue are inserted into the main queue directly.
+            s3fifo.push(ptrs[0]);
+            s3fifo.push(ptrs[4]);
+            assert_test_s3fifo(&s3fifo, vec![2, 3, 4], vec![0]);
+
+            // The ghost queue is bounded by its capacity.
+            assert_eq!(s3fifo.pop().unwrap(), ptrs[2]);
+            assert!(!s3fifo.ghost_queue.contains(0));
+            assert!(s3fifo.ghost_queue.contains(1));
+            assert!(s3fifo.ghost_queue.contains(2));
+
+            s3fifo.clear();
+
+            for ptr in ptrs {
+                let _ = Box::from_raw(ptr.as_ptr());
+            }
+        }
+    }
+
+    #[test]
+    fn test_s3fifo_reinsert() {
+        unsafe {
+            let ptrs = (0..100)
+                .map(|i| {
+                    let mut handle = Box::<TestS3FifoHandle>::default();
+                    handle.init(i, i, 1, S3FifoContext);
+                    NonNull::new_unchecked(Box::into_raw(handle))
+                })
+                .collect_vec();
+
+            // small: 2, ghost: 2
+            let config = S3FifoConfig {
+                small_queue_capacity_ratio: 0.25,
+                ghost_queue_capacity_ratio: 0.25,
+                promotion_threshold: 1,
+            };
+            let mut s3fifo = TestS3Fifo::new(8, &config);
+
+            (0..4).for_each(|i| s3fifo.push(ptrs[i]));
+            assert_eq!(s3fifo.pop().unwrap(), ptrs[0]);
+            s3fifo.push(ptrs[0]);
+            assert_test_s3fifo(&s3fifo, vec![1, 2, 3], vec![0]);
+
+            let p1 = s3fifo.pop().unwrap();
+            let p0 = s3fifo.pop().unwrap();
+            assert_eq!((p1, p0), (ptrs[1], ptrs[0]));
+            assert_eq!(p1.as_ref().popped_from, Queue::Small);
+            assert_eq!(p0.as_ref().popped_from, Queue::Main);
+            assert!(s3fifo.ghost_queue.contains(1));
+
+            // The handles are put back to the head of the queues they are popped from, and the handle from the small
+            // queue is forgotten by the ghost queue.
+            s3fifo.reinsert(p0);
+            s3fifo.reinsert(p1);
+            assert!(!s3fifo.ghost_queue.contains(1));
+            assert_test_s3fifo(&s3fifo, vec![1, 2, 3], vec![0]);
+
+            s3fifo.clear();
+
+            for ptr in ptrs {
+                let _ = Box::from_raw(ptr.as_ptr());
+            }
+        }
+    }
 }
@@ -14,6 +14,7 @@
 
 use std::{
     borrow::Borrow,
+    collections::VecDeque,
     future::Future,
     hash::{BuildHasher, Hash},
     ops::Deref,
@@ -22,6 +23,7 @@ use std::{
         atomic::{AtomicUsize, Ordering},
         Arc,
     },
+    time::{Duration, Instant},
 };
 
 use ahash::RandomState;
@@ -39,15 +41,61 @@ use crate::{
     eviction::Eviction,
     handle::{Handle, HandleExt, KeyedHandle},
     indexer::Indexer,
-    listener::CacheEventListener,
+    listener::{CacheEventListener, ReleaseReason, ReleasedEntry},
     metrics::Metrics,
-    CacheContext, DefaultCacheEventListener,
+    CacheContext, CachePriority, DefaultCacheEventListener,
 };
 
 // TODO(MrCroxx): Use `trait_alias` after stable.
 pub trait Weighter<K, V>: Fn(&K, &V) -> usize + Send + Sync + 'static {}
 impl<K, V, T> Weighter<K, V> for T where T: Fn(&K, &V) -> usize + Send + Sync + 'static {}
 
+// TODO(MrCroxx): Use `trait_alias` after stable.
+/// Return `false` to veto the eviction of the given entry.
+pub trait ShouldEvict<K, V>: Fn(&K, &V) -> bool + Send + Sync + 'static {}
+impl<K, V, T> ShouldEvict<K, V> for T where T: Fn(&K, &V) -> bool + Send + Sync + 'static {}
+
+/// Behavior of inserting an entry into a full cache.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum InsertMode {
+    /// Evict entries by the eviction algorithm to make room for the new entry.
+    #[default]
+    Evict,
+    /// Reject the new entry if inserting it requires eviction. Updates of the existing keys are not rejected and never
+    /// evict other entries.
+    ///
+    /// The rejected entry is returned as a handle that is not cached, and released after all external references are
+    /// dropped.
+    NoEvict,
+}
+
+/// Error returned by [`GenericCache::try_insert`] if the entry is rejected in [`InsertMode::NoEvict`] mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CacheFull;
+
+impl std::fmt::Display for CacheFull {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "cache is full, the new entry is rejected")
+    }
+}
+
+impl std::error::Error for CacheFull {}
+
+/// Deliver the released entries to [`CacheEventListener::on_release_batch`] in batches instead of one by one.
+///
+/// A batch is delivered once it holds `count` entries, or on the next release after `interval` has elapsed since the
+/// batch was started.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReleaseBatchConfig {
+    pub count: usize,
+    pub interval: Duration,
+}
+
+struct ReleaseBatch<K, V> {
+    entries: Vec<ReleasedEntry<K, V>>,
+    started: Instant,
+}
+
 struct CacheSharedState<T, L> {
     metrics: Metrics,
     /// The object pool to avoid frequent handle allocating, shared by all shards.
@@ -75,6 +123,12 @@ where
 
     waiters: HashMap<Arc<K>, Vec<oneshot::Sender<GenericCacheEntry<K, V, E, I, L, S>>>>,
 
+    should_evict: Option<Arc<dyn ShouldEvict<K, V>>>,
+
+    insert_mode: InsertMode,
+
+    serve_stale_on_error: Option<Duration>,
+
     state: Arc<CacheSharedState<E::Handle, L>>,
 }
 
@@ -92,6 +146,9 @@ where
         capacity: usize,
         eviction_config: &E::Config,
         usage: Arc<AtomicUsize>,
+        should_evict: Option<Arc<dyn ShouldEvict<K, V>>>,
+        insert_mode: InsertMode,
+        serve_stale_on_error: Option<Duration>,
         context: Arc<CacheSharedState<E::Handle, L>>,
     ) -> Self {
         let indexer = I::new();
@@ -103,13 +160,22 @@ where
             capacity,
             usage,
             waiters,
+            should_evict,
+            insert_mode,
+            serve_stale_on_error,
             state: context,
         }
     }
 
     /// Insert a new entry into the cache. The handle for the new entry is returned.
+    ///
+    /// The released entries evicted to make room for the new entry are pushed to `evicted_entries`, and the released
+    /// entry replaced by the new entry is pushed to `last_reference_entries`.
+    ///
+    /// Return `Err` with the handle if the new entry is rejected in [`InsertMode::NoEvict`] mode. The handle is neither
+    /// in the indexer nor in the eviction container.
     // TODO(MrCroxx): use `expect` after `lint_reasons` is stable.
-    #[allow(clippy::type_complexity)]
+    #[allow(clippy::type_complexity, clippy::too_many_arguments)]
     unsafe fn insert<AK, AV>(
         &mut self,
         hash: u64,
@@ -117,8 +183,25 @@ where
         value: AV,
         weight: usize,
         context: <E::Handle as Handle>::Context,
-        last_reference_entries: &mut Vec<(Arc<K>, Arc<V>, <E::Handle as Handle>::Context, usize)>,
-    ) -> NonNull<E::Handle>
+        priority: CachePriority,
+        expire_at: Option<Instant>,
+        evicted_entries: &mut Vec<(
+            Arc<K>,
+            Arc<V>,
+            <E::Handle as Handle>::Context,
+            CachePriority,
+            Option<Instant>,
+            usize,
+        )>,
+        last_reference_entries: &mut Vec<(
+            Arc<K>,
+            Arc<V>,
+            <E::Handle as Handle>::Context,
+            CachePriority,
+            Option<Instant>,
+            usize,
+        )>,
+    ) -> std::result::Result<NonNull<E::Handle>, NonNull<E::Handle>>
     where
         AK: Into<Arc<K>>,
         AV: Into<Arc<V>>,
@@ -126,11 +209,27 @@ where
         let key = key.into();
         let value = value.into();
 
+        let reject = self.insert_mode == InsertMode::NoEvict
+            && self.usage.load(Ordering::Relaxed) + weight > self.capacity
+            && self.indexer.get(hash, key.as_ref()).is_none();
+
         let mut handle = self.state.object_pool.acquire();
         handle.init(hash, (key.clone(), value), weight, context);
+        handle.base_mut().set_priority(priority);
+        handle.base_mut().set_expire_at(expire_at);
         let mut ptr = unsafe { NonNull::new_unchecked(Box::into_raw(handle)) };
 
-        self.evict(weight, last_reference_entries);
+        if reject {
+            self.state.metrics.reject.fetch_add(1, Ordering::Relaxed);
+            self.usage.fetch_add(weight, Ordering::Relaxed);
+            ptr.as_mut().base_mut().inc_refs();
+            return Err(ptr);
+        }
+
+        // Entries are never evicted in `NoEvict` mode, even if the updated entry is heavier than the replaced one.
+        if self.insert_mode == InsertMode::Evict {
+            self.evict(weight, evicted_entries);
+        }
 
         debug_assert!(!ptr.as_ref().base().is_in_indexer());
         if let Some(old) = self.indexer.insert(key, ptr) {
@@ -156,6 +255,27 @@ where
         self.usage.fetch_add(weight, Ordering::Relaxed);
         ptr.as_mut().base_mut().inc_refs();
 
+        Ok(ptr)
+    }
+
+    /// Create a handle of the entry that is neither in the indexer nor in the eviction container.
+    ///
+    /// The weight is counted in the usage until the handle is released after all external references are dropped.
+    unsafe fn deposit(
+        &mut self,
+        hash: u64,
+        key: Arc<K>,
+        value: Arc<V>,
+        weight: usize,
+        context: <E::Handle as Handle>::Context,
+    ) -> NonNull<E::Handle> {
+        let mut handle = self.state.object_pool.acquire();
+        handle.init(hash, (key, value), weight, context);
+        let mut ptr = unsafe { NonNull::new_unchecked(Box::into_raw(handle)) };
+
+        self.usage.fetch_add(weight, Ordering::Relaxed);
+        ptr.as_mut().base_mut().inc_refs();
+
         ptr
     }
 
@@ -178,7 +298,7 @@ where
         debug_assert!(base.is_in_indexer());
 
         base.inc_refs();
-        self.eviction.acquire(ptr);
+        self.acquire(ptr);
 
         Some(ptr)
     }
@@ -188,7 +308,36 @@ where
         K: Borrow<Q>,
         Q: Hash + Eq + ?Sized,
     {
-        self.indexer.get(hash, key).is_some()
+        self.indexer
+            .get(hash, key)
+            .is_some_and(|ptr| !ptr.as_ref().base().is_expired(Instant::now()))
+    }
+
+    /// Remove the entry of the key if it is expired.
+    ///
+    /// Return the removed handle to be released by the caller, or `None` if the entry doesn't exist or is not expired.
+    ///
+    /// With serving stale on error, an entry expired for less than the max staleness is kept in the cache instead, and
+    /// its handle is returned with a reference, to be served by [`GenericCache::entry`] if the fetch fails.
+    unsafe fn remove_expired<Q>(&mut self, hash: u64, key: &Q) -> Option<NonNull<E::Handle>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let mut ptr = self.indexer.get(hash, key)?;
+        let now = Instant::now();
+        let expire_at = ptr.as_ref().base().expire_at().filter(|&expire_at| expire_at <= now)?;
+        self.state.metrics.miss.fetch_add(1, Ordering::Relaxed);
+        if self
+            .serve_stale_on_error
+            .is_some_and(|max_staleness| now < expire_at + max_staleness)
+        {
+            ptr.as_mut().base_mut().inc_refs();
+            self.acquire(ptr);
+            return Some(ptr);
+        }
+        self.state.metrics.expire.fetch_add(1, Ordering::Relaxed);
+        self.remove(hash, key)
     }
 
     unsafe fn touch<Q>(&mut self, hash: u64, key: &Q) -> bool
@@ -198,11 +347,31 @@ where
     {
         let res = self.indexer.get(hash, key);
         if let Some(ptr) = res {
-            self.eviction.acquire(ptr);
+            self.acquire(ptr);
+            // An entry held externally is released to the eviction container when the last reference is dropped.
+            // Otherwise, release it now as if a reference were dropped, which renews its recency (e.g. for LRU).
+            let base = ptr.as_ref().base();
+            if !base.has_refs() && base.is_in_eviction() && base.priority() != CachePriority::Low {
+                self.eviction.release(ptr);
+            }
         }
         res.is_some()
     }
 
+    /// Notify the eviction container that the entry is accessed.
+    ///
+    /// Low priority entries are not promoted, and high priority entries renew their second chance.
+    unsafe fn acquire(&mut self, mut ptr: NonNull<E::Handle>) {
+        match ptr.as_ref().base().priority() {
+            CachePriority::Low => {}
+            CachePriority::Normal => self.eviction.acquire(ptr),
+            CachePriority::High => {
+                ptr.as_mut().base_mut().set_spared(false);
+                self.eviction.acquire(ptr);
+            }
+        }
+    }
+
     /// Remove a key from the cache.
     ///
     /// Return `Some(..)` if the handle is released, or `None` if the handle is still in use.
@@ -246,7 +415,14 @@ where
     #[allow(clippy::type_complexity)]
     unsafe fn clear(
         &mut self,
-        last_reference_entries: &mut Vec<(Arc<K>, Arc<V>, <E::Handle as Handle>::Context, usize)>,
+        last_reference_entries: &mut Vec<(
+            Arc<K>,
+            Arc<V>,
+            <E::Handle as Handle>::Context,
+            CachePriority,
+            Option<Instant>,
+            usize,
+        )>,
     ) {
         // TODO(MrCroxx): Avoid collecting here?
         let ptrs = self.indexer.drain().collect_vec();
@@ -277,21 +453,79 @@ where
     unsafe fn evict(
         &mut self,
         weight: usize,
-        last_reference_entries: &mut Vec<(Arc<K>, Arc<V>, <E::Handle as Handle>::Context, usize)>,
+        last_reference_entries: &mut Vec<(
+            Arc<K>,
+            Arc<V>,
+            <E::Handle as Handle>::Context,
+            CachePriority,
+            Option<Instant>,
+            usize,
+        )>,
     ) {
+        // Handles vetoed by `should_evict`, they will be reinserted to the eviction container after eviction.
+        let mut vetoed = VecDeque::new();
+
         // TODO(MrCroxx): Use `let_chains` here after it is stable.
         while self.usage.load(Ordering::Relaxed) + weight > self.capacity {
-            let evicted = match self.eviction.pop() {
+            let mut evicted = match self.eviction.pop() {
                 Some(evicted) => evicted,
                 None => break,
             };
-            self.state.metrics.evict.fetch_add(1, Ordering::Relaxed);
-            let base = evicted.as_ref().base();
-            debug_assert!(base.is_in_indexer());
-            debug_assert!(!base.is_in_eviction());
-            if let Some(entry) = self.try_release_handle(evicted, false) {
-                last_reference_entries.push(entry);
+            if let Some(should_evict) = self.should_evict.as_ref() {
+                let (key, value) = evicted.as_ref().base().data_unwrap_unchecked();
+                if !should_evict(key, value) {
+                    vetoed.push_back(evicted);
+                    continue;
+                }
+            }
+            // Give the high priority entry a second chance by pushing it back to the eviction container.
+            let base = evicted.as_mut().base_mut();
+            if base.priority() == CachePriority::High && !base.is_spared() {
+                base.set_spared(true);
+                self.eviction.push(evicted);
+                continue;
             }
+            self.evict_handle(evicted, last_reference_entries);
+        }
+
+        // Guard against starvation: if all evictable entries are vetoed, evict them anyway.
+        if self.usage.load(Ordering::Relaxed) + weight > self.capacity && !vetoed.is_empty() {
+            tracing::warn!(
+                "[memory]: all {} eviction candidates are vetoed, evict them anyway to release space",
+                vetoed.len()
+            );
+            while self.usage.load(Ordering::Relaxed) + weight > self.capacity {
+                let Some(evicted) = vetoed.pop_front() else { break };
+                self.evict_handle(evicted, last_reference_entries);
+            }
+        }
+
+        // Reinsert in the reverse order, so the vetoed handles keep their positions.
+        for ptr in vetoed.into_iter().rev() {
+            self.eviction.reinsert(ptr);
+        }
+    }
+
+    // TODO(MrCroxx): use `expect` after `lint_reasons` is stable.
+    #[allow(clippy::type_complexity)]
+    unsafe fn evict_handle(
+        &mut self,
+        evicted: NonNull<E::Handle>,
+        last_reference_entries: &mut Vec<(
+            Arc<K>,
+            Arc<V>,
+            <E::Handle as Handle>::Context,
+            CachePriority,
+            Option<Instant>,
+            usize,
+        )>,
+    ) {
+        self.state.metrics.evict.fetch_add(1, Ordering::Relaxed);
+        let base = evicted.as_ref().base();
+        debug_assert!(base.is_in_indexer());
+        debug_assert!(!base.is_in_eviction());
+        if let Some(entry) = self.try_release_handle(evicted, false) {
+            last_reference_entries.push(entry);
         }
     }
 
@@ -303,7 +537,14 @@ where
     unsafe fn try_release_external_handle(
         &mut self,
         mut ptr: NonNull<E::Handle>,
-    ) -> Option<(Arc<K>, Arc<V>, <E::Handle as Handle>::Context, usize)> {
+    ) -> Option<(
+        Arc<K>,
+        Arc<V>,
+        <E::Handle as Handle>::Context,
+        CachePriority,
+        Option<Instant>,
+        usize,
+    )> {
         ptr.as_mut().base_mut().dec_refs();
         self.try_release_handle(ptr, true)
     }
@@ -319,7 +560,14 @@ where
         &mut self,
         mut ptr: NonNull<E::Handle>,
         reinsert: bool,
-    ) -> Option<(Arc<K>, Arc<V>, <E::Handle as Handle>::Context, usize)> {
+    ) -> Option<(
+        Arc<K>,
+        Arc<V>,
+        <E::Handle as Handle>::Context,
+        CachePriority,
+        Option<Instant>,
+        usize,
+    )> {
         let handle = ptr.as_mut();
 
         if handle.base().has_refs() {
@@ -337,6 +585,10 @@ where
             // In this case, the reinsertion should be given up.
             if reinsert && self.usage.load(Ordering::Relaxed) <= self.capacity {
                 let was_in_eviction = handle.base().is_in_eviction();
+                // Low priority entries keep their positions in the eviction container.
+                if was_in_eviction && handle.base().priority() == CachePriority::Low {
+                    return None;
+                }
                 self.eviction.release(ptr);
                 if ptr.as_ref().base().is_in_eviction() {
                     if was_in_eviction {
@@ -361,12 +613,14 @@ where
         self.state.metrics.release.fetch_add(1, Ordering::Relaxed);
 
         self.usage.fetch_sub(handle.base().weight(), Ordering::Relaxed);
+        let priority = handle.base().priority();
+        let expire_at = handle.base().expire_at();
         let ((key, value), context, weight) = handle.base_mut().take();
 
         let handle = Box::from_raw(ptr.as_ptr());
         self.state.object_pool.release(handle);
 
-        Some((key, value, context, weight))
+        Some((key, value, context, priority, expire_at, weight))
     }
 }
 
@@ -401,6 +655,16 @@ where
     pub hash_builder: S,
     pub event_listener: L,
     pub weighter: Arc<dyn Weighter<K, V>>,
+    pub should_evict: Option<Arc<dyn ShouldEvict<K, V>>>,
+    pub insert_mode: InsertMode,
+    pub release_batch: Option<ReleaseBatchConfig>,
+    /// Fraction of the ttl to spread the expiry of the entries inserted with the same ttl over, see
+    /// [`GenericCache::insert_with_ttl`].
+    pub ttl_jitter: f64,
+    /// The max staleness to serve an expired entry for if refetching it fails, see [`GenericCache::entry`].
+    ///
+    /// `None` disables serving stale entries.
+    pub serve_stale_on_error: Option<Duration>,
 }
 
 // TODO(MrCroxx): use `expect` after `lint_reasons` is stable.
@@ -483,6 +747,10 @@ where
 
     hash_builder: S,
     weighter: Arc<dyn Weighter<K, V>>,
+
+    release_batch: Option<(ReleaseBatchConfig, Mutex<ReleaseBatch<K, V>>)>,
+
+    ttl_jitter: f64,
 }
 
 impl<K, V, E, I, L, S> GenericCache<K, V, E, I, L, S>
@@ -507,7 +775,17 @@ where
 
         let shards = usages
             .iter()
-            .map(|usage| CacheShard::new(shard_capacity, &config.eviction_config, usage.clone(), context.clone()))
+            .map(|usage| {
+                CacheShard::new(
+                    shard_capacity,
+                    &config.eviction_config,
+                    usage.clone(),
+                    config.should_evict.clone(),
+                    config.insert_mode,
+                    config.serve_stale_on_error,
+                    context.clone(),
+                )
+            })
             .map(Mutex::new)
             .collect_vec();
 
@@ -518,6 +796,14 @@ where
             context,
             hash_builder: config.hash_builder,
             weighter: config.weighter,
+            release_batch: config.release_batch.map(|config| {
+                let batch = ReleaseBatch {
+                    entries: Vec::with_capacity(config.count),
+                    started: Instant::now(),
+                };
+                (config, Mutex::new(batch))
+            }),
+            ttl_jitter: config.ttl_jitter,
         }
     }
 
@@ -535,6 +821,128 @@ where
         value: AV,
         context: CacheContext,
     ) -> GenericCacheEntry<K, V, E, I, L, S>
+    where
+        AK: Into<Arc<K>> + Send + 'static,
+        AV: Into<Arc<V>> + Send + 'static,
+    {
+        self.insert_inner(key, value, context, CachePriority::default(), None, false)
+            .0
+    }
+
+    /// Insert a new entry into the cache with the given priority.
+    ///
+    /// See [`CachePriority`] for how the priority affects the eviction.
+    pub fn insert_with_priority<AK, AV>(
+        self: &Arc<Self>,
+        key: AK,
+        value: AV,
+        priority: CachePriority,
+    ) -> GenericCacheEntry<K, V, E, I, L, S>
+    where
+        AK: Into<Arc<K>> + Send + 'static,
+        AV: Into<Arc<V>> + Send + 'static,
+    {
+        self.insert_inner(key, value, CacheContext::default(), priority, None, false)
+            .0
+    }
+
+    /// Insert a new entry into the cache, which expires after `ttl`.
+    ///
+    /// With the ttl jitter, the entry expires after `ttl * (1 + ttl_jitter * r)` instead, where `r` in `[0, 1)` is
+    /// derived from the key hash of the configured hash builder. So the entries inserted at once with the same ttl
+    /// don't expire at once, while the expiry of a key is still deterministic.
+    ///
+    /// An expired entry is treated as a miss and removed from the cache lazily on get.
+    pub fn insert_with_ttl<AK, AV>(
+        self: &Arc<Self>,
+        key: AK,
+        value: AV,
+        ttl: Duration,
+    ) -> GenericCacheEntry<K, V, E, I, L, S>
+    where
+        AK: Into<Arc<K>> + Send + 'static,
+        AV: Into<Arc<V>> + Send + 'static,
+    {
+        self.insert_with_ttl_at(key, value, ttl, Instant::now())
+    }
+
+    /// Insert a new entry into the cache, which expires after `ttl` since `now`.
+    fn insert_with_ttl_at<AK, AV>(
+        self: &Arc<Self>,
+        key: AK,
+        value: AV,
+        ttl: Duration,
+        now: Instant,
+    ) -> GenericCacheEntry<K, V, E, I, L, S>
+    where
+        AK: Into<Arc<K>> + Send + 'static,
+        AV: Into<Arc<V>> + Send + 'static,
+    {
+        self.insert_inner(
+            key,
+            value,
+            CacheContext::default(),
+            CachePriority::default(),
+            Some((ttl, now)),
+            false,
+        )
+        .0
+    }
+
+    /// Insert a new entry into the cache.
+    ///
+    /// Return [`CacheFull`] if the entry is rejected in [`InsertMode::NoEvict`] mode. With [`InsertMode::Evict`], it
+    /// never fails.
+    pub fn try_insert<AK, AV>(
+        self: &Arc<Self>,
+        key: AK,
+        value: AV,
+    ) -> std::result::Result<GenericCacheEntry<K, V, E, I, L, S>, CacheFull>
+    where
+        AK: Into<Arc<K>> + Send + 'static,
+        AV: Into<Arc<V>> + Send + 'static,
+    {
+        match self.insert_inner(
+            key,
+            value,
+            CacheContext::default(),
+            CachePriority::default(),
+            None,
+            false,
+        ) {
+            (entry, _, true) => Ok(entry),
+            (_, _, false) => Err(CacheFull),
+        }
+    }
+
+    /// Insert a new entry into the cache, and return the entry evicted by the eviction algorithm to make room for it.
+    ///
+    /// The returned entry is handed over to the caller instead of the event listener, so it will not be demoted to
+    /// the disk cache of a hybrid cache. If more than one entry is evicted, the first evicted one is returned and the
+    /// others are released to the event listener as usual.
+    ///
+    /// Evicted entries that are still referenced externally are not released, and thus not returned.
+    pub fn insert_and_get_evicted<AK, AV>(self: &Arc<Self>, key: AK, value: AV) -> Option<(Arc<K>, Arc<V>)>
+    where
+        AK: Into<Arc<K>> + Send + 'static,
+        AV: Into<Arc<V>> + Send + 'static,
+    {
+        self.insert_inner(
+            key,
+            value,
+            CacheContext::default(),
+            CachePriority::default(),
+            None,
+            true,
+        )
+        .1
+    }
+
+    /// Create an entry that is not cached, e.g. to hand out a value without evicting the cached entries for it.
+    ///
+    /// The entry is neither indexed nor evictable, and it is released to the event listener after all external
+    /// references are dropped.
+    pub fn deposit<AK, AV>(self: &Arc<Self>, key: AK, value: AV) -> GenericCacheEntry<K, V, E, I, L, S>
     where
         AK: Into<Arc<K>> + Send + 'static,
         AV: Into<Arc<V>> + Send + 'static,
@@ -544,12 +952,58 @@ where
         let hash = self.hash_builder.hash_one(&key);
         let weight = (self.weighter)(&key, &value);
 
+        unsafe {
+            let mut shard = self.shards[hash as usize % self.shards.len()].lock();
+            let ptr = shard.deposit(hash, key, value, weight, CacheContext::default().into());
+            GenericCacheEntry {
+                cache: self.clone(),
+                ptr,
+            }
+        }
+    }
+
+    /// Insert the entry and return `(entry, evicted entry if taken, whether the entry is cached)`.
+    // TODO(MrCroxx): use `expect` after `lint_reasons` is stable.
+    #[allow(clippy::type_complexity)]
+    fn insert_inner<AK, AV>(
+        self: &Arc<Self>,
+        key: AK,
+        value: AV,
+        context: CacheContext,
+        priority: CachePriority,
+        ttl: Option<(Duration, Instant)>,
+        take_evicted: bool,
+    ) -> (GenericCacheEntry<K, V, E, I, L, S>, Option<(Arc<K>, Arc<V>)>, bool)
+    where
+        AK: Into<Arc<K>> + Send + 'static,
+        AV: Into<Arc<V>> + Send + 'static,
+    {
+        let key = key.into();
+        let value = value.into();
+        let hash = self.hash_builder.hash_one(&key);
+        let weight = (self.weighter)(&key, &value);
+        let expire_at = ttl.map(|(ttl, now)| now + self.jittered_ttl(hash, ttl));
+
+        let mut evicted = vec![];
         let mut to_deallocate = vec![];
 
-        let (entry, waiters) = unsafe {
+        let (entry, waiters, inserted) = unsafe {
             let mut shard = self.shards[hash as usize % self.shards.len()].lock();
             let waiters = shard.waiters.remove(&key);
-            let mut ptr = shard.insert(hash, key, value, weight, context.into(), &mut to_deallocate);
+            let (mut ptr, inserted) = match shard.insert(
+                hash,
+                key,
+                value,
+                weight,
+                context.into(),
+                priority,
+                expire_at,
+                &mut evicted,
+                &mut to_deallocate,
+            ) {
+                Ok(ptr) => (ptr, true),
+                Err(ptr) => (ptr, false),
+            };
             if let Some(waiters) = waiters.as_ref() {
                 ptr.as_mut().base_mut().inc_refs_by(waiters.len());
             }
@@ -557,7 +1011,7 @@ where
                 cache: self.clone(),
                 ptr,
             };
-            (entry, waiters)
+            (entry, waiters, inserted)
         };
 
         if let Some(waiters) = waiters {
@@ -569,12 +1023,30 @@ where
             }
         }
 
+        let mut evicted = evicted.into_iter();
+        let taken = match take_evicted {
+            true => evicted.next().map(|(key, value, _, _, _, _)| (key, value)),
+            false => None,
+        };
+
         // Do not deallocate data within the lock section.
-        for (key, value, context, weight) in to_deallocate {
-            self.context.listener.on_release(key, value, context.into(), weight)
-        }
+        let evicted = evicted.map(|entry| Self::released(entry, ReleaseReason::Evict));
+        let replaced = to_deallocate
+            .into_iter()
+            .map(|entry| Self::released(entry, ReleaseReason::Replace));
+        self.release(evicted.chain(replaced));
 
-        entry
+        (entry, taken, inserted)
+    }
+
+    /// Extend `ttl` by a fraction of up to `ttl_jitter` derived from the key hash.
+    fn jittered_ttl(&self, hash: u64, ttl: Duration) -> Duration {
+        if self.ttl_jitter == 0.0 {
+            return ttl;
+        }
+        // The high 53 bits of the hash make a uniform `f64` in `[0, 1)`.
+        let r = (hash >> 11) as f64 / (1u64 << 53) as f64;
+        ttl + ttl.mul_f64(self.ttl_jitter * r)
     }
 
     pub fn remove<Q>(self: &Arc<Self>, key: &Q) -> Option<GenericCacheEntry<K, V, E, I, L, S>>
@@ -649,13 +1121,24 @@ where
     {
         let hash = self.hash_builder.hash_one(key);
 
-        unsafe {
+        let (entry, expired) = unsafe {
             let mut shard = self.shards[hash as usize % self.shards.len()].lock();
-            shard.get(hash, key).map(|ptr| GenericCacheEntry {
-                cache: self.clone(),
-                ptr,
-            })
-        }
+            match shard.remove_expired(hash, key) {
+                Some(ptr) => (None, Some(ptr)),
+                None => (shard.get(hash, key), None),
+            }
+        };
+
+        // Release the expired entry out of the lock section.
+        drop(expired.map(|ptr| GenericCacheEntry {
+            cache: self.clone(),
+            ptr,
+        }));
+
+        entry.map(|ptr| GenericCacheEntry {
+            cache: self.clone(),
+            ptr,
+        })
     }
 
     pub fn contains<Q>(self: &Arc<Self>, key: &Q) -> bool
@@ -712,10 +1195,89 @@ where
         };
 
         // Do not deallocate data within the lock section.
-        if let Some((key, value, context, weight)) = entry {
-            self.context.listener.on_release(key, value, context.into(), weight);
+        if let Some(entry) = entry {
+            self.release([Self::released(entry, ReleaseReason::Release)]);
+        }
+    }
+
+    /// Deliver the pending batch of released entries to the event listener.
+    ///
+    /// Does nothing if release batching is disabled or there is no pending entry.
+    pub fn flush_release_batch(&self) {
+        let Some((_, batch)) = self.release_batch.as_ref() else {
+            return;
+        };
+        let entries = std::mem::take(&mut batch.lock().entries);
+        if !entries.is_empty() {
+            self.context.listener.on_release_batch(entries);
         }
     }
+
+    /// Deliver the released entries to the event listener, batched if release batching is enabled.
+    fn release(&self, entries: impl IntoIterator<Item = ReleasedEntry<K, V>>) {
+        let Some((config, batch)) = self.release_batch.as_ref() else {
+            for entry in entries {
+                self.context.listener.on_release_entry(entry);
+            }
+            return;
+        };
+
+        let entries = {
+            let mut batch = batch.lock();
+            let mut entries = entries.into_iter().peekable();
+            if entries.peek().is_none() {
+                return;
+            }
+            if batch.entries.is_empty() {
+                batch.started = Instant::now();
+            }
+            batch.entries.extend(entries);
+            if batch.entries.len() < config.count && batch.started.elapsed() < config.interval {
+                return;
+            }
+            std::mem::replace(&mut batch.entries, Vec::with_capacity(config.count))
+        };
+        self.context.listener.on_release_batch(entries);
+    }
+
+    // TODO(MrCroxx): use `expect` after `lint_reasons` is stable.
+    #[allow(clippy::type_complexity)]
+    fn released(
+        (key, value, context, priority, expire_at, weight): (
+            Arc<K>,
+            Arc<V>,
+            <E::Handle as Handle>::Context,
+            CachePriority,
+            Option<Instant>,
+            usize,
+        ),
+        reason: ReleaseReason,
+    ) -> ReleasedEntry<K, V> {
+        ReleasedEntry {
+            key,
+            value,
+            context: context.into(),
+            priority,
+            expire_at,
+            weight,
+            reason,
+        }
+    }
+}
+
+impl<K, V, E, I, L, S> Drop for GenericCache<K, V, E, I, L, S>
+where
+    K: Key,
+    V: Value,
+    E: Eviction,
+    E::Handle: KeyedHandle<Key = Arc<K>, Data = (Arc<K>, Arc<V>)>,
+    I: Indexer<Key = K, Handle = E::Handle>,
+    L: CacheEventListener<K, V>,
+    S: BuildHasher + Send + Sync + 'static,
+{
+    fn drop(&mut self) {
+        self.flush_release_batch();
+    }
 }
 
 // TODO(MrCroxx): use `hashbrown::HashTable` with `Handle` may relax the `Clone` bound?
@@ -729,6 +1291,11 @@ where
     L: CacheEventListener<K, V>,
     S: BuildHasher + Send + Sync + 'static,
 {
+    /// Get the cached entry of the key, or fetch it with `f` and insert it on a miss. Concurrent fetches of the same
+    /// key are deduplicated.
+    ///
+    /// With serving stale on error, if the entry is expired for less than the max staleness and the fetch fails, the
+    /// expired entry is returned instead of the error, see [`GenericCacheEntry::is_stale`].
     pub fn entry<AK, AV, F, FU, ER>(self: &Arc<Self>, key: AK, f: F) -> GenericEntry<K, V, E, I, L, S, ER>
     where
         AK: Into<Arc<K>> + Send + 'static,
@@ -740,13 +1307,17 @@ where
         let key = key.into();
         let hash = self.hash_builder.hash_one(&key);
 
-        unsafe {
+        let (entry, expired) = unsafe {
             let mut shard = self.shards[hash as usize % self.shards.len()].lock();
-            if let Some(ptr) = shard.get(hash, &key) {
-                return GenericEntry::Hit(GenericCacheEntry {
-                    cache: self.clone(),
-                    ptr,
-                });
+            // An expired entry is removed and counted as a miss, so it is never a hit.
+            let mut expired = shard.remove_expired(hash, &key);
+            if expired.is_none() {
+                if let Some(ptr) = shard.get(hash, &key) {
+                    return GenericEntry::Hit(GenericCacheEntry {
+                        cache: self.clone(),
+                        ptr,
+                    });
+                }
             }
             let entry = match shard.waiters.entry(key.clone()) {
                 HashMapEntry::Occupied(mut o) => {
@@ -757,17 +1328,36 @@ where
                 HashMapEntry::Vacant(v) => {
                     v.insert(vec![]);
                     let cache = self.clone();
+                    // An expired entry still in the cache is within the max staleness, serve it if the fetch fails.
+                    let stale = if expired.as_ref().is_some_and(|ptr| ptr.as_ref().base().is_in_indexer()) {
+                        expired.take()
+                    } else {
+                        None
+                    }
+                    .map(|ptr| GenericCacheEntry {
+                        cache: self.clone(),
+                        ptr,
+                    });
                     let future = f();
                     let join = tokio::spawn(async move {
                         let (value, context) = match future.await {
                             Ok((value, context)) => (value, context),
                             Err(e) => {
-                                let mut shard = cache.shards[hash as usize % cache.shards.len()].lock();
-                                shard.waiters.remove(&key);
-                                return Err(e);
+                                let waiters = {
+                                    let mut shard = cache.shards[hash as usize % cache.shards.len()].lock();
+                                    shard.waiters.remove(&key)
+                                };
+                                let Some(stale) = stale else {
+                                    return Err(e);
+                                };
+                                for waiter in waiters.into_iter().flatten() {
+                                    let _ = waiter.send(stale.clone());
+                                }
+                                return Ok(stale);
                             }
                         };
                         let entry = cache.insert_with_context(key, value, context);
+                        drop(stale);
                         Ok(entry)
                     });
                     GenericEntry::Miss(join)
@@ -778,8 +1368,16 @@ where
                 GenericEntry::Miss(_) => shard.state.metrics.fetch.fetch_add(1, Ordering::Relaxed),
                 _ => unreachable!(),
             };
-            entry
-        }
+            (entry, expired)
+        };
+
+        // Release the expired entry out of the lock section.
+        drop(expired.map(|ptr| GenericCacheEntry {
+            cache: self.clone(),
+            ptr,
+        }));
+
+        entry
     }
 }
 
@@ -826,6 +1424,11 @@ where
     pub fn refs(&self) -> usize {
         unsafe { self.ptr.as_ref().base().refs() }
     }
+
+    /// Whether the entry is expired, e.g. served stale by [`GenericCache::entry`] after the fetch fails.
+    pub fn is_stale(&self) -> bool {
+        unsafe { self.ptr.as_ref().base().is_expired(Instant::now()) }
+    }
 }
 
 impl<K, V, E, I, L, S> Clone for GenericCacheEntry<K, V, E, I, L, S>
@@ -944,6 +1547,11 @@ mod tests {
             hash_builder: RandomState::default(),
             event_listener: DefaultCacheEventListener::default(),
             weighter: Arc::new(|_, _| 1),
+            should_evict: None,
+            insert_mode: InsertMode::default(),
+            release_batch: None,
+            ttl_jitter: 0.0,
+            serve_stale_on_error: None,
         };
         let cache = Arc::new(FifoCache::<u64, u64>::new(config));
 
@@ -969,6 +1577,11 @@ mod tests {
             hash_builder: RandomState::default(),
             event_listener: DefaultCacheEventListener::default(),
             weighter: Arc::new(|_, v: &String| v.len()),
+            should_evict: None,
+            insert_mode: InsertMode::default(),
+            release_batch: None,
+            ttl_jitter: 0.0,
+            serve_stale_on_error: None,
         };
         Arc::new(FifoCache::<u64, String>::new(config))
     }
@@ -984,6 +1597,11 @@ mod tests {
             hash_builder: RandomState::default(),
             event_listener: DefaultCacheEventListener::default(),
             weighter: Arc::new(|_, v: &String| v.len()),
+            should_evict: None,
+            insert_mode: InsertMode::default(),
+            release_batch: None,
+            ttl_jitter: 0.0,
+            serve_stale_on_error: None,
         };
         Arc::new(LruCache::<u64, String>::new(config))
     }
@@ -1163,4 +1781,392 @@ mod tests {
         // For cache policy like FIFO, the entries will not be reinserted while all handles are referenced.
         // It's okay for this is not a common situation and is not supposed to happen in real workload.
     }
+
+    fn fifo_with_should_evict(
+        capacity: usize,
+        should_evict: impl ShouldEvict<u64, String>,
+    ) -> Arc<FifoCache<u64, String>> {
+        let config = GenericCacheConfig {
+            capacity,
+            shards: 1,
+            eviction_config: FifoConfig {},
+            object_pool_capacity: 1,
+            hash_builder: RandomState::default(),
+            event_listener: DefaultCacheEventListener::default(),
+            weighter: Arc::new(|_, v: &String| v.len()),
+            should_evict: Some(Arc::new(should_evict)),
+            insert_mode: InsertMode::default(),
+            release_batch: None,
+            ttl_jitter: 0.0,
+            serve_stale_on_error: None,
+        };
+        Arc::new(FifoCache::<u64, String>::new(config))
+    }
+
+    #[test]
+    fn test_should_evict_veto() {
+        let cache = fifo_with_should_evict(10, |k: &u64, _: &String| *k != 1);
+
+        for i in 1..=20 {
+            insert_fifo(&cache, i, "xx");
+        }
+
+        // `1` is vetoed and survives, others are evicted in fifo order.
+        assert_eq!(cache.usage(), 10);
+        assert!(cache.contains(&1));
+        for i in 2..=16 {
+            assert!(!cache.contains(&i));
+        }
+        for i in 17..=20 {
+            assert!(cache.contains(&i));
+        }
+    }
+
+    #[test]
+    fn test_should_evict_veto_all() {
+        let cache = fifo_with_should_evict(10, |_: &u64, _: &String| false);
+
+        for i in 1..=20 {
+            insert_fifo(&cache, i, "xx");
+        }
+
+        // All candidates are vetoed, they are evicted anyway to avoid starvation.
+        assert_eq!(cache.usage(), 10);
+        for i in 1..=15 {
+            assert!(!cache.contains(&i));
+        }
+        for i in 16..=20 {
+            assert!(cache.contains(&i));
+        }
+    }
+
+    #[test]
+    fn test_should_evict_veto_keeps_order() {
+        let veto = Arc::new(std::sync::atomic::AtomicBool::new(true));
+        let should_evict = {
+            let veto = veto.clone();
+            move |k: &u64, _: &String| !veto.load(Ordering::Relaxed) || *k > 2
+        };
+        let config = GenericCacheConfig {
+            capacity: 10,
+            shards: 1,
+            eviction_config: LruConfig {
+                high_priority_pool_ratio: 0.0,
+            },
+            object_pool_capacity: 1,
+            hash_builder: RandomState::default(),
+            event_listener: DefaultCacheEventListener::default(),
+            weighter: Arc::new(|_, v: &String| v.len()),
+            should_evict: Some(Arc::new(should_evict)),
+            insert_mode: InsertMode::default(),
+            release_batch: None,
+            ttl_jitter: 0.0,
+            serve_stale_on_error: None,
+        };
+        let cache = Arc::new(LruCache::<u64, String>::new(config));
+
+        for i in 1..=5 {
+            insert_lru(&cache, i, "xx");
+        }
+        // `1` and `2` are vetoed, `3` is evicted instead.
+        insert_lru(&cache, 6, "xx");
+        assert!(!cache.contains(&3));
+
+        // The vetoed entries keep their positions at the lru end instead of being renewed.
+        veto.store(false, Ordering::Relaxed);
+        let evicted = (7..=9)
+            .map(|i| *cache.insert_and_get_evicted(i, "xx".to_string()).unwrap().0)
+            .collect_vec();
+        assert_eq!(evicted, vec![1, 2, 4]);
+    }
+
+    #[test]
+    fn test_insert_and_get_evicted() {
+        let cache = fifo(10);
+        for i in 1..=4 {
+            assert!(cache.insert_and_get_evicted(i, "xx".to_string()).is_none());
+        }
+
+        // Replacement is not an eviction.
+        assert!(cache.insert_and_get_evicted(4, "yy".to_string()).is_none());
+
+        assert!(cache.insert_and_get_evicted(5, "xx".to_string()).is_none());
+        assert_eq!(cache.usage(), 10);
+
+        let (key, value) = cache.insert_and_get_evicted(6, "xx".to_string()).unwrap();
+        assert_eq!((*key, value.as_str()), (1, "xx"));
+        assert!(!cache.contains(&1));
+
+        let cache = lru(10);
+        for i in 1..=5 {
+            assert!(cache.insert_and_get_evicted(i, "xx".to_string()).is_none());
+        }
+        drop(cache.get(&1).unwrap());
+
+        let (key, _) = cache.insert_and_get_evicted(6, "xx".to_string()).unwrap();
+        assert_eq!(*key, 2);
+        assert!(cache.contains(&1));
+        assert!(!cache.contains(&2));
+    }
+
+    #[test]
+    fn test_insert_mode_no_evict() {
+        let config = GenericCacheConfig {
+            capacity: 10,
+            shards: 1,
+            eviction_config: FifoConfig {},
+            object_pool_capacity: 1,
+            hash_builder: RandomState::default(),
+            event_listener: DefaultCacheEventListener::default(),
+            weighter: Arc::new(|_, v: &String| v.len()),
+            should_evict: None,
+            insert_mode: InsertMode::NoEvict,
+            release_batch: None,
+            ttl_jitter: 0.0,
+            serve_stale_on_error: None,
+        };
+        let cache = Arc::new(FifoCache::<u64, String>::new(config));
+
+        for i in 1..=5 {
+            cache.try_insert(i, "xx".to_string()).unwrap();
+        }
+        assert_eq!(cache.usage(), 10);
+
+        // New keys are rejected.
+        assert_eq!(cache.try_insert(6, "xx".to_string()).err(), Some(CacheFull));
+        assert!(!cache.contains(&6));
+        assert_eq!(cache.metrics().reject.load(Ordering::Relaxed), 1);
+
+        // The rejected entry is still readable via the returned handle, and released after dropped.
+        let entry = insert_fifo(&cache, 7, "yy");
+        assert_eq!(entry.value(), "yy");
+        assert!(!cache.contains(&7));
+        assert_eq!(cache.usage(), 12);
+        drop(entry);
+        assert_eq!(cache.usage(), 10);
+
+        // Updates of the existing keys succeed.
+        let entry = cache.try_insert(3, "zz".to_string()).unwrap();
+        assert_eq!(entry.value(), "zz");
+        drop(entry);
+        for i in 1..=5 {
+            assert!(cache.contains(&i));
+        }
+        assert_eq!(cache.usage(), 10);
+        assert_eq!(cache.get(&3).unwrap().value(), "zz");
+    }
+
+    #[test]
+    fn test_deposit() {
+        let cache = fifo(10);
+        for i in 1..=5 {
+            insert_fifo(&cache, i, "xx");
+        }
+
+        // The deposited entry is readable via the returned handle, and evicts nothing.
+        let entry = cache.deposit(6, "yy".to_string());
+        assert_eq!(entry.value(), "yy");
+        assert!(!cache.contains(&6));
+        for i in 1..=5 {
+            assert!(cache.contains(&i));
+        }
+        assert_eq!(cache.usage(), 12);
+
+        drop(entry);
+        assert!(!cache.contains(&6));
+        assert_eq!(cache.usage(), 10);
+    }
+
+    #[derive(Debug, Default)]
+    struct ReleaseRecorder {
+        released: AtomicUsize,
+        batches: Mutex<Vec<Vec<(u64, ReleaseReason)>>>,
+    }
+
+    impl CacheEventListener<u64, u64> for Arc<ReleaseRecorder> {
+        fn on_release(&self, _key: Arc<u64>, _value: Arc<u64>, _context: CacheContext, _weight: usize) {
+            self.released.fetch_add(1, Ordering::Relaxed);
+        }
+
+        fn on_release_batch(&self, entries: Vec<ReleasedEntry<u64, u64>>) {
+            let batch = entries.into_iter().map(|entry| (*entry.key, entry.reason)).collect();
+            self.batches.lock().push(batch);
+        }
+    }
+
+    #[test]
+    fn test_release_batch() {
+        let recorder = Arc::new(ReleaseRecorder::default());
+        let config = GenericCacheConfig {
+            capacity: 16,
+            shards: 1,
+            eviction_config: FifoConfig {},
+            object_pool_capacity: 1,
+            hash_builder: RandomState::default(),
+            event_listener: recorder.clone(),
+            weighter: Arc::new(|_, _| 1),
+            should_evict: None,
+            insert_mode: InsertMode::default(),
+            release_batch: Some(ReleaseBatchConfig {
+                count: 32,
+                interval: Duration::MAX,
+            }),
+            ttl_jitter: 0.0,
+            serve_stale_on_error: None,
+        };
+        let cache = Arc::new(FifoCache::<u64, u64, Arc<ReleaseRecorder>>::new(config));
+
+        for i in 1..=1000 {
+            cache.insert(i, i);
+        }
+        // The replacement evicts `985` to make room for the new entry before replacing `1000`.
+        cache.insert(1000, 0);
+        drop(cache.remove(&999).unwrap());
+        assert_eq!(recorder.batches.lock().len(), 987 / 32);
+
+        cache.flush_release_batch();
+
+        let batches = std::mem::take(&mut *recorder.batches.lock());
+        assert_eq!(batches.len(), 987usize.div_ceil(32));
+        for batch in &batches[..batches.len() - 1] {
+            assert_eq!(batch.len(), 32);
+        }
+        let released = batches.into_iter().flatten().collect_vec();
+        assert_eq!(released.len(), 987);
+        assert_eq!(recorder.released.load(Ordering::Relaxed), 0);
+
+        let evicted = released
+            .iter()
+            .filter(|(_, reason)| *reason == ReleaseReason::Evict)
+            .map(|(key, _)| *key)
+            .collect_vec();
+        assert_eq!(evicted, (1..=985).collect_vec());
+        assert_eq!(
+            released
+                .iter()
+                .filter(|(_, reason)| *reason != ReleaseReason::Evict)
+                .copied()
+                .collect_vec(),
+            vec![(1000, ReleaseReason::Replace), (999, ReleaseReason::Release)]
+        );
+    }
+
+    #[test]
+    fn test_ttl() {
+        let cache = fifo(100);
+
+        cache.insert_with_ttl(1, "a".to_string(), Duration::from_millis(50));
+        insert_fifo(&cache, 2, "b");
+        let entry = cache.get(&1).unwrap();
+        assert_eq!(entry.value(), "a");
+        drop(entry);
+        assert!(cache.contains(&1));
+
+        std::thread::sleep(Duration::from_millis(100));
+
+        // The expired entry is a miss and removed on get, the other entries are not affected.
+        assert!(!cache.contains(&1));
+        assert!(cache.get(&1).is_none());
+        assert_eq!(cache.metrics().expire.load(Ordering::Relaxed), 1);
+        assert_eq!(cache.usage(), 1);
+        assert_eq!(cache.get(&2).unwrap().value(), "b");
+
+        // The expired entry is replaced by a new insertion without expiration.
+        cache.insert_with_ttl(1, "a".to_string(), Duration::ZERO);
+        insert_fifo(&cache, 1, "c");
+        assert_eq!(cache.get(&1).unwrap().value(), "c");
+    }
+
+    #[test]
+    fn test_ttl_jitter() {
+        const TTL: Duration = Duration::from_secs(100);
+
+        let new_cache = |ttl_jitter| {
+            let config = GenericCacheConfig {
+                capacity: 1000,
+                shards: 1,
+                eviction_config: FifoConfig {},
+                object_pool_capacity: 1,
+                // A fixed seed keeps the jitters of the keys stable across runs.
+                hash_builder: RandomState::with_seeds(1, 2, 3, 4),
+                event_listener: DefaultCacheEventListener::default(),
+                weighter: Arc::new(|_, _| 1),
+                should_evict: None,
+                insert_mode: InsertMode::default(),
+                release_batch: None,
+                ttl_jitter,
+                serve_stale_on_error: None,
+            };
+            Arc::new(FifoCache::<u64, u64>::new(config))
+        };
+        let now = Instant::now();
+        let ttl = |cache: &Arc<FifoCache<u64, u64>>, key: u64| {
+            let entry = cache.insert_with_ttl_at(key, key, TTL, now);
+            unsafe { entry.ptr.as_ref().base().expire_at().unwrap() - now }
+        };
+
+        let cache = new_cache(0.5);
+        let ttls = (0..1000).map(|key| ttl(&cache, key)).collect_vec();
+        assert!(ttls.iter().all(|ttl| *ttl >= TTL && *ttl < TTL.mul_f64(1.5)));
+        // The expiry is spread over the window instead of synchronized.
+        assert!(*ttls.iter().min().unwrap() < TTL.mul_f64(1.05));
+        assert!(*ttls.iter().max().unwrap() > TTL.mul_f64(1.45));
+        // The jitter of a key is deterministic.
+        assert_eq!(ttl(&cache, 0), ttls[0]);
+
+        let cache = new_cache(0.0);
+        assert!((0..1000).all(|key| ttl(&cache, key) == TTL));
+    }
+
+    #[tokio::test]
+    async fn test_serve_stale_on_error() {
+        let config = GenericCacheConfig {
+            capacity: 100,
+            shards: 1,
+            eviction_config: FifoConfig {},
+            object_pool_capacity: 1,
+            hash_builder: RandomState::default(),
+            event_listener: DefaultCacheEventListener::default(),
+            weighter: Arc::new(|_, v: &String| v.len()),
+            should_evict: None,
+            insert_mode: InsertMode::default(),
+            release_batch: None,
+            ttl_jitter: 0.0,
+            serve_stale_on_error: Some(Duration::from_millis(500)),
+        };
+        let cache = Arc::new(FifoCache::<u64, String>::new(config));
+        let fetch = |value: Option<&'static str>| {
+            move || async move {
+                value
+                    .map(|value| (value.to_string(), CacheContext::default()))
+                    .ok_or_else(|| anyhow::anyhow!("origin is down"))
+            }
+        };
+
+        cache.insert_with_ttl(1, "a".to_string(), Duration::from_millis(50));
+        cache.insert_with_ttl(2, "b".to_string(), Duration::from_millis(50));
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        // The expired entry is a miss, but kept to be served if the fetch fails.
+        assert!(cache.get(&1).is_none());
+        assert!(!cache.contains(&1));
+        for _ in 0..2 {
+            let entry = cache.entry(1, fetch(None)).await.unwrap();
+            assert_eq!(entry.value(), "a");
+            assert!(entry.is_stale());
+        }
+
+        // A successful fetch replaces the stale entry.
+        let entry = cache.entry(2, fetch(Some("c"))).await.unwrap();
+        assert_eq!(entry.value(), "c");
+        assert!(!entry.is_stale());
+        drop(entry);
+
+        // The stale entry is removed after the max staleness, and the fetch error is returned.
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        assert!(cache.entry(1, fetch(None)).await.is_err());
+        assert!(cache.get(&1).is_none());
+        assert_eq!(cache.usage(), 1);
+        assert_eq!(cache.get(&2).unwrap().value(), "c");
+    }
 }
@@ -12,17 +12,20 @@
 //  See the License for the specific language governing permissions and
 //  limitations under the License.
 
+use std::time::Instant;
+
 use bitflags::bitflags;
 
 use foyer_common::code::{Key, Value};
 
-use crate::context::Context;
+use crate::context::{CachePriority, Context};
 
 bitflags! {
     #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
     struct BaseHandleFlags: u8 {
         const IN_INDEXER = 0b00000001;
         const IN_EVICTION = 0b00000010;
+        const SPARED = 0b00000100;
     }
 }
 
@@ -68,6 +71,10 @@ pub struct BaseHandle<T, C> {
     hash: u64,
     /// entry weight
     weight: usize,
+    /// entry priority
+    priority: CachePriority,
+    /// expiration time of the entry, `None` means never expire
+    expire_at: Option<Instant>,
     /// external reference count
     refs: usize,
     /// flags that used by the general cache abstraction
@@ -88,6 +95,8 @@ impl<T, C> BaseHandle<T, C> {
             entry: None,
             hash: 0,
             weight: 0,
+            priority: CachePriority::Normal,
+            expire_at: None,
             refs: 0,
             flags: BaseHandleFlags::empty(),
         }
@@ -101,6 +110,8 @@ impl<T, C> BaseHandle<T, C> {
         self.hash = hash;
         self.entry = Some((data, context));
         self.weight = weight;
+        self.priority = CachePriority::Normal;
+        self.expire_at = None;
         self.refs = 0;
         self.flags = BaseHandleFlags::empty();
     }
@@ -161,6 +172,36 @@ impl<T, C> BaseHandle<T, C> {
         self.weight
     }
 
+    /// Get the priority of the handle.
+    #[inline(always)]
+    pub fn priority(&self) -> CachePriority {
+        self.priority
+    }
+
+    /// Set the priority of the handle.
+    #[inline(always)]
+    pub fn set_priority(&mut self, priority: CachePriority) {
+        self.priority = priority;
+    }
+
+    /// Get the expiration time of the handle.
+    #[inline(always)]
+    pub fn expire_at(&self) -> Option<Instant> {
+        self.expire_at
+    }
+
+    /// Set the expiration time of the handle.
+    #[inline(always)]
+    pub fn set_expire_at(&mut self, expire_at: Option<Instant>) {
+        self.expire_at = expire_at;
+    }
+
+    /// Return `true` if the handle is expired at `now`.
+    #[inline(always)]
+    pub fn is_expired(&self, now: Instant) -> bool {
+        self.expire_at.is_some_and(|expire_at| expire_at <= now)
+    }
+
     /// Increase the external reference count of the handle, returns the new reference count.
     #[inline(always)]
     pub fn inc_refs(&mut self) -> usize {
@@ -220,6 +261,21 @@ impl<T, C> BaseHandle<T, C> {
     pub fn is_in_eviction(&self) -> bool {
         !(self.flags & BaseHandleFlags::IN_EVICTION).is_empty()
     }
+
+    /// Mark if the second chance of a high priority handle is used.
+    #[inline(always)]
+    pub fn set_spared(&mut self, spared: bool) {
+        if spared {
+            self.flags |= BaseHandleFlags::SPARED;
+        } else {
+            self.flags -= BaseHandleFlags::SPARED;
+        }
+    }
+
+    #[inline(always)]
+    pub fn is_spared(&self) -> bool {
+        !(self.flags & BaseHandleFlags::SPARED).is_empty()
+    }
 }
 
 #[cfg(test)]
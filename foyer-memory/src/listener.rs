@@ -12,11 +12,35 @@
 //  See the License for the specific language governing permissions and
 //  limitations under the License.
 
-use std::{marker::PhantomData, sync::Arc};
+use std::{marker::PhantomData, sync::Arc, time::Instant};
 
 use foyer_common::code::{Key, Value};
 
-use crate::CacheContext;
+use crate::{CacheContext, CachePriority};
+
+/// The reason why an entry is released by the cache.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReleaseReason {
+    /// The entry is evicted by the eviction algorithm to make room for new entries.
+    Evict,
+    /// The entry is replaced by a new entry with the same key.
+    Replace,
+    /// The entry is released by the last external user after it is evicted, replaced or removed.
+    Release,
+}
+
+/// An entry released by the cache, delivered to [`CacheEventListener::on_release_batch`].
+#[derive(Debug)]
+pub struct ReleasedEntry<K, V> {
+    pub key: Arc<K>,
+    pub value: Arc<V>,
+    pub context: CacheContext,
+    pub priority: CachePriority,
+    /// Expiration time of the entry, `None` means never expire.
+    pub expire_at: Option<Instant>,
+    pub weight: usize,
+    pub reason: ReleaseReason,
+}
 
 pub trait CacheEventListener<K, V>: Send + Sync + 'static
 where
@@ -27,6 +51,38 @@ where
     ///
     /// The arguments includes the key and value with ownership.
     fn on_release(&self, key: Arc<K>, value: Arc<V>, context: CacheContext, weight: usize);
+
+    /// The function is called when an entry is released by the cache and all external users, with the priority of the
+    /// entry.
+    ///
+    /// The default implementation ignores the priority and calls [`CacheEventListener::on_release`].
+    fn on_release_with_priority(
+        &self,
+        key: Arc<K>,
+        value: Arc<V>,
+        context: CacheContext,
+        _priority: CachePriority,
+        weight: usize,
+    ) {
+        self.on_release(key, value, context, weight)
+    }
+
+    /// The function is called when an entry is released by the cache and all external users, with all the attributes
+    /// of the entry, e.g. the expiration time.
+    ///
+    /// The default implementation calls [`CacheEventListener::on_release_with_priority`].
+    fn on_release_entry(&self, entry: ReleasedEntry<K, V>) {
+        self.on_release_with_priority(entry.key, entry.value, entry.context, entry.priority, entry.weight)
+    }
+
+    /// The function is called with a batch of released entries if release batching is enabled.
+    ///
+    /// The default implementation calls [`CacheEventListener::on_release_entry`] for each entry.
+    fn on_release_batch(&self, entries: Vec<ReleasedEntry<K, V>>) {
+        for entry in entries {
+            self.on_release_entry(entry);
+        }
+    }
 }
 
 pub struct DefaultCacheEventListener<K, V>(PhantomData<(K, V)>)
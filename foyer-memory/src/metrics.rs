@@ -20,6 +20,8 @@ pub struct Metrics {
     pub insert: AtomicUsize,
     /// successful replaces
     pub replace: AtomicUsize,
+    /// rejected inserts with `InsertMode::NoEvict`
+    pub reject: AtomicUsize,
 
     /// get hits
     pub hit: AtomicUsize,
@@ -33,6 +35,8 @@ pub struct Metrics {
 
     /// successful removes
     pub remove: AtomicUsize,
+    /// expired entries removed on get
+    pub expire: AtomicUsize,
 
     /// evicts from the eviction container
     pub evict: AtomicUsize,
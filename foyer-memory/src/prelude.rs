@@ -14,10 +14,10 @@
 
 pub use crate::{
     cache::{Cache, CacheBuilder, CacheEntry, Entry, EntryState, EvictionConfig},
-    context::CacheContext,
+    context::{CacheContext, CachePriority},
     eviction::{fifo::FifoConfig, lfu::LfuConfig, lru::LruConfig, s3fifo::S3FifoConfig},
-    generic::Weighter,
-    listener::{CacheEventListener, DefaultCacheEventListener},
+    generic::{CacheFull, InsertMode, ReleaseBatchConfig, ShouldEvict, Weighter},
+    listener::{CacheEventListener, DefaultCacheEventListener, ReleaseReason, ReleasedEntry},
     metrics::Metrics,
 };
 pub use ahash::RandomState;
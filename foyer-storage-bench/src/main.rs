@@ -142,7 +142,7 @@ pub struct Args {
     #[arg(long, default_value_t = false)]
     runtime: bool,
 
-    /// available values: "none", "zstd"
+    /// available values: "none", "zstd", "lz4", "snappy"
     #[arg(long, default_value = "none")]
     compression: String,
 
@@ -0,0 +1,67 @@
+//  Copyright 2024 Foyer Project Authors
+//
+//  Licensed under the Apache License, Version 2.0 (the "License");
+//  you may not use this file except in compliance with the License.
+//  You may obtain a copy of the License at
+//
+//  http://www.apache.org/licenses/LICENSE-2.0
+//
+//  Unless required by applicable law or agreed to in writing, software
+//  distributed under the License is distributed on an "AS IS" BASIS,
+//  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//  See the License for the specific language governing permissions and
+//  limitations under the License.
+
+use std::{hint::black_box, time::Instant};
+
+use foyer_storage::{checksum, ChecksumAlgorithm};
+use rand::{thread_rng, RngCore};
+
+const KB: usize = 1024;
+const MB: usize = 1024 * 1024;
+
+/// Total bytes validated for each value size and algorithm.
+const TOTAL: usize = 1024 * MB;
+
+/*
+cargo bench --bench bench_checksum
+*/
+
+/// Returns the validation throughput in MiB/s.
+fn bench_checksum(algorithm: ChecksumAlgorithm, value: &[u8]) -> f64 {
+    let loops = TOTAL / value.len();
+    let expected = checksum(algorithm, value);
+
+    let now = Instant::now();
+    for _ in 0..loops {
+        assert_eq!(checksum(algorithm, black_box(value)), expected);
+    }
+    let elapsed = now.elapsed();
+
+    (loops * value.len()) as f64 / MB as f64 / elapsed.as_secs_f64()
+}
+
+fn main() {
+    let algorithms = [
+        ChecksumAlgorithm::XxHash64,
+        ChecksumAlgorithm::Crc32c,
+        ChecksumAlgorithm::XxHash3,
+    ];
+
+    print!("{:>10}", "value size");
+    for algorithm in algorithms {
+        print!("{:>16}", format!("{:?}", algorithm));
+    }
+    println!();
+
+    for size in [4 * KB, 64 * KB, MB] {
+        let mut value = vec![0; size];
+        thread_rng().fill_bytes(&mut value);
+
+        print!("{:>10}", format!("{}KiB", size / KB));
+        for algorithm in algorithms {
+            print!("{:>16}", format!("{:.0} MiB/s", bench_checksum(algorithm, &value)));
+        }
+        println!();
+    }
+}
@@ -57,6 +57,34 @@ pub trait AdmissionPolicy: Send + Sync + 'static {
     fn init(&self, context: AdmissionContext<Self::Key, Self::Value>);
 
     fn judge(&self, key: &Arc<Self::Key>) -> bool;
+
+    /// Name of the policy reported to the [`AdmissionObserver`], the type name by default.
+    fn name(&self) -> &'static str {
+        std::any::type_name::<Self>()
+    }
+}
+
+/// Admission decision made by a policy for an entry.
+#[derive(Debug, Clone)]
+pub struct AdmissionDecision<K> {
+    /// Key of the judged entry.
+    pub key: Arc<K>,
+    /// Index of the policy in the configured admission policies.
+    pub policy: usize,
+    /// Name of the policy, see [`AdmissionPolicy::name`].
+    pub policy_name: &'static str,
+    /// `true` if the policy admits the entry.
+    pub admitted: bool,
+}
+
+/// Observer of the admission decisions, which can be used to audit and tune the admission policies.
+///
+/// The observer is called on the insertion path once per entry and policy, so it must be lightweight. The decisions
+/// are reported as made by the policies, even if the writer is forced to insert later.
+pub trait AdmissionObserver: Send + Sync + 'static + Debug {
+    type Key: StorageKey;
+
+    fn on_decision(&self, decision: AdmissionDecision<Self::Key>);
 }
 
 pub mod rated_ticket;
@@ -0,0 +1,198 @@
+//  Copyright 2024 Foyer Project Authors
+//
+//  Licensed under the Apache License, Version 2.0 (the "License");
+//  you may not use this file except in compliance with the License.
+//  You may obtain a copy of the License at
+//
+//  http://www.apache.org/licenses/LICENSE-2.0
+//
+//  Unless required by applicable law or agreed to in writing, software
+//  distributed under the License is distributed on an "AS IS" BASIS,
+//  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//  See the License for the specific language governing permissions and
+//  limitations under the License.
+
+//! Block compression, which compresses consecutive entries together.
+//!
+//! # Format
+//!
+//! A block is written as an entry with the block flag set in the entry header:
+//!
+//! | header | values (compressed) | metas | <padding> |
+//!
+//! - `values`: bincode-encoded values of the entries concatenated and compressed as a whole.
+//! - `metas`: bincode-encoded `(key, sequence, end)` of each entry, `end` is the end offset of the value in the
+//!   decompressed `values`. Metas are not compressed, so the recovery can index the entries without decompression.
+//!
+//! The sequence in the header is the sequence of the first entry of the block.
+
+use std::{collections::VecDeque, io::Read, sync::Arc};
+
+use anyhow::anyhow;
+use foyer_common::code::{StorageKey, StorageValue};
+use parking_lot::Mutex;
+
+use crate::{
+    buffer::BufferError,
+    catalog::Sequence,
+    compress::Compression,
+    error::Result,
+    generic::{checksum, EntryHeader},
+    region::RegionId,
+};
+
+pub type BlockMeta<K> = (K, Sequence, u32);
+
+/// Decompressed block.
+#[derive(Debug)]
+pub struct DecodedBlock<K> {
+    keys: Vec<Arc<K>>,
+    sequences: Vec<Sequence>,
+    ends: Vec<u32>,
+    values: Vec<u8>,
+}
+
+impl<K> DecodedBlock<K>
+where
+    K: StorageKey,
+{
+    /// Decode the block entry, checksum is verified only if `checksum` is `true`.
+    ///
+    /// # Safety
+    ///
+    /// `buf.len()` must exactly fit entry size
+    pub fn decode(buf: &[u8], checksum: bool) -> Result<Self> {
+        let header = EntryHeader::read(buf)?;
+        if !header.block {
+            return Err(anyhow!("entry is not a block").into());
+        }
+
+        let start = header.encoded_len();
+        let end = start + (header.value_len + header.key_len) as usize;
+
+        if checksum && header.checksummed {
+            let checksum = self::checksum(header.checksum_algorithm, &buf[start..end]);
+            if checksum != header.checksum {
+                return Err(anyhow!("checksum mismatch, expected: {}, got: {}", header.checksum, checksum).into());
+            }
+        }
+
+        let compressed = &buf[start..start + header.value_len as usize];
+        let mut values = vec![];
+        match header.compression {
+            Compression::None => values.extend_from_slice(compressed),
+            Compression::Zstd => {
+                zstd::Decoder::new(compressed)
+                    .map_err(BufferError::from)?
+                    .read_to_end(&mut values)
+                    .map_err(BufferError::from)?;
+            }
+            Compression::Lz4 => {
+                lz4::Decoder::new(compressed)
+                    .map_err(BufferError::from)?
+                    .read_to_end(&mut values)
+                    .map_err(BufferError::from)?;
+            }
+            Compression::Snappy => {
+                snap::read::FrameDecoder::new(compressed)
+                    .read_to_end(&mut values)
+                    .map_err(BufferError::from)?;
+            }
+        }
+
+        let metas: Vec<BlockMeta<K>> =
+            bincode::deserialize_from(&buf[start + header.value_len as usize..end]).map_err(BufferError::from)?;
+
+        let mut keys = Vec::with_capacity(metas.len());
+        let mut sequences = Vec::with_capacity(metas.len());
+        let mut ends = Vec::with_capacity(metas.len());
+        for (key, sequence, end) in metas {
+            if end as usize > values.len() || ends.last().is_some_and(|last| *last > end) {
+                return Err(anyhow!("invalid block value end: {}", end).into());
+            }
+            keys.push(Arc::new(key));
+            sequences.push(sequence);
+            ends.push(end);
+        }
+
+        Ok(Self {
+            keys,
+            sequences,
+            ends,
+            values,
+        })
+    }
+
+    pub fn key(&self, index: usize) -> Option<&Arc<K>> {
+        self.keys.get(index)
+    }
+
+    pub fn sequence(&self, index: usize) -> Option<Sequence> {
+        self.sequences.get(index).copied()
+    }
+
+    /// Bincode-encoded value of the entry at `index`.
+    pub fn value_bytes(&self, index: usize) -> Option<&[u8]> {
+        let end = *self.ends.get(index)? as usize;
+        let start = match index {
+            0 => 0,
+            i => self.ends[i - 1] as usize,
+        };
+        Some(&self.values[start..end])
+    }
+
+    pub fn value<V>(&self, index: usize) -> Result<V>
+    where
+        V: StorageValue,
+    {
+        let bytes = self
+            .value_bytes(index)
+            .ok_or_else(|| anyhow!("block index out of range, index: {}, len: {}", index, self.keys.len()))?;
+        let value = bincode::deserialize_from(bytes).map_err(BufferError::from)?;
+        Ok(value)
+    }
+}
+
+/// Cache of the recently decompressed blocks, in FIFO order.
+#[derive(Debug)]
+pub struct BlockCache<K> {
+    capacity: usize,
+    #[allow(clippy::type_complexity)]
+    blocks: Mutex<VecDeque<((RegionId, u32), Arc<DecodedBlock<K>>)>>,
+}
+
+impl<K> BlockCache<K>
+where
+    K: StorageKey,
+{
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            blocks: Mutex::new(VecDeque::with_capacity(capacity)),
+        }
+    }
+
+    /// Get the cached block at `offset` of `region`.
+    ///
+    /// The region may be reclaimed and rewritten after the block is cached, so the block is returned only if the
+    /// sequence of the entry at `index` matches.
+    pub fn get(&self, region: RegionId, offset: u32, index: usize, sequence: Sequence) -> Option<Arc<DecodedBlock<K>>> {
+        self.blocks
+            .lock()
+            .iter()
+            .find(|(position, block)| *position == (region, offset) && block.sequence(index) == Some(sequence))
+            .map(|(_, block)| block.clone())
+    }
+
+    pub fn insert(&self, region: RegionId, offset: u32, block: Arc<DecodedBlock<K>>) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut blocks = self.blocks.lock();
+        blocks.retain(|(position, _)| *position != (region, offset));
+        while blocks.len() >= self.capacity {
+            blocks.pop_front();
+        }
+        blocks.push_back(((region, offset), block));
+    }
+}
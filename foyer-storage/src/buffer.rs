@@ -12,20 +12,22 @@
 //  See the License for the specific language governing permissions and
 //  limitations under the License.
 
-use std::fmt::Debug;
+use std::{fmt::Debug, io::Write, sync::Arc, time::Duration};
 
 use allocator_api2::vec::Vec as VecA;
 use either::Either;
 use foyer_common::{
-    bits::{align_up, is_aligned},
+    bits::{align_up, is_aligned, is_pow2},
     code::{StorageKey, StorageValue},
 };
 
 use crate::{
-    compress::Compression,
+    compress::{BlockCompression, Compression, CompressionDecision, CompressionObserver},
+    cpu_budget::{CpuBudgetGuard, CpuBudgetHandle},
     device::{allocator::WritableVecA, Device, DeviceError},
     flusher::Entry,
-    generic::{checksum, EntryHeader},
+    generic::{checksum, key_hash, timestamp, ChecksumAlgorithm, EntryHeader, KEY_HASH_LEN},
+    io_limit::IoBytesLimiter,
     region::{RegionHeader, RegionId, Version, REGION_MAGIC},
 };
 
@@ -43,6 +45,99 @@ pub enum BufferError {
 
 pub type BufferResult<T> = core::result::Result<T, BufferError>;
 
+/// Padding applied to the stored values, so that their stored sizes don't reveal the exact value lengths.
+///
+/// The true value length is kept in the entry header, the padding is stripped on read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PaddingPolicy {
+    /// Store the values as they are.
+    #[default]
+    None,
+    /// Pad the (compressed) values up to the next power of 2 bytes.
+    PowerOfTwo,
+}
+
+impl PaddingPolicy {
+    /// Length of the value after padded.
+    pub fn padded_len(&self, len: usize) -> usize {
+        match self {
+            Self::None => len,
+            Self::PowerOfTwo => len.next_power_of_two(),
+        }
+    }
+
+    pub fn to_u8(&self) -> u8 {
+        match self {
+            Self::None => 0,
+            Self::PowerOfTwo => 1,
+        }
+    }
+}
+
+impl TryFrom<u8> for PaddingPolicy {
+    type Error = anyhow::Error;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(Self::None),
+            1 => Ok(Self::PowerOfTwo),
+            v => Err(anyhow::anyhow!("invalid padding policy: {}", v)),
+        }
+    }
+}
+
+/// Behavior of the flushers when writing the io buffer to the device fails.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FlushErrorPolicy {
+    /// Return the error, which stops the flusher.
+    #[default]
+    Abort,
+    /// Retry the write at most `retries` times, the backoff starts from `backoff` and doubles after each retry. The
+    /// error is returned if all retries fail.
+    Retry { retries: usize, backoff: Duration },
+    /// Drop the buffered entries with a warning and continue, their space in the region is left unused.
+    Drop,
+    /// Leave the region and write the buffered entries to a new clean region, at most `retries` times. The backoff
+    /// before each relocation starts from `backoff` and doubles after each retry. The error is returned if all
+    /// relocations fail.
+    ///
+    /// The left region is made evictable, so it is cleaned up by the reclaimer rather than leaked. The entries written
+    /// to it before stay readable until then.
+    Relocate { retries: usize, backoff: Duration },
+}
+
+/// Writer adapter that counts the bytes written into the inner writer.
+///
+/// Used to learn the serialized len of a value before it is compressed, while serializing it only once.
+struct CountingWriter<'a, W: Write> {
+    inner: W,
+    count: &'a mut usize,
+}
+
+impl<'a, W: Write> CountingWriter<'a, W> {
+    fn new(inner: W, count: &'a mut usize) -> Self {
+        Self { inner, count }
+    }
+}
+
+impl<'a, W: Write> Write for CountingWriter<'a, W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let len = self.inner.write(buf)?;
+        *self.count += len;
+        Ok(len)
+    }
+
+    fn write_all(&mut self, buf: &[u8]) -> std::io::Result<()> {
+        self.inner.write_all(buf)?;
+        *self.count += buf.len();
+        Ok(())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
 #[derive(Debug)]
 pub struct PositionedEntry<K, V>
 where
@@ -53,6 +148,8 @@ where
     pub region: RegionId,
     pub offset: usize,
     pub len: usize,
+    /// index of the entry within the block, `None` if the entry is not compressed in a block
+    pub index: Option<u32>,
 }
 
 pub struct FlushBuffer<K, V, D>
@@ -78,6 +175,57 @@ where
     device: D,
 
     default_buffer_capacity: usize,
+
+    /// calculate checksum for entries, or write zero instead
+    checksum: bool,
+
+    /// compression applied to all entries of a region, overrides the entry compression if set
+    region_compression: Option<Compression>,
+
+    /// alignment of entries packed in a device block, `None` if entries are aligned to device align
+    intra_block_align: Option<usize>,
+
+    /// observer of the compression decision of each entry
+    compression_observer: Option<Arc<dyn CompressionObserver>>,
+
+    /// compress consecutive entries together as a block if set
+    block_compression: Option<BlockCompression>,
+
+    /// offset of the first entry of a region, the space between the region header and it is reserved
+    first_entry_offset: Option<usize>,
+
+    /// entries waiting to be compressed as a block
+    block: Vec<Entry<K, V>>,
+
+    /// cpu budget for compression and checksum
+    cpu_budget: Option<CpuBudgetHandle>,
+
+    /// padding applied to the values to obscure their sizes
+    size_padding: PaddingPolicy,
+
+    /// write the key hash instead of the key
+    key_hash_only: bool,
+
+    /// level of zstd compression
+    zstd_level: i32,
+
+    /// algorithm of the entry checksums
+    checksum_algorithm: ChecksumAlgorithm,
+
+    /// behavior on a failed write to the device
+    flush_error_policy: FlushErrorPolicy,
+
+    /// entries dropped on failed writes, see [`FlushErrorPolicy::Drop`]
+    dropped: Vec<PositionedEntry<K, V>>,
+
+    /// limit of the total bytes of the in-flight device I/Os
+    io_limiter: IoBytesLimiter,
+
+    /// alignment of the flushes, `None` if flushes are aligned to device align
+    flush_alignment: Option<usize>,
+
+    /// write the value uncompressed if the compression doesn't make it smaller
+    adaptive_compression: bool,
 }
 
 impl<K, V, D> Debug for FlushBuffer<K, V, D>
@@ -91,6 +239,20 @@ where
             .field("region", &self.region)
             .field("offset", &self.offset)
             .field("default_buffer_capacity", &self.default_buffer_capacity)
+            .field("checksum", &self.checksum)
+            .field("region_compression", &self.region_compression)
+            .field("intra_block_align", &self.intra_block_align)
+            .field("compression_observer", &self.compression_observer)
+            .field("block_compression", &self.block_compression)
+            .field("first_entry_offset", &self.first_entry_offset)
+            .field("size_padding", &self.size_padding)
+            .field("key_hash_only", &self.key_hash_only)
+            .field("zstd_level", &self.zstd_level)
+            .field("checksum_algorithm", &self.checksum_algorithm)
+            .field("flush_error_policy", &self.flush_error_policy)
+            .field("io_limiter", &self.io_limiter)
+            .field("flush_alignment", &self.flush_alignment)
+            .field("adaptive_compression", &self.adaptive_compression)
             .finish()
     }
 }
@@ -101,8 +263,81 @@ where
     V: StorageValue,
     D: Device,
 {
-    pub fn new(device: D) -> Self {
-        let default_buffer_capacity = align_up(device.align(), device.io_size() + device.io_size() / 2);
+    // TODO(MrCroxx): use `expect` after `lint_reasons` is stable.
+    #[allow(clippy::too_many_arguments, clippy::manual_is_multiple_of)]
+    pub fn new(
+        device: D,
+        checksum: bool,
+        region_compression: Option<Compression>,
+        intra_block_align: Option<usize>,
+        compression_observer: Option<Arc<dyn CompressionObserver>>,
+        block_compression: Option<BlockCompression>,
+        first_entry_offset: Option<usize>,
+        cpu_budget: Option<CpuBudgetHandle>,
+        size_padding: PaddingPolicy,
+        key_hash_only: bool,
+        zstd_level: i32,
+        checksum_algorithm: ChecksumAlgorithm,
+        flush_error_policy: FlushErrorPolicy,
+        io_limiter: IoBytesLimiter,
+        flush_alignment: Option<usize>,
+        adaptive_compression: bool,
+    ) -> Self {
+        if let Some(intra_block_align) = intra_block_align {
+            assert!(
+                is_pow2(intra_block_align)
+                    && intra_block_align >= EntryHeader::serialized_len()
+                    && intra_block_align <= device.align(),
+                "intra block align must be a power of 2 in [entry header size, device align], given: {}",
+                intra_block_align
+            );
+        }
+        if let Some(block_compression) = block_compression {
+            assert!(
+                block_compression.entries > 0,
+                "block compression entries must be > 0, given: {}",
+                block_compression.entries
+            );
+        }
+        assert!(
+            zstd::compression_level_range().contains(&zstd_level),
+            "zstd level must be in {:?}, given: {}",
+            zstd::compression_level_range(),
+            zstd_level
+        );
+        assert!(
+            !key_hash_only || block_compression.is_none(),
+            "storing key hashes only is not supported with block compression"
+        );
+        if let Some(first_entry_offset) = first_entry_offset {
+            assert!(
+                is_aligned(device.align(), first_entry_offset)
+                    && first_entry_offset >= device.align()
+                    && first_entry_offset < device.region_size(),
+                "first entry offset must be aligned to device align and in [device align, region size), given: {}",
+                first_entry_offset
+            );
+        }
+
+        if let Some(flush_alignment) = flush_alignment {
+            assert!(
+                is_pow2(flush_alignment)
+                    && flush_alignment >= device.align()
+                    && device.region_size() % flush_alignment == 0,
+                "flush alignment must be a power of 2 no less than device align that divides region size, given: {}",
+                flush_alignment
+            );
+        }
+
+        // Entries are packed back-to-back if the device doesn't require aligned writes.
+        let intra_block_align = if device.requires_alignment() {
+            intra_block_align
+        } else {
+            Some(1)
+        };
+
+        let flush_threshold = align_up(flush_alignment.unwrap_or(device.align()), device.write_io_size());
+        let default_buffer_capacity = align_up(device.align(), flush_threshold + flush_threshold / 2);
         let buffer = device.io_buffer(0, default_buffer_capacity);
         Self {
             buffer,
@@ -111,13 +346,82 @@ where
             entries: vec![],
             device,
             default_buffer_capacity,
+            checksum,
+            region_compression,
+            intra_block_align,
+            compression_observer,
+            block_compression,
+            first_entry_offset,
+            block: vec![],
+            cpu_budget,
+            size_padding,
+            key_hash_only,
+            zstd_level,
+            checksum_algorithm,
+            flush_error_policy,
+            dropped: vec![],
+            io_limiter,
+            flush_alignment,
+            adaptive_compression,
         }
     }
 
+    #[cfg(test)]
     pub fn region(&self) -> Option<RegionId> {
         self.region
     }
 
+    /// Write offset of the current region, including the bytes in the io buffer.
+    #[cfg(test)]
+    pub fn offset(&self) -> usize {
+        self.offset + self.buffer.len()
+    }
+
+    /// Move the write offset of the current region, e.g. to leave a gap or to overwrite the written entries.
+    ///
+    /// Only for crafting region states in tests, the io buffer must be flushed before.
+    #[cfg(test)]
+    pub fn set_offset(&mut self, offset: usize) {
+        assert!(self.region.is_some(), "no region is being written");
+        assert!(
+            self.buffer.is_empty() && self.block.is_empty(),
+            "io buffer must be flushed"
+        );
+        assert!(
+            is_aligned(self.device.align(), offset) && offset <= self.device.region_size(),
+            "offset ({offset}) must be aligned to {} and within the region",
+            self.device.align()
+        );
+        self.offset = offset;
+    }
+
+    /// Take the entries dropped on failed writes since the last call.
+    pub fn take_dropped(&mut self) -> Vec<PositionedEntry<K, V>> {
+        std::mem::take(&mut self.dropped)
+    }
+
+    pub fn flush_error_policy(&self) -> FlushErrorPolicy {
+        self.flush_error_policy
+    }
+
+    /// Leave the current region after a failed flush, the following writes require a new region.
+    ///
+    /// Returns the entries of the failed flush in order, to be written again to the new region.
+    pub fn abandon(&mut self) -> Vec<Entry<K, V>> {
+        self.region = None;
+        self.offset = 0;
+        self.buffer.clear();
+        std::mem::take(&mut self.entries)
+            .into_iter()
+            .map(|positioned| positioned.entry)
+            .collect()
+    }
+
+    /// Alignment of entries in the io buffer.
+    fn entry_align(&self) -> usize {
+        self.intra_block_align.unwrap_or(self.device.align())
+    }
+
     pub fn remaining(&self) -> usize {
         if self.region.is_none() {
             0
@@ -130,22 +434,46 @@ where
 
     /// Flush io buffer if necessary, and reset io buffer to a new region.
     ///
+    /// The `generation` of the new region is recorded in its region header.
+    ///
     /// Returns fully flushed entries.
-    pub async fn rotate(&mut self, region: RegionId) -> BufferResult<Vec<PositionedEntry<K, V>>> {
+    pub async fn rotate(&mut self, region: RegionId, generation: u64) -> BufferResult<Vec<PositionedEntry<K, V>>> {
         let entries = self.flush().await?;
         debug_assert!(self.buffer.is_empty());
         self.region = Some(region);
         self.offset = 0;
 
-        // write region header
-        unsafe { self.buffer.set_len(self.device.align()) };
+        // write region header, and reserve the space before the first entry
+        let first_entry_offset = self.first_entry_offset.unwrap_or(self.device.align());
+        if first_entry_offset == self.device.align() {
+            unsafe { self.buffer.set_len(self.device.align()) };
+        } else {
+            self.buffer.resize(first_entry_offset, 0);
+        }
         let header = RegionHeader {
             magic: REGION_MAGIC,
             version: Version::latest(),
+            compression: self.region_compression,
+            intra_block_align: self.intra_block_align.map(|align| align as u32),
+            first_entry_offset: self.first_entry_offset.map(|offset| offset as u32),
+            size_padding: self.size_padding,
+            flush_alignment: self.flush_alignment.map(|alignment| alignment as u32),
+            generation,
+            filter: None,
         };
         header.write(&mut self.buffer[..]);
-        debug_assert_eq!(self.buffer.len(), self.device.align());
+        debug_assert_eq!(self.buffer.len(), first_entry_offset);
+
+        Ok(entries)
+    }
 
+    /// Flush io buffer and leave the current region even if it is not full, the following writes require a new region.
+    ///
+    /// Returns fully flushed entries.
+    pub async fn seal(&mut self) -> BufferResult<Vec<PositionedEntry<K, V>>> {
+        let entries = self.flush().await?;
+        self.region = None;
+        self.offset = 0;
         Ok(entries)
     }
 
@@ -160,8 +488,25 @@ where
             return Ok(vec![]);
         };
 
+        // Write the pending entries as a partial block. If it exceeds the region size, the entries are kept and
+        // written to the next region.
+        if !self.block.is_empty() {
+            let guard = self.acquire_cpu_budget().await;
+            self.write_block()?;
+            release_cpu_budget(guard).await;
+        }
+
         // align io buffer
-        let len = align_up(self.device.align(), self.buffer.len());
+        let len = match self.flush_alignment {
+            Some(flush_alignment) => align_up(flush_alignment, self.buffer.len()),
+            None if self.device.requires_alignment() => align_up(self.device.align(), self.buffer.len()),
+            None => self.buffer.len(),
+        };
+        if self.intra_block_align.is_some() || self.flush_alignment.is_some() {
+            // Entries are packed in blocks, or the flush is padded to the flush alignment. Zero the padding so the
+            // region iterator can tell it from an entry.
+            self.buffer.resize(len, 0);
+        }
         debug_assert!(len <= self.buffer.capacity());
         unsafe { self.buffer.set_len(len) };
         debug_assert!(self.offset + self.buffer.len() <= self.device.region_size());
@@ -170,8 +515,37 @@ where
         let mut buf = self.device.io_buffer(0, self.default_buffer_capacity);
         std::mem::swap(&mut self.buffer, &mut buf);
 
-        let (res, _buf) = self.device.write(buf, .., region, self.offset).await;
-        res?;
+        let mut retries = 0;
+        loop {
+            let permit = self.io_limiter.acquire(buf.len()).await;
+            let (res, b) = self.device.write(buf, .., region, self.offset).await;
+            drop(permit);
+            buf = b;
+            let e = match res {
+                Ok(_) => break,
+                Err(e) => e,
+            };
+            match self.flush_error_policy {
+                FlushErrorPolicy::Retry { retries: max, backoff } if retries < max => {
+                    let backoff = backoff.saturating_mul(1u32 << retries.min(31));
+                    tracing::warn!("[buffer]: write to region {region} error, retry after {backoff:?}: {e}");
+                    retries += 1;
+                    tokio::time::sleep(backoff).await;
+                }
+                FlushErrorPolicy::Drop => {
+                    tracing::warn!(
+                        "[buffer]: write to region {region} error, drop {} entries: {e}",
+                        self.entries.len()
+                    );
+                    self.dropped.append(&mut self.entries);
+                    break;
+                }
+                // The entries are kept for the flusher to relocate, see `FlushBuffer::abandon`.
+                FlushErrorPolicy::Abort | FlushErrorPolicy::Retry { .. } | FlushErrorPolicy::Relocate { .. } => {
+                    return Err(e.into())
+                }
+            }
+        }
 
         // advance io buffer
         self.offset += len;
@@ -186,14 +560,14 @@ where
 
     /// Write entry to io buffer.
     ///
-    /// The io buffer may be flushed if buffer size equals or exceeds device io size.
+    /// The io buffer may be flushed if buffer size equals or exceeds device write io size.
     ///
     /// Returns fully flushed entries if there is enough space in the current region.
     /// Otherwise, returns `NotEnough` error with the given `entry`.
     ///
     /// # Format
     ///
-    /// | header | value (compressed) | key | <padding> |
+    /// | header | value (compressed) | key (or key hash) | <value padding> | <padding> |
     // TODO(MrCroxx): use `expect` after `lint_reasons` is stable.
     #[allow(clippy::uninit_vec)]
     pub async fn write(
@@ -203,6 +577,8 @@ where
             value,
             sequence,
             compression,
+            enqueued,
+            expire_at,
         }: Entry<K, V>,
     ) -> BufferResult<Either<Vec<PositionedEntry<K, V>>, Entry<K, V>>> {
         // Notify caller to rotate buffer if there is not enough space for the entry.
@@ -220,64 +596,146 @@ where
                 value,
                 sequence,
                 compression,
+                enqueued,
+                expire_at,
             }));
         }
 
+        // The block header has no room for the expire at of each entry, write the entries with ttl on their own.
+        if let Some(block_compression) = self.block_compression.filter(|_| expire_at.is_none()) {
+            self.block.push(Entry {
+                key,
+                value,
+                sequence,
+                compression,
+                enqueued,
+                expire_at,
+            });
+            if self.block.len() < block_compression.entries {
+                return Ok(Either::Left(vec![]));
+            }
+            // If the block exceeds the region size, notify caller to rotate buffer with the last entry, the rest
+            // entries are written to either the current region on flush or the next region.
+            let guard = self.acquire_cpu_budget().await;
+            if !self.write_block()? {
+                return Ok(Either::Right(self.block.pop().unwrap()));
+            }
+            release_cpu_budget(guard).await;
+            return self.flush_if_needed().await.map(Either::Left);
+        }
+
+        let guard = self.acquire_cpu_budget().await;
+
         let old = self.buffer.len();
-        debug_assert!(is_aligned(self.device.align(), old));
+        debug_assert!(is_aligned(self.entry_align(), old));
 
         let mut cursor = old;
 
         // TODO(MrCroxx): reserve buffer capacity for entry
 
         // reserve space for header, header will be filled after the serialized len is known
-        cursor += EntryHeader::serialized_len();
+        let header_len = match expire_at {
+            Some(_) => EntryHeader::serialized_len_with_expire_at(),
+            None => EntryHeader::serialized_len(),
+        };
+        cursor += header_len;
         unsafe { self.buffer.set_len(cursor) };
 
-        // write value
-        match compression {
+        // the region level compression overrides the entry compression
+        let codec = self.region_compression.unwrap_or(compression);
+
+        // with adaptive compression, the raw value is written first and replaced if the compressed one is smaller
+        let adaptive = self.adaptive_compression && codec != Compression::None;
+
+        // write value, count the serialized len before compression on the way to avoid serializing it twice
+        let mut serialized_value_len = 0;
+        match if adaptive { Compression::None } else { codec } {
             Compression::None => {
-                bincode::serialize_into(WritableVecA(&mut self.buffer), &value).map_err(BufferError::from)?;
+                let mut writer = CountingWriter::new(WritableVecA(&mut self.buffer), &mut serialized_value_len);
+                bincode::serialize_into(&mut writer, &value).map_err(BufferError::from)?;
             }
             Compression::Zstd => {
-                let encoder = zstd::Encoder::new(WritableVecA(&mut self.buffer), 0)
+                let encoder = zstd::Encoder::new(WritableVecA(&mut self.buffer), self.zstd_level)
                     .map_err(BufferError::from)?
                     .auto_finish();
-                bincode::serialize_into(encoder, &value).map_err(BufferError::from)?;
+                let mut writer = CountingWriter::new(encoder, &mut serialized_value_len);
+                bincode::serialize_into(&mut writer, &value).map_err(BufferError::from)?;
             }
 
             Compression::Lz4 => {
-                let encoder = lz4::EncoderBuilder::new()
+                let mut encoder = lz4::EncoderBuilder::new()
                     .checksum(lz4::ContentChecksum::NoChecksum)
-                    .auto_flush(true)
                     .build(WritableVecA(&mut self.buffer))
                     .map_err(BufferError::from)?;
-                bincode::serialize_into(encoder, &value).map_err(BufferError::from)?;
+                let mut writer = CountingWriter::new(&mut encoder, &mut serialized_value_len);
+                bincode::serialize_into(&mut writer, &value).map_err(BufferError::from)?;
+                let (_, res) = encoder.finish();
+                res.map_err(BufferError::from)?;
+            }
+            Compression::Snappy => {
+                let mut encoder = snap::write::FrameEncoder::new(WritableVecA(&mut self.buffer));
+                let mut writer = CountingWriter::new(&mut encoder, &mut serialized_value_len);
+                bincode::serialize_into(&mut writer, &value).map_err(BufferError::from)?;
+                encoder.flush().map_err(BufferError::from)?;
             }
         }
 
+        let codec = if adaptive {
+            let mut compressed = vec![];
+            compress(codec, self.zstd_level, &self.buffer[cursor..], &mut compressed)?;
+            if compressed.len() < serialized_value_len {
+                self.buffer.truncate(cursor);
+                self.buffer.extend_from_slice(&compressed);
+                codec
+            } else {
+                Compression::None
+            }
+        } else {
+            codec
+        };
+
         let compressed_value_len = self.buffer.len() - cursor;
         cursor = self.buffer.len();
 
-        // write key
+        // write key, or replace the encoded key with its hash
         bincode::serialize_into(WritableVecA(&mut self.buffer), &key).map_err(BufferError::from)?;
+        if self.key_hash_only {
+            let hash = key_hash(&self.buffer[cursor..]);
+            self.buffer.truncate(cursor);
+            self.buffer.extend_from_slice(&hash.to_le_bytes());
+            debug_assert_eq!(self.buffer.len() - cursor, KEY_HASH_LEN);
+        }
         let encoded_key_len = self.buffer.len() - cursor;
         cursor = self.buffer.len();
 
-        // calculate checksum
+        self.pad_value(compressed_value_len);
+
+        // calculate checksum, write zero instead if checksum is disabled
         cursor -= compressed_value_len + encoded_key_len;
-        let checksum = checksum(&self.buffer[cursor..cursor + compressed_value_len + encoded_key_len]);
+        let checksum = if self.checksum {
+            checksum(
+                self.checksum_algorithm,
+                &self.buffer[cursor..cursor + compressed_value_len + encoded_key_len],
+            )
+        } else {
+            0
+        };
 
         // write entry header
-        cursor -= EntryHeader::serialized_len();
+        cursor -= header_len;
         let header = EntryHeader {
             key_len: encoded_key_len as u32,
             value_len: compressed_value_len as u32,
             sequence,
-            compression,
+            compression: codec,
             checksum,
+            block: false,
+            key_hash: self.key_hash_only,
+            checksum_algorithm: self.checksum_algorithm,
+            checksummed: self.checksum,
+            expire_at: expire_at.map(timestamp),
         };
-        header.write(&mut self.buffer[cursor..cursor + EntryHeader::serialized_len()]);
+        header.write(&mut self.buffer[cursor..cursor + header_len]);
 
         // (*) if size exceeds region limit, rollback write and return
         if self.offset + self.buffer.len() > self.device.region_size() {
@@ -287,11 +745,22 @@ where
                 value,
                 sequence,
                 compression,
+                enqueued,
+                expire_at,
             }));
         }
 
+        if let Some(observer) = self.compression_observer.as_ref() {
+            observer.on_decision(CompressionDecision {
+                requested: compression,
+                chosen: codec,
+                original_size: serialized_value_len,
+                compressed_size: compressed_value_len,
+            });
+        }
+
         // 3. align buffer size
-        let target = align_up(self.device.align(), self.buffer.len());
+        let target = align_up(self.entry_align(), self.buffer.len());
         self.buffer.reserve(target - self.buffer.len());
         unsafe { self.buffer.set_len(target) }
 
@@ -301,31 +770,232 @@ where
                 value,
                 sequence,
                 compression,
+                enqueued,
+                expire_at,
             },
             region: self.region.unwrap(),
             offset: self.offset + old,
             len: self.buffer.len() - old,
+            index: None,
         });
 
-        // flush if buffer equals or exceeds device io size
-        let entries = if self.buffer.len() >= self.device.io_size() || self.remaining() == 0 {
-            self.flush().await?
+        release_cpu_budget(guard).await;
+
+        let entries = self.flush_if_needed().await?;
+
+        Ok(Either::Left(entries))
+    }
+
+    /// Acquire the cpu budget before compression and checksum, `None` if there is no cpu budget.
+    async fn acquire_cpu_budget(&self) -> Option<CpuBudgetGuard> {
+        match self.cpu_budget.as_ref() {
+            Some(handle) => Some(handle.acquire().await),
+            None => None,
+        }
+    }
+
+    /// Flush if buffer equals or exceeds device write io size, rounded up to the flush alignment if set.
+    async fn flush_if_needed(&mut self) -> BufferResult<Vec<PositionedEntry<K, V>>> {
+        let threshold = match self.flush_alignment {
+            Some(flush_alignment) => align_up(flush_alignment, self.device.write_io_size()),
+            None => self.device.write_io_size(),
+        };
+        if self.buffer.len() >= threshold || self.remaining() == 0 {
+            self.flush().await
         } else {
-            vec![]
+            Ok(vec![])
+        }
+    }
+
+    /// Write the pending entries to io buffer as a block.
+    ///
+    /// Returns `false` and keeps the pending entries if the block exceeds the region size.
+    ///
+    /// # Format
+    ///
+    /// | header | values (compressed) | metas | <values padding> | <padding> |
+    ///
+    /// See [`crate::block`] for details.
+    // TODO(MrCroxx): use `expect` after `lint_reasons` is stable.
+    #[allow(clippy::uninit_vec)]
+    fn write_block(&mut self) -> BufferResult<bool> {
+        debug_assert!(!self.block.is_empty());
+        let block_compression = self.block_compression.unwrap();
+
+        let old = self.buffer.len();
+        debug_assert!(is_aligned(self.entry_align(), old));
+
+        let mut cursor = old;
+
+        // reserve space for header, header will be filled after the serialized len is known
+        cursor += EntryHeader::serialized_len();
+        unsafe { self.buffer.set_len(cursor) };
+
+        // the region level compression overrides the block compression
+        let codec = self.region_compression.unwrap_or(block_compression.compression);
+
+        // concat values and record the end offset of each value
+        let mut values = vec![];
+        let mut ends = Vec::with_capacity(self.block.len());
+        for entry in self.block.iter() {
+            bincode::serialize_into(&mut values, entry.value.as_ref()).map_err(BufferError::from)?;
+            ends.push(values.len() as u32);
+        }
+
+        // write values, or the raw values if the compression doesn't make them smaller with adaptive compression
+        let codec = if self.adaptive_compression && codec != Compression::None {
+            let mut compressed = vec![];
+            compress(codec, self.zstd_level, &values, &mut compressed)?;
+            if compressed.len() < values.len() {
+                self.buffer.extend_from_slice(&compressed);
+                codec
+            } else {
+                self.buffer.extend_from_slice(&values);
+                Compression::None
+            }
+        } else {
+            compress(codec, self.zstd_level, &values, WritableVecA(&mut self.buffer))?;
+            codec
         };
 
-        Ok(Either::Left(entries))
+        let compressed_values_len = self.buffer.len() - cursor;
+        cursor = self.buffer.len();
+
+        // write metas
+        let metas = self
+            .block
+            .iter()
+            .zip(ends)
+            .map(|(entry, end)| (entry.key.as_ref(), entry.sequence, end))
+            .collect::<Vec<_>>();
+        bincode::serialize_into(WritableVecA(&mut self.buffer), &metas).map_err(BufferError::from)?;
+        let encoded_metas_len = self.buffer.len() - cursor;
+        cursor = self.buffer.len();
+
+        self.pad_value(compressed_values_len);
+
+        // calculate checksum, write zero instead if checksum is disabled
+        cursor -= compressed_values_len + encoded_metas_len;
+        let checksum = if self.checksum {
+            checksum(
+                self.checksum_algorithm,
+                &self.buffer[cursor..cursor + compressed_values_len + encoded_metas_len],
+            )
+        } else {
+            0
+        };
+
+        // write entry header
+        cursor -= EntryHeader::serialized_len();
+        let header = EntryHeader {
+            key_len: encoded_metas_len as u32,
+            value_len: compressed_values_len as u32,
+            sequence: self.block[0].sequence,
+            compression: codec,
+            checksum,
+            block: true,
+            key_hash: false,
+            checksum_algorithm: self.checksum_algorithm,
+            checksummed: self.checksum,
+            expire_at: None,
+        };
+        header.write(&mut self.buffer[cursor..cursor + EntryHeader::serialized_len()]);
+
+        // rollback write if size exceeds region limit
+        if self.offset + self.buffer.len() > self.device.region_size() {
+            unsafe { self.buffer.set_len(old) };
+            return Ok(false);
+        }
+
+        if let Some(observer) = self.compression_observer.as_ref() {
+            observer.on_decision(CompressionDecision {
+                requested: block_compression.compression,
+                chosen: codec,
+                original_size: values.len(),
+                compressed_size: compressed_values_len,
+            });
+        }
+
+        // align buffer size
+        let target = align_up(self.entry_align(), self.buffer.len());
+        self.buffer.reserve(target - self.buffer.len());
+        unsafe { self.buffer.set_len(target) }
+
+        let region = self.region.unwrap();
+        let len = self.buffer.len() - old;
+        for (index, entry) in self.block.drain(..).enumerate() {
+            self.entries.push(PositionedEntry {
+                entry,
+                region,
+                offset: self.offset + old,
+                len,
+                index: Some(index as u32),
+            });
+        }
+
+        Ok(true)
+    }
+
+    /// Append zeros after the key to pad the value by the padding policy.
+    fn pad_value(&mut self, value_len: usize) {
+        let padding = self.size_padding.padded_len(value_len) - value_len;
+        self.buffer.resize(self.buffer.len() + padding, 0);
+    }
+}
+
+/// Compress the serialized bytes with `codec` and append them to `writer`.
+fn compress<W: Write>(codec: Compression, zstd_level: i32, bytes: &[u8], mut writer: W) -> BufferResult<()> {
+    match codec {
+        Compression::None => {
+            writer.write_all(bytes).map_err(BufferError::from)?;
+        }
+        Compression::Zstd => {
+            let mut encoder = zstd::Encoder::new(writer, zstd_level)
+                .map_err(BufferError::from)?
+                .auto_finish();
+            encoder.write_all(bytes).map_err(BufferError::from)?;
+        }
+        Compression::Lz4 => {
+            let mut encoder = lz4::EncoderBuilder::new()
+                .checksum(lz4::ContentChecksum::NoChecksum)
+                .build(writer)
+                .map_err(BufferError::from)?;
+            encoder.write_all(bytes).map_err(BufferError::from)?;
+            let (_, res) = encoder.finish();
+            res.map_err(BufferError::from)?;
+        }
+        Compression::Snappy => {
+            let mut encoder = snap::write::FrameEncoder::new(writer);
+            encoder.write_all(bytes).map_err(BufferError::from)?;
+            encoder.flush().map_err(BufferError::from)?;
+        }
+    }
+    Ok(())
+}
+
+async fn release_cpu_budget(guard: Option<CpuBudgetGuard>) {
+    if let Some(guard) = guard {
+        guard.release().await;
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use std::sync::Arc;
+    use std::{sync::Arc, time::Instant};
 
     use tempfile::tempdir;
 
     use super::*;
-    use crate::device::fs::{FsDevice, FsDeviceConfig};
+    use crate::{
+        block::DecodedBlock,
+        device::{
+            executor::IoExecutor,
+            fs::{FsDevice, FsDeviceConfig, RegionOpenErrorPolicy},
+            tests::UnalignedMemDevice,
+        },
+        generic::RegionEntryIter,
+        region::Region,
+    };
 
     fn ent(size: usize) -> Entry<(), Vec<u8>> {
         Entry {
@@ -333,6 +1003,8 @@ mod tests {
             value: Arc::new(vec![b'x'; size]),
             compression: Compression::None,
             sequence: 0,
+            enqueued: Instant::now(),
+            expire_at: None,
         }
     }
 
@@ -340,10 +1012,7 @@ mod tests {
         for positioned in positioneds {
             let b = &buf[positioned.offset..positioned.offset + positioned.len];
             let h = EntryHeader::read(b).unwrap();
-            let v: &[u8] = bincode::deserialize(
-                &b[EntryHeader::serialized_len()..EntryHeader::serialized_len() + h.value_len as usize],
-            )
-            .unwrap();
+            let v: &[u8] = bincode::deserialize(&b[h.encoded_len()..h.encoded_len() + h.value_len as usize]).unwrap();
             assert_eq!(v, positioned.entry.value.as_ref());
         }
     }
@@ -358,11 +1027,34 @@ mod tests {
             file_size: 64 * 1024, // 64 KiB
             align: 4 * 1024,      // 4 KiB
             io_size: 16 * 1024,   // 16 KiB
+            io_executor: IoExecutor::default(),
+            on_region_open_error: RegionOpenErrorPolicy::default(),
+            write_io_size: None,
+            read_io_size: None,
+            extra_dirs: vec![],
+            direct_io: true,
         })
         .await
         .unwrap();
 
-        let mut buffer = FlushBuffer::new(device.clone());
+        let mut buffer = FlushBuffer::new(
+            device.clone(),
+            true,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            PaddingPolicy::None,
+            false,
+            0,
+            ChecksumAlgorithm::default(),
+            FlushErrorPolicy::default(),
+            IoBytesLimiter::default(),
+            None,
+            false,
+        );
         assert_eq!(buffer.region(), None);
 
         {
@@ -375,7 +1067,7 @@ mod tests {
                 _ => panic!("got: {:?}", res),
             };
 
-            let entries = buffer.rotate(0).await.unwrap();
+            let entries = buffer.rotate(0, 0).await.unwrap();
             assert!(entries.is_empty());
 
             // 4 ~ 12 KiB
@@ -415,7 +1107,7 @@ mod tests {
                 _ => panic!("got: {:?}", res),
             };
 
-            let entries = buffer.rotate(1).await.unwrap();
+            let entries = buffer.rotate(1, 0).await.unwrap();
             assert!(entries.is_empty());
 
             // 4 ~ 60 KiB
@@ -441,4 +1133,765 @@ mod tests {
             assert!(buffer.entries.is_empty());
         }
     }
+
+    #[tokio::test]
+    async fn test_packed_entries_without_alignment() {
+        let device = UnalignedMemDevice::new(4, 64 * 1024);
+        assert!(!device.requires_alignment());
+
+        let mut buffer = FlushBuffer::new(
+            device.clone(),
+            true,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            PaddingPolicy::None,
+            false,
+            0,
+            ChecksumAlgorithm::default(),
+            FlushErrorPolicy::default(),
+            IoBytesLimiter::default(),
+            None,
+            false,
+        );
+        buffer.rotate(0, 0).await.unwrap();
+
+        let mut positioneds = vec![];
+        for i in 0..16 {
+            let mut entry = ent(100 + i * 397);
+            entry.sequence = i as u64;
+            positioneds.extend(buffer.write(entry).await.unwrap().unwrap_left());
+        }
+        positioneds.extend(buffer.flush().await.unwrap());
+        assert_eq!(positioneds.len(), 16);
+
+        // Entries are written back-to-back without padding, the header goes right after the previous entry.
+        assert_eq!(positioneds[0].offset, device.align());
+        for (prev, next) in positioneds.iter().zip(positioneds.iter().skip(1)) {
+            assert_eq!(prev.offset + prev.len, next.offset);
+        }
+        let last = positioneds.last().unwrap();
+        assert_eq!(buffer.offset, last.offset + last.len);
+        assert!(!is_aligned(device.align(), buffer.offset));
+
+        let buf = device.io_buffer(64 * 1024, 64 * 1024);
+        let (res, buf) = device.read(buf, .., 0, 0).await;
+        res.unwrap();
+
+        let mut iter =
+            RegionEntryIter::<(), Vec<u8>, _>::open(Region::new(0, device.clone(), IoBytesLimiter::default()), true)
+                .await
+                .unwrap()
+                .unwrap();
+        for positioned in positioneds.iter() {
+            let (_, value, len) = iter.next_kv().await.unwrap().unwrap();
+            assert_eq!(&value, positioned.entry.value.as_ref());
+            assert_eq!(len, positioned.len);
+        }
+        assert!(iter.next_kv().await.unwrap().is_none());
+
+        assert_buffer(positioneds, &buf);
+    }
+
+    #[tokio::test]
+    async fn test_size_padding() {
+        assert_eq!(PaddingPolicy::None.padded_len(1000), 1000);
+        assert_eq!(PaddingPolicy::PowerOfTwo.padded_len(1000), 1024);
+        assert_eq!(PaddingPolicy::PowerOfTwo.padded_len(1024), 1024);
+
+        // Entries are packed without alignment padding, so the footprints only differ by the value padding.
+        let device = UnalignedMemDevice::new(4, 64 * 1024);
+        let mut buffer = FlushBuffer::new(
+            device.clone(),
+            true,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            PaddingPolicy::PowerOfTwo,
+            false,
+            0,
+            ChecksumAlgorithm::default(),
+            FlushErrorPolicy::default(),
+            IoBytesLimiter::default(),
+            None,
+            false,
+        );
+        buffer.rotate(0, 0).await.unwrap();
+
+        let sizes = [1020, 1025, 1030, 1035];
+        let mut positioneds = vec![];
+        for size in sizes {
+            positioneds.extend(buffer.write(ent(size)).await.unwrap().unwrap_left());
+        }
+        positioneds.extend(buffer.flush().await.unwrap());
+
+        // The serialized values are 1028 ~ 1043 bytes, they are all padded to 2048 bytes.
+        for positioned in positioneds.iter() {
+            assert_eq!(positioned.len, EntryHeader::serialized_len() + 2048);
+        }
+
+        let mut iter = RegionEntryIter::<(), Vec<u8>, _>::open(Region::new(0, device, IoBytesLimiter::default()), true)
+            .await
+            .unwrap()
+            .unwrap();
+        for size in sizes {
+            let (_, value, len) = iter.next_kv().await.unwrap().unwrap();
+            assert_eq!(value, vec![b'x'; size]);
+            assert_eq!(len, EntryHeader::serialized_len() + 2048);
+        }
+        assert!(iter.next_kv().await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_first_entry_offset() {
+        let tempdir = tempdir().unwrap();
+
+        let device = FsDevice::open(FsDeviceConfig {
+            dir: tempdir.path().into(),
+            capacity: 256 * 1024, // 256 KiB
+            file_size: 64 * 1024, // 64 KiB
+            align: 4 * 1024,      // 4 KiB
+            io_size: 16 * 1024,   // 16 KiB
+            io_executor: IoExecutor::default(),
+            on_region_open_error: RegionOpenErrorPolicy::default(),
+            write_io_size: None,
+            read_io_size: None,
+            extra_dirs: vec![],
+            direct_io: true,
+        })
+        .await
+        .unwrap();
+
+        let mut buffer = FlushBuffer::new(
+            device.clone(),
+            true,
+            None,
+            None,
+            None,
+            None,
+            Some(16 * 1024),
+            None,
+            PaddingPolicy::None,
+            false,
+            0,
+            ChecksumAlgorithm::default(),
+            FlushErrorPolicy::default(),
+            IoBytesLimiter::default(),
+            None,
+            false,
+        );
+        buffer.rotate(0, 0).await.unwrap();
+
+        let mut positioneds = vec![];
+        // 16 ~ 24 KiB
+        positioneds.extend(buffer.write(ent(5 * 1024 - 128)).await.unwrap().unwrap_left());
+        // 24 ~ 32 KiB
+        positioneds.extend(buffer.write(ent(5 * 1024 - 128)).await.unwrap().unwrap_left());
+        positioneds.extend(buffer.flush().await.unwrap());
+        assert_eq!(positioneds.len(), 2);
+        assert_eq!(positioneds[0].offset, 16 * 1024);
+        assert_eq!(positioneds[1].offset, 24 * 1024);
+
+        let buf = device.io_buffer(64 * 1024, 64 * 1024);
+        let (res, buf) = device.read(buf, .., 0, 0).await;
+        res.unwrap();
+
+        let header = RegionHeader::read(&buf[..4 * 1024]).unwrap();
+        assert_eq!(header.first_entry_offset, Some(16 * 1024));
+        assert_buffer(positioneds, &buf);
+    }
+
+    #[derive(Debug, Default)]
+    struct DecisionRecorder(parking_lot::Mutex<Vec<CompressionDecision>>);
+
+    impl CompressionObserver for DecisionRecorder {
+        fn on_decision(&self, decision: CompressionDecision) {
+            self.0.lock().push(decision);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_flush_alignment() {
+        const KB: usize = 1024;
+
+        let tempdir = tempdir().unwrap();
+
+        let device = FsDevice::open(FsDeviceConfig {
+            dir: tempdir.path().into(),
+            capacity: 256 * 1024, // 256 KiB
+            file_size: 64 * 1024, // 64 KiB
+            align: 4 * 1024,      // 4 KiB
+            io_size: 16 * 1024,   // 16 KiB
+            io_executor: IoExecutor::default(),
+            on_region_open_error: RegionOpenErrorPolicy::default(),
+            write_io_size: None,
+            read_io_size: None,
+            extra_dirs: vec![],
+            direct_io: true,
+        })
+        .await
+        .unwrap();
+
+        let mut buffer = FlushBuffer::new(
+            device.clone(),
+            true,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            PaddingPolicy::None,
+            false,
+            0,
+            ChecksumAlgorithm::default(),
+            FlushErrorPolicy::default(),
+            IoBytesLimiter::default(),
+            Some(32 * KB),
+            false,
+        );
+        buffer.rotate(0, 0).await.unwrap();
+
+        // Each entry takes a 4 KiB block, the flush threshold of 16 KiB is rounded up to 32 KiB.
+        let sizes = (0..9).map(|i| 3 * KB + i).collect::<Vec<_>>();
+        let mut positioneds = vec![];
+        for (i, size) in sizes.iter().take(7).enumerate() {
+            let flushed = buffer.write(ent(*size)).await.unwrap().unwrap_left();
+            assert_eq!(flushed.is_empty(), i < 6);
+            positioneds.extend(flushed);
+        }
+        assert_eq!(buffer.offset(), 32 * KB);
+
+        // 40 KiB is padded to 64 KiB on flush.
+        for size in sizes.iter().skip(7) {
+            positioneds.extend(buffer.write(ent(*size)).await.unwrap().unwrap_left());
+        }
+        positioneds.extend(buffer.flush().await.unwrap());
+        assert_eq!(buffer.offset(), 64 * KB);
+        assert_eq!(positioneds.len(), sizes.len());
+
+        let buf = device.io_buffer(64 * KB, 64 * KB);
+        let (res, buf) = device.read(buf, .., 0, 0).await;
+        res.unwrap();
+        let header = RegionHeader::read(&buf[..4 * KB]).unwrap();
+        assert_eq!(header.flush_alignment, Some(32 * KB as u32));
+        assert!(buf[40 * KB..].iter().all(|b| *b == 0));
+        assert_buffer(positioneds, &buf);
+
+        // The entries following the padding of a flush are still iterated.
+        buffer.rotate(1, 0).await.unwrap();
+        for size in sizes.iter().take(2) {
+            buffer.write(ent(*size)).await.unwrap().unwrap_left();
+        }
+        buffer.flush().await.unwrap();
+        for size in sizes.iter().skip(2).take(2) {
+            buffer.write(ent(*size)).await.unwrap().unwrap_left();
+        }
+        buffer.flush().await.unwrap();
+        assert_eq!(buffer.offset(), 64 * KB);
+
+        let mut iter = RegionEntryIter::<(), Vec<u8>, _>::open(Region::new(1, device, IoBytesLimiter::default()), true)
+            .await
+            .unwrap()
+            .unwrap();
+        for size in sizes.iter().take(4) {
+            let (_, value, _) = iter.next_kv().await.unwrap().unwrap();
+            assert_eq!(value, vec![b'x'; *size]);
+        }
+        assert!(iter.next_kv().await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_compression_observer() {
+        let tempdir = tempdir().unwrap();
+
+        let device = FsDevice::open(FsDeviceConfig {
+            dir: tempdir.path().into(),
+            capacity: 256 * 1024, // 256 KiB
+            file_size: 64 * 1024, // 64 KiB
+            align: 4 * 1024,      // 4 KiB
+            io_size: 16 * 1024,   // 16 KiB
+            io_executor: IoExecutor::default(),
+            on_region_open_error: RegionOpenErrorPolicy::default(),
+            write_io_size: None,
+            read_io_size: None,
+            extra_dirs: vec![],
+            direct_io: true,
+        })
+        .await
+        .unwrap();
+
+        // Pseudo random bytes that can hardly be compressed.
+        let mut state = 0x2545f4914f6cdd1du64;
+        let random = (0..1024)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state as u8
+            })
+            .collect::<Vec<_>>();
+
+        let entries = [
+            (vec![b'x'; 1024], Compression::None),
+            (vec![b'x'; 1024], Compression::Zstd),
+            (vec![b'x'; 1024], Compression::Lz4),
+            (vec![b'x'; 1024], Compression::Snappy),
+            (random.clone(), Compression::None),
+            (random.clone(), Compression::Zstd),
+            (random.clone(), Compression::Lz4),
+            (random.clone(), Compression::Snappy),
+        ];
+        let original_size = bincode::serialized_size(&vec![0u8; 1024]).unwrap() as usize;
+
+        for region_compression in [None, Some(Compression::Lz4)] {
+            let recorder = Arc::new(DecisionRecorder::default());
+            let mut buffer = FlushBuffer::new(
+                device.clone(),
+                true,
+                region_compression,
+                None,
+                Some(recorder.clone()),
+                None,
+                None,
+                None,
+                PaddingPolicy::None,
+                false,
+                0,
+                ChecksumAlgorithm::default(),
+                FlushErrorPolicy::default(),
+                IoBytesLimiter::default(),
+                None,
+                false,
+            );
+            buffer.rotate(0, 0).await.unwrap();
+
+            for (value, compression) in entries.iter() {
+                let entry = Entry {
+                    key: Arc::new(()),
+                    value: Arc::new(value.clone()),
+                    compression: *compression,
+                    sequence: 0,
+                    enqueued: Instant::now(),
+                    expire_at: None,
+                };
+                buffer.write(entry).await.unwrap().unwrap_left();
+            }
+            buffer.flush().await.unwrap();
+
+            let decisions = recorder.0.lock().clone();
+            assert_eq!(decisions.len(), entries.len());
+            for (decision, (value, compression)) in decisions.iter().zip(entries.iter()) {
+                assert_eq!(decision.requested, *compression);
+                assert_eq!(decision.chosen, region_compression.unwrap_or(*compression));
+                assert_eq!(decision.original_size, original_size);
+                match decision.chosen {
+                    Compression::None => assert_eq!(decision.compressed_size, original_size),
+                    _ if value == &random => assert!(decision.compressed_size >= original_size / 2),
+                    _ => assert!(decision.compressed_size < original_size / 4),
+                }
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_adaptive_compression() {
+        let tempdir = tempdir().unwrap();
+
+        let device = FsDevice::open(FsDeviceConfig {
+            dir: tempdir.path().into(),
+            capacity: 256 * 1024, // 256 KiB
+            file_size: 64 * 1024, // 64 KiB
+            align: 4 * 1024,      // 4 KiB
+            io_size: 16 * 1024,   // 16 KiB
+            io_executor: IoExecutor::default(),
+            on_region_open_error: RegionOpenErrorPolicy::default(),
+            write_io_size: None,
+            read_io_size: None,
+            extra_dirs: vec![],
+            direct_io: true,
+        })
+        .await
+        .unwrap();
+
+        // Pseudo random bytes that can hardly be compressed.
+        let mut state = 0x2545f4914f6cdd1du64;
+        let random = (0..1024)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state as u8
+            })
+            .collect::<Vec<_>>();
+
+        // The incompressible values fall back to no compression.
+        let entries = [
+            (vec![b'x'; 1024], Compression::Zstd, Compression::Zstd),
+            (random.clone(), Compression::Zstd, Compression::None),
+            (vec![b'x'; 1024], Compression::Lz4, Compression::Lz4),
+            (random.clone(), Compression::Lz4, Compression::None),
+            (random.clone(), Compression::None, Compression::None),
+        ];
+
+        let recorder = Arc::new(DecisionRecorder::default());
+        let mut buffer = FlushBuffer::new(
+            device.clone(),
+            true,
+            None,
+            None,
+            Some(recorder.clone()),
+            None,
+            None,
+            None,
+            PaddingPolicy::None,
+            false,
+            0,
+            ChecksumAlgorithm::default(),
+            FlushErrorPolicy::default(),
+            IoBytesLimiter::default(),
+            None,
+            true,
+        );
+        buffer.rotate(0, 0).await.unwrap();
+
+        let mut positioneds = vec![];
+        for (value, compression, _) in entries.iter() {
+            let entry = Entry {
+                key: Arc::new(()),
+                value: Arc::new(value.clone()),
+                compression: *compression,
+                sequence: 0,
+                enqueued: Instant::now(),
+                expire_at: None,
+            };
+            positioneds.extend(buffer.write(entry).await.unwrap().unwrap_left());
+        }
+        positioneds.extend(buffer.flush().await.unwrap());
+        assert_eq!(positioneds.len(), entries.len());
+
+        let original_size = bincode::serialized_size(&random).unwrap() as usize;
+        let decisions = recorder.0.lock().clone();
+        for (decision, (_, requested, chosen)) in decisions.iter().zip(entries.iter()) {
+            assert_eq!(decision.requested, *requested);
+            assert_eq!(decision.chosen, *chosen);
+            assert!(decision.compressed_size <= original_size);
+        }
+
+        let buf = device.io_buffer(64 * 1024, 64 * 1024);
+        let (res, buf) = device.read(buf, .., 0, 0).await;
+        res.unwrap();
+        for (positioned, (_, _, chosen)) in positioneds.iter().zip(entries.iter()) {
+            let header = EntryHeader::read(&buf[positioned.offset..]).unwrap();
+            assert_eq!(header.compression, *chosen);
+        }
+
+        // The entries are decoded by the algorithm recorded in their own headers.
+        let mut iter = RegionEntryIter::<(), Vec<u8>, _>::open(Region::new(0, device, IoBytesLimiter::default()), true)
+            .await
+            .unwrap()
+            .unwrap();
+        for (value, _, _) in entries.iter() {
+            let (_, v, _) = iter.next_kv().await.unwrap().unwrap();
+            assert_eq!(&v, value);
+        }
+        assert!(iter.next_kv().await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_serialized_value_len() {
+        let tempdir = tempdir().unwrap();
+
+        let device = FsDevice::open(FsDeviceConfig {
+            dir: tempdir.path().into(),
+            capacity: 256 * 1024, // 256 KiB
+            file_size: 64 * 1024, // 64 KiB
+            align: 4 * 1024,      // 4 KiB
+            io_size: 16 * 1024,   // 16 KiB
+            io_executor: IoExecutor::default(),
+            on_region_open_error: RegionOpenErrorPolicy::default(),
+            write_io_size: None,
+            read_io_size: None,
+            extra_dirs: vec![],
+            direct_io: true,
+        })
+        .await
+        .unwrap();
+
+        let values = [
+            vec![],
+            vec![1u8],
+            vec![b'x'; 100],
+            (0..3000).map(|i| i as u8).collect::<Vec<_>>(),
+        ];
+
+        for compression in [
+            Compression::None,
+            Compression::Zstd,
+            Compression::Lz4,
+            Compression::Snappy,
+        ] {
+            let recorder = Arc::new(DecisionRecorder::default());
+            let mut buffer = FlushBuffer::new(
+                device.clone(),
+                true,
+                None,
+                None,
+                Some(recorder.clone()),
+                None,
+                None,
+                None,
+                PaddingPolicy::None,
+                false,
+                0,
+                ChecksumAlgorithm::default(),
+                FlushErrorPolicy::default(),
+                IoBytesLimiter::default(),
+                None,
+                false,
+            );
+            buffer.rotate(0, 0).await.unwrap();
+
+            for value in values.iter() {
+                let entry = Entry {
+                    key: Arc::new(()),
+                    value: Arc::new(value.clone()),
+                    compression,
+                    sequence: 0,
+                    enqueued: Instant::now(),
+                    expire_at: None,
+                };
+                buffer.write(entry).await.unwrap().unwrap_left();
+            }
+            buffer.flush().await.unwrap();
+
+            let decisions = recorder.0.lock().clone();
+            assert_eq!(decisions.len(), values.len());
+            for (decision, value) in decisions.iter().zip(values.iter()) {
+                assert_eq!(decision.chosen, compression);
+                assert_eq!(
+                    decision.original_size,
+                    bincode::serialized_size(value).unwrap() as usize
+                );
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_zstd_level() {
+        let tempdir = tempdir().unwrap();
+
+        let device = FsDevice::open(FsDeviceConfig {
+            dir: tempdir.path().into(),
+            capacity: 256 * 1024, // 256 KiB
+            file_size: 64 * 1024, // 64 KiB
+            align: 4 * 1024,      // 4 KiB
+            io_size: 16 * 1024,   // 16 KiB
+            io_executor: IoExecutor::default(),
+            on_region_open_error: RegionOpenErrorPolicy::default(),
+            write_io_size: None,
+            read_io_size: None,
+            extra_dirs: vec![],
+            direct_io: true,
+        })
+        .await
+        .unwrap();
+
+        // Records with scattered fields, so that the higher level finds more matches.
+        let words = [
+            "alpha", "bravo", "charlie", "delta", "echo", "foxtrot", "golf", "hotel", "india", "juliet",
+        ];
+        let value = (0..256u64)
+            .map(|i| {
+                format!(
+                    r#"{{"id":{},"name":"{}-{}","tags":["{}","{}"]}}"#,
+                    i * 7919 % 10007,
+                    words[(i * 7 % 10) as usize],
+                    i * 31 % 97,
+                    words[(i * 3 % 10) as usize],
+                    words[(i * i % 10) as usize]
+                )
+            })
+            .collect::<String>()
+            .into_bytes();
+
+        let mut sizes = vec![];
+        for (region, level) in [1, 19].into_iter().enumerate() {
+            let recorder = Arc::new(DecisionRecorder::default());
+            let mut buffer = FlushBuffer::new(
+                device.clone(),
+                true,
+                None,
+                None,
+                Some(recorder.clone()),
+                None,
+                None,
+                None,
+                PaddingPolicy::None,
+                false,
+                level,
+                ChecksumAlgorithm::default(),
+                FlushErrorPolicy::default(),
+                IoBytesLimiter::default(),
+                None,
+                false,
+            );
+            buffer.rotate(region as RegionId, 0).await.unwrap();
+            let entry = Entry {
+                key: Arc::new(()),
+                value: Arc::new(value.clone()),
+                compression: Compression::Zstd,
+                sequence: 0,
+                enqueued: Instant::now(),
+                expire_at: None,
+            };
+            buffer.write(entry).await.unwrap().unwrap_left();
+            buffer.flush().await.unwrap();
+            sizes.push(recorder.0.lock()[0].compressed_size);
+
+            // The level is not needed for decompression.
+            let mut iter = RegionEntryIter::<(), Vec<u8>, _>::open(
+                Region::new(region as RegionId, device.clone(), IoBytesLimiter::default()),
+                true,
+            )
+            .await
+            .unwrap()
+            .unwrap();
+            let (_, v, _) = iter.next_kv().await.unwrap().unwrap();
+            assert_eq!(v, value);
+        }
+        assert!(
+            sizes[1] < sizes[0],
+            "level 19 must compress better than level 1: {sizes:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_block_compression() {
+        let tempdir = tempdir().unwrap();
+
+        let device = FsDevice::open(FsDeviceConfig {
+            dir: tempdir.path().into(),
+            capacity: 256 * 1024,  // 256 KiB
+            file_size: 128 * 1024, // 128 KiB
+            align: 4 * 1024,       // 4 KiB
+            io_size: 16 * 1024,    // 16 KiB
+            io_executor: IoExecutor::default(),
+            on_region_open_error: RegionOpenErrorPolicy::default(),
+            write_io_size: None,
+            read_io_size: None,
+            extra_dirs: vec![],
+            direct_io: true,
+        })
+        .await
+        .unwrap();
+
+        // Similar small records that share most of their bytes.
+        let records = (0..16)
+            .map(|i| format!(r#"{{"id":{i},"name":"user-{i}","email":"user-{i}@example.com","status":"active"}}"#))
+            .map(String::into_bytes)
+            .collect::<Vec<_>>();
+        let entry = |sequence: usize| Entry {
+            key: Arc::new(()),
+            value: Arc::new(records[sequence].clone()),
+            compression: Compression::Zstd,
+            sequence: sequence as u64,
+            enqueued: Instant::now(),
+            expire_at: None,
+        };
+
+        // Compress per entry.
+        let recorder = Arc::new(DecisionRecorder::default());
+        let mut buffer = FlushBuffer::new(
+            device.clone(),
+            true,
+            None,
+            None,
+            Some(recorder.clone()),
+            None,
+            None,
+            None,
+            PaddingPolicy::None,
+            false,
+            0,
+            ChecksumAlgorithm::default(),
+            FlushErrorPolicy::default(),
+            IoBytesLimiter::default(),
+            None,
+            false,
+        );
+        buffer.rotate(0, 0).await.unwrap();
+        for i in 0..records.len() {
+            buffer.write(entry(i)).await.unwrap().unwrap_left();
+        }
+        buffer.flush().await.unwrap();
+        let per_entry_size: usize = recorder.0.lock().iter().map(|decision| decision.compressed_size).sum();
+
+        // Compress as a block.
+        let recorder = Arc::new(DecisionRecorder::default());
+        let mut buffer = FlushBuffer::new(
+            device.clone(),
+            true,
+            None,
+            None,
+            Some(recorder.clone()),
+            Some(BlockCompression {
+                compression: Compression::Zstd,
+                entries: records.len(),
+                cache_capacity: 0,
+            }),
+            None,
+            None,
+            PaddingPolicy::None,
+            false,
+            0,
+            ChecksumAlgorithm::default(),
+            FlushErrorPolicy::default(),
+            IoBytesLimiter::default(),
+            None,
+            false,
+        );
+        buffer.rotate(1, 0).await.unwrap();
+        let mut positioneds = vec![];
+        for i in 0..records.len() {
+            positioneds.extend(buffer.write(entry(i)).await.unwrap().unwrap_left());
+        }
+        positioneds.extend(buffer.flush().await.unwrap());
+
+        let decisions = recorder.0.lock().clone();
+        assert_eq!(decisions.len(), 1);
+        assert_eq!(
+            decisions[0].original_size,
+            records
+                .iter()
+                .map(|record| bincode::serialized_size(record).unwrap() as usize)
+                .sum::<usize>()
+        );
+        assert!(
+            decisions[0].compressed_size < per_entry_size,
+            "block: {}, per entry: {}",
+            decisions[0].compressed_size,
+            per_entry_size
+        );
+
+        // Each entry decodes correctly from the block.
+        let buf = device.io_buffer(64 * 1024, 64 * 1024);
+        let (res, buf) = device.read(buf, .., 1, 0).await;
+        res.unwrap();
+
+        assert_eq!(positioneds.len(), records.len());
+        for (i, positioned) in positioneds.iter().enumerate() {
+            assert_eq!(positioned.index, Some(i as u32));
+            let block =
+                DecodedBlock::<()>::decode(&buf[positioned.offset..positioned.offset + positioned.len], true).unwrap();
+            assert_eq!(block.sequence(i), Some(i as u64));
+            assert_eq!(block.value::<Vec<u8>>(i).unwrap(), records[i]);
+        }
+    }
 }
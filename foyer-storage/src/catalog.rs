@@ -12,31 +12,111 @@
 //  See the License for the specific language governing permissions and
 //  limitations under the License.
 
-use std::{borrow::Borrow, fmt::Debug, hash::Hash, sync::Arc, time::Instant};
+use std::{
+    borrow::Borrow,
+    fmt::Debug,
+    hash::Hash,
+    sync::Arc,
+    time::{Duration, Instant, SystemTime},
+};
 
 use ahash::RandomState;
 use foyer_common::{
     arc_key_hash_map::{ArcKeyHashMap, Entry},
     code::{StorageKey, StorageValue},
 };
+use futures::future::BoxFuture;
 use itertools::Itertools;
 use parking_lot::{Mutex, RwLock};
 
 use crate::{
+    error::Result,
     metrics::Metrics,
     region::{RegionId, RegionView},
 };
 
 pub type Sequence = u64;
 
+/// Policy to decide the winner when the same key is inserted concurrently.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WriteConflictPolicy {
+    /// The insertion called later wins.
+    ///
+    /// The sequence is stamped when the writer is created, and the catalog ignores the index updates with a smaller
+    /// sequence than the indexed one, regardless of the order the flushers apply them.
+    #[default]
+    LastWriterWins,
+    /// The insertion applied to the catalog later wins, which depends on the flusher receive order.
+    ///
+    /// The sequence is stamped when the writer is finished.
+    LastAppliedWins,
+}
+
+/// Location of a flushed entry passed to the [`CatalogBackend`].
+#[derive(Debug, Clone)]
+pub struct CatalogRecord<K> {
+    pub key: Arc<K>,
+    pub sequence: Sequence,
+    pub region: RegionId,
+    /// Offset of the entry, or of its block, in the region.
+    pub offset: u32,
+    /// Len of the entry, or of its block.
+    pub len: u32,
+    /// Index of the entry within the block, `None` if the entry is not compressed in a block.
+    pub block_index: Option<u32>,
+}
+
+/// Backend that persists the index of the flushed entries besides the in-memory catalog, e.g. spills it to another
+/// storage.
+///
+/// A flushed entry is indexed in memory only after the backend accepts it. The flushers retry the failed inserts with
+/// backoff, see [`CatalogRetryPolicy`].
+pub trait CatalogBackend<K>: Send + Sync + 'static + Debug {
+    fn insert(&self, record: CatalogRecord<K>) -> BoxFuture<'static, Result<()>>;
+}
+
+/// Retry of the failed [`CatalogBackend`] inserts on flush.
+///
+/// If all retries fail, the entry is dropped, and its region is marked orphaned so the reclaimer cleans it up before
+/// the other evictable regions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CatalogRetryPolicy {
+    /// Max count of the retries after the first insert fails.
+    pub retries: usize,
+    /// Backoff before the first retry, doubled after each retry.
+    pub backoff: Duration,
+}
+
+impl Default for CatalogRetryPolicy {
+    fn default() -> Self {
+        Self {
+            retries: 3,
+            backoff: Duration::from_millis(10),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum Index<K, V>
 where
     K: StorageKey,
     V: StorageValue,
 {
-    Inflight { key: Arc<K>, value: Arc<V> },
-    Region { view: RegionView },
+    /// Entry not written to the device yet, including the entry pending in the flush buffer below the write io size.
+    ///
+    /// It is served from memory until the flusher indexes it by region after the write completes.
+    Inflight {
+        key: Arc<K>,
+        value: Arc<V>,
+    },
+    Region {
+        view: RegionView,
+    },
+    /// Entry compressed in a block, `view` is the view of the block and `index` is the entry index within the block.
+    Block {
+        view: RegionView,
+        index: u32,
+    },
 }
 
 impl<K, V> Clone for Index<K, V>
@@ -51,6 +131,10 @@ where
                 value: value.clone(),
             },
             Self::Region { view } => Self::Region { view: view.clone() },
+            Self::Block { view, index } => Self::Block {
+                view: view.clone(),
+                index: *index,
+            },
         }
     }
 }
@@ -63,6 +147,7 @@ where
 {
     sequence: Sequence,
     index: Index<K, V>,
+    expire_at: Option<SystemTime>,
 
     inserted: Option<Instant>,
 }
@@ -76,6 +161,7 @@ where
         Self {
             sequence: self.sequence,
             index: self.index.clone(),
+            expire_at: self.expire_at,
             inserted: self.inserted,
         }
     }
@@ -90,10 +176,17 @@ where
         Self {
             sequence,
             index,
+            expire_at: None,
             inserted: None,
         }
     }
 
+    /// Set the time after which the item is treated as a miss.
+    pub fn with_expire_at(mut self, expire_at: Option<SystemTime>) -> Self {
+        self.expire_at = expire_at;
+        self
+    }
+
     pub fn sequence(&self) -> &Sequence {
         &self.sequence
     }
@@ -102,6 +195,14 @@ where
         &self.index
     }
 
+    pub fn expire_at(&self) -> Option<SystemTime> {
+        self.expire_at
+    }
+
+    pub fn is_expired(&self, now: SystemTime) -> bool {
+        self.expire_at.is_some_and(|expire_at| expire_at <= now)
+    }
+
     pub fn consume(self) -> (Sequence, Index<K, V>) {
         (self.sequence, self.index)
     }
@@ -121,6 +222,10 @@ where
 
     hash_builder: RandomState,
 
+    write_conflict_policy: WriteConflictPolicy,
+
+    backend: Option<Arc<dyn CatalogBackend<K>>>,
+
     metrics: Arc<Metrics>,
 }
 
@@ -129,7 +234,13 @@ where
     K: StorageKey,
     V: StorageValue,
 {
-    pub fn new(regions: usize, shards: usize, metrics: Arc<Metrics>) -> Self {
+    pub fn new(
+        regions: usize,
+        shards: usize,
+        write_conflict_policy: WriteConflictPolicy,
+        backend: Option<Arc<dyn CatalogBackend<K>>>,
+        metrics: Arc<Metrics>,
+    ) -> Self {
         assert!(shards > 0, "catalog shard count must be > 0, given: {}", shards);
 
         let items = (0..shards).map(|_| RwLock::new(ArcKeyHashMap::new())).collect_vec();
@@ -143,24 +254,69 @@ where
 
             hash_builder,
 
+            write_conflict_policy,
+
+            backend,
+
             metrics,
         }
     }
 
-    pub fn insert(&self, key: Arc<K>, mut item: Item<K, V>) {
-        // TODO(MrCroxx): compare sequence.
+    /// Index the item of the key.
+    ///
+    /// With [`WriteConflictPolicy::LastWriterWins`], the item is ignored if the key is indexed with a larger sequence.
+    pub fn insert(&self, key: Arc<K>, item: Item<K, V>) {
+        let sequence_wins = self.write_conflict_policy == WriteConflictPolicy::LastWriterWins;
+        self.insert_inner(key, item, sequence_wins)
+    }
 
-        if let Index::Region { view } = &item.index {
-            self.regions[*view.id() as usize]
-                .lock()
-                .insert(key.clone(), item.sequence);
+    /// Persist the item of the flushed entry to the [`CatalogBackend`] if any, before it is indexed.
+    pub async fn persist(&self, key: &Arc<K>, item: &Item<K, V>) -> Result<()> {
+        let Some(backend) = self.backend.as_ref() else {
+            return Ok(());
         };
+        let (view, block_index) = match &item.index {
+            Index::Region { view } => (view, None),
+            Index::Block { view, index } => (view, Some(*index)),
+            Index::Inflight { .. } => unreachable!("only the flushed entries are persisted to the catalog backend"),
+        };
+        let record = CatalogRecord {
+            key: key.clone(),
+            sequence: item.sequence,
+            region: *view.id(),
+            offset: *view.offset(),
+            len: *view.len(),
+            block_index,
+        };
+        backend.insert(record).await
+    }
+
+    /// Index the item of the key recovered from the device.
+    ///
+    /// The regions are recovered concurrently in no particular order, so the item is always ignored if the key is
+    /// indexed with a larger sequence, regardless of the write conflict policy.
+    pub fn recover(&self, key: Arc<K>, item: Item<K, V>) {
+        self.insert_inner(key, item, true)
+    }
 
+    fn insert_inner(&self, key: Arc<K>, mut item: Item<K, V>, sequence_wins: bool) {
         let shard = self.shard(&key);
         // TODO(MrCroxx): handle old key?
         let old = {
             let mut guard = self.items[shard].write();
+
+            if sequence_wins && guard.get(&key).map(|old| old.sequence > item.sequence).unwrap_or(false) {
+                return;
+            }
+
+            if let Index::Region { view } | Index::Block { view, .. } = &item.index {
+                self.regions[*view.id() as usize]
+                    .lock()
+                    .insert(key.clone(), item.sequence);
+            };
+
             item.inserted = Some(Instant::now());
+            self.metrics.catalog_ops_insert.inc_by(1);
             guard.insert(key.clone(), item)
         };
         // TODO(MrCroxx): Use `let_chains` here after it is stable.
@@ -173,13 +329,37 @@ where
         }
     }
 
+    /// Get the item of the key.
+    ///
+    /// An expired item is treated as a miss, and removed from the catalog by the way.
     pub fn get<Q>(&self, key: &Q) -> Option<Item<K, V>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.get_key_value(key).map(|(_, item)| item)
+    }
+
+    /// Get the item of the key, with the indexed key.
+    ///
+    /// An expired item is treated as a miss, and removed from the catalog by the way.
+    pub fn get_key_value<Q>(&self, key: &Q) -> Option<(Arc<K>, Item<K, V>)>
     where
         K: Borrow<Q>,
         Q: Hash + Eq + ?Sized,
     {
         let shard = self.shard(key);
-        self.items[shard].read().get(key).cloned()
+        let (k, item) = self.items[shard]
+            .read()
+            .get_key_value(key)
+            .map(|(k, item)| (k.clone(), item.clone()))?;
+        if item.is_expired(SystemTime::now()) {
+            if self.remove_with_sequence(key, item.sequence).is_some() {
+                self.metrics.catalog_ops_expire.inc_by(1);
+            }
+            return None;
+        }
+        Some((k, item))
     }
 
     pub fn remove<Q>(&self, key: &Q) -> Option<Item<K, V>>
@@ -191,7 +371,32 @@ where
         let info: Option<Item<K, V>> = self.items[shard].write().remove(key);
         // TODO(MrCroxx): Use `let_chains` here after it is stable.
         if let Some(info) = &info {
-            if let Index::Region { view } = &info.index {
+            self.metrics.catalog_ops_remove.inc_by(1);
+            if let Index::Region { view } | Index::Block { view, .. } = &info.index {
+                self.regions[*view.id() as usize].lock().remove(key);
+            }
+        }
+        info
+    }
+
+    /// Remove the key only if it is indexed with the given sequence.
+    pub fn remove_with_sequence<Q>(&self, key: &Q, sequence: Sequence) -> Option<Item<K, V>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let shard = self.shard(key);
+        let info = {
+            let mut guard = self.items[shard].write();
+            match guard.get(key) {
+                Some(item) if item.sequence == sequence => guard.remove(key),
+                _ => None,
+            }
+        };
+        // TODO(MrCroxx): Use `let_chains` here after it is stable.
+        if let Some(info) = &info {
+            self.metrics.catalog_ops_remove.inc_by(1);
+            if let Index::Region { view } | Index::Block { view, .. } = &info.index {
                 self.regions[*view.id() as usize].lock().remove(key);
             }
         }
@@ -216,9 +421,91 @@ where
                 }
             };
         }
+        self.metrics.catalog_ops_remove.inc_by(items.len() as u64);
         items
     }
 
+    /// Keys that are currently indexed in the given region.
+    pub fn region_keys(&self, region: &RegionId) -> Vec<Arc<K>> {
+        self.regions[*region as usize]
+            .lock()
+            .iter()
+            .map(|entry| entry.key().clone())
+            .collect_vec()
+    }
+
+    /// Entries that are currently indexed in regions, as
+    /// `(key, sequence, region, offset, len, block index, expire at)`.
+    ///
+    /// Inflight entries are not included.
+    // TODO(MrCroxx): use `expect` after `lint_reasons` is stable.
+    #[allow(clippy::type_complexity)]
+    pub fn snapshot(&self) -> Vec<(Arc<K>, Sequence, RegionId, u32, u32, Option<u32>, Option<SystemTime>)> {
+        let mut entries = vec![];
+        for shard in self.items.iter() {
+            let shard = shard.read();
+            for entry in shard.iter() {
+                let item = entry.value();
+                let (view, index) = match &item.index {
+                    Index::Inflight { .. } => continue,
+                    Index::Region { view } => (view, None),
+                    Index::Block { view, index } => (view, Some(*index)),
+                };
+                entries.push((
+                    entry.key().clone(),
+                    item.sequence,
+                    *view.id(),
+                    *view.offset(),
+                    *view.len(),
+                    index,
+                    item.expire_at,
+                ));
+            }
+        }
+        entries
+    }
+
+    /// Keys of the entries with sequence greater than `sequence`, in sequence order.
+    pub fn keys_since(&self, sequence: Sequence) -> Vec<(Arc<K>, Sequence)> {
+        let mut keys = vec![];
+        for shard in self.items.iter() {
+            let shard = shard.read();
+            for entry in shard.iter() {
+                if entry.value().sequence > sequence {
+                    keys.push((entry.key().clone(), entry.value().sequence));
+                }
+            }
+        }
+        keys.sort_by_key(|(_, sequence)| *sequence);
+        keys
+    }
+
+    /// Keys of the entries indexed in regions no later than `deadline`, oldest first.
+    ///
+    /// Inflight entries are not included.
+    pub fn indexed_before(&self, deadline: Instant) -> Vec<(Arc<K>, Sequence)> {
+        let mut keys = vec![];
+        for shard in self.items.iter() {
+            let shard = shard.read();
+            for entry in shard.iter() {
+                let item = entry.value();
+                if let Index::Inflight { .. } = item.index {
+                    continue;
+                }
+                match item.inserted {
+                    Some(inserted) if inserted <= deadline => keys.push((entry.key().clone(), item.sequence, inserted)),
+                    _ => {}
+                }
+            }
+        }
+        keys.sort_by_key(|(_, _, inserted)| *inserted);
+        keys.into_iter().map(|(key, sequence, _)| (key, sequence)).collect_vec()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.iter().all(|shard| shard.read().is_empty())
+    }
+
     pub fn clear(&self) {
         for shard in self.items.iter() {
             shard.write().clear();
@@ -14,6 +14,8 @@
 
 // TODO(MrCroxx): unify compress interface?
 
+use std::fmt::Debug;
+
 use anyhow::anyhow;
 
 const NOT_SUPPORT: &str = "compression algorithm not support";
@@ -23,6 +25,7 @@ pub enum Compression {
     None,
     Zstd,
     Lz4,
+    Snappy,
 }
 
 impl Compression {
@@ -31,6 +34,7 @@ impl Compression {
             Self::None => 0,
             Self::Zstd => 1,
             Self::Lz4 => 2,
+            Self::Snappy => 3,
         }
     }
 
@@ -39,6 +43,7 @@ impl Compression {
             Self::None => "none",
             Self::Zstd => "zstd",
             Self::Lz4 => "lz4",
+            Self::Snappy => "snappy",
         }
     }
 }
@@ -49,6 +54,7 @@ impl From<Compression> for u8 {
             Compression::None => 0,
             Compression::Zstd => 1,
             Compression::Lz4 => 2,
+            Compression::Snappy => 3,
         }
     }
 }
@@ -59,6 +65,7 @@ impl From<Compression> for &str {
             Compression::None => "none",
             Compression::Zstd => "zstd",
             Compression::Lz4 => "lz4",
+            Compression::Snappy => "snappy",
         }
     }
 }
@@ -71,6 +78,7 @@ impl TryFrom<u8> for Compression {
             0 => Ok(Self::None),
             1 => Ok(Self::Zstd),
             2 => Ok(Self::Lz4),
+            3 => Ok(Self::Snappy),
             _ => Err(anyhow!(NOT_SUPPORT)),
         }
     }
@@ -84,6 +92,7 @@ impl TryFrom<&str> for Compression {
             "none" => Ok(Self::None),
             "zstd" => Ok(Self::Zstd),
             "lz4" => Ok(Self::Lz4),
+            "snappy" => Ok(Self::Snappy),
             _ => Err(anyhow!(NOT_SUPPORT)),
         }
     }
@@ -96,3 +105,39 @@ impl TryFrom<String> for Compression {
         Self::try_from(value.as_str())
     }
 }
+
+/// Compression decision made for an entry when it is written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CompressionDecision {
+    /// Compression requested by the entry writer.
+    pub requested: Compression,
+    /// Compression actually applied to the entry, which may be overridden by the region compression.
+    pub chosen: Compression,
+    /// Serialized size of the value before compression.
+    pub original_size: usize,
+    /// Size of the value after compression.
+    pub compressed_size: usize,
+}
+
+/// Observer of the compression decisions, which can be used to audit and tune the compression configurations.
+///
+/// The observer is called on the flusher for each written entry, so it must be lightweight.
+pub trait CompressionObserver: Send + Sync + 'static + Debug {
+    fn on_decision(&self, decision: CompressionDecision);
+}
+
+/// Block compression configurations.
+///
+/// Consecutive entries are compressed together as one block, which exploits the redundancy across entries, e.g. many
+/// similar small records. Each entry is indexed by the block and its index within the block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockCompression {
+    /// Compression algorithm applied to the blocks, overridden by the region compression if set.
+    pub compression: Compression,
+    /// Count of entries compressed together in a block.
+    pub entries: usize,
+    /// Count of the recently decompressed blocks cached for reads.
+    ///
+    /// `0` means each read decompresses the block.
+    pub cache_capacity: usize,
+}
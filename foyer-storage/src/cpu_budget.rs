@@ -0,0 +1,136 @@
+//  Copyright 2024 Foyer Project Authors
+//
+//  Licensed under the Apache License, Version 2.0 (the "License");
+//  you may not use this file except in compliance with the License.
+//  You may obtain a copy of the License at
+//
+//  http://www.apache.org/licenses/LICENSE-2.0
+//
+//  Unless required by applicable law or agreed to in writing, software
+//  distributed under the License is distributed on an "AS IS" BASIS,
+//  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//  See the License for the specific language governing permissions and
+//  limitations under the License.
+
+//! CPU budget of the background tasks, e.g. compression and checksum on flush, and decompression on reclamation.
+//!
+//! The budget is configured as a number of cores, which can be fractional. CPU-bound sections of the background tasks
+//! must hold a permit, and there are `ceil(cores)` permits. If the budget is fractional, each permit is throttled by
+//! holding it idle for a while after the section finishes, so the average consumption of all permits stays within the
+//! budget.
+
+#[cfg(test)]
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::metrics::FloatCounter;
+
+#[derive(Debug)]
+pub struct CpuBudget {
+    semaphore: Arc<Semaphore>,
+
+    /// Ratio of the busy time of each permit, in (0, 1].
+    duty: f64,
+
+    #[cfg(test)]
+    inflight: AtomicUsize,
+    #[cfg(test)]
+    peak: AtomicUsize,
+}
+
+impl CpuBudget {
+    /// Create a CPU budget of `cores`, `None` means unlimited and only the consumption is recorded.
+    pub fn new(cores: Option<f64>) -> Self {
+        let (permits, duty) = match cores {
+            Some(cores) => {
+                assert!(cores > 0.0, "cpu budget cores must be > 0, given: {}", cores);
+                let permits = cores.ceil() as usize;
+                (permits, cores / permits as f64)
+            }
+            None => (Semaphore::MAX_PERMITS, 1.0),
+        };
+        Self {
+            semaphore: Arc::new(Semaphore::new(permits)),
+            duty,
+            #[cfg(test)]
+            inflight: AtomicUsize::new(0),
+            #[cfg(test)]
+            peak: AtomicUsize::new(0),
+        }
+    }
+
+    /// Max count of the concurrent CPU-bound sections ever observed, only for tests.
+    #[cfg(test)]
+    pub fn peak(&self) -> usize {
+        self.peak.load(Ordering::SeqCst)
+    }
+}
+
+/// Handle of the CPU budget for a kind of background task, the consumed time is recorded to `counter`.
+#[derive(Debug, Clone)]
+pub struct CpuBudgetHandle {
+    budget: Arc<CpuBudget>,
+    counter: FloatCounter,
+}
+
+impl CpuBudgetHandle {
+    pub fn new(budget: Arc<CpuBudget>, counter: FloatCounter) -> Self {
+        Self { budget, counter }
+    }
+
+    /// Acquire a permit before a CPU-bound section.
+    pub async fn acquire(&self) -> CpuBudgetGuard {
+        let permit = self.budget.semaphore.clone().acquire_owned().await.unwrap();
+
+        #[cfg(test)]
+        {
+            let inflight = self.budget.inflight.fetch_add(1, Ordering::SeqCst) + 1;
+            self.budget.peak.fetch_max(inflight, Ordering::SeqCst);
+        }
+
+        CpuBudgetGuard {
+            handle: self.clone(),
+            start: Instant::now(),
+            busy: None,
+            _permit: permit,
+        }
+    }
+}
+
+/// Permit of a CPU-bound section, which is returned on [`CpuBudgetGuard::release`] or drop.
+#[derive(Debug)]
+pub struct CpuBudgetGuard {
+    handle: CpuBudgetHandle,
+    start: Instant,
+    busy: Option<Duration>,
+    _permit: OwnedSemaphorePermit,
+}
+
+impl CpuBudgetGuard {
+    /// Finish the CPU-bound section, and hold the permit idle to throttle if the budget is fractional.
+    pub async fn release(mut self) {
+        let busy = self.start.elapsed();
+        self.busy = Some(busy);
+        let duty = self.handle.budget.duty;
+        let idle = busy.as_secs_f64() * (1.0 - duty) / duty;
+        if idle > 0.0 {
+            tokio::time::sleep(Duration::from_secs_f64(idle)).await;
+        }
+    }
+}
+
+impl Drop for CpuBudgetGuard {
+    fn drop(&mut self) {
+        // The idle time for throttling is not counted as consumed.
+        let busy = self.busy.unwrap_or_else(|| self.start.elapsed());
+        self.handle.counter.inc_by(busy.as_secs_f64());
+
+        #[cfg(test)]
+        self.handle.budget.inflight.fetch_sub(1, Ordering::SeqCst);
+    }
+}
@@ -0,0 +1,305 @@
+//  Copyright 2024 Foyer Project Authors
+//
+//  Licensed under the Apache License, Version 2.0 (the "License");
+//  you may not use this file except in compliance with the License.
+//  You may obtain a copy of the License at
+//
+//  http://www.apache.org/licenses/LICENSE-2.0
+//
+//  Unless required by applicable law or agreed to in writing, software
+//  distributed under the License is distributed on an "AS IS" BASIS,
+//  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//  See the License for the specific language governing permissions and
+//  limitations under the License.
+
+use std::{
+    any::Any,
+    fmt::Debug,
+    panic::{catch_unwind, AssertUnwindSafe},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc, Arc,
+    },
+};
+
+use parking_lot::Mutex;
+
+use super::{DeviceError, DeviceResult};
+use crate::metrics::{blocking_ops_inflight, Gauge};
+
+pub type BlockingJob = Box<dyn FnOnce() + Send + 'static>;
+
+/// Executor to run blocking jobs (e.g. blocking syscalls) of devices.
+pub trait BlockingExecutor: Send + Sync + Debug + 'static {
+    /// Name of the executor, used as the metrics label.
+    fn name(&self) -> &str;
+
+    /// Execute the blocking job.
+    ///
+    /// The job must be executed eventually, or the waiter will never be notified.
+    fn execute(&self, job: BlockingJob);
+}
+
+/// Execute blocking jobs with the tokio blocking thread pool.
+#[derive(Debug, Default)]
+pub struct TokioBlockingExecutor;
+
+impl BlockingExecutor for TokioBlockingExecutor {
+    fn name(&self) -> &str {
+        "tokio"
+    }
+
+    fn execute(&self, job: BlockingJob) {
+        tokio::task::spawn_blocking(job);
+    }
+}
+
+/// Execute blocking jobs with a dedicated thread pool.
+///
+/// The concurrency of the blocking jobs is bounded by the thread count.
+#[derive(Debug)]
+pub struct ThreadPoolExecutor {
+    name: String,
+    tx: Mutex<mpsc::Sender<BlockingJob>>,
+}
+
+impl ThreadPoolExecutor {
+    pub fn new(threads: usize, name: &str) -> Self {
+        assert!(threads > 0, "thread pool executor requires at least 1 thread");
+
+        let (tx, rx) = mpsc::channel::<BlockingJob>();
+        let rx = Arc::new(Mutex::new(rx));
+
+        for i in 0..threads {
+            let rx = rx.clone();
+            std::thread::Builder::new()
+                .name(format!("{name}-{i}"))
+                .spawn(move || loop {
+                    // Release the lock before executing the job.
+                    let job = rx.lock().recv();
+                    match job {
+                        // Keep the worker alive if the job panics.
+                        Ok(job) => {
+                            let _ = catch_unwind(AssertUnwindSafe(job));
+                        }
+                        // All senders are dropped, exit.
+                        Err(_) => return,
+                    }
+                })
+                .unwrap();
+        }
+
+        Self {
+            name: name.to_string(),
+            tx: Mutex::new(tx),
+        }
+    }
+}
+
+impl BlockingExecutor for ThreadPoolExecutor {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn execute(&self, job: BlockingJob) {
+        self.tx.lock().send(job).unwrap();
+    }
+}
+
+/// Executor wrapper used by devices to offload blocking syscalls.
+///
+/// [`IoExecutor`] tracks the count of in-flight blocking operations (queued or running), which is also exposed as
+/// metrics `foyer_storage_blocking_ops_inflight{executor = {{ name }}}`.
+#[derive(Debug, Clone)]
+pub struct IoExecutor {
+    executor: Arc<dyn BlockingExecutor>,
+    inflight: Arc<AtomicUsize>,
+    gauge: Gauge,
+}
+
+impl Default for IoExecutor {
+    fn default() -> Self {
+        Self::tokio()
+    }
+}
+
+impl IoExecutor {
+    /// Use the tokio blocking thread pool.
+    pub fn tokio() -> Self {
+        Self::custom(Arc::new(TokioBlockingExecutor))
+    }
+
+    /// Use a dedicated thread pool with `threads` threads.
+    pub fn thread_pool(threads: usize, name: &str) -> Self {
+        Self::custom(Arc::new(ThreadPoolExecutor::new(threads, name)))
+    }
+
+    /// Use a custom blocking executor.
+    pub fn custom(executor: Arc<dyn BlockingExecutor>) -> Self {
+        let gauge = blocking_ops_inflight(executor.name());
+        Self {
+            executor,
+            inflight: Arc::new(AtomicUsize::new(0)),
+            gauge,
+        }
+    }
+
+    /// Name of the underlying executor.
+    pub fn name(&self) -> &str {
+        self.executor.name()
+    }
+
+    /// Count of in-flight (queued or running) blocking operations.
+    pub fn inflight(&self) -> usize {
+        self.inflight.load(Ordering::Relaxed)
+    }
+
+    /// Run the blocking `f` with the executor.
+    ///
+    /// A panic of `f` is returned as an error instead of unwinding the executor.
+    #[cfg(not(madsim))]
+    #[tracing::instrument(level = "trace", skip_all)]
+    pub(crate) async fn asyncify<F, T>(&self, f: F) -> DeviceResult<T>
+    where
+        F: FnOnce() -> DeviceResult<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        let (tx, rx) = tokio::sync::oneshot::channel();
+
+        self.inflight.fetch_add(1, Ordering::Relaxed);
+        self.gauge.inc();
+
+        // The guard is moved into the job, so the in-flight count is decreased even if the job is dropped unexecuted.
+        let guard = InflightGuard {
+            inflight: self.inflight.clone(),
+            gauge: self.gauge.clone(),
+        };
+        self.executor.execute(Box::new(move || {
+            let res = catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|panic| {
+                Err(DeviceError::Other(
+                    format!("blocking job panicked: {}", panic_message(panic.as_ref())).into(),
+                ))
+            });
+            drop(guard);
+            let _ = tx.send(res);
+        }));
+
+        rx.await
+            .map_err(|_| DeviceError::Other("blocking job is dropped before executed".into()))?
+    }
+
+    #[cfg(madsim)]
+    #[tracing::instrument(level = "trace", skip_all)]
+    pub(crate) async fn asyncify<F, T>(&self, f: F) -> DeviceResult<T>
+    where
+        F: FnOnce() -> DeviceResult<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        f()
+    }
+
+    /// Run the blocking `f` on `buf` with the executor.
+    ///
+    /// The buffer is lent to `f`, so it is returned even if `f` panics.
+    pub(crate) async fn asyncify_with_buf<B, F, T>(&self, buf: B, f: F) -> (DeviceResult<T>, B)
+    where
+        B: Send + 'static,
+        F: FnOnce(&mut B) -> DeviceResult<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        let slot = Arc::new(Mutex::new(Some(buf)));
+        let res = {
+            let slot = slot.clone();
+            self.asyncify(move || f(slot.lock().as_mut().unwrap())).await
+        };
+        let buf = slot.lock().take().unwrap();
+        (res, buf)
+    }
+}
+
+/// Decrease the in-flight count of the blocking operations on drop.
+struct InflightGuard {
+    inflight: Arc<AtomicUsize>,
+    gauge: Gauge,
+}
+
+impl Drop for InflightGuard {
+    fn drop(&mut self) {
+        self.inflight.fetch_sub(1, Ordering::Relaxed);
+        self.gauge.dec();
+    }
+}
+
+fn panic_message(panic: &(dyn Any + Send)) -> &str {
+    if let Some(message) = panic.downcast_ref::<&str>() {
+        message
+    } else if let Some(message) = panic.downcast_ref::<String>() {
+        message
+    } else {
+        "unknown panic"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use itertools::Itertools;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_thread_pool_executor() {
+        let executor = IoExecutor::thread_pool(2, "test-thread-pool-executor");
+
+        let running = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+
+        let futures = (0..16)
+            .map(|i| {
+                let executor = executor.clone();
+                let running = running.clone();
+                let peak = peak.clone();
+                async move {
+                    executor
+                        .asyncify(move || {
+                            let current = running.fetch_add(1, Ordering::SeqCst) + 1;
+                            peak.fetch_max(current, Ordering::SeqCst);
+                            std::thread::sleep(std::time::Duration::from_millis(10));
+                            running.fetch_sub(1, Ordering::SeqCst);
+                            Ok(i)
+                        })
+                        .await
+                }
+            })
+            .collect_vec();
+
+        let res = futures::future::try_join_all(futures).await.unwrap();
+
+        assert_eq!(res, (0..16).collect_vec());
+        assert!(peak.load(Ordering::SeqCst) <= 2);
+        assert_eq!(executor.inflight(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_thread_pool_executor_panic() {
+        let executor = IoExecutor::thread_pool(1, "test-thread-pool-executor-panic");
+
+        let err = executor
+            .asyncify(|| -> DeviceResult<()> { panic!("injected panic") })
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("injected panic"), "{err}");
+        assert_eq!(executor.inflight(), 0);
+
+        // The only worker survives the panic.
+        assert_eq!(executor.asyncify(|| Ok(42)).await.unwrap(), 42);
+        assert_eq!(executor.inflight(), 0);
+
+        // The lent buffer is returned even if the job panics.
+        let (res, buf) = executor
+            .asyncify_with_buf(vec![42u8; 16], |_| -> DeviceResult<()> { panic!("injected panic") })
+            .await;
+        assert!(res.is_err());
+        assert_eq!(buf, vec![42u8; 16]);
+        assert_eq!(executor.inflight(), 0);
+    }
+}
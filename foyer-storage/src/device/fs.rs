@@ -13,7 +13,8 @@
 //  limitations under the License.
 
 use std::{
-    fs::{create_dir_all, File, OpenOptions},
+    fs::{create_dir_all, remove_dir_all, remove_file, rename, File, OpenOptions},
+    io::{BufReader, BufWriter, ErrorKind, Write},
     os::fd::{AsRawFd, BorrowedFd, RawFd},
     path::{Path, PathBuf},
     sync::Arc,
@@ -23,10 +24,31 @@ use allocator_api2::vec::Vec as VecA;
 use foyer_common::{fs::freespace, range::RangeBoundsExt};
 use futures::future::try_join_all;
 use itertools::Itertools;
+use nix::errno::Errno;
 
-use super::{allocator::AlignedAllocator, asyncify, Device, DeviceError, DeviceResult, IoBuf, IoBufMut, IoRange};
+use super::{
+    allocator::AlignedAllocator, executor::IoExecutor, Device, DeviceError, DeviceResult, IoBuf, IoBufMut, IoRange,
+};
 use crate::region::RegionId;
 
+#[derive(thiserror::Error, Debug)]
+pub enum ConfigError {
+    #[error("get freespace of {dir:?} error: {errno}")]
+    Freespace { dir: PathBuf, errno: Errno },
+    #[error("capacity {capacity} rounds to zero with align {align}")]
+    ZeroCapacity { capacity: usize, align: usize },
+}
+
+/// Policy for the region files that fail to open.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RegionOpenErrorPolicy {
+    /// Abort opening the device.
+    #[default]
+    Abort,
+    /// Skip the bad region file with a warning and recreate it empty, losing only its data.
+    SkipAndRecreate,
+}
+
 #[derive(Debug)]
 pub struct FsDeviceConfigBuilder {
     pub dir: PathBuf,
@@ -34,6 +56,12 @@ pub struct FsDeviceConfigBuilder {
     pub file_size: Option<usize>,
     pub align: Option<usize>,
     pub io_size: Option<usize>,
+    pub io_executor: Option<IoExecutor>,
+    pub on_region_open_error: RegionOpenErrorPolicy,
+    pub write_io_size: Option<usize>,
+    pub read_io_size: Option<usize>,
+    pub extra_dirs: Vec<PathBuf>,
+    pub direct_io: bool,
 }
 
 impl FsDeviceConfigBuilder {
@@ -49,9 +77,25 @@ impl FsDeviceConfigBuilder {
             file_size: None,
             align: None,
             io_size: None,
+            io_executor: None,
+            on_region_open_error: RegionOpenErrorPolicy::default(),
+            write_io_size: None,
+            read_io_size: None,
+            extra_dirs: vec![],
+            direct_io: true,
         }
     }
 
+    /// Stripe the regions round-robin across the base dirs, e.g. on different disks, for aggregate bandwidth.
+    ///
+    /// The first dir replaces the dir given by [`FsDeviceConfigBuilder::new`].
+    pub fn with_dirs(mut self, dirs: Vec<PathBuf>) -> Self {
+        let mut dirs = dirs.into_iter();
+        self.dir = dirs.next().expect("at least one dir must be given");
+        self.extra_dirs = dirs.collect();
+        self
+    }
+
     pub fn with_capacity(mut self, capacity: usize) -> Self {
         self.capacity = Some(capacity);
         self
@@ -72,14 +116,80 @@ impl FsDeviceConfigBuilder {
         self
     }
 
+    pub fn with_io_executor(mut self, io_executor: IoExecutor) -> Self {
+        self.io_executor = Some(io_executor);
+        self
+    }
+
+    /// Set the io size of writes, which overrides `io_size` for the flush threshold.
+    pub fn with_write_io_size(mut self, write_io_size: usize) -> Self {
+        self.write_io_size = Some(write_io_size);
+        self
+    }
+
+    /// Set the io size of reads, which overrides `io_size` for the read-ahead window.
+    pub fn with_read_io_size(mut self, read_io_size: usize) -> Self {
+        self.read_io_size = Some(read_io_size);
+        self
+    }
+
+    pub fn with_on_region_open_error(mut self, policy: RegionOpenErrorPolicy) -> Self {
+        self.on_region_open_error = policy;
+        self
+    }
+
+    /// Decide if the region files are opened with `O_DIRECT`, which only takes effect on Linux.
+    ///
+    /// Disable it for the filesystems that don't support `O_DIRECT`, e.g. tmpfs, some overlay filesystems and ZFS. The
+    /// layout still honors the configured `align`. With buffered I/O, the writes are only durable after they are
+    /// synced to the disk by `flush` (`syncfs(2)` on Linux).
+    ///
+    /// Default: `true`.
+    pub fn with_direct_io(mut self, direct_io: bool) -> Self {
+        self.direct_io = direct_io;
+        self
+    }
+
+    /// Build the config, panics if [`FsDeviceConfigBuilder::try_build`] fails.
     pub fn build(self) -> FsDeviceConfig {
+        self.try_build().unwrap()
+    }
+
+    /// Build the config.
+    ///
+    /// Fails if the capacity is not given and the freespace of any dir cannot be got, e.g. the dir doesn't exist yet,
+    /// or if the capacity rounds to zero, e.g. on a tiny volume.
+    ///
+    /// The default capacity is 80% of the total freespace of all dirs.
+    pub fn try_build(self) -> Result<FsDeviceConfig, ConfigError> {
         let align_v = |value: usize, align: usize| value - value % align;
 
         let dir = self.dir;
+        let extra_dirs = self.extra_dirs;
 
         let align = self.align.unwrap_or(Self::DEFAULT_ALIGN);
 
-        let capacity = self.capacity.unwrap_or(freespace(&dir).unwrap() / 10 * 8);
+        let capacity = match self.capacity {
+            Some(capacity) => capacity,
+            None => {
+                let mut total = 0;
+                for dir in std::iter::once(&dir).chain(extra_dirs.iter()) {
+                    match freespace(dir) {
+                        Ok(freespace) => total += freespace,
+                        Err(errno) => {
+                            return Err(ConfigError::Freespace {
+                                dir: dir.clone(),
+                                errno,
+                            })
+                        }
+                    }
+                }
+                total / 10 * 8
+            }
+        };
+        if align_v(capacity, align) == 0 {
+            return Err(ConfigError::ZeroCapacity { capacity, align });
+        }
         let capacity = align_v(capacity, align);
 
         let file_size = self.file_size.unwrap_or(Self::DEFAULT_FILE_SIZE).clamp(align, capacity);
@@ -90,13 +200,24 @@ impl FsDeviceConfigBuilder {
         let io_size = self.io_size.unwrap_or(Self::DEFAULT_IO_SIZE).max(align);
         let io_size = align_v(io_size, align);
 
-        FsDeviceConfig {
+        let write_io_size = self.write_io_size.map(|size| align_v(size.max(align), align));
+        let read_io_size = self.read_io_size.map(|size| align_v(size.max(align), align));
+
+        let io_executor = self.io_executor.unwrap_or_default();
+
+        Ok(FsDeviceConfig {
             dir,
             capacity,
             file_size,
             align,
             io_size,
-        }
+            io_executor,
+            on_region_open_error: self.on_region_open_error,
+            write_io_size,
+            read_io_size,
+            extra_dirs,
+            direct_io: self.direct_io,
+        })
     }
 }
 
@@ -116,13 +237,49 @@ pub struct FsDeviceConfig {
 
     /// recommended optimized io block size
     pub io_size: usize,
+
+    /// executor to offload blocking syscalls
+    pub io_executor: IoExecutor,
+
+    /// what to do with a region file that fails to open
+    pub on_region_open_error: RegionOpenErrorPolicy,
+
+    /// io size of writes, must be multipliers of `align`, `None` means `io_size`
+    pub write_io_size: Option<usize>,
+
+    /// io size of reads, must be multipliers of `align`, `None` means `io_size`
+    pub read_io_size: Option<usize>,
+
+    /// extra base dir paths, regions are striped round-robin across `dir` and the extra dirs
+    ///
+    /// The region of each file is decided by the order of the dirs, which must not change across restarts.
+    pub extra_dirs: Vec<PathBuf>,
+
+    /// open the region files with `O_DIRECT`, only takes effect on Linux
+    ///
+    /// Without direct I/O, the writes go through the page cache and are only durable after `flush`.
+    pub direct_io: bool,
 }
 
 impl FsDeviceConfig {
+    /// All base dirs, `dir` first.
+    pub fn dirs(&self) -> impl Iterator<Item = &PathBuf> {
+        std::iter::once(&self.dir).chain(self.extra_dirs.iter())
+    }
+
+    #[allow(clippy::manual_is_multiple_of)]
     pub fn assert(&self) {
         assert!(self.align.is_power_of_two());
         assert_eq!(self.file_size % self.align, 0);
         assert_eq!(self.capacity % self.file_size, 0);
+        for size in [self.write_io_size, self.read_io_size].into_iter().flatten() {
+            assert!(
+                size > 0 && size % self.align == 0,
+                "io size must be a positive multiplier of align {}, given: {}",
+                self.align,
+                size
+            );
+        }
     }
 }
 
@@ -132,7 +289,10 @@ struct FsDeviceInner {
 
     // TODO(MrCroxx): use `expect` after `lint_reasons` is stable.
     #[cfg_attr(not(target_os = "linux"), allow(dead_code))]
-    dir: File,
+    dirs: Vec<File>,
+
+    /// Index of the base dir of each region.
+    region_dirs: Vec<usize>,
 
     files: Vec<File>,
 
@@ -168,21 +328,17 @@ impl Device for FsDevice {
 
         let fd = self.fd(region);
 
-        asyncify(move || {
-            let fd = unsafe { BorrowedFd::borrow_raw(fd) };
-            let res = nix::sys::uio::pwrite(fd, &buf.as_ref()[range], offset as i64).map_err(DeviceError::from);
-            (res, buf)
-        })
-        .await
+        self.inner
+            .config
+            .io_executor
+            .asyncify_with_buf(buf, move |buf| {
+                let fd = unsafe { BorrowedFd::borrow_raw(fd) };
+                nix::sys::uio::pwrite(fd, &buf.as_ref()[range], offset as i64).map_err(DeviceError::from)
+            })
+            .await
     }
 
-    async fn read<B>(
-        &self,
-        mut buf: B,
-        range: impl IoRange,
-        region: RegionId,
-        offset: usize,
-    ) -> (DeviceResult<usize>, B)
+    async fn read<B>(&self, buf: B, range: impl IoRange, region: RegionId, offset: usize) -> (DeviceResult<usize>, B)
     where
         B: IoBufMut,
     {
@@ -198,29 +354,49 @@ impl Device for FsDevice {
 
         let fd = self.fd(region);
 
-        asyncify(move || {
-            let fd = unsafe { BorrowedFd::borrow_raw(fd) };
-            let res = nix::sys::uio::pread(fd, &mut buf.as_mut()[range], offset as i64).map_err(DeviceError::from);
-            (res, buf)
-        })
-        .await
+        self.inner
+            .config
+            .io_executor
+            .asyncify_with_buf(buf, move |buf| {
+                let fd = unsafe { BorrowedFd::borrow_raw(fd) };
+                nix::sys::uio::pread(fd, &mut buf.as_mut()[range], offset as i64).map_err(DeviceError::from)
+            })
+            .await
     }
 
     #[cfg(target_os = "linux")]
     async fn flush(&self) -> DeviceResult<()> {
-        let fd = self.inner.dir.as_raw_fd();
-        // Commit fs cache to disk. Linux waits for I/O completions.
-        //
-        // See also [syncfs(2)](https://man7.org/linux/man-pages/man2/sync.2.html)
-        asyncify(move || nix::unistd::syncfs(fd).map_err(DeviceError::from)).await?;
+        // The base dirs may be on different filesystems, sync all of them.
+        let futures = self
+            .inner
+            .dirs
+            .iter()
+            .map(|dir| {
+                let fd = dir.as_raw_fd();
+                // Commit fs cache to disk. Linux waits for I/O completions.
+                //
+                // See also [syncfs(2)](https://man7.org/linux/man-pages/man2/sync.2.html)
+                self.inner
+                    .config
+                    .io_executor
+                    .asyncify(move || nix::unistd::syncfs(fd).map_err(DeviceError::from))
+            })
+            .collect_vec();
+        try_join_all(futures).await?;
         Ok(())
     }
 
     #[cfg(target_os = "macos")]
     async fn flush(&self) -> DeviceResult<()> {
         // Use `nix` aftre https://github.com/nix-rust/nix/issues/2376 is closed.
-        asyncify(move || unsafe { libc::sync() }).await;
-        Ok(())
+        self.inner
+            .config
+            .io_executor
+            .asyncify(move || {
+                unsafe { libc::sync() };
+                Ok(())
+            })
+            .await
     }
 
     #[cfg(not(any(target_os = "linux", target_os = "macos")))]
@@ -229,6 +405,68 @@ impl Device for FsDevice {
         Ok(())
     }
 
+    #[cfg(target_os = "linux")]
+    async fn discard(&self, region: RegionId) -> DeviceResult<()> {
+        use nix::fcntl::{fallocate, FallocateFlags};
+
+        let fd = self.fd(region);
+        let len = self.inner.config.file_size as i64;
+
+        // Punch a hole over the whole region file to release its blocks, keep the file size unchanged.
+        //
+        // See also [fallocate(2)](https://man7.org/linux/man-pages/man2/fallocate.2.html)
+        self.inner
+            .config
+            .io_executor
+            .asyncify(move || {
+                fallocate(
+                    fd,
+                    FallocateFlags::FALLOC_FL_PUNCH_HOLE | FallocateFlags::FALLOC_FL_KEEP_SIZE,
+                    0,
+                    len,
+                )
+                .map_err(DeviceError::from)
+            })
+            .await
+    }
+
+    async fn truncate(&self, regions: usize) -> DeviceResult<()> {
+        let config = &self.inner.config;
+        assert!(
+            regions <= self.regions(),
+            "regions to truncate to must not exceed {}, given: {}",
+            self.regions(),
+            regions
+        );
+
+        // Record the truncated layout before deleting the trailing files, a crash in between only leaves unused files.
+        let manifest = Manifest {
+            align: config.align,
+            file_size: config.file_size,
+            capacity: regions * config.file_size,
+            region_dirs: self.inner.region_dirs[..regions].to_vec(),
+        };
+        let path = config.dir.join(MANIFEST_FILENAME);
+        let paths = (regions..self.regions())
+            .map(|i| self.path(i as RegionId))
+            .collect_vec();
+
+        config
+            .io_executor
+            .asyncify(move || {
+                manifest.write(&path)?;
+                for path in paths {
+                    match remove_file(&path) {
+                        Ok(()) => {}
+                        Err(e) if e.kind() == ErrorKind::NotFound => {}
+                        Err(e) => return Err(e.into()),
+                    }
+                }
+                Ok(())
+            })
+            .await
+    }
+
     fn capacity(&self) -> usize {
         self.inner.config.capacity
     }
@@ -245,6 +483,14 @@ impl Device for FsDevice {
         self.inner.config.io_size
     }
 
+    fn write_io_size(&self) -> usize {
+        self.inner.config.write_io_size.unwrap_or(self.inner.config.io_size)
+    }
+
+    fn read_io_size(&self) -> usize {
+        self.inner.config.read_io_size.unwrap_or(self.inner.config.io_size)
+    }
+
     fn io_buffer_allocator(&self) -> &Self::IoBufferAllocator {
         &self.inner.io_buffer_allocator
     }
@@ -261,34 +507,58 @@ impl FsDevice {
     pub async fn open(config: FsDeviceConfig) -> DeviceResult<Self> {
         config.assert();
 
-        // TODO(MrCroxx): write and read config to a manifest file for pinning
-
         let regions = config.capacity / config.file_size;
 
-        let path = config.dir.clone();
-        let dir = asyncify(move || {
-            create_dir_all(&path)?;
-            File::open(&path).map_err(DeviceError::from)
-        })
-        .await?;
+        let futures = config
+            .dirs()
+            .map(|path| {
+                let path = path.clone();
+                config.io_executor.asyncify(move || {
+                    create_dir_all(&path)?;
+                    File::open(&path).map_err(DeviceError::from)
+                })
+            })
+            .collect_vec();
+        let dirs = try_join_all(futures).await?;
+
+        // Stripe the regions round-robin across the dirs.
+        let region_dirs = (0..regions).map(|i| i % dirs.len()).collect_vec();
+        let paths = config.dirs().cloned().collect_vec();
+
+        // Pin the layout on the first open, the regions are garbage if reopened with another layout.
+        let manifest = Manifest {
+            align: config.align,
+            file_size: config.file_size,
+            capacity: config.capacity,
+            region_dirs: region_dirs.clone(),
+        };
+        let path = config.dir.join(MANIFEST_FILENAME);
+        config.io_executor.asyncify(move || manifest.pin(&path)).await?;
 
+        let policy = config.on_region_open_error;
+        let direct_io = config.direct_io;
         let futures = (0..regions)
             .map(|i| {
-                let path = config.dir.clone().join(Self::filename(i as RegionId));
+                let region = i as RegionId;
+                let path = paths[region_dirs[i]].join(Self::filename(region));
                 async move {
-                    #[cfg(target_os = "linux")]
-                    use std::os::unix::prelude::OpenOptionsExt;
-
-                    let mut opts = OpenOptions::new();
-                    opts.create(true);
-                    opts.write(true);
-                    opts.read(true);
-                    #[cfg(target_os = "linux")]
-                    opts.custom_flags(libc::O_DIRECT);
-
-                    let file = opts.open(path)?;
-
-                    Ok::<_, DeviceError>(file)
+                    match Self::open_file(&path, direct_io) {
+                        Ok(file) => Ok(file),
+                        Err(e) => match policy {
+                            RegionOpenErrorPolicy::Abort => Err(e),
+                            RegionOpenErrorPolicy::SkipAndRecreate => {
+                                tracing::warn!(
+                                    "[fs device]: open region {region} file {path:?} error: {e}, recreate it empty"
+                                );
+                                if path.is_dir() {
+                                    remove_dir_all(&path)?;
+                                } else if path.exists() {
+                                    remove_file(&path)?;
+                                }
+                                Self::open_file(&path, direct_io)
+                            }
+                        },
+                    }
                 }
             })
             .collect_vec();
@@ -298,7 +568,8 @@ impl FsDevice {
 
         let inner = FsDeviceInner {
             config,
-            dir,
+            dirs,
+            region_dirs,
             files,
             io_buffer_allocator,
         };
@@ -306,15 +577,153 @@ impl FsDevice {
         Ok(Self { inner: Arc::new(inner) })
     }
 
+    fn open_file(path: &Path, direct_io: bool) -> DeviceResult<File> {
+        #[cfg(target_os = "linux")]
+        use std::os::unix::prelude::OpenOptionsExt;
+
+        let mut opts = OpenOptions::new();
+        opts.create(true);
+        opts.write(true);
+        opts.read(true);
+        #[cfg(target_os = "linux")]
+        if direct_io {
+            opts.custom_flags(libc::O_DIRECT);
+        }
+        #[cfg(not(target_os = "linux"))]
+        let _ = direct_io;
+
+        let file = opts.open(path)?;
+
+        Ok(file)
+    }
+
     fn fd(&self, region: RegionId) -> RawFd {
         self.inner.files[region as usize].as_raw_fd()
     }
 
+    /// Path of the file of the region, under the base dir the region is striped to.
+    pub fn path(&self, region: RegionId) -> PathBuf {
+        let dir = match self.inner.region_dirs[region as usize] {
+            0 => &self.inner.config.dir,
+            i => &self.inner.config.extra_dirs[i - 1],
+        };
+        dir.join(Self::filename(region))
+    }
+
     fn filename(region: RegionId) -> String {
         format!("foyer-cache-{:08}", region)
     }
 }
 
+const MANIFEST_FILENAME: &str = "foyer-manifest";
+const MANIFEST_MAGIC: u64 = 0x20240620;
+const MANIFEST_VERSION: u32 = 1;
+
+/// Layout of the device, recorded in the manifest file under the base dir on the first open.
+///
+/// # Format
+///
+/// The manifest is bincode-encoded `(magic, version, align, file_size, capacity, region_dirs)`, where `region_dirs` is
+/// the index of the base dir of each region.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Manifest {
+    align: usize,
+    file_size: usize,
+    capacity: usize,
+    region_dirs: Vec<usize>,
+}
+
+impl Manifest {
+    /// Validate the manifest against the recorded one at `path`, or record it if there is none.
+    fn pin(&self, path: &Path) -> DeviceResult<()> {
+        match Self::read(path)? {
+            Some(recorded) => recorded.validate(self),
+            None => self.write(path),
+        }
+    }
+
+    fn read(path: &Path) -> DeviceResult<Option<Self>> {
+        let file = match File::open(path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let mut reader = BufReader::new(file);
+
+        let (magic, version): (u64, u32) = bincode::deserialize_from(&mut reader).map_err(Self::error)?;
+        if magic != MANIFEST_MAGIC {
+            return Err(mismatch("magic", format!("{magic:#x}"), format!("{MANIFEST_MAGIC:#x}")));
+        }
+        if version != MANIFEST_VERSION {
+            return Err(mismatch("version", version, MANIFEST_VERSION));
+        }
+
+        let (align, file_size, capacity, region_dirs) = bincode::deserialize_from(&mut reader).map_err(Self::error)?;
+        Ok(Some(Self {
+            align,
+            file_size,
+            capacity,
+            region_dirs,
+        }))
+    }
+
+    /// Write the manifest to `path` atomically.
+    fn write(&self, path: &Path) -> DeviceResult<()> {
+        let tmp = path.with_extension("tmp");
+        {
+            let mut writer = BufWriter::new(File::create(&tmp)?);
+            bincode::serialize_into(
+                &mut writer,
+                &(
+                    MANIFEST_MAGIC,
+                    MANIFEST_VERSION,
+                    self.align,
+                    self.file_size,
+                    self.capacity,
+                    &self.region_dirs,
+                ),
+            )
+            .map_err(Self::error)?;
+            writer.flush()?;
+            writer.get_ref().sync_all()?;
+        }
+        rename(&tmp, path)?;
+        Ok(())
+    }
+
+    fn validate(&self, given: &Self) -> DeviceResult<()> {
+        if self.align != given.align {
+            return Err(mismatch("align", self.align, given.align));
+        }
+        if self.file_size != given.file_size {
+            return Err(mismatch("file size", self.file_size, given.file_size));
+        }
+        if self.capacity != given.capacity {
+            return Err(mismatch("capacity", self.capacity, given.capacity));
+        }
+        if self.region_dirs != given.region_dirs {
+            return Err(mismatch(
+                "region dirs",
+                format!("{:?}", self.region_dirs),
+                format!("{:?}", given.region_dirs),
+            ));
+        }
+        Ok(())
+    }
+
+    fn error(e: bincode::Error) -> DeviceError {
+        DeviceError::Other(e)
+    }
+}
+
+fn mismatch(field: &'static str, recorded: impl ToString, given: impl ToString) -> DeviceError {
+    DeviceError::ManifestMismatch {
+        field,
+        recorded: recorded.to_string(),
+        given: given.to_string(),
+    }
+}
+
 #[cfg(test)]
 mod tests {
 
@@ -338,6 +747,12 @@ mod tests {
             file_size: FILE_CAPACITY,
             align: ALIGN,
             io_size: ALIGN,
+            io_executor: IoExecutor::default(),
+            on_region_open_error: RegionOpenErrorPolicy::default(),
+            write_io_size: None,
+            read_io_size: None,
+            extra_dirs: vec![],
+            direct_io: true,
         };
         let dev = FsDevice::open(config).await.unwrap();
 
@@ -357,6 +772,281 @@ mod tests {
         drop(rbuffer);
     }
 
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_fs_device_buffered_io() {
+        let dir = tempfile::tempdir().unwrap();
+        let builder = || {
+            FsDeviceConfigBuilder::new(dir.path())
+                .with_capacity(CAPACITY)
+                .with_file_size(FILE_CAPACITY)
+                .with_align(ALIGN)
+                .with_io_size(ALIGN)
+        };
+        assert!(builder().build().direct_io);
+
+        let config = builder().with_direct_io(false).build();
+        let dev = FsDevice::open(config).await.unwrap();
+        for region in 0..FILES as RegionId {
+            let flags = unsafe { libc::fcntl(dev.fd(region), libc::F_GETFL) };
+            assert_eq!(flags & libc::O_DIRECT, 0);
+        }
+
+        let mut wbuffer = dev.io_buffer(ALIGN, ALIGN);
+        (&mut wbuffer[..]).put_slice(&[b'x'; ALIGN]);
+        let (res, wbuffer) = dev.write(wbuffer, .., 0, 0).await;
+        res.unwrap();
+        dev.flush().await.unwrap();
+
+        let rbuffer = dev.io_buffer(ALIGN, ALIGN);
+        let (res, rbuffer) = dev.read(rbuffer, .., 0, 0).await;
+        res.unwrap();
+        assert_eq!(&wbuffer, &rbuffer);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_fs_device_discard() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = FsDeviceConfig {
+            dir: PathBuf::from(dir.path()),
+            capacity: CAPACITY,
+            file_size: FILE_CAPACITY,
+            align: ALIGN,
+            io_size: ALIGN,
+            io_executor: IoExecutor::default(),
+            on_region_open_error: RegionOpenErrorPolicy::default(),
+            write_io_size: None,
+            read_io_size: None,
+            extra_dirs: vec![],
+            direct_io: true,
+        };
+        let dev = FsDevice::open(config).await.unwrap();
+
+        for region in [0, 1] {
+            let mut wbuffer = dev.io_buffer(FILE_CAPACITY, FILE_CAPACITY);
+            (&mut wbuffer[..]).put_slice(&[b'x'; FILE_CAPACITY]);
+            let (res, _) = dev.write(wbuffer, .., region, 0).await;
+            res.unwrap();
+        }
+
+        dev.discard(0).await.unwrap();
+
+        // The discarded region reads zeros and keeps its size, other regions are untouched.
+        assert_eq!(std::fs::metadata(dev.path(0)).unwrap().len(), FILE_CAPACITY as u64);
+        let rbuffer = dev.io_buffer(FILE_CAPACITY, FILE_CAPACITY);
+        let (res, rbuffer) = dev.read(rbuffer, .., 0, 0).await;
+        res.unwrap();
+        assert_eq!(&rbuffer[..], &[0; FILE_CAPACITY]);
+        let rbuffer = dev.io_buffer(FILE_CAPACITY, FILE_CAPACITY);
+        let (res, rbuffer) = dev.read(rbuffer, .., 1, 0).await;
+        res.unwrap();
+        assert_eq!(&rbuffer[..], &[b'x'; FILE_CAPACITY]);
+    }
+
+    #[tokio::test]
+    async fn test_fs_device_thread_pool_executor() {
+        let dir = tempfile::tempdir().unwrap();
+        let io_executor = IoExecutor::thread_pool(1, "test-fs-device-thread-pool-executor");
+        let config = FsDeviceConfig {
+            dir: PathBuf::from(dir.path()),
+            capacity: CAPACITY,
+            file_size: FILE_CAPACITY,
+            align: ALIGN,
+            io_size: ALIGN,
+            io_executor: io_executor.clone(),
+            on_region_open_error: RegionOpenErrorPolicy::default(),
+            write_io_size: None,
+            read_io_size: None,
+            extra_dirs: vec![],
+            direct_io: true,
+        };
+        let dev = FsDevice::open(config).await.unwrap();
+
+        let futures = (0..FILES as RegionId)
+            .map(|region| {
+                let dev = dev.clone();
+                async move {
+                    let mut wbuffer = dev.io_buffer(ALIGN, ALIGN);
+                    (&mut wbuffer[..]).put_slice(&[region as u8; ALIGN]);
+                    let (res, _) = dev.write(wbuffer, .., region, 0).await;
+                    res.unwrap();
+
+                    let rbuffer = dev.io_buffer(ALIGN, ALIGN);
+                    let (res, rbuffer) = dev.read(rbuffer, .., region, 0).await;
+                    res.unwrap();
+                    assert_eq!(&rbuffer[..], &[region as u8; ALIGN]);
+                }
+            })
+            .collect_vec();
+        futures::future::join_all(futures).await;
+
+        assert_eq!(io_executor.inflight(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_fs_device_region_open_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = FsDeviceConfigBuilder::new(dir.path())
+            .with_capacity(CAPACITY)
+            .with_file_size(FILE_CAPACITY)
+            .with_align(ALIGN)
+            .with_io_size(ALIGN)
+            .build();
+        let dev = FsDevice::open(config.clone()).await.unwrap();
+
+        for region in 0..FILES as RegionId {
+            let mut wbuffer = dev.io_buffer(ALIGN, ALIGN);
+            (&mut wbuffer[..]).put_slice(&[region as u8 + 1; ALIGN]);
+            let (res, _) = dev.write(wbuffer, .., region, 0).await;
+            res.unwrap();
+        }
+        dev.flush().await.unwrap();
+        drop(dev);
+
+        // Replace the file of region 3 with a dir, which cannot be opened as a file.
+        let bad = dir.path().join(FsDevice::filename(3));
+        std::fs::remove_file(&bad).unwrap();
+        std::fs::create_dir(&bad).unwrap();
+
+        assert!(FsDevice::open(config.clone()).await.is_err());
+
+        let config = FsDeviceConfig {
+            on_region_open_error: RegionOpenErrorPolicy::SkipAndRecreate,
+            ..config
+        };
+        let dev = FsDevice::open(config).await.unwrap();
+        assert_eq!(dev.regions(), FILES);
+        assert!(bad.is_file());
+
+        for region in 0..FILES as RegionId {
+            let rbuffer = dev.io_buffer(ALIGN, ALIGN);
+            let (res, rbuffer) = dev.read(rbuffer, .., region, 0).await;
+            if region == 3 {
+                // The recreated file is empty.
+                assert_eq!(res.unwrap(), 0);
+            } else {
+                assert_eq!(res.unwrap(), ALIGN);
+                assert_eq!(&rbuffer[..], &[region as u8 + 1; ALIGN]);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_fs_device_multiple_dirs() {
+        let dirs = (0..3).map(|_| tempfile::tempdir().unwrap()).collect_vec();
+        let config = FsDeviceConfigBuilder::new(dirs[0].path())
+            .with_dirs(dirs.iter().map(|dir| dir.path().to_path_buf()).collect())
+            .with_capacity(CAPACITY)
+            .with_file_size(FILE_CAPACITY)
+            .with_align(ALIGN)
+            .with_io_size(ALIGN)
+            .build();
+        assert_eq!(config.dirs().count(), 3);
+        let dev = FsDevice::open(config.clone()).await.unwrap();
+
+        for region in 0..FILES as RegionId {
+            // The regions are striped round-robin across the dirs.
+            let path = dirs[region as usize % 3].path().join(FsDevice::filename(region));
+            assert_eq!(dev.path(region), path);
+            assert!(path.is_file());
+
+            let mut wbuffer = dev.io_buffer(ALIGN, ALIGN);
+            (&mut wbuffer[..]).put_slice(&[region as u8 + 1; ALIGN]);
+            let (res, _) = dev.write(wbuffer, .., region, 0).await;
+            res.unwrap();
+        }
+        dev.flush().await.unwrap();
+        drop(dev);
+
+        // The regions are mapped back to the same files after reopened.
+        let dev = FsDevice::open(config).await.unwrap();
+        for region in 0..FILES as RegionId {
+            let rbuffer = dev.io_buffer(ALIGN, ALIGN);
+            let (res, rbuffer) = dev.read(rbuffer, .., region, 0).await;
+            res.unwrap();
+            assert_eq!(&rbuffer[..], &[region as u8 + 1; ALIGN]);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_fs_device_manifest() {
+        let dirs = (0..2).map(|_| tempfile::tempdir().unwrap()).collect_vec();
+        let config = FsDeviceConfigBuilder::new(dirs[0].path())
+            .with_capacity(CAPACITY)
+            .with_file_size(FILE_CAPACITY)
+            .with_align(ALIGN)
+            .with_io_size(ALIGN)
+            .build();
+
+        let dev = FsDevice::open(config.clone()).await.unwrap();
+        drop(dev);
+        assert!(dirs[0].path().join(MANIFEST_FILENAME).is_file());
+
+        // Reopen with the same layout.
+        let dev = FsDevice::open(config.clone()).await.unwrap();
+        drop(dev);
+
+        let assert_mismatch = |res: DeviceResult<FsDevice>, expected: &str| match res {
+            Err(DeviceError::ManifestMismatch { field, .. }) => assert_eq!(field, expected),
+            res => panic!("expected manifest mismatch of {expected}, given: {res:?}"),
+        };
+
+        let mismatched = FsDeviceConfig {
+            align: 2 * ALIGN,
+            io_size: 2 * ALIGN,
+            ..config.clone()
+        };
+        assert_mismatch(FsDevice::open(mismatched).await, "align");
+
+        let mismatched = FsDeviceConfig {
+            file_size: 2 * FILE_CAPACITY,
+            ..config.clone()
+        };
+        assert_mismatch(FsDevice::open(mismatched).await, "file size");
+
+        let mismatched = FsDeviceConfig {
+            capacity: 2 * CAPACITY,
+            ..config.clone()
+        };
+        assert_mismatch(FsDevice::open(mismatched).await, "capacity");
+
+        let mismatched = FsDeviceConfig {
+            extra_dirs: vec![dirs[1].path().to_path_buf()],
+            ..config.clone()
+        };
+        assert_mismatch(FsDevice::open(mismatched).await, "region dirs");
+
+        // The manifest is not overwritten by the mismatched opens.
+        FsDevice::open(config.clone()).await.unwrap();
+
+        std::fs::write(dirs[0].path().join(MANIFEST_FILENAME), b"garbage").unwrap();
+        assert!(FsDevice::open(config).await.is_err());
+    }
+
+    #[test]
+    fn test_config_builder_multiple_dirs() {
+        let dir = tempfile::tempdir().unwrap();
+        let nonexistent = dir.path().join("nonexistent");
+
+        // The default capacity is computed from the freespace of all dirs.
+        FsDeviceConfigBuilder::new(dir.path())
+            .with_dirs(vec![dir.path().to_path_buf(), current_dir().unwrap()])
+            .build()
+            .assert();
+
+        match FsDeviceConfigBuilder::new(dir.path())
+            .with_dirs(vec![dir.path().to_path_buf(), nonexistent.clone()])
+            .try_build()
+        {
+            Err(ConfigError::Freespace { dir: d, errno }) => {
+                assert_eq!(d, nonexistent);
+                assert_eq!(errno, Errno::ENOENT);
+            }
+            res => panic!("build must fail if any dir is nonexistent, got: {res:?}"),
+        }
+    }
+
     #[test]
     fn test_config_builder() {
         let dir = current_dir().unwrap();
@@ -366,4 +1056,43 @@ mod tests {
 
         config.assert();
     }
+
+    #[test]
+    fn test_config_builder_nonexistent_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path().join("nonexistent");
+
+        match FsDeviceConfigBuilder::new(&dir).try_build() {
+            Err(ConfigError::Freespace { dir: d, errno }) => {
+                assert_eq!(d, dir);
+                assert_eq!(errno, Errno::ENOENT);
+            }
+            res => panic!("build must fail on a nonexistent dir, got: {res:?}"),
+        }
+
+        // The capacity is not computed from the freespace if given.
+        FsDeviceConfigBuilder::new(&dir)
+            .with_capacity(CAPACITY)
+            .try_build()
+            .unwrap()
+            .assert();
+    }
+
+    #[test]
+    fn test_config_builder_zero_capacity() {
+        let dir = tempfile::tempdir().unwrap();
+
+        // As the capacity computed from the freespace of a tiny volume.
+        match FsDeviceConfigBuilder::new(dir.path())
+            .with_capacity(ALIGN - 1)
+            .with_align(ALIGN)
+            .try_build()
+        {
+            Err(ConfigError::ZeroCapacity { capacity, align }) => {
+                assert_eq!(capacity, ALIGN - 1);
+                assert_eq!(align, ALIGN);
+            }
+            res => panic!("build must fail if the capacity rounds to zero, got: {res:?}"),
+        }
+    }
 }
@@ -0,0 +1,349 @@
+//  Copyright 2024 Foyer Project Authors
+//
+//  Licensed under the Apache License, Version 2.0 (the "License");
+//  you may not use this file except in compliance with the License.
+//  You may obtain a copy of the License at
+//
+//  http://www.apache.org/licenses/LICENSE-2.0
+//
+//  Unless required by applicable law or agreed to in writing, software
+//  distributed under the License is distributed on an "AS IS" BASIS,
+//  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//  See the License for the specific language governing permissions and
+//  limitations under the License.
+
+//! Device backed by memory, which is suitable for tests and ephemeral caches.
+//!
+//! Each region is an aligned buffer allocated when the device is opened. Writes and reads copy from and to the region
+//! buffers directly, and flush is a no-op. Nothing is persisted, the content is lost after the device is dropped.
+//!
+//! The device honors the same alignment as [`super::fs::FsDevice`] with direct I/O, so the store works against it
+//! unchanged.
+
+use std::sync::Arc;
+
+use allocator_api2::vec::Vec as VecA;
+use foyer_common::{bits, range::RangeBoundsExt};
+use itertools::Itertools;
+use parking_lot::RwLock;
+
+use super::{allocator::AlignedAllocator, Device, DeviceResult, IoBuf, IoBufMut, IoRange};
+use crate::region::RegionId;
+
+#[derive(Debug, Clone)]
+pub struct MemDeviceConfig {
+    /// must be multipliers of `align` and `region_size`
+    pub capacity: usize,
+
+    /// must be multipliers of `align`
+    pub region_size: usize,
+
+    /// io block alignment, must be pow of 2
+    pub align: usize,
+
+    /// recommended optimized io block size
+    pub io_size: usize,
+
+    /// io size of writes, must be multipliers of `align`, `None` means `io_size`
+    pub write_io_size: Option<usize>,
+
+    /// io size of reads, must be multipliers of `align`, `None` means `io_size`
+    pub read_io_size: Option<usize>,
+}
+
+impl MemDeviceConfig {
+    #[allow(clippy::manual_is_multiple_of)]
+    pub fn assert(&self) {
+        assert!(self.align.is_power_of_two());
+        assert_eq!(self.region_size % self.align, 0);
+        assert_eq!(self.capacity % self.region_size, 0);
+        for size in [self.write_io_size, self.read_io_size].into_iter().flatten() {
+            assert!(
+                size > 0 && size % self.align == 0,
+                "io size must be a positive multiplier of align {}, given: {}",
+                self.align,
+                size
+            );
+        }
+    }
+}
+
+#[derive(Debug)]
+struct MemDeviceInner {
+    config: MemDeviceConfig,
+
+    regions: Vec<RwLock<VecA<u8, AlignedAllocator>>>,
+
+    io_buffer_allocator: AlignedAllocator,
+}
+
+#[derive(Debug, Clone)]
+pub struct MemDevice {
+    inner: Arc<MemDeviceInner>,
+}
+
+impl Device for MemDevice {
+    type Config = MemDeviceConfig;
+    type IoBufferAllocator = AlignedAllocator;
+
+    async fn open(config: MemDeviceConfig) -> DeviceResult<Self> {
+        Ok(Self::new(config))
+    }
+
+    async fn write<B>(&self, buf: B, range: impl IoRange, region: RegionId, offset: usize) -> (DeviceResult<usize>, B)
+    where
+        B: IoBuf,
+    {
+        let region_size = self.inner.config.region_size;
+
+        let range = range.bounds(0..buf.as_ref().len());
+        let len = RangeBoundsExt::size(&range).unwrap();
+
+        assert!(
+            offset + len <= region_size,
+            "offset ({offset}) + len ({len}) <= region size ({region_size})"
+        );
+        self.assert_aligned(offset, len);
+
+        self.inner.regions[region as usize].write()[offset..offset + len].copy_from_slice(&buf.as_ref()[range]);
+
+        (Ok(len), buf)
+    }
+
+    async fn read<B>(
+        &self,
+        mut buf: B,
+        range: impl IoRange,
+        region: RegionId,
+        offset: usize,
+    ) -> (DeviceResult<usize>, B)
+    where
+        B: IoBufMut,
+    {
+        let region_size = self.inner.config.region_size;
+
+        let range = range.bounds(0..buf.as_ref().len());
+        let len = RangeBoundsExt::size(&range).unwrap();
+
+        assert!(
+            offset + len <= region_size,
+            "offset ({offset}) + len ({len}) <= region size ({region_size})"
+        );
+        self.assert_aligned(offset, len);
+
+        buf.as_mut()[range].copy_from_slice(&self.inner.regions[region as usize].read()[offset..offset + len]);
+
+        (Ok(len), buf)
+    }
+
+    async fn flush(&self) -> DeviceResult<()> {
+        Ok(())
+    }
+
+    async fn discard(&self, region: RegionId) -> DeviceResult<()> {
+        self.inner.regions[region as usize].write().fill(0);
+        Ok(())
+    }
+
+    fn capacity(&self) -> usize {
+        self.inner.config.capacity
+    }
+
+    fn regions(&self) -> usize {
+        self.inner.regions.len()
+    }
+
+    fn align(&self) -> usize {
+        self.inner.config.align
+    }
+
+    fn io_size(&self) -> usize {
+        self.inner.config.io_size
+    }
+
+    fn write_io_size(&self) -> usize {
+        self.inner.config.write_io_size.unwrap_or(self.inner.config.io_size)
+    }
+
+    fn read_io_size(&self) -> usize {
+        self.inner.config.read_io_size.unwrap_or(self.inner.config.io_size)
+    }
+
+    fn io_buffer_allocator(&self) -> &Self::IoBufferAllocator {
+        &self.inner.io_buffer_allocator
+    }
+
+    fn io_buffer(&self, len: usize, capacity: usize) -> VecA<u8, Self::IoBufferAllocator> {
+        assert!(len <= capacity);
+        let mut buf = VecA::with_capacity_in(capacity, self.inner.io_buffer_allocator);
+        unsafe { buf.set_len(len) };
+        buf
+    }
+}
+
+impl MemDevice {
+    pub fn new(config: MemDeviceConfig) -> Self {
+        config.assert();
+
+        let io_buffer_allocator = AlignedAllocator::new(config.align);
+
+        let regions = (0..config.capacity / config.region_size)
+            .map(|_| {
+                let mut buf = VecA::with_capacity_in(config.region_size, io_buffer_allocator);
+                buf.resize(config.region_size, 0);
+                RwLock::new(buf)
+            })
+            .collect_vec();
+
+        let inner = MemDeviceInner {
+            config,
+            regions,
+            io_buffer_allocator,
+        };
+
+        Self { inner: Arc::new(inner) }
+    }
+
+    /// The same as the direct I/O of [`super::fs::FsDevice`], both the offset and the len must be aligned.
+    fn assert_aligned(&self, offset: usize, len: usize) {
+        let align = self.inner.config.align;
+        assert!(
+            bits::is_aligned(align, offset) && bits::is_aligned(align, len),
+            "offset ({offset}) and len ({len}) must be aligned to {align}"
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use bytes::BufMut;
+
+    use super::*;
+    use crate::{
+        buffer::{FlushBuffer, FlushErrorPolicy, PaddingPolicy},
+        compress::Compression,
+        flusher::Entry,
+        generic::{ChecksumAlgorithm, RegionEntryIter},
+        io_limit::IoBytesLimiter,
+        region::Region,
+    };
+
+    const REGIONS: usize = 8;
+    const REGION_SIZE: usize = 8 * 1024; // 8 KiB
+    const CAPACITY: usize = REGIONS * REGION_SIZE; // 64 KiB
+    const ALIGN: usize = 4 * 1024;
+
+    fn config() -> MemDeviceConfig {
+        MemDeviceConfig {
+            capacity: CAPACITY,
+            region_size: REGION_SIZE,
+            align: ALIGN,
+            io_size: ALIGN,
+            write_io_size: None,
+            read_io_size: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_mem_device_simple() {
+        let dev = MemDevice::new(config());
+        assert_eq!(dev.regions(), REGIONS);
+        assert_eq!(dev.region_size(), REGION_SIZE);
+
+        for region in 0..REGIONS as RegionId {
+            let mut wbuffer = dev.io_buffer(ALIGN, ALIGN);
+            (&mut wbuffer[..]).put_slice(&[region as u8 + 1; ALIGN]);
+            let (res, _) = dev.write(wbuffer, .., region, ALIGN).await;
+            assert_eq!(res.unwrap(), ALIGN);
+        }
+
+        for region in 0..REGIONS as RegionId {
+            let rbuffer = dev.io_buffer(2 * ALIGN, 2 * ALIGN);
+            let (res, rbuffer) = dev.read(rbuffer, .., region, 0).await;
+            assert_eq!(res.unwrap(), 2 * ALIGN);
+            // The unwritten part is zeroed.
+            assert_eq!(&rbuffer[..ALIGN], &[0; ALIGN]);
+            assert_eq!(&rbuffer[ALIGN..], &[region as u8 + 1; ALIGN]);
+        }
+    }
+
+    #[test]
+    fn test_mem_device_io_buffer_aligned() {
+        let dev = MemDevice::new(config());
+        let buf = dev.io_buffer(ALIGN, 2 * ALIGN);
+        assert_eq!(buf.len(), ALIGN);
+        assert!(bits::is_aligned(ALIGN, buf.as_ptr() as usize));
+    }
+
+    #[tokio::test]
+    #[should_panic]
+    async fn test_mem_device_unaligned_write() {
+        let dev = MemDevice::new(config());
+        let buf = dev.io_buffer(ALIGN, ALIGN);
+        let _ = dev.write(buf, ..ALIGN / 2, 0, 0).await;
+    }
+
+    #[tokio::test]
+    async fn test_mem_device_flush_buffer() {
+        let dev = MemDevice::new(MemDeviceConfig {
+            capacity: 4 * 64 * 1024,
+            region_size: 64 * 1024,
+            align: ALIGN,
+            io_size: 16 * 1024,
+            write_io_size: None,
+            read_io_size: None,
+        });
+
+        let mut buffer = FlushBuffer::new(
+            dev.clone(),
+            true,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            PaddingPolicy::None,
+            false,
+            0,
+            ChecksumAlgorithm::default(),
+            FlushErrorPolicy::default(),
+            IoBytesLimiter::default(),
+            None,
+            false,
+        );
+        buffer.rotate(0, 0).await.unwrap();
+
+        let mut positioneds = vec![];
+        for i in 0..8u64 {
+            let entry = Entry {
+                key: Arc::new(i),
+                value: Arc::new(vec![i as u8; 3 * 1024]),
+                compression: Compression::None,
+                sequence: i,
+                enqueued: Instant::now(),
+                expire_at: None,
+            };
+            positioneds.extend(buffer.write(entry).await.unwrap().unwrap_left());
+        }
+        positioneds.extend(buffer.flush().await.unwrap());
+        assert_eq!(positioneds.len(), 8);
+        for positioned in positioneds.iter() {
+            assert!(bits::is_aligned(ALIGN, positioned.offset));
+        }
+
+        let mut iter =
+            RegionEntryIter::<u64, Vec<u8>, _>::open(Region::new(0, dev.clone(), IoBytesLimiter::default()), true)
+                .await
+                .unwrap()
+                .unwrap();
+        for i in 0..8u64 {
+            let (key, value, _) = iter.next_kv().await.unwrap().unwrap();
+            assert_eq!(key, i);
+            assert_eq!(value, vec![i as u8; 3 * 1024]);
+        }
+        assert!(iter.next_kv().await.unwrap().is_none());
+    }
+}
@@ -0,0 +1,426 @@
+//  Copyright 2024 Foyer Project Authors
+//
+//  Licensed under the Apache License, Version 2.0 (the "License");
+//  you may not use this file except in compliance with the License.
+//  You may obtain a copy of the License at
+//
+//  http://www.apache.org/licenses/LICENSE-2.0
+//
+//  Unless required by applicable law or agreed to in writing, software
+//  distributed under the License is distributed on an "AS IS" BASIS,
+//  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//  See the License for the specific language governing permissions and
+//  limitations under the License.
+
+//! Device backed by memory-mapped region files, which is suitable for read-mostly caches that fit in the page cache.
+//!
+//! Each region maps to a fixed-size file that is mapped as a whole when the device is opened. Writes go through
+//! `pwrite(2)` and are persisted by `msync(2)` on flush. Reads are served by copying from the mapping directly, without
+//! any syscall or blocking job. [`MmapDevice::slice`] exposes the mapped bytes without copying at all.
+//!
+//! Reading a page that is not resident triggers a page fault on the calling thread, so the device only fits workloads
+//! whose hot set stays in memory.
+
+use std::{
+    fs::{create_dir_all, File, OpenOptions},
+    num::NonZeroUsize,
+    ops::{Deref, Range},
+    os::fd::{AsRawFd, BorrowedFd, RawFd},
+    path::PathBuf,
+    ptr::NonNull,
+    sync::Arc,
+};
+
+use allocator_api2::vec::Vec as VecA;
+use foyer_common::range::RangeBoundsExt;
+use futures::future::try_join_all;
+use itertools::Itertools;
+use nix::sys::mman::{mmap, msync, munmap, MapFlags, MsFlags, ProtFlags};
+
+use super::{
+    allocator::AlignedAllocator, executor::IoExecutor, Device, DeviceError, DeviceResult, IoBuf, IoBufMut, IoRange,
+};
+use crate::region::RegionId;
+
+#[derive(Debug, Clone)]
+pub struct MmapDeviceConfig {
+    /// base dir path
+    pub dir: PathBuf,
+
+    /// must be multipliers of `align` and `file_capacity`
+    pub capacity: usize,
+
+    /// must be multipliers of `align` and the page size
+    pub file_size: usize,
+
+    /// io block alignment, must be pow of 2
+    pub align: usize,
+
+    /// recommended optimized io block size
+    pub io_size: usize,
+
+    /// executor to offload blocking syscalls
+    pub io_executor: IoExecutor,
+}
+
+impl MmapDeviceConfig {
+    pub fn assert(&self) {
+        assert!(self.align.is_power_of_two());
+        assert_eq!(self.file_size % self.align, 0);
+        assert_eq!(self.capacity % self.file_size, 0);
+    }
+}
+
+/// A shared read-only mapping of a whole region file.
+#[derive(Debug)]
+struct Mapping {
+    ptr: NonNull<u8>,
+    len: usize,
+}
+
+// The mapping is never written through the pointer, and lives as long as the device.
+unsafe impl Send for Mapping {}
+unsafe impl Sync for Mapping {}
+
+impl Mapping {
+    fn new(file: &File, len: usize) -> DeviceResult<Self> {
+        let ptr = unsafe {
+            mmap(
+                None,
+                NonZeroUsize::new(len).unwrap(),
+                ProtFlags::PROT_READ,
+                MapFlags::MAP_SHARED,
+                file,
+                0,
+            )?
+        };
+        Ok(Self { ptr: ptr.cast(), len })
+    }
+
+    fn as_slice(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self.ptr.as_ptr(), self.len) }
+    }
+
+    fn sync(&self) -> DeviceResult<()> {
+        unsafe { msync(self.ptr.cast(), self.len, MsFlags::MS_SYNC) }?;
+        Ok(())
+    }
+}
+
+impl Drop for Mapping {
+    fn drop(&mut self) {
+        if let Err(e) = unsafe { munmap(self.ptr.cast(), self.len) } {
+            tracing::warn!("[mmap device]: munmap error: {e}");
+        }
+    }
+}
+
+#[derive(Debug)]
+struct MmapDeviceInner {
+    config: MmapDeviceConfig,
+
+    files: Vec<File>,
+
+    mappings: Vec<Mapping>,
+
+    io_buffer_allocator: AlignedAllocator,
+}
+
+#[derive(Debug, Clone)]
+pub struct MmapDevice {
+    inner: Arc<MmapDeviceInner>,
+}
+
+/// Zero-copy view of the mapped bytes of a region.
+///
+/// The view keeps the device mapping alive. The bytes may change if the range is overwritten while the view is held.
+#[derive(Debug, Clone)]
+pub struct MmapSlice {
+    inner: Arc<MmapDeviceInner>,
+    region: RegionId,
+    range: Range<usize>,
+}
+
+impl Deref for MmapSlice {
+    type Target = [u8];
+
+    fn deref(&self) -> &Self::Target {
+        &self.inner.mappings[self.region as usize].as_slice()[self.range.clone()]
+    }
+}
+
+impl AsRef<[u8]> for MmapSlice {
+    fn as_ref(&self) -> &[u8] {
+        self
+    }
+}
+
+impl Device for MmapDevice {
+    type Config = MmapDeviceConfig;
+    type IoBufferAllocator = AlignedAllocator;
+
+    async fn open(config: MmapDeviceConfig) -> DeviceResult<Self> {
+        Self::open(config).await
+    }
+
+    async fn write<B>(&self, buf: B, range: impl IoRange, region: RegionId, offset: usize) -> (DeviceResult<usize>, B)
+    where
+        B: IoBuf,
+    {
+        let file_capacity = self.inner.config.file_size;
+        let align = self.inner.config.align;
+
+        let range = range.bounds(0..buf.as_ref().len());
+        let len = RangeBoundsExt::size(&range).unwrap();
+
+        assert!(
+            offset + len <= file_capacity,
+            "offset ({offset}) + len ({len}) <= file capacity ({file_capacity})"
+        );
+        assert_eq!(offset % align, 0, "offset ({offset}) must be aligned to {align}");
+        assert_eq!(len % align, 0, "len ({len}) must be aligned to {align}");
+
+        let fd = self.fd(region);
+
+        self.inner
+            .config
+            .io_executor
+            .asyncify_with_buf(buf, move |buf| {
+                let fd = unsafe { BorrowedFd::borrow_raw(fd) };
+                nix::sys::uio::pwrite(fd, &buf.as_ref()[range], offset as i64).map_err(DeviceError::from)
+            })
+            .await
+    }
+
+    async fn read<B>(
+        &self,
+        mut buf: B,
+        range: impl IoRange,
+        region: RegionId,
+        offset: usize,
+    ) -> (DeviceResult<usize>, B)
+    where
+        B: IoBufMut,
+    {
+        let file_capacity = self.inner.config.file_size;
+
+        let range = range.bounds(0..buf.as_ref().len());
+        let len = RangeBoundsExt::size(&range).unwrap();
+
+        assert!(
+            offset + len <= file_capacity,
+            "offset ({offset}) + len ({len}) <= file capacity ({file_capacity})"
+        );
+
+        // The page cache is shared by `pwrite(2)` and the mapping, so the written data is visible without `msync(2)`.
+        let mapping = self.inner.mappings[region as usize].as_slice();
+        buf.as_mut()[range].copy_from_slice(&mapping[offset..offset + len]);
+
+        (Ok(len), buf)
+    }
+
+    async fn flush(&self) -> DeviceResult<()> {
+        let inner = self.inner.clone();
+        self.inner
+            .config
+            .io_executor
+            .asyncify(move || inner.mappings.iter().try_for_each(|mapping| mapping.sync()))
+            .await
+    }
+
+    fn capacity(&self) -> usize {
+        self.inner.config.capacity
+    }
+
+    fn regions(&self) -> usize {
+        self.inner.files.len()
+    }
+
+    fn align(&self) -> usize {
+        self.inner.config.align
+    }
+
+    fn io_size(&self) -> usize {
+        self.inner.config.io_size
+    }
+
+    fn io_buffer_allocator(&self) -> &Self::IoBufferAllocator {
+        &self.inner.io_buffer_allocator
+    }
+
+    fn io_buffer(&self, len: usize, capacity: usize) -> VecA<u8, Self::IoBufferAllocator> {
+        assert!(len <= capacity);
+        let mut buf = VecA::with_capacity_in(capacity, self.inner.io_buffer_allocator);
+        unsafe { buf.set_len(len) };
+        buf
+    }
+}
+
+impl MmapDevice {
+    pub async fn open(config: MmapDeviceConfig) -> DeviceResult<Self> {
+        config.assert();
+
+        let regions = config.capacity / config.file_size;
+        let file_size = config.file_size;
+
+        let path = config.dir.clone();
+        config
+            .io_executor
+            .asyncify(move || create_dir_all(path).map_err(DeviceError::from))
+            .await?;
+
+        let futures = (0..regions)
+            .map(|i| {
+                let path = config.dir.clone().join(Self::filename(i as RegionId));
+                config.io_executor.asyncify(move || {
+                    // The page cache is required by the mapping, so `O_DIRECT` is not used here.
+                    let file = OpenOptions::new()
+                        .create(true)
+                        .write(true)
+                        .read(true)
+                        .truncate(false)
+                        .open(path)?;
+                    file.set_len(file_size as u64)?;
+                    let mapping = Mapping::new(&file, file_size)?;
+                    Ok::<_, DeviceError>((file, mapping))
+                })
+            })
+            .collect_vec();
+        let (files, mappings) = try_join_all(futures).await?.into_iter().unzip();
+
+        let io_buffer_allocator = AlignedAllocator::new(config.align);
+
+        let inner = MmapDeviceInner {
+            config,
+            files,
+            mappings,
+            io_buffer_allocator,
+        };
+
+        Ok(Self { inner: Arc::new(inner) })
+    }
+
+    /// Get a zero-copy view of the mapped bytes of the region.
+    pub fn slice(&self, region: RegionId, range: Range<usize>) -> MmapSlice {
+        let file_capacity = self.inner.config.file_size;
+        assert!(
+            range.end <= file_capacity,
+            "range end ({}) <= file capacity ({file_capacity})",
+            range.end
+        );
+        MmapSlice {
+            inner: self.inner.clone(),
+            region,
+            range,
+        }
+    }
+
+    fn fd(&self, region: RegionId) -> RawFd {
+        self.inner.files[region as usize].as_raw_fd()
+    }
+
+    fn filename(region: RegionId) -> String {
+        format!("foyer-cache-{:08}", region)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use bytes::BufMut;
+
+    use super::*;
+    use crate::device::executor::{BlockingExecutor, BlockingJob};
+
+    const FILES: usize = 4;
+    const FILE_CAPACITY: usize = 16 * 1024; // 16 KiB
+    const CAPACITY: usize = FILES * FILE_CAPACITY; // 64 KiB
+    const ALIGN: usize = 4 * 1024;
+
+    /// Run blocking jobs inline and count them.
+    #[derive(Debug, Default)]
+    struct CountingExecutor {
+        jobs: AtomicUsize,
+    }
+
+    impl BlockingExecutor for CountingExecutor {
+        fn name(&self) -> &str {
+            "test-mmap-device-counting-executor"
+        }
+
+        fn execute(&self, job: BlockingJob) {
+            self.jobs.fetch_add(1, Ordering::SeqCst);
+            job()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_mmap_device_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let executor = Arc::new(CountingExecutor::default());
+        let config = MmapDeviceConfig {
+            dir: PathBuf::from(dir.path()),
+            capacity: CAPACITY,
+            file_size: FILE_CAPACITY,
+            align: ALIGN,
+            io_size: ALIGN,
+            io_executor: IoExecutor::custom(executor.clone()),
+        };
+        let dev = MmapDevice::open(config).await.unwrap();
+
+        // Write one aligned entry to each block of each region.
+        for region in 0..FILES as RegionId {
+            for block in 0..FILE_CAPACITY / ALIGN {
+                let mut wbuffer = dev.io_buffer(ALIGN, ALIGN);
+                (&mut wbuffer[..]).put_slice(&[(region as usize * FILES + block) as u8; ALIGN]);
+                let (res, _) = dev.write(wbuffer, .., region, block * ALIGN).await;
+                assert_eq!(res.unwrap(), ALIGN);
+            }
+        }
+        dev.flush().await.unwrap();
+
+        let jobs = executor.jobs.load(Ordering::SeqCst);
+
+        for region in 0..FILES as RegionId {
+            for block in 0..FILE_CAPACITY / ALIGN {
+                let expected = [(region as usize * FILES + block) as u8; ALIGN];
+
+                let rbuffer = dev.io_buffer(ALIGN, ALIGN);
+                let (res, rbuffer) = dev.read(rbuffer, .., region, block * ALIGN).await;
+                assert_eq!(res.unwrap(), ALIGN);
+                assert_eq!(&rbuffer[..], &expected);
+
+                let slice = dev.slice(region, block * ALIGN..(block + 1) * ALIGN);
+                assert_eq!(&slice[..], &expected);
+            }
+        }
+
+        // Reads are served by the mapping, without dispatching any `pread(2)` to the io executor.
+        assert_eq!(executor.jobs.load(Ordering::SeqCst), jobs);
+
+        // Data is persisted to the region files.
+        drop(dev);
+        let data = std::fs::read(dir.path().join(MmapDevice::filename(1))).unwrap();
+        assert_eq!(data.len(), FILE_CAPACITY);
+        assert_eq!(&data[ALIGN..2 * ALIGN], &[(FILES + 1) as u8; ALIGN]);
+    }
+
+    #[tokio::test]
+    #[should_panic]
+    async fn test_mmap_device_unaligned_write() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = MmapDeviceConfig {
+            dir: PathBuf::from(dir.path()),
+            capacity: CAPACITY,
+            file_size: FILE_CAPACITY,
+            align: ALIGN,
+            io_size: ALIGN,
+            io_executor: IoExecutor::default(),
+        };
+        let dev = MmapDevice::open(config).await.unwrap();
+
+        let wbuffer = dev.io_buffer(ALIGN, ALIGN);
+        let _ = dev.write(wbuffer, ..ALIGN / 2, 0, 0).await;
+    }
+}
@@ -13,7 +13,17 @@
 //  limitations under the License.
 
 pub mod allocator;
+pub mod executor;
 pub mod fs;
+pub mod mem;
+#[cfg(all(feature = "mmap", unix))]
+pub mod mmap;
+#[cfg(feature = "object-store")]
+pub mod object;
+#[cfg(all(feature = "raw", target_os = "linux"))]
+pub mod raw;
+#[cfg(all(feature = "io_uring", target_os = "linux"))]
+pub mod uring;
 
 use std::{fmt::Debug, ops::Range};
 
@@ -47,6 +57,12 @@ pub enum DeviceError {
     Nix(#[from] nix::errno::Errno),
     #[error("other error: {0}")]
     Other(#[from] Box<dyn std::error::Error + Send + Sync + 'static>),
+    #[error("manifest mismatch: {field} is recorded as {recorded}, but given {given}")]
+    ManifestMismatch {
+        field: &'static str,
+        recorded: String,
+        given: String,
+    },
 }
 
 pub type DeviceResult<T> = std::result::Result<T, DeviceError>;
@@ -83,6 +99,28 @@ pub trait Device: Sized + Clone + Send + Sync + 'static {
     #[must_use]
     fn flush(&self) -> impl Future<Output = DeviceResult<()>> + Send;
 
+    /// Discard the content of the region, called when the region is reclaimed and before it is marked clean.
+    ///
+    /// Devices can use it as a hint to release the underlying storage (e.g. TRIM on SSDs). The content of the region
+    /// is undefined after discarded. The default implementation is a no-op.
+    #[must_use]
+    fn discard(&self, region: RegionId) -> impl Future<Output = DeviceResult<()>> + Send {
+        let _ = region;
+        async { Ok(()) }
+    }
+
+    /// Truncate the device to the first `regions` regions, called after the store is shrunk and the trailing regions
+    /// are emptied.
+    ///
+    /// The trailing regions are never read or written afterwards. Devices can release their storage, and record the
+    /// truncated capacity for the following opens. The capacity and the regions of the opened device are unchanged. The
+    /// default implementation is a no-op.
+    #[must_use]
+    fn truncate(&self, regions: usize) -> impl Future<Output = DeviceResult<()>> + Send {
+        let _ = regions;
+        async { Ok(()) }
+    }
+
     fn capacity(&self) -> usize;
 
     fn regions(&self) -> usize;
@@ -93,6 +131,24 @@ pub trait Device: Sized + Clone + Send + Sync + 'static {
     /// optimized io size
     fn io_size(&self) -> usize;
 
+    /// Optimized io size of writes, which is the flush threshold of the flush buffer.
+    fn write_io_size(&self) -> usize {
+        self.io_size()
+    }
+
+    /// Optimized io size of reads, which is the read-ahead window of the region scans.
+    fn read_io_size(&self) -> usize {
+        self.io_size()
+    }
+
+    /// Whether writes to the device must be aligned to [`Device::align`].
+    ///
+    /// Devices backed by buffered I/O (e.g. memory or page cache) can return `false`, entries are then packed
+    /// back-to-back in a region without alignment padding.
+    fn requires_alignment(&self) -> bool {
+        true
+    }
+
     fn io_buffer_allocator(&self) -> &Self::IoBufferAllocator;
 
     fn io_buffer(&self, len: usize, capacity: usize) -> VecA<u8, Self::IoBufferAllocator>;
@@ -127,29 +183,26 @@ pub trait DeviceExt: Device {
 
 impl<D: Device> DeviceExt for D {}
 
-#[cfg(not(madsim))]
-#[tracing::instrument(level = "trace", skip(f))]
-async fn asyncify<F, T>(f: F) -> T
-where
-    F: FnOnce() -> T + Send + 'static,
-    T: Send + 'static,
-{
-    tokio::task::spawn_blocking(f).await.unwrap()
-}
-
-#[cfg(madsim)]
-#[tracing::instrument(level = "trace", skip(f))]
-async fn asyncify<F, T>(f: F) -> T
-where
-    F: FnOnce() -> T + Send + 'static,
-    T: Send + 'static,
-{
-    f()
-}
-
 #[cfg(test)]
 pub mod tests {
-    use super::{allocator::AlignedAllocator, *};
+    use std::{
+        ptr::NonNull,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+
+    use allocator_api2::alloc::AllocError;
+
+    use parking_lot::Mutex;
+
+    use super::{
+        allocator::AlignedAllocator,
+        mem::{MemDevice, MemDeviceConfig},
+        *,
+    };
 
     #[derive(Debug, Clone)]
     pub struct NullDevice(AlignedAllocator);
@@ -199,7 +252,7 @@ pub mod tests {
         }
 
         fn capacity(&self) -> usize {
-            usize::MAX
+            usize::MAX / self.regions() * self.regions()
         }
 
         fn regions(&self) -> usize {
@@ -224,4 +277,344 @@ pub mod tests {
             buf
         }
     }
+
+    /// An in-memory device that doesn't require aligned writes.
+    #[derive(Debug, Clone)]
+    pub struct UnalignedMemDevice {
+        regions: Arc<Vec<Mutex<Vec<u8>>>>,
+        region_size: usize,
+        allocator: AlignedAllocator,
+    }
+
+    impl UnalignedMemDevice {
+        pub fn new(regions: usize, region_size: usize) -> Self {
+            Self {
+                regions: Arc::new((0..regions).map(|_| Mutex::new(vec![0; region_size])).collect()),
+                region_size,
+                allocator: AlignedAllocator::new(4096),
+            }
+        }
+    }
+
+    impl Device for UnalignedMemDevice {
+        type Config = (usize, usize);
+        type IoBufferAllocator = AlignedAllocator;
+
+        async fn open((regions, region_size): (usize, usize)) -> DeviceResult<Self> {
+            Ok(Self::new(regions, region_size))
+        }
+
+        async fn write<B>(
+            &self,
+            buf: B,
+            range: impl IoRange,
+            region: RegionId,
+            offset: usize,
+        ) -> (DeviceResult<usize>, B)
+        where
+            B: IoBuf,
+        {
+            let range = range.bounds(0..buf.as_ref().len());
+            let len = RangeBoundsExt::size(&range).unwrap();
+            assert!(offset + len <= self.region_size);
+            self.regions[region as usize].lock()[offset..offset + len].copy_from_slice(&buf.as_ref()[range]);
+            (Ok(len), buf)
+        }
+
+        async fn read<B>(
+            &self,
+            mut buf: B,
+            range: impl IoRange,
+            region: RegionId,
+            offset: usize,
+        ) -> (DeviceResult<usize>, B)
+        where
+            B: IoBufMut,
+        {
+            let range = range.bounds(0..buf.as_ref().len());
+            let len = RangeBoundsExt::size(&range).unwrap();
+            assert!(offset + len <= self.region_size);
+            buf.as_mut()[range].copy_from_slice(&self.regions[region as usize].lock()[offset..offset + len]);
+            (Ok(len), buf)
+        }
+
+        async fn flush(&self) -> DeviceResult<()> {
+            Ok(())
+        }
+
+        fn capacity(&self) -> usize {
+            self.regions.len() * self.region_size
+        }
+
+        fn regions(&self) -> usize {
+            self.regions.len()
+        }
+
+        fn align(&self) -> usize {
+            4096
+        }
+
+        fn io_size(&self) -> usize {
+            4096
+        }
+
+        fn requires_alignment(&self) -> bool {
+            false
+        }
+
+        fn io_buffer_allocator(&self) -> &Self::IoBufferAllocator {
+            &self.allocator
+        }
+
+        fn io_buffer(&self, len: usize, capacity: usize) -> VecA<u8, Self::IoBufferAllocator> {
+            let mut buf = VecA::with_capacity_in(capacity, self.allocator);
+            unsafe { buf.set_len(len) };
+            buf
+        }
+    }
+
+    /// An in-memory device that fails the given count of writes, then recovers.
+    ///
+    /// The reads of a region can be failed as well, see [`FlakyDevice::fail_reads`].
+    #[derive(Debug, Clone)]
+    pub struct FlakyDevice {
+        device: MemDevice,
+        failures: Arc<AtomicUsize>,
+        failing_reads: Arc<Mutex<Option<RegionId>>>,
+        max_read: Arc<AtomicUsize>,
+        read_bytes: Arc<AtomicUsize>,
+    }
+
+    impl FlakyDevice {
+        /// Count of the writes left to fail.
+        pub fn failures(&self) -> usize {
+            self.failures.load(Ordering::Relaxed)
+        }
+
+        /// Fail all reads of the region until reset with `None`.
+        pub fn fail_reads(&self, region: Option<RegionId>) {
+            *self.failing_reads.lock() = region;
+        }
+
+        /// Take the max len of a single read since the last take.
+        pub fn take_max_read(&self) -> usize {
+            self.max_read.swap(0, Ordering::Relaxed)
+        }
+
+        /// Total bytes read from the device.
+        pub fn read_bytes(&self) -> &Arc<AtomicUsize> {
+            &self.read_bytes
+        }
+    }
+
+    impl Device for FlakyDevice {
+        type Config = (MemDeviceConfig, Arc<AtomicUsize>);
+        type IoBufferAllocator = AlignedAllocator;
+
+        async fn open((config, failures): (MemDeviceConfig, Arc<AtomicUsize>)) -> DeviceResult<Self> {
+            Ok(Self {
+                device: MemDevice::new(config),
+                failures,
+                failing_reads: Arc::new(Mutex::new(None)),
+                max_read: Arc::new(AtomicUsize::new(0)),
+                read_bytes: Arc::new(AtomicUsize::new(0)),
+            })
+        }
+
+        async fn write<B>(
+            &self,
+            buf: B,
+            range: impl IoRange,
+            region: RegionId,
+            offset: usize,
+        ) -> (DeviceResult<usize>, B)
+        where
+            B: IoBuf,
+        {
+            if self
+                .failures
+                .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |failures| failures.checked_sub(1))
+                .is_ok()
+            {
+                return (Err(std::io::Error::other("injected write failure").into()), buf);
+            }
+            self.device.write(buf, range, region, offset).await
+        }
+
+        async fn read<B>(
+            &self,
+            buf: B,
+            range: impl IoRange,
+            region: RegionId,
+            offset: usize,
+        ) -> (DeviceResult<usize>, B)
+        where
+            B: IoBufMut,
+        {
+            if *self.failing_reads.lock() == Some(region) {
+                return (Err(std::io::Error::other("injected read failure").into()), buf);
+            }
+            let len = RangeBoundsExt::size(&range.bounds(0..buf.as_ref().len())).unwrap();
+            self.max_read.fetch_max(len, Ordering::Relaxed);
+            self.read_bytes.fetch_add(len, Ordering::Relaxed);
+            self.device.read(buf, range, region, offset).await
+        }
+
+        async fn flush(&self) -> DeviceResult<()> {
+            self.device.flush().await
+        }
+
+        async fn discard(&self, region: RegionId) -> DeviceResult<()> {
+            self.device.discard(region).await
+        }
+
+        fn capacity(&self) -> usize {
+            self.device.capacity()
+        }
+
+        fn regions(&self) -> usize {
+            self.device.regions()
+        }
+
+        fn align(&self) -> usize {
+            self.device.align()
+        }
+
+        fn io_size(&self) -> usize {
+            self.device.io_size()
+        }
+
+        fn io_buffer_allocator(&self) -> &Self::IoBufferAllocator {
+            self.device.io_buffer_allocator()
+        }
+
+        fn io_buffer(&self, len: usize, capacity: usize) -> VecA<u8, Self::IoBufferAllocator> {
+            self.device.io_buffer(len, capacity)
+        }
+    }
+
+    /// Aligned allocator that counts the live buffers allocated by it.
+    #[derive(Debug, Clone)]
+    pub struct CountingAllocator {
+        allocator: AlignedAllocator,
+        live: Arc<AtomicUsize>,
+    }
+
+    unsafe impl Allocator for CountingAllocator {
+        fn allocate(&self, layout: std::alloc::Layout) -> Result<NonNull<[u8]>, AllocError> {
+            let ptr = self.allocator.allocate(layout)?;
+            self.live.fetch_add(1, Ordering::SeqCst);
+            Ok(ptr)
+        }
+
+        unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: std::alloc::Layout) {
+            self.live.fetch_sub(1, Ordering::SeqCst);
+            self.allocator.deallocate(ptr, layout)
+        }
+    }
+
+    /// An in-memory device whose reads are blocking and slowed down by the given delay, like a slow disk.
+    ///
+    /// The reads are executed on the blocking threads as the fs device does, so a cancelled read keeps running.
+    #[derive(Debug, Clone)]
+    pub struct SlowDevice {
+        device: MemDevice,
+        delay: Arc<Mutex<Duration>>,
+        allocator: CountingAllocator,
+    }
+
+    impl SlowDevice {
+        pub fn set_read_delay(&self, delay: Duration) {
+            *self.delay.lock() = delay;
+        }
+
+        /// Count of the io buffers that are not reclaimed yet.
+        pub fn live_buffers(&self) -> usize {
+            self.allocator.live.load(Ordering::SeqCst)
+        }
+    }
+
+    impl Device for SlowDevice {
+        type Config = MemDeviceConfig;
+        type IoBufferAllocator = CountingAllocator;
+
+        async fn open(config: MemDeviceConfig) -> DeviceResult<Self> {
+            let allocator = CountingAllocator {
+                allocator: AlignedAllocator::new(config.align),
+                live: Arc::new(AtomicUsize::new(0)),
+            };
+            Ok(Self {
+                device: MemDevice::new(config),
+                delay: Arc::new(Mutex::new(Duration::ZERO)),
+                allocator,
+            })
+        }
+
+        async fn write<B>(
+            &self,
+            buf: B,
+            range: impl IoRange,
+            region: RegionId,
+            offset: usize,
+        ) -> (DeviceResult<usize>, B)
+        where
+            B: IoBuf,
+        {
+            self.device.write(buf, range, region, offset).await
+        }
+
+        async fn read<B>(
+            &self,
+            buf: B,
+            range: impl IoRange,
+            region: RegionId,
+            offset: usize,
+        ) -> (DeviceResult<usize>, B)
+        where
+            B: IoBufMut,
+        {
+            let device = self.device.clone();
+            let delay = *self.delay.lock();
+            tokio::task::spawn_blocking(move || {
+                std::thread::sleep(delay);
+                futures::executor::block_on(device.read(buf, range, region, offset))
+            })
+            .await
+            .unwrap()
+        }
+
+        async fn flush(&self) -> DeviceResult<()> {
+            self.device.flush().await
+        }
+
+        async fn discard(&self, region: RegionId) -> DeviceResult<()> {
+            self.device.discard(region).await
+        }
+
+        fn capacity(&self) -> usize {
+            self.device.capacity()
+        }
+
+        fn regions(&self) -> usize {
+            self.device.regions()
+        }
+
+        fn align(&self) -> usize {
+            self.device.align()
+        }
+
+        fn io_size(&self) -> usize {
+            self.device.io_size()
+        }
+
+        fn io_buffer_allocator(&self) -> &Self::IoBufferAllocator {
+            &self.allocator
+        }
+
+        fn io_buffer(&self, len: usize, capacity: usize) -> VecA<u8, Self::IoBufferAllocator> {
+            let mut buf = VecA::with_capacity_in(capacity, self.allocator.clone());
+            unsafe { buf.set_len(len) };
+            buf
+        }
+    }
 }
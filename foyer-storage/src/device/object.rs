@@ -0,0 +1,336 @@
+//  Copyright 2024 Foyer Project Authors
+//
+//  Licensed under the Apache License, Version 2.0 (the "License");
+//  you may not use this file except in compliance with the License.
+//  You may obtain a copy of the License at
+//
+//  http://www.apache.org/licenses/LICENSE-2.0
+//
+//  Unless required by applicable law or agreed to in writing, software
+//  distributed under the License is distributed on an "AS IS" BASIS,
+//  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//  See the License for the specific language governing permissions and
+//  limitations under the License.
+
+//! Device backed by an object store (e.g. S3), which is suitable for a cold tier.
+//!
+//! Each region maps to an object. Objects are immutable, so the writes of a region are staged in memory and the
+//! region is uploaded as a whole object when it is sealed. Reads of a sealed region fetch the byte range of the object.
+
+use std::sync::Arc;
+
+use allocator_api2::vec::Vec as VecA;
+use foyer_common::range::RangeBoundsExt;
+use futures::future::try_join_all;
+use itertools::Itertools;
+use object_store::{path::Path, ObjectStore};
+use parking_lot::Mutex;
+
+use super::{allocator::AlignedAllocator, Device, DeviceError, DeviceResult, IoBuf, IoBufMut, IoRange};
+use crate::region::RegionId;
+
+#[derive(Debug, Clone)]
+pub struct ObjectStoreDeviceConfig {
+    /// object store backend
+    pub store: Arc<dyn ObjectStore>,
+
+    /// prefix of the region objects
+    pub prefix: String,
+
+    /// must be multipliers of `align` and `region_size`
+    pub capacity: usize,
+
+    /// size of each region object, must be multipliers of `align`
+    pub region_size: usize,
+
+    /// io block alignment, must be pow of 2
+    pub align: usize,
+
+    /// recommended optimized io block size
+    pub io_size: usize,
+}
+
+impl ObjectStoreDeviceConfig {
+    pub fn assert(&self) {
+        assert!(self.align.is_power_of_two());
+        assert_eq!(self.region_size % self.align, 0);
+        assert_eq!(self.capacity % self.region_size, 0);
+    }
+}
+
+#[derive(Debug)]
+struct ObjectStoreDeviceInner {
+    config: ObjectStoreDeviceConfig,
+
+    /// Staged data of the regions that are being written, `None` if the region is sealed.
+    staging: Vec<Mutex<Option<Vec<u8>>>>,
+
+    io_buffer_allocator: AlignedAllocator,
+}
+
+/// Device backed by an object store, each region maps to an object.
+///
+/// Writing at offset 0 starts a new version of the region and removes the old object. Following writes must be
+/// appended to the staged region, in-place updates of a sealed region are not supported. The region is sealed and
+/// uploaded when the write reaches the end of the region.
+///
+/// [`Device::flush`] uploads the staged regions without sealing them.
+#[derive(Debug, Clone)]
+pub struct ObjectStoreDevice {
+    inner: Arc<ObjectStoreDeviceInner>,
+}
+
+impl Device for ObjectStoreDevice {
+    type Config = ObjectStoreDeviceConfig;
+    type IoBufferAllocator = AlignedAllocator;
+
+    async fn open(config: ObjectStoreDeviceConfig) -> DeviceResult<Self> {
+        Self::open(config).await
+    }
+
+    async fn write<B>(&self, buf: B, range: impl IoRange, region: RegionId, offset: usize) -> (DeviceResult<usize>, B)
+    where
+        B: IoBuf,
+    {
+        let region_size = self.inner.config.region_size;
+
+        let range = range.bounds(0..buf.as_ref().len());
+        let len = RangeBoundsExt::size(&range).unwrap();
+
+        assert!(
+            offset + len <= region_size,
+            "offset ({offset}) + len ({len}) <= region size ({region_size})"
+        );
+
+        if offset == 0 {
+            // Start a new version of the region, the old object is outdated.
+            *self.inner.staging[region as usize].lock() = Some(vec![0; region_size]);
+            if let Err(e) = self.delete(region).await {
+                return (Err(e), buf);
+            }
+        }
+
+        let sealed = {
+            let mut staging = self.inner.staging[region as usize].lock();
+            let Some(data) = staging.as_mut() else {
+                return (
+                    Err(DeviceError::from(Box::<dyn std::error::Error + Send + Sync>::from(
+                        format!(
+                            "object store device does not support in-place updates, region: {region}, offset: {offset}"
+                        ),
+                    ))),
+                    buf,
+                );
+            };
+            data[offset..offset + len].copy_from_slice(&buf.as_ref()[range]);
+
+            if offset + len == region_size {
+                staging.take()
+            } else {
+                None
+            }
+        };
+
+        if let Some(data) = sealed {
+            if let Err(e) = self.upload(region, data).await {
+                return (Err(e), buf);
+            }
+        }
+
+        (Ok(len), buf)
+    }
+
+    async fn read<B>(
+        &self,
+        mut buf: B,
+        range: impl IoRange,
+        region: RegionId,
+        offset: usize,
+    ) -> (DeviceResult<usize>, B)
+    where
+        B: IoBufMut,
+    {
+        let region_size = self.inner.config.region_size;
+
+        let range = range.bounds(0..buf.as_ref().len());
+        let len = RangeBoundsExt::size(&range).unwrap();
+
+        assert!(
+            offset + len <= region_size,
+            "offset ({offset}) + len ({len}) <= region size ({region_size})"
+        );
+
+        {
+            let staging = self.inner.staging[region as usize].lock();
+            if let Some(data) = staging.as_ref() {
+                buf.as_mut()[range].copy_from_slice(&data[offset..offset + len]);
+                return (Ok(len), buf);
+            }
+        }
+
+        let res = self
+            .inner
+            .config
+            .store
+            .get_range(&self.path(region), offset..offset + len)
+            .await;
+        match res {
+            Ok(bytes) => buf.as_mut()[range].copy_from_slice(&bytes),
+            // The region has never been written, read as zeros like a fresh disk.
+            Err(object_store::Error::NotFound { .. }) => buf.as_mut()[range].fill(0),
+            Err(e) => return (Err(to_device_error(e)), buf),
+        }
+
+        (Ok(len), buf)
+    }
+
+    async fn flush(&self) -> DeviceResult<()> {
+        let staged = (0..self.regions() as RegionId)
+            .filter_map(|region| {
+                self.inner.staging[region as usize]
+                    .lock()
+                    .as_ref()
+                    .map(|data| (region, data.clone()))
+            })
+            .collect_vec();
+        try_join_all(staged.into_iter().map(|(region, data)| self.upload(region, data))).await?;
+        Ok(())
+    }
+
+    fn capacity(&self) -> usize {
+        self.inner.config.capacity
+    }
+
+    fn regions(&self) -> usize {
+        self.inner.staging.len()
+    }
+
+    fn align(&self) -> usize {
+        self.inner.config.align
+    }
+
+    fn io_size(&self) -> usize {
+        self.inner.config.io_size
+    }
+
+    fn io_buffer_allocator(&self) -> &Self::IoBufferAllocator {
+        &self.inner.io_buffer_allocator
+    }
+
+    fn io_buffer(&self, len: usize, capacity: usize) -> VecA<u8, Self::IoBufferAllocator> {
+        assert!(len <= capacity);
+        let mut buf = VecA::with_capacity_in(capacity, self.inner.io_buffer_allocator);
+        unsafe { buf.set_len(len) };
+        buf
+    }
+}
+
+impl ObjectStoreDevice {
+    pub async fn open(config: ObjectStoreDeviceConfig) -> DeviceResult<Self> {
+        config.assert();
+
+        let regions = config.capacity / config.region_size;
+        let staging = (0..regions).map(|_| Mutex::new(None)).collect_vec();
+        let io_buffer_allocator = AlignedAllocator::new(config.align);
+
+        let inner = ObjectStoreDeviceInner {
+            config,
+            staging,
+            io_buffer_allocator,
+        };
+
+        Ok(Self { inner: Arc::new(inner) })
+    }
+
+    async fn upload(&self, region: RegionId, data: Vec<u8>) -> DeviceResult<()> {
+        self.inner
+            .config
+            .store
+            .put(&self.path(region), data.into())
+            .await
+            .map_err(to_device_error)?;
+        Ok(())
+    }
+
+    async fn delete(&self, region: RegionId) -> DeviceResult<()> {
+        match self.inner.config.store.delete(&self.path(region)).await {
+            Ok(()) | Err(object_store::Error::NotFound { .. }) => Ok(()),
+            Err(e) => Err(to_device_error(e)),
+        }
+    }
+
+    fn path(&self, region: RegionId) -> Path {
+        Path::from(format!("{}/foyer-cache-{:08}", self.inner.config.prefix, region))
+    }
+}
+
+fn to_device_error(e: object_store::Error) -> DeviceError {
+    DeviceError::from(Box::<dyn std::error::Error + Send + Sync>::from(e))
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::BufMut;
+    use object_store::memory::InMemory;
+
+    use super::*;
+
+    const REGIONS: usize = 4;
+    const REGION_SIZE: usize = 16 * 1024; // 16 KiB
+    const CAPACITY: usize = REGIONS * REGION_SIZE; // 64 KiB
+    const ALIGN: usize = 4 * 1024;
+
+    #[tokio::test]
+    async fn test_object_store_device() {
+        let store = Arc::new(InMemory::new());
+        let config = ObjectStoreDeviceConfig {
+            store: store.clone(),
+            prefix: "test".to_string(),
+            capacity: CAPACITY,
+            region_size: REGION_SIZE,
+            align: ALIGN,
+            io_size: ALIGN,
+        };
+        let dev = ObjectStoreDevice::open(config.clone()).await.unwrap();
+
+        // A region that has never been written is read as zeros.
+        let rbuffer = dev.io_buffer(ALIGN, ALIGN);
+        let (res, rbuffer) = dev.read(rbuffer, .., 1, 0).await;
+        res.unwrap();
+        assert_eq!(&rbuffer[..], &[0; ALIGN]);
+
+        // Fill the region block by block, it is uploaded when sealed.
+        for i in 0..REGION_SIZE / ALIGN {
+            let mut wbuffer = dev.io_buffer(ALIGN, ALIGN);
+            (&mut wbuffer[..]).put_slice(&[i as u8; ALIGN]);
+            let (res, _) = dev.write(wbuffer, .., 1, i * ALIGN).await;
+            res.unwrap();
+            if i < REGION_SIZE / ALIGN - 1 {
+                assert!(store.head(&dev.path(1)).await.is_err());
+            }
+        }
+        assert_eq!(store.head(&dev.path(1)).await.unwrap().size, REGION_SIZE);
+
+        // Serve range reads from the region object with a newly opened device.
+        let dev = ObjectStoreDevice::open(config).await.unwrap();
+        for i in 0..REGION_SIZE / ALIGN {
+            let rbuffer = dev.io_buffer(ALIGN, ALIGN);
+            let (res, rbuffer) = dev.read(rbuffer, .., 1, i * ALIGN).await;
+            res.unwrap();
+            assert_eq!(&rbuffer[..], &[i as u8; ALIGN]);
+        }
+
+        // In-place updates of a sealed region are not supported.
+        let mut wbuffer = dev.io_buffer(ALIGN, ALIGN);
+        (&mut wbuffer[..]).put_slice(&[b'x'; ALIGN]);
+        let (res, _) = dev.write(wbuffer, .., 1, ALIGN).await;
+        assert!(res.is_err());
+
+        // Rewriting the region removes the old object.
+        let mut wbuffer = dev.io_buffer(ALIGN, ALIGN);
+        (&mut wbuffer[..]).put_slice(&[b'x'; ALIGN]);
+        let (res, _) = dev.write(wbuffer, .., 1, 0).await;
+        res.unwrap();
+        assert!(store.head(&dev.path(1)).await.is_err());
+    }
+}
@@ -0,0 +1,380 @@
+//  Copyright 2024 Foyer Project Authors
+//
+//  Licensed under the Apache License, Version 2.0 (the "License");
+//  you may not use this file except in compliance with the License.
+//  You may obtain a copy of the License at
+//
+//  http://www.apache.org/licenses/LICENSE-2.0
+//
+//  Unless required by applicable law or agreed to in writing, software
+//  distributed under the License is distributed on an "AS IS" BASIS,
+//  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//  See the License for the specific language governing permissions and
+//  limitations under the License.
+
+//! Device backed by a raw block device (e.g. a whole partition `/dev/nvme1n1`), which is only available on Linux.
+//!
+//! There is no filesystem in between, so there is neither the filesystem overhead nor the fragmentation. The block
+//! device is opened once with `O_DIRECT`, and the regions are mapped to fixed offsets of it: region `i` spans
+//! `[i * region_size, (i + 1) * region_size)`.
+//!
+//! The size of the block device is queried by the `BLKGETSIZE64` ioctl, and the io alignment by the `BLKSSZGET` ioctl,
+//! which is the logical block size of the device. A regular file (e.g. a preallocated image) is accepted as well, with
+//! the size of the file and the preferred block size of its filesystem.
+//!
+//! The content of the device is overwritten without any check, make sure the device is dedicated to the cache.
+
+use std::{
+    fs::{File, OpenOptions},
+    os::{
+        fd::{AsRawFd, BorrowedFd, RawFd},
+        unix::{
+            fs::{FileTypeExt, MetadataExt},
+            prelude::OpenOptionsExt,
+        },
+    },
+    path::PathBuf,
+    sync::Arc,
+};
+
+use allocator_api2::vec::Vec as VecA;
+use foyer_common::range::RangeBoundsExt;
+
+use super::{
+    allocator::AlignedAllocator, executor::IoExecutor, Device, DeviceError, DeviceResult, IoBuf, IoBufMut, IoRange,
+};
+use crate::region::RegionId;
+
+nix::ioctl_read!(blkgetsize64, 0x12, 114, u64);
+nix::ioctl_read_bad!(blksszget, nix::request_code_none!(0x12, 104), libc::c_int);
+
+#[derive(Debug, Clone)]
+pub struct RawDeviceConfig {
+    /// path of the block device
+    pub path: PathBuf,
+
+    /// bytes used from the start of the device, `None` means the whole device, rounded down to `region_size`
+    pub capacity: Option<usize>,
+
+    /// must be multipliers of the io alignment
+    pub region_size: usize,
+
+    /// io block alignment, must be pow of 2 and multipliers of the logical block size, `None` means the logical block
+    /// size of the device
+    pub align: Option<usize>,
+
+    /// recommended optimized io block size
+    pub io_size: usize,
+
+    /// executor to offload blocking syscalls
+    pub io_executor: IoExecutor,
+}
+
+impl RawDeviceConfig {
+    pub fn assert(&self) {
+        assert!(self.region_size > 0);
+        if let Some(align) = self.align {
+            assert!(align.is_power_of_two());
+            assert_eq!(self.region_size % align, 0);
+        }
+    }
+}
+
+#[derive(Debug)]
+struct RawDeviceInner {
+    config: RawDeviceConfig,
+
+    file: File,
+
+    capacity: usize,
+
+    align: usize,
+
+    io_buffer_allocator: AlignedAllocator,
+}
+
+#[derive(Debug, Clone)]
+pub struct RawDevice {
+    inner: Arc<RawDeviceInner>,
+}
+
+impl Device for RawDevice {
+    type Config = RawDeviceConfig;
+    type IoBufferAllocator = AlignedAllocator;
+
+    async fn open(config: RawDeviceConfig) -> DeviceResult<Self> {
+        Self::open(config).await
+    }
+
+    async fn write<B>(&self, buf: B, range: impl IoRange, region: RegionId, offset: usize) -> (DeviceResult<usize>, B)
+    where
+        B: IoBuf,
+    {
+        let region_size = self.inner.config.region_size;
+
+        let range = range.bounds(0..buf.as_ref().len());
+        let len = RangeBoundsExt::size(&range).unwrap();
+
+        assert!(
+            offset + len <= region_size,
+            "offset ({offset}) + len ({len}) <= region size ({region_size})"
+        );
+
+        let fd = self.fd(region);
+        let position = self.position(region, offset);
+
+        self.inner
+            .config
+            .io_executor
+            .asyncify_with_buf(buf, move |buf| {
+                let fd = unsafe { BorrowedFd::borrow_raw(fd) };
+                nix::sys::uio::pwrite(fd, &buf.as_ref()[range], position as i64).map_err(DeviceError::from)
+            })
+            .await
+    }
+
+    async fn read<B>(&self, buf: B, range: impl IoRange, region: RegionId, offset: usize) -> (DeviceResult<usize>, B)
+    where
+        B: IoBufMut,
+    {
+        let region_size = self.inner.config.region_size;
+
+        let range = range.bounds(0..buf.as_ref().len());
+        let len = RangeBoundsExt::size(&range).unwrap();
+
+        assert!(
+            offset + len <= region_size,
+            "offset ({offset}) + len ({len}) <= region size ({region_size})"
+        );
+
+        let fd = self.fd(region);
+        let position = self.position(region, offset);
+
+        self.inner
+            .config
+            .io_executor
+            .asyncify_with_buf(buf, move |buf| {
+                let fd = unsafe { BorrowedFd::borrow_raw(fd) };
+                nix::sys::uio::pread(fd, &mut buf.as_mut()[range], position as i64).map_err(DeviceError::from)
+            })
+            .await
+    }
+
+    async fn flush(&self) -> DeviceResult<()> {
+        // The writes bypass the page cache with `O_DIRECT`, `fdatasync(2)` flushes the volatile cache of the device.
+        let fd = self.inner.file.as_raw_fd();
+        self.inner
+            .config
+            .io_executor
+            .asyncify(move || nix::unistd::fdatasync(fd).map_err(DeviceError::from))
+            .await
+    }
+
+    fn capacity(&self) -> usize {
+        self.inner.capacity
+    }
+
+    fn regions(&self) -> usize {
+        self.inner.capacity / self.inner.config.region_size
+    }
+
+    fn align(&self) -> usize {
+        self.inner.align
+    }
+
+    fn io_size(&self) -> usize {
+        self.inner.config.io_size
+    }
+
+    fn io_buffer_allocator(&self) -> &Self::IoBufferAllocator {
+        &self.inner.io_buffer_allocator
+    }
+
+    fn io_buffer(&self, len: usize, capacity: usize) -> VecA<u8, Self::IoBufferAllocator> {
+        assert!(len <= capacity);
+        let mut buf = VecA::with_capacity_in(capacity, self.inner.io_buffer_allocator);
+        unsafe { buf.set_len(len) };
+        buf
+    }
+}
+
+impl RawDevice {
+    #[allow(clippy::manual_is_multiple_of)]
+    pub async fn open(config: RawDeviceConfig) -> DeviceResult<Self> {
+        config.assert();
+
+        let path = config.path.clone();
+        let (file, size, block_size) = config
+            .io_executor
+            .asyncify(move || {
+                let file = OpenOptions::new()
+                    .read(true)
+                    .write(true)
+                    .custom_flags(libc::O_DIRECT)
+                    .open(path)?;
+                let (size, block_size) = Self::probe(&file)?;
+                Ok::<_, DeviceError>((file, size, block_size))
+            })
+            .await?;
+
+        let align = config.align.unwrap_or(block_size);
+        if align % block_size != 0 || config.region_size % align != 0 {
+            return Err(DeviceError::Other(
+                format!(
+                    "align {align} must be a multiplier of the logical block size {block_size} and a divisor of the \
+                     region size {}",
+                    config.region_size
+                )
+                .into(),
+            ));
+        }
+
+        let capacity = config.capacity.map_or(size, |capacity| capacity.min(size));
+        let capacity = capacity - capacity % config.region_size;
+        if capacity == 0 {
+            return Err(DeviceError::Other(
+                format!(
+                    "{:?} of {size} bytes cannot hold a region of {} bytes",
+                    config.path, config.region_size
+                )
+                .into(),
+            ));
+        }
+
+        tracing::info!(
+            "[raw device]: open {:?} with capacity: {capacity}, logical block size: {block_size}, align: {align}",
+            config.path
+        );
+
+        let io_buffer_allocator = AlignedAllocator::new(align);
+
+        let inner = RawDeviceInner {
+            config,
+            file,
+            capacity,
+            align,
+            io_buffer_allocator,
+        };
+
+        Ok(Self { inner: Arc::new(inner) })
+    }
+
+    /// Get the size and the logical block size of the block device, or of the regular file.
+    fn probe(file: &File) -> DeviceResult<(usize, usize)> {
+        let metadata = file.metadata()?;
+        if !metadata.file_type().is_block_device() {
+            return Ok((metadata.len() as usize, metadata.blksize() as usize));
+        }
+
+        let fd = file.as_raw_fd();
+        let mut size: u64 = 0;
+        let mut block_size: libc::c_int = 0;
+        unsafe {
+            blkgetsize64(fd, &mut size)?;
+            blksszget(fd, &mut block_size)?;
+        }
+        Ok((size as usize, block_size as usize))
+    }
+
+    /// All regions share the fd of the block device.
+    fn fd(&self, region: RegionId) -> RawFd {
+        debug_assert!((region as usize) < self.regions());
+        self.inner.file.as_raw_fd()
+    }
+
+    /// Position on the block device of the offset of the region.
+    fn position(&self, region: RegionId, offset: usize) -> usize {
+        region as usize * self.inner.config.region_size + offset
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::BufMut;
+
+    use super::*;
+
+    const REGIONS: usize = 4;
+    const REGION_SIZE: usize = 16 * 1024; // 16 KiB
+    const ALIGN: usize = 4 * 1024;
+
+    fn config(path: PathBuf, capacity: Option<usize>) -> RawDeviceConfig {
+        RawDeviceConfig {
+            path,
+            capacity,
+            region_size: REGION_SIZE,
+            align: Some(ALIGN),
+            io_size: ALIGN,
+            io_executor: IoExecutor::default(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_raw_device_region_offsets() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("raw");
+        // A trailing partial region is left unused.
+        File::create(&path)
+            .unwrap()
+            .set_len((REGIONS * REGION_SIZE + ALIGN) as u64)
+            .unwrap();
+
+        let dev = RawDevice::open(config(path.clone(), None)).await.unwrap();
+        assert_eq!(dev.capacity(), REGIONS * REGION_SIZE);
+        assert_eq!(dev.regions(), REGIONS);
+        assert_eq!(dev.align(), ALIGN);
+
+        for region in 0..REGIONS as RegionId {
+            for block in 0..REGION_SIZE / ALIGN {
+                let mut wbuffer = dev.io_buffer(ALIGN, ALIGN);
+                (&mut wbuffer[..]).put_slice(&[(region as usize * REGIONS + block) as u8; ALIGN]);
+                let (res, _) = dev.write(wbuffer, .., region, block * ALIGN).await;
+                assert_eq!(res.unwrap(), ALIGN);
+            }
+        }
+        dev.flush().await.unwrap();
+
+        for region in 0..REGIONS as RegionId {
+            for block in 0..REGION_SIZE / ALIGN {
+                let rbuffer = dev.io_buffer(ALIGN, ALIGN);
+                let (res, rbuffer) = dev.read(rbuffer, .., region, block * ALIGN).await;
+                assert_eq!(res.unwrap(), ALIGN);
+                assert_eq!(&rbuffer[..], &[(region as usize * REGIONS + block) as u8; ALIGN]);
+            }
+        }
+
+        // The regions are laid out back-to-back from the start of the device.
+        drop(dev);
+        let data = std::fs::read(&path).unwrap();
+        let position = 2 * REGION_SIZE + ALIGN;
+        assert_eq!(&data[position..position + ALIGN], &[(2 * REGIONS + 1) as u8; ALIGN]);
+        assert_eq!(&data[REGIONS * REGION_SIZE..], &[0; ALIGN]);
+
+        // Only the given capacity is used.
+        let dev = RawDevice::open(config(path.clone(), Some(2 * REGION_SIZE + ALIGN)))
+            .await
+            .unwrap();
+        assert_eq!(dev.capacity(), 2 * REGION_SIZE);
+        assert_eq!(dev.regions(), 2);
+        drop(dev);
+
+        // The device must hold at least one region.
+        assert!(RawDevice::open(config(path, Some(ALIGN))).await.is_err());
+    }
+
+    #[tokio::test]
+    #[should_panic]
+    async fn test_raw_device_write_out_of_region() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("raw");
+        File::create(&path)
+            .unwrap()
+            .set_len((REGIONS * REGION_SIZE) as u64)
+            .unwrap();
+
+        let dev = RawDevice::open(config(path, None)).await.unwrap();
+
+        let wbuffer = dev.io_buffer(2 * ALIGN, 2 * ALIGN);
+        let _ = dev.write(wbuffer, .., 0, REGION_SIZE - ALIGN).await;
+    }
+}
@@ -0,0 +1,566 @@
+//  Copyright 2024 Foyer Project Authors
+//
+//  Licensed under the Apache License, Version 2.0 (the "License");
+//  you may not use this file except in compliance with the License.
+//  You may obtain a copy of the License at
+//
+//  http://www.apache.org/licenses/LICENSE-2.0
+//
+//  Unless required by applicable law or agreed to in writing, software
+//  distributed under the License is distributed on an "AS IS" BASIS,
+//  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//  See the License for the specific language governing permissions and
+//  limitations under the License.
+
+//! Device backed by region files accessed with `io_uring(7)`, which is only available on Linux.
+//!
+//! The layout is the same as [`super::fs::FsDevice`] with direct I/O: each region maps to a fixed-size file opened with
+//! `O_DIRECT`. Instead of offloading the blocking `pread(2)` and `pwrite(2)` to a thread pool, the reads and writes are
+//! submitted to a ring as SQEs and completed by a dedicated ring thread, so no worker thread is blocked by the I/O and
+//! there is no context switch per I/O under high concurrency.
+//!
+//! The io buffers are owned by the ring thread until the I/O completes, so a cancelled read or write never leaves the
+//! kernel writing to or reading from a freed buffer.
+
+use std::{
+    any::Any,
+    collections::HashMap,
+    fs::{create_dir_all, File, OpenOptions},
+    os::{
+        fd::{AsRawFd, FromRawFd, OwnedFd, RawFd},
+        unix::prelude::OpenOptionsExt,
+    },
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{channel, Receiver, Sender},
+        Arc,
+    },
+};
+
+use allocator_api2::vec::Vec as VecA;
+use foyer_common::range::RangeBoundsExt;
+use futures::future::try_join_all;
+use io_uring::{cqueue, opcode, squeue, types, IoUring};
+use itertools::Itertools;
+use tokio::sync::oneshot;
+
+use super::{allocator::AlignedAllocator, Device, DeviceError, DeviceResult, IoBuf, IoBufMut, IoRange};
+use crate::region::RegionId;
+
+/// User data of the read on the eventfd, which wakes up the ring thread on new requests.
+const WAKE: u64 = u64::MAX;
+
+#[derive(Debug, Clone)]
+pub struct IoUringDeviceConfig {
+    /// base dir path
+    pub dir: PathBuf,
+
+    /// must be multipliers of `align` and `file_capacity`
+    pub capacity: usize,
+
+    /// must be multipliers of `align`
+    pub file_size: usize,
+
+    /// io block alignment, must be pow of 2
+    pub align: usize,
+
+    /// recommended optimized io block size
+    pub io_size: usize,
+
+    /// count of the submission queue entries of the ring, which also bounds the in-flight I/Os, must be pow of 2
+    pub ring_entries: u32,
+}
+
+impl IoUringDeviceConfig {
+    pub fn assert(&self) {
+        assert!(self.align.is_power_of_two());
+        assert_eq!(self.file_size % self.align, 0);
+        assert_eq!(self.capacity % self.file_size, 0);
+        assert!(self.ring_entries.is_power_of_two());
+    }
+}
+
+type Buffer = Box<dyn Any + Send>;
+
+/// I/O request sent to the ring thread.
+struct Request {
+    sqe: squeue::Entry,
+    /// The buffer referenced by the SQE, held by the ring thread until the I/O completes.
+    buf: Option<Buffer>,
+    tx: oneshot::Sender<(i32, Option<Buffer>)>,
+}
+
+/// I/O submitted to the ring and not completed yet.
+struct Inflight {
+    buf: Option<Buffer>,
+    tx: oneshot::Sender<(i32, Option<Buffer>)>,
+}
+
+#[derive(Debug)]
+struct IoUringDeviceInner {
+    config: IoUringDeviceConfig,
+
+    files: Vec<File>,
+
+    tx: Sender<Request>,
+
+    /// eventfd to wake up the ring thread
+    eventfd: Arc<OwnedFd>,
+
+    closed: Arc<AtomicBool>,
+
+    io_buffer_allocator: AlignedAllocator,
+}
+
+impl Drop for IoUringDeviceInner {
+    fn drop(&mut self) {
+        // The ring thread exits after the in-flight I/Os complete.
+        self.closed.store(true, Ordering::Release);
+        wake(&self.eventfd);
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct IoUringDevice {
+    inner: Arc<IoUringDeviceInner>,
+}
+
+impl Device for IoUringDevice {
+    type Config = IoUringDeviceConfig;
+    type IoBufferAllocator = AlignedAllocator;
+
+    async fn open(config: IoUringDeviceConfig) -> DeviceResult<Self> {
+        Self::open(config).await
+    }
+
+    async fn write<B>(&self, buf: B, range: impl IoRange, region: RegionId, offset: usize) -> (DeviceResult<usize>, B)
+    where
+        B: IoBuf,
+    {
+        let file_capacity = self.inner.config.file_size;
+
+        let range = range.bounds(0..buf.as_ref().len());
+        let len = RangeBoundsExt::size(&range).unwrap();
+
+        assert!(
+            offset + len <= file_capacity,
+            "offset ({offset}) + len ({len}) <= file capacity ({file_capacity})"
+        );
+
+        // Box the buffer before taking the pointer, so the pointer stays valid after the buffer is moved around.
+        let buf = Box::new(buf);
+        let ptr = (*buf).as_ref()[range].as_ptr();
+        let sqe = opcode::Write::new(types::Fd(self.fd(region)), ptr, len as u32)
+            .offset(offset as u64)
+            .build();
+
+        let (res, buf) = self.submit(sqe, Some(buf as Buffer)).await;
+        (res, *buf.unwrap().downcast::<B>().unwrap())
+    }
+
+    async fn read<B>(&self, buf: B, range: impl IoRange, region: RegionId, offset: usize) -> (DeviceResult<usize>, B)
+    where
+        B: IoBufMut,
+    {
+        let file_capacity = self.inner.config.file_size;
+
+        let range = range.bounds(0..buf.as_ref().len());
+        let len = RangeBoundsExt::size(&range).unwrap();
+
+        assert!(
+            offset + len <= file_capacity,
+            "offset ({offset}) + len ({len}) <= file capacity ({file_capacity})"
+        );
+
+        // Box the buffer before taking the pointer, so the pointer stays valid after the buffer is moved around.
+        let mut buf = Box::new(buf);
+        let ptr = (*buf).as_mut()[range].as_mut_ptr();
+        let sqe = opcode::Read::new(types::Fd(self.fd(region)), ptr, len as u32)
+            .offset(offset as u64)
+            .build();
+
+        let (res, buf) = self.submit(sqe, Some(buf as Buffer)).await;
+        (res, *buf.unwrap().downcast::<B>().unwrap())
+    }
+
+    async fn flush(&self) -> DeviceResult<()> {
+        let futures = (0..self.inner.files.len() as RegionId)
+            .map(|region| {
+                let sqe = opcode::Fsync::new(types::Fd(self.fd(region)))
+                    .flags(types::FsyncFlags::DATASYNC)
+                    .build();
+                async move { self.submit(sqe, None).await.0 }
+            })
+            .collect_vec();
+        try_join_all(futures).await?;
+        Ok(())
+    }
+
+    async fn discard(&self, region: RegionId) -> DeviceResult<()> {
+        // Punch a hole over the whole region file to release its blocks, keep the file size unchanged.
+        //
+        // See also [fallocate(2)](https://man7.org/linux/man-pages/man2/fallocate.2.html)
+        let sqe = opcode::Fallocate::new(types::Fd(self.fd(region)), self.inner.config.file_size as u64)
+            .offset(0)
+            .mode(libc::FALLOC_FL_PUNCH_HOLE | libc::FALLOC_FL_KEEP_SIZE)
+            .build();
+        self.submit(sqe, None).await.0?;
+        Ok(())
+    }
+
+    fn capacity(&self) -> usize {
+        self.inner.config.capacity
+    }
+
+    fn regions(&self) -> usize {
+        self.inner.files.len()
+    }
+
+    fn align(&self) -> usize {
+        self.inner.config.align
+    }
+
+    fn io_size(&self) -> usize {
+        self.inner.config.io_size
+    }
+
+    fn io_buffer_allocator(&self) -> &Self::IoBufferAllocator {
+        &self.inner.io_buffer_allocator
+    }
+
+    fn io_buffer(&self, len: usize, capacity: usize) -> VecA<u8, Self::IoBufferAllocator> {
+        assert!(len <= capacity);
+        let mut buf = VecA::with_capacity_in(capacity, self.inner.io_buffer_allocator);
+        unsafe { buf.set_len(len) };
+        buf
+    }
+}
+
+impl IoUringDevice {
+    pub async fn open(config: IoUringDeviceConfig) -> DeviceResult<Self> {
+        config.assert();
+
+        create_dir_all(&config.dir)?;
+
+        let files: Vec<File> = (0..config.capacity / config.file_size)
+            .map(|i| {
+                let path = config.dir.join(Self::filename(i as RegionId));
+                OpenOptions::new()
+                    .create(true)
+                    .write(true)
+                    .read(true)
+                    .custom_flags(libc::O_DIRECT)
+                    .open(path)
+            })
+            .try_collect()?;
+
+        let ring = IoUring::new(config.ring_entries)?;
+
+        let fd = unsafe { libc::eventfd(0, libc::EFD_CLOEXEC) };
+        if fd < 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        let eventfd = Arc::new(unsafe { OwnedFd::from_raw_fd(fd) });
+
+        let (tx, rx) = channel();
+        let closed = Arc::new(AtomicBool::new(false));
+
+        let runner = Runner {
+            ring,
+            rx,
+            eventfd: eventfd.clone(),
+            closed: closed.clone(),
+            inflight: HashMap::new(),
+            next: 0,
+            armed: false,
+            wake: Box::new([0; 8]),
+        };
+        std::thread::Builder::new()
+            .name("foyer-io-uring".to_string())
+            .spawn(move || runner.run())?;
+
+        let io_buffer_allocator = AlignedAllocator::new(config.align);
+
+        let inner = IoUringDeviceInner {
+            config,
+            files,
+            tx,
+            eventfd,
+            closed,
+            io_buffer_allocator,
+        };
+
+        Ok(Self { inner: Arc::new(inner) })
+    }
+
+    /// Send the request to the ring thread and wait for its completion.
+    ///
+    /// Returns the buffer of the request after the I/O completes, even if the I/O fails.
+    async fn submit(&self, sqe: squeue::Entry, buf: Option<Buffer>) -> (DeviceResult<usize>, Option<Buffer>) {
+        let (tx, rx) = oneshot::channel();
+        if let Err(e) = self.inner.tx.send(Request { sqe, buf, tx }) {
+            return (
+                Err(std::io::Error::other("io_uring ring thread exited").into()),
+                e.0.buf,
+            );
+        }
+        wake(&self.inner.eventfd);
+
+        let (res, buf) = rx.await.unwrap();
+        let res = if res < 0 {
+            Err(DeviceError::from(std::io::Error::from_raw_os_error(-res)))
+        } else {
+            Ok(res as usize)
+        };
+        (res, buf)
+    }
+
+    fn fd(&self, region: RegionId) -> RawFd {
+        self.inner.files[region as usize].as_raw_fd()
+    }
+
+    fn filename(region: RegionId) -> String {
+        format!("foyer-cache-{:08}", region)
+    }
+}
+
+fn wake(eventfd: &OwnedFd) {
+    let v = 1u64;
+    let res = unsafe { libc::write(eventfd.as_raw_fd(), &v as *const u64 as *const libc::c_void, 8) };
+    if res < 0 {
+        tracing::warn!(
+            "[io_uring device]: wake ring thread error: {}",
+            std::io::Error::last_os_error()
+        );
+    }
+}
+
+/// Ring thread that submits the requests as SQEs and completes the requests with the CQEs.
+struct Runner {
+    ring: IoUring,
+    rx: Receiver<Request>,
+    eventfd: Arc<OwnedFd>,
+    closed: Arc<AtomicBool>,
+
+    inflight: HashMap<u64, Inflight>,
+    next: u64,
+
+    /// `true` if the read on the eventfd is submitted and not completed yet.
+    armed: bool,
+    /// Buffer of the read on the eventfd.
+    wake: Box<[u8; 8]>,
+}
+
+impl Runner {
+    fn run(mut self) {
+        let entries = self.ring.params().sq_entries() as usize;
+
+        loop {
+            if !self.armed {
+                let sqe = opcode::Read::new(types::Fd(self.eventfd.as_raw_fd()), self.wake.as_mut_ptr(), 8)
+                    .build()
+                    .user_data(WAKE);
+                self.push(sqe);
+                self.armed = true;
+            }
+
+            // Keep one entry for the read on the eventfd.
+            while self.inflight.len() + 1 < entries {
+                match self.rx.try_recv() {
+                    Ok(Request { sqe, buf, tx }) => {
+                        let id = self.next;
+                        self.next = self.next.wrapping_add(1) % WAKE;
+                        self.push(sqe.user_data(id));
+                        self.inflight.insert(id, Inflight { buf, tx });
+                    }
+                    Err(_) => break,
+                }
+            }
+
+            if self.closed.load(Ordering::Acquire) && self.inflight.is_empty() {
+                break;
+            }
+
+            match self.ring.submit_and_wait(1) {
+                Ok(_) => {}
+                Err(e) if e.raw_os_error() == Some(libc::EINTR) => continue,
+                Err(e) if e.raw_os_error() == Some(libc::EBUSY) => {}
+                Err(e) => {
+                    tracing::error!("[io_uring device]: submit error: {e}, ring thread exits");
+                    break;
+                }
+            }
+
+            let cqes: Vec<cqueue::Entry> = self.ring.completion().collect();
+            for cqe in cqes {
+                if cqe.user_data() == WAKE {
+                    self.armed = false;
+                    continue;
+                }
+                if let Some(Inflight { buf, tx }) = self.inflight.remove(&cqe.user_data()) {
+                    // The receiver is dropped if the I/O is cancelled, then the buffer is released here.
+                    let _ = tx.send((cqe.result(), buf));
+                }
+            }
+        }
+
+        // The in-flight I/Os may still access their buffers if the thread exits on error, leak the buffers.
+        if !self.inflight.is_empty() {
+            tracing::warn!(
+                "[io_uring device]: {} in-flight I/Os are leaked as the ring thread exits",
+                self.inflight.len()
+            );
+            std::mem::forget(std::mem::take(&mut self.inflight));
+        }
+        // The read on the eventfd may still be pending, keep its buffer alive until the ring is dropped.
+        let Self { ring, wake, .. } = self;
+        drop(ring);
+        drop(wake);
+    }
+
+    fn push(&mut self, sqe: squeue::Entry) {
+        loop {
+            let pushed = unsafe { self.ring.submission().push(&sqe) };
+            match pushed {
+                Ok(()) => return,
+                // The submission queue is full, submit the entries to make room.
+                Err(_) => {
+                    if let Err(e) = self.ring.submit() {
+                        tracing::warn!("[io_uring device]: submit error: {e}");
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::BufMut;
+
+    use super::*;
+
+    const FILES: usize = 8;
+    const FILE_CAPACITY: usize = 8 * 1024; // 8 KiB
+    const CAPACITY: usize = FILES * FILE_CAPACITY; // 64 KiB
+    const ALIGN: usize = 4 * 1024;
+
+    fn config(dir: &tempfile::TempDir) -> IoUringDeviceConfig {
+        IoUringDeviceConfig {
+            dir: PathBuf::from(dir.path()),
+            capacity: CAPACITY,
+            file_size: FILE_CAPACITY,
+            align: ALIGN,
+            io_size: ALIGN,
+            ring_entries: 32,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_io_uring_device_simple() {
+        let dir = tempfile::tempdir().unwrap();
+        let dev = IoUringDevice::open(config(&dir)).await.unwrap();
+
+        let mut wbuffer = dev.io_buffer(ALIGN, ALIGN);
+        (&mut wbuffer[..]).put_slice(&[b'x'; ALIGN]);
+        let mut rbuffer = dev.io_buffer(ALIGN, ALIGN);
+        (&mut rbuffer[..]).put_slice(&[0; ALIGN]);
+
+        let (res, wbuffer) = dev.write(wbuffer, .., 0, 0).await;
+        assert_eq!(res.unwrap(), ALIGN);
+        dev.flush().await.unwrap();
+        let (res, rbuffer) = dev.read(rbuffer, .., 0, 0).await;
+        assert_eq!(res.unwrap(), ALIGN);
+
+        assert_eq!(&wbuffer, &rbuffer);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_io_uring_device_concurrent() {
+        const SLOTS: usize = FILE_CAPACITY / ALIGN;
+
+        let dir = tempfile::tempdir().unwrap();
+        let dev = IoUringDevice::open(config(&dir)).await.unwrap();
+
+        let location = |i: usize| ((i % FILES) as RegionId, i / FILES % SLOTS * ALIGN);
+
+        let futures = (0..FILES * SLOTS)
+            .map(|i| {
+                let dev = dev.clone();
+                let (region, offset) = location(i);
+                async move {
+                    let mut wbuffer = dev.io_buffer(ALIGN, ALIGN);
+                    (&mut wbuffer[..]).put_slice(&[i as u8; ALIGN]);
+                    let (res, _) = dev.write(wbuffer, .., region, offset).await;
+                    assert_eq!(res.unwrap(), ALIGN);
+                }
+            })
+            .collect_vec();
+        futures::future::join_all(futures).await;
+
+        // More concurrent I/Os than the ring entries.
+        let futures = (0..64usize)
+            .map(|i| {
+                let dev = dev.clone();
+                let (region, offset) = location(i);
+                async move {
+                    let rbuffer = dev.io_buffer(ALIGN, ALIGN);
+                    let (res, rbuffer) = dev.read(rbuffer, .., region, offset).await;
+                    assert_eq!(res.unwrap(), ALIGN);
+                    assert_eq!(&rbuffer[..], &[(i % (FILES * SLOTS)) as u8; ALIGN]);
+                }
+            })
+            .collect_vec();
+        futures::future::join_all(futures).await;
+    }
+
+    #[tokio::test]
+    async fn test_io_uring_device_cancelled() {
+        let dir = tempfile::tempdir().unwrap();
+        let dev = IoUringDevice::open(config(&dir)).await.unwrap();
+
+        // Cancel the reads right after they are submitted, the buffers are released after the reads complete.
+        for region in 0..FILES as RegionId {
+            let rbuffer = dev.io_buffer(FILE_CAPACITY, FILE_CAPACITY);
+            let read = dev.read(rbuffer, .., region, 0);
+            let _ = futures::FutureExt::now_or_never(read);
+        }
+
+        let mut wbuffer = dev.io_buffer(ALIGN, ALIGN);
+        (&mut wbuffer[..]).put_slice(&[b'x'; ALIGN]);
+        let (res, _) = dev.write(wbuffer, .., 0, 0).await;
+        res.unwrap();
+        let rbuffer = dev.io_buffer(ALIGN, ALIGN);
+        let (res, rbuffer) = dev.read(rbuffer, .., 0, 0).await;
+        res.unwrap();
+        assert_eq!(&rbuffer[..], &[b'x'; ALIGN]);
+
+        drop(dev);
+    }
+
+    #[tokio::test]
+    async fn test_io_uring_device_discard() {
+        let dir = tempfile::tempdir().unwrap();
+        let dev = IoUringDevice::open(config(&dir)).await.unwrap();
+
+        for region in [0, 1] {
+            let mut wbuffer = dev.io_buffer(FILE_CAPACITY, FILE_CAPACITY);
+            (&mut wbuffer[..]).put_slice(&[b'x'; FILE_CAPACITY]);
+            let (res, _) = dev.write(wbuffer, .., region, 0).await;
+            res.unwrap();
+        }
+
+        dev.discard(0).await.unwrap();
+
+        // The discarded region reads zeros and keeps its size, other regions are untouched.
+        let rbuffer = dev.io_buffer(FILE_CAPACITY, FILE_CAPACITY);
+        let (res, rbuffer) = dev.read(rbuffer, .., 0, 0).await;
+        assert_eq!(res.unwrap(), FILE_CAPACITY);
+        assert_eq!(&rbuffer[..], &[0; FILE_CAPACITY]);
+        let rbuffer = dev.io_buffer(FILE_CAPACITY, FILE_CAPACITY);
+        let (res, rbuffer) = dev.read(rbuffer, .., 1, 0).await;
+        res.unwrap();
+        assert_eq!(&rbuffer[..], &[b'x'; FILE_CAPACITY]);
+    }
+}
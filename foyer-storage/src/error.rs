@@ -12,8 +12,8 @@
 //  See the License for the specific language governing permissions and
 //  limitations under the License.
 
-use crate::{buffer::BufferError, device::DeviceError};
-use std::fmt::Debug;
+use crate::{buffer::BufferError, device::DeviceError, region::RegionId};
+use std::{fmt::Debug, time::Duration};
 
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
@@ -21,6 +21,28 @@ pub enum Error {
     Device(#[from] DeviceError),
     #[error("buffer error: {0}")]
     Buffer(#[from] BufferError),
+    #[error("key too large: {size} bytes exceeds the limit of {limit} bytes")]
+    KeyTooLarge { size: usize, limit: usize },
+    #[error("region {region} has an unknown format version {version}, which is newer than the current build")]
+    UnknownRegionVersion { region: RegionId, version: u64 },
+    #[error("region {region} is clobbered, its header magic mismatches")]
+    RegionClobbered { region: RegionId },
+    #[error("region view is stale, region {region} is recycled from generation {view} to {current}")]
+    StaleRegionView { region: RegionId, view: u64, current: u64 },
+    #[error("entry decode error: {0}")]
+    EntryDecode(Box<Error>),
+    #[error("region {region} is not sealed, it is clean or still being written")]
+    RegionNotSealed { region: RegionId },
+    #[error("live data of {live} bytes exceeds the capacity of {capacity} bytes to shrink to")]
+    ShrinkLiveDataExceeded { live: usize, capacity: usize },
+    #[error("device self-test failed: {reason}, the device may not support direct I/O or the align")]
+    DeviceSelfTest { reason: String },
+    #[error("flusher queue is full, {capacity} entries are queued")]
+    QueueFull { capacity: usize },
+    #[error("store is recovering")]
+    Recovering,
+    #[error("operation timed out after {0:?}")]
+    Timeout(Duration),
     #[error("other error: {0}")]
     Other(#[from] anyhow::Error),
 }
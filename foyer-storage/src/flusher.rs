@@ -12,21 +12,37 @@
 //  See the License for the specific language governing permissions and
 //  limitations under the License.
 
-use std::{fmt::Debug, sync::Arc};
+use std::{
+    collections::{hash_map::Entry as HashMapEntry, HashMap},
+    fmt::Debug,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant, SystemTime},
+};
 
+use anyhow::anyhow;
 use either::Either;
 use foyer_common::code::{StorageKey, StorageValue};
-use tokio::sync::{broadcast, mpsc};
+use futures::{future::BoxFuture, FutureExt};
+use itertools::Itertools;
+use tokio::{
+    sync::{broadcast, mpsc, oneshot, Semaphore, TryAcquireError},
+    time::MissedTickBehavior,
+};
 use tracing::Instrument;
 
 use crate::{
-    buffer::{FlushBuffer, PositionedEntry},
-    catalog::{Catalog, Index, Item, Sequence},
+    buffer::{BufferError, FlushBuffer, FlushErrorPolicy, PositionedEntry},
+    catalog::{Catalog, CatalogRetryPolicy, Index, Item, Sequence},
     compress::Compression,
     device::Device,
-    error::Result,
-    metrics::Metrics,
-    region_manager::RegionManager,
+    error::{Error, Result},
+    metrics::{Gauge, Metrics},
+    region::RegionId,
+    region_manager::{RegionManager, WritingRegionGuard},
+    storage::DrainedEntry,
 };
 
 pub struct Entry<K, V>
@@ -38,6 +54,10 @@ where
     pub value: Arc<V>,
     pub sequence: Sequence,
     pub compression: Compression,
+    /// Time the entry is sent to the flusher, for the flush queue lag.
+    pub enqueued: Instant,
+    /// Time after which the entry is treated as a miss, `None` means the entry never expires.
+    pub expire_at: Option<SystemTime>,
 }
 
 impl<K, V> Debug for Entry<K, V>
@@ -49,6 +69,7 @@ where
         f.debug_struct("Entry")
             .field("sequence", &self.sequence)
             .field("compression", &self.compression)
+            .field("expire_at", &self.expire_at)
             .finish()
     }
 }
@@ -64,8 +85,143 @@ where
             value: self.value.clone(),
             sequence: self.sequence,
             compression: self.compression,
+            enqueued: self.enqueued,
+            expire_at: self.expire_at,
+        }
+    }
+}
+
+/// Write-order barrier of a flusher, see [`crate::storage::Storage::barrier`].
+#[derive(Debug)]
+pub struct Barrier {
+    /// Notified after the entries received before the barrier are written to the device.
+    pub flushed_tx: oneshot::Sender<()>,
+    /// Notified after the written entries are synced, the following entries are held until then.
+    pub synced_rx: oneshot::Receiver<()>,
+}
+
+/// Behavior of the flushers when the entry channel is closed while the store is not being closed.
+///
+/// On a graceful close, the store signals the flushers to stop before the channel is closed. The channel is only closed
+/// otherwise if the store is dropped without being closed, e.g. while unwinding from a panic.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ChannelDropPolicy {
+    /// Log an error and mark the store as unhealthy, then flush the buffered entries and exit.
+    #[default]
+    Unhealthy,
+    /// Treat it as a graceful close, flush the buffered entries and exit.
+    Graceful,
+}
+
+/// Behavior of the inserts when the entries queued to the flushers reach the capacity.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum QueueFullPolicy {
+    /// Wait until the flushers receive enough queued entries.
+    #[default]
+    Block,
+    /// Drop the entries as if they are not admitted.
+    Drop,
+    /// Fail the inserts with [`Error::QueueFull`].
+    Reject,
+}
+
+/// Entries sent to the flushers but not received yet, bounded by the capacity if set.
+///
+/// Only the entries take the slots of the queue. The other messages, e.g. barriers, are never held behind the entries,
+/// and they can be sent while holding the drain lock.
+#[derive(Debug)]
+pub struct FlusherQueue {
+    queued: AtomicUsize,
+    /// Free slots of the queue, `None` if unbounded.
+    slots: Option<Semaphore>,
+    capacity: Option<usize>,
+    policy: QueueFullPolicy,
+    depth: Gauge,
+}
+
+impl FlusherQueue {
+    pub fn new(capacity: Option<usize>, policy: QueueFullPolicy, depth: Gauge) -> Self {
+        if let Some(capacity) = capacity {
+            assert!(
+                capacity > 0 && capacity <= u32::MAX as usize,
+                "flusher queue capacity must be in (0, {}], given: {}",
+                u32::MAX,
+                capacity
+            );
+        }
+        Self {
+            queued: AtomicUsize::new(0),
+            slots: capacity.map(Semaphore::new),
+            capacity,
+            policy,
+            depth,
+        }
+    }
+
+    /// Take the slots of `entries` before they are sent to the flushers.
+    ///
+    /// Returns `false` if the queue is full and the entries are dropped by [`QueueFullPolicy::Drop`].
+    pub async fn reserve(&self, entries: usize) -> Result<bool> {
+        if let Some(slots) = self.slots.as_ref() {
+            let permits = self.permits(entries);
+            let permit = match self.policy {
+                QueueFullPolicy::Block => slots.acquire_many(permits).await.unwrap(),
+                QueueFullPolicy::Drop | QueueFullPolicy::Reject => match slots.try_acquire_many(permits) {
+                    Ok(permit) => permit,
+                    Err(TryAcquireError::NoPermits) if self.policy == QueueFullPolicy::Drop => return Ok(false),
+                    Err(TryAcquireError::NoPermits) => {
+                        return Err(Error::QueueFull {
+                            capacity: self.capacity.unwrap(),
+                        })
+                    }
+                    Err(TryAcquireError::Closed) => unreachable!("flusher queue slots are never closed"),
+                },
+            };
+            // Returned by `release` once the entries are received.
+            permit.forget();
+        }
+        self.queued.fetch_add(entries, Ordering::Relaxed);
+        self.depth.add(entries as u64);
+        Ok(true)
+    }
+
+    /// Return the slots of `entries` received by the flushers, or not sent after reserved.
+    pub fn release(&self, entries: usize) {
+        self.queued.fetch_sub(entries, Ordering::Relaxed);
+        self.depth.sub(entries as u64);
+        if let Some(slots) = self.slots.as_ref() {
+            slots.add_permits(self.permits(entries) as usize);
         }
     }
+
+    /// Entries more than the capacity take all slots, so they are sent alone rather than blocked forever.
+    fn permits(&self, entries: usize) -> u32 {
+        entries.min(self.capacity.unwrap_or(entries)) as u32
+    }
+
+    pub fn len(&self) -> usize {
+        self.queued.load(Ordering::Relaxed)
+    }
+
+    pub fn capacity(&self) -> Option<usize> {
+        self.capacity
+    }
+}
+
+pub enum FlusherMessage<K, V>
+where
+    K: StorageKey,
+    V: StorageValue,
+{
+    Entry(Entry<K, V>),
+    /// Entries sent in one message, written in order and indexed together.
+    Batch(Vec<Entry<K, V>>),
+    Barrier(Barrier),
+    /// Flush the buffered entries, and report the entries written since the store started draining.
+    Drain(oneshot::Sender<Vec<DrainedEntry<K>>>),
+    /// Seal the region being written if it is retired by shrinking, so the following entries are written to the
+    /// regions in use.
+    SealRetired(oneshot::Sender<()>),
 }
 
 #[derive(Debug)]
@@ -78,13 +234,45 @@ where
     region_manager: Arc<RegionManager<D>>,
 
     catalog: Arc<Catalog<K, V>>,
+    catalog_retry_policy: CatalogRetryPolicy,
 
     buffer: FlushBuffer<K, V, D>,
 
-    entry_rx: mpsc::UnboundedReceiver<Entry<K, V>>,
+    entry_rx: mpsc::UnboundedReceiver<FlusherMessage<K, V>>,
 
     metrics: Arc<Metrics>,
 
+    /// Set when the store starts draining, the written entries are recorded since then.
+    draining: Arc<AtomicBool>,
+    drained: Vec<DrainedEntry<K>>,
+
+    /// If set, the entries are coalesced per key and only the newest one of each key is written per interval.
+    coalescing_interval: Option<Duration>,
+    pending: HashMap<Arc<K>, Entry<K, V>>,
+
+    /// If set, the region being written is sealed after written for the max age, even if it is not full.
+    max_region_age: Option<Duration>,
+    /// Time the region being written is started.
+    region_started: Option<Instant>,
+    /// Region being written. The flush buffer leaves the region once it is filled up, but it still needs to be made
+    /// evictable on rotation.
+    region: Option<RegionId>,
+
+    queue: Arc<FlusherQueue>,
+
+    /// Entries taken by a failed write before written to the flush buffer, relocated with the buffered entries.
+    unwritten: Vec<Entry<K, V>>,
+    /// Whether the entries are being relocated, see [`FlushErrorPolicy::Relocate`].
+    relocating: bool,
+
+    /// Held while writing, released once the flusher is idle so that other flushers can write under the max writing
+    /// regions limit.
+    writing: Option<WritingRegionGuard>,
+
+    channel_drop_policy: ChannelDropPolicy,
+    /// Cleared if the entry channel is closed unexpectedly, see [`ChannelDropPolicy::Unhealthy`].
+    healthy: Arc<AtomicBool>,
+
     stop_rx: broadcast::Receiver<()>,
 }
 
@@ -94,39 +282,124 @@ where
     V: StorageValue,
     D: Device,
 {
+    // TODO(MrCroxx): use `expect` after `lint_reasons` is stable.
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         region_manager: Arc<RegionManager<D>>,
         catalog: Arc<Catalog<K, V>>,
-        device: D,
-        entry_rx: mpsc::UnboundedReceiver<Entry<K, V>>,
+        catalog_retry_policy: CatalogRetryPolicy,
+        buffer: FlushBuffer<K, V, D>,
+        entry_rx: mpsc::UnboundedReceiver<FlusherMessage<K, V>>,
         metrics: Arc<Metrics>,
+        draining: Arc<AtomicBool>,
+        coalescing_interval: Option<Duration>,
+        max_region_age: Option<Duration>,
+        queue: Arc<FlusherQueue>,
+        channel_drop_policy: ChannelDropPolicy,
+        healthy: Arc<AtomicBool>,
         stop_rx: broadcast::Receiver<()>,
     ) -> Self {
-        let buffer = FlushBuffer::new(device.clone());
         Self {
             region_manager,
             catalog,
+            catalog_retry_policy,
             buffer,
             entry_rx,
             metrics,
+            draining,
+            drained: vec![],
+            coalescing_interval,
+            pending: HashMap::new(),
+            max_region_age,
+            region_started: None,
+            region: None,
+            queue,
+            unwritten: vec![],
+            relocating: false,
+            writing: None,
+            channel_drop_policy,
+            healthy,
             stop_rx,
         }
     }
 
     pub async fn run(mut self) -> Result<()> {
+        let mut ticker = self.coalescing_interval.map(|interval| {
+            let mut ticker = tokio::time::interval(interval);
+            ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            ticker
+        });
+
         loop {
+            if self.entry_rx.is_empty() {
+                self.writing = None;
+            }
+
+            let seal_after = self
+                .region_deadline()
+                .map(|deadline| deadline.saturating_duration_since(Instant::now()));
+
             tokio::select! {
                 biased;
-                entry = self.entry_rx.recv() => {
-                    let Some(entry) = entry else {
-                        self.buffer.flush().await?;
+                message = self.entry_rx.recv() => {
+                    let Some(message) = message else {
+                        self.on_channel_closed();
+                        self.acquire_writing().await;
+                        self.flush_pending().await?;
+                        self.flush().await?;
                         tracing::info!("[flusher] exit");
                         return Ok(());
                     };
-                    self.handle(entry).await?;
+                    match &message {
+                        FlusherMessage::Entry(_) => {
+                            self.queue.release(1);
+                        }
+                        FlusherMessage::Batch(entries) => {
+                            self.queue.release(entries.len());
+                        }
+                        _ => {}
+                    }
+                    if !matches!(
+                        message,
+                        FlusherMessage::Entry(_) | FlusherMessage::Batch(_) if self.coalescing_interval.is_some()
+                    ) {
+                        self.acquire_writing().await;
+                    }
+                    match message {
+                        FlusherMessage::Entry(entry) if self.coalescing_interval.is_some() => self.coalesce(entry),
+                        FlusherMessage::Entry(entry) => self.handle(entry).await?,
+                        FlusherMessage::Batch(entries) if self.coalescing_interval.is_some() => {
+                            entries.into_iter().for_each(|entry| self.coalesce(entry))
+                        }
+                        FlusherMessage::Batch(entries) => self.handle_batch(entries).await?,
+                        FlusherMessage::Barrier(barrier) => {
+                            if !self.handle_barrier(barrier).await? {
+                                tracing::info!("[flusher] exit");
+                                return Ok(());
+                            }
+                        }
+                        FlusherMessage::Drain(drained_tx) => self.handle_drain(drained_tx).await?,
+                        FlusherMessage::SealRetired(sealed_tx) => self.handle_seal_retired(sealed_tx).await?,
+                    }
+                }
+                _ = async { ticker.as_mut().unwrap().tick().await }, if ticker.is_some() => {
+                    if !self.pending.is_empty() {
+                        self.acquire_writing().await;
+                    }
+                    self.flush_pending().await?;
+                }
+                _ = async { tokio::time::sleep(seal_after.unwrap()).await }, if seal_after.is_some() => {
+                    self.acquire_writing().await;
+                    // The pending entries are written before sealing, which may rotate the region on the way.
+                    self.flush_pending().await?;
+                    if self.region_deadline().is_some_and(|deadline| deadline <= Instant::now()) {
+                        self.seal(&mut vec![]).await?;
+                    }
                 }
                 _ = self.stop_rx.recv() => {
-                    self.buffer.flush().await?;
+                    self.acquire_writing().await;
+                    self.flush_pending().await?;
+                    self.flush().await?;
                     tracing::info!("[flusher] exit");
                     return Ok(())
                 }
@@ -134,13 +407,251 @@ where
         }
     }
 
+    /// Wait for the permission to write if the flusher is not writing.
+    async fn acquire_writing(&mut self) {
+        if self.writing.is_none() {
+            self.writing = Some(self.region_manager.acquire_writing().await);
+        }
+    }
+
+    /// Handle the entry channel being closed, which is unexpected unless the store has signaled the flusher to stop.
+    fn on_channel_closed(&mut self) {
+        if self.stop_rx.try_recv().is_ok() {
+            return;
+        }
+        match self.channel_drop_policy {
+            ChannelDropPolicy::Unhealthy => {
+                tracing::error!("[flusher] entry channel is closed without the store being closed, mark it unhealthy");
+                self.healthy.store(false, Ordering::Release);
+            }
+            ChannelDropPolicy::Graceful => {
+                tracing::info!("[flusher] entry channel is closed without the store being closed");
+            }
+        }
+    }
+
+    /// Time after which the region being written is sealed, `None` if there is no max region age or no region.
+    fn region_deadline(&self) -> Option<Instant> {
+        Some(self.region_started? + self.max_region_age?)
+    }
+
+    /// Write the buffered entries and seal the region being written, the written entries are collected into `entries`
+    /// and indexed before the region becomes evictable.
+    ///
+    /// The following entries are written to a new region.
+    async fn seal(&mut self, entries: &mut Vec<PositionedEntry<K, V>>) -> Result<()> {
+        let sealed = match self.buffer.seal().await {
+            Ok(sealed) => sealed,
+            // Seal the region the entries are relocated to instead.
+            Err(e) => {
+                self.relocate(e, entries).await?;
+                self.buffer.seal().await?
+            }
+        };
+        entries.extend(sealed);
+        let region = self.region.take();
+        self.update_catalog(std::mem::take(entries)).await?;
+        if let Some(region) = region {
+            self.region_manager.eviction_push(region);
+        }
+        self.region_started = None;
+
+        Ok(())
+    }
+
+    /// Keep the entry pending until the next interval, replacing the older pending entry of the same key.
+    fn coalesce(&mut self, entry: Entry<K, V>) {
+        match self.pending.entry(entry.key.clone()) {
+            HashMapEntry::Occupied(mut o) => {
+                if o.get().sequence < entry.sequence {
+                    o.insert(entry);
+                }
+            }
+            HashMapEntry::Vacant(v) => {
+                v.insert(entry);
+            }
+        }
+    }
+
+    /// Write the pending entries in sequence order and flush them to the device.
+    async fn flush_pending(&mut self) -> Result<()> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+
+        let entries = self
+            .pending
+            .drain()
+            .map(|(_, entry)| entry)
+            .sorted_by_key(|entry| entry.sequence)
+            .collect_vec();
+        for entry in entries {
+            self.handle(entry).await?;
+        }
+
+        let entries = self.flush().await?;
+        self.update_catalog(entries).await
+    }
+
     async fn handle(&mut self, entry: Entry<K, V>) -> Result<()> {
         let timer = self.metrics.inner_op_duration_flusher_handle.start_timer();
 
-        let old_region = self.buffer.region();
+        let mut entries = vec![];
+        self.write(entry, &mut entries).await?;
+        self.update_catalog(entries).await?;
+
+        drop(timer);
+        Ok(())
+    }
+
+    /// Write the entries in order, the catalog is updated once for the entries written to the device meanwhile.
+    async fn handle_batch(&mut self, batch: Vec<Entry<K, V>>) -> Result<()> {
+        let timer = self.metrics.inner_op_duration_flusher_handle.start_timer();
+
+        let mut entries = vec![];
+        for entry in batch {
+            self.write(entry, &mut entries).await?;
+        }
+        self.update_catalog(entries).await?;
+
+        drop(timer);
+        Ok(())
+    }
+
+    /// Write the entry to the flush buffer, and collect the entries written to the device into `entries`.
+    ///
+    /// The collected entries are indexed before the region is rotated, so a region is never evictable before its
+    /// entries are indexed.
+    async fn write(&mut self, entry: Entry<K, V>, entries: &mut Vec<PositionedEntry<K, V>>) -> Result<()> {
+        match self.try_write(entry, entries).await {
+            Err(Error::Buffer(e @ BufferError::Device(_))) => self.relocate(e, entries).await,
+            res => res,
+        }
+    }
+
+    /// Flush the buffered entries, and relocate them if the write fails, see [`FlushErrorPolicy::Relocate`].
+    async fn flush(&mut self) -> Result<Vec<PositionedEntry<K, V>>> {
+        match self.buffer.flush().await {
+            Ok(entries) => Ok(entries),
+            Err(e) => {
+                let mut entries = vec![];
+                self.relocate(e, &mut entries).await?;
+                Ok(entries)
+            }
+        }
+    }
+
+    /// Leave the region of the failed write and write the buffered and the unwritten entries to a new region, see
+    /// [`FlushErrorPolicy::Relocate`].
+    ///
+    /// The left region is made evictable, so the reclaimer cleans it up. Returns the error of the last relocation if
+    /// all relocations fail, or the original error with the other policies.
+    async fn relocate(&mut self, error: BufferError, entries: &mut Vec<PositionedEntry<K, V>>) -> Result<()> {
+        let FlushErrorPolicy::Relocate { retries, backoff } = self.buffer.flush_error_policy() else {
+            return Err(error.into());
+        };
+        // A write failed while relocating is retried by the ongoing relocation.
+        if self.relocating || !matches!(error, BufferError::Device(_)) {
+            return Err(error.into());
+        }
+
+        self.relocating = true;
+        let res = self.relocate_with_retries(error, retries, backoff, entries).await;
+        self.relocating = false;
+        res
+    }
+
+    async fn relocate_with_retries(
+        &mut self,
+        mut error: BufferError,
+        retries: usize,
+        backoff: Duration,
+        entries: &mut Vec<PositionedEntry<K, V>>,
+    ) -> Result<()> {
+        for retry in 0..retries {
+            let relocated = self.leave_region(entries).await?;
+            tracing::warn!(
+                "[flusher]: write to the device error, relocate {} entries after {retry} retries: {error}",
+                relocated.len()
+            );
+
+            tokio::time::sleep(backoff.saturating_mul(1u32 << retry.min(31))).await;
+
+            match self.rewrite(relocated, entries).await {
+                Ok(()) => return Ok(()),
+                Err(Error::Buffer(e @ BufferError::Device(_))) => error = e,
+                Err(e) => return Err(e),
+            }
+        }
+
+        let dropped = self.leave_region(entries).await?;
+        Err(anyhow!(
+            "write to the device error after {retries} relocations, {} entries are dropped: {error}",
+            dropped.len()
+        )
+        .into())
+    }
+
+    /// Leave the region of the failed write and make it evictable, returns the entries to be relocated.
+    async fn leave_region(&mut self, entries: &mut Vec<PositionedEntry<K, V>>) -> Result<Vec<Entry<K, V>>> {
+        let mut relocated = self.buffer.abandon();
+        relocated.append(&mut self.unwritten);
+        // Index the entries written before the failed write, before the region becomes evictable.
+        self.update_catalog(std::mem::take(entries)).await?;
+        if let Some(region) = self.region.take() {
+            self.region_manager.eviction_push(region);
+        }
+        self.region_started = None;
+        Ok(relocated)
+    }
+
+    /// Write the relocated entries and flush them, so a failed write is found before the next relocation.
+    fn rewrite<'a>(
+        &'a mut self,
+        relocated: Vec<Entry<K, V>>,
+        entries: &'a mut Vec<PositionedEntry<K, V>>,
+    ) -> BoxFuture<'a, Result<()>> {
+        async move {
+            let mut relocated = relocated.into_iter();
+            while let Some(entry) = relocated.next() {
+                if let Err(e) = self.try_write(entry, entries).await {
+                    self.unwritten.extend(relocated);
+                    return Err(e);
+                }
+            }
+            entries.extend(self.buffer.flush().await?);
+            Ok(())
+        }
+        .boxed()
+    }
+
+    /// Write the entry without relocation on a failed write, see [`Flusher::write`].
+    async fn try_write(&mut self, entry: Entry<K, V>, entries: &mut Vec<PositionedEntry<K, V>>) -> Result<()> {
+        // Skip the entries expired while queued, there is no point in writing them.
+        if entry.expire_at.is_some_and(|expire_at| expire_at <= SystemTime::now()) {
+            self.catalog.remove_with_sequence(&entry.key, entry.sequence);
+            self.metrics.catalog_ops_expire.inc_by(1);
+            return Ok(());
+        }
+
+        // Seal the region before writing if it is too old, in case the flusher is too busy to be woken by the timer.
+        if self
+            .region_deadline()
+            .is_some_and(|deadline| deadline <= Instant::now())
+        {
+            if let Err(e) = self.seal(entries).await {
+                self.unwritten.push(entry);
+                return Err(e);
+            }
+        }
+
+        let old_region = self.region;
 
         let entry = match self.buffer.write(entry).await? {
-            Either::Left(entries) => return self.update_catalog(entries).await,
+            Either::Left(written) => {
+                entries.extend(written);
+                return Ok(());
+            }
             Either::Right(entry) => entry,
         };
 
@@ -156,48 +667,148 @@ where
             .await;
         drop(acquire_clean_region_timer);
 
-        // 2. rotate flush buffer
-        let entries = self.buffer.rotate(new_region).await?;
-        self.update_catalog(entries).await?;
+        // 2. rotate flush buffer, the views taken on the previous content of the region are stale
+        let generation = self.region_manager.region(&new_region).advance_generation();
+        match self.buffer.rotate(new_region, generation).await {
+            Ok(rotated) => entries.extend(rotated),
+            Err(e) => {
+                // The new region is not written yet, give it back and keep the entry to be relocated.
+                self.region_manager.clean_regions().release(new_region);
+                self.unwritten.push(entry);
+                return Err(e.into());
+            }
+        }
+        self.update_catalog(std::mem::take(entries)).await?;
         if let Some(old_region) = old_region {
             self.region_manager.eviction_push(old_region);
         }
+        self.region = Some(new_region);
+        self.region_started = Some(Instant::now());
 
         self.metrics
             .total_bytes
             .add(self.region_manager.region(&new_region).device().region_size() as u64);
 
         // 3. retry write
-        let entries = self.buffer.write(entry).await?.unwrap_left();
+        entries.extend(self.buffer.write(entry).await?.unwrap_left());
+
+        Ok(())
+    }
 
+    /// Write the buffered entries, and hold the following entries until the written entries are synced.
+    ///
+    /// Returns `false` if the flusher is stopped while holding.
+    async fn handle_barrier(&mut self, Barrier { flushed_tx, synced_rx }: Barrier) -> Result<bool> {
+        self.flush_pending().await?;
+        let entries = self.flush().await?;
         self.update_catalog(entries).await?;
 
-        drop(timer);
+        let _ = flushed_tx.send(());
+
+        // Other flushers need to write to reach the barrier too.
+        self.writing = None;
+
+        tokio::select! {
+            // The barrier is also released if the waiter is dropped.
+            _ = synced_rx => Ok(true),
+            _ = self.stop_rx.recv() => Ok(false),
+        }
+    }
+
+    /// Write the buffered entries, and report the entries written since the store started draining.
+    async fn handle_drain(&mut self, drained_tx: oneshot::Sender<Vec<DrainedEntry<K>>>) -> Result<()> {
+        self.flush_pending().await?;
+        let entries = self.flush().await?;
+        self.update_catalog(entries).await?;
+
+        let _ = drained_tx.send(std::mem::take(&mut self.drained));
+        Ok(())
+    }
+
+    /// Write the pending entries, and seal the region being written if it is retired.
+    async fn handle_seal_retired(&mut self, sealed_tx: oneshot::Sender<()>) -> Result<()> {
+        self.flush_pending().await?;
+        if self.region.is_some_and(|region| self.region_manager.is_retired(region)) {
+            self.seal(&mut vec![]).await?;
+        }
+
+        let _ = sealed_tx.send(());
         Ok(())
     }
 
     #[tracing::instrument(skip_all)]
-    async fn update_catalog(&self, entries: Vec<PositionedEntry<K, V>>) -> Result<()> {
+    async fn update_catalog(&mut self, entries: Vec<PositionedEntry<K, V>>) -> Result<()> {
+        // Remove the entries dropped on failed writes, unless they are overwritten since.
+        for PositionedEntry {
+            entry: Entry { key, sequence, .. },
+            ..
+        } in self.buffer.take_dropped()
+        {
+            self.catalog.remove_with_sequence(&key, sequence);
+        }
+
         if entries.is_empty() {
             return Ok(());
         }
 
+        if self.draining.load(Ordering::Acquire) {
+            self.drained.extend(entries.iter().map(|entry| {
+                (
+                    entry.entry.key.clone(),
+                    entry.entry.sequence,
+                    entry.region,
+                    entry.offset,
+                )
+            }));
+        }
+
         // record fully flushed bytes by the way
         let mut bytes = 0;
 
         let timer = self.metrics.inner_op_duration_update_catalog.start_timer();
         for PositionedEntry {
-            entry: Entry { key, sequence, .. },
+            entry:
+                Entry {
+                    key,
+                    sequence,
+                    enqueued,
+                    expire_at,
+                    ..
+                },
             region,
             offset,
             len,
+            index,
         } in entries
         {
-            bytes += len;
-            let index = Index::Region {
-                view: self.region_manager.region(&region).view(offset as u32, len as u32),
+            self.metrics.flush_queue_lag.observe(enqueued.elapsed().as_secs_f64());
+
+            let view = self.region_manager.region(&region).view(offset as u32, len as u32);
+            let index = match index {
+                // Entries of a block share the same view, count the bytes of the block once.
+                Some(index) => {
+                    if index == 0 {
+                        bytes += len;
+                    }
+                    Index::Block { view, index }
+                }
+                None => {
+                    bytes += len;
+                    Index::Region { view }
+                }
             };
-            let item = Item::new(sequence, index);
+            let item = Item::new(sequence, index).with_expire_at(expire_at);
+            if let Err(e) = self.persist_with_retries(&key, &item).await {
+                tracing::error!(
+                    "[flusher]: persist the entry to the catalog backend error after {} retries, drop the entry and mark region {region} orphaned: {e}",
+                    self.catalog_retry_policy.retries
+                );
+                // Drop the inflight index of the entry, unless it is overwritten since.
+                self.catalog.remove_with_sequence(&key, sequence);
+                self.region_manager.mark_orphaned(region);
+                self.metrics.catalog_ops_persist_failed.inc_by(1);
+                continue;
+            }
             self.catalog.insert(key, item);
         }
         drop(timer);
@@ -206,4 +817,21 @@ where
 
         Ok(())
     }
+
+    /// Persist the entry to the catalog backend, and retry with backoff if it fails, see [`CatalogRetryPolicy`].
+    async fn persist_with_retries(&self, key: &Arc<K>, item: &Item<K, V>) -> Result<()> {
+        let CatalogRetryPolicy { retries, backoff } = self.catalog_retry_policy;
+        let mut retry = 0;
+        loop {
+            match self.catalog.persist(key, item).await {
+                Ok(()) => return Ok(()),
+                Err(e) if retry < retries => {
+                    tracing::warn!("[flusher]: persist the entry to the catalog backend error, retry {retry}: {e}");
+                    tokio::time::sleep(backoff.saturating_mul(1u32 << retry.min(31))).await;
+                    retry += 1;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
 }
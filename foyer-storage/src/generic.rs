@@ -14,53 +14,78 @@
 
 use std::{
     borrow::Borrow,
+    collections::{HashMap, HashSet, VecDeque},
     fmt::Debug,
     hash::{Hash, Hasher},
     marker::PhantomData,
+    ops::{Deref, Range},
+    path::{Path, PathBuf},
     sync::{
-        atomic::{AtomicU64, Ordering},
+        atomic::{AtomicBool, AtomicU64, Ordering},
         Arc,
     },
-    time::{Duration, Instant},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 
+use allocator_api2::vec::Vec as VecA;
 use anyhow::anyhow;
 use bitmaps::Bitmap;
 use bytes::{Buf, BufMut};
 use foyer_common::{
     bits,
     code::{StorageKey, StorageValue},
+    rate::RateLimiter,
 };
 
 use foyer_memory::EvictionConfig;
-use futures::future::try_join_all;
+use futures::{
+    future::{join, try_join_all, BoxFuture},
+    stream::BoxStream,
+    FutureExt, StreamExt,
+};
 use itertools::Itertools;
-use parking_lot::Mutex;
+use parking_lot::{Mutex, RwLock};
 use tokio::{
-    sync::{broadcast, mpsc, Semaphore},
+    io::{AsyncWrite, AsyncWriteExt},
+    sync::{broadcast, mpsc, oneshot, Semaphore, SemaphorePermit},
     task::JoinHandle,
 };
-use twox_hash::XxHash64;
+use twox_hash::{xxh3, XxHash64};
 
 use crate::{
-    admission::{AdmissionContext, AdmissionPolicy},
-    buffer::BufferError,
-    catalog::{Catalog, Index, Item, Sequence},
-    compress::Compression,
-    device::Device,
-    error::Result,
-    flusher::{Entry, Flusher},
+    admission::{AdmissionContext, AdmissionDecision, AdmissionObserver, AdmissionPolicy},
+    block::{BlockCache, BlockMeta, DecodedBlock},
+    buffer::{BufferError, FlushBuffer, FlushErrorPolicy, PaddingPolicy},
+    catalog::{Catalog, CatalogBackend, CatalogRetryPolicy, Index, Item, Sequence, WriteConflictPolicy},
+    compress::{BlockCompression, Compression, CompressionObserver},
+    cpu_budget::{CpuBudget, CpuBudgetHandle},
+    device::{Device, DeviceExt},
+    error::{Error, Result},
+    flusher::{Barrier, ChannelDropPolicy, Entry, Flusher, FlusherMessage, FlusherQueue, QueueFullPolicy},
+    io_limit::IoBytesLimiter,
     judge::Judges,
-    metrics::{Metrics, METRICS},
+    metrics::{get_metrics_recorder, Gauge, Metrics, MetricsRecorder},
     reclaimer::Reclaimer,
-    region::{Region, RegionHeader, RegionId},
+    region::{KeyFilter, Region, RegionHeader, RegionId, RegionView, UnknownRegionVersionPolicy, Version},
     region_manager::RegionManager,
     reinsertion::{ReinsertionContext, ReinsertionPolicy},
-    storage::{CachedEntry, Storage, StorageWriter},
+    snapshot,
+    storage::{CachedEntry, CompactionReport, CompactionStrategy, DrainedEntry, ScrubReport, Storage, StorageWriter},
 };
 
 const DEFAULT_BROADCAST_CAPACITY: usize = 4096;
 
+/// Interval to re-verify the region header magic on read, see [`GenericStoreConfig::verify_region_magic_on_read`].
+const REGION_MAGIC_VERIFY_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Max size of each chunk written to the writer by `get_stream`.
+const STREAM_CHUNK_SIZE: usize = 64 * 1024;
+
+// TODO(MrCroxx): Use `trait_alias` after stable.
+/// Extract the prefix of the key for entry grouping, see [`GenericStoreConfig::key_prefix`].
+pub trait KeyPrefix<K>: Fn(&K) -> u64 + Send + Sync + 'static {}
+impl<K, T> KeyPrefix<K> for T where T: Fn(&K) -> u64 + Send + Sync + 'static {}
+
 pub struct GenericStoreConfig<K, V, D>
 where
     K: StorageKey,
@@ -88,6 +113,9 @@ where
     pub reinsertions: Vec<Arc<dyn ReinsertionPolicy<Key = K, Value = V>>>,
 
     /// Count of flushers.
+    ///
+    /// Each flusher owns its flush buffer and writes its own region acquired from the clean regions, the entries are
+    /// sharded to the flushers. A flusher makes its region evictable once the region is fully written.
     pub flushers: usize,
 
     /// Count of reclaimers.
@@ -98,11 +126,249 @@ where
     /// `clean_region_threshold` is recommended to be equal or larger than `reclaimers`.
     pub clean_region_threshold: usize,
 
-    /// Concurrency of recovery.
+    /// Count of regions scanned concurrently during recovery, must be > 0.
+    ///
+    /// The same key recovered from different regions is resolved by the sequence, so the recovered catalog is the
+    /// same with any concurrency. `1` means scanning the regions serially.
     pub recover_concurrency: usize,
 
     /// Compression algorithm.
     pub compression: Compression,
+
+    /// Enable checksum for entries.
+    ///
+    /// If disabled, a zero checksum is written to the entry header and verification is skipped on read. The choice is
+    /// recorded per entry, so the entries written with checksum disabled are never verified, even after the store is
+    /// reopened with checksum enabled.
+    ///
+    /// WARNING: Corrupted entries can NOT be detected if disabled. Only disable it for ephemeral caches on trusted
+    /// devices.
+    pub checksum: bool,
+
+    /// Capacity of the ring buffer that records the recent inserted keys and sequences for diagnosis.
+    ///
+    /// `0` means disabled.
+    pub recent_inserts_capacity: usize,
+
+    /// Max serialized size of a key.
+    ///
+    /// Inserting a key larger than the limit fails with [`Error::KeyTooLarge`] before the entry is written.
+    ///
+    /// `None` means unlimited.
+    pub max_key_size: Option<usize>,
+
+    /// Compression algorithm applied to all entries of a region, recorded in the region header.
+    ///
+    /// If set, it overrides the compression of each entry written into the region.
+    ///
+    /// `None` means entries are compressed per entry.
+    pub region_compression: Option<Compression>,
+
+    /// Alignment of entries packed in a device block, recorded in the region header.
+    ///
+    /// Entries smaller than the device align are packed in device blocks with the intra block align, which is
+    /// independent of the device io align. Must be a power of 2 between the entry header size and the device align.
+    ///
+    /// `None` means each entry is aligned to the device align.
+    pub intra_block_align: Option<usize>,
+
+    /// Path of the catalog snapshot to recover from on open.
+    ///
+    /// The snapshot is taken by [`Storage::snapshot_catalog`]. If the snapshot is valid, the catalog is recovered
+    /// from it without a full region scan. Otherwise, the store falls back to scanning all regions.
+    ///
+    /// `None` means always recover by scanning all regions.
+    pub catalog_snapshot_path: Option<PathBuf>,
+
+    /// Duration that an evictable region remains readable before it is physically reclaimed.
+    ///
+    /// Reclamation drops the indices of a region and waits for the unfinished readers. The grace period gives slow
+    /// readers that are about to read the region time to finish.
+    ///
+    /// `Duration::ZERO` means the region is reclaimed immediately.
+    pub reclaim_grace_period: Duration,
+
+    /// Observer of the compression decision of each written entry.
+    ///
+    /// `None` means no observer.
+    pub compression_observer: Option<Arc<dyn CompressionObserver>>,
+
+    /// Policy for the regions with an unknown (newer) format version found during recovery.
+    pub unknown_region_version_policy: UnknownRegionVersionPolicy,
+
+    /// Compress consecutive entries together as blocks.
+    ///
+    /// The compression of each entry is ignored, but the region compression still overrides the block compression if
+    /// set.
+    ///
+    /// `None` means entries are compressed per entry.
+    pub block_compression: Option<BlockCompression>,
+
+    /// Max CPU cores consumed by the background tasks, e.g. compression and checksum on flush, and decompression on
+    /// reclamation.
+    ///
+    /// The CPU-bound sections of the background tasks are limited to `ceil(cores)` concurrency. If the budget is
+    /// fractional, the sections are throttled to keep the average consumption within the budget.
+    ///
+    /// `None` means unlimited.
+    pub background_cpu_cores: Option<f64>,
+
+    /// Metrics recorder of the store.
+    ///
+    /// `None` means the default metrics recorder, see [`crate::set_metrics_recorder`].
+    pub metrics_recorder: Option<Arc<dyn MetricsRecorder>>,
+
+    /// Policy to decide the winner when the same key is inserted concurrently.
+    pub write_conflict_policy: WriteConflictPolicy,
+
+    /// Max rate of the catalog mutations issued by the inserts, in ops per second.
+    ///
+    /// Inserts exceeding the rate are delayed before they mutate the catalog, which applies backpressure to the
+    /// callers instead of contending on the catalog locks. The index updates by the flushers follow the admitted
+    /// inserts and are not limited separately.
+    ///
+    /// `None` means unlimited.
+    pub catalog_ops_limit: Option<f64>,
+
+    /// Offset of the first entry in a region, the space between the region header and the first entry is reserved.
+    ///
+    /// Must be aligned to the device align, and in `[device align, region size)`.
+    ///
+    /// `None` means the first entry is placed right after the region header, at the device align.
+    pub first_entry_offset: Option<usize>,
+
+    /// Verify the region header magic before reading entries from a region, to detect the region clobbered by other
+    /// processes on shared storage.
+    ///
+    /// The magic is verified on the first read of a region, and re-verified periodically. A read from a clobbered
+    /// region fails with [`Error::RegionClobbered`].
+    pub verify_region_magic_on_read: bool,
+
+    /// Write an aligned block to the device, read it back and verify it on open, before trusting the device.
+    ///
+    /// The open fails with [`Error::DeviceSelfTest`] if the round-trip fails, e.g. the filesystem rejects direct I/O.
+    pub self_test_on_open: bool,
+
+    /// Max bytes per second read by [`Storage::scrub`], to keep the scrub from interfering with the foreground reads.
+    ///
+    /// `None` means unlimited.
+    pub scrub_throughput_limit: Option<usize>,
+
+    /// Coalesce the writes of update-heavy keys.
+    ///
+    /// If set, the inserted entries are kept pending in memory, and only the newest entry of each key is written per
+    /// interval, rather than every intermediate version. The pending entries are still served by reads. `None` means
+    /// the entries are written as soon as inserted.
+    pub write_coalescing_interval: Option<Duration>,
+
+    /// Max count of the regions being written concurrently by the flushers.
+    ///
+    /// A flusher waits for the others to become idle before writing if the limit is reached, which keeps the writes
+    /// concentrated in fewer regions. `None` means each flusher writes its own region independently.
+    pub max_writing_regions: Option<usize>,
+
+    /// Padding applied to the stored values, so that their stored sizes don't reveal the exact value lengths.
+    pub size_padding: PaddingPolicy,
+
+    /// Store a 128-bit hash of the key on disk instead of the full key.
+    ///
+    /// It shrinks the per-entry overhead of long keys. Reads resolve the full key from the in-memory catalog and verify
+    /// it against the stored hash, so a hash collision is tolerated only between keys indexed at the same time, which
+    /// is negligible for a 128-bit hash.
+    ///
+    /// WARNING: The full keys can NOT be rebuilt from the entries stored with key hashes. Region scans, e.g. recovery
+    /// without a valid catalog snapshot, reinsertion and scrub, skip these entries, so they are lost after a restart
+    /// unless recovered from [`GenericStoreConfig::catalog_snapshot_path`]. Not supported with block compression.
+    pub store_key_hash_only: bool,
+
+    /// Level of [`Compression::Zstd`], applied to both the entries and the blocks.
+    ///
+    /// The level is only needed for compression, so it can be changed across restarts. `0` means the zstd default
+    /// level.
+    pub zstd_level: i32,
+
+    /// Max count of the concurrent decompressions on the get path.
+    ///
+    /// Gets of compressed entries wait for the others to finish decompressing if the limit is reached, which bounds
+    /// the CPU consumed by a burst of gets of large compressed values. `None` means unlimited.
+    pub max_concurrent_decompressions: Option<usize>,
+
+    /// Algorithm of the entry checksums.
+    ///
+    /// The algorithm is recorded in each entry header and picked on reads, so it can be changed across restarts.
+    pub checksum_algorithm: ChecksumAlgorithm,
+
+    /// Extract the prefix of a key, e.g. the tenant id, to group the entries for locality.
+    ///
+    /// Entries with the same prefix are routed to the same flusher, so they are written into the same regions instead
+    /// of interleaving with the entries of the other prefixes. `None` means no grouping.
+    pub key_prefix: Option<Arc<dyn KeyPrefix<K>>>,
+
+    /// Behavior of the flushers when writing to the device fails.
+    pub flush_error_policy: FlushErrorPolicy,
+
+    /// Observer of the admission decisions made by each admission policy.
+    pub admission_observer: Option<Arc<dyn AdmissionObserver<Key = K>>>,
+
+    /// Max wall-clock age of the region being written by a flusher.
+    ///
+    /// A region that has been written for longer is sealed even if it is not full, and the following entries are
+    /// written to a new region. It bounds the window of the writes buffered in a region, and keeps the entries of the
+    /// same region close in age. `None` means the regions are only rotated when full.
+    pub max_region_age: Option<Duration>,
+
+    /// Behavior of the flushers when the entry channel is closed while the store is not being closed.
+    pub channel_drop_policy: ChannelDropPolicy,
+
+    /// Compression of the entries by key prefix, which overrides `compression` for the listed prefixes.
+    ///
+    /// The prefix of a key is extracted by `key_prefix`, so it takes no effect if `key_prefix` is not set.
+    pub prefix_compressions: HashMap<u64, Compression>,
+
+    /// Max total bytes of the in-flight device reads and writes.
+    ///
+    /// Each I/O waits for the others to complete if the limit is reached, which bounds the memory held by the pending
+    /// io buffers and the queue depth of the device on large flushes. An I/O larger than the limit is issued alone.
+    /// `None` means unlimited.
+    pub io_bytes_in_flight_limit: Option<usize>,
+
+    /// Max count of the entries sent to the flushers but not received yet.
+    ///
+    /// A burst of inserts is queued to the flushers without bound if the device can't keep up, which holds the memory
+    /// of the queued entries. The inserts exceeding the capacity are handled by `queue_full_policy`. `None` means
+    /// unbounded.
+    pub flusher_queue_capacity: Option<usize>,
+
+    /// Behavior of the inserts when the flusher queue is full.
+    pub queue_full_policy: QueueFullPolicy,
+
+    /// Alignment of the flushes to the regions, e.g. the physical erase block size of the device.
+    ///
+    /// Each flush is padded up to the alignment and the flush threshold is rounded up to it, which trades some space
+    /// for the write patterns that reduce the write amplification of some SSDs. Must be a power of 2 no less than the
+    /// device align that divides the region size. `None` means the flushes are aligned to the device align.
+    pub flush_alignment: Option<usize>,
+    /// Write the value uncompressed if the compression doesn't make it smaller, e.g. for the incompressible values.
+    ///
+    /// The raw value is serialized first and compressed into a scratch buffer to compare the sizes. The entry header
+    /// records the algorithm actually applied, so the reads are not affected.
+    pub adaptive_compression: bool,
+    /// Count of the regions read ahead by [`Storage::scan`] while the current region is parsed, `0` to read the
+    /// regions one at a time.
+    ///
+    /// Each region read ahead holds an io buffer of the region size.
+    pub scan_read_ahead: usize,
+    /// Verify the checksums of the entries while the regions are scanned to rebuild the catalog on open.
+    ///
+    /// The catalog is always recovered on open, there is no separate call to recover it. An entry that fails the
+    /// verification is skipped and counted by the `checksum_mismatch` recover metric, the scan goes on with the next
+    /// entry. The values are read in full for the verification, which slows down the recovery.
+    pub verify_checksum_on_recover: bool,
+    /// Backend that persists the index of the flushed entries besides the in-memory catalog, `None` if the catalog is
+    /// in memory only.
+    pub catalog_backend: Option<Arc<dyn CatalogBackend<K>>>,
+    /// Retry of the failed catalog backend inserts on flush.
+    pub catalog_retry_policy: CatalogRetryPolicy,
 }
 
 impl<K, V, D> Debug for GenericStoreConfig<K, V, D>
@@ -123,6 +389,46 @@ where
             .field("clean_region_threshold", &self.clean_region_threshold)
             .field("recover_concurrency", &self.recover_concurrency)
             .field("compression", &self.compression)
+            .field("checksum", &self.checksum)
+            .field("recent_inserts_capacity", &self.recent_inserts_capacity)
+            .field("max_key_size", &self.max_key_size)
+            .field("region_compression", &self.region_compression)
+            .field("intra_block_align", &self.intra_block_align)
+            .field("catalog_snapshot_path", &self.catalog_snapshot_path)
+            .field("reclaim_grace_period", &self.reclaim_grace_period)
+            .field("compression_observer", &self.compression_observer)
+            .field("unknown_region_version_policy", &self.unknown_region_version_policy)
+            .field("block_compression", &self.block_compression)
+            .field("background_cpu_cores", &self.background_cpu_cores)
+            .field("metrics_recorder", &self.metrics_recorder)
+            .field("write_conflict_policy", &self.write_conflict_policy)
+            .field("catalog_ops_limit", &self.catalog_ops_limit)
+            .field("first_entry_offset", &self.first_entry_offset)
+            .field("verify_region_magic_on_read", &self.verify_region_magic_on_read)
+            .field("self_test_on_open", &self.self_test_on_open)
+            .field("scrub_throughput_limit", &self.scrub_throughput_limit)
+            .field("write_coalescing_interval", &self.write_coalescing_interval)
+            .field("max_writing_regions", &self.max_writing_regions)
+            .field("size_padding", &self.size_padding)
+            .field("store_key_hash_only", &self.store_key_hash_only)
+            .field("zstd_level", &self.zstd_level)
+            .field("max_concurrent_decompressions", &self.max_concurrent_decompressions)
+            .field("checksum_algorithm", &self.checksum_algorithm)
+            .field("key_prefix", &self.key_prefix.is_some())
+            .field("flush_error_policy", &self.flush_error_policy)
+            .field("admission_observer", &self.admission_observer)
+            .field("max_region_age", &self.max_region_age)
+            .field("channel_drop_policy", &self.channel_drop_policy)
+            .field("prefix_compressions", &self.prefix_compressions)
+            .field("io_bytes_in_flight_limit", &self.io_bytes_in_flight_limit)
+            .field("flusher_queue_capacity", &self.flusher_queue_capacity)
+            .field("queue_full_policy", &self.queue_full_policy)
+            .field("flush_alignment", &self.flush_alignment)
+            .field("adaptive_compression", &self.adaptive_compression)
+            .field("scan_read_ahead", &self.scan_read_ahead)
+            .field("verify_checksum_on_recover", &self.verify_checksum_on_recover)
+            .field("catalog_backend", &self.catalog_backend)
+            .field("catalog_retry_policy", &self.catalog_retry_policy)
             .finish()
     }
 }
@@ -146,6 +452,46 @@ where
             clean_region_threshold: self.clean_region_threshold,
             recover_concurrency: self.recover_concurrency,
             compression: self.compression,
+            checksum: self.checksum,
+            recent_inserts_capacity: self.recent_inserts_capacity,
+            max_key_size: self.max_key_size,
+            region_compression: self.region_compression,
+            intra_block_align: self.intra_block_align,
+            catalog_snapshot_path: self.catalog_snapshot_path.clone(),
+            reclaim_grace_period: self.reclaim_grace_period,
+            compression_observer: self.compression_observer.clone(),
+            unknown_region_version_policy: self.unknown_region_version_policy,
+            block_compression: self.block_compression,
+            background_cpu_cores: self.background_cpu_cores,
+            metrics_recorder: self.metrics_recorder.clone(),
+            write_conflict_policy: self.write_conflict_policy,
+            catalog_ops_limit: self.catalog_ops_limit,
+            first_entry_offset: self.first_entry_offset,
+            verify_region_magic_on_read: self.verify_region_magic_on_read,
+            self_test_on_open: self.self_test_on_open,
+            scrub_throughput_limit: self.scrub_throughput_limit,
+            write_coalescing_interval: self.write_coalescing_interval,
+            max_writing_regions: self.max_writing_regions,
+            size_padding: self.size_padding,
+            store_key_hash_only: self.store_key_hash_only,
+            zstd_level: self.zstd_level,
+            max_concurrent_decompressions: self.max_concurrent_decompressions,
+            checksum_algorithm: self.checksum_algorithm,
+            key_prefix: self.key_prefix.clone(),
+            flush_error_policy: self.flush_error_policy,
+            admission_observer: self.admission_observer.clone(),
+            max_region_age: self.max_region_age,
+            channel_drop_policy: self.channel_drop_policy,
+            prefix_compressions: self.prefix_compressions.clone(),
+            io_bytes_in_flight_limit: self.io_bytes_in_flight_limit,
+            flusher_queue_capacity: self.flusher_queue_capacity,
+            queue_full_policy: self.queue_full_policy,
+            flush_alignment: self.flush_alignment,
+            adaptive_compression: self.adaptive_compression,
+            scan_read_ahead: self.scan_read_ahead,
+            verify_checksum_on_recover: self.verify_checksum_on_recover,
+            catalog_backend: self.catalog_backend.clone(),
+            catalog_retry_policy: self.catalog_retry_policy,
         }
     }
 }
@@ -186,9 +532,10 @@ where
     device: D,
 
     admissions: Vec<Arc<dyn AdmissionPolicy<Key = K, Value = V>>>,
+    admission_observer: Option<Arc<dyn AdmissionObserver<Key = K>>>,
     reinsertions: Vec<Arc<dyn ReinsertionPolicy<Key = K, Value = V>>>,
 
-    flusher_entry_txs: Vec<mpsc::UnboundedSender<Entry<K, V>>>,
+    flusher_entry_txs: Vec<mpsc::UnboundedSender<FlusherMessage<K, V>>>,
     flusher_handles: Mutex<Vec<JoinHandle<()>>>,
     flushers_stop_tx: broadcast::Sender<()>,
 
@@ -199,6 +546,59 @@ where
 
     compression: Compression,
 
+    checksum: bool,
+
+    /// Ring buffer of the recent inserted keys and sequences, `None` if disabled.
+    #[allow(clippy::type_complexity)]
+    recent_inserts: Option<Mutex<VecDeque<(Arc<K>, Sequence)>>>,
+    recent_inserts_capacity: usize,
+
+    max_key_size: Option<usize>,
+
+    write_conflict_policy: WriteConflictPolicy,
+
+    catalog_ops_limiter: Option<RateLimiter>,
+
+    scrub_throughput_limiter: Option<RateLimiter>,
+
+    write_coalescing: bool,
+
+    key_prefix: Option<Arc<dyn KeyPrefix<K>>>,
+    prefix_compressions: HashMap<u64, Compression>,
+
+    flusher_queue: Arc<FlusherQueue>,
+
+    flush_alignment: Option<usize>,
+
+    scan_read_ahead: usize,
+
+    store_key_hash_only: bool,
+
+    /// Set when the store starts draining, the following inserts are rejected.
+    draining: Arc<AtomicBool>,
+    /// Cleared if a flusher finds its entry channel closed unexpectedly, see [`ChannelDropPolicy`].
+    healthy: Arc<AtomicBool>,
+    /// Inserts hold the read lock until the entry is sent to the flusher, so each entry is either rejected or drained.
+    drain_lock: RwLock<()>,
+
+    /// Last time the region header magic is verified of each region, `None` if the verification is disabled.
+    region_magic_verified_at: Option<Vec<Mutex<Option<Instant>>>>,
+
+    /// Cache of the recently decompressed blocks.
+    block_cache: BlockCache<K>,
+
+    /// Limits the concurrent decompressions on the get path, `None` if unlimited.
+    decompression_limiter: Option<Semaphore>,
+
+    /// The flushers and the reclaimers hold their own handles, kept here for tests to read the peak concurrency.
+    #[cfg(test)]
+    cpu_budget: Arc<CpuBudget>,
+
+    /// Limits the total bytes of the in-flight device I/Os, shared with the regions and the flush buffers.
+    io_limiter: IoBytesLimiter,
+
+    verify_checksum_on_recover: bool,
+
     _marker: PhantomData<V>,
 }
 
@@ -209,28 +609,59 @@ where
     D: Device,
 {
     async fn open(config: GenericStoreConfig<K, V, D>) -> Result<Self> {
-        let metrics = Arc::new(METRICS.foyer(&config.name));
+        let recorder = config
+            .metrics_recorder
+            .clone()
+            .unwrap_or_else(|| get_metrics_recorder().clone());
+        let metrics = Arc::new(Metrics::new(&recorder, &config.name));
 
         let device = D::open(config.device_config).await?;
+        if config.self_test_on_open {
+            Self::self_test(&device).await?;
+        }
         assert!(device.regions() >= config.flushers * 2);
+        if let Some(limit) = config.max_concurrent_decompressions {
+            assert!(limit > 0, "max concurrent decompressions must be > 0, given: {}", limit);
+        }
+
+        let io_limiter = IoBytesLimiter::new(config.io_bytes_in_flight_limit);
 
         let region_manager = Arc::new(RegionManager::new(
             device.regions(),
             config.eviction_config,
+            config.reclaim_grace_period,
+            config.max_writing_regions,
             device.clone(),
+            io_limiter.clone(),
+        ));
+
+        let catalog = Arc::new(Catalog::new(
+            device.regions(),
+            config.catalog_shards,
+            config.write_conflict_policy,
+            config.catalog_backend.clone(),
+            metrics.clone(),
         ));
 
-        let catalog = Arc::new(Catalog::new(device.regions(), config.catalog_shards, metrics.clone()));
+        let cpu_budget = Arc::new(CpuBudget::new(config.background_cpu_cores));
 
         let (flushers_stop_tx, _) = broadcast::channel(DEFAULT_BROADCAST_CAPACITY);
         let flusher_stop_rxs = (0..config.flushers).map(|_| flushers_stop_tx.subscribe()).collect_vec();
         // TODO(MrCroxx): use `expect` after `lint_reasons` is stable.
         #[allow(clippy::type_complexity)]
         let (flusher_entry_txs, flusher_entry_rxs): (
-            Vec<mpsc::UnboundedSender<Entry<K, V>>>,
-            Vec<mpsc::UnboundedReceiver<Entry<K, V>>>,
+            Vec<mpsc::UnboundedSender<FlusherMessage<K, V>>>,
+            Vec<mpsc::UnboundedReceiver<FlusherMessage<K, V>>>,
         ) = (0..config.flushers).map(|_| mpsc::unbounded_channel()).unzip();
 
+        let draining = Arc::new(AtomicBool::new(false));
+        let healthy = Arc::new(AtomicBool::new(true));
+        let flusher_queue = Arc::new(FlusherQueue::new(
+            config.flusher_queue_capacity,
+            config.queue_full_policy,
+            metrics.flusher_queue_depth.clone(),
+        ));
+
         let (reclaimers_stop_tx, _) = broadcast::channel(DEFAULT_BROADCAST_CAPACITY);
         let reclaimer_stop_rxs = (0..config.reclaimers)
             .map(|_| reclaimers_stop_tx.subscribe())
@@ -242,6 +673,7 @@ where
             region_manager: region_manager.clone(),
             device: device.clone(),
             admissions: config.admissions,
+            admission_observer: config.admission_observer.clone(),
             reinsertions: config.reinsertions,
             flusher_entry_txs,
             flusher_handles: Mutex::new(vec![]),
@@ -250,6 +682,40 @@ where
             reclaimers_stop_tx,
             metrics: metrics.clone(),
             compression: config.compression,
+            checksum: config.checksum,
+            recent_inserts: (config.recent_inserts_capacity > 0)
+                .then(|| Mutex::new(VecDeque::with_capacity(config.recent_inserts_capacity))),
+            recent_inserts_capacity: config.recent_inserts_capacity,
+            max_key_size: config.max_key_size,
+            write_conflict_policy: config.write_conflict_policy,
+            catalog_ops_limiter: config.catalog_ops_limit.map(RateLimiter::new),
+            scrub_throughput_limiter: config
+                .scrub_throughput_limit
+                .map(|limit| RateLimiter::new(limit as f64)),
+            write_coalescing: config.write_coalescing_interval.is_some(),
+            key_prefix: config.key_prefix.clone(),
+            prefix_compressions: config.prefix_compressions.clone(),
+            flusher_queue: flusher_queue.clone(),
+            flush_alignment: config.flush_alignment,
+            scan_read_ahead: config.scan_read_ahead,
+            store_key_hash_only: config.store_key_hash_only,
+            draining: draining.clone(),
+            healthy: healthy.clone(),
+            drain_lock: RwLock::new(()),
+            region_magic_verified_at: config
+                .verify_region_magic_on_read
+                .then(|| (0..device.regions()).map(|_| Mutex::new(None)).collect_vec()),
+            block_cache: BlockCache::new(
+                config
+                    .block_compression
+                    .map(|block_compression| block_compression.cache_capacity)
+                    .unwrap_or_default(),
+            ),
+            decompression_limiter: config.max_concurrent_decompressions.map(Semaphore::new),
+            #[cfg(test)]
+            cpu_budget: cpu_budget.clone(),
+            io_limiter: io_limiter.clone(),
+            verify_checksum_on_recover: config.verify_checksum_on_recover,
             _marker: PhantomData,
         };
         let store = Self { inner: Arc::new(inner) };
@@ -274,12 +740,40 @@ where
             .into_iter()
             .zip_eq(flusher_entry_rxs.into_iter())
             .map(|(stop_rx, entry_rx)| {
+                let buffer = FlushBuffer::new(
+                    device.clone(),
+                    config.checksum,
+                    config.region_compression,
+                    config.intra_block_align,
+                    config.compression_observer.clone(),
+                    config.block_compression,
+                    config.first_entry_offset,
+                    Some(CpuBudgetHandle::new(
+                        cpu_budget.clone(),
+                        metrics.background_cpu_seconds_flush.clone(),
+                    )),
+                    config.size_padding,
+                    config.store_key_hash_only,
+                    config.zstd_level,
+                    config.checksum_algorithm,
+                    config.flush_error_policy,
+                    io_limiter.clone(),
+                    config.flush_alignment,
+                    config.adaptive_compression,
+                );
                 Flusher::new(
                     region_manager.clone(),
                     catalog.clone(),
-                    device.clone(),
+                    config.catalog_retry_policy,
+                    buffer,
                     entry_rx,
                     metrics.clone(),
+                    draining.clone(),
+                    config.write_coalescing_interval,
+                    config.max_region_age,
+                    flusher_queue.clone(),
+                    config.channel_drop_policy,
+                    healthy.clone(),
                     stop_rx,
                 )
             })
@@ -293,12 +787,19 @@ where
                     store.clone(),
                     region_manager.clone(),
                     metrics.clone(),
+                    CpuBudgetHandle::new(cpu_budget.clone(), metrics.background_cpu_seconds_reclaim.clone()),
                     stop_rx,
                 )
             })
             .collect_vec();
 
-        let sequence = store.recover(config.recover_concurrency).await?;
+        let sequence = store
+            .recover(
+                config.recover_concurrency,
+                config.catalog_snapshot_path.as_deref(),
+                config.unknown_region_version_policy,
+            )
+            .await?;
         store.inner.sequence.store(sequence + 1, Ordering::Relaxed);
 
         let flusher_handles = flushers
@@ -344,6 +845,94 @@ where
         GenericStoreWriter::new(self.clone(), key)
     }
 
+    #[tracing::instrument(skip_all)]
+    async fn insert_batch(&self, entries: Vec<(Arc<K>, Arc<V>)>) -> Result<Vec<Option<CachedEntry<K, V>>>> {
+        let mut writers = entries
+            .into_iter()
+            .map(|(key, value)| {
+                let mut writer = self.writer(key);
+                let admitted = writer.judge();
+                (writer, value, admitted)
+            })
+            .collect_vec();
+
+        // Reject oversized keys before any entry of the batch is inserted.
+        if let Some(limit) = self.inner.max_key_size {
+            for (writer, _, _) in writers.iter().filter(|(_, _, admitted)| *admitted) {
+                let size = bincode::serialized_size(writer.key.as_ref().unwrap().as_ref()).map_err(BufferError::from)?
+                    as usize;
+                if size > limit {
+                    return Err(Error::KeyTooLarge { size, limit });
+                }
+            }
+        }
+
+        let now = Instant::now();
+
+        // Apply backpressure if the catalog mutations exceed the limit.
+        let admitted = writers.iter().filter(|(_, _, admitted)| *admitted).count();
+        if let Some(limiter) = self.inner.catalog_ops_limiter.as_ref() {
+            if admitted > 0 {
+                if let Some(wait) = limiter.consume(admitted as f64) {
+                    let _timer = self.inner.metrics.inner_op_duration_throttle_catalog.start_timer();
+                    tokio::time::sleep(wait).await;
+                }
+            }
+        }
+
+        // Apply backpressure if the entries queued to the flushers reach the capacity, the batch is dropped as a whole.
+        if admitted > 0 && !self.inner.flusher_queue.reserve(admitted).await? {
+            return Ok(writers.iter().map(|_| None).collect());
+        }
+
+        // Hold the lock until the entries are sent to the flushers, there is no await point after it.
+        let _guard = self.inner.drain_lock.read();
+        if self.inner.draining.load(Ordering::Acquire) {
+            self.inner.flusher_queue.release(admitted);
+            return Ok(writers.iter().map(|_| None).collect());
+        }
+
+        // Without write coalescing or key prefix grouping, the whole batch is sent to a single flusher.
+        let mut batches = (0..self.inner.flusher_entry_txs.len()).map(|_| vec![]).collect_vec();
+        let flusher = self.inner.sequence.load(Ordering::Relaxed) as usize % self.inner.flusher_entry_txs.len();
+
+        let res = writers
+            .iter_mut()
+            .map(|(writer, value, admitted)| {
+                if !*admitted {
+                    return None;
+                }
+                let entry = self.stage(writer, value.clone());
+                let res = CachedEntry::Shared {
+                    key: entry.key.clone(),
+                    value: entry.value.clone(),
+                };
+                let flusher = if self.inner.write_coalescing || self.inner.key_prefix.is_some() {
+                    self.flusher_of(&entry)
+                } else {
+                    flusher
+                };
+                batches[flusher].push(entry);
+
+                let duration = now.elapsed() + writer.duration;
+                self.inner
+                    .metrics
+                    .op_duration_insert_inserted
+                    .observe(duration.as_secs_f64());
+
+                Some(res)
+            })
+            .collect_vec();
+
+        for (flusher, batch) in batches.into_iter().enumerate().filter(|(_, batch)| !batch.is_empty()) {
+            self.inner.flusher_entry_txs[flusher]
+                .send(FlusherMessage::Batch(batch))
+                .unwrap();
+        }
+
+        Ok(res)
+    }
+
     #[tracing::instrument(skip_all)]
     fn exists<Q>(&self, key: &Q) -> Result<bool>
     where
@@ -354,15 +943,43 @@ where
     }
 
     #[tracing::instrument(skip_all)]
-    async fn get<Q>(&self, key: &Q) -> Result<Option<CachedEntry<K, V>>>
+    fn touch<Q>(&self, key: &Q) -> Result<bool>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let Some(item) = self.inner.catalog.get(key) else {
+            return Ok(false);
+        };
+        match item.index() {
+            // The entry is not written to any region yet.
+            Index::Inflight { .. } => {}
+            Index::Region { view } | Index::Block { view, .. } => self.inner.region_manager.record_access(view.id()),
+        }
+        Ok(true)
+    }
+
+    fn recent_inserts(&self, n: usize) -> Vec<(Arc<K>, Sequence)> {
+        match self.inner.recent_inserts.as_ref() {
+            Some(recent_inserts) => {
+                let recent_inserts = recent_inserts.lock();
+                let skip = recent_inserts.len().saturating_sub(n);
+                recent_inserts.iter().skip(skip).cloned().collect_vec()
+            }
+            None => vec![],
+        }
+    }
+
+    #[tracing::instrument(skip_all)]
+    pub(crate) async fn get<Q>(&self, key: &Q) -> Result<Option<CachedEntry<K, V>>>
     where
         K: Borrow<Q>,
         Q: Hash + Eq + ?Sized,
     {
         let now = Instant::now();
 
-        let (_sequence, index) = match self.inner.catalog.get(key) {
-            Some(item) => item.consume(),
+        let (indexed, (sequence, index)) = match self.inner.catalog.get_key_value(key) {
+            Some((indexed, item)) => (indexed, item.consume()),
             None => {
                 self.inner
                     .metrics
@@ -372,6 +989,19 @@ where
             }
         };
 
+        // Skip the device read if the key filter of the region reports that the key is never written to it.
+        if let Index::Region { view } | Index::Block { view, .. } = &index {
+            if !self.region_may_contain(*view.id(), &indexed)? {
+                // Remove index if the storage layer fails to get it (because the region doesn't hold the key).
+                self.inner.catalog.remove(key);
+                self.inner
+                    .metrics
+                    .op_duration_get_miss
+                    .observe(now.elapsed().as_secs_f64());
+                return Ok(None);
+            }
+        }
+
         match index {
             crate::catalog::Index::Inflight { key, value } => {
                 let value = value.clone();
@@ -387,6 +1017,7 @@ where
                 let region = view.id();
 
                 self.inner.region_manager.record_access(region);
+                self.verify_region_magic(*region).await?;
                 let region = self.inner.region_manager.region(region);
 
                 // TODO(MrCroxx): read value only
@@ -403,18 +1034,81 @@ where
                     }
                 };
 
-                let res = match read_entry::<K, V>(buf.as_ref()) {
-                    Ok((key, value)) => {
+                let permit = self.acquire_decompression(buf.as_ref()).await;
+                let res = read_entry::<K, V>(buf.as_ref(), self.inner.checksum);
+                drop(permit);
+
+                let res = match res {
+                    Ok((EntryKey::Key(key), value)) => {
                         self.inner.metrics.op_bytes_get.inc_by(buf.len() as u64);
                         Ok(Some(CachedEntry::Owned {
                             key: Box::new(key),
                             value: Box::new(value),
                         }))
                     }
+                    // Only the key hash is stored, resolve the full key from the catalog.
+                    Ok((EntryKey::Hash(hash), value)) => match encoded_key_hash(indexed.as_ref()) {
+                        Ok(expected) if expected == hash => {
+                            self.inner.metrics.op_bytes_get.inc_by(buf.len() as u64);
+                            Ok(Some(CachedEntry::Shared {
+                                key: indexed,
+                                value: Arc::new(value),
+                            }))
+                        }
+                        Ok(expected) => {
+                            // Remove index if the storage layer fails to get it (because of key hash mismatch).
+                            self.inner.catalog.remove(key);
+                            Err(Error::EntryDecode(Box::new(
+                                anyhow!("key hash mismatch, expected: {}, got: {}", expected, hash).into(),
+                            )))
+                        }
+                        Err(e) => Err(e),
+                    },
+                    Err(e) => {
+                        // Remove index if the storage layer fails to get it (because of entry magic mismatch).
+                        self.inner.catalog.remove(key);
+                        Err(Error::EntryDecode(Box::new(e)))
+                    }
+                };
+
+                self.inner
+                    .metrics
+                    .op_duration_get_hit
+                    .observe(now.elapsed().as_secs_f64());
+
+                res
+            }
+            crate::catalog::Index::Block { view, index } => {
+                let block = match self.load_block(view, index as usize, sequence).await {
+                    Ok(Some(block)) => block,
+                    Ok(None) => {
+                        // Remove index if the storage layer fails to get it (because of region version mismatch).
+                        self.inner.catalog.remove(key);
+                        self.inner
+                            .metrics
+                            .op_duration_get_miss
+                            .observe(now.elapsed().as_secs_f64());
+                        return Ok(None);
+                    }
                     Err(e) => {
                         // Remove index if the storage layer fails to get it (because of entry magic mismatch).
                         self.inner.catalog.remove(key);
-                        Err(e)
+                        return Err(e);
+                    }
+                };
+
+                let res = match block.value::<V>(index as usize) {
+                    Ok(value) => Ok(Some(CachedEntry::Shared {
+                        key: block
+                            .key(index as usize)
+                            .expect("sequence of the index has been checked")
+                            .clone(),
+                        value: Arc::new(value),
+                    })),
+                    Err(e) => {
+                        // Remove index if the storage layer fails to get it (because of value decode error).
+                        self.inner.catalog.remove(key);
+                        Err(Error::EntryDecode(Box::new(e)))
                     }
                 };
 
@@ -428,739 +1122,9698 @@ where
         }
     }
 
-    #[tracing::instrument(skip_all)]
-    fn remove<Q>(&self, key: &Q) -> Result<bool>
-    where
-        K: Borrow<Q>,
-        Q: Hash + Eq + ?Sized,
-    {
-        let _timer = self.inner.metrics.op_duration_remove.start_timer();
+    /// Return `false` only if the key filter of the region is built and the key is never written to the region.
+    fn region_may_contain(&self, region: RegionId, key: &K) -> Result<bool> {
+        match self.inner.region_manager.region(&region).filter() {
+            Some(filter) => Ok(filter.may_contain(encoded_key_hash(key)?)),
+            None => Ok(true),
+        }
+    }
 
-        let res = self.inner.catalog.remove(key).is_some();
+    /// Acquire the permit to decompress the entry in `buf`, waiting if the max concurrent decompressions is reached.
+    ///
+    /// Returns `None` if the decompressions are unlimited or the entry is not compressed.
+    async fn acquire_decompression(&self, buf: &[u8]) -> Option<SemaphorePermit<'_>> {
+        let limiter = self.inner.decompression_limiter.as_ref()?;
+        match EntryHeader::read(buf) {
+            Ok(header) if header.compression != Compression::None => {}
+            // Leave the invalid entries to the decoding.
+            _ => return None,
+        }
 
-        Ok(res)
-    }
+        if let Ok(permit) = limiter.try_acquire() {
+            return Some(permit);
+        }
 
-    #[tracing::instrument(skip(self))]
-    fn clear(&self) -> Result<()> {
-        self.inner.catalog.clear();
+        /// Decrease the queue depth on drop, in case the get is cancelled while waiting.
+        struct Queued<'a>(&'a Gauge);
 
-        // TODO(MrCroxx): set all regions as clean?
+        impl Drop for Queued<'_> {
+            fn drop(&mut self) {
+                self.0.dec();
+            }
+        }
 
-        Ok(())
+        self.inner.metrics.decompression_queue_depth.inc();
+        let _queued = Queued(&self.inner.metrics.decompression_queue_depth);
+        limiter.acquire().await.ok()
     }
 
-    pub(crate) fn catalog(&self) -> &Arc<Catalog<K, V>> {
-        &self.inner.catalog
-    }
+    /// Verify the region header magic before trusting the offsets of the region, if enabled.
+    ///
+    /// The result is cached, and the magic is re-verified after [`REGION_MAGIC_VERIFY_INTERVAL`].
+    async fn verify_region_magic(&self, region: RegionId) -> Result<()> {
+        let Some(verified_at) = self.inner.region_magic_verified_at.as_ref() else {
+            return Ok(());
+        };
+        let verified_at = &verified_at[region as usize];
+        if verified_at
+            .lock()
+            .is_some_and(|at| at.elapsed() < REGION_MAGIC_VERIFY_INTERVAL)
+        {
+            return Ok(());
+        }
 
-    pub(crate) fn reinsertions(&self) -> &Vec<Arc<dyn ReinsertionPolicy<Key = K, Value = V>>> {
-        &self.inner.reinsertions
+        let align = self.inner.device.align();
+        let verified = match self.inner.region_manager.region(&region).load_range(0..align).await? {
+            Some(slice) => RegionHeader::verify_magic(slice.as_ref()),
+            None => false,
+        };
+        if !verified {
+            return Err(Error::RegionClobbered { region });
+        }
+
+        *verified_at.lock() = Some(Instant::now());
+        Ok(())
     }
 
-    #[tracing::instrument(skip(self))]
-    async fn recover(&self, concurrency: usize) -> Result<Sequence> {
-        tracing::info!("start store recovery");
+    /// Load and decompress the block of the view, or get it from the block cache.
+    ///
+    /// Returns `None` if the storage layer fails to load it (because of region version mismatch).
+    async fn load_block(
+        &self,
+        view: RegionView,
+        index: usize,
+        sequence: Sequence,
+    ) -> Result<Option<Arc<DecodedBlock<K>>>> {
+        let region = *view.id();
+        let offset = *view.offset();
 
-        let semaphore = Arc::new(Semaphore::new(concurrency));
+        self.inner.region_manager.record_access(&region);
 
-        let mut handles = vec![];
-        for region_id in 0..self.inner.device.regions() as RegionId {
-            let semaphore = semaphore.clone();
-            let region_manager = self.inner.region_manager.clone();
-            let indices = self.inner.catalog.clone();
-            let handle = tokio::spawn(async move {
-                let permit = semaphore.acquire().await;
-                let res = Self::recover_region(region_id, region_manager, indices).await;
-                drop(permit);
-                res
-            });
-            handles.push(handle);
+        if let Some(block) = self.inner.block_cache.get(region, offset, index, sequence) {
+            return Ok(Some(block));
         }
 
-        let mut recovered = 0;
-        let mut sequence = 0;
+        self.verify_region_magic(region).await?;
+        let Some(buf) = self.inner.region_manager.region(&region).load(view).await? else {
+            return Ok(None);
+        };
+        let permit = self.acquire_decompression(buf.as_ref()).await;
+        let block = Arc::new(DecodedBlock::decode(buf.as_ref(), self.inner.checksum)?);
+        drop(permit);
+        self.inner.metrics.op_bytes_get.inc_by(buf.len() as u64);
+
+        if block.sequence(index) != Some(sequence) {
+            return Err(anyhow!(
+                "block entry sequence mismatch, expected: {}, got: {:?}",
+                sequence,
+                block.sequence(index)
+            )
+            .into());
+        }
+        self.inner.block_cache.insert(region, offset, block.clone());
 
-        let results = try_join_all(handles).await.map_err(anyhow::Error::from)?;
+        Ok(Some(block))
+    }
 
-        for (region_id, result) in results.into_iter().enumerate() {
-            if let Some(seq) = result? {
-                tracing::debug!("region {} is recovered", region_id);
-                recovered += 1;
-                sequence = std::cmp::max(sequence, seq);
+    #[tracing::instrument(skip_all)]
+    async fn get_stream<Q, W>(&self, key: &Q, mut writer: W) -> Result<bool>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+        W: AsyncWrite + Send + Unpin,
+    {
+        let now = Instant::now();
+
+        let (sequence, index) = match self.inner.catalog.get(key) {
+            Some(item) => item.consume(),
+            None => {
+                self.inner
+                    .metrics
+                    .op_duration_get_miss
+                    .observe(now.elapsed().as_secs_f64());
+                return Ok(false);
+            }
+        };
+
+        match index {
+            crate::catalog::Index::Inflight { key: _, value } => {
+                stream_serialized(value, &mut writer).await?;
+            }
+            crate::catalog::Index::Region { view } => {
+                let region = view.id();
+
+                self.inner.region_manager.record_access(region);
+                let region = self.inner.region_manager.region(region);
+
+                match self.stream_region_value(region, &view, &mut writer).await {
+                    Ok(true) => {}
+                    Ok(false) => {
+                        // Remove index if the storage layer fails to get it (because of region version mismatch).
+                        self.inner.catalog.remove(key);
+                        self.inner
+                            .metrics
+                            .op_duration_get_miss
+                            .observe(now.elapsed().as_secs_f64());
+                        return Ok(false);
+                    }
+                    Err(e) => {
+                        // Remove index if the storage layer fails to get it (because of entry magic mismatch).
+                        self.inner.catalog.remove(key);
+                        return Err(e);
+                    }
+                }
+            }
+            crate::catalog::Index::Block { view, index } => {
+                let block = match self.load_block(view, index as usize, sequence).await {
+                    Ok(Some(block)) => block,
+                    Ok(None) => {
+                        // Remove index if the storage layer fails to get it (because of region version mismatch).
+                        self.inner.catalog.remove(key);
+                        self.inner
+                            .metrics
+                            .op_duration_get_miss
+                            .observe(now.elapsed().as_secs_f64());
+                        return Ok(false);
+                    }
+                    Err(e) => {
+                        // Remove index if the storage layer fails to get it (because of entry magic mismatch).
+                        self.inner.catalog.remove(key);
+                        return Err(e);
+                    }
+                };
+
+                let value = block
+                    .value_bytes(index as usize)
+                    .expect("sequence of the index has been checked");
+                copy_chunked(value, &mut writer).await?;
             }
         }
 
-        tracing::info!("finish store recovery, {} region recovered", recovered);
+        writer.flush().await.map_err(BufferError::from)?;
+
         self.inner
             .metrics
-            .total_bytes
-            .set((recovered * self.inner.device.region_size()) as u64);
+            .op_duration_get_hit
+            .observe(now.elapsed().as_secs_f64());
 
-        // Force trigger reclamation.
-        if recovered == self.inner.device.regions() {
-            self.inner.region_manager.clean_regions().flash();
+        Ok(true)
+    }
+
+    /// Stream the value of the entry of `view` into `writer`, reading the device in windows of at most
+    /// [`STREAM_CHUNK_SIZE`] instead of loading the whole entry.
+    ///
+    /// The checksum is verified with a pass over the entry before anything is written. A compressed value is collected
+    /// before it is decompressed chunk by chunk, for the decoders pull from a contiguous buffer.
+    ///
+    /// Returns `false` if the region is reclaimed before anything is written.
+    async fn stream_region_value<W>(&self, region: &Region<D>, view: &RegionView, writer: &mut W) -> Result<bool>
+    where
+        W: AsyncWrite + Send + Unpin,
+    {
+        let align = self.inner.device.align();
+
+        let header_len = std::cmp::min(EntryHeader::serialized_len_with_expire_at() as u32, *view.len());
+        let Some(header_buf) = region.load(view.slice(0..header_len)).await? else {
+            return Ok(false);
+        };
+        let header = EntryHeader::read(header_buf.as_ref())?;
+        self.inner.metrics.op_bytes_get.inc_by(header_len as u64);
+
+        let start = header.encoded_len() as u32;
+        let value_end = start + header.value_len;
+        let end = value_end + header.key_len;
+        if end > *view.len() {
+            return Err(anyhow!("entry len {} exceeds the view len {}", end, view.len()).into());
         }
 
-        Ok(sequence)
-    }
+        // Verify checksum before writing anything, for the bytes written cannot be taken back.
+        if self.inner.checksum && header.checksummed {
+            let mut checksummer = Checksummer::new(header.checksum_algorithm);
+            for window in stream_windows(view, start..end, align) {
+                let Some(buf) = region.load(view.slice(window)).await? else {
+                    return Ok(false);
+                };
+                self.inner.metrics.op_bytes_get.inc_by(buf.len() as u64);
+                checksummer.update(buf.as_ref());
+            }
+            let checksum = checksummer.finish();
+            if checksum != header.checksum {
+                return Err(anyhow!("checksum mismatch, expected: {}, got: {}", header.checksum, checksum).into());
+            }
+        }
 
-    /// Return `Some(max sequence)` if region is valid, otherwise `None`
-    async fn recover_region(
-        region_id: RegionId,
-        region_manager: Arc<RegionManager<D>>,
-        catalog: Arc<Catalog<K, V>>,
-    ) -> Result<Option<Sequence>> {
-        let region = region_manager.region(&region_id).clone();
-        let mut sequence = 0;
-        let res = if let Some(mut iter) = RegionEntryIter::<K, V, D>::open(region).await? {
-            while let Some((key, item)) = iter.next().await? {
-                sequence = std::cmp::max(sequence, *item.sequence());
-                catalog.insert(Arc::new(key), item);
+        if header.compression == Compression::None {
+            for (i, window) in stream_windows(view, start..value_end, align).enumerate() {
+                let buf = match region.load(view.slice(window)).await? {
+                    Some(buf) => buf,
+                    None if i == 0 => return Ok(false),
+                    None => return Err(anyhow!("region {} is reclaimed while streaming", view.id()).into()),
+                };
+                self.inner.metrics.op_bytes_get.inc_by(buf.len() as u64);
+                writer.write_all(buf.as_ref()).await.map_err(BufferError::from)?;
             }
-            region_manager.eviction_push(region_id);
-            Some(sequence)
-        } else {
-            region_manager.clean_regions().release(region_id);
-            None
+            return Ok(true);
+        }
+
+        // Feed the windows to the decoder one by one, so at most a few windows of the value are held in memory.
+        let _permit = self.acquire_decompression(header_buf.as_ref()).await;
+        let (tx, rx) = mpsc::channel(1);
+        let feed = async move {
+            for window in stream_windows(view, start..value_end, align) {
+                let Some(buf) = region.load(view.slice(window)).await? else {
+                    return Err(anyhow!("region {} is reclaimed while streaming", view.id()).into());
+                };
+                self.inner.metrics.op_bytes_get.inc_by(buf.len() as u64);
+                // The decoder stops early on error, which is returned by the decoding.
+                if tx.send(buf).await.is_err() {
+                    break;
+                }
+            }
+            Ok::<_, Error>(())
         };
-        Ok(res)
-    }
+        let (fed, decoded) = join(feed, decode_stream(header.compression, rx, writer)).await;
+        fed?;
+        decoded?;
 
-    fn judge_inner(&self, writer: &mut GenericStoreWriter<K, V, D>) {
-        for (index, admission) in self.inner.admissions.iter().enumerate() {
-            let judge = admission.judge(writer.key.as_ref().unwrap());
-            writer.judges.set(index, judge);
-        }
-        writer.is_judged = true;
+        Ok(true)
     }
 
     #[tracing::instrument(skip_all)]
-    async fn apply_writer(
-        &self,
-        mut writer: GenericStoreWriter<K, V, D>,
-        value: impl Into<Arc<V>>,
-    ) -> Result<Option<CachedEntry<K, V>>> {
-        debug_assert!(!writer.is_inserted);
-
-        if !writer.judge() {
-            return Ok(None);
-        }
-
+    async fn get_range<Q>(&self, key: &Q, range: Range<usize>) -> Result<Option<Vec<u8>>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
         let now = Instant::now();
 
-        let sequence = if let Some(sequence) = writer.sequence {
-            sequence
-        } else {
-            self.inner.sequence.fetch_add(1, Ordering::Relaxed)
+        let (sequence, index) = match self.inner.catalog.get(key) {
+            Some(item) => item.consume(),
+            None => {
+                self.inner
+                    .metrics
+                    .op_duration_get_miss
+                    .observe(now.elapsed().as_secs_f64());
+                return Ok(None);
+            }
         };
 
-        writer.is_inserted = true;
-        let key = writer.key.take().unwrap();
-        let value = value.into();
+        let bytes = match index {
+            crate::catalog::Index::Inflight { key: _, value } => {
+                let buf = bincode::serialize(value.as_ref()).map_err(BufferError::from)?;
+                buf[clamp_range(range, buf.len())].to_vec()
+            }
+            crate::catalog::Index::Region { view } => {
+                let region = view.id();
 
-        // TODO(MrCroxx): FIX ME!!!
-        // record aligned header + key + value size for metrics
-        // let len = bits::align_up(
-        //     self.inner.device.align(),
-        //     EntryHeader::serialized_len() + key.serialized_len() + value.serialized_len(),
-        // );
-        // self.inner.metrics.op_bytes_insert.inc_by(len as u64);
-        // self.inner.metrics.insert_entry_bytes.observe(len as f64);
+                self.inner.region_manager.record_access(region);
+                let region = self.inner.region_manager.region(region);
 
-        self.inner.catalog.insert(
-            key.clone(),
-            Item::new(
-                sequence,
-                Index::Inflight {
-                    key: key.clone(),
-                    value: value.clone(),
-                },
-            ),
-        );
+                // Read the header first to learn the compression and the len of the value.
+                let header_len = std::cmp::min(EntryHeader::serialized_len_with_expire_at() as u32, *view.len());
+                let header = match region.load(view.slice(0..header_len)).await? {
+                    Some(buf) => EntryHeader::read(buf.as_ref()).map(Some),
+                    None => Ok(None),
+                };
+                let header = match header {
+                    Ok(Some(header)) => header,
+                    Ok(None) => {
+                        // Remove index if the storage layer fails to get it (because of region version mismatch).
+                        self.inner.catalog.remove(key);
+                        self.inner
+                            .metrics
+                            .op_duration_get_miss
+                            .observe(now.elapsed().as_secs_f64());
+                        return Ok(None);
+                    }
+                    Err(e) => {
+                        // Remove index if the storage layer fails to get it (because of entry magic mismatch).
+                        self.inner.catalog.remove(key);
+                        return Err(e);
+                    }
+                };
+                self.inner.metrics.op_bytes_get.inc_by(header_len as u64);
+                let header_len = header.encoded_len();
+
+                match header.compression {
+                    // The value is stored as it is, read only the part covering the range.
+                    Compression::None => {
+                        let range = clamp_range(range, header.value_len as usize);
+                        if range.is_empty() {
+                            vec![]
+                        } else {
+                            let start = (header_len + range.start) as u32;
+                            let end = (header_len + range.end) as u32;
+                            match region.load(view.slice(start..end)).await? {
+                                Some(buf) => {
+                                    self.inner.metrics.op_bytes_get.inc_by(buf.len() as u64);
+                                    buf.to_vec()
+                                }
+                                None => {
+                                    // Remove index if the storage layer fails to get it (region version mismatch).
+                                    self.inner.catalog.remove(key);
+                                    self.inner
+                                        .metrics
+                                        .op_duration_get_miss
+                                        .observe(now.elapsed().as_secs_f64());
+                                    return Ok(None);
+                                }
+                            }
+                        }
+                    }
+                    // The value is compressed, read and decompress the whole value.
+                    _ => {
+                        let buf = match region.load(view).await? {
+                            Some(buf) => buf,
+                            None => {
+                                // Remove index if the storage layer fails to get it (region version mismatch).
+                                self.inner.catalog.remove(key);
+                                self.inner
+                                    .metrics
+                                    .op_duration_get_miss
+                                    .observe(now.elapsed().as_secs_f64());
+                                return Ok(None);
+                            }
+                        };
+
+                        let mut value = vec![];
+                        let permit = self.acquire_decompression(buf.as_ref()).await;
+                        let res = stream_entry_value(buf.as_ref(), &mut value, self.inner.checksum).await;
+                        drop(permit);
+                        if let Err(e) = res {
+                            // Remove index if the storage layer fails to get it (because of entry magic mismatch).
+                            self.inner.catalog.remove(key);
+                            return Err(e);
+                        }
+                        self.inner.metrics.op_bytes_get.inc_by(buf.len() as u64);
+                        let range = clamp_range(range, value.len());
+                        value.truncate(range.end);
+                        value.drain(..range.start);
+                        value
+                    }
+                }
+            }
+            crate::catalog::Index::Block { view, index } => {
+                let block = match self.load_block(view, index as usize, sequence).await {
+                    Ok(Some(block)) => block,
+                    Ok(None) => {
+                        // Remove index if the storage layer fails to get it (because of region version mismatch).
+                        self.inner.catalog.remove(key);
+                        self.inner
+                            .metrics
+                            .op_duration_get_miss
+                            .observe(now.elapsed().as_secs_f64());
+                        return Ok(None);
+                    }
+                    Err(e) => {
+                        // Remove index if the storage layer fails to get it (because of entry magic mismatch).
+                        self.inner.catalog.remove(key);
+                        return Err(e);
+                    }
+                };
 
-        let flusher = sequence as usize % self.inner.flusher_entry_txs.len();
-        self.inner.flusher_entry_txs[flusher]
-            .send(Entry {
-                sequence,
-                key: key.clone(),
-                value: value.clone(),
-                compression: writer.compression,
-            })
-            .unwrap();
+                let value = block
+                    .value_bytes(index as usize)
+                    .expect("sequence of the index has been checked");
+                value[clamp_range(range, value.len())].to_vec()
+            }
+        };
 
-        let duration = now.elapsed() + writer.duration;
         self.inner
             .metrics
-            .op_duration_insert_inserted
-            .observe(duration.as_secs_f64());
+            .op_duration_get_hit
+            .observe(now.elapsed().as_secs_f64());
 
-        Ok(Some(CachedEntry::Shared { key, value }))
+        Ok(Some(bytes))
     }
-}
 
-pub struct GenericStoreWriter<K, V, D>
-where
-    K: StorageKey,
-    V: StorageValue,
-    D: Device,
-{
-    store: GenericStore<K, V, D>,
-    /// `key` is always `Some` before `apply_writer`.
-    key: Option<Arc<K>>,
+    #[tracing::instrument(skip_all)]
+    fn remove<Q>(&self, key: &Q) -> Result<bool>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let _timer = self.inner.metrics.op_duration_remove.start_timer();
 
-    sequence: Option<Sequence>,
+        let res = self.inner.catalog.remove(key).is_some();
 
-    judges: Judges,
-    is_judged: bool,
+        Ok(res)
+    }
 
-    /// judge duration
-    duration: Duration,
+    #[tracing::instrument(skip(self))]
+    fn clear(&self) -> Result<()> {
+        self.inner.catalog.clear();
 
-    is_inserted: bool,
-    is_skippable: bool,
-    compression: Compression,
-}
+        // TODO(MrCroxx): set all regions as clean?
 
-impl<K, V, D> GenericStoreWriter<K, V, D>
-where
-    K: StorageKey,
-    V: StorageValue,
-    D: Device,
-{
-    fn new(store: GenericStore<K, V, D>, key: impl Into<Arc<K>>) -> Self {
-        let judges = Judges::new(store.inner.admissions.len());
-        let compression = store.inner.compression;
-        Self {
-            store,
-            key: Some(key.into()),
-            sequence: None,
-            judges,
-            is_judged: false,
-            duration: Duration::from_nanos(0),
-            is_inserted: false,
-            is_skippable: false,
-            compression,
-        }
+        Ok(())
     }
 
-    /// Judge if the entry can be admitted by configured admission policies.
-    pub fn judge(&mut self) -> bool {
-        let store = self.store.clone();
-        if !self.is_judged {
-            let now = Instant::now();
-            store.judge_inner(self);
-            self.duration = now.elapsed();
-        }
-        self.judges.judge()
+    pub(crate) fn catalog(&self) -> &Arc<Catalog<K, V>> {
+        &self.inner.catalog
     }
 
-    pub async fn finish(self, value: impl Into<Arc<V>>) -> Result<Option<CachedEntry<K, V>>> {
+    #[cfg(test)]
+    pub(crate) fn region_manager(&self) -> &Arc<RegionManager<D>> {
+        &self.inner.region_manager
+    }
+
+    pub(crate) fn device(&self) -> &D {
+        &self.inner.device
+    }
+
+    pub(crate) fn reinsertions(&self) -> &Vec<Arc<dyn ReinsertionPolicy<Key = K, Value = V>>> {
+        &self.inner.reinsertions
+    }
+
+    /// Whether the store is healthy, which is `false` if the entry channel of a flusher is closed unexpectedly, see
+    /// [`ChannelDropPolicy::Unhealthy`].
+    pub fn is_healthy(&self) -> bool {
+        self.inner.healthy.load(Ordering::Acquire)
+    }
+
+    /// Metrics of the store, which can be registered to a Prometheus registry with `Metrics::register`.
+    pub fn metrics(&self) -> &Arc<Metrics> {
+        &self.inner.metrics
+    }
+
+    /// Current generation of the region, incremented each time the region is reclaimed.
+    ///
+    /// A reference to the region taken at an older generation points at recycled storage.
+    pub fn region_generation(&self, region: RegionId) -> u64 {
+        self.inner.region_manager.region(&region).generation()
+    }
+
+    /// Count of the entries sent to the flushers but not received yet.
+    pub fn channel_len(&self) -> usize {
+        self.inner.flusher_queue.len()
+    }
+
+    /// Capacity of the entries queued to the flushers, `None` if unbounded.
+    pub fn channel_capacity(&self) -> Option<usize> {
+        self.inner.flusher_queue.capacity()
+    }
+
+    pub(crate) fn is_checksum_enabled(&self) -> bool {
+        self.inner.checksum
+    }
+
+    #[allow(clippy::type_complexity)]
+    pub(crate) fn entries_since(&self, sequence: Sequence) -> BoxStream<'static, Result<(Arc<K>, Arc<V>, Sequence)>> {
+        let keys = self.inner.catalog.keys_since(sequence);
+        let store = self.clone();
+        futures::stream::iter(keys)
+            .then(move |(key, sequence)| {
+                let store = store.clone();
+                async move {
+                    // Skip the entry if it has been overwritten or removed since the keys are collected.
+                    match store.inner.catalog.get(key.as_ref()) {
+                        Some(item) if *item.sequence() == sequence => {}
+                        _ => return Ok(None),
+                    }
+                    let entry = store.get(key.as_ref()).await?;
+                    Ok(entry.map(|entry| {
+                        let (key, value) = entry.to_arc();
+                        (key, value, sequence)
+                    }))
+                }
+            })
+            .filter_map(|res| async move { res.transpose() })
+            .boxed()
+    }
+
+    /// Up to `scan_read_ahead` regions are read ahead while the current region is parsed, each into a buffer of the
+    /// region size taken from a pool that is refilled by the parsed regions, so the memory held by the scan is bounded
+    /// by `scan_read_ahead + 1` regions.
+    pub(crate) fn scan(&self) -> BoxStream<'static, Result<(K, V)>> {
+        let store = self.clone();
+        let regions = self.inner.device.regions() as RegionId;
+        let depth = self.inner.scan_read_ahead + 1;
+        futures::stream::unfold(
+            (0, VecDeque::new(), vec![]),
+            move |(mut next, mut reads, mut pool): (RegionId, VecDeque<_>, Vec<_>)| {
+                let store = store.clone();
+                async move {
+                    // Keep the read of the current region and the following ones in flight.
+                    while reads.len() < depth && next < regions {
+                        let store = store.clone();
+                        let buf = pool.pop();
+                        let read = tokio::spawn(async move { store.read_region(next, buf).await });
+                        reads.push_back((next, read));
+                        next += 1;
+                    }
+                    let (region_id, read) = reads.pop_front()?;
+                    // The error of a region read ahead is yielded when the region is consumed.
+                    let (entries, buf) = match read.await {
+                        Ok((Ok(()), buf)) => store.scan_region(region_id, buf).await,
+                        Ok((Err(e), buf)) => (vec![Err(e)], Some(buf)),
+                        Err(e) => (vec![Err(anyhow::Error::from(e).into())], None),
+                    };
+                    pool.extend(buf);
+                    Some((futures::stream::iter(entries), (next, reads, pool)))
+                }
+            },
+        )
+        .flatten()
+        .boxed()
+    }
+
+    /// Read the whole region into the buffer, or into a new buffer of the region size if not given.
+    ///
+    /// The buffer is returned even if the read fails, so it can be reused.
+    async fn read_region(
+        &self,
+        region_id: RegionId,
+        buf: Option<VecA<u8, D::IoBufferAllocator>>,
+    ) -> (Result<()>, VecA<u8, D::IoBufferAllocator>) {
+        let device = &self.inner.device;
+        let region_size = device.region_size();
+
+        let mut buf = buf.unwrap_or_else(|| device.io_buffer(region_size, region_size));
+        unsafe { buf.set_len(region_size) };
+        let permit = self.inner.io_limiter.acquire(region_size).await;
+        let (res, mut buf) = device.read(buf, 0..region_size, region_id, 0).await;
+        drop(permit);
+        // The read is short if the tail of the region has never been written.
+        match res {
+            Ok(bytes) => {
+                unsafe { buf.set_len(bytes) };
+                (Ok(()), buf)
+            }
+            Err(e) => (Err(e.into()), buf),
+        }
+    }
+
+    /// Decode the entries of the region read by [`Self::read_region`] that are still indexed by the catalog.
+    ///
+    /// Returns the buffer for the next region if it is not referenced anymore.
+    #[allow(clippy::type_complexity)]
+    async fn scan_region(
+        &self,
+        region_id: RegionId,
+        buf: VecA<u8, D::IoBufferAllocator>,
+    ) -> (Vec<Result<(K, V)>>, Option<VecA<u8, D::IoBufferAllocator>>) {
+        let data = Arc::new(buf);
+        let region = self.inner.region_manager.region(&region_id).clone();
+        let Some(mut iter) = RegionEntryIter::<K, V, D>::open_loaded(region, self.inner.checksum, data.clone()) else {
+            return (vec![], Arc::try_unwrap(data).ok());
+        };
+
+        let mut entries = vec![];
+        // Offset and the decoded last block, shared by all its entries.
+        let mut block: Option<(u32, Result<DecodedBlock<K>>)> = None;
+
+        loop {
+            let (key, item) = match iter.next().await {
+                Ok(Some(res)) => res,
+                Ok(None) => break,
+                Err(e) => {
+                    entries.push(Err(e));
+                    break;
+                }
+            };
+
+            // Skip the entry if it has been overwritten or removed.
+            match self.inner.catalog.get(&key) {
+                Some(indexed) if indexed.sequence() == item.sequence() => {}
+                _ => continue,
+            }
+
+            match item.index() {
+                Index::Region { view } => {
+                    let slice = &data[*view.offset() as usize..(*view.offset() + *view.len()) as usize];
+                    match read_entry::<K, V>(slice, self.inner.checksum) {
+                        Ok((EntryKey::Key(key), value)) => entries.push(Ok((key, value))),
+                        // The full key can't be rebuilt from the key hash.
+                        Ok((EntryKey::Hash(_), _)) => {}
+                        Err(e) => entries.push(Err(e)),
+                    }
+                }
+                Index::Block { view, index } => {
+                    if block.as_ref().map(|(offset, _)| *offset) != Some(*view.offset()) {
+                        let slice = &data[*view.offset() as usize..(*view.offset() + *view.len()) as usize];
+                        block = Some((*view.offset(), DecodedBlock::<K>::decode(slice, self.inner.checksum)));
+                    }
+                    match block.as_ref().unwrap() {
+                        (_, Ok(block)) => entries.push(block.value::<V>(*index as usize).map(|value| (key, value))),
+                        (_, Err(e)) => entries.push(Err(anyhow!("corrupted block: {e}").into())),
+                    }
+                }
+                Index::Inflight { .. } => unreachable!("entry loaded from region must have index of region"),
+            }
+        }
+        drop(iter);
+
+        (entries, Arc::try_unwrap(data).ok())
+    }
+
+    /// The barrier is placed on all flushers when called.
+    fn barrier(&self) -> BoxFuture<'static, Result<()>> {
+        let (flushed_rxs, synced_txs): (Vec<_>, Vec<_>) = self
+            .inner
+            .flusher_entry_txs
+            .iter()
+            .filter_map(|tx| {
+                let (flushed_tx, flushed_rx) = oneshot::channel();
+                let (synced_tx, synced_rx) = oneshot::channel();
+                // The flusher has exited and flushed its buffer if the channel is closed.
+                tx.send(FlusherMessage::Barrier(Barrier { flushed_tx, synced_rx }))
+                    .ok()
+                    .map(|_| (flushed_rx, synced_tx))
+            })
+            .unzip();
+        let device = self.inner.device.clone();
+
+        async move {
+            for flushed_rx in flushed_rxs {
+                flushed_rx
+                    .await
+                    .map_err(|_| anyhow!("flusher exited before the barrier is flushed"))?;
+            }
+            device.flush().await?;
+            for synced_tx in synced_txs {
+                let _ = synced_tx.send(());
+            }
+            Ok(())
+        }
+        .boxed()
+    }
+
+    /// The store starts draining when called.
+    fn drain(&self) -> BoxFuture<'static, Result<Vec<DrainedEntry<K>>>> {
+        let drained_rxs = {
+            let _guard = self.inner.drain_lock.write();
+            self.inner.draining.store(true, Ordering::Release);
+            self.inner
+                .flusher_entry_txs
+                .iter()
+                .filter_map(|tx| {
+                    let (drained_tx, drained_rx) = oneshot::channel();
+                    // The flusher has exited and flushed its buffer if the channel is closed.
+                    tx.send(FlusherMessage::Drain(drained_tx)).ok().map(|_| drained_rx)
+                })
+                .collect_vec()
+        };
+        let device = self.inner.device.clone();
+
+        async move {
+            let mut drained = vec![];
+            for drained_rx in drained_rxs {
+                drained.extend(
+                    drained_rx
+                        .await
+                        .map_err(|_| anyhow!("flusher exited before the pending entries are drained"))?,
+                );
+            }
+            device.flush().await?;
+            drained.sort_by_key(|(_, sequence, _, _)| *sequence);
+            Ok(drained)
+        }
+        .boxed()
+    }
+
+    /// Only the entries still indexed by the catalog are verified, the stale entries left in the regions are skipped.
+    fn scrub(&self, remove_corrupted: bool) -> BoxFuture<'static, Result<ScrubReport<K>>> {
+        let store = self.clone();
+        async move { store.scrub_inner(remove_corrupted).await }.boxed()
+    }
+
+    async fn scrub_inner(&self, remove_corrupted: bool) -> Result<ScrubReport<K>> {
+        if !self.inner.checksum {
+            return Err(anyhow!("scrub requires the entry checksum to be enabled").into());
+        }
+
+        let mut report = ScrubReport::default();
+
+        for region_id in 0..self.inner.device.regions() as RegionId {
+            let region = self.inner.region_manager.region(&region_id).clone();
+            let Some(mut iter) = RegionEntryIter::<K, V, D>::open(region.clone(), true).await? else {
+                continue;
+            };
+            report.regions += 1;
+
+            // Offset and the verification result of the last verified block, shared by all its entries.
+            let mut block: Option<(u32, bool)> = None;
+
+            while let Some((key, item)) = iter.next().await? {
+                // Skip the entry if it has been overwritten or removed, e.g. the region is reclaimed and rewritten.
+                match self.inner.catalog.get(&key) {
+                    Some(indexed) if indexed.sequence() == item.sequence() => {}
+                    _ => continue,
+                }
+
+                let (view, verified) = match item.index() {
+                    Index::Region { view } => {
+                        self.throttle_scrub(*view.len() as usize).await;
+                        let Some(buf) = region.load(view.clone()).await? else {
+                            continue;
+                        };
+                        (view, read_entry::<K, V>(buf.as_ref(), true).is_ok())
+                    }
+                    Index::Block { view, .. } => {
+                        if block.map(|(offset, _)| offset) != Some(*view.offset()) {
+                            self.throttle_scrub(*view.len() as usize).await;
+                            let Some(buf) = region.load(view.clone()).await? else {
+                                continue;
+                            };
+                            block = Some((*view.offset(), DecodedBlock::<K>::decode(buf.as_ref(), true).is_ok()));
+                        }
+                        (view, block.unwrap().1)
+                    }
+                    Index::Inflight { .. } => unreachable!("entry loaded from region must have index of region"),
+                };
+                report.entries += 1;
+
+                if verified {
+                    continue;
+                }
+                if remove_corrupted
+                    && self
+                        .inner
+                        .catalog
+                        .remove_with_sequence(&key, *item.sequence())
+                        .is_none()
+                {
+                    // Overwritten or removed after verified.
+                    continue;
+                }
+                report
+                    .corrupted
+                    .push((Arc::new(key), *item.sequence(), region_id, *view.offset() as usize));
+            }
+        }
+
+        Ok(report)
+    }
+
+    async fn throttle_scrub(&self, bytes: usize) {
+        if let Some(limiter) = self.inner.scrub_throughput_limiter.as_ref() {
+            if let Some(wait) = limiter.consume(bytes as f64) {
+                tokio::time::sleep(wait).await;
+            }
+        }
+    }
+
+    fn compact(&self, strategy: CompactionStrategy) -> BoxFuture<'static, Result<CompactionReport>> {
+        let store = self.clone();
+        async move { store.compact_inner(strategy, Instant::now()).await }.boxed()
+    }
+
+    /// Compact with the ages of the entries measured at `now`.
+    async fn compact_inner(&self, strategy: CompactionStrategy, now: Instant) -> Result<CompactionReport> {
+        let mut report = CompactionReport::default();
+
+        let CompactionStrategy::AgeBalance { max_age } = strategy;
+        let Some(deadline) = now.checked_sub(max_age) else {
+            return Ok(report);
+        };
+        let keys = self.inner.catalog.indexed_before(deadline);
+        report.selected = keys.len();
+
+        for (key, sequence) in keys {
+            let value = match self.get(&key).await? {
+                Some(entry) => entry.to_arc().1,
+                None => {
+                    report.skipped += 1;
+                    continue;
+                }
+            };
+
+            // Stamp the sequence before checking the entry, so a following overwrite of the key still wins.
+            let mut writer = self.writer(key.clone());
+            writer.force();
+            writer.set_skippable();
+
+            // Skip the entry if it has been overwritten or removed since selected, and keep the expire at otherwise.
+            match self.inner.catalog.get(&key) {
+                Some(item) if *item.sequence() == sequence => {
+                    if let Some(expire_at) = item.expire_at() {
+                        writer.set_expire_at(expire_at);
+                    }
+                }
+                _ => {
+                    report.skipped += 1;
+                    continue;
+                }
+            }
+
+            match writer.finish(value).await? {
+                Some(_) => report.relocated += 1,
+                None => report.skipped += 1,
+            }
+        }
+
+        Ok(report)
+    }
+
+    fn read_region_bytes(&self, region: RegionId) -> BoxFuture<'static, Result<Vec<u8>>> {
+        let store = self.clone();
+        async move { store.read_region_bytes_inner(region).await }.boxed()
+    }
+
+    async fn read_region_bytes_inner(&self, region_id: RegionId) -> Result<Vec<u8>> {
+        let region_manager = &self.inner.region_manager;
+        if !region_manager.is_sealed(region_id) {
+            return Err(Error::RegionNotSealed { region: region_id });
+        }
+
+        let region = region_manager.region(&region_id);
+        let generation = region.generation();
+        let region_size = self.inner.device.region_size();
+        let Some(buf) = region.load_range(0..region_size).await? else {
+            return Err(anyhow!("failed to read the whole region {}", region_id).into());
+        };
+
+        // The region may be reclaimed during the read.
+        let current = region.generation();
+        if current != generation {
+            return Err(Error::StaleRegionView {
+                region: region_id,
+                view: generation,
+                current,
+            });
+        }
+
+        Ok(buf.to_vec())
+    }
+
+    fn apply_region_bytes(&self, region: RegionId, bytes: Vec<u8>) -> BoxFuture<'static, Result<()>> {
+        let store = self.clone();
+        async move { store.apply_region_bytes_inner(region, bytes).await }.boxed()
+    }
+
+    async fn apply_region_bytes_inner(&self, region_id: RegionId, bytes: Vec<u8>) -> Result<()> {
+        let region_size = self.inner.device.region_size();
+        if bytes.len() != region_size {
+            return Err(anyhow!(
+                "region bytes len {} mismatches the region size {}",
+                bytes.len(),
+                region_size
+            )
+            .into());
+        }
+
+        // Take the region out of both the clean and the evictable regions, so neither the flushers nor the
+        // reclaimer touch it while it is rewritten.
+        let region_manager = &self.inner.region_manager;
+        let sealed = if region_manager.clean_regions().remove(&region_id) {
+            false
+        } else if region_manager.eviction_remove(region_id) {
+            self.inner.catalog.take_region(&region_id);
+            true
+        } else {
+            return Err(Error::RegionNotSealed { region: region_id });
+        };
+        // The views taken on the previous content are stale.
+        region_manager.region(&region_id).advance_generation();
+
+        let mut buf = self.inner.device.io_buffer(0, region_size);
+        buf.extend_from_slice(&bytes);
+        let permit = self.inner.io_limiter.acquire(buf.len()).await;
+        let (res, _) = self.inner.device.write(buf, .., region_id, 0).await;
+        drop(permit);
+        let res = match res {
+            Ok(_) => {
+                Self::recover_region(
+                    region_id,
+                    region_manager.clone(),
+                    self.inner.catalog.clone(),
+                    self.inner.metrics.clone(),
+                    UnknownRegionVersionPolicy::Abort,
+                    self.inner.verify_checksum_on_recover,
+                )
+                .await
+            }
+            Err(e) => Err(e.into()),
+        };
+
+        match res {
+            Ok(Some(sequence)) => {
+                // Keep the following insertions newer than the replicated entries.
+                self.inner.sequence.fetch_max(sequence + 1, Ordering::Relaxed);
+                if !sealed {
+                    self.inner.metrics.total_bytes.add(region_size as u64);
+                }
+                Ok(())
+            }
+            Ok(None) => {
+                if sealed {
+                    self.inner.metrics.total_bytes.sub(region_size as u64);
+                }
+                Ok(())
+            }
+            Err(e) => {
+                // Drop the entries indexed before the scan fails.
+                self.inner.catalog.take_region(&region_id);
+                region_manager.clean_regions().release(region_id);
+                if sealed {
+                    self.inner.metrics.total_bytes.sub(region_size as u64);
+                }
+                Err(e)
+            }
+        }
+    }
+
+    fn rebuild_region_filters(&self) -> BoxFuture<'static, Result<usize>> {
+        let store = self.clone();
+        async move { store.rebuild_region_filters_inner().await }.boxed()
+    }
+
+    async fn rebuild_region_filters_inner(&self) -> Result<usize> {
+        if self.inner.store_key_hash_only {
+            return Err(anyhow!("key filters can't be rebuilt from the regions storing the key hashes only").into());
+        }
+
+        let region_manager = &self.inner.region_manager;
+        let mut rebuilt = 0;
+        for region_id in 0..region_manager.active_regions() as RegionId {
+            let region = region_manager.region(&region_id).clone();
+            // Hold a view before checking, so the sealed region can't be reclaimed until the filter is written.
+            let _view = region.view(0, 0);
+            if !region_manager.is_sealed(region_id) {
+                continue;
+            }
+            if self.rebuild_region_filter(region_id, region).await? {
+                rebuilt += 1;
+            }
+        }
+        Ok(rebuilt)
+    }
+
+    /// Rebuild the key filter of the region from its entries, and rewrite only the first device block that holds the
+    /// region header.
+    ///
+    /// Returns `false` if the region has no valid region header, or the first device block has no room for the filter.
+    async fn rebuild_region_filter(&self, region_id: RegionId, region: Region<D>) -> Result<bool> {
+        let device = &self.inner.device;
+        let align = device.align();
+        let capacity = RegionHeader::filter_capacity(align);
+        if capacity == 0 {
+            return Ok(false);
+        }
+        let Some(slice) = region.load_range(0..align).await? else {
+            return Ok(false);
+        };
+        let Ok(mut header) = RegionHeader::read(slice.as_ref()) else {
+            return Ok(false);
+        };
+        drop(slice);
+
+        let mut filter = KeyFilter::new(capacity);
+        let Some(mut iter) = RegionEntryIter::<K, V, D>::open(region.clone(), false).await? else {
+            return Ok(false);
+        };
+        while let Some((key, _)) = iter.next().await? {
+            filter.insert(encoded_key_hash(&key)?);
+        }
+        drop(iter);
+
+        header.version = Version::latest();
+        header.filter = Some(filter.clone());
+        let mut buf = device.io_buffer(align, align);
+        buf.fill(0);
+        header.write(&mut buf[..]);
+        let permit = self.inner.io_limiter.acquire(align).await;
+        let (res, _) = device.write(buf, .., region_id, 0).await;
+        drop(permit);
+        res?;
+        region.set_filter(Some(filter));
+
+        Ok(true)
+    }
+
+    fn shrink(&self, capacity: usize) -> BoxFuture<'static, Result<()>> {
+        let store = self.clone();
+        async move { store.shrink_inner(capacity).await }.boxed()
+    }
+
+    #[allow(clippy::manual_is_multiple_of)]
+    async fn shrink_inner(&self, capacity: usize) -> Result<()> {
+        let region_manager = &self.inner.region_manager;
+        let region_size = self.inner.device.region_size();
+        let active = region_manager.active_regions();
+        if capacity == 0 || capacity % region_size != 0 || capacity > active * region_size {
+            return Err(anyhow!(
+                "capacity must be a positive multiple of region size {} and no more than {}, given: {}",
+                region_size,
+                active * region_size,
+                capacity
+            )
+            .into());
+        }
+        let regions = capacity / region_size;
+        if regions == active {
+            return Ok(());
+        }
+
+        let live = self.live_bytes();
+        if live > capacity {
+            return Err(Error::ShrinkLiveDataExceeded { live, capacity });
+        }
+
+        // The flushers only acquire the leading regions after the trailing regions are retired. Take the retired
+        // regions out of the evictable regions, so the reclaimers don't drop their entries.
+        region_manager.retire(regions);
+        let mut retired = (regions..active)
+            .map(|id| id as RegionId)
+            .filter(|id| region_manager.eviction_remove(*id))
+            .collect_vec();
+
+        // Seal the retired regions being written, which become evictable then.
+        let sealed_rxs = self
+            .inner
+            .flusher_entry_txs
+            .iter()
+            .filter_map(|tx| {
+                let (sealed_tx, sealed_rx) = oneshot::channel();
+                // The flusher has exited and flushed its buffer if the channel is closed.
+                tx.send(FlusherMessage::SealRetired(sealed_tx)).ok().map(|_| sealed_rx)
+            })
+            .collect_vec();
+        for sealed_rx in sealed_rxs {
+            sealed_rx
+                .await
+                .map_err(|_| anyhow!("flusher exited before the retired regions are sealed"))?;
+        }
+        retired.extend(
+            (regions..active)
+                .map(|id| id as RegionId)
+                .filter(|id| region_manager.eviction_remove(*id)),
+        );
+
+        // The other retired regions are clean, or being reclaimed and dropped after reclaimed.
+        for region_id in retired {
+            self.relocate_region(region_id).await?;
+            self.inner.metrics.total_bytes.sub(region_size as u64);
+        }
+
+        self.inner.device.truncate(regions).await?;
+
+        Ok(())
+    }
+
+    /// Bytes of the entries indexed in regions, a block of entries is counted once.
+    fn live_bytes(&self) -> usize {
+        let mut blocks = HashSet::new();
+        self.inner
+            .catalog
+            .snapshot()
+            .into_iter()
+            .filter(|(_, _, region, offset, _, index, _)| index.is_none() || blocks.insert((*region, *offset)))
+            .map(|(_, _, _, _, len, _, _)| len as usize)
+            .sum()
+    }
+
+    /// Rewrite the entries indexed in the region into the regions in use, and drop the region content.
+    ///
+    /// The rewrites wait for clean regions rather than being skipped as the compaction does, so the entries are
+    /// only lost if evicted from the leading regions meanwhile.
+    async fn relocate_region(&self, region_id: RegionId) -> Result<()> {
+        for key in self.inner.catalog.region_keys(&region_id) {
+            let sequence = match self.inner.catalog.get(&key) {
+                Some(item) => *item.sequence(),
+                None => continue,
+            };
+            let value = match self.get(&key).await? {
+                Some(entry) => entry.to_arc().1,
+                None => continue,
+            };
+
+            // Stamp the sequence before checking the entry, so a following overwrite of the key still wins.
+            let mut writer = self.writer(key.clone());
+            writer.force();
+
+            // Skip the entry if it has been overwritten or removed since read, and keep the expire at otherwise.
+            match self.inner.catalog.get(&key) {
+                Some(item) if *item.sequence() == sequence => {
+                    if let Some(expire_at) = item.expire_at() {
+                        writer.set_expire_at(expire_at);
+                    }
+                }
+                _ => continue,
+            }
+
+            writer.finish(value).await?;
+        }
+
+        // Drop the entries left in the region, the views taken on it are stale.
+        self.inner.catalog.take_region(&region_id);
+        self.inner.region_manager.region(&region_id).advance_generation();
+
+        Ok(())
+    }
+
+    #[tracing::instrument(skip(self, path))]
+    async fn snapshot_catalog(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref().to_path_buf();
+
+        // Fingerprints must be taken before the catalog. If a region is reclaimed and rewritten in between, its
+        // fingerprint mismatches on recovery and the snapshot is discarded.
+        let mut fingerprints = Vec::with_capacity(self.inner.device.regions());
+        for region_id in 0..self.inner.device.regions() as RegionId {
+            let region = self.inner.region_manager.region(&region_id);
+            fingerprints.push(Self::region_fingerprint(region).await?);
+        }
+        let entries = self.inner.catalog.snapshot();
+
+        tokio::task::spawn_blocking(move || snapshot::write::<K>(&path, &fingerprints, &entries))
+            .await
+            .map_err(anyhow::Error::from)??;
+
+        Ok(())
+    }
+
+    /// Round-trip an aligned block on the device to verify that the alignment and direct I/O work.
+    ///
+    /// The last block of region 0 is used as the scratch area, and its original data is restored after the test.
+    async fn self_test(device: &D) -> Result<()> {
+        let align = device.align();
+        let offset = device.region_size() - align;
+        let fail = |reason: String| Error::DeviceSelfTest { reason };
+
+        let origin = device
+            .load(0, offset..offset + align)
+            .await
+            .map_err(|e| fail(format!("read an aligned block error: {e}")))?;
+
+        let mut buf = device.io_buffer(align, align);
+        buf.iter_mut().enumerate().for_each(|(i, b)| *b = (i % 251) as u8);
+        let (res, buf) = device.write(buf, .., 0, offset).await;
+        match res {
+            Ok(bytes) if bytes == align => {}
+            Ok(bytes) => {
+                return Err(fail(format!(
+                    "short write of an aligned block: {bytes} / {align} bytes"
+                )))
+            }
+            Err(e) => return Err(fail(format!("write an aligned block error: {e}"))),
+        }
+
+        let read = device
+            .load(0, offset..offset + align)
+            .await
+            .map_err(|e| fail(format!("read an aligned block error: {e}")))?;
+        if read.as_slice() != buf.as_slice() {
+            return Err(fail(
+                "the aligned block read back mismatches the written one".to_string(),
+            ));
+        }
+
+        if origin.len() == align {
+            let (res, _) = device.write(origin, .., 0, offset).await;
+            res.map_err(|e| fail(format!("restore the aligned block error: {e}")))?;
+        }
+
+        Ok(())
+    }
+
+    /// Return the sequence of the first entry of the region as its fingerprint, `None` if the region is clean.
+    ///
+    /// The generation of the region is restored from its header on the way.
+    async fn region_fingerprint(region: &Region<D>) -> Result<Option<Sequence>> {
+        let align = region.device().align();
+
+        let Some(slice) = region.load_range(0..align).await? else {
+            return Ok(None);
+        };
+        let Ok(header) = RegionHeader::read(slice.as_ref()) else {
+            return Ok(None);
+        };
+        drop(slice);
+        region.recover_generation(header.generation);
+
+        let offset = header.first_entry_offset.map(|offset| offset as usize).unwrap_or(align);
+        let Some(slice) = region.load_range(offset..offset + align).await? else {
+            return Ok(None);
+        };
+        let sequence = EntryHeader::read(slice.as_ref())
+            .map(|header| header.sequence)
+            .unwrap_or_default();
+
+        Ok(Some(sequence))
+    }
+
+    #[tracing::instrument(skip(self, catalog_snapshot_path))]
+    async fn recover(
+        &self,
+        concurrency: usize,
+        catalog_snapshot_path: Option<&Path>,
+        unknown_region_version_policy: UnknownRegionVersionPolicy,
+    ) -> Result<Sequence> {
+        if let Some(path) = catalog_snapshot_path {
+            match self.recover_from_snapshot(path).await {
+                Ok(Some(sequence)) => return Ok(sequence),
+                Ok(None) => tracing::info!("catalog snapshot {:?} is missing or stale, fall back to scan", path),
+                Err(e) => tracing::warn!(
+                    "failed to recover from catalog snapshot {:?}, fall back to scan: {}",
+                    path,
+                    e
+                ),
+            }
+        }
+
+        tracing::info!("start store recovery");
+
+        assert!(concurrency > 0, "recover concurrency must be > 0");
+        // Scan at most `concurrency` regions at the same time, the catalog merges the results by sequence.
+        let semaphore = Arc::new(Semaphore::new(concurrency));
+
+        let mut handles = vec![];
+        for region_id in 0..self.inner.device.regions() as RegionId {
+            let semaphore = semaphore.clone();
+            let region_manager = self.inner.region_manager.clone();
+            let indices = self.inner.catalog.clone();
+            let metrics = self.inner.metrics.clone();
+            let verify_checksum = self.inner.verify_checksum_on_recover;
+            let handle = tokio::spawn(async move {
+                let permit = semaphore.acquire().await;
+                let res = Self::recover_region(
+                    region_id,
+                    region_manager,
+                    indices,
+                    metrics,
+                    unknown_region_version_policy,
+                    verify_checksum,
+                )
+                .await;
+                drop(permit);
+                res
+            });
+            handles.push(handle);
+        }
+
+        let mut recovered = 0;
+        let mut sequence = 0;
+
+        let results = try_join_all(handles).await.map_err(anyhow::Error::from)?;
+
+        for (region_id, result) in results.into_iter().enumerate() {
+            if let Some(seq) = result? {
+                tracing::debug!("region {} is recovered", region_id);
+                recovered += 1;
+                sequence = std::cmp::max(sequence, seq);
+            }
+        }
+
+        tracing::info!("finish store recovery, {} region recovered", recovered);
+        self.inner
+            .metrics
+            .total_bytes
+            .set((recovered * self.inner.device.region_size()) as u64);
+
+        // Force trigger reclamation.
+        if recovered == self.inner.device.regions() {
+            self.inner.region_manager.clean_regions().flash();
+        }
+
+        Ok(sequence)
+    }
+
+    /// Return `Some(max sequence)` if the catalog is recovered from the snapshot, or `None` if the snapshot is missing
+    /// or stale.
+    ///
+    /// The catalog is untouched unless the snapshot is valid.
+    async fn recover_from_snapshot(&self, path: &Path) -> Result<Option<Sequence>> {
+        let path = path.to_path_buf();
+        let Some(snapshot) = tokio::task::spawn_blocking(move || snapshot::read_catalog_snapshot::<K>(&path))
+            .await
+            .map_err(anyhow::Error::from)??
+        else {
+            return Ok(None);
+        };
+
+        let regions = self.inner.device.regions();
+        if snapshot.fingerprints.len() != regions {
+            return Ok(None);
+        }
+
+        // Validate the snapshot against the region headers before trusting it.
+        let mut ends = vec![None; regions];
+        for entry in snapshot.entries.iter() {
+            let Some(end) = ends.get_mut(entry.region as usize) else {
+                return Ok(None);
+            };
+            if snapshot.fingerprints[entry.region as usize].is_none() {
+                return Ok(None);
+            }
+            *end = std::cmp::max(*end, Some((entry.offset + entry.len) as usize));
+        }
+        for region_id in 0..regions as RegionId {
+            let region = self.inner.region_manager.region(&region_id);
+            if Self::region_fingerprint(region).await? != snapshot.fingerprints[region_id as usize] {
+                return Ok(None);
+            }
+            // Entries appended to the region after the snapshot is taken are missing in the snapshot.
+            if let Some(end) = ends[region_id as usize] {
+                if self.has_entry_after(region, end).await? {
+                    return Ok(None);
+                }
+            }
+        }
+
+        let mut sequence = 0;
+        for entry in snapshot.entries {
+            sequence = std::cmp::max(sequence, entry.sequence);
+            let view = self
+                .inner
+                .region_manager
+                .region(&entry.region)
+                .view(entry.offset, entry.len);
+            let index = match entry.block_index {
+                Some(index) => Index::Block { view, index },
+                None => Index::Region { view },
+            };
+            self.inner.catalog.insert(
+                Arc::new(entry.key),
+                Item::new(entry.sequence, index).with_expire_at(entry.expire_at),
+            );
+        }
+
+        let mut recovered = 0;
+        for (region_id, fingerprint) in snapshot.fingerprints.into_iter().enumerate() {
+            match fingerprint {
+                Some(seq) => {
+                    sequence = std::cmp::max(sequence, seq);
+                    recovered += 1;
+                    self.inner.region_manager.eviction_push(region_id as RegionId);
+                }
+                None => self.inner.region_manager.clean_regions().release(region_id as RegionId),
+            }
+        }
+
+        tracing::info!(
+            "finish store recovery from catalog snapshot, {} region recovered",
+            recovered
+        );
+        self.inner
+            .metrics
+            .total_bytes
+            .set((recovered * self.inner.device.region_size()) as u64);
+
+        // Force trigger reclamation.
+        if recovered == regions {
+            self.inner.region_manager.clean_regions().flash();
+        }
+
+        Ok(Some(sequence))
+    }
+
+    /// Check if there is a valid entry at `end`, or at the next block if the rest of the block is padding.
+    async fn has_entry_after(&self, region: &Region<D>, end: usize) -> Result<bool> {
+        let align = self.inner.device.align();
+        let region_size = self.inner.device.region_size();
+
+        let mut offsets = vec![end];
+        if !bits::is_aligned(align, end) {
+            offsets.push(bits::align_up(align, end));
+        }
+        // The following flush starts at the next flush alignment boundary.
+        if let Some(flush_alignment) = self.inner.flush_alignment {
+            if !bits::is_aligned(flush_alignment, end) {
+                offsets.push(bits::align_up(flush_alignment, end));
+            }
+        }
+
+        for offset in offsets {
+            let block_start = bits::align_down(align, offset);
+            if block_start + align >= region_size {
+                continue;
+            }
+            let Some(slice) = region.load_range(block_start..block_start + align).await? else {
+                continue;
+            };
+            if offset - block_start + EntryHeader::serialized_len() > align {
+                continue;
+            }
+            if EntryHeader::read(&slice.as_ref()[offset - block_start..]).is_ok() {
+                return Ok(true);
+            }
+        }
+
+        Ok(false)
+    }
+
+    /// Return `Some(max sequence)` if region is valid, otherwise `None`
+    async fn recover_region(
+        region_id: RegionId,
+        region_manager: Arc<RegionManager<D>>,
+        catalog: Arc<Catalog<K, V>>,
+        metrics: Arc<Metrics>,
+        unknown_region_version_policy: UnknownRegionVersionPolicy,
+        verify_checksum: bool,
+    ) -> Result<Option<Sequence>> {
+        #[cfg(test)]
+        REGION_SCANS.with(|count| count.set(count.get() + 1));
+
+        let region = region_manager.region(&region_id).clone();
+
+        let align = region.device().align();
+        if let Some(slice) = region.load_range(0..align).await? {
+            if let Ok(header) = RegionHeader::read(slice.as_ref()) {
+                region.recover_generation(header.generation);
+                region.set_filter(header.filter);
+            }
+            if let Some(version) = RegionHeader::unknown_version(slice.as_ref()) {
+                match unknown_region_version_policy {
+                    UnknownRegionVersionPolicy::SkipWithWarning => {
+                        tracing::warn!(
+                            "region {} has an unknown format version {}, skip it and reuse it as a clean region",
+                            region_id,
+                            version
+                        );
+                        region_manager.clean_regions().release(region_id);
+                        return Ok(None);
+                    }
+                    UnknownRegionVersionPolicy::Abort => {
+                        return Err(Error::UnknownRegionVersion {
+                            region: region_id,
+                            version,
+                        })
+                    }
+                }
+            }
+        }
+
+        let mut sequence = 0;
+        let mut entries = 0;
+        // Checksum is not verified on loading kv while recovering indices, only on iteration if enabled.
+        let iter = RegionEntryIter::<K, V, D>::open(region, false)
+            .await?
+            .map(|iter| iter.with_entry_verification(verify_checksum));
+        let res = if let Some(mut iter) = iter {
+            while let Some((key, item)) = iter.next().await? {
+                sequence = std::cmp::max(sequence, *item.sequence());
+                catalog.recover(Arc::new(key), item);
+                entries += 1;
+            }
+            metrics.recover_entries_recovered.inc_by(entries);
+            metrics.op_bytes_recover.inc_by(iter.bytes() as u64);
+            if iter.corrupted() {
+                // The entries after a torn write are lost, the region is still recovered with the entries before.
+                tracing::warn!("region {} stops recovery at a corrupted entry", region_id);
+                metrics.recover_entries_corrupted.inc_by(1);
+            }
+            if iter.checksum_mismatches() > 0 {
+                tracing::warn!(
+                    "region {} skips {} entries with checksum mismatch on recovery",
+                    region_id,
+                    iter.checksum_mismatches()
+                );
+                metrics
+                    .recover_entries_checksum_mismatch
+                    .inc_by(iter.checksum_mismatches() as u64);
+            }
+            region_manager.eviction_push(region_id);
+            Some(sequence)
+        } else {
+            region_manager.clean_regions().release(region_id);
+            None
+        };
+        Ok(res)
+    }
+
+    fn judge_inner(&self, writer: &mut GenericStoreWriter<K, V, D>) {
+        for (index, admission) in self.inner.admissions.iter().enumerate() {
+            let key = writer.key.as_ref().unwrap();
+            let judge = admission.judge(key);
+            if let Some(observer) = self.inner.admission_observer.as_ref() {
+                observer.on_decision(AdmissionDecision {
+                    key: key.clone(),
+                    policy: index,
+                    policy_name: admission.name(),
+                    admitted: judge,
+                });
+            }
+            writer.judges.set(index, judge);
+        }
+        writer.is_judged = true;
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn apply_writer(
+        &self,
+        mut writer: GenericStoreWriter<K, V, D>,
+        value: impl Into<Arc<V>>,
+    ) -> Result<Option<CachedEntry<K, V>>> {
+        debug_assert!(!writer.is_inserted);
+
+        if !writer.judge() {
+            return Ok(None);
+        }
+
+        // Reject oversized key before the entry is serialized into the buffer.
+        if let Some(limit) = self.inner.max_key_size {
+            let size =
+                bincode::serialized_size(writer.key.as_ref().unwrap().as_ref()).map_err(BufferError::from)? as usize;
+            if size > limit {
+                return Err(Error::KeyTooLarge { size, limit });
+            }
+        }
+
+        let now = Instant::now();
+
+        // Apply backpressure if the catalog mutations exceed the limit.
+        if let Some(limiter) = self.inner.catalog_ops_limiter.as_ref() {
+            if let Some(wait) = limiter.consume(1.0) {
+                let _timer = self.inner.metrics.inner_op_duration_throttle_catalog.start_timer();
+                tokio::time::sleep(wait).await;
+            }
+        }
+
+        // Apply backpressure if the entries queued to the flushers reach the capacity.
+        if !self.inner.flusher_queue.reserve(1).await? {
+            return Ok(None);
+        }
+
+        // Hold the lock until the entry is sent to the flusher, there is no await point after it.
+        let _guard = self.inner.drain_lock.read();
+        if self.inner.draining.load(Ordering::Acquire) {
+            self.inner.flusher_queue.release(1);
+            return Ok(None);
+        }
+
+        let entry = self.stage(&mut writer, value.into());
+        let (key, value) = (entry.key.clone(), entry.value.clone());
+        let flusher = self.flusher_of(&entry);
+        self.inner.flusher_entry_txs[flusher]
+            .send(FlusherMessage::Entry(entry))
+            .unwrap();
+
+        let duration = now.elapsed() + writer.duration;
+        self.inner
+            .metrics
+            .op_duration_insert_inserted
+            .observe(duration.as_secs_f64());
+
+        Ok(Some(CachedEntry::Shared { key, value }))
+    }
+
+    /// Index the entry of the writer as inflight, and build the entry to send to the flusher.
+    ///
+    /// Must be called with the drain lock held.
+    fn stage(&self, writer: &mut GenericStoreWriter<K, V, D>, value: Arc<V>) -> Entry<K, V> {
+        let sequence = if let Some(sequence) = writer.sequence {
+            sequence
+        } else {
+            self.inner.sequence.fetch_add(1, Ordering::Relaxed)
+        };
+
+        writer.is_inserted = true;
+        let key = writer.key.take().unwrap();
+
+        // TODO(MrCroxx): FIX ME!!!
+        // record aligned header + key + value size for metrics
+        // let len = bits::align_up(
+        //     self.inner.device.align(),
+        //     EntryHeader::serialized_len() + key.serialized_len() + value.serialized_len(),
+        // );
+        // self.inner.metrics.op_bytes_insert.inc_by(len as u64);
+        // self.inner.metrics.insert_entry_bytes.observe(len as f64);
+
+        self.inner.catalog.insert(
+            key.clone(),
+            Item::new(
+                sequence,
+                Index::Inflight {
+                    key: key.clone(),
+                    value: value.clone(),
+                },
+            )
+            .with_expire_at(writer.expire_at),
+        );
+
+        if let Some(recent_inserts) = self.inner.recent_inserts.as_ref() {
+            let mut recent_inserts = recent_inserts.lock();
+            if recent_inserts.len() == self.inner.recent_inserts_capacity {
+                recent_inserts.pop_front();
+            }
+            recent_inserts.push_back((key.clone(), sequence));
+        }
+
+        Entry {
+            sequence,
+            key,
+            value,
+            compression: writer.compression,
+            enqueued: Instant::now(),
+            expire_at: writer.expire_at,
+        }
+    }
+
+    /// Default compression of the entry of the key, by its prefix if configured in the prefix compressions.
+    fn compression_of(&self, key: &K) -> Compression {
+        self.inner
+            .key_prefix
+            .as_ref()
+            .and_then(|key_prefix| self.inner.prefix_compressions.get(&key_prefix(key)))
+            .copied()
+            .unwrap_or(self.inner.compression)
+    }
+
+    /// Entries of the same key prefix are sent to the same flusher to be written into the same regions, and entries of
+    /// the same key are sent to the same flusher to be coalesced.
+    fn flusher_of(&self, entry: &Entry<K, V>) -> usize {
+        if let Some(key_prefix) = self.inner.key_prefix.as_ref() {
+            key_prefix(&entry.key) as usize % self.inner.flusher_entry_txs.len()
+        } else if self.inner.write_coalescing {
+            let mut hasher = XxHash64::with_seed(0);
+            entry.key.hash(&mut hasher);
+            hasher.finish() as usize % self.inner.flusher_entry_txs.len()
+        } else {
+            entry.sequence as usize % self.inner.flusher_entry_txs.len()
+        }
+    }
+}
+
+pub struct GenericStoreWriter<K, V, D>
+where
+    K: StorageKey,
+    V: StorageValue,
+    D: Device,
+{
+    store: GenericStore<K, V, D>,
+    /// `key` is always `Some` before `apply_writer`.
+    key: Option<Arc<K>>,
+
+    sequence: Option<Sequence>,
+
+    judges: Judges,
+    is_judged: bool,
+
+    /// judge duration
+    duration: Duration,
+
+    is_inserted: bool,
+    is_skippable: bool,
+    compression: Compression,
+    expire_at: Option<SystemTime>,
+}
+
+impl<K, V, D> GenericStoreWriter<K, V, D>
+where
+    K: StorageKey,
+    V: StorageValue,
+    D: Device,
+{
+    fn new(store: GenericStore<K, V, D>, key: impl Into<Arc<K>>) -> Self {
+        let key = key.into();
+        let judges = Judges::new(store.inner.admissions.len());
+        let compression = store.compression_of(&key);
+        // Stamp the sequence at insert-call time, so the later call wins regardless of the flusher order.
+        let sequence = match store.inner.write_conflict_policy {
+            WriteConflictPolicy::LastWriterWins => Some(store.inner.sequence.fetch_add(1, Ordering::Relaxed)),
+            WriteConflictPolicy::LastAppliedWins => None,
+        };
+        Self {
+            store,
+            key: Some(key),
+            sequence,
+            judges,
+            is_judged: false,
+            duration: Duration::from_nanos(0),
+            is_inserted: false,
+            is_skippable: false,
+            compression,
+            expire_at: None,
+        }
+    }
+
+    /// Judge if the entry can be admitted by configured admission policies.
+    pub fn judge(&mut self) -> bool {
+        let store = self.store.clone();
+        if !self.is_judged {
+            let now = Instant::now();
+            store.judge_inner(self);
+            self.duration = now.elapsed();
+        }
+        self.judges.judge()
+    }
+
+    pub async fn finish(self, value: impl Into<Arc<V>>) -> Result<Option<CachedEntry<K, V>>> {
         let store = self.store.clone();
         store.apply_writer(self, value).await
     }
 
-    pub fn force(&mut self) {
-        self.judges.set_mask(Bitmap::new());
-    }
+    pub fn force(&mut self) {
+        self.judges.set_mask(Bitmap::new());
+    }
+
+    pub fn set_judge_mask(&mut self, mask: Bitmap<64>) {
+        self.judges.set_mask(mask);
+    }
+
+    pub fn set_skippable(&mut self) {
+        self.is_skippable = true
+    }
+
+    pub fn set_sequence(&mut self, sequence: Sequence) {
+        self.sequence = Some(sequence);
+    }
+
+    pub fn compression(&self) -> Compression {
+        self.compression
+    }
+
+    pub fn set_compression(&mut self, compression: Compression) {
+        self.compression = compression
+    }
+
+    pub fn expire_at(&self) -> Option<SystemTime> {
+        self.expire_at
+    }
+
+    pub fn set_expire_at(&mut self, expire_at: SystemTime) {
+        self.expire_at = Some(expire_at)
+    }
+}
+
+impl<K, V, D> Debug for GenericStoreWriter<K, V, D>
+where
+    K: StorageKey + Debug,
+    V: StorageValue,
+    D: Device,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("StoreWriter")
+            .field("key", &self.key)
+            .field("judges", &self.judges)
+            .field("is_judged", &self.is_judged)
+            .field("duration", &self.duration)
+            .field("inserted", &self.is_inserted)
+            .finish()
+    }
+}
+
+impl<K, V, D> Drop for GenericStoreWriter<K, V, D>
+where
+    K: StorageKey,
+    V: StorageValue,
+    D: Device,
+{
+    fn drop(&mut self) {
+        if !self.is_inserted {
+            debug_assert!(self.key.is_some());
+
+            let filtered = self.is_judged && !self.judge();
+
+            if filtered {
+                self.store
+                    .inner
+                    .metrics
+                    .op_duration_insert_filtered
+                    .observe(self.duration.as_secs_f64());
+            } else {
+                self.store
+                    .inner
+                    .metrics
+                    .op_duration_insert_dropped
+                    .observe(self.duration.as_secs_f64());
+            }
+        }
+    }
+}
+
+const ENTRY_MAGIC: u32 = 0x97_03_27_00;
+const ENTRY_MAGIC_MASK: u32 = 0xFF_FF_FF_00;
+/// Marks that the entry is a block of entries compressed together, see [`crate::block`].
+const ENTRY_BLOCK_FLAG: u8 = 0x80;
+/// Marks that the key part of the entry is the key hash instead of the key, see [`key_hash`].
+const ENTRY_KEY_HASH_FLAG: u8 = 0x40;
+/// Marks that the checksum of the entry is calculated by [`ChecksumAlgorithm::Crc32c`].
+const ENTRY_CRC32C_FLAG: u8 = 0x20;
+/// Marks that the header is followed by the expire at of the entry, see [`EntryHeader::expire_at`].
+const ENTRY_EXPIRE_FLAG: u8 = 0x10;
+/// Marks that the checksum of the entry is calculated by [`ChecksumAlgorithm::XxHash3`].
+const ENTRY_XXH3_FLAG: u8 = 0x08;
+/// Marks that the checksum of the entry is not calculated, see [`GenericStoreConfig::checksum`].
+const ENTRY_NO_CHECKSUM_FLAG: u8 = 0x04;
+
+/// Serialized size of a key hash.
+pub const KEY_HASH_LEN: usize = 16;
+
+#[derive(Debug)]
+pub struct EntryHeader {
+    pub key_len: u32,
+    pub value_len: u32,
+    pub sequence: Sequence,
+    pub checksum: u64,
+    pub compression: Compression,
+    pub block: bool,
+    /// the key part is the key hash instead of the key
+    pub key_hash: bool,
+    pub checksum_algorithm: ChecksumAlgorithm,
+    /// `false` if the entry is written with checksum disabled, the checksum is zero and never verified then.
+    pub checksummed: bool,
+    /// Milliseconds since the unix epoch after which the entry expires, see [`timestamp`].
+    ///
+    /// Only written if set, the headers written before are decoded as never expire.
+    pub expire_at: Option<u64>,
+}
+
+impl EntryHeader {
+    /// Serialized len of the header without the optional fields.
+    pub const fn serialized_len() -> usize {
+        4 + 4 + 8 + 8 + 4 /* magic & compression */
+    }
+
+    /// Serialized len of the header with the expire at.
+    pub const fn serialized_len_with_expire_at() -> usize {
+        Self::serialized_len() + 8
+    }
+
+    /// Serialized len of the header, including the optional fields.
+    pub fn encoded_len(&self) -> usize {
+        match self.expire_at {
+            Some(_) => Self::serialized_len_with_expire_at(),
+            None => Self::serialized_len(),
+        }
+    }
+
+    pub fn write(&self, mut buf: &mut [u8]) {
+        buf.put_u32(self.key_len);
+        buf.put_u32(self.value_len);
+        buf.put_u64(self.sequence);
+        buf.put_u64(self.checksum);
+
+        let mut flag = if self.block { ENTRY_BLOCK_FLAG } else { 0 };
+        if self.key_hash {
+            flag |= ENTRY_KEY_HASH_FLAG;
+        }
+        match self.checksum_algorithm {
+            ChecksumAlgorithm::XxHash64 => {}
+            ChecksumAlgorithm::Crc32c => flag |= ENTRY_CRC32C_FLAG,
+            ChecksumAlgorithm::XxHash3 => flag |= ENTRY_XXH3_FLAG,
+        }
+        if !self.checksummed {
+            flag |= ENTRY_NO_CHECKSUM_FLAG;
+        }
+        if self.expire_at.is_some() {
+            flag |= ENTRY_EXPIRE_FLAG;
+        }
+        let v = ENTRY_MAGIC | (self.compression.to_u8() | flag) as u32;
+        buf.put_u32(v);
+
+        if let Some(expire_at) = self.expire_at {
+            buf.put_u64(expire_at);
+        }
+    }
+
+    /// Return if the header is followed by the expire at, which only requires the first
+    /// [`EntryHeader::serialized_len`] bytes.
+    #[cfg(test)]
+    pub fn has_expire_at(buf: &[u8]) -> bool {
+        buf[Self::serialized_len() - 1] & ENTRY_EXPIRE_FLAG != 0
+    }
+
+    pub fn read(mut buf: &[u8]) -> Result<Self> {
+        let key_len = buf.get_u32();
+        let value_len = buf.get_u32();
+        let sequence = buf.get_u64();
+        let checksum = buf.get_u64();
+
+        let v = buf.get_u32();
+        let magic = v & ENTRY_MAGIC_MASK;
+        if magic != ENTRY_MAGIC {
+            return Err(anyhow!("magic mismatch, expected: {}, got: {}", ENTRY_MAGIC, magic).into());
+        }
+        let block = (v as u8 & ENTRY_BLOCK_FLAG) != 0;
+        let key_hash = (v as u8 & ENTRY_KEY_HASH_FLAG) != 0;
+        let checksum_algorithm = if (v as u8 & ENTRY_CRC32C_FLAG) != 0 {
+            ChecksumAlgorithm::Crc32c
+        } else if (v as u8 & ENTRY_XXH3_FLAG) != 0 {
+            ChecksumAlgorithm::XxHash3
+        } else {
+            ChecksumAlgorithm::XxHash64
+        };
+        let checksummed = (v as u8 & ENTRY_NO_CHECKSUM_FLAG) == 0;
+        let expire_at = if (v as u8 & ENTRY_EXPIRE_FLAG) != 0 {
+            if buf.remaining() < 8 {
+                return Err(anyhow!("entry header truncated, the expire at is missing").into());
+            }
+            Some(buf.get_u64())
+        } else {
+            None
+        };
+        let compression = Compression::try_from(
+            v as u8
+                & !(ENTRY_BLOCK_FLAG
+                    | ENTRY_KEY_HASH_FLAG
+                    | ENTRY_CRC32C_FLAG
+                    | ENTRY_EXPIRE_FLAG
+                    | ENTRY_XXH3_FLAG
+                    | ENTRY_NO_CHECKSUM_FLAG),
+        )?;
+
+        Ok(Self {
+            key_len,
+            value_len,
+            sequence,
+            compression,
+            checksum,
+            block,
+            key_hash,
+            checksum_algorithm,
+            checksummed,
+            expire_at,
+        })
+    }
+}
+
+/// Milliseconds since the unix epoch of `time`, as recorded in [`EntryHeader::expire_at`].
+pub fn timestamp(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64
+}
+
+/// The time of the milliseconds since the unix epoch, the inverse of [`timestamp`].
+pub fn from_timestamp(timestamp: u64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_millis(timestamp)
+}
+
+/// Key part of an entry.
+#[derive(Debug)]
+enum EntryKey<K> {
+    Key(K),
+    /// Only the key hash is stored, see [`GenericStoreConfig::store_key_hash_only`].
+    Hash(u128),
+}
+
+/// Hash of the encoded key, stored instead of the key if [`GenericStoreConfig::store_key_hash_only`] is set.
+pub fn key_hash(encoded_key: &[u8]) -> u128 {
+    xxh3::hash128(encoded_key)
+}
+
+fn encoded_key_hash<K>(key: &K) -> Result<u128>
+where
+    K: StorageKey,
+{
+    let encoded = bincode::serialize(key).map_err(BufferError::from)?;
+    Ok(key_hash(&encoded))
+}
+
+/// | header | value (compressed) | key | <padding> |
+///
+/// Checksum is verified only if `checksum` is `true`.
+///
+/// # Safety
+///
+/// `buf.len()` must exactly fit entry size
+fn read_entry<K, V>(buf: &[u8], checksum: bool) -> Result<(EntryKey<K>, V)>
+where
+    K: StorageKey,
+    V: StorageValue,
+{
+    // read entry header
+    let header = EntryHeader::read(buf)?;
+
+    // TODO(MrCroxx): optimize buffer copy here.
+
+    // read value
+    let mut offset = header.encoded_len();
+    let compressed = &buf[offset..offset + header.value_len as usize];
+    offset += header.value_len as usize;
+    let value = match header.compression {
+        Compression::None => bincode::deserialize_from(compressed).map_err(BufferError::from)?,
+        Compression::Zstd => {
+            let decoder = zstd::Decoder::new(compressed).map_err(BufferError::from)?;
+            bincode::deserialize_from(decoder).map_err(BufferError::from)?
+        }
+        Compression::Lz4 => {
+            let decoder = lz4::Decoder::new(compressed).map_err(BufferError::from)?;
+            bincode::deserialize_from(decoder).map_err(BufferError::from)?
+        }
+        Compression::Snappy => {
+            let decoder = snap::read::FrameDecoder::new(compressed);
+            bincode::deserialize_from(decoder).map_err(BufferError::from)?
+        }
+    };
+
+    // read key
+    let compressed = &buf[offset..offset + header.key_len as usize];
+    let key = if header.key_hash {
+        let hash = compressed.try_into().map_err(|_| {
+            anyhow!(
+                "key hash length mismatch, expected: {}, got: {}",
+                KEY_HASH_LEN,
+                header.key_len
+            )
+        })?;
+        EntryKey::Hash(u128::from_le_bytes(hash))
+    } else {
+        EntryKey::Key(bincode::deserialize_from(compressed).map_err(BufferError::from)?)
+    };
+    offset += header.key_len as usize;
+
+    if checksum && header.checksummed {
+        let checksum = self::checksum(header.checksum_algorithm, &buf[header.encoded_len()..offset]);
+        if checksum != header.checksum {
+            return Err(anyhow!("checksum mismatch, expected: {}, got: {}", header.checksum, checksum).into());
+        }
+    }
+
+    Ok((key, value))
+}
+
+/// Decompress the value of the entry and pipe it into `writer` in chunks.
+///
+/// | header | value (compressed) | key | <padding> |
+///
+/// # Safety
+///
+/// `buf.len()` must exactly fit entry size
+async fn stream_entry_value<W>(buf: &[u8], writer: &mut W, checksum: bool) -> Result<()>
+where
+    W: AsyncWrite + Send + Unpin,
+{
+    let header = EntryHeader::read(buf)?;
+
+    let start = header.encoded_len();
+    let end = start + (header.value_len + header.key_len) as usize;
+
+    // Verify checksum before writing anything, for the bytes written cannot be taken back.
+    if checksum && header.checksummed {
+        let checksum = self::checksum(header.checksum_algorithm, &buf[start..end]);
+        if checksum != header.checksum {
+            return Err(anyhow!("checksum mismatch, expected: {}, got: {}", header.checksum, checksum).into());
+        }
+    }
+
+    let compressed = &buf[start..start + header.value_len as usize];
+    decode_chunked(header.compression, compressed, writer).await
+}
+
+/// Decompress `compressed` and pipe it into `writer` in chunks.
+async fn decode_chunked<W>(compression: Compression, compressed: &[u8], writer: &mut W) -> Result<()>
+where
+    W: AsyncWrite + Send + Unpin,
+{
+    match compression {
+        Compression::None => copy_chunked(compressed, writer).await,
+        Compression::Zstd => {
+            let decoder = zstd::Decoder::new(compressed).map_err(BufferError::from)?;
+            copy_chunked(decoder, writer).await
+        }
+        Compression::Lz4 => {
+            let decoder = lz4::Decoder::new(compressed).map_err(BufferError::from)?;
+            copy_chunked(decoder, writer).await
+        }
+        Compression::Snappy => {
+            let decoder = snap::read::FrameDecoder::new(compressed);
+            copy_chunked(decoder, writer).await
+        }
+    }
+}
+
+/// Decompress the compressed value received from `rx` in chunks and pipe it into `writer` in chunks, without holding the
+/// whole value in memory.
+///
+/// The decoders only pull from a reader, so the decoding runs on a blocking thread that waits for the compressed chunks.
+async fn decode_stream<B, W>(compression: Compression, rx: mpsc::Receiver<B>, writer: &mut W) -> Result<()>
+where
+    B: Deref + Send + 'static,
+    B::Target: AsRef<[u8]>,
+    W: AsyncWrite + Send + Unpin,
+{
+    /// Read the received chunks in order, blocks while the next chunk is not received yet.
+    struct ChunkReceiver<B> {
+        rx: mpsc::Receiver<B>,
+        chunk: Option<B>,
+        pos: usize,
+    }
+
+    impl<B> std::io::Read for ChunkReceiver<B>
+    where
+        B: Deref,
+        B::Target: AsRef<[u8]>,
+    {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            if buf.is_empty() {
+                return Ok(0);
+            }
+            loop {
+                if let Some(chunk) = self.chunk.as_ref() {
+                    let rest = &AsRef::<[u8]>::as_ref(chunk.deref())[self.pos..];
+                    if !rest.is_empty() {
+                        let len = std::cmp::min(rest.len(), buf.len());
+                        buf[..len].copy_from_slice(&rest[..len]);
+                        self.pos += len;
+                        return Ok(len);
+                    }
+                }
+                match self.rx.blocking_recv() {
+                    Some(chunk) => {
+                        self.chunk = Some(chunk);
+                        self.pos = 0;
+                    }
+                    None => return Ok(0),
+                }
+            }
+        }
+    }
+
+    let (tx, mut output) = mpsc::channel(1);
+    let handle = tokio::task::spawn_blocking(move || {
+        let mut reader = ChunkReceiver {
+            rx,
+            chunk: None,
+            pos: 0,
+        };
+        let mut sender = ChunkSender::new(tx);
+        match compression {
+            Compression::None => std::io::copy(&mut reader, &mut sender)?,
+            Compression::Zstd => std::io::copy(&mut zstd::Decoder::new(reader)?, &mut sender)?,
+            Compression::Lz4 => std::io::copy(&mut lz4::Decoder::new(reader)?, &mut sender)?,
+            Compression::Snappy => std::io::copy(&mut snap::read::FrameDecoder::new(reader), &mut sender)?,
+        };
+        std::io::Write::flush(&mut sender)
+    });
+
+    while let Some(chunk) = output.recv().await {
+        writer.write_all(&chunk).await.map_err(BufferError::from)?;
+    }
+    handle.await.map_err(anyhow::Error::from)?.map_err(BufferError::from)?;
+
+    Ok(())
+}
+
+/// Send the written bytes in chunks of [`STREAM_CHUNK_SIZE`], blocks while the previous chunk is not received yet.
+struct ChunkSender {
+    tx: mpsc::Sender<Vec<u8>>,
+    chunk: Vec<u8>,
+}
+
+impl ChunkSender {
+    fn new(tx: mpsc::Sender<Vec<u8>>) -> Self {
+        Self {
+            tx,
+            chunk: Vec::with_capacity(STREAM_CHUNK_SIZE),
+        }
+    }
+}
+
+impl std::io::Write for ChunkSender {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let len = std::cmp::min(buf.len(), STREAM_CHUNK_SIZE - self.chunk.len());
+        self.chunk.extend_from_slice(&buf[..len]);
+        if self.chunk.len() == STREAM_CHUNK_SIZE {
+            self.flush()?;
+        }
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        if self.chunk.is_empty() {
+            return Ok(());
+        }
+        let chunk = std::mem::replace(&mut self.chunk, Vec::with_capacity(STREAM_CHUNK_SIZE));
+        self.tx
+            .blocking_send(chunk)
+            .map_err(|_| std::io::Error::from(std::io::ErrorKind::BrokenPipe))
+    }
+}
+
+/// Split `range` of `view` into the windows whose aligned loads from the device are at most [`STREAM_CHUNK_SIZE`].
+fn stream_windows(view: &RegionView, range: Range<u32>, align: usize) -> impl Iterator<Item = Range<u32>> {
+    let offset = *view.offset() as usize;
+    let window = std::cmp::max(bits::align_down(align, STREAM_CHUNK_SIZE), align);
+    let end = range.end as usize;
+    let mut cursor = range.start as usize;
+    std::iter::from_fn(move || {
+        if cursor >= end {
+            return None;
+        }
+        let next = std::cmp::min(end, bits::align_down(align, offset + cursor) + window - offset);
+        let range = cursor as u32..next as u32;
+        cursor = next;
+        Some(range)
+    })
+}
+
+/// Serialize `value` and pipe it into `writer` in chunks of [`STREAM_CHUNK_SIZE`], without serializing the whole value
+/// into memory.
+///
+/// The value is serialized on a blocking thread, for the serializer cannot wait for `writer`.
+async fn stream_serialized<V, W>(value: Arc<V>, writer: &mut W) -> Result<()>
+where
+    V: StorageValue,
+    W: AsyncWrite + Send + Unpin,
+{
+    let (tx, mut rx) = mpsc::channel(1);
+    let handle = tokio::task::spawn_blocking(move || {
+        let mut sender = ChunkSender::new(tx);
+        bincode::serialize_into(&mut sender, value.as_ref())?;
+        std::io::Write::flush(&mut sender).map_err(bincode::Error::from)
+    });
+
+    while let Some(chunk) = rx.recv().await {
+        writer.write_all(&chunk).await.map_err(BufferError::from)?;
+    }
+    handle.await.map_err(anyhow::Error::from)?.map_err(BufferError::from)?;
+
+    Ok(())
+}
+
+/// Clamp `range` to `0..len`.
+fn clamp_range(range: Range<usize>, len: usize) -> Range<usize> {
+    let end = range.end.min(len);
+    range.start.min(end)..end
+}
+
+/// Copy all bytes from `reader` to `writer` with a chunk buffer of [`STREAM_CHUNK_SIZE`].
+async fn copy_chunked<R, W>(mut reader: R, writer: &mut W) -> Result<()>
+where
+    R: std::io::Read + Send,
+    W: AsyncWrite + Send + Unpin,
+{
+    let mut chunk = vec![0; STREAM_CHUNK_SIZE];
+    loop {
+        let len = reader.read(&mut chunk).map_err(BufferError::from)?;
+        if len == 0 {
+            return Ok(());
+        }
+        writer.write_all(&chunk[..len]).await.map_err(BufferError::from)?;
+    }
+}
+
+#[cfg(test)]
+thread_local! {
+    /// Count of checksum calculations on the current thread, only for tests.
+    static CHECKSUM_COUNT: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+
+    /// Count of region scans during recovery on the current thread, only for tests.
+    static REGION_SCANS: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+}
+
+/// Algorithm of the entry checksums.
+///
+/// The algorithm is recorded in each entry header, so entries written with different algorithms can be verified in
+/// the same store.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ChecksumAlgorithm {
+    /// 64-bit xxHash, the algorithm used by the entries written before the algorithm is configurable.
+    #[default]
+    XxHash64,
+    /// CRC-32C (Castagnoli), which is hardware accelerated on most modern CPUs, e.g. SSE 4.2 on x86_64.
+    Crc32c,
+    /// 64-bit XXH3, which is much faster than [`ChecksumAlgorithm::XxHash64`] on large values.
+    XxHash3,
+}
+
+/// Checksum of `buf` calculated by `algorithm`, as recorded in the entry headers.
+pub fn checksum(algorithm: ChecksumAlgorithm, buf: &[u8]) -> u64 {
+    #[cfg(test)]
+    CHECKSUM_COUNT.with(|count| count.set(count.get() + 1));
+
+    match algorithm {
+        ChecksumAlgorithm::XxHash64 => {
+            let mut hasher = XxHash64::with_seed(0);
+            hasher.write(buf);
+            hasher.finish()
+        }
+        ChecksumAlgorithm::Crc32c => crc32c::crc32c(buf) as u64,
+        ChecksumAlgorithm::XxHash3 => xxh3::hash64(buf),
+    }
+}
+
+/// Incremental form of [`checksum`], for the entries read in windows.
+enum Checksummer {
+    XxHash64(XxHash64),
+    Crc32c(u32),
+    XxHash3(Box<xxh3::Hash64>),
+}
+
+impl Checksummer {
+    fn new(algorithm: ChecksumAlgorithm) -> Self {
+        #[cfg(test)]
+        CHECKSUM_COUNT.with(|count| count.set(count.get() + 1));
+
+        match algorithm {
+            ChecksumAlgorithm::XxHash64 => Self::XxHash64(XxHash64::with_seed(0)),
+            ChecksumAlgorithm::Crc32c => Self::Crc32c(0),
+            ChecksumAlgorithm::XxHash3 => Self::XxHash3(Box::new(xxh3::Hash64::with_seed(0))),
+        }
+    }
+
+    fn update(&mut self, buf: &[u8]) {
+        match self {
+            Self::XxHash64(hasher) => hasher.write(buf),
+            Self::Crc32c(crc) => *crc = crc32c::crc32c_append(*crc, buf),
+            Self::XxHash3(hasher) => hasher.write(buf),
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        match self {
+            Self::XxHash64(hasher) => hasher.finish(),
+            Self::Crc32c(crc) => *crc as u64,
+            Self::XxHash3(hasher) => hasher.finish(),
+        }
+    }
+}
+
+pub struct RegionEntryIter<K, V, D>
+where
+    K: StorageKey,
+    V: StorageValue,
+    D: Device,
+{
+    region: Region<D>,
+
+    cursor: usize,
+
+    /// Verify checksum when loading kv.
+    checksum: bool,
+
+    /// Alignment of entries in the region, recorded in the region header.
+    entry_align: usize,
+
+    /// Padding applied to the values of the region, recorded in the region header.
+    size_padding: PaddingPolicy,
+
+    /// Alignment of the flushes to the region, recorded in the region header.
+    flush_alignment: Option<usize>,
+
+    /// Indexed entries of the current block that are not returned yet.
+    pending: VecDeque<(K, Item<K, V>)>,
+
+    /// Offset and the decompressed last block loaded by `next_kv`.
+    block: Option<(u32, DecodedBlock<K>)>,
+
+    /// Start offset and the data of the range loaded ahead by the last read, see [`Device::read_io_size`].
+    #[allow(clippy::type_complexity)]
+    window: Option<(usize, Arc<VecA<u8, D::IoBufferAllocator>>)>,
+
+    /// Bytes of the entries returned so far, a block is counted once for all its entries.
+    bytes: usize,
+
+    /// Whether the iteration stopped at an entry that can't be decoded.
+    corrupted: bool,
+
+    /// Verify the checksum of each entry on iteration, and skip the entries that fail.
+    verify_entries: bool,
+
+    /// Count of the entries skipped for checksum mismatch.
+    checksum_mismatches: usize,
+
+    _marker: PhantomData<(K, V)>,
+}
+
+impl<K, V, D> RegionEntryIter<K, V, D>
+where
+    K: StorageKey,
+    V: StorageValue,
+    D: Device,
+{
+    pub async fn open(region: Region<D>, checksum: bool) -> Result<Option<Self>> {
+        let align = region.device().align();
+
+        let slice = match region.load_range(0..align).await? {
+            Some(slice) => slice,
+            None => return Ok(None),
+        };
+
+        let Ok(header) = RegionHeader::read(slice.as_ref()) else {
+            return Ok(None);
+        };
+
+        Ok(Some(Self::with_header(region, checksum, header, None)))
+    }
+
+    /// Open the iterator on the data of the whole region loaded ahead, the entries are parsed without extra reads.
+    pub fn open_loaded(region: Region<D>, checksum: bool, data: Arc<VecA<u8, D::IoBufferAllocator>>) -> Option<Self> {
+        let align = region.device().align();
+        if data.len() < align {
+            return None;
+        }
+        let header = RegionHeader::read(&data[..align]).ok()?;
+        Some(Self::with_header(region, checksum, header, Some((0, data))))
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn with_header(
+        region: Region<D>,
+        checksum: bool,
+        header: RegionHeader,
+        window: Option<(usize, Arc<VecA<u8, D::IoBufferAllocator>>)>,
+    ) -> Self {
+        let align = region.device().align();
+        Self {
+            region,
+            cursor: header.first_entry_offset.map(|offset| offset as usize).unwrap_or(align),
+            checksum,
+            entry_align: header
+                .intra_block_align
+                .map(|intra_block_align| intra_block_align as usize)
+                .unwrap_or(align),
+            size_padding: header.size_padding,
+            flush_alignment: header.flush_alignment.map(|alignment| alignment as usize),
+            pending: VecDeque::new(),
+            block: None,
+            window,
+            bytes: 0,
+            corrupted: false,
+            verify_entries: false,
+            checksum_mismatches: 0,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Bytes of the entries returned so far.
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+
+    /// Whether the iteration stopped at an entry with a valid header but a body that can't be decoded, e.g. a torn
+    /// write, rather than at the end of the written data.
+    pub fn corrupted(&self) -> bool {
+        self.corrupted
+    }
+
+    /// Verify the checksum of each entry on iteration instead of on loading kv, the entries that fail are skipped and
+    /// counted by [`RegionEntryIter::checksum_mismatches`].
+    pub fn with_entry_verification(mut self, verify: bool) -> Self {
+        self.verify_entries = verify;
+        self
+    }
+
+    /// Count of the entries skipped for checksum mismatch so far.
+    pub fn checksum_mismatches(&self) -> usize {
+        self.checksum_mismatches
+    }
+
+    pub async fn next(&mut self) -> Result<Option<(K, Item<K, V>)>> {
+        if let Some(res) = self.pending.pop_front() {
+            return Ok(Some(res));
+        }
+
+        let region_size = self.region.device().region_size();
+        let align = self.region.device().align();
+
+        let header = loop {
+            let block_start = bits::align_down(align, self.cursor);
+
+            // The last block of the region may hold an entry as well.
+            if block_start + align > region_size {
+                return Ok(None);
+            }
+
+            // The entry header may cross the block boundary, which happens only with entries packed back-to-back.
+            let header_end = bits::align_up(align, self.cursor + EntryHeader::serialized_len_with_expire_at());
+            let Some((slice, base)) = self.load_window(block_start..header_end).await? else {
+                return Ok(None);
+            };
+
+            match EntryHeader::read(&slice.as_ref()[self.cursor - base..]) {
+                // The full key can't be rebuilt from the key hash, skip the entry.
+                Ok(header) if header.key_hash => self.cursor += self.entry_len(&header),
+                Ok(header) => {
+                    if self.verify_entries && !self.verify_entry(&header).await? {
+                        // The header is intact, so the following entries are still reachable.
+                        self.checksum_mismatches += 1;
+                        self.cursor += self.entry_len(&header);
+                        continue;
+                    }
+                    break header;
+                }
+                // The rest of a block with packed entries is padding, skip to the next block.
+                Err(_) if self.cursor != block_start => self.cursor = block_start + align,
+                // The rest of a flush is padding to the flush alignment, skip to the next flush.
+                Err(_)
+                    if self
+                        .flush_alignment
+                        .is_some_and(|alignment| !bits::is_aligned(alignment, self.cursor)) =>
+                {
+                    self.cursor = bits::align_up(self.flush_alignment.unwrap(), self.cursor)
+                }
+                Err(_) => return Ok(None),
+            }
+        };
+
+        let entry_len = self.entry_len(&header);
+
+        let abs_start = self.cursor + header.encoded_len() + header.value_len as usize;
+        let abs_end = self.cursor + header.encoded_len() + header.key_len as usize + header.value_len as usize;
+
+        // The key may be zero-sized, e.g. `()`.
+        if abs_start > abs_end || abs_end > region_size {
+            // Double check wrong entry.
+            self.corrupted = true;
+            return Ok(None);
+        }
+
+        let align_start = bits::align_down(align, abs_start);
+        let align_end = bits::align_up(align, abs_end).max(align_start + align);
+
+        // The key is usually loaded ahead with the header.
+        let Some((slice, base)) = self.load_window(align_start..align_end).await? else {
+            return Ok(None);
+        };
+        let (rel_start, rel_end) = (abs_start - base, abs_end - base);
+
+        if header.block {
+            // The key part of a block is the metas of its entries, index all of them.
+            let Ok(metas) = bincode::deserialize_from::<_, Vec<BlockMeta<K>>>(&slice.as_ref()[rel_start..rel_end])
+            else {
+                self.corrupted = true;
+                return Ok(None);
+            };
+            drop(slice);
+
+            let view = self.region.view(self.cursor as u32, entry_len as u32);
+            for (index, (key, sequence, _)) in metas.into_iter().enumerate() {
+                let index = Index::Block {
+                    view: view.clone(),
+                    index: index as u32,
+                };
+                self.pending.push_back((key, Item::new(sequence, index)));
+            }
+            drop(view);
+
+            self.cursor += entry_len;
+            self.bytes += entry_len;
+
+            return Ok(self.pending.pop_front());
+        }
+
+        let Ok(key) = bincode::deserialize_from(&slice.as_ref()[rel_start..rel_end]) else {
+            self.corrupted = true;
+            return Ok(None);
+        };
+        drop(slice);
+
+        let info = Item::new(
+            header.sequence,
+            Index::Region {
+                view: self.region.view(self.cursor as u32, entry_len as u32),
+            },
+        )
+        .with_expire_at(header.expire_at.map(from_timestamp));
+
+        self.cursor += entry_len;
+        self.bytes += entry_len;
+
+        Ok(Some((key, info)))
+    }
+
+    /// Verify the checksum of the value and the key of the entry at the cursor.
+    ///
+    /// Returns `true` if the entry is written without checksum, or its length runs beyond the region, which is left to
+    /// be detected as corrupted.
+    async fn verify_entry(&mut self, header: &EntryHeader) -> Result<bool> {
+        if !header.checksummed {
+            return Ok(true);
+        }
+
+        let align = self.region.device().align();
+        let start = self.cursor + header.encoded_len();
+        let end = start + header.value_len as usize + header.key_len as usize;
+        if end > self.region.device().region_size() {
+            return Ok(true);
+        }
+
+        let align_start = bits::align_down(align, start);
+        let align_end = bits::align_up(align, end).max(align_start + align);
+        let Some((slice, base)) = self.load_window(align_start..align_end).await? else {
+            return Ok(true);
+        };
+        Ok(checksum(header.checksum_algorithm, &slice.as_ref()[start - base..end - base]) == header.checksum)
+    }
+
+    /// Load the aligned `range` of the region, from the read-ahead window if it covers the range.
+    ///
+    /// Otherwise, a new window of at least [`Device::read_io_size`] is loaded from the start of the range. Returns the
+    /// data and its start offset in the region.
+    // TODO(MrCroxx): use `expect` after `lint_reasons` is stable.
+    #[allow(clippy::type_complexity)]
+    async fn load_window(
+        &mut self,
+        range: Range<usize>,
+    ) -> Result<Option<(Arc<VecA<u8, D::IoBufferAllocator>>, usize)>> {
+        if let Some((start, data)) = self.window.as_ref() {
+            if *start <= range.start && range.end <= *start + data.len() {
+                return Ok(Some((data.clone(), *start)));
+            }
+        }
+
+        let device = self.region.device();
+        let end = range
+            .end
+            .max(range.start + device.read_io_size())
+            .min(device.region_size())
+            .max(range.end);
+        let data = match self.region.load_range(range.start..end).await? {
+            Some(data) => data,
+            // The window may exceed the written part of the region, fall back to the requested range.
+            None if end > range.end => match self.region.load_range(range.clone()).await? {
+                Some(data) => data,
+                None => return Ok(None),
+            },
+            None => return Ok(None),
+        };
+        self.window = Some((range.start, data.clone()));
+        Ok(Some((data, range.start)))
+    }
+
+    /// Aligned length of the entry in the region, including the value padding.
+    fn entry_len(&self, header: &EntryHeader) -> usize {
+        bits::align_up(
+            self.entry_align,
+            self.size_padding.padded_len(header.value_len as usize) + header.key_len as usize + header.encoded_len(),
+        )
+    }
+
+    pub async fn next_kv(&mut self) -> Result<Option<(K, V, usize)>> {
+        let (key, item) = match self.next().await {
+            Ok(Some(res)) => res,
+            Ok(None) => return Ok(None),
+            Err(e) => return Err(e),
+        };
+
+        let view = match item.index() {
+            Index::Region { view } => view,
+            Index::Block { view, index } => return self.next_block_kv(key, view, *index as usize).await,
+            Index::Inflight { .. } => unreachable!("kv loaded from region must have index of region"),
+        };
+
+        // TODO(MrCroxx): Optimize if all key, value and footer are in the same read block.
+        let Some(slice) = self.region.load(view.clone()).await? else {
+            return Ok(None);
+        };
+        let res = match read_entry::<K, V>(slice.as_ref(), self.checksum) {
+            Ok((EntryKey::Key(k), v)) => Some((k, v, slice.len())),
+            Ok((EntryKey::Hash(_), _)) | Err(_) => None,
+        };
+        drop(slice);
+
+        Ok(res)
+    }
+
+    /// Load the kv of the entry compressed in a block, the block is decompressed once for all its entries.
+    ///
+    /// The returned size is the size of the block divided by its entry count.
+    async fn next_block_kv(&mut self, key: K, view: &RegionView, index: usize) -> Result<Option<(K, V, usize)>> {
+        if self.block.as_ref().map(|(offset, _)| *offset) != Some(*view.offset()) {
+            let Some(slice) = self.region.load(view.clone()).await? else {
+                return Ok(None);
+            };
+            let Ok(block) = DecodedBlock::decode(slice.as_ref(), self.checksum) else {
+                return Ok(None);
+            };
+            self.block = Some((*view.offset(), block));
+        }
+        let (_, block) = self.block.as_ref().unwrap();
+
+        let size = *view.len() as usize / (index + 1 + self.pending.len());
+        Ok(block.value(index).ok().map(|value| (key, value, size)))
+    }
+}
+
+impl<K, V, D> StorageWriter<K, V> for GenericStoreWriter<K, V, D>
+where
+    K: StorageKey,
+    V: StorageValue,
+    D: Device,
+{
+    fn key(&self) -> &K {
+        self.key.as_ref().unwrap()
+    }
+
+    fn judge(&mut self) -> bool {
+        self.judge()
+    }
+
+    fn force(&mut self) {
+        self.force()
+    }
+
+    async fn finish<AV>(self, value: AV) -> Result<Option<CachedEntry<K, V>>>
+    where
+        AV: Into<Arc<V>> + Send + 'static,
+    {
+        self.finish(value).await
+    }
+
+    fn compression(&self) -> Compression {
+        self.compression()
+    }
+
+    fn set_compression(&mut self, compression: Compression) {
+        self.set_compression(compression)
+    }
+
+    fn expire_at(&self) -> Option<SystemTime> {
+        self.expire_at()
+    }
+
+    fn set_expire_at(&mut self, expire_at: SystemTime) {
+        self.set_expire_at(expire_at)
+    }
+}
+
+impl<K, V, D> Storage<K, V> for GenericStore<K, V, D>
+where
+    K: StorageKey,
+    V: StorageValue,
+    D: Device,
+{
+    type Config = GenericStoreConfig<K, V, D>;
+    type Writer = GenericStoreWriter<K, V, D>;
+
+    async fn open(config: Self::Config) -> Result<Self> {
+        Self::open(config).await
+    }
+
+    fn is_ready(&self) -> bool {
+        true
+    }
+
+    async fn close(&self) -> Result<()> {
+        self.close().await
+    }
+
+    fn writer<AK>(&self, key: AK) -> Self::Writer
+    where
+        AK: Into<Arc<K>> + Send + 'static,
+    {
+        self.writer(key)
+    }
+
+    async fn insert_batch(&self, entries: Vec<(Arc<K>, Arc<V>)>) -> Result<Vec<Option<CachedEntry<K, V>>>> {
+        self.insert_batch(entries).await
+    }
+
+    fn exists<Q>(&self, key: &Q) -> Result<bool>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.exists(key)
+    }
+
+    fn touch<Q>(&self, key: &Q) -> Result<bool>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.touch(key)
+    }
+
+    fn recent_inserts(&self, n: usize) -> Vec<(Arc<K>, Sequence)> {
+        self.recent_inserts(n)
+    }
+
+    async fn get<Q>(&self, key: &Q) -> Result<Option<CachedEntry<K, V>>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.get(key).await
+    }
+
+    async fn get_stream<Q, W>(&self, key: &Q, writer: W) -> Result<bool>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + Send + Sync + 'static + Clone,
+        W: AsyncWrite + Send + Unpin + 'static,
+    {
+        self.get_stream(key, writer).await
+    }
+
+    async fn get_range<Q>(&self, key: &Q, range: Range<usize>) -> Result<Option<Vec<u8>>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + Send + Sync + 'static + Clone,
+    {
+        self.get_range(key, range).await
+    }
+
+    fn remove<Q>(&self, key: &Q) -> Result<bool>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.remove(key)
+    }
+
+    fn clear(&self) -> Result<()> {
+        self.clear()
+    }
+
+    async fn snapshot_catalog(&self, path: impl AsRef<Path> + Send) -> Result<()> {
+        self.snapshot_catalog(path).await
+    }
+
+    fn entries_since(&self, sequence: Sequence) -> BoxStream<'static, Result<(Arc<K>, Arc<V>, Sequence)>> {
+        self.entries_since(sequence)
+    }
+
+    fn scan(&self) -> BoxStream<'static, Result<(K, V)>> {
+        self.scan()
+    }
+
+    fn barrier(&self) -> BoxFuture<'static, Result<()>> {
+        self.barrier()
+    }
+
+    fn drain(&self) -> BoxFuture<'static, Result<Vec<DrainedEntry<K>>>> {
+        self.drain()
+    }
+
+    fn scrub(&self, remove_corrupted: bool) -> BoxFuture<'static, Result<ScrubReport<K>>> {
+        self.scrub(remove_corrupted)
+    }
+
+    fn compact(&self, strategy: CompactionStrategy) -> BoxFuture<'static, Result<CompactionReport>> {
+        self.compact(strategy)
+    }
+    fn read_region_bytes(&self, region: RegionId) -> BoxFuture<'static, Result<Vec<u8>>> {
+        self.read_region_bytes(region)
+    }
+
+    fn apply_region_bytes(&self, region: RegionId, bytes: Vec<u8>) -> BoxFuture<'static, Result<()>> {
+        self.apply_region_bytes(region, bytes)
+    }
+
+    fn shrink(&self, capacity: usize) -> BoxFuture<'static, Result<()>> {
+        self.shrink(capacity)
+    }
+
+    fn rebuild_region_filters(&self) -> BoxFuture<'static, Result<usize>> {
+        self.rebuild_region_filters()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashSet, path::PathBuf, sync::atomic::AtomicUsize};
+
+    use foyer_memory::FifoConfig;
+
+    use super::*;
+    use crate::{
+        catalog::CatalogRecord,
+        device::{
+            executor::{BlockingExecutor, BlockingJob, IoExecutor},
+            fs::{FsDevice, FsDeviceConfig, RegionOpenErrorPolicy},
+            mem::{MemDevice, MemDeviceConfig},
+            tests::{FlakyDevice, NullDevice, SlowDevice},
+        },
+        metrics::{MetricKey, INNER_OP_DURATION, OP_BYTES, OP_DURATION, RECOVER_ENTRIES, TOTAL_BYTES},
+        region::{Version, REGION_MAGIC},
+        reinsertion::exist::ExistReinsertionPolicy,
+        storage::StorageExt,
+        test_utils::{self, base_config, JudgeRecorder},
+    };
+
+    type TestStore = GenericStore<u64, Vec<u8>, FsDevice>;
+    type TestStoreConfig = GenericStoreConfig<u64, Vec<u8>, FsDevice>;
+
+    #[tokio::test]
+    // TODO(MrCroxx): use `expect` after `lint_reasons` is stable.
+    #[allow(clippy::identity_op)]
+    async fn test_recovery() {
+        const KB: usize = 1024;
+        const MB: usize = 1024 * 1024;
+
+        let tempdir = tempfile::tempdir().unwrap();
+
+        let recorder = Arc::new(JudgeRecorder::default());
+        let admissions: Vec<Arc<dyn AdmissionPolicy<Key = u64, Value = Vec<u8>>>> = vec![recorder.clone()];
+        let reinsertions: Vec<Arc<dyn ReinsertionPolicy<Key = u64, Value = Vec<u8>>>> = vec![recorder.clone()];
+
+        let config = TestStoreConfig {
+            name: "".to_string(),
+            eviction_config: FifoConfig {}.into(),
+            device_config: FsDeviceConfig {
+                dir: PathBuf::from(tempdir.path()),
+                capacity: 16 * MB,
+                file_size: 4 * MB,
+                align: 4 * KB,
+                io_size: 4 * KB,
+                io_executor: IoExecutor::default(),
+                on_region_open_error: RegionOpenErrorPolicy::default(),
+                write_io_size: None,
+                read_io_size: None,
+                extra_dirs: vec![],
+                direct_io: true,
+            },
+            catalog_shards: 1,
+            admissions,
+            reinsertions,
+            flushers: 1,
+            reclaimers: 1,
+            recover_concurrency: 2,
+            clean_region_threshold: 1,
+            compression: Compression::None,
+            checksum: true,
+            recent_inserts_capacity: 0,
+            max_key_size: None,
+            region_compression: None,
+            intra_block_align: None,
+            catalog_snapshot_path: None,
+            reclaim_grace_period: Duration::ZERO,
+            compression_observer: None,
+            unknown_region_version_policy: UnknownRegionVersionPolicy::default(),
+            block_compression: None,
+            background_cpu_cores: None,
+            metrics_recorder: None,
+            write_conflict_policy: WriteConflictPolicy::default(),
+            catalog_ops_limit: None,
+            first_entry_offset: None,
+            verify_region_magic_on_read: false,
+            self_test_on_open: false,
+            scrub_throughput_limit: None,
+            write_coalescing_interval: None,
+            max_writing_regions: None,
+            size_padding: PaddingPolicy::default(),
+            store_key_hash_only: false,
+            zstd_level: 0,
+            max_concurrent_decompressions: None,
+            checksum_algorithm: ChecksumAlgorithm::default(),
+            key_prefix: None,
+            flush_error_policy: FlushErrorPolicy::default(),
+            admission_observer: None,
+            max_region_age: None,
+            channel_drop_policy: ChannelDropPolicy::default(),
+            prefix_compressions: HashMap::new(),
+            io_bytes_in_flight_limit: None,
+            flusher_queue_capacity: None,
+            queue_full_policy: QueueFullPolicy::default(),
+            flush_alignment: None,
+            adaptive_compression: false,
+            scan_read_ahead: 2,
+            verify_checksum_on_recover: false,
+            catalog_backend: None,
+            catalog_retry_policy: CatalogRetryPolicy::default(),
+        };
+
+        let store = TestStore::open(config).await.unwrap();
+
+        // files:
+        // [0, 1, 2]
+        // [3, 4, 5]
+        // [6, 7, 8]
+        // [9, 10, 11]
+        // ... ...
+        for i in 0..21 {
+            store.insert(i, vec![i as u8; 1 * MB]).await.unwrap();
+        }
+
+        store.close().await.unwrap();
+
+        let remains = recorder.remains();
+
+        for i in 0..21 {
+            if remains.contains(&i) {
+                assert_eq!(store.get(&i).await.unwrap().unwrap().value(), &vec![i as u8; 1 * MB],);
+            } else {
+                assert!(store.get(&i).await.unwrap().is_none());
+            }
+        }
+
+        drop(store);
+
+        let config = TestStoreConfig {
+            name: "".to_string(),
+            eviction_config: FifoConfig {}.into(),
+            device_config: FsDeviceConfig {
+                dir: PathBuf::from(tempdir.path()),
+                capacity: 16 * MB,
+                file_size: 4 * MB,
+                align: 4096,
+                io_size: 4096 * KB,
+                io_executor: IoExecutor::default(),
+                on_region_open_error: RegionOpenErrorPolicy::default(),
+                write_io_size: None,
+                read_io_size: None,
+                extra_dirs: vec![],
+                direct_io: true,
+            },
+            catalog_shards: 1,
+            admissions: vec![],
+            reinsertions: vec![],
+            flushers: 1,
+            reclaimers: 0,
+            recover_concurrency: 2,
+            clean_region_threshold: 1,
+            compression: Compression::None,
+            checksum: true,
+            recent_inserts_capacity: 0,
+            max_key_size: None,
+            region_compression: None,
+            intra_block_align: None,
+            catalog_snapshot_path: None,
+            reclaim_grace_period: Duration::ZERO,
+            compression_observer: None,
+            unknown_region_version_policy: UnknownRegionVersionPolicy::default(),
+            block_compression: None,
+            background_cpu_cores: None,
+            metrics_recorder: None,
+            write_conflict_policy: WriteConflictPolicy::default(),
+            catalog_ops_limit: None,
+            first_entry_offset: None,
+            verify_region_magic_on_read: false,
+            self_test_on_open: false,
+            scrub_throughput_limit: None,
+            write_coalescing_interval: None,
+            max_writing_regions: None,
+            size_padding: PaddingPolicy::default(),
+            store_key_hash_only: false,
+            zstd_level: 0,
+            max_concurrent_decompressions: None,
+            checksum_algorithm: ChecksumAlgorithm::default(),
+            key_prefix: None,
+            flush_error_policy: FlushErrorPolicy::default(),
+            admission_observer: None,
+            max_region_age: None,
+            channel_drop_policy: ChannelDropPolicy::default(),
+            prefix_compressions: HashMap::new(),
+            io_bytes_in_flight_limit: None,
+            flusher_queue_capacity: None,
+            queue_full_policy: QueueFullPolicy::default(),
+            flush_alignment: None,
+            adaptive_compression: false,
+            scan_read_ahead: 2,
+            verify_checksum_on_recover: false,
+            catalog_backend: None,
+            catalog_retry_policy: CatalogRetryPolicy::default(),
+        };
+        let store = TestStore::open(config).await.unwrap();
+
+        for i in 0..21 {
+            if remains.contains(&i) {
+                assert_eq!(store.get(&i).await.unwrap().unwrap().value(), &vec![i as u8; 1 * MB],);
+            } else {
+                assert!(store.get(&i).await.unwrap().is_none());
+            }
+        }
+
+        store.close().await.unwrap();
+
+        drop(store);
+    }
+
+    /// A writer that collects written bytes and records the max size of a single write.
+    ///
+    /// If `reads` is given, its value on the first write is recorded to `reads_before_write`, e.g. the bytes read from
+    /// the device before the first write.
+    #[derive(Debug, Default)]
+    struct ChunkRecorder {
+        buf: Arc<Mutex<Vec<u8>>>,
+        max_write: Arc<AtomicU64>,
+        reads: Option<Arc<AtomicUsize>>,
+        reads_before_write: Arc<AtomicUsize>,
+    }
+
+    impl AsyncWrite for ChunkRecorder {
+        fn poll_write(
+            self: std::pin::Pin<&mut Self>,
+            _: &mut std::task::Context<'_>,
+            buf: &[u8],
+        ) -> std::task::Poll<std::io::Result<usize>> {
+            self.max_write.fetch_max(buf.len() as u64, Ordering::Relaxed);
+            let mut written = self.buf.lock();
+            if let Some(reads) = self.reads.as_ref().filter(|_| written.is_empty() && !buf.is_empty()) {
+                self.reads_before_write
+                    .store(reads.load(Ordering::Relaxed), Ordering::Relaxed);
+            }
+            written.extend_from_slice(buf);
+            std::task::Poll::Ready(Ok(buf.len()))
+        }
+
+        fn poll_flush(
+            self: std::pin::Pin<&mut Self>,
+            _: &mut std::task::Context<'_>,
+        ) -> std::task::Poll<std::io::Result<()>> {
+            std::task::Poll::Ready(Ok(()))
+        }
+
+        fn poll_shutdown(
+            self: std::pin::Pin<&mut Self>,
+            _: &mut std::task::Context<'_>,
+        ) -> std::task::Poll<std::io::Result<()>> {
+            std::task::Poll::Ready(Ok(()))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_get_stream() {
+        const KB: usize = 1024;
+        const MB: usize = 1024 * 1024;
+
+        let tempdir = tempfile::tempdir().unwrap();
+
+        let config = TestStoreConfig {
+            name: "".to_string(),
+            eviction_config: FifoConfig {}.into(),
+            device_config: FsDeviceConfig {
+                dir: PathBuf::from(tempdir.path()),
+                capacity: 32 * MB,
+                file_size: 8 * MB,
+                align: 4 * KB,
+                io_size: 64 * KB,
+                io_executor: IoExecutor::default(),
+                on_region_open_error: RegionOpenErrorPolicy::default(),
+                write_io_size: None,
+                read_io_size: None,
+                extra_dirs: vec![],
+                direct_io: true,
+            },
+            catalog_shards: 1,
+            admissions: vec![],
+            reinsertions: vec![],
+            flushers: 1,
+            reclaimers: 1,
+            recover_concurrency: 2,
+            clean_region_threshold: 1,
+            compression: Compression::Zstd,
+            checksum: true,
+            recent_inserts_capacity: 0,
+            max_key_size: None,
+            region_compression: None,
+            intra_block_align: None,
+            catalog_snapshot_path: None,
+            reclaim_grace_period: Duration::ZERO,
+            compression_observer: None,
+            unknown_region_version_policy: UnknownRegionVersionPolicy::default(),
+            block_compression: None,
+            background_cpu_cores: None,
+            metrics_recorder: None,
+            write_conflict_policy: WriteConflictPolicy::default(),
+            catalog_ops_limit: None,
+            first_entry_offset: None,
+            verify_region_magic_on_read: false,
+            self_test_on_open: false,
+            scrub_throughput_limit: None,
+            write_coalescing_interval: None,
+            max_writing_regions: None,
+            size_padding: PaddingPolicy::default(),
+            store_key_hash_only: false,
+            zstd_level: 0,
+            max_concurrent_decompressions: None,
+            checksum_algorithm: ChecksumAlgorithm::default(),
+            key_prefix: None,
+            flush_error_policy: FlushErrorPolicy::default(),
+            admission_observer: None,
+            max_region_age: None,
+            channel_drop_policy: ChannelDropPolicy::default(),
+            prefix_compressions: HashMap::new(),
+            io_bytes_in_flight_limit: None,
+            flusher_queue_capacity: None,
+            queue_full_policy: QueueFullPolicy::default(),
+            flush_alignment: None,
+            adaptive_compression: false,
+            scan_read_ahead: 2,
+            verify_checksum_on_recover: false,
+            catalog_backend: None,
+            catalog_retry_policy: CatalogRetryPolicy::default(),
+        };
+
+        let store = TestStore::open(config.clone()).await.unwrap();
+
+        let value = (0..4 * MB).map(|i| (i % 251) as u8).collect_vec();
+        let expected = bincode::serialize(&value).unwrap();
+        store.insert(1, value).await.unwrap();
+
+        // inflight entry
+        let recorder = ChunkRecorder::default();
+        let (buf, max_write) = (recorder.buf.clone(), recorder.max_write.clone());
+        assert!(store.get_stream(&1, recorder).await.unwrap());
+        assert_eq!(*buf.lock(), expected);
+        assert!(max_write.load(Ordering::Relaxed) as usize <= STREAM_CHUNK_SIZE);
+
+        // flushed entry, reopen the store to make sure the entry is read from the device
+        store.close().await.unwrap();
+        drop(store);
+
+        let store = TestStore::open(config).await.unwrap();
+        assert!(matches!(store.catalog().get(&1).unwrap().index(), Index::Region { .. }));
+
+        let recorder = ChunkRecorder::default();
+        let (buf, max_write) = (recorder.buf.clone(), recorder.max_write.clone());
+        assert!(store.get_stream(&1, recorder).await.unwrap());
+        assert_eq!(*buf.lock(), expected);
+        assert!(max_write.load(Ordering::Relaxed) as usize <= STREAM_CHUNK_SIZE);
+
+        assert!(!store.get_stream(&2, ChunkRecorder::default()).await.unwrap());
+
+        store.close().await.unwrap();
+        drop(store);
+    }
+
+    #[tokio::test]
+    async fn test_get_stream_bounded_reads() {
+        const KB: usize = 1024;
+        const MB: usize = 1024 * 1024;
+
+        for compression in [
+            Compression::None,
+            Compression::Zstd,
+            Compression::Lz4,
+            Compression::Snappy,
+        ] {
+            let store = GenericStore::open(GenericStoreConfig::<u64, Vec<u8>, FlakyDevice> {
+                compression,
+                // Skip the checksum pass, so the value is read only once, by the streaming.
+                checksum: false,
+                ..base_config((
+                    MemDeviceConfig {
+                        capacity: 32 * MB,
+                        region_size: 8 * MB,
+                        align: 4 * KB,
+                        io_size: 16 * KB,
+                        write_io_size: None,
+                        read_io_size: None,
+                    },
+                    Arc::new(AtomicUsize::new(0)),
+                ))
+            })
+            .await
+            .unwrap();
+
+            // Hardly compressible, so the compressed value takes about as many windows as the raw one.
+            let mut state = 0x2545f4914f6cdd1du64;
+            let value = (0..2 * MB + 3 * KB)
+                .map(|_| {
+                    state ^= state << 13;
+                    state ^= state >> 7;
+                    state ^= state << 17;
+                    state as u8
+                })
+                .collect_vec();
+            let expected = bincode::serialize(&value).unwrap();
+            store.insert(1, value).await.unwrap();
+            store.barrier().await.unwrap();
+            assert!(matches!(store.catalog().get(&1).unwrap().index(), Index::Region { .. }));
+
+            store.device().take_max_read();
+            let reads = store.device().read_bytes().clone();
+            let start = reads.load(Ordering::Relaxed);
+            let recorder = ChunkRecorder {
+                reads: Some(reads.clone()),
+                ..Default::default()
+            };
+            let (buf, max_write, reads_before_write) = (
+                recorder.buf.clone(),
+                recorder.max_write.clone(),
+                recorder.reads_before_write.clone(),
+            );
+            assert!(store.get_stream(&1, recorder).await.unwrap());
+            assert_eq!(*buf.lock(), expected);
+            assert!(max_write.load(Ordering::Relaxed) as usize <= STREAM_CHUNK_SIZE);
+            let max_read = store.device().take_max_read();
+            assert!(max_read > 0 && max_read <= STREAM_CHUNK_SIZE, "max read: {max_read}");
+            // Only the windows buffered by the channel and the decoder are read ahead of the first write, rather than the
+            // whole compressed value.
+            let read = reads.load(Ordering::Relaxed) - start;
+            let read_ahead = reads_before_write.load(Ordering::Relaxed) - start;
+            assert!(read > 2 * MB, "read: {read}");
+            assert!(
+                read_ahead <= 8 * STREAM_CHUNK_SIZE,
+                "{compression:?} read ahead: {read_ahead}"
+            );
+
+            store.close().await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_read_pending_entry() {
+        const KB: usize = 1024;
+        const MB: usize = 1024 * 1024;
+
+        let tempdir = tempfile::tempdir().unwrap();
+
+        let config = TestStoreConfig {
+            name: "".to_string(),
+            eviction_config: FifoConfig {}.into(),
+            device_config: FsDeviceConfig {
+                dir: PathBuf::from(tempdir.path()),
+                capacity: 16 * MB,
+                file_size: 4 * MB,
+                align: 4 * KB,
+                io_size: 64 * KB,
+                io_executor: IoExecutor::default(),
+                on_region_open_error: RegionOpenErrorPolicy::default(),
+                write_io_size: None,
+                read_io_size: None,
+                extra_dirs: vec![],
+                direct_io: true,
+            },
+            catalog_shards: 1,
+            admissions: vec![],
+            reinsertions: vec![],
+            flushers: 1,
+            reclaimers: 1,
+            recover_concurrency: 2,
+            clean_region_threshold: 1,
+            compression: Compression::None,
+            checksum: true,
+            recent_inserts_capacity: 0,
+            max_key_size: None,
+            region_compression: None,
+            intra_block_align: None,
+            catalog_snapshot_path: None,
+            reclaim_grace_period: Duration::ZERO,
+            compression_observer: None,
+            unknown_region_version_policy: UnknownRegionVersionPolicy::default(),
+            block_compression: None,
+            background_cpu_cores: None,
+            metrics_recorder: None,
+            write_conflict_policy: WriteConflictPolicy::default(),
+            catalog_ops_limit: None,
+            first_entry_offset: None,
+            verify_region_magic_on_read: false,
+            self_test_on_open: false,
+            scrub_throughput_limit: None,
+            write_coalescing_interval: None,
+            max_writing_regions: None,
+            size_padding: PaddingPolicy::default(),
+            store_key_hash_only: false,
+            zstd_level: 0,
+            max_concurrent_decompressions: None,
+            checksum_algorithm: ChecksumAlgorithm::default(),
+            key_prefix: None,
+            flush_error_policy: FlushErrorPolicy::default(),
+            admission_observer: None,
+            max_region_age: None,
+            channel_drop_policy: ChannelDropPolicy::default(),
+            prefix_compressions: HashMap::new(),
+            io_bytes_in_flight_limit: None,
+            flusher_queue_capacity: None,
+            queue_full_policy: QueueFullPolicy::default(),
+            flush_alignment: None,
+            adaptive_compression: false,
+            scan_read_ahead: 2,
+            verify_checksum_on_recover: false,
+            catalog_backend: None,
+            catalog_retry_policy: CatalogRetryPolicy::default(),
+        };
+
+        let store = TestStore::open(config.clone()).await.unwrap();
+
+        // The entries are far below the write io size, they are kept in the flush buffer after handled by the flusher.
+        for i in 0..4u64 {
+            store.insert(i, vec![i as u8; KB]).await.unwrap();
+        }
+        while store.inner.flusher_queue.len() > 0 {
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+
+        for i in 0..4u64 {
+            assert!(matches!(
+                store.catalog().get(&i).unwrap().index(),
+                Index::Inflight { .. }
+            ));
+            assert_eq!(store.get(&i).await.unwrap().unwrap().value(), &vec![i as u8; KB]);
+        }
+
+        // The entries are read from the device once flushed.
+        store.barrier().await.unwrap();
+        for i in 0..4u64 {
+            assert!(matches!(store.catalog().get(&i).unwrap().index(), Index::Region { .. }));
+            assert_eq!(store.get(&i).await.unwrap().unwrap().value(), &vec![i as u8; KB]);
+        }
+
+        store.close().await.unwrap();
+        drop(store);
+
+        let store = TestStore::open(config).await.unwrap();
+        for i in 0..4u64 {
+            assert_eq!(store.get(&i).await.unwrap().unwrap().value(), &vec![i as u8; KB]);
+        }
+        store.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_io_bytes_in_flight_limit() {
+        const KB: usize = 1024;
+        const MB: usize = 1024 * 1024;
+        const LIMIT: usize = 32 * KB;
+
+        let tempdir = tempfile::tempdir().unwrap();
+
+        let config = TestStoreConfig {
+            name: "".to_string(),
+            eviction_config: FifoConfig {}.into(),
+            device_config: FsDeviceConfig {
+                dir: PathBuf::from(tempdir.path()),
+                capacity: 16 * MB,
+                file_size: 4 * MB,
+                align: 4 * KB,
+                io_size: 16 * KB,
+                io_executor: IoExecutor::default(),
+                on_region_open_error: RegionOpenErrorPolicy::default(),
+                write_io_size: None,
+                read_io_size: None,
+                extra_dirs: vec![],
+                direct_io: true,
+            },
+            catalog_shards: 1,
+            admissions: vec![],
+            reinsertions: vec![],
+            flushers: 1,
+            reclaimers: 1,
+            recover_concurrency: 2,
+            clean_region_threshold: 1,
+            compression: Compression::None,
+            checksum: true,
+            recent_inserts_capacity: 0,
+            max_key_size: None,
+            region_compression: None,
+            intra_block_align: None,
+            catalog_snapshot_path: None,
+            reclaim_grace_period: Duration::ZERO,
+            compression_observer: None,
+            unknown_region_version_policy: UnknownRegionVersionPolicy::default(),
+            block_compression: None,
+            background_cpu_cores: None,
+            metrics_recorder: None,
+            write_conflict_policy: WriteConflictPolicy::default(),
+            catalog_ops_limit: None,
+            first_entry_offset: None,
+            verify_region_magic_on_read: false,
+            self_test_on_open: false,
+            scrub_throughput_limit: None,
+            write_coalescing_interval: None,
+            max_writing_regions: None,
+            size_padding: PaddingPolicy::default(),
+            store_key_hash_only: false,
+            zstd_level: 0,
+            max_concurrent_decompressions: None,
+            checksum_algorithm: ChecksumAlgorithm::default(),
+            key_prefix: None,
+            flush_error_policy: FlushErrorPolicy::default(),
+            admission_observer: None,
+            max_region_age: None,
+            channel_drop_policy: ChannelDropPolicy::default(),
+            prefix_compressions: HashMap::new(),
+            io_bytes_in_flight_limit: Some(LIMIT),
+            flusher_queue_capacity: None,
+            queue_full_policy: QueueFullPolicy::default(),
+            flush_alignment: None,
+            adaptive_compression: false,
+            scan_read_ahead: 2,
+            verify_checksum_on_recover: false,
+            catalog_backend: None,
+            catalog_retry_policy: CatalogRetryPolicy::default(),
+        };
+
+        let store = TestStore::open(config).await.unwrap();
+
+        // The flushes are up to 1.5x of the write io size, which is within the limit.
+        let futures = (0..256u64)
+            .map(|i| {
+                let store = store.clone();
+                async move { store.insert(i, vec![i as u8; 8 * KB]).await.unwrap() }
+            })
+            .collect_vec();
+        futures::future::join_all(futures).await;
+        store.barrier().await.unwrap();
+
+        let futures = (0..256u64)
+            .map(|i| {
+                let store = store.clone();
+                async move {
+                    let entry = store.get(&i).await.unwrap().unwrap();
+                    assert_eq!(entry.value(), &vec![i as u8; 8 * KB]);
+                }
+            })
+            .collect_vec();
+        futures::future::join_all(futures).await;
+
+        let peak = store.inner.io_limiter.peak();
+        assert!(peak > 0);
+        assert!(peak <= LIMIT, "peak in-flight io bytes: {peak}, limit: {LIMIT}");
+
+        store.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_checksum_disabled() {
+        const KB: usize = 1024;
+        const MB: usize = 1024 * 1024;
+
+        let tempdir = tempfile::tempdir().unwrap();
+
+        let config = |checksum| TestStoreConfig {
+            name: "".to_string(),
+            eviction_config: FifoConfig {}.into(),
+            device_config: FsDeviceConfig {
+                dir: PathBuf::from(tempdir.path()),
+                capacity: 16 * MB,
+                file_size: 4 * MB,
+                align: 4 * KB,
+                io_size: 4 * KB,
+                io_executor: IoExecutor::default(),
+                on_region_open_error: RegionOpenErrorPolicy::default(),
+                write_io_size: None,
+                read_io_size: None,
+                extra_dirs: vec![],
+                direct_io: true,
+            },
+            catalog_shards: 1,
+            admissions: vec![],
+            reinsertions: vec![],
+            flushers: 1,
+            reclaimers: 0,
+            recover_concurrency: 2,
+            clean_region_threshold: 1,
+            compression: Compression::None,
+            checksum,
+            recent_inserts_capacity: 0,
+            max_key_size: None,
+            region_compression: None,
+            intra_block_align: None,
+            catalog_snapshot_path: None,
+            reclaim_grace_period: Duration::ZERO,
+            compression_observer: None,
+            unknown_region_version_policy: UnknownRegionVersionPolicy::default(),
+            block_compression: None,
+            background_cpu_cores: None,
+            metrics_recorder: None,
+            write_conflict_policy: WriteConflictPolicy::default(),
+            catalog_ops_limit: None,
+            first_entry_offset: None,
+            verify_region_magic_on_read: false,
+            self_test_on_open: false,
+            scrub_throughput_limit: None,
+            write_coalescing_interval: None,
+            max_writing_regions: None,
+            size_padding: PaddingPolicy::default(),
+            store_key_hash_only: false,
+            zstd_level: 0,
+            max_concurrent_decompressions: None,
+            checksum_algorithm: ChecksumAlgorithm::default(),
+            key_prefix: None,
+            flush_error_policy: FlushErrorPolicy::default(),
+            admission_observer: None,
+            max_region_age: None,
+            channel_drop_policy: ChannelDropPolicy::default(),
+            prefix_compressions: HashMap::new(),
+            io_bytes_in_flight_limit: None,
+            flusher_queue_capacity: None,
+            queue_full_policy: QueueFullPolicy::default(),
+            flush_alignment: None,
+            adaptive_compression: false,
+            scan_read_ahead: 2,
+            verify_checksum_on_recover: false,
+            catalog_backend: None,
+            catalog_retry_policy: CatalogRetryPolicy::default(),
+        };
+
+        // The flusher and the store share the current thread runtime, so the thread local counter observes all
+        // checksum calculations.
+        CHECKSUM_COUNT.with(|count| count.set(0));
+
+        let store = TestStore::open(config(false)).await.unwrap();
+        for i in 0..8 {
+            store.insert(i, vec![i as u8; 64 * KB]).await.unwrap();
+        }
+        store.close().await.unwrap();
+        drop(store);
+
+        let store = TestStore::open(config(false)).await.unwrap();
+        for i in 0..8 {
+            assert_eq!(store.get(&i).await.unwrap().unwrap().value(), &vec![i as u8; 64 * KB]);
+        }
+        store.close().await.unwrap();
+        drop(store);
+
+        assert_eq!(CHECKSUM_COUNT.with(|count| count.get()), 0);
+
+        // The entries written without checksum are still readable after checksum is enabled.
+        let store = TestStore::open(config(true)).await.unwrap();
+        for i in 0..8 {
+            assert_eq!(store.get(&i).await.unwrap().unwrap().value(), &vec![i as u8; 64 * KB]);
+        }
+        store.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_recent_inserts() {
+        const KB: usize = 1024;
+        const MB: usize = 1024 * 1024;
+
+        let tempdir = tempfile::tempdir().unwrap();
+
+        let config = TestStoreConfig {
+            name: "".to_string(),
+            eviction_config: FifoConfig {}.into(),
+            device_config: FsDeviceConfig {
+                dir: PathBuf::from(tempdir.path()),
+                capacity: 16 * MB,
+                file_size: 4 * MB,
+                align: 4 * KB,
+                io_size: 4 * KB,
+                io_executor: IoExecutor::default(),
+                on_region_open_error: RegionOpenErrorPolicy::default(),
+                write_io_size: None,
+                read_io_size: None,
+                extra_dirs: vec![],
+                direct_io: true,
+            },
+            catalog_shards: 1,
+            admissions: vec![],
+            reinsertions: vec![],
+            flushers: 1,
+            reclaimers: 0,
+            recover_concurrency: 2,
+            clean_region_threshold: 1,
+            compression: Compression::None,
+            checksum: true,
+            recent_inserts_capacity: 4,
+            max_key_size: None,
+            region_compression: None,
+            intra_block_align: None,
+            catalog_snapshot_path: None,
+            reclaim_grace_period: Duration::ZERO,
+            compression_observer: None,
+            unknown_region_version_policy: UnknownRegionVersionPolicy::default(),
+            block_compression: None,
+            background_cpu_cores: None,
+            metrics_recorder: None,
+            write_conflict_policy: WriteConflictPolicy::default(),
+            catalog_ops_limit: None,
+            first_entry_offset: None,
+            verify_region_magic_on_read: false,
+            self_test_on_open: false,
+            scrub_throughput_limit: None,
+            write_coalescing_interval: None,
+            max_writing_regions: None,
+            size_padding: PaddingPolicy::default(),
+            store_key_hash_only: false,
+            zstd_level: 0,
+            max_concurrent_decompressions: None,
+            checksum_algorithm: ChecksumAlgorithm::default(),
+            key_prefix: None,
+            flush_error_policy: FlushErrorPolicy::default(),
+            admission_observer: None,
+            max_region_age: None,
+            channel_drop_policy: ChannelDropPolicy::default(),
+            prefix_compressions: HashMap::new(),
+            io_bytes_in_flight_limit: None,
+            flusher_queue_capacity: None,
+            queue_full_policy: QueueFullPolicy::default(),
+            flush_alignment: None,
+            adaptive_compression: false,
+            scan_read_ahead: 2,
+            verify_checksum_on_recover: false,
+            catalog_backend: None,
+            catalog_retry_policy: CatalogRetryPolicy::default(),
+        };
+
+        let store = TestStore::open(config).await.unwrap();
+        assert!(store.recent_inserts(4).is_empty());
+
+        for i in 0..10 {
+            store.insert(i, vec![i as u8; KB]).await.unwrap();
+        }
+
+        let recent = |n| {
+            store
+                .recent_inserts(n)
+                .into_iter()
+                .map(|(key, sequence)| (*key, sequence))
+                .collect_vec()
+        };
+        // Sequence starts from 1 for an empty store.
+        assert_eq!(recent(4), vec![(6, 7), (7, 8), (8, 9), (9, 10)]);
+        assert_eq!(recent(2), vec![(8, 9), (9, 10)]);
+        assert_eq!(recent(100), vec![(6, 7), (7, 8), (8, 9), (9, 10)]);
+
+        store.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_last_writer_wins() {
+        const KB: usize = 1024;
+        const MB: usize = 1024 * 1024;
+
+        let tempdir = tempfile::tempdir().unwrap();
+
+        let config = TestStoreConfig {
+            name: "".to_string(),
+            eviction_config: FifoConfig {}.into(),
+            device_config: FsDeviceConfig {
+                dir: PathBuf::from(tempdir.path()),
+                capacity: 16 * MB,
+                file_size: 4 * MB,
+                align: 4 * KB,
+                io_size: 4 * KB,
+                io_executor: IoExecutor::default(),
+                on_region_open_error: RegionOpenErrorPolicy::default(),
+                write_io_size: None,
+                read_io_size: None,
+                extra_dirs: vec![],
+                direct_io: true,
+            },
+            catalog_shards: 1,
+            admissions: vec![],
+            reinsertions: vec![],
+            // The two insertions of the same key are sent to different flushers.
+            flushers: 2,
+            reclaimers: 0,
+            recover_concurrency: 2,
+            clean_region_threshold: 1,
+            compression: Compression::None,
+            checksum: true,
+            recent_inserts_capacity: 0,
+            max_key_size: None,
+            region_compression: None,
+            intra_block_align: None,
+            catalog_snapshot_path: None,
+            reclaim_grace_period: Duration::ZERO,
+            compression_observer: None,
+            unknown_region_version_policy: UnknownRegionVersionPolicy::default(),
+            block_compression: None,
+            background_cpu_cores: None,
+            metrics_recorder: None,
+            write_conflict_policy: WriteConflictPolicy::LastWriterWins,
+            catalog_ops_limit: None,
+            first_entry_offset: None,
+            verify_region_magic_on_read: false,
+            self_test_on_open: false,
+            scrub_throughput_limit: None,
+            write_coalescing_interval: None,
+            max_writing_regions: None,
+            size_padding: PaddingPolicy::default(),
+            store_key_hash_only: false,
+            zstd_level: 0,
+            max_concurrent_decompressions: None,
+            checksum_algorithm: ChecksumAlgorithm::default(),
+            key_prefix: None,
+            flush_error_policy: FlushErrorPolicy::default(),
+            admission_observer: None,
+            max_region_age: None,
+            channel_drop_policy: ChannelDropPolicy::default(),
+            prefix_compressions: HashMap::new(),
+            io_bytes_in_flight_limit: None,
+            flusher_queue_capacity: None,
+            queue_full_policy: QueueFullPolicy::default(),
+            flush_alignment: None,
+            adaptive_compression: false,
+            scan_read_ahead: 2,
+            verify_checksum_on_recover: false,
+            catalog_backend: None,
+            catalog_retry_policy: CatalogRetryPolicy::default(),
+        };
+
+        let store = TestStore::open(config.clone()).await.unwrap();
+
+        for i in 0..100 {
+            store.insert(i, vec![1; KB]).await.unwrap();
+            store.insert(i, vec![2; KB]).await.unwrap();
+        }
+
+        for i in 0..100 {
+            assert_eq!(store.get(&i).await.unwrap().unwrap().value(), &vec![2; KB]);
+        }
+
+        store.close().await.unwrap();
+
+        for i in 0..100 {
+            assert_eq!(store.get(&i).await.unwrap().unwrap().value(), &vec![2; KB]);
+        }
+
+        drop(store);
+
+        // Both versions are on the device, the recovered index must point to the later one.
+        let store = TestStore::open(config).await.unwrap();
+
+        for i in 0..100 {
+            assert_eq!(store.get(&i).await.unwrap().unwrap().value(), &vec![2; KB]);
+        }
+
+        store.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_entries_since() {
+        const KB: usize = 1024;
+        const MB: usize = 1024 * 1024;
+
+        let tempdir = tempfile::tempdir().unwrap();
+
+        let config = TestStoreConfig {
+            name: "".to_string(),
+            eviction_config: FifoConfig {}.into(),
+            device_config: FsDeviceConfig {
+                dir: PathBuf::from(tempdir.path()),
+                capacity: 16 * MB,
+                file_size: 4 * MB,
+                align: 4 * KB,
+                io_size: 4 * KB,
+                io_executor: IoExecutor::default(),
+                on_region_open_error: RegionOpenErrorPolicy::default(),
+                write_io_size: None,
+                read_io_size: None,
+                extra_dirs: vec![],
+                direct_io: true,
+            },
+            catalog_shards: 4,
+            admissions: vec![],
+            reinsertions: vec![],
+            flushers: 1,
+            reclaimers: 0,
+            recover_concurrency: 2,
+            clean_region_threshold: 1,
+            compression: Compression::None,
+            checksum: true,
+            recent_inserts_capacity: 1,
+            max_key_size: None,
+            region_compression: None,
+            intra_block_align: None,
+            catalog_snapshot_path: None,
+            reclaim_grace_period: Duration::ZERO,
+            compression_observer: None,
+            unknown_region_version_policy: UnknownRegionVersionPolicy::default(),
+            block_compression: None,
+            background_cpu_cores: None,
+            metrics_recorder: None,
+            write_conflict_policy: WriteConflictPolicy::default(),
+            catalog_ops_limit: None,
+            first_entry_offset: None,
+            verify_region_magic_on_read: false,
+            self_test_on_open: false,
+            scrub_throughput_limit: None,
+            write_coalescing_interval: None,
+            max_writing_regions: None,
+            size_padding: PaddingPolicy::default(),
+            store_key_hash_only: false,
+            zstd_level: 0,
+            max_concurrent_decompressions: None,
+            checksum_algorithm: ChecksumAlgorithm::default(),
+            key_prefix: None,
+            flush_error_policy: FlushErrorPolicy::default(),
+            admission_observer: None,
+            max_region_age: None,
+            channel_drop_policy: ChannelDropPolicy::default(),
+            prefix_compressions: HashMap::new(),
+            io_bytes_in_flight_limit: None,
+            flusher_queue_capacity: None,
+            queue_full_policy: QueueFullPolicy::default(),
+            flush_alignment: None,
+            adaptive_compression: false,
+            scan_read_ahead: 2,
+            verify_checksum_on_recover: false,
+            catalog_backend: None,
+            catalog_retry_policy: CatalogRetryPolicy::default(),
+        };
+
+        let store = TestStore::open(config.clone()).await.unwrap();
+
+        let entries_since = |store: &TestStore, sequence| {
+            let stream = store.entries_since(sequence);
+            async move {
+                stream
+                    .map(|res| {
+                        let (key, value, sequence) = res.unwrap();
+                        (*key, value[0], sequence)
+                    })
+                    .collect::<Vec<_>>()
+                    .await
+            }
+        };
+
+        for i in 0..10 {
+            store.insert(i, vec![i as u8; KB]).await.unwrap();
+        }
+        let (_, watermark) = store.recent_inserts(1)[0];
+
+        for i in 10..20 {
+            store.insert(i, vec![i as u8; KB]).await.unwrap();
+        }
+        // Overwritten entry is yielded once with its latest sequence.
+        store.insert(15, vec![115; KB]).await.unwrap();
+
+        let mut expected = (10..20)
+            .filter(|i| *i != 15)
+            .map(|i| (i, i as u8, watermark + i - 9))
+            .collect_vec();
+        expected.push((15, 115, watermark + 11));
+        assert_eq!(entries_since(&store, watermark).await, expected);
+        assert_eq!(entries_since(&store, watermark + 11).await, vec![]);
+
+        // Flushed entries are read from the device after reopen.
+        store.close().await.unwrap();
+        drop(store);
+        let store = TestStore::open(config).await.unwrap();
+        assert_eq!(entries_since(&store, watermark).await, expected);
+        assert_eq!(entries_since(&store, 0).await.len(), 20);
+
+        store.close().await.unwrap();
+    }
+
+    #[derive(Debug, Default)]
+    struct RecordingMetricsRecorder {
+        events: Mutex<Vec<(&'static str, MetricKey, f64)>>,
+    }
+
+    impl RecordingMetricsRecorder {
+        fn count(&self, kind: &str, name: &str, labels: &[&str]) -> usize {
+            self.events
+                .lock()
+                .iter()
+                .filter(|(k, key, _)| {
+                    *k == kind && key.name == name && key.labels.iter().map(|(_, v)| v.as_str()).collect_vec() == labels
+                })
+                .count()
+        }
+
+        fn sum(&self, kind: &str, name: &str, labels: &[&str]) -> f64 {
+            self.events
+                .lock()
+                .iter()
+                .filter(|(k, key, _)| {
+                    *k == kind && key.name == name && key.labels.iter().map(|(_, v)| v.as_str()).collect_vec() == labels
+                })
+                .map(|(_, _, value)| value)
+                .sum()
+        }
+    }
+
+    impl MetricsRecorder for RecordingMetricsRecorder {
+        fn increment_counter(&self, key: &MetricKey, value: f64) {
+            self.events.lock().push(("counter", key.clone(), value));
+        }
+
+        fn observe_histogram(&self, key: &MetricKey, value: f64) {
+            self.events.lock().push(("histogram", key.clone(), value));
+        }
+
+        fn set_gauge(&self, key: &MetricKey, value: f64) {
+            self.events.lock().push(("gauge", key.clone(), value));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_barrier() {
+        const KB: usize = 1024;
+        const MB: usize = 1024 * 1024;
+
+        let tempdir = tempfile::tempdir().unwrap();
+
+        let config = TestStoreConfig {
+            name: "".to_string(),
+            eviction_config: FifoConfig {}.into(),
+            device_config: FsDeviceConfig {
+                dir: PathBuf::from(tempdir.path()),
+                capacity: 16 * MB,
+                file_size: 4 * MB,
+                align: 4 * KB,
+                // Large enough to keep the entries in the flush buffer until flushed explicitly.
+                io_size: 64 * KB,
+                io_executor: IoExecutor::default(),
+                on_region_open_error: RegionOpenErrorPolicy::default(),
+                write_io_size: None,
+                read_io_size: None,
+                extra_dirs: vec![],
+                direct_io: true,
+            },
+            catalog_shards: 1,
+            admissions: vec![],
+            reinsertions: vec![],
+            flushers: 2,
+            reclaimers: 0,
+            recover_concurrency: 2,
+            clean_region_threshold: 1,
+            compression: Compression::None,
+            checksum: true,
+            recent_inserts_capacity: 0,
+            max_key_size: None,
+            region_compression: None,
+            intra_block_align: None,
+            catalog_snapshot_path: None,
+            reclaim_grace_period: Duration::ZERO,
+            compression_observer: None,
+            unknown_region_version_policy: UnknownRegionVersionPolicy::default(),
+            block_compression: None,
+            background_cpu_cores: None,
+            metrics_recorder: None,
+            write_conflict_policy: WriteConflictPolicy::default(),
+            catalog_ops_limit: None,
+            first_entry_offset: None,
+            verify_region_magic_on_read: false,
+            self_test_on_open: false,
+            scrub_throughput_limit: None,
+            write_coalescing_interval: None,
+            max_writing_regions: None,
+            size_padding: PaddingPolicy::default(),
+            store_key_hash_only: false,
+            zstd_level: 0,
+            max_concurrent_decompressions: None,
+            checksum_algorithm: ChecksumAlgorithm::default(),
+            key_prefix: None,
+            flush_error_policy: FlushErrorPolicy::default(),
+            admission_observer: None,
+            max_region_age: None,
+            channel_drop_policy: ChannelDropPolicy::default(),
+            prefix_compressions: HashMap::new(),
+            io_bytes_in_flight_limit: None,
+            flusher_queue_capacity: None,
+            queue_full_policy: QueueFullPolicy::default(),
+            flush_alignment: None,
+            adaptive_compression: false,
+            scan_read_ahead: 2,
+            verify_checksum_on_recover: false,
+            catalog_backend: None,
+            catalog_retry_policy: CatalogRetryPolicy::default(),
+        };
+
+        let store = TestStore::open(config.clone()).await.unwrap();
+
+        store.insert(1, vec![1; KB]).await.unwrap();
+        store.barrier().await.unwrap();
+        store.insert(2, vec![2; KB]).await.unwrap();
+
+        // Simulate a crash after the entry inserted after the barrier is buffered but before it is synced, by
+        // recovering from the device while the store is still running.
+        let recovered = TestStore::open(config).await.unwrap();
+        assert_eq!(recovered.get(&1).await.unwrap().unwrap().value(), &vec![1; KB]);
+        assert!(recovered.get(&2).await.unwrap().is_none());
+        recovered.close().await.unwrap();
+
+        assert_eq!(store.get(&1).await.unwrap().unwrap().value(), &vec![1; KB]);
+        assert_eq!(store.get(&2).await.unwrap().unwrap().value(), &vec![2; KB]);
+        store.close().await.unwrap();
+
+        // The barrier resolves immediately after the flushers exit.
+        store.barrier().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_drain() {
+        const KB: usize = 1024;
+        const MB: usize = 1024 * 1024;
+
+        let tempdir = tempfile::tempdir().unwrap();
+
+        let config = TestStoreConfig {
+            name: "".to_string(),
+            eviction_config: FifoConfig {}.into(),
+            device_config: FsDeviceConfig {
+                dir: PathBuf::from(tempdir.path()),
+                capacity: 16 * MB,
+                file_size: 4 * MB,
+                align: 4 * KB,
+                // Large enough to keep the entries in the flush buffer until flushed explicitly.
+                io_size: 64 * KB,
+                io_executor: IoExecutor::default(),
+                on_region_open_error: RegionOpenErrorPolicy::default(),
+                write_io_size: None,
+                read_io_size: None,
+                extra_dirs: vec![],
+                direct_io: true,
+            },
+            catalog_shards: 1,
+            admissions: vec![],
+            reinsertions: vec![],
+            flushers: 2,
+            reclaimers: 0,
+            recover_concurrency: 2,
+            clean_region_threshold: 1,
+            compression: Compression::None,
+            checksum: true,
+            recent_inserts_capacity: 0,
+            max_key_size: None,
+            region_compression: None,
+            intra_block_align: None,
+            catalog_snapshot_path: None,
+            reclaim_grace_period: Duration::ZERO,
+            compression_observer: None,
+            unknown_region_version_policy: UnknownRegionVersionPolicy::default(),
+            block_compression: None,
+            background_cpu_cores: None,
+            metrics_recorder: None,
+            write_conflict_policy: WriteConflictPolicy::default(),
+            catalog_ops_limit: None,
+            first_entry_offset: None,
+            verify_region_magic_on_read: false,
+            self_test_on_open: false,
+            scrub_throughput_limit: None,
+            write_coalescing_interval: None,
+            max_writing_regions: None,
+            size_padding: PaddingPolicy::default(),
+            store_key_hash_only: false,
+            zstd_level: 0,
+            max_concurrent_decompressions: None,
+            checksum_algorithm: ChecksumAlgorithm::default(),
+            key_prefix: None,
+            flush_error_policy: FlushErrorPolicy::default(),
+            admission_observer: None,
+            max_region_age: None,
+            channel_drop_policy: ChannelDropPolicy::default(),
+            prefix_compressions: HashMap::new(),
+            io_bytes_in_flight_limit: None,
+            flusher_queue_capacity: None,
+            queue_full_policy: QueueFullPolicy::default(),
+            flush_alignment: None,
+            adaptive_compression: false,
+            scan_read_ahead: 2,
+            verify_checksum_on_recover: false,
+            catalog_backend: None,
+            catalog_retry_policy: CatalogRetryPolicy::default(),
+        };
+
+        let store = TestStore::open(config.clone()).await.unwrap();
+
+        for i in 0..8 {
+            store.insert(i, vec![i as u8; KB]).await.unwrap();
+        }
+        let drained = store.drain().await.unwrap();
+
+        // All entries are pending in the flush buffers before the drain, and are reported in the insert order.
+        assert_eq!(
+            drained.iter().map(|(key, ..)| **key).collect_vec(),
+            (0..8).collect_vec()
+        );
+        assert!(drained.windows(2).all(|w| w[0].1 < w[1].1));
+        for (key, sequence, region, offset) in drained.iter() {
+            let (item_sequence, index) = store.catalog().get(key.as_ref()).unwrap().consume();
+            assert_eq!(item_sequence, *sequence);
+            let Index::Region { view } = index else {
+                panic!("entry must be indexed by region after drained");
+            };
+            assert_eq!((*view.id(), *view.offset() as usize), (*region, *offset));
+        }
+
+        // Inserts are rejected after the drain.
+        assert!(store.insert(8, vec![8; KB]).await.unwrap().is_none());
+        assert!(!store.exists(&8).unwrap());
+
+        // The drained entries are durable.
+        let recovered = TestStore::open(config).await.unwrap();
+        for i in 0..8 {
+            assert_eq!(recovered.get(&i).await.unwrap().unwrap().value(), &vec![i as u8; KB]);
+        }
+        recovered.close().await.unwrap();
+
+        store.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_catalog_ops_limit() {
+        const KB: usize = 1024;
+        const MB: usize = 1024 * 1024;
+
+        const LIMIT: f64 = 2000.0;
+        const TASKS: u64 = 4;
+        const INSERTS: u64 = 250;
+
+        let tempdir = tempfile::tempdir().unwrap();
+
+        let config = TestStoreConfig {
+            name: "".to_string(),
+            eviction_config: FifoConfig {}.into(),
+            device_config: FsDeviceConfig {
+                dir: PathBuf::from(tempdir.path()),
+                capacity: 16 * MB,
+                file_size: 4 * MB,
+                align: 4 * KB,
+                io_size: 16 * KB,
+                io_executor: IoExecutor::default(),
+                on_region_open_error: RegionOpenErrorPolicy::default(),
+                write_io_size: None,
+                read_io_size: None,
+                extra_dirs: vec![],
+                direct_io: true,
+            },
+            catalog_shards: 1,
+            admissions: vec![],
+            reinsertions: vec![],
+            flushers: 1,
+            reclaimers: 0,
+            recover_concurrency: 2,
+            clean_region_threshold: 1,
+            compression: Compression::None,
+            checksum: true,
+            recent_inserts_capacity: 0,
+            max_key_size: None,
+            region_compression: None,
+            intra_block_align: None,
+            catalog_snapshot_path: None,
+            reclaim_grace_period: Duration::ZERO,
+            compression_observer: None,
+            unknown_region_version_policy: UnknownRegionVersionPolicy::default(),
+            block_compression: None,
+            background_cpu_cores: None,
+            metrics_recorder: None,
+            write_conflict_policy: WriteConflictPolicy::default(),
+            catalog_ops_limit: Some(LIMIT),
+            first_entry_offset: None,
+            verify_region_magic_on_read: false,
+            self_test_on_open: false,
+            scrub_throughput_limit: None,
+            write_coalescing_interval: None,
+            max_writing_regions: None,
+            size_padding: PaddingPolicy::default(),
+            store_key_hash_only: false,
+            zstd_level: 0,
+            max_concurrent_decompressions: None,
+            checksum_algorithm: ChecksumAlgorithm::default(),
+            key_prefix: None,
+            flush_error_policy: FlushErrorPolicy::default(),
+            admission_observer: None,
+            max_region_age: None,
+            channel_drop_policy: ChannelDropPolicy::default(),
+            prefix_compressions: HashMap::new(),
+            io_bytes_in_flight_limit: None,
+            flusher_queue_capacity: None,
+            queue_full_policy: QueueFullPolicy::default(),
+            flush_alignment: None,
+            adaptive_compression: false,
+            scan_read_ahead: 2,
+            verify_checksum_on_recover: false,
+            catalog_backend: None,
+            catalog_retry_policy: CatalogRetryPolicy::default(),
+        };
+
+        let store = TestStore::open(config).await.unwrap();
+
+        // Flood tiny inserts from concurrent tasks.
+        let now = Instant::now();
+        let handles = (0..TASKS)
+            .map(|task| {
+                let store = store.clone();
+                tokio::spawn(async move {
+                    for i in 0..INSERTS {
+                        store.insert(task * INSERTS + i, vec![0; 16]).await.unwrap();
+                    }
+                })
+            })
+            .collect_vec();
+        for handle in handles {
+            handle.await.unwrap();
+        }
+        let elapsed = now.elapsed();
+
+        // The inserts are delayed to keep the catalog mutation rate within the limit, instead of failing or contending
+        // on the catalog.
+        let rate = (TASKS * INSERTS) as f64 / elapsed.as_secs_f64();
+        assert!(rate <= LIMIT * 1.1, "rate: {rate}, limit: {LIMIT}");
+        assert!(elapsed >= Duration::from_millis(400), "elapsed: {elapsed:?}");
+        assert!(store.inner.metrics.catalog_ops_insert.get() >= TASKS * INSERTS);
+
+        for key in 0..TASKS * INSERTS {
+            assert!(store.exists(&key).unwrap());
+        }
+
+        store.close().await.unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_max_concurrent_decompressions() {
+        const KB: usize = 1024;
+        const MB: usize = 1024 * 1024;
+
+        const LIMIT: usize = 2;
+        const ENTRIES: u64 = 16;
+        const GETS: u64 = 64;
+
+        let tempdir = tempfile::tempdir().unwrap();
+
+        let config = TestStoreConfig {
+            name: "".to_string(),
+            eviction_config: FifoConfig {}.into(),
+            device_config: FsDeviceConfig {
+                dir: PathBuf::from(tempdir.path()),
+                capacity: 16 * MB,
+                file_size: 4 * MB,
+                align: 4 * KB,
+                io_size: 16 * KB,
+                io_executor: IoExecutor::default(),
+                on_region_open_error: RegionOpenErrorPolicy::default(),
+                write_io_size: None,
+                read_io_size: None,
+                extra_dirs: vec![],
+                direct_io: true,
+            },
+            catalog_shards: 1,
+            admissions: vec![],
+            reinsertions: vec![],
+            flushers: 1,
+            reclaimers: 0,
+            recover_concurrency: 2,
+            clean_region_threshold: 1,
+            compression: Compression::Zstd,
+            checksum: true,
+            recent_inserts_capacity: 0,
+            max_key_size: None,
+            region_compression: None,
+            intra_block_align: None,
+            catalog_snapshot_path: None,
+            reclaim_grace_period: Duration::ZERO,
+            compression_observer: None,
+            unknown_region_version_policy: UnknownRegionVersionPolicy::default(),
+            block_compression: None,
+            background_cpu_cores: None,
+            metrics_recorder: None,
+            write_conflict_policy: WriteConflictPolicy::default(),
+            catalog_ops_limit: None,
+            first_entry_offset: None,
+            verify_region_magic_on_read: false,
+            self_test_on_open: false,
+            scrub_throughput_limit: None,
+            write_coalescing_interval: None,
+            max_writing_regions: None,
+            size_padding: PaddingPolicy::default(),
+            store_key_hash_only: false,
+            zstd_level: 0,
+            max_concurrent_decompressions: Some(LIMIT),
+            checksum_algorithm: ChecksumAlgorithm::default(),
+            key_prefix: None,
+            flush_error_policy: FlushErrorPolicy::default(),
+            admission_observer: None,
+            max_region_age: None,
+            channel_drop_policy: ChannelDropPolicy::default(),
+            prefix_compressions: HashMap::new(),
+            io_bytes_in_flight_limit: None,
+            flusher_queue_capacity: None,
+            queue_full_policy: QueueFullPolicy::default(),
+            flush_alignment: None,
+            adaptive_compression: false,
+            scan_read_ahead: 2,
+            verify_checksum_on_recover: false,
+            catalog_backend: None,
+            catalog_retry_policy: CatalogRetryPolicy::default(),
+        };
+
+        let value = |i: u64| (0..MB).map(|j| (j % 251) as u8 ^ i as u8).collect_vec();
+
+        let store = TestStore::open(config.clone()).await.unwrap();
+        for i in 0..ENTRIES {
+            store.insert(i, value(i)).await.unwrap();
+        }
+        store.close().await.unwrap();
+        drop(store);
+
+        // Reopen the store to make sure the entries are read from the device and decompressed.
+        let store = TestStore::open(config).await.unwrap();
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let monitor = tokio::spawn({
+            let store = store.clone();
+            let stop = stop.clone();
+            async move {
+                let limiter = store.inner.decompression_limiter.as_ref().unwrap();
+                let mut max_inflight = 0;
+                while !stop.load(Ordering::Relaxed) {
+                    max_inflight = max_inflight.max(LIMIT - limiter.available_permits());
+                    tokio::task::yield_now().await;
+                }
+                max_inflight
+            }
+        });
+
+        let handles = (0..GETS)
+            .map(|i| {
+                let store = store.clone();
+                tokio::spawn(async move {
+                    let key = i % ENTRIES;
+                    let entry = store.get(&key).await.unwrap().unwrap();
+                    assert_eq!(entry.value(), &value(key));
+                })
+            })
+            .collect_vec();
+        for handle in handles {
+            handle.await.unwrap();
+        }
+
+        stop.store(true, Ordering::Relaxed);
+        let max_inflight = monitor.await.unwrap();
+        assert!(max_inflight <= LIMIT, "max inflight: {max_inflight}, limit: {LIMIT}");
+        assert_eq!(store.inner.metrics.decompression_queue_depth.get(), 0);
+
+        store.close().await.unwrap();
+    }
+
+    #[test]
+    fn test_checksum_algorithm() {
+        assert_eq!(checksum(ChecksumAlgorithm::Crc32c, b"123456789"), 0xE3069283);
+        assert_ne!(
+            checksum(ChecksumAlgorithm::XxHash64, b"123456789"),
+            checksum(ChecksumAlgorithm::Crc32c, b"123456789")
+        );
+        assert_eq!(
+            checksum(ChecksumAlgorithm::XxHash3, b"123456789"),
+            xxh3::hash64(b"123456789")
+        );
+        assert_ne!(
+            checksum(ChecksumAlgorithm::XxHash3, b"123456789"),
+            checksum(ChecksumAlgorithm::XxHash64, b"123456789")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_mixed_checksum_algorithms() {
+        const KB: usize = 1024;
+        const MB: usize = 1024 * 1024;
+
+        let tempdir = tempfile::tempdir().unwrap();
+
+        let config = |checksum_algorithm| TestStoreConfig {
+            name: "".to_string(),
+            eviction_config: FifoConfig {}.into(),
+            device_config: FsDeviceConfig {
+                dir: PathBuf::from(tempdir.path()),
+                capacity: 16 * MB,
+                file_size: 4 * MB,
+                align: 4 * KB,
+                io_size: 16 * KB,
+                io_executor: IoExecutor::default(),
+                on_region_open_error: RegionOpenErrorPolicy::default(),
+                write_io_size: None,
+                read_io_size: None,
+                extra_dirs: vec![],
+                direct_io: true,
+            },
+            catalog_shards: 1,
+            admissions: vec![],
+            reinsertions: vec![],
+            flushers: 1,
+            reclaimers: 0,
+            recover_concurrency: 2,
+            clean_region_threshold: 1,
+            compression: Compression::None,
+            checksum: true,
+            recent_inserts_capacity: 0,
+            max_key_size: None,
+            region_compression: None,
+            intra_block_align: None,
+            catalog_snapshot_path: None,
+            reclaim_grace_period: Duration::ZERO,
+            compression_observer: None,
+            unknown_region_version_policy: UnknownRegionVersionPolicy::default(),
+            block_compression: None,
+            background_cpu_cores: None,
+            metrics_recorder: None,
+            write_conflict_policy: WriteConflictPolicy::default(),
+            catalog_ops_limit: None,
+            first_entry_offset: None,
+            verify_region_magic_on_read: false,
+            self_test_on_open: false,
+            scrub_throughput_limit: None,
+            write_coalescing_interval: None,
+            max_writing_regions: None,
+            size_padding: PaddingPolicy::default(),
+            store_key_hash_only: false,
+            zstd_level: 0,
+            max_concurrent_decompressions: None,
+            checksum_algorithm,
+            key_prefix: None,
+            flush_error_policy: FlushErrorPolicy::default(),
+            admission_observer: None,
+            max_region_age: None,
+            channel_drop_policy: ChannelDropPolicy::default(),
+            prefix_compressions: HashMap::new(),
+            io_bytes_in_flight_limit: None,
+            flusher_queue_capacity: None,
+            queue_full_policy: QueueFullPolicy::default(),
+            flush_alignment: None,
+            adaptive_compression: false,
+            scan_read_ahead: 2,
+            verify_checksum_on_recover: false,
+            catalog_backend: None,
+            catalog_retry_policy: CatalogRetryPolicy::default(),
+        };
+
+        // Write entries with the default algorithm, then with CRC32C and XXH3 after restarts.
+        let store = TestStore::open(config(ChecksumAlgorithm::XxHash64)).await.unwrap();
+        for i in 0..4u64 {
+            store.insert(i, vec![i as u8; 3 * KB]).await.unwrap();
+        }
+        store.close().await.unwrap();
+        drop(store);
+
+        let store = TestStore::open(config(ChecksumAlgorithm::Crc32c)).await.unwrap();
+        for i in 4..8u64 {
+            store.insert(i, vec![i as u8; 3 * KB]).await.unwrap();
+        }
+        store.close().await.unwrap();
+        drop(store);
+
+        let store = TestStore::open(config(ChecksumAlgorithm::XxHash3)).await.unwrap();
+        for i in 8..12u64 {
+            store.insert(i, vec![i as u8; 3 * KB]).await.unwrap();
+        }
+        store.close().await.unwrap();
+        drop(store);
+
+        // Each entry is verified with the algorithm recorded in its header.
+        let store = TestStore::open(config(ChecksumAlgorithm::XxHash64)).await.unwrap();
+        for i in 0..12u64 {
+            let Index::Region { view } = store.catalog().get(&i).unwrap().consume().1 else {
+                panic!("entry must be indexed by region after flushed");
+            };
+            let buf = store
+                .inner
+                .region_manager
+                .region(view.id())
+                .load(view)
+                .await
+                .unwrap()
+                .unwrap();
+            let expected = if i < 4 {
+                ChecksumAlgorithm::XxHash64
+            } else if i < 8 {
+                ChecksumAlgorithm::Crc32c
+            } else {
+                ChecksumAlgorithm::XxHash3
+            };
+            assert_eq!(EntryHeader::read(buf.as_ref()).unwrap().checksum_algorithm, expected);
+
+            assert_eq!(store.get(&i).await.unwrap().unwrap().value(), &vec![i as u8; 3 * KB]);
+        }
+        let report = store.scrub(false).await.unwrap();
+        assert_eq!(report.entries, 12);
+        assert!(report.corrupted.is_empty());
+
+        store.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_metrics_recorder() {
+        const KB: usize = 1024;
+        const MB: usize = 1024 * 1024;
+
+        let tempdir = tempfile::tempdir().unwrap();
+        let recorder = Arc::new(RecordingMetricsRecorder::default());
+
+        let config = TestStoreConfig {
+            name: "test".to_string(),
+            eviction_config: FifoConfig {}.into(),
+            device_config: FsDeviceConfig {
+                dir: PathBuf::from(tempdir.path()),
+                capacity: 16 * MB,
+                file_size: 4 * MB,
+                align: 4 * KB,
+                io_size: 4 * KB,
+                io_executor: IoExecutor::default(),
+                on_region_open_error: RegionOpenErrorPolicy::default(),
+                write_io_size: None,
+                read_io_size: None,
+                extra_dirs: vec![],
+                direct_io: true,
+            },
+            catalog_shards: 1,
+            admissions: vec![],
+            reinsertions: vec![],
+            flushers: 1,
+            reclaimers: 0,
+            recover_concurrency: 2,
+            clean_region_threshold: 1,
+            compression: Compression::None,
+            checksum: true,
+            recent_inserts_capacity: 0,
+            max_key_size: None,
+            region_compression: None,
+            intra_block_align: None,
+            catalog_snapshot_path: None,
+            reclaim_grace_period: Duration::ZERO,
+            compression_observer: None,
+            unknown_region_version_policy: UnknownRegionVersionPolicy::default(),
+            block_compression: None,
+            background_cpu_cores: None,
+            metrics_recorder: Some(recorder.clone()),
+            write_conflict_policy: WriteConflictPolicy::default(),
+            catalog_ops_limit: None,
+            first_entry_offset: None,
+            verify_region_magic_on_read: false,
+            self_test_on_open: false,
+            scrub_throughput_limit: None,
+            write_coalescing_interval: None,
+            max_writing_regions: None,
+            size_padding: PaddingPolicy::default(),
+            store_key_hash_only: false,
+            zstd_level: 0,
+            max_concurrent_decompressions: None,
+            checksum_algorithm: ChecksumAlgorithm::default(),
+            key_prefix: None,
+            flush_error_policy: FlushErrorPolicy::default(),
+            admission_observer: None,
+            max_region_age: None,
+            channel_drop_policy: ChannelDropPolicy::default(),
+            prefix_compressions: HashMap::new(),
+            io_bytes_in_flight_limit: None,
+            flusher_queue_capacity: None,
+            queue_full_policy: QueueFullPolicy::default(),
+            flush_alignment: None,
+            adaptive_compression: false,
+            scan_read_ahead: 2,
+            verify_checksum_on_recover: false,
+            catalog_backend: None,
+            catalog_retry_policy: CatalogRetryPolicy::default(),
+        };
+
+        let store = TestStore::open(config).await.unwrap();
+        // Total bytes is reset after recovery.
+        assert_eq!(recorder.count("gauge", TOTAL_BYTES, &["test"]), 1);
+
+        for i in 0..4 {
+            store.insert(i, vec![i as u8; KB]).await.unwrap();
+        }
+        store.close().await.unwrap();
+
+        assert!(store.get(&0).await.unwrap().is_some());
+        assert!(store.get(&4).await.unwrap().is_none());
+        assert!(store.remove(&1).unwrap());
+
+        assert_eq!(
+            recorder.count("histogram", OP_DURATION, &["test", "insert", "inserted"]),
+            4
+        );
+        assert_eq!(recorder.count("histogram", OP_DURATION, &["test", "get", "hit"]), 1);
+        assert_eq!(recorder.count("histogram", OP_DURATION, &["test", "get", "miss"]), 1);
+        assert_eq!(recorder.count("histogram", OP_DURATION, &["test", "remove", ""]), 1);
+
+        // All entries are flushed to a newly acquired region on close.
+        assert_eq!(recorder.count("gauge", TOTAL_BYTES, &["test"]), 2);
+        assert!(recorder.sum("counter", OP_BYTES, &["test", "flush", ""]) >= (4 * KB) as f64);
+        assert_eq!(
+            recorder.sum("counter", OP_BYTES, &["test", "flush", ""]),
+            store.inner.metrics.op_bytes_flush.get() as f64
+        );
+        assert_eq!(
+            recorder.sum("counter", OP_BYTES, &["test", "get", ""]),
+            store.inner.metrics.op_bytes_get.get() as f64
+        );
+        assert!(store.inner.metrics.op_bytes_get.get() > 0);
+    }
+
+    #[tokio::test]
+    async fn test_recover_metrics() {
+        const KB: usize = 1024;
+        const MB: usize = 1024 * 1024;
+
+        let tempdir = tempfile::tempdir().unwrap();
+        let recorder = Arc::new(RecordingMetricsRecorder::default());
+
+        let mut config = TestStoreConfig {
+            name: "test".to_string(),
+            eviction_config: FifoConfig {}.into(),
+            device_config: FsDeviceConfig {
+                dir: PathBuf::from(tempdir.path()),
+                capacity: 16 * MB,
+                file_size: 4 * MB,
+                align: 4 * KB,
+                io_size: 4 * KB,
+                io_executor: IoExecutor::default(),
+                on_region_open_error: RegionOpenErrorPolicy::default(),
+                write_io_size: None,
+                read_io_size: None,
+                extra_dirs: vec![],
+                direct_io: true,
+            },
+            catalog_shards: 1,
+            admissions: vec![],
+            reinsertions: vec![],
+            flushers: 1,
+            reclaimers: 0,
+            recover_concurrency: 2,
+            clean_region_threshold: 1,
+            compression: Compression::None,
+            checksum: true,
+            recent_inserts_capacity: 0,
+            max_key_size: None,
+            region_compression: None,
+            intra_block_align: None,
+            catalog_snapshot_path: None,
+            reclaim_grace_period: Duration::ZERO,
+            compression_observer: None,
+            unknown_region_version_policy: UnknownRegionVersionPolicy::default(),
+            block_compression: None,
+            background_cpu_cores: None,
+            metrics_recorder: Some(recorder.clone()),
+            write_conflict_policy: WriteConflictPolicy::default(),
+            catalog_ops_limit: None,
+            first_entry_offset: None,
+            verify_region_magic_on_read: false,
+            self_test_on_open: false,
+            scrub_throughput_limit: None,
+            write_coalescing_interval: None,
+            max_writing_regions: None,
+            size_padding: PaddingPolicy::default(),
+            store_key_hash_only: false,
+            zstd_level: 0,
+            max_concurrent_decompressions: None,
+            checksum_algorithm: ChecksumAlgorithm::default(),
+            key_prefix: None,
+            flush_error_policy: FlushErrorPolicy::default(),
+            admission_observer: None,
+            max_region_age: None,
+            channel_drop_policy: ChannelDropPolicy::default(),
+            prefix_compressions: HashMap::new(),
+            io_bytes_in_flight_limit: None,
+            flusher_queue_capacity: None,
+            queue_full_policy: QueueFullPolicy::default(),
+            flush_alignment: None,
+            adaptive_compression: false,
+            scan_read_ahead: 2,
+            verify_checksum_on_recover: false,
+            catalog_backend: None,
+            catalog_retry_policy: CatalogRetryPolicy::default(),
+        };
+
+        let store = TestStore::open(config.clone()).await.unwrap();
+        for i in 0..4 {
+            store.insert(i, vec![i as u8; KB]).await.unwrap();
+        }
+        store.close().await.unwrap();
+
+        // Tear the last entry, its key length now runs beyond the region.
+        let Index::Region { view } = store.inner.catalog.get(&3).unwrap().consume().1 else {
+            panic!("entry must be indexed by region after flushed");
+        };
+        let (region, offset) = (*view.id(), *view.offset() as usize);
+        drop(view);
+        let align = store.device().align();
+        let slice = store
+            .inner
+            .region_manager
+            .region(&region)
+            .load_range(offset..offset + align)
+            .await
+            .unwrap()
+            .unwrap();
+        let mut buf = store.device().io_buffer(0, align);
+        buf.extend_from_slice(slice.as_ref());
+        drop(slice);
+        buf[..4].copy_from_slice(&u32::MAX.to_be_bytes());
+        let (res, _) = store.device().write(buf, .., region, offset).await;
+        res.unwrap();
+        drop(store);
+
+        let recorder = Arc::new(RecordingMetricsRecorder::default());
+        config.metrics_recorder = Some(recorder.clone());
+        let store = TestStore::open(config).await.unwrap();
+
+        assert_eq!(recorder.sum("counter", RECOVER_ENTRIES, &["test", "recovered"]), 3.0);
+        assert_eq!(recorder.sum("counter", RECOVER_ENTRIES, &["test", "corrupted"]), 1.0);
+        assert_eq!(store.inner.metrics.recover_entries_recovered.get(), 3);
+        assert_eq!(store.inner.metrics.recover_entries_corrupted.get(), 1);
+        // Each entry takes a block.
+        assert_eq!(store.inner.metrics.op_bytes_recover.get(), (3 * align) as u64);
+        assert_eq!(
+            recorder.sum("counter", OP_BYTES, &["test", "recover", ""]),
+            (3 * align) as f64
+        );
+
+        for i in 0..3 {
+            assert_eq!(store.get(&i).await.unwrap().unwrap().value(), &vec![i as u8; KB]);
+        }
+        assert!(store.get(&3).await.unwrap().is_none());
+
+        store.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_recover_verify_checksum() {
+        const KB: usize = 1024;
+        const MB: usize = 1024 * 1024;
+
+        let tempdir = tempfile::tempdir().unwrap();
+        let config = base_config::<u64, Vec<u8>, FsDevice>(FsDeviceConfig {
+            dir: PathBuf::from(tempdir.path()),
+            capacity: 16 * MB,
+            file_size: 4 * MB,
+            align: 4 * KB,
+            io_size: 4 * KB,
+            io_executor: IoExecutor::default(),
+            on_region_open_error: RegionOpenErrorPolicy::default(),
+            write_io_size: None,
+            read_io_size: None,
+            extra_dirs: vec![],
+            direct_io: true,
+        });
+
+        let store = TestStore::open(config.clone()).await.unwrap();
+        for i in 0..4 {
+            store.insert(i, vec![i as u8; KB]).await.unwrap();
+        }
+        store.close().await.unwrap();
+
+        // Flip a byte of the value of an entry in the middle, its header is left intact.
+        let Index::Region { view } = store.inner.catalog.get(&1).unwrap().consume().1 else {
+            panic!("entry must be indexed by region after flushed");
+        };
+        let (region, offset) = (*view.id(), *view.offset() as usize);
+        drop(view);
+        let align = store.device().align();
+        let slice = store
+            .inner
+            .region_manager
+            .region(&region)
+            .load_range(offset..offset + align)
+            .await
+            .unwrap()
+            .unwrap();
+        let mut buf = store.device().io_buffer(0, align);
+        buf.extend_from_slice(slice.as_ref());
+        drop(slice);
+        let pos = EntryHeader::serialized_len_with_expire_at() + KB / 2;
+        buf[pos] = !buf[pos];
+        let (res, _) = store.device().write(buf, .., region, offset).await;
+        res.unwrap();
+        drop(store);
+
+        // The entry is recovered without verification, and fails on read.
+        let store = TestStore::open(config.clone()).await.unwrap();
+        assert_eq!(store.inner.metrics.recover_entries_recovered.get(), 4);
+        assert_eq!(store.inner.metrics.recover_entries_checksum_mismatch.get(), 0);
+        assert!(store.get(&1).await.is_err());
+        store.close().await.unwrap();
+
+        // The entry is skipped with verification, the following entries are still recovered.
+        let store = TestStore::open(GenericStoreConfig {
+            verify_checksum_on_recover: true,
+            ..config
+        })
+        .await
+        .unwrap();
+        assert_eq!(store.inner.metrics.recover_entries_recovered.get(), 3);
+        assert_eq!(store.inner.metrics.recover_entries_checksum_mismatch.get(), 1);
+        assert_eq!(store.inner.metrics.recover_entries_corrupted.get(), 0);
+        assert!(store.get(&1).await.unwrap().is_none());
+        for i in [0, 2, 3] {
+            assert_eq!(store.get(&i).await.unwrap().unwrap().value(), &vec![i as u8; KB]);
+        }
+        store.close().await.unwrap();
+    }
+
+    /// Catalog backend that fails the inserts of the given keys for the given times.
+    #[derive(Debug, Default)]
+    struct FlakyCatalogBackend {
+        failures: Mutex<HashMap<u64, usize>>,
+        attempts: Mutex<HashMap<u64, usize>>,
+        /// Region of the last failed insert.
+        failed: Mutex<Option<RegionId>>,
+    }
+
+    impl CatalogBackend<u64> for FlakyCatalogBackend {
+        fn insert(&self, record: CatalogRecord<u64>) -> BoxFuture<'static, Result<()>> {
+            *self.attempts.lock().entry(*record.key).or_default() += 1;
+            let res = match self.failures.lock().get_mut(&record.key) {
+                Some(failures) if *failures > 0 => {
+                    *failures -= 1;
+                    *self.failed.lock() = Some(record.region);
+                    Err(anyhow!("catalog backend insert of key {} failed", record.key).into())
+                }
+                _ => Ok(()),
+            };
+            async move { res }.boxed()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_catalog_backend_retry() {
+        const KB: usize = 1024;
+        const MB: usize = 1024 * 1024;
+
+        let backend = Arc::new(FlakyCatalogBackend::default());
+        // The insert of key 1 succeeds after retried, the inserts of key 150 always fail.
+        backend.failures.lock().extend([(1, 1), (150, usize::MAX)]);
+        let store = GenericStore::<u64, Vec<u8>, MemDevice>::open(GenericStoreConfig {
+            catalog_backend: Some(backend.clone()),
+            catalog_retry_policy: CatalogRetryPolicy {
+                retries: 2,
+                backoff: Duration::from_millis(1),
+            },
+            ..base_config(MemDeviceConfig {
+                capacity: 16 * MB,
+                region_size: MB,
+                align: 4 * KB,
+                io_size: 16 * KB,
+                write_io_size: None,
+                read_io_size: None,
+            })
+        })
+        .await
+        .unwrap();
+
+        for i in 0..160u64 {
+            store.insert(i, vec![i as u8; 16 * KB]).await.unwrap();
+        }
+        store.barrier().await.unwrap();
+
+        assert_eq!(backend.attempts.lock()[&1], 2);
+        assert_eq!(backend.attempts.lock()[&150], 3);
+        assert_eq!(store.inner.metrics.catalog_ops_persist_failed.get(), 1);
+        assert!(matches!(
+            store.inner.catalog.get(&1).unwrap().consume().1,
+            Index::Region { .. }
+        ));
+        assert_eq!(store.get(&1).await.unwrap().unwrap().as_slice(), &vec![1; 16 * KB][..]);
+        assert!(store.inner.catalog.get(&150).is_none());
+        assert!(store.get(&150).await.unwrap().is_none());
+
+        // The orphaned region is reclaimed before the regions sealed earlier once it is sealed.
+        let orphaned = backend.failed.lock().unwrap();
+        let mut i = 160u64;
+        while !store.inner.region_manager.is_sealed(orphaned) {
+            store.insert(i, vec![i as u8; 16 * KB]).await.unwrap();
+            store.barrier().await.unwrap();
+            i += 1;
+        }
+        assert!((0..store.device().regions() as RegionId)
+            .any(|region| region != orphaned && store.inner.region_manager.is_sealed(region)));
+        assert_eq!(store.inner.region_manager.eviction_pop(), Some(orphaned));
+        assert!(!store.inner.region_manager.is_sealed(orphaned));
+        store.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_max_key_size() {
+        const KB: usize = 1024;
+        const MB: usize = 1024 * 1024;
+
+        let tempdir = tempfile::tempdir().unwrap();
+
+        let config = GenericStoreConfig::<Vec<u8>, Vec<u8>, FsDevice> {
+            name: "test_max_key_size".to_string(),
+            eviction_config: FifoConfig {}.into(),
+            device_config: FsDeviceConfig {
+                dir: PathBuf::from(tempdir.path()),
+                capacity: 16 * MB,
+                file_size: 4 * MB,
+                align: 4 * KB,
+                io_size: 4 * KB,
+                io_executor: IoExecutor::default(),
+                on_region_open_error: RegionOpenErrorPolicy::default(),
+                write_io_size: None,
+                read_io_size: None,
+                extra_dirs: vec![],
+                direct_io: true,
+            },
+            catalog_shards: 1,
+            admissions: vec![],
+            reinsertions: vec![],
+            flushers: 1,
+            reclaimers: 0,
+            recover_concurrency: 2,
+            clean_region_threshold: 1,
+            compression: Compression::None,
+            checksum: true,
+            recent_inserts_capacity: 0,
+            max_key_size: Some(KB),
+            region_compression: None,
+            intra_block_align: None,
+            catalog_snapshot_path: None,
+            reclaim_grace_period: Duration::ZERO,
+            compression_observer: None,
+            unknown_region_version_policy: UnknownRegionVersionPolicy::default(),
+            block_compression: None,
+            background_cpu_cores: None,
+            metrics_recorder: None,
+            write_conflict_policy: WriteConflictPolicy::default(),
+            catalog_ops_limit: None,
+            first_entry_offset: None,
+            verify_region_magic_on_read: false,
+            self_test_on_open: false,
+            scrub_throughput_limit: None,
+            write_coalescing_interval: None,
+            max_writing_regions: None,
+            size_padding: PaddingPolicy::default(),
+            store_key_hash_only: false,
+            zstd_level: 0,
+            max_concurrent_decompressions: None,
+            checksum_algorithm: ChecksumAlgorithm::default(),
+            key_prefix: None,
+            flush_error_policy: FlushErrorPolicy::default(),
+            admission_observer: None,
+            max_region_age: None,
+            channel_drop_policy: ChannelDropPolicy::default(),
+            prefix_compressions: HashMap::new(),
+            io_bytes_in_flight_limit: None,
+            flusher_queue_capacity: None,
+            queue_full_policy: QueueFullPolicy::default(),
+            flush_alignment: None,
+            adaptive_compression: false,
+            scan_read_ahead: 2,
+            verify_checksum_on_recover: false,
+            catalog_backend: None,
+            catalog_retry_policy: CatalogRetryPolicy::default(),
+        };
+
+        let store = GenericStore::open(config).await.unwrap();
+
+        let key = vec![b'k'; MB];
+        let res = store.insert(key.clone(), vec![b'v'; KB]).await;
+        assert!(matches!(res, Err(Error::KeyTooLarge { limit, .. }) if limit == KB));
+        assert!(!store.exists(&key).unwrap());
+
+        store.close().await.unwrap();
+
+        // Nothing is written to the device.
+        assert_eq!(store.inner.metrics.op_bytes_flush.get(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_region_compression() {
+        const KB: usize = 1024;
+        const MB: usize = 1024 * 1024;
+
+        let tempdir = tempfile::tempdir().unwrap();
+
+        let config = TestStoreConfig {
+            name: "".to_string(),
+            eviction_config: FifoConfig {}.into(),
+            device_config: FsDeviceConfig {
+                dir: PathBuf::from(tempdir.path()),
+                capacity: 16 * MB,
+                file_size: 4 * MB,
+                align: 4 * KB,
+                io_size: 4 * KB,
+                io_executor: IoExecutor::default(),
+                on_region_open_error: RegionOpenErrorPolicy::default(),
+                write_io_size: None,
+                read_io_size: None,
+                extra_dirs: vec![],
+                direct_io: true,
+            },
+            catalog_shards: 1,
+            admissions: vec![],
+            reinsertions: vec![],
+            flushers: 1,
+            reclaimers: 0,
+            recover_concurrency: 2,
+            clean_region_threshold: 1,
+            compression: Compression::Zstd,
+            checksum: true,
+            recent_inserts_capacity: 0,
+            max_key_size: None,
+            region_compression: Some(Compression::Lz4),
+            intra_block_align: None,
+            catalog_snapshot_path: None,
+            reclaim_grace_period: Duration::ZERO,
+            compression_observer: None,
+            unknown_region_version_policy: UnknownRegionVersionPolicy::default(),
+            block_compression: None,
+            background_cpu_cores: None,
+            metrics_recorder: None,
+            write_conflict_policy: WriteConflictPolicy::default(),
+            catalog_ops_limit: None,
+            first_entry_offset: None,
+            verify_region_magic_on_read: false,
+            self_test_on_open: false,
+            scrub_throughput_limit: None,
+            write_coalescing_interval: None,
+            max_writing_regions: None,
+            size_padding: PaddingPolicy::default(),
+            store_key_hash_only: false,
+            zstd_level: 0,
+            max_concurrent_decompressions: None,
+            checksum_algorithm: ChecksumAlgorithm::default(),
+            key_prefix: None,
+            flush_error_policy: FlushErrorPolicy::default(),
+            admission_observer: None,
+            max_region_age: None,
+            channel_drop_policy: ChannelDropPolicy::default(),
+            prefix_compressions: HashMap::new(),
+            io_bytes_in_flight_limit: None,
+            flusher_queue_capacity: None,
+            queue_full_policy: QueueFullPolicy::default(),
+            flush_alignment: None,
+            adaptive_compression: false,
+            scan_read_ahead: 2,
+            verify_checksum_on_recover: false,
+            catalog_backend: None,
+            catalog_retry_policy: CatalogRetryPolicy::default(),
+        };
+
+        let store = TestStore::open(config).await.unwrap();
+        for i in 0..8 {
+            store.insert(i, vec![i as u8; 64 * KB]).await.unwrap();
+        }
+        store.close().await.unwrap();
+
+        let mut entries = 0;
+        for id in 0..store.inner.device.regions() as RegionId {
+            let region = store.inner.region_manager.region(&id).clone();
+            let Some(mut iter) = RegionEntryIter::<u64, Vec<u8>, FsDevice>::open(region.clone(), true)
+                .await
+                .unwrap()
+            else {
+                continue;
+            };
+            let align = store.inner.device.align();
+            let slice = region.load_range(0..align).await.unwrap().unwrap();
+            assert_eq!(
+                RegionHeader::read(slice.as_ref()).unwrap().compression,
+                Some(Compression::Lz4)
+            );
+
+            while let Some((_, item)) = iter.next().await.unwrap() {
+                let Index::Region { view } = item.consume().1 else {
+                    panic!("entry from region iter must be indexed by region");
+                };
+                let buf = region.load(view).await.unwrap().unwrap();
+                let header = EntryHeader::read(buf.as_ref()).unwrap();
+                assert_eq!(header.compression, Compression::Lz4);
+                entries += 1;
+            }
+        }
+        assert_eq!(entries, 8);
+
+        for i in 0..8 {
+            assert_eq!(store.get(&i).await.unwrap().unwrap().value(), &vec![i as u8; 64 * KB]);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_intra_block_align() {
+        const KB: usize = 1024;
+        const MB: usize = 1024 * 1024;
+
+        let tempdir = tempfile::tempdir().unwrap();
+
+        let config = TestStoreConfig {
+            name: "".to_string(),
+            eviction_config: FifoConfig {}.into(),
+            device_config: FsDeviceConfig {
+                dir: PathBuf::from(tempdir.path()),
+                capacity: 16 * MB,
+                file_size: 4 * MB,
+                align: 4 * KB,
+                io_size: 16 * KB,
+                io_executor: IoExecutor::default(),
+                on_region_open_error: RegionOpenErrorPolicy::default(),
+                write_io_size: None,
+                read_io_size: None,
+                extra_dirs: vec![],
+                direct_io: true,
+            },
+            catalog_shards: 1,
+            admissions: vec![],
+            reinsertions: vec![],
+            flushers: 1,
+            reclaimers: 0,
+            recover_concurrency: 2,
+            clean_region_threshold: 1,
+            compression: Compression::None,
+            checksum: true,
+            recent_inserts_capacity: 0,
+            max_key_size: None,
+            region_compression: None,
+            intra_block_align: Some(64),
+            catalog_snapshot_path: None,
+            reclaim_grace_period: Duration::ZERO,
+            compression_observer: None,
+            unknown_region_version_policy: UnknownRegionVersionPolicy::default(),
+            block_compression: None,
+            background_cpu_cores: None,
+            metrics_recorder: None,
+            write_conflict_policy: WriteConflictPolicy::default(),
+            catalog_ops_limit: None,
+            first_entry_offset: None,
+            verify_region_magic_on_read: false,
+            self_test_on_open: false,
+            scrub_throughput_limit: None,
+            write_coalescing_interval: None,
+            max_writing_regions: None,
+            size_padding: PaddingPolicy::default(),
+            store_key_hash_only: false,
+            zstd_level: 0,
+            max_concurrent_decompressions: None,
+            checksum_algorithm: ChecksumAlgorithm::default(),
+            key_prefix: None,
+            flush_error_policy: FlushErrorPolicy::default(),
+            admission_observer: None,
+            max_region_age: None,
+            channel_drop_policy: ChannelDropPolicy::default(),
+            prefix_compressions: HashMap::new(),
+            io_bytes_in_flight_limit: None,
+            flusher_queue_capacity: None,
+            queue_full_policy: QueueFullPolicy::default(),
+            flush_alignment: None,
+            adaptive_compression: false,
+            scan_read_ahead: 2,
+            verify_checksum_on_recover: false,
+            catalog_backend: None,
+            catalog_retry_policy: CatalogRetryPolicy::default(),
+        };
+
+        let store = TestStore::open(config.clone()).await.unwrap();
+        for i in 0..16 {
+            store.insert(i, vec![i as u8; 100 + i as usize]).await.unwrap();
+        }
+        store.close().await.unwrap();
+        drop(store);
+
+        // Recover the packed entries from the device.
+        let store = TestStore::open(config).await.unwrap();
+
+        let mut blocks = HashSet::new();
+        for i in 0..16 {
+            let Index::Region { view } = store.inner.catalog.get(&i).unwrap().consume().1 else {
+                panic!("entry must be indexed by region after recovery");
+            };
+            assert_eq!(*view.offset() as usize % 64, 0);
+            blocks.insert(*view.offset() as usize / (4 * KB));
+        }
+        // Small entries are packed in device blocks.
+        assert!(blocks.len() < 16);
+
+        for i in 0..16 {
+            assert_eq!(
+                store.get(&i).await.unwrap().unwrap().value(),
+                &vec![i as u8; 100 + i as usize]
+            );
+        }
+
+        store.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_first_entry_offset() {
+        const KB: usize = 1024;
+        const MB: usize = 1024 * 1024;
+
+        let tempdir = tempfile::tempdir().unwrap();
+
+        let config = TestStoreConfig {
+            name: "".to_string(),
+            eviction_config: FifoConfig {}.into(),
+            device_config: FsDeviceConfig {
+                dir: PathBuf::from(tempdir.path()),
+                capacity: 16 * MB,
+                file_size: 4 * MB,
+                align: 4 * KB,
+                io_size: 16 * KB,
+                io_executor: IoExecutor::default(),
+                on_region_open_error: RegionOpenErrorPolicy::default(),
+                write_io_size: None,
+                read_io_size: None,
+                extra_dirs: vec![],
+                direct_io: true,
+            },
+            catalog_shards: 1,
+            admissions: vec![],
+            reinsertions: vec![],
+            flushers: 1,
+            reclaimers: 0,
+            recover_concurrency: 2,
+            clean_region_threshold: 1,
+            compression: Compression::None,
+            checksum: true,
+            recent_inserts_capacity: 0,
+            max_key_size: None,
+            region_compression: None,
+            intra_block_align: None,
+            catalog_snapshot_path: None,
+            reclaim_grace_period: Duration::ZERO,
+            compression_observer: None,
+            unknown_region_version_policy: UnknownRegionVersionPolicy::default(),
+            block_compression: None,
+            background_cpu_cores: None,
+            metrics_recorder: None,
+            write_conflict_policy: WriteConflictPolicy::default(),
+            catalog_ops_limit: None,
+            first_entry_offset: Some(16 * KB),
+            verify_region_magic_on_read: false,
+            self_test_on_open: false,
+            scrub_throughput_limit: None,
+            write_coalescing_interval: None,
+            max_writing_regions: None,
+            size_padding: PaddingPolicy::default(),
+            store_key_hash_only: false,
+            zstd_level: 0,
+            max_concurrent_decompressions: None,
+            checksum_algorithm: ChecksumAlgorithm::default(),
+            key_prefix: None,
+            flush_error_policy: FlushErrorPolicy::default(),
+            admission_observer: None,
+            max_region_age: None,
+            channel_drop_policy: ChannelDropPolicy::default(),
+            prefix_compressions: HashMap::new(),
+            io_bytes_in_flight_limit: None,
+            flusher_queue_capacity: None,
+            queue_full_policy: QueueFullPolicy::default(),
+            flush_alignment: None,
+            adaptive_compression: false,
+            scan_read_ahead: 2,
+            verify_checksum_on_recover: false,
+            catalog_backend: None,
+            catalog_retry_policy: CatalogRetryPolicy::default(),
+        };
+
+        let store = TestStore::open(config.clone()).await.unwrap();
+        for i in 0..4 {
+            store.insert(i, vec![i as u8; KB]).await.unwrap();
+        }
+        store.close().await.unwrap();
+        drop(store);
+
+        // Recover the entries after the reserved space from the device.
+        let store = TestStore::open(config).await.unwrap();
+
+        let mut offsets = vec![];
+        for i in 0..4 {
+            let Index::Region { view } = store.inner.catalog.get(&i).unwrap().consume().1 else {
+                panic!("entry must be indexed by region after recovery");
+            };
+            offsets.push(*view.offset() as usize);
+        }
+        assert_eq!(offsets.iter().min(), Some(&(16 * KB)));
+
+        for i in 0..4 {
+            assert_eq!(store.get(&i).await.unwrap().unwrap().value(), &vec![i as u8; KB]);
+        }
+
+        store.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_block_compression() {
+        const KB: usize = 1024;
+        const MB: usize = 1024 * 1024;
+
+        let tempdir = tempfile::tempdir().unwrap();
+
+        let config = TestStoreConfig {
+            name: "".to_string(),
+            eviction_config: FifoConfig {}.into(),
+            device_config: FsDeviceConfig {
+                dir: PathBuf::from(tempdir.path()),
+                capacity: 16 * MB,
+                file_size: 4 * MB,
+                align: 4 * KB,
+                io_size: 16 * KB,
+                io_executor: IoExecutor::default(),
+                on_region_open_error: RegionOpenErrorPolicy::default(),
+                write_io_size: None,
+                read_io_size: None,
+                extra_dirs: vec![],
+                direct_io: true,
+            },
+            catalog_shards: 1,
+            admissions: vec![],
+            reinsertions: vec![],
+            flushers: 1,
+            reclaimers: 0,
+            recover_concurrency: 2,
+            clean_region_threshold: 1,
+            compression: Compression::None,
+            checksum: true,
+            recent_inserts_capacity: 0,
+            max_key_size: None,
+            region_compression: None,
+            intra_block_align: None,
+            catalog_snapshot_path: None,
+            reclaim_grace_period: Duration::ZERO,
+            compression_observer: None,
+            unknown_region_version_policy: UnknownRegionVersionPolicy::default(),
+            block_compression: Some(BlockCompression {
+                compression: Compression::Zstd,
+                entries: 4,
+                cache_capacity: 2,
+            }),
+            background_cpu_cores: None,
+            metrics_recorder: None,
+            write_conflict_policy: WriteConflictPolicy::default(),
+            catalog_ops_limit: None,
+            first_entry_offset: None,
+            verify_region_magic_on_read: false,
+            self_test_on_open: false,
+            scrub_throughput_limit: None,
+            write_coalescing_interval: None,
+            max_writing_regions: None,
+            size_padding: PaddingPolicy::default(),
+            store_key_hash_only: false,
+            zstd_level: 0,
+            max_concurrent_decompressions: None,
+            checksum_algorithm: ChecksumAlgorithm::default(),
+            key_prefix: None,
+            flush_error_policy: FlushErrorPolicy::default(),
+            admission_observer: None,
+            max_region_age: None,
+            channel_drop_policy: ChannelDropPolicy::default(),
+            prefix_compressions: HashMap::new(),
+            io_bytes_in_flight_limit: None,
+            flusher_queue_capacity: None,
+            queue_full_policy: QueueFullPolicy::default(),
+            flush_alignment: None,
+            adaptive_compression: false,
+            scan_read_ahead: 2,
+            verify_checksum_on_recover: false,
+            catalog_backend: None,
+            catalog_retry_policy: CatalogRetryPolicy::default(),
+        };
+
+        let value = |i: u64| format!("record-{i}: the quick brown fox jumps over the lazy dog").into_bytes();
+
+        let store = TestStore::open(config.clone()).await.unwrap();
+        // The last 2 entries are written as a partial block on close.
+        for i in 0..10 {
+            store.insert(i, value(i)).await.unwrap();
+        }
+        store.close().await.unwrap();
+        drop(store);
+
+        // Recover the entries compressed in blocks from the device.
+        let store = TestStore::open(config).await.unwrap();
+
+        let mut blocks = HashSet::new();
+        for i in 0..10 {
+            let Index::Block { view, index } = store.inner.catalog.get(&i).unwrap().consume().1 else {
+                panic!("entry must be indexed by block after recovery");
+            };
+            assert_eq!(index as u64, i % 4);
+            blocks.insert(*view.offset());
+        }
+        assert_eq!(blocks.len(), 3);
+
+        // Read twice to read from both the device and the block cache.
+        for _ in 0..2 {
+            for i in 0..10 {
+                assert_eq!(store.get(&i).await.unwrap().unwrap().value(), &value(i));
+            }
+        }
+
+        store.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_background_cpu_budget() {
+        const KB: usize = 1024;
+        const MB: usize = 1024 * 1024;
+
+        let tempdir = tempfile::tempdir().unwrap();
+
+        let config = TestStoreConfig {
+            name: "test-background-cpu-budget".to_string(),
+            eviction_config: FifoConfig {}.into(),
+            device_config: FsDeviceConfig {
+                dir: PathBuf::from(tempdir.path()),
+                capacity: 4 * MB,
+                file_size: 256 * KB,
+                align: 4 * KB,
+                io_size: 16 * KB,
+                io_executor: IoExecutor::default(),
+                on_region_open_error: RegionOpenErrorPolicy::default(),
+                write_io_size: None,
+                read_io_size: None,
+                extra_dirs: vec![],
+                direct_io: true,
+            },
+            catalog_shards: 1,
+            admissions: vec![],
+            // The reclaimed entries are decoded under the budget but not reinserted, or the reclaimers would keep the
+            // flushers busy with the reinserted entries forever.
+            reinsertions: vec![Arc::new(ExistReinsertionPolicy::default())],
+            flushers: 4,
+            reclaimers: 4,
+            recover_concurrency: 2,
+            clean_region_threshold: 4,
+            compression: Compression::Zstd,
+            checksum: true,
+            recent_inserts_capacity: 0,
+            max_key_size: None,
+            region_compression: None,
+            intra_block_align: None,
+            catalog_snapshot_path: None,
+            reclaim_grace_period: Duration::ZERO,
+            compression_observer: None,
+            unknown_region_version_policy: UnknownRegionVersionPolicy::default(),
+            block_compression: None,
+            background_cpu_cores: Some(2.0),
+            metrics_recorder: None,
+            write_conflict_policy: WriteConflictPolicy::default(),
+            catalog_ops_limit: None,
+            first_entry_offset: None,
+            verify_region_magic_on_read: false,
+            self_test_on_open: false,
+            scrub_throughput_limit: None,
+            write_coalescing_interval: None,
+            max_writing_regions: None,
+            size_padding: PaddingPolicy::default(),
+            store_key_hash_only: false,
+            zstd_level: 0,
+            max_concurrent_decompressions: None,
+            checksum_algorithm: ChecksumAlgorithm::default(),
+            key_prefix: None,
+            flush_error_policy: FlushErrorPolicy::default(),
+            admission_observer: None,
+            max_region_age: None,
+            channel_drop_policy: ChannelDropPolicy::default(),
+            prefix_compressions: HashMap::new(),
+            io_bytes_in_flight_limit: None,
+            flusher_queue_capacity: None,
+            queue_full_policy: QueueFullPolicy::default(),
+            flush_alignment: None,
+            adaptive_compression: false,
+            scan_read_ahead: 2,
+            verify_checksum_on_recover: false,
+            catalog_backend: None,
+            catalog_retry_policy: CatalogRetryPolicy::default(),
+        };
+
+        let store = TestStore::open(config).await.unwrap();
+
+        // Write 4x of the capacity concurrently with hardly compressible values to trigger reclamation.
+        let handles = (0..8u64)
+            .map(|task| {
+                let store = store.clone();
+                tokio::spawn(async move {
+                    let mut state = 0x2545f4914f6cdd1d ^ task;
+                    for i in 0..128 {
+                        let value = (0..16 * KB)
+                            .map(|_| {
+                                state ^= state << 13;
+                                state ^= state >> 7;
+                                state ^= state << 17;
+                                state as u8
+                            })
+                            .collect_vec();
+                        store.insert(task * 128 + i, value).await.unwrap();
+                    }
+                })
+            })
+            .collect_vec();
+        for handle in handles {
+            handle.await.unwrap();
+        }
+        store.close().await.unwrap();
+
+        let peak = store.inner.cpu_budget.peak();
+        assert!(peak > 0 && peak <= 2, "peak: {}", peak);
+        assert!(store.inner.metrics.background_cpu_seconds_flush.get() > 0.0);
+        assert!(store.inner.metrics.op_bytes_reclaim.get() > 0);
+    }
+
+    #[tokio::test]
+    async fn test_catalog_snapshot() {
+        const KB: usize = 1024;
+        const MB: usize = 1024 * 1024;
+
+        let tempdir = tempfile::tempdir().unwrap();
+        let snapshot = tempdir.path().join("catalog.snapshot");
+
+        let config = TestStoreConfig {
+            name: "".to_string(),
+            eviction_config: FifoConfig {}.into(),
+            device_config: FsDeviceConfig {
+                dir: PathBuf::from(tempdir.path()),
+                capacity: 16 * MB,
+                file_size: 4 * MB,
+                align: 4 * KB,
+                io_size: 16 * KB,
+                io_executor: IoExecutor::default(),
+                on_region_open_error: RegionOpenErrorPolicy::default(),
+                write_io_size: None,
+                read_io_size: None,
+                extra_dirs: vec![],
+                direct_io: true,
+            },
+            catalog_shards: 1,
+            admissions: vec![],
+            reinsertions: vec![],
+            flushers: 1,
+            reclaimers: 0,
+            recover_concurrency: 2,
+            clean_region_threshold: 1,
+            compression: Compression::None,
+            checksum: true,
+            recent_inserts_capacity: 0,
+            max_key_size: None,
+            region_compression: None,
+            intra_block_align: None,
+            catalog_snapshot_path: Some(snapshot.clone()),
+            reclaim_grace_period: Duration::ZERO,
+            compression_observer: None,
+            unknown_region_version_policy: UnknownRegionVersionPolicy::default(),
+            block_compression: None,
+            background_cpu_cores: None,
+            metrics_recorder: None,
+            write_conflict_policy: WriteConflictPolicy::default(),
+            catalog_ops_limit: None,
+            first_entry_offset: None,
+            verify_region_magic_on_read: false,
+            self_test_on_open: false,
+            scrub_throughput_limit: None,
+            write_coalescing_interval: None,
+            max_writing_regions: None,
+            size_padding: PaddingPolicy::default(),
+            store_key_hash_only: false,
+            zstd_level: 0,
+            max_concurrent_decompressions: None,
+            checksum_algorithm: ChecksumAlgorithm::default(),
+            key_prefix: None,
+            flush_error_policy: FlushErrorPolicy::default(),
+            admission_observer: None,
+            max_region_age: None,
+            channel_drop_policy: ChannelDropPolicy::default(),
+            prefix_compressions: HashMap::new(),
+            io_bytes_in_flight_limit: None,
+            flusher_queue_capacity: None,
+            queue_full_policy: QueueFullPolicy::default(),
+            flush_alignment: None,
+            adaptive_compression: false,
+            scan_read_ahead: 2,
+            verify_checksum_on_recover: false,
+            catalog_backend: None,
+            catalog_retry_policy: CatalogRetryPolicy::default(),
+        };
+
+        // No snapshot yet, recover by scanning all regions.
+        let store = TestStore::open(config.clone()).await.unwrap();
+        for i in 0..8 {
+            store.insert(i, vec![i as u8; 256 * KB]).await.unwrap();
+        }
+        store.close().await.unwrap();
+        drop(store);
+
+        // All entries are indexed by region after recovery.
+        let store = TestStore::open(config.clone()).await.unwrap();
+        store.snapshot_catalog(&snapshot).await.unwrap();
+        store.close().await.unwrap();
+        drop(store);
+
+        // The flusher and the store share the current thread runtime, so the thread local counter observes all
+        // region scans.
+        REGION_SCANS.with(|count| count.set(0));
+
+        let store = TestStore::open(config.clone()).await.unwrap();
+        assert_eq!(REGION_SCANS.with(|count| count.get()), 0);
+        for i in 0..8 {
+            assert_eq!(store.get(&i).await.unwrap().unwrap().value(), &vec![i as u8; 256 * KB]);
+        }
+
+        // Entries written after the snapshot is taken make the snapshot stale.
+        store.insert(8, vec![8; 256 * KB]).await.unwrap();
+        store.close().await.unwrap();
+        drop(store);
+
+        let store = TestStore::open(config).await.unwrap();
+        assert!(REGION_SCANS.with(|count| count.get()) > 0);
+        for i in 0..9 {
+            assert_eq!(store.get(&i).await.unwrap().unwrap().value(), &vec![i as u8; 256 * KB]);
+        }
+        store.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_read_catalog_snapshot() {
+        const KB: usize = 1024;
+        const MB: usize = 1024 * 1024;
+
+        let tempdir = tempfile::tempdir().unwrap();
+        let snapshot = tempdir.path().join("catalog.snapshot");
+
+        let config = TestStoreConfig {
+            name: "".to_string(),
+            eviction_config: FifoConfig {}.into(),
+            device_config: FsDeviceConfig {
+                dir: PathBuf::from(tempdir.path()),
+                capacity: 16 * MB,
+                file_size: 4 * MB,
+                align: 4 * KB,
+                io_size: 16 * KB,
+                io_executor: IoExecutor::default(),
+                on_region_open_error: RegionOpenErrorPolicy::default(),
+                write_io_size: None,
+                read_io_size: None,
+                extra_dirs: vec![],
+                direct_io: true,
+            },
+            catalog_shards: 1,
+            admissions: vec![],
+            reinsertions: vec![],
+            flushers: 1,
+            reclaimers: 0,
+            recover_concurrency: 2,
+            clean_region_threshold: 1,
+            compression: Compression::None,
+            checksum: true,
+            recent_inserts_capacity: 0,
+            max_key_size: None,
+            region_compression: None,
+            intra_block_align: None,
+            catalog_snapshot_path: Some(snapshot.clone()),
+            reclaim_grace_period: Duration::ZERO,
+            compression_observer: None,
+            unknown_region_version_policy: UnknownRegionVersionPolicy::default(),
+            block_compression: None,
+            background_cpu_cores: None,
+            metrics_recorder: None,
+            write_conflict_policy: WriteConflictPolicy::default(),
+            catalog_ops_limit: None,
+            first_entry_offset: None,
+            verify_region_magic_on_read: false,
+            self_test_on_open: false,
+            scrub_throughput_limit: None,
+            write_coalescing_interval: None,
+            max_writing_regions: None,
+            size_padding: PaddingPolicy::default(),
+            store_key_hash_only: false,
+            zstd_level: 0,
+            max_concurrent_decompressions: None,
+            checksum_algorithm: ChecksumAlgorithm::default(),
+            key_prefix: None,
+            flush_error_policy: FlushErrorPolicy::default(),
+            admission_observer: None,
+            max_region_age: None,
+            channel_drop_policy: ChannelDropPolicy::default(),
+            prefix_compressions: HashMap::new(),
+            io_bytes_in_flight_limit: None,
+            flusher_queue_capacity: None,
+            queue_full_policy: QueueFullPolicy::default(),
+            flush_alignment: None,
+            adaptive_compression: false,
+            scan_read_ahead: 2,
+            verify_checksum_on_recover: false,
+            catalog_backend: None,
+            catalog_retry_policy: CatalogRetryPolicy::default(),
+        };
+
+        let store = TestStore::open(config).await.unwrap();
+        for i in 0..16 {
+            store.insert(i, vec![i as u8; 64 * KB]).await.unwrap();
+        }
+        store.insert(0, vec![u8::MAX; 64 * KB]).await.unwrap();
+        store.barrier().await.unwrap();
+        store.remove(&1).unwrap();
+        store.snapshot_catalog(&snapshot).await.unwrap();
+
+        let bytes = std::fs::read(&snapshot).unwrap();
+        assert_eq!(&bytes[..8], b"foyercat");
+        assert_eq!(&bytes[8..12], &snapshot::CATALOG_SNAPSHOT_VERSION.to_le_bytes());
+
+        let parsed = snapshot::read_catalog_snapshot::<u64>(&snapshot).unwrap().unwrap();
+        assert_eq!(parsed.version, snapshot::CATALOG_SNAPSHOT_VERSION);
+        assert_eq!(parsed.fingerprints.len(), store.device().regions());
+        assert_eq!(
+            parsed.entries.iter().map(|entry| entry.key).sorted().collect_vec(),
+            (0..16).filter(|i| *i != 1).collect_vec()
+        );
+        for entry in parsed.entries.iter() {
+            let item = store.inner.catalog.get(&entry.key).unwrap();
+            assert_eq!(entry.sequence, *item.sequence());
+            let Index::Region { view } = item.index() else {
+                panic!("entry must be indexed by region after flushed");
+            };
+            assert_eq!(
+                (
+                    entry.region,
+                    entry.offset,
+                    entry.len,
+                    entry.block_index,
+                    entry.expire_at
+                ),
+                (*view.id(), *view.offset(), *view.len(), None, None)
+            );
+        }
+
+        store.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_unknown_region_version() {
+        const KB: usize = 1024;
+        const MB: usize = 1024 * 1024;
+
+        let tempdir = tempfile::tempdir().unwrap();
+
+        let config = |unknown_region_version_policy| TestStoreConfig {
+            name: "".to_string(),
+            eviction_config: FifoConfig {}.into(),
+            device_config: FsDeviceConfig {
+                dir: PathBuf::from(tempdir.path()),
+                capacity: 16 * MB,
+                file_size: 4 * MB,
+                align: 4 * KB,
+                io_size: 16 * KB,
+                io_executor: IoExecutor::default(),
+                on_region_open_error: RegionOpenErrorPolicy::default(),
+                write_io_size: None,
+                read_io_size: None,
+                extra_dirs: vec![],
+                direct_io: true,
+            },
+            catalog_shards: 1,
+            admissions: vec![],
+            reinsertions: vec![],
+            flushers: 1,
+            reclaimers: 0,
+            recover_concurrency: 2,
+            clean_region_threshold: 1,
+            compression: Compression::None,
+            checksum: true,
+            recent_inserts_capacity: 0,
+            max_key_size: None,
+            region_compression: None,
+            intra_block_align: None,
+            catalog_snapshot_path: None,
+            reclaim_grace_period: Duration::ZERO,
+            compression_observer: None,
+            unknown_region_version_policy,
+            block_compression: None,
+            background_cpu_cores: None,
+            metrics_recorder: None,
+            write_conflict_policy: WriteConflictPolicy::default(),
+            catalog_ops_limit: None,
+            first_entry_offset: None,
+            verify_region_magic_on_read: false,
+            self_test_on_open: false,
+            scrub_throughput_limit: None,
+            write_coalescing_interval: None,
+            max_writing_regions: None,
+            size_padding: PaddingPolicy::default(),
+            store_key_hash_only: false,
+            zstd_level: 0,
+            max_concurrent_decompressions: None,
+            checksum_algorithm: ChecksumAlgorithm::default(),
+            key_prefix: None,
+            flush_error_policy: FlushErrorPolicy::default(),
+            admission_observer: None,
+            max_region_age: None,
+            channel_drop_policy: ChannelDropPolicy::default(),
+            prefix_compressions: HashMap::new(),
+            io_bytes_in_flight_limit: None,
+            flusher_queue_capacity: None,
+            queue_full_policy: QueueFullPolicy::default(),
+            flush_alignment: None,
+            adaptive_compression: false,
+            scan_read_ahead: 2,
+            verify_checksum_on_recover: false,
+            catalog_backend: None,
+            catalog_retry_policy: CatalogRetryPolicy::default(),
+        };
+
+        let store = TestStore::open(config(UnknownRegionVersionPolicy::Abort))
+            .await
+            .unwrap();
+        for i in 0..4 {
+            store.insert(i, vec![i as u8; 64 * KB]).await.unwrap();
+        }
+        store.close().await.unwrap();
+
+        // Bump the format version of the region, as if it is written by a newer build.
+        let Index::Region { view } = store.inner.catalog.get(&0).unwrap().consume().1 else {
+            panic!("entry must be indexed by region after flushed");
+        };
+        let region = *view.id();
+        drop(view);
+        let align = store.device().align();
+        let mut buf = store.device().io_buffer(align, align);
+        buf.fill(0);
+        (&mut buf[..]).put_u64(REGION_MAGIC);
+        (&mut buf[8..]).put_u64(Version::latest().to_u64() + 1);
+        let (res, _) = store.device().write(buf, .., region, 0).await;
+        res.unwrap();
+        drop(store);
+
+        match TestStore::open(config(UnknownRegionVersionPolicy::Abort)).await {
+            Err(Error::UnknownRegionVersion { region: r, version }) => {
+                assert_eq!(r, region);
+                assert_eq!(version, Version::latest().to_u64() + 1);
+            }
+            res => panic!(
+                "open must abort on an unknown region version, got: {:?}",
+                res.map(|_| ())
+            ),
+        }
+
+        // The region is skipped and the store opens as a degraded cache.
+        let store = TestStore::open(config(UnknownRegionVersionPolicy::SkipWithWarning))
+            .await
+            .unwrap();
+        for i in 0..4 {
+            assert!(store.get(&i).await.unwrap().is_none());
+        }
+        store.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_verify_region_magic_on_read() {
+        const KB: usize = 1024;
+        const MB: usize = 1024 * 1024;
+
+        let tempdir = tempfile::tempdir().unwrap();
+
+        let config = TestStoreConfig {
+            name: "".to_string(),
+            eviction_config: FifoConfig {}.into(),
+            device_config: FsDeviceConfig {
+                dir: PathBuf::from(tempdir.path()),
+                capacity: 16 * MB,
+                file_size: 4 * MB,
+                align: 4 * KB,
+                io_size: 16 * KB,
+                io_executor: IoExecutor::default(),
+                on_region_open_error: RegionOpenErrorPolicy::default(),
+                write_io_size: None,
+                read_io_size: None,
+                extra_dirs: vec![],
+                direct_io: true,
+            },
+            catalog_shards: 1,
+            admissions: vec![],
+            reinsertions: vec![],
+            flushers: 1,
+            reclaimers: 0,
+            recover_concurrency: 2,
+            clean_region_threshold: 1,
+            compression: Compression::None,
+            checksum: true,
+            recent_inserts_capacity: 0,
+            max_key_size: None,
+            region_compression: None,
+            intra_block_align: None,
+            catalog_snapshot_path: None,
+            reclaim_grace_period: Duration::ZERO,
+            compression_observer: None,
+            unknown_region_version_policy: UnknownRegionVersionPolicy::default(),
+            block_compression: None,
+            background_cpu_cores: None,
+            metrics_recorder: None,
+            write_conflict_policy: WriteConflictPolicy::default(),
+            catalog_ops_limit: None,
+            first_entry_offset: None,
+            verify_region_magic_on_read: true,
+            self_test_on_open: false,
+            scrub_throughput_limit: None,
+            write_coalescing_interval: None,
+            max_writing_regions: None,
+            size_padding: PaddingPolicy::default(),
+            store_key_hash_only: false,
+            zstd_level: 0,
+            max_concurrent_decompressions: None,
+            checksum_algorithm: ChecksumAlgorithm::default(),
+            key_prefix: None,
+            flush_error_policy: FlushErrorPolicy::default(),
+            admission_observer: None,
+            max_region_age: None,
+            channel_drop_policy: ChannelDropPolicy::default(),
+            prefix_compressions: HashMap::new(),
+            io_bytes_in_flight_limit: None,
+            flusher_queue_capacity: None,
+            queue_full_policy: QueueFullPolicy::default(),
+            flush_alignment: None,
+            adaptive_compression: false,
+            scan_read_ahead: 2,
+            verify_checksum_on_recover: false,
+            catalog_backend: None,
+            catalog_retry_policy: CatalogRetryPolicy::default(),
+        };
+
+        let store = TestStore::open(config).await.unwrap();
+        store.insert(0, vec![0; 64 * KB]).await.unwrap();
+        store.barrier().await.unwrap();
+
+        // Clobber the region header behind the store, as if the region is overwritten by another process.
+        let Index::Region { view } = store.inner.catalog.get(&0).unwrap().consume().1 else {
+            panic!("entry must be indexed by region after flushed");
+        };
+        let region = *view.id();
+        drop(view);
+        let align = store.device().align();
+        let mut buf = store.device().io_buffer(align, align);
+        buf.fill(0);
+        let (res, _) = store.device().write(buf, .., region, 0).await;
+        res.unwrap();
+
+        match store.get(&0).await {
+            Err(Error::RegionClobbered { region: r }) => assert_eq!(r, region),
+            res => panic!(
+                "read from a clobbered region must fail, got: {:?}",
+                res.map(|v| v.is_some())
+            ),
+        }
+
+        store.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_self_test_on_open() {
+        const KB: usize = 1024;
+        const MB: usize = 1024 * 1024;
+
+        let tempdir = tempfile::tempdir().unwrap();
+
+        let config = TestStoreConfig {
+            name: "".to_string(),
+            eviction_config: FifoConfig {}.into(),
+            device_config: FsDeviceConfig {
+                dir: PathBuf::from(tempdir.path()),
+                capacity: 16 * MB,
+                file_size: 4 * MB,
+                align: 4 * KB,
+                io_size: 16 * KB,
+                io_executor: IoExecutor::default(),
+                on_region_open_error: RegionOpenErrorPolicy::default(),
+                write_io_size: None,
+                read_io_size: None,
+                extra_dirs: vec![],
+                direct_io: true,
+            },
+            catalog_shards: 1,
+            admissions: vec![],
+            reinsertions: vec![],
+            flushers: 1,
+            reclaimers: 0,
+            recover_concurrency: 2,
+            clean_region_threshold: 1,
+            compression: Compression::None,
+            checksum: true,
+            recent_inserts_capacity: 0,
+            max_key_size: None,
+            region_compression: None,
+            intra_block_align: None,
+            catalog_snapshot_path: None,
+            reclaim_grace_period: Duration::ZERO,
+            compression_observer: None,
+            unknown_region_version_policy: UnknownRegionVersionPolicy::default(),
+            block_compression: None,
+            background_cpu_cores: None,
+            metrics_recorder: None,
+            write_conflict_policy: WriteConflictPolicy::default(),
+            catalog_ops_limit: None,
+            first_entry_offset: None,
+            verify_region_magic_on_read: false,
+            self_test_on_open: true,
+            scrub_throughput_limit: None,
+            write_coalescing_interval: None,
+            max_writing_regions: None,
+            size_padding: PaddingPolicy::default(),
+            store_key_hash_only: false,
+            zstd_level: 0,
+            max_concurrent_decompressions: None,
+            checksum_algorithm: ChecksumAlgorithm::default(),
+            key_prefix: None,
+            flush_error_policy: FlushErrorPolicy::default(),
+            admission_observer: None,
+            max_region_age: None,
+            channel_drop_policy: ChannelDropPolicy::default(),
+            prefix_compressions: HashMap::new(),
+            io_bytes_in_flight_limit: None,
+            flusher_queue_capacity: None,
+            queue_full_policy: QueueFullPolicy::default(),
+            flush_alignment: None,
+            adaptive_compression: false,
+            scan_read_ahead: 2,
+            verify_checksum_on_recover: false,
+            catalog_backend: None,
+            catalog_retry_policy: CatalogRetryPolicy::default(),
+        };
+
+        let store = TestStore::open(config).await.unwrap();
+        store.insert(0, vec![0; 64 * KB]).await.unwrap();
+        assert!(store.get(&0).await.unwrap().is_some());
+        store.close().await.unwrap();
+
+        // The null device drops all writes, as a device on which the aligned direct I/O doesn't work.
+        let config = GenericStoreConfig::<u64, Vec<u8>, NullDevice> {
+            name: "".to_string(),
+            eviction_config: FifoConfig {}.into(),
+            device_config: 4 * KB,
+            catalog_shards: 1,
+            admissions: vec![],
+            reinsertions: vec![],
+            flushers: 1,
+            reclaimers: 0,
+            recover_concurrency: 2,
+            clean_region_threshold: 1,
+            compression: Compression::None,
+            checksum: true,
+            recent_inserts_capacity: 0,
+            max_key_size: None,
+            region_compression: None,
+            intra_block_align: None,
+            catalog_snapshot_path: None,
+            reclaim_grace_period: Duration::ZERO,
+            compression_observer: None,
+            unknown_region_version_policy: UnknownRegionVersionPolicy::default(),
+            block_compression: None,
+            background_cpu_cores: None,
+            metrics_recorder: None,
+            write_conflict_policy: WriteConflictPolicy::default(),
+            catalog_ops_limit: None,
+            first_entry_offset: None,
+            verify_region_magic_on_read: false,
+            self_test_on_open: true,
+            scrub_throughput_limit: None,
+            write_coalescing_interval: None,
+            max_writing_regions: None,
+            size_padding: PaddingPolicy::default(),
+            store_key_hash_only: false,
+            zstd_level: 0,
+            max_concurrent_decompressions: None,
+            checksum_algorithm: ChecksumAlgorithm::default(),
+            key_prefix: None,
+            flush_error_policy: FlushErrorPolicy::default(),
+            admission_observer: None,
+            max_region_age: None,
+            channel_drop_policy: ChannelDropPolicy::default(),
+            prefix_compressions: HashMap::new(),
+            io_bytes_in_flight_limit: None,
+            flusher_queue_capacity: None,
+            queue_full_policy: QueueFullPolicy::default(),
+            flush_alignment: None,
+            adaptive_compression: false,
+            scan_read_ahead: 2,
+            verify_checksum_on_recover: false,
+            catalog_backend: None,
+            catalog_retry_policy: CatalogRetryPolicy::default(),
+        };
+
+        match GenericStore::open(config).await {
+            Err(Error::DeviceSelfTest { reason }) => assert!(reason.contains("short write"), "{reason}"),
+            res => panic!(
+                "open must fail on a device that fails the self-test, got: {:?}",
+                res.map(|_| ())
+            ),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_flush_error_policy() {
+        const KB: usize = 1024;
+        const MB: usize = 1024 * 1024;
+
+        let config = |flush_error_policy: FlushErrorPolicy, failures: usize| {
+            let failures = Arc::new(AtomicUsize::new(failures));
+            GenericStoreConfig::<u64, Vec<u8>, FlakyDevice> {
+                name: "".to_string(),
+                eviction_config: FifoConfig {}.into(),
+                device_config: (
+                    MemDeviceConfig {
+                        capacity: 16 * MB,
+                        region_size: MB,
+                        align: 4 * KB,
+                        io_size: 16 * KB,
+                        write_io_size: None,
+                        read_io_size: None,
+                    },
+                    failures,
+                ),
+                catalog_shards: 1,
+                admissions: vec![],
+                reinsertions: vec![],
+                flushers: 1,
+                reclaimers: 0,
+                recover_concurrency: 2,
+                clean_region_threshold: 1,
+                compression: Compression::None,
+                checksum: true,
+                recent_inserts_capacity: 0,
+                max_key_size: None,
+                region_compression: None,
+                intra_block_align: None,
+                catalog_snapshot_path: None,
+                reclaim_grace_period: Duration::ZERO,
+                compression_observer: None,
+                unknown_region_version_policy: UnknownRegionVersionPolicy::default(),
+                block_compression: None,
+                background_cpu_cores: None,
+                metrics_recorder: None,
+                write_conflict_policy: WriteConflictPolicy::default(),
+                catalog_ops_limit: None,
+                first_entry_offset: None,
+                verify_region_magic_on_read: false,
+                self_test_on_open: false,
+                scrub_throughput_limit: None,
+                write_coalescing_interval: None,
+                max_writing_regions: None,
+                size_padding: PaddingPolicy::default(),
+                store_key_hash_only: false,
+                zstd_level: 0,
+                max_concurrent_decompressions: None,
+                checksum_algorithm: ChecksumAlgorithm::default(),
+                key_prefix: None,
+                flush_error_policy,
+                admission_observer: None,
+                max_region_age: None,
+                channel_drop_policy: ChannelDropPolicy::default(),
+                prefix_compressions: HashMap::new(),
+                io_bytes_in_flight_limit: None,
+                flusher_queue_capacity: None,
+                queue_full_policy: QueueFullPolicy::default(),
+                flush_alignment: None,
+                adaptive_compression: false,
+                scan_read_ahead: 2,
+                verify_checksum_on_recover: false,
+                catalog_backend: None,
+                catalog_retry_policy: CatalogRetryPolicy::default(),
+            }
+        };
+
+        // The failed writes are retried until the device recovers.
+        let store = GenericStore::open(config(
+            FlushErrorPolicy::Retry {
+                retries: 5,
+                backoff: Duration::from_millis(1),
+            },
+            3,
+        ))
+        .await
+        .unwrap();
+        for i in 0..4u64 {
+            store.insert(i, vec![i as u8; 16 * KB]).await.unwrap();
+        }
+        store.barrier().await.unwrap();
+        assert_eq!(store.device().failures(), 0);
+        for i in 0..4u64 {
+            assert_eq!(
+                store.get(&i).await.unwrap().unwrap().as_slice(),
+                &vec![i as u8; 16 * KB][..]
+            );
+        }
+        store.close().await.unwrap();
+
+        // The entries of the failed write are dropped, and the flusher keeps running.
+        let store = GenericStore::open(config(FlushErrorPolicy::Drop, 1)).await.unwrap();
+        store.insert(0, vec![0; 16 * KB]).await.unwrap();
+        store.barrier().await.unwrap();
+        assert_eq!(store.device().failures(), 0);
+        assert!(store.get(&0).await.unwrap().is_none());
+        assert!(!store.exists(&0).unwrap());
+        for i in 1..4u64 {
+            store.insert(i, vec![i as u8; 16 * KB]).await.unwrap();
+        }
+        store.barrier().await.unwrap();
+        for i in 1..4u64 {
+            assert_eq!(
+                store.get(&i).await.unwrap().unwrap().as_slice(),
+                &vec![i as u8; 16 * KB][..]
+            );
+        }
+        store.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_flush_error_policy_relocate() {
+        const KB: usize = 1024;
+        const MB: usize = 1024 * 1024;
+
+        let config = |failures: usize| {
+            let failures = Arc::new(AtomicUsize::new(failures));
+            GenericStoreConfig::<u64, Vec<u8>, FlakyDevice> {
+                flush_error_policy: FlushErrorPolicy::Relocate {
+                    retries: 3,
+                    backoff: Duration::from_millis(1),
+                },
+                ..base_config((
+                    MemDeviceConfig {
+                        capacity: 16 * MB,
+                        region_size: MB,
+                        align: 4 * KB,
+                        io_size: 16 * KB,
+                        write_io_size: None,
+                        read_io_size: None,
+                    },
+                    failures,
+                ))
+            }
+        };
+        let evictable = |store: &GenericStore<u64, Vec<u8>, FlakyDevice>| {
+            (0..store.device().regions() as RegionId)
+                .filter(|region| store.inner.region_manager.is_sealed(*region))
+                .count()
+        };
+
+        // The entries are relocated until the write to a new region succeeds, the left regions are evictable.
+        let store = GenericStore::open(config(2)).await.unwrap();
+        for i in 0..4u64 {
+            store.insert(i, vec![i as u8; 16 * KB]).await.unwrap();
+        }
+        store.barrier().await.unwrap();
+        assert_eq!(store.device().failures(), 0);
+        assert_eq!(evictable(&store), 2);
+        for i in 0..4u64 {
+            assert_eq!(
+                store.get(&i).await.unwrap().unwrap().as_slice(),
+                &vec![i as u8; 16 * KB][..]
+            );
+        }
+        store.close().await.unwrap();
+
+        // The flusher stops after all relocations fail, the left regions are still evictable rather than leaked.
+        let store = GenericStore::open(config(usize::MAX)).await.unwrap();
+        store.insert(0, vec![0; 16 * KB]).await.unwrap();
+        assert!(store.barrier().await.is_err());
+        assert_eq!(evictable(&store), 4);
+    }
+
+    #[tokio::test]
+    async fn test_get_with_timeout() {
+        const KB: usize = 1024;
+        const MB: usize = 1024 * 1024;
+
+        let store = GenericStore::open(GenericStoreConfig::<u64, Vec<u8>, SlowDevice> {
+            name: "".to_string(),
+            eviction_config: FifoConfig {}.into(),
+            device_config: MemDeviceConfig {
+                capacity: 16 * MB,
+                region_size: MB,
+                align: 4 * KB,
+                io_size: 16 * KB,
+                write_io_size: None,
+                read_io_size: None,
+            },
+            catalog_shards: 1,
+            admissions: vec![],
+            reinsertions: vec![],
+            flushers: 1,
+            reclaimers: 0,
+            recover_concurrency: 2,
+            clean_region_threshold: 1,
+            compression: Compression::None,
+            checksum: true,
+            recent_inserts_capacity: 0,
+            max_key_size: None,
+            region_compression: None,
+            intra_block_align: None,
+            catalog_snapshot_path: None,
+            reclaim_grace_period: Duration::ZERO,
+            compression_observer: None,
+            unknown_region_version_policy: UnknownRegionVersionPolicy::default(),
+            block_compression: None,
+            background_cpu_cores: None,
+            metrics_recorder: None,
+            write_conflict_policy: WriteConflictPolicy::default(),
+            catalog_ops_limit: None,
+            first_entry_offset: None,
+            verify_region_magic_on_read: false,
+            self_test_on_open: false,
+            scrub_throughput_limit: None,
+            write_coalescing_interval: None,
+            max_writing_regions: None,
+            size_padding: PaddingPolicy::default(),
+            store_key_hash_only: false,
+            zstd_level: 0,
+            max_concurrent_decompressions: None,
+            checksum_algorithm: ChecksumAlgorithm::default(),
+            key_prefix: None,
+            flush_error_policy: FlushErrorPolicy::default(),
+            admission_observer: None,
+            max_region_age: None,
+            channel_drop_policy: ChannelDropPolicy::default(),
+            prefix_compressions: HashMap::new(),
+            io_bytes_in_flight_limit: None,
+            flusher_queue_capacity: None,
+            queue_full_policy: QueueFullPolicy::default(),
+            flush_alignment: None,
+            adaptive_compression: false,
+            scan_read_ahead: 2,
+            verify_checksum_on_recover: false,
+            catalog_backend: None,
+            catalog_retry_policy: CatalogRetryPolicy::default(),
+        })
+        .await
+        .unwrap();
+
+        store.insert(1, vec![1; 16 * KB]).await.unwrap();
+        store.barrier().await.unwrap();
+        let buffers = store.device().live_buffers();
+
+        // A fast enough read is not affected.
+        assert_eq!(
+            store
+                .get_with_timeout(&1, Duration::from_secs(10))
+                .await
+                .unwrap()
+                .unwrap()
+                .as_slice(),
+            &vec![1; 16 * KB][..]
+        );
+
+        // A slow read times out, while the io buffer is still held by the in-flight read.
+        store.device().set_read_delay(Duration::from_millis(500));
+        let res = store.get_with_timeout(&1, Duration::from_millis(10)).await;
+        assert!(matches!(res, Err(Error::Timeout(timeout)) if timeout == Duration::from_millis(10)));
+        assert!(store.device().live_buffers() > buffers);
+
+        // The io buffer is reclaimed after the in-flight read completes.
+        let start = Instant::now();
+        while store.device().live_buffers() > buffers {
+            assert!(start.elapsed() < Duration::from_secs(10), "io buffer is not reclaimed");
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        store.device().set_read_delay(Duration::ZERO);
+        assert_eq!(store.get(&1).await.unwrap().unwrap().as_slice(), &vec![1; 16 * KB][..]);
+        assert_eq!(store.device().live_buffers(), buffers);
+
+        store.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_get_range() {
+        const KB: usize = 1024;
+        const MB: usize = 1024 * 1024;
+
+        let config = |compression: Compression| GenericStoreConfig::<u64, Vec<u8>, MemDevice> {
+            name: "".to_string(),
+            eviction_config: FifoConfig {}.into(),
+            device_config: MemDeviceConfig {
+                capacity: 16 * MB,
+                region_size: MB,
+                align: 4 * KB,
+                io_size: 16 * KB,
+                write_io_size: None,
+                read_io_size: None,
+            },
+            catalog_shards: 1,
+            admissions: vec![],
+            reinsertions: vec![],
+            flushers: 1,
+            reclaimers: 0,
+            recover_concurrency: 2,
+            clean_region_threshold: 1,
+            compression,
+            checksum: true,
+            recent_inserts_capacity: 0,
+            max_key_size: None,
+            region_compression: None,
+            intra_block_align: None,
+            catalog_snapshot_path: None,
+            reclaim_grace_period: Duration::ZERO,
+            compression_observer: None,
+            unknown_region_version_policy: UnknownRegionVersionPolicy::default(),
+            block_compression: None,
+            background_cpu_cores: None,
+            metrics_recorder: None,
+            write_conflict_policy: WriteConflictPolicy::default(),
+            catalog_ops_limit: None,
+            first_entry_offset: None,
+            verify_region_magic_on_read: false,
+            self_test_on_open: false,
+            scrub_throughput_limit: None,
+            write_coalescing_interval: None,
+            max_writing_regions: None,
+            size_padding: PaddingPolicy::default(),
+            store_key_hash_only: false,
+            zstd_level: 0,
+            max_concurrent_decompressions: None,
+            checksum_algorithm: ChecksumAlgorithm::default(),
+            key_prefix: None,
+            flush_error_policy: FlushErrorPolicy::default(),
+            admission_observer: None,
+            max_region_age: None,
+            channel_drop_policy: ChannelDropPolicy::default(),
+            prefix_compressions: HashMap::new(),
+            io_bytes_in_flight_limit: None,
+            flusher_queue_capacity: None,
+            queue_full_policy: QueueFullPolicy::default(),
+            flush_alignment: None,
+            adaptive_compression: false,
+            scan_read_ahead: 2,
+            verify_checksum_on_recover: false,
+            catalog_backend: None,
+            catalog_retry_policy: CatalogRetryPolicy::default(),
+        };
+
+        let value = (0..256 * KB).map(|i| i as u8).collect_vec();
+        let serialized = bincode::serialize(&value).unwrap();
+
+        for compression in [Compression::None, Compression::Zstd] {
+            let store = GenericStore::open(config(compression)).await.unwrap();
+            store.insert(1, value.clone()).await.unwrap();
+            store.barrier().await.unwrap();
+
+            let bytes = store.inner.metrics.op_bytes_get.get();
+            assert_eq!(
+                store.get_range(&1, 100 * KB..100 * KB + 100).await.unwrap().unwrap(),
+                &serialized[100 * KB..100 * KB + 100]
+            );
+            // Only the header and the requested range are read if the value is not compressed.
+            if compression == Compression::None {
+                let read = (store.inner.metrics.op_bytes_get.get() - bytes) as usize;
+                assert!(read < 16 * KB, "read: {read}");
+            }
+
+            // The range is clamped to the serialized value.
+            assert_eq!(
+                store
+                    .get_range(&1, serialized.len() - 10..usize::MAX)
+                    .await
+                    .unwrap()
+                    .unwrap(),
+                &serialized[serialized.len() - 10..]
+            );
+            assert!(store
+                .get_range(&1, serialized.len() + 1..serialized.len() + 2)
+                .await
+                .unwrap()
+                .unwrap()
+                .is_empty());
+            assert!(store.get_range(&2, 0..1).await.unwrap().is_none());
+
+            store.close().await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_compaction_age_balance() {
+        const KB: usize = 1024;
+        const MB: usize = 1024 * 1024;
+
+        let config = GenericStoreConfig::<u64, Vec<u8>, MemDevice> {
+            name: "".to_string(),
+            eviction_config: FifoConfig {}.into(),
+            device_config: MemDeviceConfig {
+                capacity: 16 * MB,
+                region_size: MB,
+                align: 4 * KB,
+                io_size: 16 * KB,
+                write_io_size: None,
+                read_io_size: None,
+            },
+            catalog_shards: 1,
+            admissions: vec![],
+            reinsertions: vec![],
+            flushers: 1,
+            reclaimers: 0,
+            recover_concurrency: 2,
+            clean_region_threshold: 1,
+            compression: Compression::None,
+            checksum: true,
+            recent_inserts_capacity: 0,
+            max_key_size: None,
+            region_compression: None,
+            intra_block_align: None,
+            catalog_snapshot_path: None,
+            reclaim_grace_period: Duration::ZERO,
+            compression_observer: None,
+            unknown_region_version_policy: UnknownRegionVersionPolicy::default(),
+            block_compression: None,
+            background_cpu_cores: None,
+            metrics_recorder: None,
+            write_conflict_policy: WriteConflictPolicy::default(),
+            catalog_ops_limit: None,
+            first_entry_offset: None,
+            verify_region_magic_on_read: false,
+            self_test_on_open: false,
+            scrub_throughput_limit: None,
+            write_coalescing_interval: None,
+            max_writing_regions: None,
+            size_padding: PaddingPolicy::default(),
+            store_key_hash_only: false,
+            zstd_level: 0,
+            max_concurrent_decompressions: None,
+            checksum_algorithm: ChecksumAlgorithm::default(),
+            key_prefix: None,
+            flush_error_policy: FlushErrorPolicy::default(),
+            admission_observer: None,
+            max_region_age: None,
+            channel_drop_policy: ChannelDropPolicy::default(),
+            prefix_compressions: HashMap::new(),
+            io_bytes_in_flight_limit: None,
+            flusher_queue_capacity: None,
+            queue_full_policy: QueueFullPolicy::default(),
+            flush_alignment: None,
+            adaptive_compression: false,
+            scan_read_ahead: 2,
+            verify_checksum_on_recover: false,
+            catalog_backend: None,
+            catalog_retry_policy: CatalogRetryPolicy::default(),
+        };
+
+        let store = GenericStore::open(config).await.unwrap();
+
+        let locate = |key: u64| {
+            let item = store.inner.catalog.get(&key).unwrap();
+            let region = match item.index() {
+                Index::Region { view } => *view.id(),
+                _ => unreachable!(),
+            };
+            (*item.sequence(), region)
+        };
+
+        // Batch A is old enough to be compacted.
+        for key in 0..3u64 {
+            store.insert(key, vec![key as u8; 256 * KB]).await.unwrap();
+        }
+        store.barrier().await.unwrap();
+        let olds = (0..3u64).map(locate).collect_vec();
+
+        let boundary = Instant::now();
+
+        // Batch B is younger than the max age.
+        for key in 3..6u64 {
+            store.insert(key, vec![key as u8; 256 * KB]).await.unwrap();
+        }
+        store.barrier().await.unwrap();
+        let youngs = (3..6u64).map(locate).collect_vec();
+
+        // Measure the ages at a fixed time to keep the test independent of the real clock.
+        let now = Instant::now();
+        let report = store
+            .compact_inner(
+                CompactionStrategy::AgeBalance {
+                    max_age: now - boundary,
+                },
+                now,
+            )
+            .await
+            .unwrap();
+        store.barrier().await.unwrap();
+        assert_eq!(
+            report,
+            CompactionReport {
+                selected: 3,
+                relocated: 3,
+                skipped: 0
+            }
+        );
+
+        for (key, (sequence, region)) in (0..3u64).zip_eq(olds) {
+            let (new_sequence, new_region) = locate(key);
+            assert!(new_sequence > sequence);
+            assert_ne!(new_region, region);
+        }
+        for (key, located) in (3..6u64).zip_eq(youngs) {
+            assert_eq!(locate(key), located);
+        }
+        for key in 0..6u64 {
+            assert_eq!(
+                store.get(&key).await.unwrap().unwrap().value(),
+                &vec![key as u8; 256 * KB]
+            );
+        }
+
+        // Nothing is older than a max age that covers all the entries.
+        let report = store
+            .compact(CompactionStrategy::AgeBalance {
+                max_age: Duration::from_secs(3600),
+            })
+            .await
+            .unwrap();
+        assert_eq!(report, CompactionReport::default());
+
+        store.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_admission_observer() {
+        const KB: usize = 1024;
+        const MB: usize = 1024 * 1024;
+
+        #[derive(Debug)]
+        struct RejectOdd;
+
+        impl AdmissionPolicy for RejectOdd {
+            type Key = u64;
+            type Value = Vec<u8>;
+
+            fn init(&self, _: AdmissionContext<Self::Key, Self::Value>) {}
+
+            fn judge(&self, key: &Arc<Self::Key>) -> bool {
+                *key.as_ref() % 2 == 0
+            }
+
+            fn name(&self) -> &'static str {
+                "reject-odd"
+            }
+        }
+
+        #[derive(Debug, Default)]
+        struct DecisionRecorder {
+            decisions: Mutex<Vec<AdmissionDecision<u64>>>,
+        }
+
+        impl AdmissionObserver for DecisionRecorder {
+            type Key = u64;
+
+            fn on_decision(&self, decision: AdmissionDecision<Self::Key>) {
+                self.decisions.lock().push(decision);
+            }
+        }
+
+        let recorder = Arc::new(JudgeRecorder::default());
+        let admissions: Vec<Arc<dyn AdmissionPolicy<Key = u64, Value = Vec<u8>>>> =
+            vec![recorder.clone(), Arc::new(RejectOdd)];
+        let observer = Arc::new(DecisionRecorder::default());
+
+        let config = GenericStoreConfig::<u64, Vec<u8>, MemDevice> {
+            name: "".to_string(),
+            eviction_config: FifoConfig {}.into(),
+            device_config: MemDeviceConfig {
+                capacity: 16 * MB,
+                region_size: MB,
+                align: 4 * KB,
+                io_size: 16 * KB,
+                write_io_size: None,
+                read_io_size: None,
+            },
+            catalog_shards: 1,
+            admissions,
+            reinsertions: vec![],
+            flushers: 1,
+            reclaimers: 0,
+            recover_concurrency: 2,
+            clean_region_threshold: 1,
+            compression: Compression::None,
+            checksum: true,
+            recent_inserts_capacity: 0,
+            max_key_size: None,
+            region_compression: None,
+            intra_block_align: None,
+            catalog_snapshot_path: None,
+            reclaim_grace_period: Duration::ZERO,
+            compression_observer: None,
+            unknown_region_version_policy: UnknownRegionVersionPolicy::default(),
+            block_compression: None,
+            background_cpu_cores: None,
+            metrics_recorder: None,
+            write_conflict_policy: WriteConflictPolicy::default(),
+            catalog_ops_limit: None,
+            first_entry_offset: None,
+            verify_region_magic_on_read: false,
+            self_test_on_open: false,
+            scrub_throughput_limit: None,
+            write_coalescing_interval: None,
+            max_writing_regions: None,
+            size_padding: PaddingPolicy::default(),
+            store_key_hash_only: false,
+            zstd_level: 0,
+            max_concurrent_decompressions: None,
+            checksum_algorithm: ChecksumAlgorithm::default(),
+            key_prefix: None,
+            flush_error_policy: FlushErrorPolicy::default(),
+            admission_observer: Some(observer.clone()),
+            max_region_age: None,
+            channel_drop_policy: ChannelDropPolicy::default(),
+            prefix_compressions: HashMap::new(),
+            io_bytes_in_flight_limit: None,
+            flusher_queue_capacity: None,
+            queue_full_policy: QueueFullPolicy::default(),
+            flush_alignment: None,
+            adaptive_compression: false,
+            scan_read_ahead: 2,
+            verify_checksum_on_recover: false,
+            catalog_backend: None,
+            catalog_retry_policy: CatalogRetryPolicy::default(),
+        };
+
+        let store = GenericStore::open(config).await.unwrap();
+
+        for key in 0..4u64 {
+            store.insert(key, vec![key as u8; KB]).await.unwrap();
+        }
+        store.barrier().await.unwrap();
+
+        let decisions = observer.decisions.lock().clone();
+        assert_eq!(decisions.len(), 8);
+        for (key, decisions) in (0..4u64).zip_eq(decisions.chunks(2)) {
+            assert!(decisions.iter().all(|decision| *decision.key == key));
+
+            assert_eq!(decisions[0].policy, 0);
+            assert_eq!(decisions[0].policy_name, recorder.name());
+            assert!(decisions[0].admitted);
+
+            assert_eq!(decisions[1].policy, 1);
+            assert_eq!(decisions[1].policy_name, "reject-odd");
+            assert_eq!(decisions[1].admitted, key % 2 == 0);
+
+            assert_eq!(store.get(&key).await.unwrap().is_some(), key % 2 == 0);
+        }
+
+        store.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_scrub() {
+        const KB: usize = 1024;
+        const MB: usize = 1024 * 1024;
+
+        let tempdir = tempfile::tempdir().unwrap();
+
+        let config = TestStoreConfig {
+            name: "".to_string(),
+            eviction_config: FifoConfig {}.into(),
+            device_config: FsDeviceConfig {
+                dir: PathBuf::from(tempdir.path()),
+                capacity: 16 * MB,
+                file_size: 4 * MB,
+                align: 4 * KB,
+                io_size: 16 * KB,
+                io_executor: IoExecutor::default(),
+                on_region_open_error: RegionOpenErrorPolicy::default(),
+                write_io_size: None,
+                read_io_size: None,
+                extra_dirs: vec![],
+                direct_io: true,
+            },
+            catalog_shards: 1,
+            admissions: vec![],
+            reinsertions: vec![],
+            flushers: 1,
+            reclaimers: 0,
+            recover_concurrency: 2,
+            clean_region_threshold: 1,
+            compression: Compression::None,
+            checksum: true,
+            recent_inserts_capacity: 0,
+            max_key_size: None,
+            region_compression: None,
+            intra_block_align: None,
+            catalog_snapshot_path: None,
+            reclaim_grace_period: Duration::ZERO,
+            compression_observer: None,
+            unknown_region_version_policy: UnknownRegionVersionPolicy::default(),
+            block_compression: None,
+            background_cpu_cores: None,
+            metrics_recorder: None,
+            write_conflict_policy: WriteConflictPolicy::default(),
+            catalog_ops_limit: None,
+            first_entry_offset: None,
+            verify_region_magic_on_read: false,
+            self_test_on_open: false,
+            scrub_throughput_limit: Some(64 * MB),
+            write_coalescing_interval: None,
+            max_writing_regions: None,
+            size_padding: PaddingPolicy::default(),
+            store_key_hash_only: false,
+            zstd_level: 0,
+            max_concurrent_decompressions: None,
+            checksum_algorithm: ChecksumAlgorithm::default(),
+            key_prefix: None,
+            flush_error_policy: FlushErrorPolicy::default(),
+            admission_observer: None,
+            max_region_age: None,
+            channel_drop_policy: ChannelDropPolicy::default(),
+            prefix_compressions: HashMap::new(),
+            io_bytes_in_flight_limit: None,
+            flusher_queue_capacity: None,
+            queue_full_policy: QueueFullPolicy::default(),
+            flush_alignment: None,
+            adaptive_compression: false,
+            scan_read_ahead: 2,
+            verify_checksum_on_recover: false,
+            catalog_backend: None,
+            catalog_retry_policy: CatalogRetryPolicy::default(),
+        };
+
+        let store = TestStore::open(config).await.unwrap();
+        for i in 0..10 {
+            store.insert(i, vec![i as u8; 16 * KB]).await.unwrap();
+        }
+        store.barrier().await.unwrap();
+
+        let report = store.scrub(false).await.unwrap();
+        assert_eq!(report.entries, 10);
+        assert!(report.corrupted.is_empty());
+
+        // Corrupt the value of some entries behind the store.
+        let corrupted = [1, 4, 7];
+        let mut locations = vec![];
+        for i in corrupted {
+            let Index::Region { view } = store.inner.catalog.get(&i).unwrap().consume().1 else {
+                panic!("entry must be indexed by region after flushed");
+            };
+            let (region, offset) = (*view.id(), *view.offset() as usize);
+            drop(view);
+            locations.push((region, offset));
+
+            let align = store.device().align();
+            let mut buf = store.device().io_buffer(align, align);
+            buf.fill(u8::MAX);
+            let (res, _) = store.device().write(buf, .., region, offset + align).await;
+            res.unwrap();
+        }
+
+        let report = store.scrub(false).await.unwrap();
+        assert_eq!(report.entries, 10);
+        assert_eq!(
+            report
+                .corrupted
+                .iter()
+                .map(|(key, _, region, offset)| (**key, *region, *offset))
+                .collect_vec(),
+            corrupted
+                .iter()
+                .zip(locations.iter())
+                .map(|(key, (region, offset))| (*key, *region, *offset))
+                .collect_vec()
+        );
+        let report = store.scrub(true).await.unwrap();
+        assert_eq!(report.corrupted.len(), corrupted.len());
+        for i in 0..10 {
+            assert_eq!(store.get(&i).await.unwrap().is_none(), corrupted.contains(&i));
+        }
+
+        let report = store.scrub(false).await.unwrap();
+        assert_eq!(report.entries, 10 - corrupted.len());
+        assert!(report.corrupted.is_empty());
+
+        store.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_scan() {
+        const KB: usize = 1024;
+        const MB: usize = 1024 * 1024;
+
+        let tempdir = tempfile::tempdir().unwrap();
+
+        let config = TestStoreConfig {
+            name: "".to_string(),
+            eviction_config: FifoConfig {}.into(),
+            device_config: FsDeviceConfig {
+                dir: PathBuf::from(tempdir.path()),
+                capacity: 16 * MB,
+                file_size: 4 * MB,
+                align: 4 * KB,
+                io_size: 16 * KB,
+                io_executor: IoExecutor::default(),
+                on_region_open_error: RegionOpenErrorPolicy::default(),
+                write_io_size: None,
+                read_io_size: None,
+                extra_dirs: vec![],
+                direct_io: true,
+            },
+            catalog_shards: 1,
+            admissions: vec![],
+            reinsertions: vec![],
+            flushers: 1,
+            reclaimers: 0,
+            recover_concurrency: 2,
+            clean_region_threshold: 1,
+            compression: Compression::None,
+            checksum: true,
+            recent_inserts_capacity: 0,
+            max_key_size: None,
+            region_compression: None,
+            intra_block_align: None,
+            catalog_snapshot_path: None,
+            reclaim_grace_period: Duration::ZERO,
+            compression_observer: None,
+            unknown_region_version_policy: UnknownRegionVersionPolicy::default(),
+            block_compression: None,
+            background_cpu_cores: None,
+            metrics_recorder: None,
+            write_conflict_policy: WriteConflictPolicy::default(),
+            catalog_ops_limit: None,
+            first_entry_offset: None,
+            verify_region_magic_on_read: false,
+            self_test_on_open: false,
+            scrub_throughput_limit: None,
+            write_coalescing_interval: None,
+            max_writing_regions: None,
+            size_padding: PaddingPolicy::default(),
+            store_key_hash_only: false,
+            zstd_level: 0,
+            max_concurrent_decompressions: None,
+            checksum_algorithm: ChecksumAlgorithm::default(),
+            key_prefix: None,
+            flush_error_policy: FlushErrorPolicy::default(),
+            admission_observer: None,
+            max_region_age: None,
+            channel_drop_policy: ChannelDropPolicy::default(),
+            prefix_compressions: HashMap::new(),
+            io_bytes_in_flight_limit: None,
+            flusher_queue_capacity: None,
+            queue_full_policy: QueueFullPolicy::default(),
+            flush_alignment: None,
+            adaptive_compression: false,
+            scan_read_ahead: 2,
+            verify_checksum_on_recover: false,
+            catalog_backend: None,
+            catalog_retry_policy: CatalogRetryPolicy::default(),
+        };
+
+        let store = TestStore::open(config).await.unwrap();
+        for i in 0..20 {
+            store.insert(i, vec![i as u8; 16 * KB]).await.unwrap();
+        }
+        // Overwritten and removed entries are left in the regions.
+        for i in 0..5 {
+            store.insert(i, vec![u8::MAX; 16 * KB]).await.unwrap();
+        }
+        store.barrier().await.unwrap();
+        for i in 5..8 {
+            store.remove(&i).unwrap();
+        }
+
+        let mut expected = (0..20).map(|i| (i, vec![i as u8; 16 * KB])).collect::<HashMap<_, _>>();
+        for i in 0..5 {
+            expected.insert(i, vec![u8::MAX; 16 * KB]);
+        }
+        for i in 5..8 {
+            expected.remove(&i);
+        }
+
+        let scanned = store.scan().map(|res| res.unwrap()).collect::<Vec<_>>().await;
+        assert_eq!(scanned.len(), expected.len());
+        assert_eq!(scanned.into_iter().collect::<HashMap<_, _>>(), expected);
+
+        // Corrupt the value of an entry behind the store, the other entries are still scanned.
+        let Index::Region { view } = store.inner.catalog.get(&10).unwrap().consume().1 else {
+            panic!("entry must be indexed by region after flushed");
+        };
+        let (region, offset) = (*view.id(), *view.offset() as usize);
+        drop(view);
+        let align = store.device().align();
+        let mut buf = store.device().io_buffer(align, align);
+        buf.fill(u8::MAX);
+        let (res, _) = store.device().write(buf, .., region, offset + align).await;
+        res.unwrap();
+
+        let (oks, errs): (Vec<_>, Vec<_>) = store
+            .scan()
+            .collect::<Vec<_>>()
+            .await
+            .into_iter()
+            .partition(Result::is_ok);
+        assert_eq!(errs.len(), 1);
+        expected.remove(&10);
+        assert_eq!(oks.into_iter().map(Result::unwrap).collect::<HashMap<_, _>>(), expected);
+
+        store.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_scan_read_ahead() {
+        const KB: usize = 1024;
+        const MB: usize = 1024 * 1024;
+
+        for scan_read_ahead in [0, 3] {
+            let store = GenericStore::open(GenericStoreConfig::<u64, Vec<u8>, SlowDevice> {
+                name: "".to_string(),
+                eviction_config: FifoConfig {}.into(),
+                device_config: MemDeviceConfig {
+                    capacity: 16 * MB,
+                    region_size: MB,
+                    align: 4 * KB,
+                    io_size: 16 * KB,
+                    write_io_size: None,
+                    read_io_size: None,
+                },
+                catalog_shards: 1,
+                admissions: vec![],
+                reinsertions: vec![],
+                flushers: 1,
+                reclaimers: 0,
+                recover_concurrency: 2,
+                clean_region_threshold: 1,
+                compression: Compression::None,
+                checksum: true,
+                recent_inserts_capacity: 0,
+                max_key_size: None,
+                region_compression: None,
+                intra_block_align: None,
+                catalog_snapshot_path: None,
+                reclaim_grace_period: Duration::ZERO,
+                compression_observer: None,
+                unknown_region_version_policy: UnknownRegionVersionPolicy::default(),
+                block_compression: None,
+                background_cpu_cores: None,
+                metrics_recorder: None,
+                write_conflict_policy: WriteConflictPolicy::default(),
+                catalog_ops_limit: None,
+                first_entry_offset: None,
+                verify_region_magic_on_read: false,
+                self_test_on_open: false,
+                scrub_throughput_limit: None,
+                write_coalescing_interval: None,
+                max_writing_regions: None,
+                size_padding: PaddingPolicy::default(),
+                store_key_hash_only: false,
+                zstd_level: 0,
+                max_concurrent_decompressions: None,
+                checksum_algorithm: ChecksumAlgorithm::default(),
+                key_prefix: None,
+                flush_error_policy: FlushErrorPolicy::default(),
+                admission_observer: None,
+                max_region_age: None,
+                channel_drop_policy: ChannelDropPolicy::default(),
+                prefix_compressions: HashMap::new(),
+                io_bytes_in_flight_limit: None,
+                flusher_queue_capacity: None,
+                queue_full_policy: QueueFullPolicy::default(),
+                flush_alignment: None,
+                adaptive_compression: false,
+                scan_read_ahead,
+                verify_checksum_on_recover: false,
+                catalog_backend: None,
+                catalog_retry_policy: CatalogRetryPolicy::default(),
+            })
+            .await
+            .unwrap();
+
+            // The entries span several regions.
+            for i in 0..64 {
+                store.insert(i, vec![i as u8; 64 * KB]).await.unwrap();
+            }
+            store.barrier().await.unwrap();
+            let buffers = store.device().live_buffers();
+            store.device().set_read_delay(Duration::from_millis(5));
+
+            let mut scan = store.scan();
+            let mut scanned = HashMap::new();
+            let mut max_buffers = buffers;
+            while let Some(res) = scan.next().await {
+                let (key, value) = res.unwrap();
+                scanned.insert(key, value);
+                max_buffers = max_buffers.max(store.device().live_buffers());
+            }
+            // The buffers are reused, one for the current region and one for each region read ahead.
+            assert_eq!(max_buffers, buffers + scan_read_ahead + 1);
+            assert_eq!(
+                scanned,
+                (0..64).map(|i| (i, vec![i as u8; 64 * KB])).collect::<HashMap<_, _>>()
+            );
+            drop(scan);
+            assert_eq!(store.device().live_buffers(), buffers);
+
+            store.device().set_read_delay(Duration::ZERO);
+            store.close().await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_scan_read_error() {
+        const KB: usize = 1024;
+        const MB: usize = 1024 * 1024;
+
+        let store = GenericStore::open(GenericStoreConfig::<u64, Vec<u8>, FlakyDevice> {
+            name: "".to_string(),
+            eviction_config: FifoConfig {}.into(),
+            device_config: (
+                MemDeviceConfig {
+                    capacity: 16 * MB,
+                    region_size: MB,
+                    align: 4 * KB,
+                    io_size: 16 * KB,
+                    write_io_size: None,
+                    read_io_size: None,
+                },
+                Arc::new(AtomicUsize::new(0)),
+            ),
+            catalog_shards: 1,
+            admissions: vec![],
+            reinsertions: vec![],
+            flushers: 1,
+            reclaimers: 0,
+            recover_concurrency: 2,
+            clean_region_threshold: 1,
+            compression: Compression::None,
+            checksum: true,
+            recent_inserts_capacity: 0,
+            max_key_size: None,
+            region_compression: None,
+            intra_block_align: None,
+            catalog_snapshot_path: None,
+            reclaim_grace_period: Duration::ZERO,
+            compression_observer: None,
+            unknown_region_version_policy: UnknownRegionVersionPolicy::default(),
+            block_compression: None,
+            background_cpu_cores: None,
+            metrics_recorder: None,
+            write_conflict_policy: WriteConflictPolicy::default(),
+            catalog_ops_limit: None,
+            first_entry_offset: None,
+            verify_region_magic_on_read: false,
+            self_test_on_open: false,
+            scrub_throughput_limit: None,
+            write_coalescing_interval: None,
+            max_writing_regions: None,
+            size_padding: PaddingPolicy::default(),
+            store_key_hash_only: false,
+            zstd_level: 0,
+            max_concurrent_decompressions: None,
+            checksum_algorithm: ChecksumAlgorithm::default(),
+            key_prefix: None,
+            flush_error_policy: FlushErrorPolicy::default(),
+            admission_observer: None,
+            max_region_age: None,
+            channel_drop_policy: ChannelDropPolicy::default(),
+            prefix_compressions: HashMap::new(),
+            io_bytes_in_flight_limit: None,
+            flusher_queue_capacity: None,
+            queue_full_policy: QueueFullPolicy::default(),
+            flush_alignment: None,
+            adaptive_compression: false,
+            scan_read_ahead: 2,
+            verify_checksum_on_recover: false,
+            catalog_backend: None,
+            catalog_retry_policy: CatalogRetryPolicy::default(),
+        })
+        .await
+        .unwrap();
+
+        for i in 0..64 {
+            store.insert(i, vec![i as u8; 64 * KB]).await.unwrap();
+        }
+        store.barrier().await.unwrap();
+
+        let region = |key: u64| match store.inner.catalog.get(&key).unwrap().consume().1 {
+            Index::Region { view } => *view.id(),
+            _ => panic!("entry must be indexed by region after flushed"),
+        };
+        let regions = (0..64).map(region).collect::<HashSet<_>>();
+        assert!(regions.len() >= 3);
+        // The region is read ahead while the first region is parsed.
+        let failing = regions.iter().copied().sorted().nth(2).unwrap();
+        store.device().fail_reads(Some(failing));
+
+        let results = store.scan().collect::<Vec<_>>().await;
+        let errs = results.iter().positions(Result::is_err).collect_vec();
+        assert_eq!(errs.len(), 1);
+        // The error is yielded in place of the entries of the failing region.
+        let (before, after) = results.split_at(errs[0]);
+        assert!(before.iter().all(|res| region(res.as_ref().unwrap().0) < failing));
+        assert!(after[1..].iter().all(|res| region(res.as_ref().unwrap().0) > failing));
+        assert_eq!(results.len() - 1, (0..64).filter(|key| region(*key) != failing).count());
+
+        store.device().fail_reads(None);
+        assert_eq!(
+            store
+                .scan()
+                .filter(|res| futures::future::ready(res.is_ok()))
+                .count()
+                .await,
+            64
+        );
+
+        store.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_key_prefix() {
+        const KB: usize = 1024;
+        const MB: usize = 1024 * 1024;
+
+        let tempdir = tempfile::tempdir().unwrap();
+
+        let config = TestStoreConfig {
+            name: "".to_string(),
+            eviction_config: FifoConfig {}.into(),
+            device_config: FsDeviceConfig {
+                dir: PathBuf::from(tempdir.path()),
+                capacity: 16 * MB,
+                file_size: MB,
+                align: 4 * KB,
+                io_size: 16 * KB,
+                io_executor: IoExecutor::default(),
+                on_region_open_error: RegionOpenErrorPolicy::default(),
+                write_io_size: None,
+                read_io_size: None,
+                extra_dirs: vec![],
+                direct_io: true,
+            },
+            catalog_shards: 1,
+            admissions: vec![],
+            reinsertions: vec![],
+            flushers: 2,
+            reclaimers: 0,
+            recover_concurrency: 2,
+            clean_region_threshold: 1,
+            compression: Compression::None,
+            checksum: true,
+            recent_inserts_capacity: 0,
+            max_key_size: None,
+            region_compression: None,
+            intra_block_align: None,
+            catalog_snapshot_path: None,
+            reclaim_grace_period: Duration::ZERO,
+            compression_observer: None,
+            unknown_region_version_policy: UnknownRegionVersionPolicy::default(),
+            block_compression: None,
+            background_cpu_cores: None,
+            metrics_recorder: None,
+            write_conflict_policy: WriteConflictPolicy::default(),
+            catalog_ops_limit: None,
+            first_entry_offset: None,
+            verify_region_magic_on_read: false,
+            self_test_on_open: false,
+            scrub_throughput_limit: None,
+            write_coalescing_interval: None,
+            max_writing_regions: None,
+            size_padding: PaddingPolicy::default(),
+            store_key_hash_only: false,
+            zstd_level: 0,
+            max_concurrent_decompressions: None,
+            checksum_algorithm: ChecksumAlgorithm::default(),
+            key_prefix: Some(Arc::new(|key: &u64| key >> 32)),
+            flush_error_policy: FlushErrorPolicy::default(),
+            admission_observer: None,
+            max_region_age: None,
+            channel_drop_policy: ChannelDropPolicy::default(),
+            prefix_compressions: HashMap::new(),
+            io_bytes_in_flight_limit: None,
+            flusher_queue_capacity: None,
+            queue_full_policy: QueueFullPolicy::default(),
+            flush_alignment: None,
+            adaptive_compression: false,
+            scan_read_ahead: 2,
+            verify_checksum_on_recover: false,
+            catalog_backend: None,
+            catalog_retry_policy: CatalogRetryPolicy::default(),
+        };
+
+        let store = TestStore::open(config).await.unwrap();
+
+        // Interleave the inserts of two prefixes.
+        for i in 0..100u64 {
+            for prefix in 0..2u64 {
+                store.insert((prefix << 32) + i, vec![i as u8; 16 * KB]).await.unwrap();
+            }
+        }
+        store.barrier().await.unwrap();
+
+        let mut prefixes = vec![];
+        for region_id in 0..store.device().regions() as RegionId {
+            let region = store.inner.region_manager.region(&region_id).clone();
+            let Some(mut iter) = RegionEntryIter::<u64, Vec<u8>, FsDevice>::open(region, true)
+                .await
+                .unwrap()
+            else {
+                continue;
+            };
+            let mut keys = vec![];
+            while let Some((key, _, _)) = iter.next_kv().await.unwrap() {
+                keys.push(key);
+            }
+            if keys.is_empty() {
+                continue;
+            }
+            // Each region holds the entries of a single prefix.
+            let prefix = keys[0] >> 32;
+            assert!(
+                keys.iter().all(|key| key >> 32 == prefix),
+                "region {region_id}: {keys:?}"
+            );
+            prefixes.push(prefix);
+        }
+        // Each prefix spans more than one region.
+        assert_eq!(prefixes.iter().filter(|prefix| **prefix == 0).count(), 2);
+        assert_eq!(prefixes.iter().filter(|prefix| **prefix == 1).count(), 2);
+
+        store.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_prefix_compressions() {
+        const KB: usize = 1024;
+        const MB: usize = 1024 * 1024;
+
+        let tempdir = tempfile::tempdir().unwrap();
+
+        let config = TestStoreConfig {
+            name: "".to_string(),
+            eviction_config: FifoConfig {}.into(),
+            device_config: FsDeviceConfig {
+                dir: PathBuf::from(tempdir.path()),
+                capacity: 16 * MB,
+                file_size: MB,
+                align: 4 * KB,
+                io_size: 16 * KB,
+                io_executor: IoExecutor::default(),
+                on_region_open_error: RegionOpenErrorPolicy::default(),
+                write_io_size: None,
+                read_io_size: None,
+                extra_dirs: vec![],
+                direct_io: true,
+            },
+            catalog_shards: 1,
+            admissions: vec![],
+            reinsertions: vec![],
+            flushers: 2,
+            reclaimers: 0,
+            recover_concurrency: 2,
+            clean_region_threshold: 1,
+            compression: Compression::None,
+            checksum: true,
+            recent_inserts_capacity: 0,
+            max_key_size: None,
+            region_compression: None,
+            intra_block_align: None,
+            catalog_snapshot_path: None,
+            reclaim_grace_period: Duration::ZERO,
+            compression_observer: None,
+            unknown_region_version_policy: UnknownRegionVersionPolicy::default(),
+            block_compression: None,
+            background_cpu_cores: None,
+            metrics_recorder: None,
+            write_conflict_policy: WriteConflictPolicy::default(),
+            catalog_ops_limit: None,
+            first_entry_offset: None,
+            verify_region_magic_on_read: false,
+            self_test_on_open: false,
+            scrub_throughput_limit: None,
+            write_coalescing_interval: None,
+            max_writing_regions: None,
+            size_padding: PaddingPolicy::default(),
+            store_key_hash_only: false,
+            zstd_level: 0,
+            max_concurrent_decompressions: None,
+            checksum_algorithm: ChecksumAlgorithm::default(),
+            key_prefix: Some(Arc::new(|key: &u64| key >> 32)),
+            flush_error_policy: FlushErrorPolicy::default(),
+            admission_observer: None,
+            max_region_age: None,
+            channel_drop_policy: ChannelDropPolicy::default(),
+            prefix_compressions: HashMap::from([(0, Compression::Zstd), (1, Compression::Lz4)]),
+            io_bytes_in_flight_limit: None,
+            flusher_queue_capacity: None,
+            queue_full_policy: QueueFullPolicy::default(),
+            flush_alignment: None,
+            adaptive_compression: false,
+            scan_read_ahead: 2,
+            verify_checksum_on_recover: false,
+            catalog_backend: None,
+            catalog_retry_policy: CatalogRetryPolicy::default(),
+        };
+
+        let store = TestStore::open(config).await.unwrap();
+
+        // Prefix 2 is not configured and falls back to the store compression.
+        let expected = [(0, Compression::Zstd), (1, Compression::Lz4), (2, Compression::None)];
+        for (prefix, _) in expected {
+            for i in 0..4u64 {
+                store.insert((prefix << 32) + i, vec![i as u8; 16 * KB]).await.unwrap();
+            }
+        }
+        store.barrier().await.unwrap();
+
+        for (prefix, compression) in expected {
+            for i in 0..4u64 {
+                let key = (prefix << 32) + i;
+                let Index::Region { view } = store.inner.catalog.get(&key).unwrap().consume().1 else {
+                    panic!("entry must be indexed by region after flushed");
+                };
+                let buf = store
+                    .inner
+                    .region_manager
+                    .region(view.id())
+                    .load(view)
+                    .await
+                    .unwrap()
+                    .unwrap();
+                assert_eq!(EntryHeader::read(buf.as_ref()).unwrap().compression, compression);
+                assert_eq!(store.get(&key).await.unwrap().unwrap().value(), &vec![i as u8; 16 * KB]);
+            }
+        }
+
+        store.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_entry_compression() {
+        const KB: usize = 1024;
+        const MB: usize = 1024 * 1024;
+
+        let tempdir = tempfile::tempdir().unwrap();
+
+        let config = TestStoreConfig {
+            name: "".to_string(),
+            eviction_config: FifoConfig {}.into(),
+            device_config: FsDeviceConfig {
+                dir: PathBuf::from(tempdir.path()),
+                capacity: 16 * MB,
+                file_size: MB,
+                align: 4 * KB,
+                io_size: 16 * KB,
+                io_executor: IoExecutor::default(),
+                on_region_open_error: RegionOpenErrorPolicy::default(),
+                write_io_size: None,
+                read_io_size: None,
+                extra_dirs: vec![],
+                direct_io: true,
+            },
+            catalog_shards: 1,
+            admissions: vec![],
+            reinsertions: vec![],
+            flushers: 1,
+            reclaimers: 0,
+            recover_concurrency: 2,
+            clean_region_threshold: 1,
+            compression: Compression::Lz4,
+            checksum: true,
+            recent_inserts_capacity: 0,
+            max_key_size: None,
+            region_compression: None,
+            intra_block_align: None,
+            catalog_snapshot_path: None,
+            reclaim_grace_period: Duration::ZERO,
+            compression_observer: None,
+            unknown_region_version_policy: UnknownRegionVersionPolicy::default(),
+            block_compression: None,
+            background_cpu_cores: None,
+            metrics_recorder: None,
+            write_conflict_policy: WriteConflictPolicy::default(),
+            catalog_ops_limit: None,
+            first_entry_offset: None,
+            verify_region_magic_on_read: false,
+            self_test_on_open: false,
+            scrub_throughput_limit: None,
+            write_coalescing_interval: None,
+            max_writing_regions: None,
+            size_padding: PaddingPolicy::default(),
+            store_key_hash_only: false,
+            zstd_level: 0,
+            max_concurrent_decompressions: None,
+            checksum_algorithm: ChecksumAlgorithm::default(),
+            key_prefix: None,
+            flush_error_policy: FlushErrorPolicy::default(),
+            admission_observer: None,
+            max_region_age: None,
+            channel_drop_policy: ChannelDropPolicy::default(),
+            prefix_compressions: HashMap::new(),
+            io_bytes_in_flight_limit: None,
+            flusher_queue_capacity: None,
+            queue_full_policy: QueueFullPolicy::default(),
+            flush_alignment: None,
+            adaptive_compression: false,
+            scan_read_ahead: 2,
+            verify_checksum_on_recover: false,
+            catalog_backend: None,
+            catalog_retry_policy: CatalogRetryPolicy::default(),
+        };
+
+        let store = TestStore::open(config).await.unwrap();
+
+        // Entries without a compression of their own fall back to the store compression.
+        let compressions = [
+            Some(Compression::None),
+            Some(Compression::Zstd),
+            None,
+            Some(Compression::Lz4),
+            Some(Compression::None),
+            None,
+        ];
+        for (i, compression) in compressions.iter().enumerate() {
+            let mut writer = store.writer(i as u64);
+            assert_eq!(writer.compression(), Compression::Lz4);
+            if let Some(compression) = compression {
+                writer.set_compression(*compression);
+            }
+            writer.finish(vec![i as u8; 16 * KB]).await.unwrap().unwrap();
+        }
+        store.barrier().await.unwrap();
+
+        let mut regions = HashSet::new();
+        for (i, compression) in compressions.iter().enumerate() {
+            let key = i as u64;
+            let Index::Region { view } = store.inner.catalog.get(&key).unwrap().consume().1 else {
+                panic!("entry must be indexed by region after flushed");
+            };
+            regions.insert(*view.id());
+            let buf = store
+                .inner
+                .region_manager
+                .region(view.id())
+                .load(view)
+                .await
+                .unwrap()
+                .unwrap();
+            let header = EntryHeader::read(buf.as_ref()).unwrap();
+            assert_eq!(header.compression, compression.unwrap_or(Compression::Lz4));
+            assert_eq!(store.get(&key).await.unwrap().unwrap().value(), &vec![i as u8; 16 * KB]);
+        }
+        // All entries are mixed in the same region.
+        assert_eq!(regions.len(), 1);
+
+        store.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_insert_batch() {
+        const KB: usize = 1024;
+        const MB: usize = 1024 * 1024;
+
+        let tempdir = tempfile::tempdir().unwrap();
+
+        let config = || TestStoreConfig {
+            name: "".to_string(),
+            eviction_config: FifoConfig {}.into(),
+            device_config: FsDeviceConfig {
+                dir: PathBuf::from(tempdir.path()),
+                capacity: 16 * MB,
+                file_size: MB,
+                align: 4 * KB,
+                io_size: 16 * KB,
+                io_executor: IoExecutor::default(),
+                on_region_open_error: RegionOpenErrorPolicy::default(),
+                write_io_size: None,
+                read_io_size: None,
+                extra_dirs: vec![],
+                direct_io: true,
+            },
+            catalog_shards: 1,
+            admissions: vec![],
+            reinsertions: vec![],
+            flushers: 2,
+            reclaimers: 0,
+            recover_concurrency: 2,
+            clean_region_threshold: 1,
+            compression: Compression::None,
+            checksum: true,
+            recent_inserts_capacity: 0,
+            max_key_size: None,
+            region_compression: None,
+            intra_block_align: None,
+            catalog_snapshot_path: None,
+            reclaim_grace_period: Duration::ZERO,
+            compression_observer: None,
+            unknown_region_version_policy: UnknownRegionVersionPolicy::default(),
+            block_compression: None,
+            background_cpu_cores: None,
+            metrics_recorder: None,
+            write_conflict_policy: WriteConflictPolicy::default(),
+            catalog_ops_limit: None,
+            first_entry_offset: None,
+            verify_region_magic_on_read: false,
+            self_test_on_open: false,
+            scrub_throughput_limit: None,
+            write_coalescing_interval: None,
+            max_writing_regions: None,
+            size_padding: PaddingPolicy::default(),
+            store_key_hash_only: false,
+            zstd_level: 0,
+            max_concurrent_decompressions: None,
+            checksum_algorithm: ChecksumAlgorithm::default(),
+            key_prefix: None,
+            flush_error_policy: FlushErrorPolicy::default(),
+            admission_observer: None,
+            max_region_age: None,
+            channel_drop_policy: ChannelDropPolicy::default(),
+            prefix_compressions: HashMap::new(),
+            io_bytes_in_flight_limit: None,
+            flusher_queue_capacity: None,
+            queue_full_policy: QueueFullPolicy::default(),
+            flush_alignment: None,
+            adaptive_compression: false,
+            scan_read_ahead: 2,
+            verify_checksum_on_recover: false,
+            catalog_backend: None,
+            catalog_retry_policy: CatalogRetryPolicy::default(),
+        };
+
+        let store = TestStore::open(config()).await.unwrap();
+
+        // The batch spans more than one region, and the later entry of key 0 wins.
+        let entries = (0..100u64)
+            .map(|i| (Arc::new(i), Arc::new(vec![i as u8; 16 * KB])))
+            .chain([(Arc::new(0), Arc::new(vec![100; 16 * KB]))])
+            .collect_vec();
+        let res = store.insert_batch(entries).await.unwrap();
+        assert_eq!(res.len(), 101);
+        assert!(res.iter().all(|entry| entry.is_some()));
+        assert_eq!(store.get(&0).await.unwrap().unwrap().as_slice(), &[100; 16 * KB]);
+
+        store.close().await.unwrap();
+        drop(store);
+
+        let store = TestStore::open(config()).await.unwrap();
+        assert_eq!(store.get(&0).await.unwrap().unwrap().as_slice(), &[100; 16 * KB]);
+        for i in 1..100u64 {
+            assert_eq!(
+                store.get(&i).await.unwrap().unwrap().as_slice(),
+                &vec![i as u8; 16 * KB][..]
+            );
+        }
+        assert_eq!(store.inner.flusher_queue.len(), 0);
+
+        store.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_write_coalescing() {
+        const KB: usize = 1024;
+        const MB: usize = 1024 * 1024;
+
+        let tempdir = tempfile::tempdir().unwrap();
+
+        let config = TestStoreConfig {
+            name: "".to_string(),
+            eviction_config: FifoConfig {}.into(),
+            device_config: FsDeviceConfig {
+                dir: PathBuf::from(tempdir.path()),
+                capacity: 16 * MB,
+                file_size: 4 * MB,
+                align: 4 * KB,
+                io_size: 16 * KB,
+                io_executor: IoExecutor::default(),
+                on_region_open_error: RegionOpenErrorPolicy::default(),
+                write_io_size: None,
+                read_io_size: None,
+                extra_dirs: vec![],
+                direct_io: true,
+            },
+            catalog_shards: 1,
+            admissions: vec![],
+            reinsertions: vec![],
+            flushers: 2,
+            reclaimers: 0,
+            recover_concurrency: 2,
+            clean_region_threshold: 1,
+            compression: Compression::None,
+            checksum: true,
+            recent_inserts_capacity: 0,
+            max_key_size: None,
+            region_compression: None,
+            intra_block_align: None,
+            catalog_snapshot_path: None,
+            reclaim_grace_period: Duration::ZERO,
+            compression_observer: None,
+            unknown_region_version_policy: UnknownRegionVersionPolicy::default(),
+            block_compression: None,
+            background_cpu_cores: None,
+            metrics_recorder: None,
+            write_conflict_policy: WriteConflictPolicy::default(),
+            catalog_ops_limit: None,
+            first_entry_offset: None,
+            verify_region_magic_on_read: false,
+            self_test_on_open: false,
+            scrub_throughput_limit: None,
+            write_coalescing_interval: Some(Duration::from_secs(3600)),
+            max_writing_regions: None,
+            size_padding: PaddingPolicy::default(),
+            store_key_hash_only: false,
+            zstd_level: 0,
+            max_concurrent_decompressions: None,
+            checksum_algorithm: ChecksumAlgorithm::default(),
+            key_prefix: None,
+            flush_error_policy: FlushErrorPolicy::default(),
+            admission_observer: None,
+            max_region_age: None,
+            channel_drop_policy: ChannelDropPolicy::default(),
+            prefix_compressions: HashMap::new(),
+            io_bytes_in_flight_limit: None,
+            flusher_queue_capacity: None,
+            queue_full_policy: QueueFullPolicy::default(),
+            flush_alignment: None,
+            adaptive_compression: false,
+            scan_read_ahead: 2,
+            verify_checksum_on_recover: false,
+            catalog_backend: None,
+            catalog_retry_policy: CatalogRetryPolicy::default(),
+        };
+
+        let store = TestStore::open(config).await.unwrap();
+
+        // All updates are issued within the interval.
+        for i in 0..100 {
+            store.insert(0, vec![i as u8; 16 * KB]).await.unwrap();
+        }
+        // The pending entry is served before written.
+        assert_eq!(store.get(&0).await.unwrap().unwrap().as_slice(), &[99; 16 * KB]);
+
+        store.barrier().await.unwrap();
+
+        let mut entries = vec![];
+        for region_id in 0..store.device().regions() as RegionId {
+            let region = store.inner.region_manager.region(&region_id).clone();
+            let Some(mut iter) = RegionEntryIter::<u64, Vec<u8>, FsDevice>::open(region, true)
+                .await
+                .unwrap()
+            else {
+                continue;
+            };
+            while let Some((key, value, _)) = iter.next_kv().await.unwrap() {
+                entries.push((key, value));
+            }
+        }
+        assert_eq!(entries, vec![(0, vec![99; 16 * KB])]);
+
+        store.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_flush_queue() {
+        const KB: usize = 1024;
+        const MB: usize = 1024 * 1024;
+        const ENTRIES: usize = 64;
+
+        let tempdir = tempfile::tempdir().unwrap();
+        let recorder = Arc::new(RecordingMetricsRecorder::default());
+
+        let config = TestStoreConfig {
+            name: "test".to_string(),
+            eviction_config: FifoConfig {}.into(),
+            device_config: FsDeviceConfig {
+                dir: PathBuf::from(tempdir.path()),
+                capacity: 16 * MB,
+                file_size: 4 * MB,
+                align: 4 * KB,
+                io_size: 16 * KB,
+                io_executor: IoExecutor::default(),
+                on_region_open_error: RegionOpenErrorPolicy::default(),
+                write_io_size: None,
+                read_io_size: None,
+                extra_dirs: vec![],
+                direct_io: true,
+            },
+            catalog_shards: 1,
+            admissions: vec![],
+            reinsertions: vec![],
+            flushers: 1,
+            reclaimers: 0,
+            recover_concurrency: 2,
+            clean_region_threshold: 1,
+            compression: Compression::None,
+            checksum: true,
+            recent_inserts_capacity: 0,
+            max_key_size: None,
+            region_compression: None,
+            intra_block_align: None,
+            catalog_snapshot_path: None,
+            reclaim_grace_period: Duration::ZERO,
+            compression_observer: None,
+            unknown_region_version_policy: UnknownRegionVersionPolicy::default(),
+            block_compression: None,
+            background_cpu_cores: None,
+            metrics_recorder: Some(recorder.clone()),
+            write_conflict_policy: WriteConflictPolicy::default(),
+            catalog_ops_limit: None,
+            first_entry_offset: None,
+            verify_region_magic_on_read: false,
+            self_test_on_open: false,
+            scrub_throughput_limit: None,
+            write_coalescing_interval: None,
+            max_writing_regions: None,
+            size_padding: PaddingPolicy::default(),
+            store_key_hash_only: false,
+            zstd_level: 0,
+            max_concurrent_decompressions: None,
+            checksum_algorithm: ChecksumAlgorithm::default(),
+            key_prefix: None,
+            flush_error_policy: FlushErrorPolicy::default(),
+            admission_observer: None,
+            max_region_age: None,
+            channel_drop_policy: ChannelDropPolicy::default(),
+            prefix_compressions: HashMap::new(),
+            io_bytes_in_flight_limit: None,
+            flusher_queue_capacity: None,
+            queue_full_policy: QueueFullPolicy::default(),
+            flush_alignment: None,
+            adaptive_compression: false,
+            scan_read_ahead: 2,
+            verify_checksum_on_recover: false,
+            catalog_backend: None,
+            catalog_retry_policy: CatalogRetryPolicy::default(),
+        };
+
+        let store = TestStore::open(config).await.unwrap();
+        assert_eq!(store.channel_capacity(), None);
+        assert_eq!(store.channel_len(), 0);
+
+        // Hold the flusher with a barrier that is not polled yet, so the following entries stay in the channel.
+        let barrier = store.barrier();
+        for i in 0..ENTRIES {
+            store.insert(i as u64, vec![i as u8; 4 * KB]).await.unwrap();
+            assert_eq!(store.channel_len(), i + 1);
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+
+        barrier.await.unwrap();
+        store.barrier().await.unwrap();
+        assert_eq!(store.channel_len(), 0);
+
+        let labels = ["test", "flush_queue_lag", ""];
+        assert_eq!(recorder.count("histogram", INNER_OP_DURATION, &labels), ENTRIES);
+        assert!(recorder.sum("histogram", INNER_OP_DURATION, &labels) >= ENTRIES as f64 * 0.01);
+
+        store.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_flusher_queue_capacity() {
+        const KB: usize = 1024;
+        const MB: usize = 1024 * 1024;
+        const CAPACITY: usize = 4;
+
+        for policy in [QueueFullPolicy::Block, QueueFullPolicy::Drop, QueueFullPolicy::Reject] {
+            let tempdir = tempfile::tempdir().unwrap();
+
+            let config = TestStoreConfig {
+                name: "".to_string(),
+                eviction_config: FifoConfig {}.into(),
+                device_config: FsDeviceConfig {
+                    dir: PathBuf::from(tempdir.path()),
+                    capacity: 16 * MB,
+                    file_size: 4 * MB,
+                    align: 4 * KB,
+                    io_size: 16 * KB,
+                    io_executor: IoExecutor::default(),
+                    on_region_open_error: RegionOpenErrorPolicy::default(),
+                    write_io_size: None,
+                    read_io_size: None,
+                    extra_dirs: vec![],
+                    direct_io: true,
+                },
+                catalog_shards: 1,
+                admissions: vec![],
+                reinsertions: vec![],
+                flushers: 1,
+                reclaimers: 0,
+                recover_concurrency: 2,
+                clean_region_threshold: 1,
+                compression: Compression::None,
+                checksum: true,
+                recent_inserts_capacity: 0,
+                max_key_size: None,
+                region_compression: None,
+                intra_block_align: None,
+                catalog_snapshot_path: None,
+                reclaim_grace_period: Duration::ZERO,
+                compression_observer: None,
+                unknown_region_version_policy: UnknownRegionVersionPolicy::default(),
+                block_compression: None,
+                background_cpu_cores: None,
+                metrics_recorder: None,
+                write_conflict_policy: WriteConflictPolicy::default(),
+                catalog_ops_limit: None,
+                first_entry_offset: None,
+                verify_region_magic_on_read: false,
+                self_test_on_open: false,
+                scrub_throughput_limit: None,
+                write_coalescing_interval: None,
+                max_writing_regions: None,
+                size_padding: PaddingPolicy::default(),
+                store_key_hash_only: false,
+                zstd_level: 0,
+                max_concurrent_decompressions: None,
+                checksum_algorithm: ChecksumAlgorithm::default(),
+                key_prefix: None,
+                flush_error_policy: FlushErrorPolicy::default(),
+                admission_observer: None,
+                max_region_age: None,
+                channel_drop_policy: ChannelDropPolicy::default(),
+                prefix_compressions: HashMap::new(),
+                io_bytes_in_flight_limit: None,
+                flusher_queue_capacity: Some(CAPACITY),
+                queue_full_policy: policy,
+                flush_alignment: None,
+                adaptive_compression: false,
+                scan_read_ahead: 2,
+                verify_checksum_on_recover: false,
+                catalog_backend: None,
+                catalog_retry_policy: CatalogRetryPolicy::default(),
+            };
+
+            let store = TestStore::open(config).await.unwrap();
+            assert_eq!(store.channel_capacity(), Some(CAPACITY));
+
+            // Hold the flusher with a barrier that is not polled yet, so the following entries stay in the queue.
+            let barrier = store.barrier();
+            for i in 0..CAPACITY {
+                store.insert(i as u64, vec![i as u8; 4 * KB]).await.unwrap();
+            }
+            assert_eq!(store.channel_len(), CAPACITY);
+            assert_eq!(store.inner.metrics.flusher_queue_depth.get(), CAPACITY as u64);
+
+            let key = CAPACITY as u64;
+            let value = vec![CAPACITY as u8; 4 * KB];
+            match policy {
+                QueueFullPolicy::Block => {
+                    let handle = tokio::spawn({
+                        let store = store.clone();
+                        async move { store.insert(key, value).await }
+                    });
+                    tokio::time::sleep(Duration::from_millis(10)).await;
+                    assert!(!handle.is_finished());
+
+                    barrier.await.unwrap();
+                    assert!(handle.await.unwrap().unwrap().is_some());
+                }
+                QueueFullPolicy::Drop => {
+                    assert!(store.insert(key, value).await.unwrap().is_none());
+                    barrier.await.unwrap();
+                }
+                QueueFullPolicy::Reject => {
+                    assert!(matches!(
+                        store.insert(key, value).await,
+                        Err(Error::QueueFull { capacity: CAPACITY })
+                    ));
+                    barrier.await.unwrap();
+                }
+            }
+
+            store.barrier().await.unwrap();
+            assert_eq!(store.channel_len(), 0);
+            assert_eq!(store.inner.metrics.flusher_queue_depth.get(), 0);
+            assert_eq!(
+                store.get(&key).await.unwrap().is_some(),
+                policy == QueueFullPolicy::Block
+            );
+            for i in 0..CAPACITY as u64 {
+                assert!(store.get(&i).await.unwrap().is_some());
+            }
+
+            store.close().await.unwrap();
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_max_writing_regions() {
+        const KB: usize = 1024;
+        const MB: usize = 1024 * 1024;
+        const ENTRIES: usize = 128;
+
+        let tempdir = tempfile::tempdir().unwrap();
+
+        let config = TestStoreConfig {
+            name: "".to_string(),
+            eviction_config: FifoConfig {}.into(),
+            device_config: FsDeviceConfig {
+                dir: PathBuf::from(tempdir.path()),
+                capacity: 16 * MB,
+                file_size: MB,
+                align: 4 * KB,
+                io_size: 16 * KB,
+                io_executor: IoExecutor::default(),
+                on_region_open_error: RegionOpenErrorPolicy::default(),
+                write_io_size: None,
+                read_io_size: None,
+                extra_dirs: vec![],
+                direct_io: true,
+            },
+            catalog_shards: 1,
+            admissions: vec![],
+            reinsertions: vec![],
+            flushers: 4,
+            reclaimers: 0,
+            recover_concurrency: 2,
+            clean_region_threshold: 1,
+            compression: Compression::None,
+            checksum: true,
+            recent_inserts_capacity: 0,
+            max_key_size: None,
+            region_compression: None,
+            intra_block_align: None,
+            catalog_snapshot_path: None,
+            reclaim_grace_period: Duration::ZERO,
+            compression_observer: None,
+            unknown_region_version_policy: UnknownRegionVersionPolicy::default(),
+            block_compression: None,
+            background_cpu_cores: None,
+            metrics_recorder: None,
+            write_conflict_policy: WriteConflictPolicy::default(),
+            catalog_ops_limit: None,
+            first_entry_offset: None,
+            verify_region_magic_on_read: false,
+            self_test_on_open: false,
+            scrub_throughput_limit: None,
+            write_coalescing_interval: None,
+            max_writing_regions: Some(1),
+            size_padding: PaddingPolicy::default(),
+            store_key_hash_only: false,
+            zstd_level: 0,
+            max_concurrent_decompressions: None,
+            checksum_algorithm: ChecksumAlgorithm::default(),
+            key_prefix: None,
+            flush_error_policy: FlushErrorPolicy::default(),
+            admission_observer: None,
+            max_region_age: None,
+            channel_drop_policy: ChannelDropPolicy::default(),
+            prefix_compressions: HashMap::new(),
+            io_bytes_in_flight_limit: None,
+            flusher_queue_capacity: None,
+            queue_full_policy: QueueFullPolicy::default(),
+            flush_alignment: None,
+            adaptive_compression: false,
+            scan_read_ahead: 2,
+            verify_checksum_on_recover: false,
+            catalog_backend: None,
+            catalog_retry_policy: CatalogRetryPolicy::default(),
+        };
+
+        let store = TestStore::open(config).await.unwrap();
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let sampler = tokio::spawn({
+            let store = store.clone();
+            let stop = stop.clone();
+            async move {
+                let mut max = 0;
+                while !stop.load(Ordering::Relaxed) {
+                    max = max.max(store.inner.region_manager.writing_regions());
+                    tokio::task::yield_now().await;
+                }
+                max
+            }
+        });
+
+        // The entries are distributed to all flushers.
+        for i in 0..ENTRIES {
+            store.insert(i as u64, vec![i as u8; 16 * KB]).await.unwrap();
+        }
+        store.barrier().await.unwrap();
+
+        stop.store(true, Ordering::Relaxed);
+        assert!(sampler.await.unwrap() <= 1);
+
+        for i in 0..ENTRIES {
+            assert_eq!(
+                store.get(&(i as u64)).await.unwrap().unwrap().as_slice(),
+                &[i as u8; 16 * KB]
+            );
+        }
+
+        store.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_store_key_hash_only() {
+        const KB: usize = 1024;
+        const MB: usize = 1024 * 1024;
+
+        let tempdir = tempfile::tempdir().unwrap();
+        let snapshot = tempdir.path().join("catalog.snapshot");
+
+        let config = GenericStoreConfig::<String, Vec<u8>, FsDevice> {
+            name: "".to_string(),
+            eviction_config: FifoConfig {}.into(),
+            device_config: FsDeviceConfig {
+                dir: PathBuf::from(tempdir.path()),
+                capacity: 16 * MB,
+                file_size: 4 * MB,
+                align: 4 * KB,
+                io_size: 16 * KB,
+                io_executor: IoExecutor::default(),
+                on_region_open_error: RegionOpenErrorPolicy::default(),
+                write_io_size: None,
+                read_io_size: None,
+                extra_dirs: vec![],
+                direct_io: true,
+            },
+            catalog_shards: 1,
+            admissions: vec![],
+            reinsertions: vec![],
+            flushers: 1,
+            reclaimers: 1,
+            recover_concurrency: 2,
+            clean_region_threshold: 1,
+            compression: Compression::None,
+            checksum: true,
+            recent_inserts_capacity: 0,
+            max_key_size: None,
+            region_compression: None,
+            intra_block_align: None,
+            catalog_snapshot_path: Some(snapshot.clone()),
+            reclaim_grace_period: Duration::ZERO,
+            compression_observer: None,
+            unknown_region_version_policy: UnknownRegionVersionPolicy::default(),
+            block_compression: None,
+            background_cpu_cores: None,
+            metrics_recorder: None,
+            write_conflict_policy: WriteConflictPolicy::default(),
+            catalog_ops_limit: None,
+            first_entry_offset: None,
+            verify_region_magic_on_read: false,
+            self_test_on_open: false,
+            scrub_throughput_limit: None,
+            write_coalescing_interval: None,
+            max_writing_regions: None,
+            size_padding: PaddingPolicy::default(),
+            store_key_hash_only: true,
+            zstd_level: 0,
+            max_concurrent_decompressions: None,
+            checksum_algorithm: ChecksumAlgorithm::default(),
+            key_prefix: None,
+            flush_error_policy: FlushErrorPolicy::default(),
+            admission_observer: None,
+            max_region_age: None,
+            channel_drop_policy: ChannelDropPolicy::default(),
+            prefix_compressions: HashMap::new(),
+            io_bytes_in_flight_limit: None,
+            flusher_queue_capacity: None,
+            queue_full_policy: QueueFullPolicy::default(),
+            flush_alignment: None,
+            adaptive_compression: false,
+            scan_read_ahead: 2,
+            verify_checksum_on_recover: false,
+            catalog_backend: None,
+            catalog_retry_policy: CatalogRetryPolicy::default(),
+        };
+
+        let key = |i: usize| format!("{i:04}-").repeat(200);
+
+        let store = GenericStore::open(config.clone()).await.unwrap();
+        for i in 0..8 {
+            store.insert(key(i), vec![i as u8; KB]).await.unwrap();
+        }
+        store.barrier().await.unwrap();
+
+        for i in 0..8 {
+            let Index::Region { view } = store.catalog().get(key(i).as_str()).unwrap().index().clone() else {
+                panic!("entry must be indexed by region");
+            };
+            let buf = store
+                .inner
+                .region_manager
+                .region(view.id())
+                .load(view)
+                .await
+                .unwrap()
+                .unwrap();
+            let header = EntryHeader::read(buf.as_ref()).unwrap();
+            assert!(header.key_hash);
+            assert_eq!(header.key_len as usize, KEY_HASH_LEN);
+            assert!(!buf.as_ref().windows(key(i).len()).any(|w| w == key(i).as_bytes()));
+
+            let entry = store.get(key(i).as_str()).await.unwrap().unwrap();
+            assert_eq!(entry.key(), &key(i));
+            assert_eq!(entry.value(), &vec![i as u8; KB]);
+        }
+        store.close().await.unwrap();
+        drop(store);
+
+        // The full keys can't be rebuilt by scanning the regions.
+        let store = GenericStore::open(config.clone()).await.unwrap();
+        for i in 0..8 {
+            assert!(store.get(key(i).as_str()).await.unwrap().is_none());
+        }
+        store.close().await.unwrap();
+        drop(store);
+
+        // The full keys are recovered from the catalog snapshot.
+        let store = GenericStore::open(config.clone()).await.unwrap();
+        for i in 0..8 {
+            store.insert(key(i), vec![i as u8; KB]).await.unwrap();
+        }
+        store.barrier().await.unwrap();
+        store.snapshot_catalog(&snapshot).await.unwrap();
+        store.close().await.unwrap();
+        drop(store);
+
+        let store = GenericStore::open(config).await.unwrap();
+        for i in 0..8 {
+            assert_eq!(
+                store.get(key(i).as_str()).await.unwrap().unwrap().value(),
+                &vec![i as u8; KB]
+            );
+        }
+        store.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_write_and_read_io_size() {
+        const KB: usize = 1024;
+
+        let device = MemDevice::new(MemDeviceConfig {
+            capacity: 4 * 64 * KB,
+            region_size: 64 * KB,
+            align: 4 * KB,
+            io_size: 4 * KB,
+            write_io_size: Some(16 * KB),
+            read_io_size: Some(32 * KB),
+        });
+        assert_eq!(device.write_io_size(), 16 * KB);
+        assert_eq!(device.read_io_size(), 32 * KB);
+
+        let mut buffer = FlushBuffer::new(
+            device.clone(),
+            true,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            PaddingPolicy::None,
+            false,
+            0,
+            ChecksumAlgorithm::default(),
+            FlushErrorPolicy::default(),
+            IoBytesLimiter::default(),
+            None,
+            false,
+        );
+        buffer.rotate(0, 0).await.unwrap();
+
+        // Each entry takes an aligned 4 KiB block after the region header, the buffer is flushed only after it reaches
+        // `write_io_size`.
+        for i in 0..4u64 {
+            let entry = Entry {
+                key: Arc::new(i),
+                value: Arc::new(vec![i as u8; 3 * KB]),
+                compression: Compression::None,
+                sequence: i,
+                enqueued: Instant::now(),
+                expire_at: None,
+            };
+            let positioneds = buffer.write(entry).await.unwrap().unwrap_left();
+            if i == 2 {
+                assert_eq!(positioneds.len(), 3);
+            } else {
+                assert!(positioneds.is_empty());
+            }
+        }
+        assert_eq!(buffer.flush().await.unwrap().len(), 1);
+
+        // The region scan loads a whole `read_io_size` window ahead and serves the following entries from it.
+        let mut iter =
+            RegionEntryIter::<u64, Vec<u8>, _>::open(Region::new(0, device.clone(), IoBytesLimiter::default()), true)
+                .await
+                .unwrap()
+                .unwrap();
+        for i in 0..4u64 {
+            let (key, _) = iter.next().await.unwrap().unwrap();
+            assert_eq!(key, i);
+            let (start, window) = iter.window.as_ref().unwrap();
+            assert_eq!(*start, 4 * KB);
+            assert_eq!(window.len(), 32 * KB);
+        }
+        assert!(iter.next().await.unwrap().is_none());
+    }
+
+    /// Blocking executor that slows down each io and records the peak count of concurrent ios.
+    #[derive(Debug, Default)]
+    struct SlowExecutor {
+        running: Arc<AtomicUsize>,
+        peak: Arc<AtomicUsize>,
+    }
+
+    impl BlockingExecutor for SlowExecutor {
+        fn name(&self) -> &str {
+            "slow"
+        }
+
+        fn execute(&self, job: BlockingJob) {
+            let running = self.running.clone();
+            let peak = self.peak.clone();
+            tokio::task::spawn_blocking(move || {
+                let current = running.fetch_add(1, Ordering::SeqCst) + 1;
+                peak.fetch_max(current, Ordering::SeqCst);
+                std::thread::sleep(Duration::from_millis(1));
+                // Leave before the job wakes up the waiter, which may issue the next io at once.
+                running.fetch_sub(1, Ordering::SeqCst);
+                job();
+            });
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_recover_concurrency() {
+        const KB: usize = 1024;
+        const MB: usize = 1024 * 1024;
+
+        let tempdir = tempfile::tempdir().unwrap();
+
+        let config = |recover_concurrency: usize, io_executor: IoExecutor| TestStoreConfig {
+            name: "".to_string(),
+            eviction_config: FifoConfig {}.into(),
+            device_config: FsDeviceConfig {
+                dir: PathBuf::from(tempdir.path()),
+                capacity: 2 * MB,
+                file_size: 64 * KB,
+                align: 4 * KB,
+                io_size: 4 * KB,
+                io_executor,
+                on_region_open_error: RegionOpenErrorPolicy::default(),
+                write_io_size: None,
+                read_io_size: None,
+                extra_dirs: vec![],
+                direct_io: true,
+            },
+            catalog_shards: 1,
+            admissions: vec![],
+            reinsertions: vec![],
+            flushers: 1,
+            reclaimers: 1,
+            recover_concurrency,
+            clean_region_threshold: 1,
+            compression: Compression::None,
+            checksum: true,
+            recent_inserts_capacity: 0,
+            max_key_size: None,
+            region_compression: None,
+            intra_block_align: None,
+            catalog_snapshot_path: None,
+            reclaim_grace_period: Duration::ZERO,
+            compression_observer: None,
+            unknown_region_version_policy: UnknownRegionVersionPolicy::default(),
+            block_compression: None,
+            background_cpu_cores: None,
+            metrics_recorder: None,
+            // The recovered catalog must not depend on the order the regions are scanned even without sequence-wins
+            // for the writes.
+            write_conflict_policy: WriteConflictPolicy::LastAppliedWins,
+            catalog_ops_limit: None,
+            first_entry_offset: None,
+            verify_region_magic_on_read: false,
+            self_test_on_open: false,
+            scrub_throughput_limit: None,
+            write_coalescing_interval: None,
+            max_writing_regions: None,
+            size_padding: PaddingPolicy::default(),
+            store_key_hash_only: false,
+            zstd_level: 0,
+            max_concurrent_decompressions: None,
+            checksum_algorithm: ChecksumAlgorithm::default(),
+            key_prefix: None,
+            flush_error_policy: FlushErrorPolicy::default(),
+            admission_observer: None,
+            max_region_age: None,
+            channel_drop_policy: ChannelDropPolicy::default(),
+            prefix_compressions: HashMap::new(),
+            io_bytes_in_flight_limit: None,
+            flusher_queue_capacity: None,
+            queue_full_policy: QueueFullPolicy::default(),
+            flush_alignment: None,
+            adaptive_compression: false,
+            scan_read_ahead: 2,
+            verify_checksum_on_recover: false,
+            catalog_backend: None,
+            catalog_retry_policy: CatalogRetryPolicy::default(),
+        };
+
+        // Overwrite the keys in rounds, so each key is found in several regions.
+        let store = TestStore::open(config(1, IoExecutor::default())).await.unwrap();
+        for round in 0..3u8 {
+            for i in 0..64u64 {
+                store.insert(i, vec![round; 3 * KB]).await.unwrap();
+            }
+            store.barrier().await.unwrap();
+        }
+        store.close().await.unwrap();
+        drop(store);
+
+        let recover = |recover_concurrency: usize| {
+            let config = &config;
+            async move {
+                let executor = Arc::new(SlowExecutor::default());
+                let store = TestStore::open(config(recover_concurrency, IoExecutor::custom(executor.clone())))
+                    .await
+                    .unwrap();
+                let peak = executor.peak.load(Ordering::SeqCst);
+                let mut entries = store.catalog().snapshot();
+                entries.sort();
+                for i in 0..64u64 {
+                    assert_eq!(store.get(&i).await.unwrap().unwrap().value(), &vec![2; 3 * KB]);
+                }
+                store.close().await.unwrap();
+                (entries, peak)
+            }
+        };
+
+        let (serial, serial_peak) = recover(1).await;
+        let (parallel, parallel_peak) = recover(8).await;
+
+        assert_eq!(serial.len(), 64);
+        assert_eq!(serial, parallel);
+        assert_eq!(serial_peak, 1);
+        assert!(parallel_peak > 1);
+    }
+
+    fn craft_entry(key: u64, value: Vec<u8>) -> Entry<u64, Vec<u8>> {
+        Entry {
+            key: Arc::new(key),
+            value: Arc::new(value),
+            compression: Compression::None,
+            sequence: key,
+            enqueued: Instant::now(),
+            expire_at: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_recover_torn_trailing_entry() {
+        const KB: usize = 1024;
+        const MB: usize = 1024 * 1024;
+
+        let tempdir = tempfile::tempdir().unwrap();
+        let device_config = FsDeviceConfig {
+            dir: PathBuf::from(tempdir.path()),
+            capacity: 16 * MB,
+            file_size: 4 * MB,
+            align: 4 * KB,
+            io_size: 16 * KB,
+            io_executor: IoExecutor::default(),
+            on_region_open_error: RegionOpenErrorPolicy::default(),
+            write_io_size: None,
+            read_io_size: None,
+            extra_dirs: vec![],
+            direct_io: true,
+        };
+
+        // Craft region 0 with 4 valid entries, followed by an entry torn by a crash.
+        let device = FsDevice::open(device_config.clone()).await.unwrap();
+        let mut buffer = FlushBuffer::new(
+            device.clone(),
+            true,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            PaddingPolicy::None,
+            false,
+            0,
+            ChecksumAlgorithm::default(),
+            FlushErrorPolicy::default(),
+            IoBytesLimiter::default(),
+            None,
+            false,
+        );
+        buffer.rotate(0, 0).await.unwrap();
+
+        for i in 1..=4 {
+            buffer
+                .write(craft_entry(i, vec![i as u8; 3 * KB]))
+                .await
+                .unwrap()
+                .unwrap_left();
+        }
+        buffer.flush().await.unwrap();
+        let valid_end = buffer.offset();
+
+        buffer
+            .write(craft_entry(5, vec![5; 16 * KB]))
+            .await
+            .unwrap()
+            .unwrap_left();
+        buffer.flush().await.unwrap();
+        let torn_end = buffer.offset();
+
+        // Only the first block of the trailing entry reaches the disk, the rest (including its key) is lost.
+        let torn_start = valid_end + device.align();
+        test_utils::inject(&device, 0, torn_start, &vec![0; torn_end - torn_start])
+            .await
+            .unwrap();
+        // The header of the torn entry is intact, while the rest is zeroed.
+        let header = test_utils::inspect(&device, 0, valid_end..valid_end + EntryHeader::serialized_len())
+            .await
+            .unwrap();
+        assert_eq!(EntryHeader::read(&header).unwrap().sequence, 5);
+        let torn = test_utils::inspect(&device, 0, torn_start..torn_end).await.unwrap();
+        assert!(torn.iter().all(|b| *b == 0));
+        drop(buffer);
+        drop(device);
+
+        let store = TestStore::open(TestStoreConfig {
+            name: "".to_string(),
+            eviction_config: FifoConfig {}.into(),
+            device_config: device_config.clone(),
+            catalog_shards: 1,
+            admissions: vec![],
+            reinsertions: vec![],
+            flushers: 1,
+            reclaimers: 0,
+            recover_concurrency: 2,
+            clean_region_threshold: 1,
+            compression: Compression::None,
+            checksum: true,
+            recent_inserts_capacity: 0,
+            max_key_size: None,
+            region_compression: None,
+            intra_block_align: None,
+            catalog_snapshot_path: None,
+            reclaim_grace_period: Duration::ZERO,
+            compression_observer: None,
+            unknown_region_version_policy: UnknownRegionVersionPolicy::default(),
+            block_compression: None,
+            background_cpu_cores: None,
+            metrics_recorder: None,
+            write_conflict_policy: WriteConflictPolicy::default(),
+            catalog_ops_limit: None,
+            first_entry_offset: None,
+            verify_region_magic_on_read: false,
+            self_test_on_open: false,
+            scrub_throughput_limit: None,
+            write_coalescing_interval: None,
+            max_writing_regions: None,
+            size_padding: PaddingPolicy::default(),
+            store_key_hash_only: false,
+            zstd_level: 0,
+            max_concurrent_decompressions: None,
+            checksum_algorithm: ChecksumAlgorithm::default(),
+            key_prefix: None,
+            flush_error_policy: FlushErrorPolicy::default(),
+            admission_observer: None,
+            max_region_age: None,
+            channel_drop_policy: ChannelDropPolicy::default(),
+            prefix_compressions: HashMap::new(),
+            io_bytes_in_flight_limit: None,
+            flusher_queue_capacity: None,
+            queue_full_policy: QueueFullPolicy::default(),
+            flush_alignment: None,
+            adaptive_compression: false,
+            scan_read_ahead: 2,
+            verify_checksum_on_recover: false,
+            catalog_backend: None,
+            catalog_retry_policy: CatalogRetryPolicy::default(),
+        })
+        .await
+        .unwrap();
+
+        // The valid prefix is recovered, and the torn entry is never served.
+        for i in 1..=4 {
+            assert_eq!(store.get(&i).await.unwrap().unwrap().value(), &vec![i as u8; 3 * KB]);
+        }
+        assert!(store.get(&5).await.unwrap().is_none());
+        assert!(!matches!(store.get(&0).await, Ok(Some(_))));
 
-    pub fn set_judge_mask(&mut self, mask: Bitmap<64>) {
-        self.judges.set_mask(mask);
+        store.close().await.unwrap();
     }
 
-    pub fn set_skippable(&mut self) {
-        self.is_skippable = true
-    }
+    #[tokio::test]
+    async fn test_recover_partially_filled_region() {
+        const KB: usize = 1024;
+        const MB: usize = 1024 * 1024;
 
-    pub fn set_sequence(&mut self, sequence: Sequence) {
-        self.sequence = Some(sequence);
-    }
+        let tempdir = tempfile::tempdir().unwrap();
+        let device_config = FsDeviceConfig {
+            dir: PathBuf::from(tempdir.path()),
+            capacity: 16 * MB,
+            file_size: 4 * MB,
+            align: 4 * KB,
+            io_size: 16 * KB,
+            io_executor: IoExecutor::default(),
+            on_region_open_error: RegionOpenErrorPolicy::default(),
+            write_io_size: None,
+            read_io_size: None,
+            extra_dirs: vec![],
+            direct_io: true,
+        };
 
-    pub fn compression(&self) -> Compression {
-        self.compression
-    }
+        // Craft region 0 with 2 valid entries, a block never written, and an entry after it.
+        let device = FsDevice::open(device_config.clone()).await.unwrap();
+        let mut buffer = FlushBuffer::new(
+            device.clone(),
+            true,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            PaddingPolicy::None,
+            false,
+            0,
+            ChecksumAlgorithm::default(),
+            FlushErrorPolicy::default(),
+            IoBytesLimiter::default(),
+            None,
+            false,
+        );
+        buffer.rotate(0, 0).await.unwrap();
+
+        for i in 1..=2 {
+            buffer
+                .write(craft_entry(i, vec![i as u8; 3 * KB]))
+                .await
+                .unwrap()
+                .unwrap_left();
+        }
+        buffer.flush().await.unwrap();
+        buffer.set_offset(buffer.offset() + device.align());
+        buffer
+            .write(craft_entry(3, vec![3; 3 * KB]))
+            .await
+            .unwrap()
+            .unwrap_left();
+        buffer.flush().await.unwrap();
+        drop(buffer);
+        drop(device);
+
+        let store = TestStore::open(TestStoreConfig {
+            name: "".to_string(),
+            eviction_config: FifoConfig {}.into(),
+            device_config: device_config.clone(),
+            catalog_shards: 1,
+            admissions: vec![],
+            reinsertions: vec![],
+            flushers: 1,
+            reclaimers: 0,
+            recover_concurrency: 2,
+            clean_region_threshold: 1,
+            compression: Compression::None,
+            checksum: true,
+            recent_inserts_capacity: 0,
+            max_key_size: None,
+            region_compression: None,
+            intra_block_align: None,
+            catalog_snapshot_path: None,
+            reclaim_grace_period: Duration::ZERO,
+            compression_observer: None,
+            unknown_region_version_policy: UnknownRegionVersionPolicy::default(),
+            block_compression: None,
+            background_cpu_cores: None,
+            metrics_recorder: None,
+            write_conflict_policy: WriteConflictPolicy::default(),
+            catalog_ops_limit: None,
+            first_entry_offset: None,
+            verify_region_magic_on_read: false,
+            self_test_on_open: false,
+            scrub_throughput_limit: None,
+            write_coalescing_interval: None,
+            max_writing_regions: None,
+            size_padding: PaddingPolicy::default(),
+            store_key_hash_only: false,
+            zstd_level: 0,
+            max_concurrent_decompressions: None,
+            checksum_algorithm: ChecksumAlgorithm::default(),
+            key_prefix: None,
+            flush_error_policy: FlushErrorPolicy::default(),
+            admission_observer: None,
+            max_region_age: None,
+            channel_drop_policy: ChannelDropPolicy::default(),
+            prefix_compressions: HashMap::new(),
+            io_bytes_in_flight_limit: None,
+            flusher_queue_capacity: None,
+            queue_full_policy: QueueFullPolicy::default(),
+            flush_alignment: None,
+            adaptive_compression: false,
+            scan_read_ahead: 2,
+            verify_checksum_on_recover: false,
+            catalog_backend: None,
+            catalog_retry_policy: CatalogRetryPolicy::default(),
+        })
+        .await
+        .unwrap();
 
-    pub fn set_compression(&mut self, compression: Compression) {
-        self.compression = compression
-    }
-}
+        // Recovery stops at the first block without a valid entry.
+        for i in 1..=2 {
+            assert_eq!(store.get(&i).await.unwrap().unwrap().value(), &vec![i as u8; 3 * KB]);
+        }
+        assert!(store.get(&3).await.unwrap().is_none());
 
-impl<K, V, D> Debug for GenericStoreWriter<K, V, D>
-where
-    K: StorageKey + Debug,
-    V: StorageValue,
-    D: Device,
-{
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.debug_struct("StoreWriter")
-            .field("key", &self.key)
-            .field("judges", &self.judges)
-            .field("is_judged", &self.is_judged)
-            .field("duration", &self.duration)
-            .field("inserted", &self.is_inserted)
-            .finish()
+        store.close().await.unwrap();
     }
-}
 
-impl<K, V, D> Drop for GenericStoreWriter<K, V, D>
-where
-    K: StorageKey,
-    V: StorageValue,
-    D: Device,
-{
-    fn drop(&mut self) {
-        if !self.is_inserted {
-            debug_assert!(self.key.is_some());
+    #[test]
+    fn test_entry_header_expire_at() {
+        let header = |expire_at| EntryHeader {
+            key_len: 8,
+            value_len: 16,
+            sequence: 42,
+            checksum: 0x1234,
+            compression: Compression::Lz4,
+            block: false,
+            key_hash: false,
+            checksum_algorithm: ChecksumAlgorithm::Crc32c,
+            checksummed: true,
+            expire_at,
+        };
 
-            let filtered = self.is_judged && !self.judge();
+        // The headers without the expire at are the same as the ones written before.
+        let mut buf = vec![0; EntryHeader::serialized_len()];
+        header(None).write(&mut buf);
+        assert!(!EntryHeader::has_expire_at(&buf));
+        let decoded = EntryHeader::read(&buf).unwrap();
+        assert_eq!(decoded.expire_at, None);
+        assert_eq!(decoded.encoded_len(), EntryHeader::serialized_len());
+        assert_eq!(decoded.compression, Compression::Lz4);
+        assert_eq!(decoded.checksum_algorithm, ChecksumAlgorithm::Crc32c);
+
+        let expire_at = timestamp(SystemTime::now());
+        let mut buf = vec![0; EntryHeader::serialized_len_with_expire_at()];
+        header(Some(expire_at)).write(&mut buf);
+        assert!(EntryHeader::has_expire_at(&buf));
+        let decoded = EntryHeader::read(&buf).unwrap();
+        assert_eq!(decoded.expire_at, Some(expire_at));
+        assert_eq!(decoded.encoded_len(), EntryHeader::serialized_len_with_expire_at());
+        assert_eq!(decoded.compression, Compression::Lz4);
+        assert_eq!(decoded.sequence, 42);
+
+        // The truncated expire at is rejected instead of read out of bounds.
+        assert!(EntryHeader::read(&buf[..EntryHeader::serialized_len()]).is_err());
+    }
 
-            if filtered {
-                self.store
-                    .inner
-                    .metrics
-                    .op_duration_insert_filtered
-                    .observe(self.duration.as_secs_f64());
-            } else {
-                self.store
-                    .inner
-                    .metrics
-                    .op_duration_insert_dropped
-                    .observe(self.duration.as_secs_f64());
+    #[test]
+    fn test_entry_checksum_flag() {
+        let encoded = |checksummed: bool| {
+            let mut buf = vec![0; EntryHeader::serialized_len()];
+            bincode::serialize_into(&mut buf, &vec![7u8; 16]).unwrap();
+            let value_len = buf.len() - EntryHeader::serialized_len();
+            bincode::serialize_into(&mut buf, &42u64).unwrap();
+            let key_len = buf.len() - EntryHeader::serialized_len() - value_len;
+            let checksum = match checksummed {
+                true => checksum(ChecksumAlgorithm::XxHash64, &buf[EntryHeader::serialized_len()..]),
+                false => 0,
+            };
+            EntryHeader {
+                key_len: key_len as u32,
+                value_len: value_len as u32,
+                sequence: 1,
+                checksum,
+                compression: Compression::None,
+                block: false,
+                key_hash: false,
+                checksum_algorithm: ChecksumAlgorithm::XxHash64,
+                checksummed,
+                expire_at: None,
             }
-        }
-    }
-}
+            .write(&mut buf);
+            buf
+        };
 
-const ENTRY_MAGIC: u32 = 0x97_03_27_00;
-const ENTRY_MAGIC_MASK: u32 = 0xFF_FF_FF_00;
+        let buf = encoded(true);
+        assert!(EntryHeader::read(&buf).unwrap().checksummed);
+        assert!(read_entry::<u64, Vec<u8>>(&buf, true).is_ok());
 
-#[derive(Debug)]
-pub struct EntryHeader {
-    pub key_len: u32,
-    pub value_len: u32,
-    pub sequence: Sequence,
-    pub checksum: u64,
-    pub compression: Compression,
-}
+        // A corrupted entry is reported as a checksum mismatch.
+        let mut corrupted = buf.clone();
+        corrupted[EntryHeader::serialized_len() + 8] ^= 0xFF;
+        let err = read_entry::<u64, Vec<u8>>(&corrupted, true).unwrap_err();
+        assert!(err.to_string().contains("checksum mismatch"), "{err}");
 
-impl EntryHeader {
-    pub const fn serialized_len() -> usize {
-        4 + 4 + 8 + 8 + 4 /* magic & compression */
+        // An entry written with checksum disabled is never verified, even if checksum is enabled on read.
+        let buf = encoded(false);
+        assert!(!EntryHeader::read(&buf).unwrap().checksummed);
+        assert!(read_entry::<u64, Vec<u8>>(&buf, true).is_ok());
     }
 
-    pub fn write(&self, mut buf: &mut [u8]) {
-        buf.put_u32(self.key_len);
-        buf.put_u32(self.value_len);
-        buf.put_u64(self.sequence);
-        buf.put_u64(self.checksum);
-
-        let v = ENTRY_MAGIC | self.compression.to_u8() as u32;
-        buf.put_u32(v);
-    }
+    #[tokio::test]
+    async fn test_ttl() {
+        const KB: usize = 1024;
+        const MB: usize = 1024 * 1024;
 
-    pub fn read(mut buf: &[u8]) -> Result<Self> {
-        let key_len = buf.get_u32();
-        let value_len = buf.get_u32();
-        let sequence = buf.get_u64();
-        let checksum = buf.get_u64();
+        let config = GenericStoreConfig::<u64, Vec<u8>, MemDevice> {
+            name: "".to_string(),
+            eviction_config: FifoConfig {}.into(),
+            device_config: MemDeviceConfig {
+                capacity: 16 * MB,
+                region_size: MB,
+                align: 4 * KB,
+                io_size: 16 * KB,
+                write_io_size: None,
+                read_io_size: None,
+            },
+            catalog_shards: 1,
+            admissions: vec![],
+            reinsertions: vec![],
+            flushers: 1,
+            reclaimers: 0,
+            recover_concurrency: 2,
+            clean_region_threshold: 1,
+            compression: Compression::None,
+            checksum: true,
+            recent_inserts_capacity: 0,
+            max_key_size: None,
+            region_compression: None,
+            intra_block_align: None,
+            catalog_snapshot_path: None,
+            reclaim_grace_period: Duration::ZERO,
+            compression_observer: None,
+            unknown_region_version_policy: UnknownRegionVersionPolicy::default(),
+            block_compression: None,
+            background_cpu_cores: None,
+            metrics_recorder: None,
+            write_conflict_policy: WriteConflictPolicy::default(),
+            catalog_ops_limit: None,
+            first_entry_offset: None,
+            verify_region_magic_on_read: false,
+            self_test_on_open: false,
+            scrub_throughput_limit: None,
+            write_coalescing_interval: None,
+            max_writing_regions: None,
+            size_padding: PaddingPolicy::default(),
+            store_key_hash_only: false,
+            zstd_level: 0,
+            max_concurrent_decompressions: None,
+            checksum_algorithm: ChecksumAlgorithm::default(),
+            key_prefix: None,
+            flush_error_policy: FlushErrorPolicy::default(),
+            admission_observer: None,
+            max_region_age: None,
+            channel_drop_policy: ChannelDropPolicy::default(),
+            prefix_compressions: HashMap::new(),
+            io_bytes_in_flight_limit: None,
+            flusher_queue_capacity: None,
+            queue_full_policy: QueueFullPolicy::default(),
+            flush_alignment: None,
+            adaptive_compression: false,
+            scan_read_ahead: 2,
+            verify_checksum_on_recover: false,
+            catalog_backend: None,
+            catalog_retry_policy: CatalogRetryPolicy::default(),
+        };
 
-        let v = buf.get_u32();
-        let magic = v & ENTRY_MAGIC_MASK;
-        if magic != ENTRY_MAGIC {
-            return Err(anyhow!("magic mismatch, expected: {}, got: {}", ENTRY_MAGIC, magic).into());
+        let store = GenericStore::open(config).await.unwrap();
+
+        let expire_at = SystemTime::now() + Duration::from_secs(3600);
+        store.insert(0, vec![0; 16 * KB]).await.unwrap();
+        let mut writer = store.writer(1);
+        writer.set_expire_at(expire_at);
+        writer.finish(vec![1; 16 * KB]).await.unwrap();
+        // The entry expired before flushed is never written.
+        let mut writer = store.writer(2);
+        writer.set_expire_at(SystemTime::now() - Duration::from_secs(1));
+        writer.finish(vec![2; 16 * KB]).await.unwrap();
+        store.barrier().await.unwrap();
+
+        assert_eq!(store.get(&0).await.unwrap().unwrap().value(), &vec![0; 16 * KB]);
+        assert_eq!(store.get(&1).await.unwrap().unwrap().value(), &vec![1; 16 * KB]);
+        assert!(store.get(&2).await.unwrap().is_none());
+
+        // The expire at is recovered from the entry headers, in milliseconds.
+        let item = store.inner.catalog.get(&1).unwrap();
+        assert_eq!(item.expire_at(), Some(expire_at));
+        let Index::Region { view } = item.consume().1 else {
+            unreachable!()
+        };
+        let region_id = *view.id();
+        drop(view);
+        let region = store.inner.region_manager.region(&region_id).clone();
+        let mut iter = RegionEntryIter::<u64, Vec<u8>, _>::open(region, true)
+            .await
+            .unwrap()
+            .unwrap();
+        let mut recovered = vec![];
+        while let Some((key, item)) = iter.next().await.unwrap() {
+            recovered.push((key, item.expire_at()));
         }
-        let compression = Compression::try_from(v as u8)?;
+        assert_eq!(
+            recovered,
+            vec![(0, None), (1, Some(from_timestamp(timestamp(expire_at))))]
+        );
+        drop(iter);
 
-        Ok(Self {
-            key_len,
-            value_len,
-            sequence,
-            compression,
-            checksum,
-        })
+        // The expired entry is removed lazily on get.
+        store
+            .insert_with_ttl(3, vec![3; 16 * KB], Duration::from_millis(100))
+            .await
+            .unwrap();
+        store.barrier().await.unwrap();
+        assert_eq!(store.get(&3).await.unwrap().unwrap().value(), &vec![3; 16 * KB]);
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert!(store.get(&3).await.unwrap().is_none());
+        assert!(!store.exists(&3).unwrap());
+        assert!(!store.inner.catalog.region_keys(&region_id).iter().any(|key| **key == 3));
+
+        store.close().await.unwrap();
     }
-}
 
-/// | header | value (compressed) | key | <padding> |
-///
-/// # Safety
-///
-/// `buf.len()` must exactly fit entry size
-fn read_entry<K, V>(buf: &[u8]) -> Result<(K, V)>
-where
-    K: StorageKey,
-    V: StorageValue,
-{
-    // read entry header
-    let header = EntryHeader::read(buf)?;
+    #[tokio::test]
+    async fn test_contains() {
+        const KB: usize = 1024;
+        const MB: usize = 1024 * 1024;
 
-    // TODO(MrCroxx): optimize buffer copy here.
+        let config = GenericStoreConfig::<u64, Vec<u8>, MemDevice> {
+            name: "".to_string(),
+            eviction_config: FifoConfig {}.into(),
+            device_config: MemDeviceConfig {
+                capacity: 4 * MB,
+                region_size: MB,
+                align: 4 * KB,
+                io_size: 16 * KB,
+                write_io_size: None,
+                read_io_size: None,
+            },
+            catalog_shards: 1,
+            admissions: vec![],
+            reinsertions: vec![],
+            flushers: 1,
+            reclaimers: 1,
+            recover_concurrency: 2,
+            clean_region_threshold: 1,
+            compression: Compression::None,
+            checksum: true,
+            recent_inserts_capacity: 0,
+            max_key_size: None,
+            region_compression: None,
+            intra_block_align: None,
+            catalog_snapshot_path: None,
+            reclaim_grace_period: Duration::ZERO,
+            compression_observer: None,
+            unknown_region_version_policy: UnknownRegionVersionPolicy::default(),
+            block_compression: None,
+            background_cpu_cores: None,
+            metrics_recorder: None,
+            write_conflict_policy: WriteConflictPolicy::default(),
+            catalog_ops_limit: None,
+            first_entry_offset: None,
+            verify_region_magic_on_read: false,
+            self_test_on_open: false,
+            scrub_throughput_limit: None,
+            write_coalescing_interval: None,
+            max_writing_regions: None,
+            size_padding: PaddingPolicy::default(),
+            store_key_hash_only: false,
+            zstd_level: 0,
+            max_concurrent_decompressions: None,
+            checksum_algorithm: ChecksumAlgorithm::default(),
+            key_prefix: None,
+            flush_error_policy: FlushErrorPolicy::default(),
+            admission_observer: None,
+            max_region_age: None,
+            channel_drop_policy: ChannelDropPolicy::default(),
+            prefix_compressions: HashMap::new(),
+            io_bytes_in_flight_limit: None,
+            flusher_queue_capacity: None,
+            queue_full_policy: QueueFullPolicy::default(),
+            flush_alignment: None,
+            adaptive_compression: false,
+            scan_read_ahead: 2,
+            verify_checksum_on_recover: false,
+            catalog_backend: None,
+            catalog_retry_policy: CatalogRetryPolicy::default(),
+        };
 
-    // read value
-    let mut offset = EntryHeader::serialized_len();
-    let compressed = &buf[offset..offset + header.value_len as usize];
-    offset += header.value_len as usize;
-    let value = match header.compression {
-        Compression::None => bincode::deserialize_from(compressed).map_err(BufferError::from)?,
-        Compression::Zstd => {
-            let decoder = zstd::Decoder::new(compressed).map_err(BufferError::from)?;
-            bincode::deserialize_from(decoder).map_err(BufferError::from)?
+        let store = GenericStore::open(config).await.unwrap();
+
+        // Each region holds 7 entries, the region of the first entries is reclaimed after the entries fill 4 regions.
+        for i in 0..31u64 {
+            store.insert(i, vec![i as u8; 128 * KB]).await.unwrap();
         }
-        Compression::Lz4 => {
-            let decoder = lz4::Decoder::new(compressed).map_err(BufferError::from)?;
-            bincode::deserialize_from(decoder).map_err(BufferError::from)?
+        store.insert(0, vec![u8::MAX; 128 * KB]).await.unwrap();
+        for i in 31..35u64 {
+            store.insert(i, vec![i as u8; 128 * KB]).await.unwrap();
         }
-    };
+        store.barrier().await.unwrap();
 
-    // read key
-    let compressed = &buf[offset..offset + header.key_len as usize];
-    let key = bincode::deserialize_from(compressed).map_err(BufferError::from)?;
-    offset += header.key_len as usize;
+        // The evicted entries are not contained, while the superseding entry in a later region is.
+        assert!(!store.contains(&1));
+        assert!(store.get(&1).await.unwrap().is_none());
+        assert!(store.contains(&0));
+        assert_eq!(store.get(&0).await.unwrap().unwrap().value(), &vec![u8::MAX; 128 * KB]);
+        assert!(store.contains(&34));
+
+        store.remove(&34).unwrap();
+        assert!(!store.contains(&34));
+
+        store
+            .insert_with_ttl(35, vec![35; 16 * KB], Duration::from_millis(100))
+            .await
+            .unwrap();
+        assert!(store.contains(&35));
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert!(!store.contains(&35));
 
-    let checksum = checksum(&buf[EntryHeader::serialized_len()..offset]);
-    if checksum != header.checksum {
-        return Err(anyhow!("magic mismatch, expected: {}, got: {}", header.checksum, checksum).into());
+        store.close().await.unwrap();
     }
 
-    Ok((key, value))
-}
+    #[tokio::test]
+    async fn test_max_region_age() {
+        const KB: usize = 1024;
+        const MB: usize = 1024 * 1024;
 
-pub fn checksum(buf: &[u8]) -> u64 {
-    let mut hasher = XxHash64::with_seed(0);
-    hasher.write(buf);
-    hasher.finish()
-}
+        let config = GenericStoreConfig::<u64, Vec<u8>, MemDevice> {
+            name: "".to_string(),
+            eviction_config: FifoConfig {}.into(),
+            device_config: MemDeviceConfig {
+                capacity: 16 * MB,
+                region_size: MB,
+                align: 4 * KB,
+                io_size: 16 * KB,
+                write_io_size: None,
+                read_io_size: None,
+            },
+            catalog_shards: 1,
+            admissions: vec![],
+            reinsertions: vec![],
+            flushers: 1,
+            reclaimers: 0,
+            recover_concurrency: 2,
+            clean_region_threshold: 1,
+            compression: Compression::None,
+            checksum: true,
+            recent_inserts_capacity: 0,
+            max_key_size: None,
+            region_compression: None,
+            intra_block_align: None,
+            catalog_snapshot_path: None,
+            reclaim_grace_period: Duration::ZERO,
+            compression_observer: None,
+            unknown_region_version_policy: UnknownRegionVersionPolicy::default(),
+            block_compression: None,
+            background_cpu_cores: None,
+            metrics_recorder: None,
+            write_conflict_policy: WriteConflictPolicy::default(),
+            catalog_ops_limit: None,
+            first_entry_offset: None,
+            verify_region_magic_on_read: false,
+            self_test_on_open: false,
+            scrub_throughput_limit: None,
+            write_coalescing_interval: None,
+            max_writing_regions: None,
+            size_padding: PaddingPolicy::default(),
+            store_key_hash_only: false,
+            zstd_level: 0,
+            max_concurrent_decompressions: None,
+            checksum_algorithm: ChecksumAlgorithm::default(),
+            key_prefix: None,
+            flush_error_policy: FlushErrorPolicy::default(),
+            admission_observer: None,
+            max_region_age: Some(Duration::from_millis(100)),
+            channel_drop_policy: ChannelDropPolicy::default(),
+            prefix_compressions: HashMap::new(),
+            io_bytes_in_flight_limit: None,
+            flusher_queue_capacity: None,
+            queue_full_policy: QueueFullPolicy::default(),
+            flush_alignment: None,
+            adaptive_compression: false,
+            scan_read_ahead: 2,
+            verify_checksum_on_recover: false,
+            catalog_backend: None,
+            catalog_retry_policy: CatalogRetryPolicy::default(),
+        };
 
-pub struct RegionEntryIter<K, V, D>
-where
-    K: StorageKey,
-    V: StorageValue,
-    D: Device,
-{
-    region: Region<D>,
+        let store = GenericStore::open(config).await.unwrap();
 
-    cursor: usize,
+        let locate = |key: u64| {
+            let item = store.inner.catalog.get(&key).unwrap();
+            match item.index() {
+                Index::Region { view } => *view.id(),
+                _ => unreachable!(),
+            }
+        };
 
-    _marker: PhantomData<(K, V)>,
-}
+        store.insert(0, vec![0; 16 * KB]).await.unwrap();
+        store.insert(1, vec![1; 16 * KB]).await.unwrap();
+        store.barrier().await.unwrap();
+        let region = locate(0);
+        assert_eq!(locate(1), region);
+
+        // The partially filled region is sealed after the max age, and the following entries go to a new region.
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        store.insert(2, vec![2; 16 * KB]).await.unwrap();
+        store.barrier().await.unwrap();
+        assert_ne!(locate(2), region);
+        // The sealed region is evictable.
+        assert_eq!(store.inner.region_manager.eviction_pop(), Some(region));
+
+        for key in 0..3u64 {
+            assert_eq!(
+                store.get(&key).await.unwrap().unwrap().value(),
+                &vec![key as u8; 16 * KB]
+            );
+        }
 
-impl<K, V, D> RegionEntryIter<K, V, D>
-where
-    K: StorageKey,
-    V: StorageValue,
-    D: Device,
-{
-    pub async fn open(region: Region<D>) -> Result<Option<Self>> {
-        let align = region.device().align();
+        store.close().await.unwrap();
+    }
 
-        let slice = match region.load_range(0..align).await? {
-            Some(slice) => slice,
-            None => return Ok(None),
-        };
+    #[tokio::test]
+    async fn test_replicate_region_bytes() {
+        const KB: usize = 1024;
+        const MB: usize = 1024 * 1024;
 
-        let Ok(_) = RegionHeader::read(slice.as_ref()) else {
-            return Ok(None);
+        let config = || GenericStoreConfig::<u64, Vec<u8>, MemDevice> {
+            name: "".to_string(),
+            eviction_config: FifoConfig {}.into(),
+            device_config: MemDeviceConfig {
+                capacity: 16 * MB,
+                region_size: MB,
+                align: 4 * KB,
+                io_size: 16 * KB,
+                write_io_size: None,
+                read_io_size: None,
+            },
+            catalog_shards: 1,
+            admissions: vec![],
+            reinsertions: vec![],
+            flushers: 1,
+            reclaimers: 0,
+            recover_concurrency: 2,
+            clean_region_threshold: 1,
+            compression: Compression::None,
+            checksum: true,
+            recent_inserts_capacity: 0,
+            max_key_size: None,
+            region_compression: None,
+            intra_block_align: None,
+            catalog_snapshot_path: None,
+            reclaim_grace_period: Duration::ZERO,
+            compression_observer: None,
+            unknown_region_version_policy: UnknownRegionVersionPolicy::default(),
+            block_compression: None,
+            background_cpu_cores: None,
+            metrics_recorder: None,
+            write_conflict_policy: WriteConflictPolicy::default(),
+            catalog_ops_limit: None,
+            first_entry_offset: None,
+            verify_region_magic_on_read: false,
+            self_test_on_open: false,
+            scrub_throughput_limit: None,
+            write_coalescing_interval: None,
+            max_writing_regions: None,
+            size_padding: PaddingPolicy::default(),
+            store_key_hash_only: false,
+            zstd_level: 0,
+            max_concurrent_decompressions: None,
+            checksum_algorithm: ChecksumAlgorithm::default(),
+            key_prefix: None,
+            flush_error_policy: FlushErrorPolicy::default(),
+            admission_observer: None,
+            max_region_age: Some(Duration::from_millis(100)),
+            channel_drop_policy: ChannelDropPolicy::default(),
+            prefix_compressions: HashMap::new(),
+            io_bytes_in_flight_limit: None,
+            flusher_queue_capacity: None,
+            queue_full_policy: QueueFullPolicy::default(),
+            flush_alignment: None,
+            adaptive_compression: false,
+            scan_read_ahead: 2,
+            verify_checksum_on_recover: false,
+            catalog_backend: None,
+            catalog_retry_policy: CatalogRetryPolicy::default(),
         };
 
-        Ok(Some(Self {
-            region,
-            cursor: align,
-            _marker: PhantomData,
-        }))
-    }
-
-    pub async fn next(&mut self) -> Result<Option<(K, Item<K, V>)>> {
-        let region_size = self.region.device().region_size();
-        let align = self.region.device().align();
+        let primary = GenericStore::open(config()).await.unwrap();
+        let standby = GenericStore::open(config()).await.unwrap();
 
-        if self.cursor + align >= region_size {
-            return Ok(None);
+        for key in 0..4u64 {
+            primary.insert(key, vec![key as u8; 16 * KB]).await.unwrap();
         }
-
-        let Some(slice) = self.region.load_range(self.cursor..self.cursor + align).await? else {
-            return Ok(None);
+        primary.barrier().await.unwrap();
+        let region = match primary.inner.catalog.get(&0).unwrap().index() {
+            Index::Region { view } => *view.id(),
+            _ => unreachable!(),
         };
 
-        let Ok(header) = EntryHeader::read(slice.as_ref()) else {
-            return Ok(None);
-        };
+        // The region is still being written.
+        assert!(matches!(
+            primary.read_region_bytes(region).await,
+            Err(Error::RegionNotSealed { region: r }) if r == region
+        ));
 
-        let entry_len = bits::align_up(
-            align,
-            (header.value_len + header.key_len) as usize + EntryHeader::serialized_len(),
-        );
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        assert!(primary.inner.region_manager.is_sealed(region));
 
-        let abs_start = self.cursor + EntryHeader::serialized_len() + header.value_len as usize;
-        let abs_end = self.cursor + EntryHeader::serialized_len() + (header.key_len + header.value_len) as usize;
+        let bytes = primary.read_region_bytes(region).await.unwrap();
+        assert_eq!(bytes.len(), MB);
+        standby.apply_region_bytes(region, bytes).await.unwrap();
 
-        if abs_start >= abs_end || abs_end > region_size {
-            // Double check wrong entry.
-            return Ok(None);
+        assert!(standby.inner.region_manager.is_sealed(region));
+        for key in 0..4u64 {
+            assert_eq!(
+                standby.get(&key).await.unwrap().unwrap().value(),
+                &vec![key as u8; 16 * KB]
+            );
         }
 
-        let align_start = bits::align_down(align, abs_start);
-        let align_end = bits::align_up(align, abs_end);
-
-        let key = if align_start == self.cursor - align && align_end == self.cursor {
-            // header and key are in the same block, read directly from slice
-            let rel_start = EntryHeader::serialized_len() + header.value_len as usize;
-            let rel_end = rel_start + header.key_len as usize;
-
-            let Ok(key) = bincode::deserialize_from(&slice.as_ref()[rel_start..rel_end]) else {
-                return Ok(None);
-            };
-            drop(slice);
-            key
-        } else {
-            drop(slice);
-            let Some(s) = self.region.load_range(align_start..align_end).await? else {
-                return Ok(None);
-            };
-            let rel_start = abs_start - align_start;
-            let rel_end = abs_end - align_start;
+        // The insertions on the standby are newer than the replicated entries.
+        standby.insert(0, vec![42; 16 * KB]).await.unwrap();
+        standby.barrier().await.unwrap();
+        assert_eq!(standby.get(&0).await.unwrap().unwrap().value(), &vec![42; 16 * KB]);
 
-            let Ok(key) = bincode::deserialize_from(&s.as_ref()[rel_start..rel_end]) else {
-                return Ok(None);
-            };
-            drop(s);
-            key
-        };
+        primary.close().await.unwrap();
+        standby.close().await.unwrap();
+    }
 
-        let info = Item::new(
-            header.sequence,
-            Index::Region {
-                view: self.region.view(self.cursor as u32, entry_len as u32),
-            },
-        );
+    #[tokio::test]
+    async fn test_rebuild_region_filters() {
+        const KB: usize = 1024;
+        const MB: usize = 1024 * 1024;
 
-        self.cursor += entry_len;
+        let store = GenericStore::open(base_config::<u64, Vec<u8>, MemDevice>(MemDeviceConfig {
+            capacity: 16 * MB,
+            region_size: MB,
+            align: 4 * KB,
+            io_size: 16 * KB,
+            write_io_size: None,
+            read_io_size: None,
+        }))
+        .await
+        .unwrap();
 
-        Ok(Some((key, info)))
-    }
+        for key in 0..200u64 {
+            store.insert(key, vec![key as u8; 16 * KB]).await.unwrap();
+        }
+        store.barrier().await.unwrap();
 
-    pub async fn next_kv(&mut self) -> Result<Option<(K, V, usize)>> {
-        let (_, item) = match self.next().await {
-            Ok(Some(res)) => res,
-            Ok(None) => return Ok(None),
-            Err(e) => return Err(e),
+        let align = store.device().align();
+        let region_manager = store.inner.region_manager.clone();
+        let header = |region: RegionId| {
+            let region = region_manager.region(&region).clone();
+            async move { RegionHeader::read(region.load_range(0..align).await.unwrap().unwrap().as_ref()).unwrap() }
         };
+        let mut region_keys: HashMap<RegionId, Vec<u64>> = HashMap::new();
+        for key in 0..200u64 {
+            let region = match store.inner.catalog.get(&key).unwrap().index() {
+                Index::Region { view } => *view.id(),
+                _ => unreachable!(),
+            };
+            region_keys.entry(region).or_default().push(key);
+        }
+        let sealed = region_keys
+            .keys()
+            .copied()
+            .filter(|region| store.inner.region_manager.is_sealed(*region))
+            .collect_vec();
+        assert!(!sealed.is_empty());
 
-        let Index::Region { view } = item.index() else {
-            unreachable!("kv loaded from region must have index of region")
-        };
+        // The regions are written without key filters.
+        for region in region_keys.keys() {
+            assert!(header(*region).await.filter.is_none());
+        }
 
-        // TODO(MrCroxx): Optimize if all key, value and footer are in the same read block.
-        let start = *view.offset() as usize;
-        let end = start + *view.len() as usize;
-        let Some(slice) = self.region.load_range(start..end).await? else {
-            return Ok(None);
+        let assert_no_false_negative = |filter: &KeyFilter, keys: &[u64]| {
+            for key in keys {
+                assert!(
+                    filter.may_contain(encoded_key_hash(key).unwrap()),
+                    "false negative of key {key}"
+                );
+            }
         };
-        let res = read_entry::<K, V>(slice.as_ref())
-            .ok()
-            .map(|(k, v)| (k, v, slice.len()));
-        drop(slice);
-
-        Ok(res)
-    }
-}
-
-impl<K, V, D> StorageWriter<K, V> for GenericStoreWriter<K, V, D>
-where
-    K: StorageKey,
-    V: StorageValue,
-    D: Device,
-{
-    fn key(&self) -> &K {
-        self.key.as_ref().unwrap()
-    }
 
-    fn judge(&mut self) -> bool {
-        self.judge()
-    }
+        assert_eq!(store.rebuild_region_filters().await.unwrap(), sealed.len());
+        for region in region_keys.keys() {
+            let header = header(*region).await;
+            if !sealed.contains(region) {
+                // The region being written is skipped.
+                assert!(header.filter.is_none());
+                continue;
+            }
+            assert_eq!(header.version, Version::latest());
+            let filter = header.filter.unwrap();
+            assert_eq!(filter.as_bytes().len(), RegionHeader::filter_capacity(align));
+            assert_no_false_negative(&filter, &region_keys[region]);
+            // Not all the keys of the other regions pass the filter.
+            assert!((1000..2000u64).any(|key| !filter.may_contain(encoded_key_hash(&key).unwrap())));
+        }
 
-    fn force(&mut self) {
-        self.force()
-    }
+        // Clear the filter of a region behind the store and rebuild it.
+        let region = sealed[0];
+        let mut cleared = header(region).await;
+        cleared.filter = None;
+        let mut buf = store.device().io_buffer(align, align);
+        buf.fill(0);
+        cleared.write(&mut buf[..]);
+        let (res, _) = store.device().write(buf, .., region, 0).await;
+        res.unwrap();
+        assert!(header(region).await.filter.is_none());
+
+        assert_eq!(store.rebuild_region_filters().await.unwrap(), sealed.len());
+        assert_no_false_negative(&header(region).await.filter.unwrap(), &region_keys[&region]);
+
+        // The entries are left in place.
+        for key in 0..200u64 {
+            assert_eq!(
+                store.get(&key).await.unwrap().unwrap().value(),
+                &vec![key as u8; 16 * KB]
+            );
+        }
 
-    async fn finish<AV>(self, value: AV) -> Result<Option<CachedEntry<K, V>>>
-    where
-        AV: Into<Arc<V>> + Send + 'static,
-    {
-        self.finish(value).await
-    }
+        // The get path skips the device read of a key the region filter rejects.
+        let filtered = store.inner.region_manager.region(&region).clone();
+        assert!(filtered.filter().is_some());
+        filtered.set_filter(Some(KeyFilter::new(RegionHeader::filter_capacity(align))));
+        let key = region_keys[&region][0];
+        assert!(store.get(&key).await.unwrap().is_none());
+        assert!(!store.exists(&key).unwrap());
 
-    fn compression(&self) -> Compression {
-        self.compression()
-    }
+        // The filter only covers the entries of the current generation.
+        filtered.advance_generation();
+        assert!(filtered.filter().is_none());
 
-    fn set_compression(&mut self, compression: Compression) {
-        self.set_compression(compression)
+        store.close().await.unwrap();
     }
-}
-
-impl<K, V, D> Storage<K, V> for GenericStore<K, V, D>
-where
-    K: StorageKey,
-    V: StorageValue,
-    D: Device,
-{
-    type Config = GenericStoreConfig<K, V, D>;
-    type Writer = GenericStoreWriter<K, V, D>;
 
-    async fn open(config: Self::Config) -> Result<Self> {
-        Self::open(config).await
-    }
+    #[tokio::test]
+    async fn test_multiple_flushers_evictable_once() {
+        const KB: usize = 1024;
+        const MB: usize = 1024 * 1024;
+        const FLUSHERS: usize = 4;
+        const ENTRIES: usize = 96;
 
-    fn is_ready(&self) -> bool {
-        true
-    }
+        let config = GenericStoreConfig::<u64, Vec<u8>, MemDevice> {
+            name: "".to_string(),
+            eviction_config: FifoConfig {}.into(),
+            device_config: MemDeviceConfig {
+                capacity: 16 * MB,
+                region_size: MB,
+                align: 4 * KB,
+                io_size: 16 * KB,
+                write_io_size: None,
+                read_io_size: None,
+            },
+            catalog_shards: 1,
+            admissions: vec![],
+            reinsertions: vec![],
+            flushers: FLUSHERS,
+            reclaimers: 0,
+            recover_concurrency: 2,
+            clean_region_threshold: 1,
+            compression: Compression::None,
+            checksum: true,
+            recent_inserts_capacity: 0,
+            max_key_size: None,
+            region_compression: None,
+            intra_block_align: None,
+            catalog_snapshot_path: None,
+            reclaim_grace_period: Duration::ZERO,
+            compression_observer: None,
+            unknown_region_version_policy: UnknownRegionVersionPolicy::default(),
+            block_compression: None,
+            background_cpu_cores: None,
+            metrics_recorder: None,
+            write_conflict_policy: WriteConflictPolicy::default(),
+            catalog_ops_limit: None,
+            first_entry_offset: None,
+            verify_region_magic_on_read: false,
+            self_test_on_open: false,
+            scrub_throughput_limit: None,
+            write_coalescing_interval: None,
+            max_writing_regions: None,
+            size_padding: PaddingPolicy::default(),
+            store_key_hash_only: false,
+            zstd_level: 0,
+            max_concurrent_decompressions: None,
+            checksum_algorithm: ChecksumAlgorithm::default(),
+            key_prefix: None,
+            flush_error_policy: FlushErrorPolicy::default(),
+            admission_observer: None,
+            max_region_age: None,
+            channel_drop_policy: ChannelDropPolicy::default(),
+            prefix_compressions: HashMap::new(),
+            io_bytes_in_flight_limit: None,
+            flusher_queue_capacity: None,
+            queue_full_policy: QueueFullPolicy::default(),
+            flush_alignment: None,
+            adaptive_compression: false,
+            scan_read_ahead: 2,
+            verify_checksum_on_recover: false,
+            catalog_backend: None,
+            catalog_retry_policy: CatalogRetryPolicy::default(),
+        };
 
-    async fn close(&self) -> Result<()> {
-        self.close().await
-    }
+        let store = GenericStore::open(config).await.unwrap();
 
-    fn writer<AK>(&self, key: AK) -> Self::Writer
-    where
-        AK: Into<Arc<K>> + Send + 'static,
-    {
-        self.writer(key)
-    }
+        // The entries are distributed to all flushers.
+        for i in 0..ENTRIES {
+            store.insert(i as u64, vec![i as u8; 64 * KB]).await.unwrap();
+        }
+        store.barrier().await.unwrap();
 
-    fn exists<Q>(&self, key: &Q) -> Result<bool>
-    where
-        K: Borrow<Q>,
-        Q: Hash + Eq + ?Sized,
-    {
-        self.exists(key)
-    }
+        let written: HashSet<RegionId> = (0..ENTRIES as u64)
+            .map(|key| match store.inner.catalog.get(&key).unwrap().index() {
+                Index::Region { view } => *view.id(),
+                _ => unreachable!(),
+            })
+            .collect();
 
-    async fn get<Q>(&self, key: &Q) -> Result<Option<CachedEntry<K, V>>>
-    where
-        K: Borrow<Q>,
-        Q: Hash + Eq + ?Sized,
-    {
-        self.get(key).await
-    }
+        let mut evictable = vec![];
+        while let Some(region) = store.inner.region_manager.eviction_pop() {
+            evictable.push(region);
+        }
+        let unique: HashSet<RegionId> = evictable.iter().copied().collect();
 
-    fn remove<Q>(&self, key: &Q) -> Result<bool>
-    where
-        K: Borrow<Q>,
-        Q: Hash + Eq + ?Sized,
-    {
-        self.remove(key)
-    }
+        // Each fully written region is evictable exactly once, only the regions still being written are not.
+        assert_eq!(unique.len(), evictable.len());
+        assert!(unique.is_subset(&written));
+        assert!(written.len() - unique.len() <= FLUSHERS);
+        assert!(!unique.is_empty());
 
-    fn clear(&self) -> Result<()> {
-        self.clear()
+        store.close().await.unwrap();
     }
-}
-
-#[cfg(test)]
-mod tests {
-    use std::path::PathBuf;
-
-    use foyer_memory::FifoConfig;
-
-    use super::*;
-    use crate::{
-        device::fs::{FsDevice, FsDeviceConfig},
-        storage::StorageExt,
-        test_utils::JudgeRecorder,
-    };
-
-    type TestStore = GenericStore<u64, Vec<u8>, FsDevice>;
-    type TestStoreConfig = GenericStoreConfig<u64, Vec<u8>, FsDevice>;
 
     #[tokio::test]
-    // TODO(MrCroxx): use `expect` after `lint_reasons` is stable.
-    #[allow(clippy::identity_op)]
-    async fn test_recovery() {
+    async fn test_channel_drop_policy() {
         const KB: usize = 1024;
         const MB: usize = 1024 * 1024;
 
-        let tempdir = tempfile::tempdir().unwrap();
-
-        let recorder = Arc::new(JudgeRecorder::default());
-        let admissions: Vec<Arc<dyn AdmissionPolicy<Key = u64, Value = Vec<u8>>>> = vec![recorder.clone()];
-        let reinsertions: Vec<Arc<dyn ReinsertionPolicy<Key = u64, Value = Vec<u8>>>> = vec![recorder.clone()];
-
-        let config = TestStoreConfig {
+        let config = |channel_drop_policy| GenericStoreConfig::<u64, Vec<u8>, MemDevice> {
             name: "".to_string(),
             eviction_config: FifoConfig {}.into(),
-            device_config: FsDeviceConfig {
-                dir: PathBuf::from(tempdir.path()),
+            device_config: MemDeviceConfig {
                 capacity: 16 * MB,
-                file_size: 4 * MB,
+                region_size: MB,
                 align: 4 * KB,
-                io_size: 4 * KB,
+                io_size: 16 * KB,
+                write_io_size: None,
+                read_io_size: None,
             },
             catalog_shards: 1,
-            admissions,
-            reinsertions,
+            admissions: vec![],
+            reinsertions: vec![],
             flushers: 1,
-            reclaimers: 1,
+            reclaimers: 0,
             recover_concurrency: 2,
             clean_region_threshold: 1,
             compression: Compression::None,
+            checksum: true,
+            recent_inserts_capacity: 0,
+            max_key_size: None,
+            region_compression: None,
+            intra_block_align: None,
+            catalog_snapshot_path: None,
+            reclaim_grace_period: Duration::ZERO,
+            compression_observer: None,
+            unknown_region_version_policy: UnknownRegionVersionPolicy::default(),
+            block_compression: None,
+            background_cpu_cores: None,
+            metrics_recorder: None,
+            write_conflict_policy: WriteConflictPolicy::default(),
+            catalog_ops_limit: None,
+            first_entry_offset: None,
+            verify_region_magic_on_read: false,
+            self_test_on_open: false,
+            scrub_throughput_limit: None,
+            write_coalescing_interval: None,
+            max_writing_regions: None,
+            size_padding: PaddingPolicy::default(),
+            store_key_hash_only: false,
+            zstd_level: 0,
+            max_concurrent_decompressions: None,
+            checksum_algorithm: ChecksumAlgorithm::default(),
+            key_prefix: None,
+            flush_error_policy: FlushErrorPolicy::default(),
+            admission_observer: None,
+            max_region_age: None,
+            channel_drop_policy,
+            prefix_compressions: HashMap::new(),
+            io_bytes_in_flight_limit: None,
+            flusher_queue_capacity: None,
+            queue_full_policy: QueueFullPolicy::default(),
+            flush_alignment: None,
+            adaptive_compression: false,
+            scan_read_ahead: 2,
+            verify_checksum_on_recover: false,
+            catalog_backend: None,
+            catalog_retry_policy: CatalogRetryPolicy::default(),
         };
 
-        let store = TestStore::open(config).await.unwrap();
-
-        // files:
-        // [0, 1, 2]
-        // [3, 4, 5]
-        // [6, 7, 8]
-        // [9, 10, 11]
-        // ... ...
-        for i in 0..21 {
-            store.insert(i, vec![i as u8; 1 * MB]).await.unwrap();
-        }
-
+        // A graceful close never marks the store unhealthy.
+        let store = GenericStore::open(config(ChannelDropPolicy::Unhealthy)).await.unwrap();
+        store.insert(1, vec![1; KB]).await.unwrap();
         store.close().await.unwrap();
-
-        let remains = recorder.remains();
-
-        for i in 0..21 {
-            if remains.contains(&i) {
-                assert_eq!(store.get(&i).await.unwrap().unwrap().value(), &vec![i as u8; 1 * MB],);
-            } else {
-                assert!(store.get(&i).await.unwrap().is_none());
+        assert!(store.is_healthy());
+
+        // Dropping the store without closing it closes the entry channels of the flushers.
+        for (policy, healthy) in [
+            (ChannelDropPolicy::Unhealthy, false),
+            (ChannelDropPolicy::Graceful, true),
+        ] {
+            let store = GenericStore::open(config(policy)).await.unwrap();
+            store.insert(1, vec![1; KB]).await.unwrap();
+            assert!(store.is_healthy());
+
+            let flag = store.inner.healthy.clone();
+            let handles = store.inner.flusher_handles.lock().drain(..).collect_vec();
+            drop(store);
+            for handle in handles {
+                handle.await.unwrap();
             }
+            assert_eq!(flag.load(Ordering::Acquire), healthy);
         }
+    }
 
-        drop(store);
+    #[tokio::test]
+    async fn test_shrink() {
+        const KB: usize = 1024;
+        const MB: usize = 1024 * 1024;
 
-        let config = TestStoreConfig {
+        let tempdir = tempfile::tempdir().unwrap();
+
+        let config = |capacity| TestStoreConfig {
             name: "".to_string(),
             eviction_config: FifoConfig {}.into(),
             device_config: FsDeviceConfig {
                 dir: PathBuf::from(tempdir.path()),
-                capacity: 16 * MB,
-                file_size: 4 * MB,
-                align: 4096,
-                io_size: 4096 * KB,
+                capacity,
+                file_size: MB,
+                align: 4 * KB,
+                io_size: 16 * KB,
+                io_executor: IoExecutor::default(),
+                on_region_open_error: RegionOpenErrorPolicy::default(),
+                write_io_size: None,
+                read_io_size: None,
+                extra_dirs: vec![],
+                direct_io: true,
             },
             catalog_shards: 1,
             admissions: vec![],
             reinsertions: vec![],
             flushers: 1,
-            reclaimers: 0,
+            reclaimers: 1,
             recover_concurrency: 2,
             clean_region_threshold: 1,
             compression: Compression::None,
+            checksum: true,
+            recent_inserts_capacity: 0,
+            max_key_size: None,
+            region_compression: None,
+            intra_block_align: None,
+            catalog_snapshot_path: None,
+            reclaim_grace_period: Duration::ZERO,
+            compression_observer: None,
+            unknown_region_version_policy: UnknownRegionVersionPolicy::default(),
+            block_compression: None,
+            background_cpu_cores: None,
+            metrics_recorder: None,
+            write_conflict_policy: WriteConflictPolicy::default(),
+            catalog_ops_limit: None,
+            first_entry_offset: None,
+            verify_region_magic_on_read: false,
+            self_test_on_open: false,
+            scrub_throughput_limit: None,
+            write_coalescing_interval: None,
+            max_writing_regions: None,
+            size_padding: PaddingPolicy::default(),
+            store_key_hash_only: false,
+            zstd_level: 0,
+            max_concurrent_decompressions: None,
+            checksum_algorithm: ChecksumAlgorithm::default(),
+            key_prefix: None,
+            flush_error_policy: FlushErrorPolicy::default(),
+            admission_observer: None,
+            max_region_age: None,
+            channel_drop_policy: ChannelDropPolicy::default(),
+            prefix_compressions: HashMap::new(),
+            io_bytes_in_flight_limit: None,
+            flusher_queue_capacity: None,
+            queue_full_policy: QueueFullPolicy::default(),
+            flush_alignment: None,
+            adaptive_compression: false,
+            scan_read_ahead: 2,
+            verify_checksum_on_recover: false,
+            catalog_backend: None,
+            catalog_retry_policy: CatalogRetryPolicy::default(),
         };
-        let store = TestStore::open(config).await.unwrap();
 
-        for i in 0..21 {
-            if remains.contains(&i) {
-                assert_eq!(store.get(&i).await.unwrap().unwrap().value(), &vec![i as u8; 1 * MB],);
-            } else {
-                assert!(store.get(&i).await.unwrap().is_none());
-            }
+        let store = TestStore::open(config(8 * MB)).await.unwrap();
+
+        // Each region holds 7 entries, the entries fill 7 of the 8 regions.
+        for i in 0..48u64 {
+            store.insert(i, vec![i as u8; 128 * KB]).await.unwrap();
         }
+        store.barrier().await.unwrap();
 
-        store.close().await.unwrap();
+        // Refuse to shrink while the live data exceeds the capacity.
+        assert!(matches!(
+            store.shrink(4 * MB).await,
+            Err(Error::ShrinkLiveDataExceeded { capacity, .. }) if capacity == 4 * MB
+        ));
+        assert!(store.shrink(3 * MB / 2).await.is_err());
+
+        // Only keep a few entries in the trailing regions.
+        let region = |i: u64| match store.catalog().get(&i).unwrap().consume().1 {
+            Index::Region { view } => *view.id(),
+            _ => panic!("entry must be indexed by region after flushed"),
+        };
+        let trailing = (0..48u64).filter(|&i| region(i) >= 4).collect_vec();
+        let live = trailing.iter().copied().step_by(5).collect_vec();
+        assert!(live.len() >= 3);
+        for i in (0..48u64).filter(|i| !live.contains(i)) {
+            store.remove(&i).unwrap();
+        }
+
+        store.shrink(4 * MB).await.unwrap();
+        store.barrier().await.unwrap();
 
+        for region in 0..8 {
+            assert_eq!(store.inner.device.path(region).exists(), region < 4);
+        }
+        for &i in &live {
+            assert_eq!(store.get(&i).await.unwrap().unwrap().value(), &vec![i as u8; 128 * KB]);
+            assert!(region(i) < 4);
+        }
+        store.close().await.unwrap();
         drop(store);
+
+        // The store is reopened with the shrunk capacity recorded in the manifest.
+        assert!(TestStore::open(config(8 * MB)).await.is_err());
+        let store = TestStore::open(config(4 * MB)).await.unwrap();
+        // The entries of the trailing regions are gone with the region files, except the relocated ones.
+        for &i in &trailing {
+            let value = store.get(&i).await.unwrap();
+            if live.contains(&i) {
+                assert_eq!(value.unwrap().value(), &vec![i as u8; 128 * KB]);
+            } else {
+                assert!(value.is_none());
+            }
+        }
+        store.close().await.unwrap();
     }
 }
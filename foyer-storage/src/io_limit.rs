@@ -0,0 +1,115 @@
+//  Copyright 2024 Foyer Project Authors
+//
+//  Licensed under the Apache License, Version 2.0 (the "License");
+//  you may not use this file except in compliance with the License.
+//  You may obtain a copy of the License at
+//
+//  http://www.apache.org/licenses/LICENSE-2.0
+//
+//  Unless required by applicable law or agreed to in writing, software
+//  distributed under the License is distributed on an "AS IS" BASIS,
+//  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//  See the License for the specific language governing permissions and
+//  limitations under the License.
+
+//! Limit of the total bytes of the in-flight device I/Os, which bounds the memory held by the pending io buffers and
+//! keeps the queue depth of the device reasonable on large flushes.
+//!
+//! Each read or write acquires the permits of its size before it is issued to the device, and releases them on
+//! completion. An I/O larger than the limit acquires all permits, so it is issued alone rather than blocked forever.
+
+#[cfg(test)]
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+#[derive(Debug)]
+struct Limit {
+    semaphore: Arc<Semaphore>,
+    bytes: usize,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct IoBytesLimiter {
+    limit: Option<Arc<Limit>>,
+
+    #[cfg(test)]
+    inflight: Arc<AtomicUsize>,
+    #[cfg(test)]
+    peak: Arc<AtomicUsize>,
+}
+
+impl IoBytesLimiter {
+    /// Create a limiter of `bytes` in flight, `None` means unlimited.
+    pub fn new(bytes: Option<usize>) -> Self {
+        let limit = bytes.map(|bytes| {
+            assert!(
+                bytes > 0 && bytes <= u32::MAX as usize,
+                "io bytes in flight limit must be in (0, {}], given: {}",
+                u32::MAX,
+                bytes
+            );
+            Arc::new(Limit {
+                semaphore: Arc::new(Semaphore::new(bytes)),
+                bytes,
+            })
+        });
+        Self {
+            limit,
+            #[cfg(test)]
+            inflight: Arc::new(AtomicUsize::new(0)),
+            #[cfg(test)]
+            peak: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    /// Acquire the permits of an I/O of `bytes` before it is issued, waiting if the limit is reached.
+    pub async fn acquire(&self, bytes: usize) -> IoBytesPermit {
+        let permit = match self.limit.as_ref() {
+            Some(limit) => {
+                let permits = bytes.min(limit.bytes) as u32;
+                Some(limit.semaphore.clone().acquire_many_owned(permits).await.unwrap())
+            }
+            None => None,
+        };
+
+        #[cfg(test)]
+        {
+            let inflight = self.inflight.fetch_add(bytes, Ordering::SeqCst) + bytes;
+            self.peak.fetch_max(inflight, Ordering::SeqCst);
+        }
+
+        IoBytesPermit {
+            _permit: permit,
+            #[cfg(test)]
+            limiter: self.clone(),
+            #[cfg(test)]
+            bytes,
+        }
+    }
+
+    /// Max total bytes of the in-flight I/Os ever observed, only for tests.
+    #[cfg(test)]
+    pub fn peak(&self) -> usize {
+        self.peak.load(Ordering::SeqCst)
+    }
+}
+
+/// Permits of an in-flight I/O, which are released on drop.
+#[derive(Debug)]
+pub struct IoBytesPermit {
+    _permit: Option<OwnedSemaphorePermit>,
+
+    #[cfg(test)]
+    limiter: IoBytesLimiter,
+    #[cfg(test)]
+    bytes: usize,
+}
+
+#[cfg(test)]
+impl Drop for IoBytesPermit {
+    fn drop(&mut self) {
+        self.limiter.inflight.fetch_sub(self.bytes, Ordering::SeqCst);
+    }
+}
@@ -14,20 +14,80 @@
 
 use std::{
     borrow::Borrow,
+    fmt::Debug,
     hash::Hash,
+    ops::Range,
+    path::Path,
     sync::{Arc, OnceLock},
+    time::SystemTime,
 };
 
 use foyer_common::code::{StorageKey, StorageValue};
-use tokio::task::JoinHandle;
+use futures::{future::BoxFuture, stream::BoxStream};
+use tokio::{io::AsyncWrite, sync::watch, task::JoinHandle};
 
 use crate::{
+    catalog::Sequence,
     compress::Compression,
-    error::Result,
+    error::{Error, Result},
     none::{NoneStore, NoneStoreWriter},
-    storage::{CachedEntry, Storage, StorageWriter},
+    region::RegionId,
+    storage::{CachedEntry, CompactionReport, CompactionStrategy, DrainedEntry, ScrubReport, Storage, StorageWriter},
 };
 
+/// Behavior of reads on the lazy store before its recovery finishes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RecoveryReadPolicy {
+    /// Treat the read as a miss, even if the entry is on the device.
+    #[default]
+    Miss,
+    /// Wait until the recovery finishes, then read from the recovered store.
+    ///
+    /// The regions are recovered concurrently and the store serves reads after all of them are recovered, so the
+    /// read waits for the whole recovery rather than the region of the entry. If the recovery fails, the read misses.
+    Wait,
+    /// Fail the read with [`Error::Recovering`].
+    Recovering,
+}
+
+pub struct LazyStoreConfig<K, V, S>
+where
+    K: StorageKey,
+    V: StorageValue,
+    S: Storage<K, V>,
+{
+    pub store_config: S::Config,
+    pub recovery_read_policy: RecoveryReadPolicy,
+}
+
+impl<K, V, S> Debug for LazyStoreConfig<K, V, S>
+where
+    K: StorageKey,
+    V: StorageValue,
+    S: Storage<K, V>,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LazyStoreConfig")
+            .field("store_config", &self.store_config)
+            .field("recovery_read_policy", &self.recovery_read_policy)
+            .finish()
+    }
+}
+
+impl<K, V, S> Clone for LazyStoreConfig<K, V, S>
+where
+    K: StorageKey,
+    V: StorageValue,
+    S: Storage<K, V>,
+{
+    fn clone(&self) -> Self {
+        Self {
+            store_config: self.store_config.clone(),
+            recovery_read_policy: self.recovery_read_policy,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum LazyStoreWriter<K, V, S>
 where
@@ -89,6 +149,20 @@ where
             LazyStoreWriter::None { writer } => writer.set_compression(compression),
         }
     }
+
+    fn expire_at(&self) -> Option<SystemTime> {
+        match self {
+            LazyStoreWriter::Store { writer } => writer.expire_at(),
+            LazyStoreWriter::None { writer } => writer.expire_at(),
+        }
+    }
+
+    fn set_expire_at(&mut self, expire_at: SystemTime) {
+        match self {
+            LazyStoreWriter::Store { writer } => writer.set_expire_at(expire_at),
+            LazyStoreWriter::None { writer } => writer.set_expire_at(expire_at),
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -100,6 +174,10 @@ where
 {
     once: Arc<OnceLock<S>>,
     none: NoneStore<K, V>,
+
+    /// Becomes `true` after the store is recovered, and is closed if the recovery fails.
+    ready: watch::Receiver<bool>,
+    recovery_read_policy: RecoveryReadPolicy,
 }
 
 impl<K, V, S> Clone for Lazy<K, V, S>
@@ -112,6 +190,8 @@ where
         Self {
             once: Arc::clone(&self.once),
             none: NoneStore::default(),
+            ready: self.ready.clone(),
+            recovery_read_policy: self.recovery_read_policy,
         }
     }
 }
@@ -122,13 +202,14 @@ where
     V: StorageValue,
     S: Storage<K, V>,
 {
-    fn with_handle(config: S::Config) -> (Self, JoinHandle<Result<S>>) {
+    fn with_handle(config: LazyStoreConfig<K, V, S>) -> (Self, JoinHandle<Result<S>>) {
         let once = Arc::new(OnceLock::new());
+        let (ready_tx, ready_rx) = watch::channel(false);
 
         let handle = tokio::spawn({
             let once = once.clone();
             async move {
-                let store = match S::open(config).await {
+                let store = match S::open(config.store_config).await {
                     Ok(store) => store,
                     Err(e) => {
                         tracing::error!("Lazy open store fail: {}", e);
@@ -138,6 +219,7 @@ where
                 if once.set(store.clone()).is_err() {
                     panic!("Lazy store has been initialized before.");
                 }
+                ready_tx.send_replace(true);
                 Ok(store)
             }
         });
@@ -145,10 +227,35 @@ where
         let res = Self {
             once,
             none: NoneStore::default(),
+            ready: ready_rx,
+            recovery_read_policy: config.recovery_read_policy,
         };
 
         (res, handle)
     }
+
+    /// The recovered store, `None` if the store is still recovering or fails to recover.
+    pub fn store(&self) -> Option<&S> {
+        self.once.get()
+    }
+
+    /// Get the recovered store for a read, following the [`RecoveryReadPolicy`] if the store is still recovering.
+    ///
+    /// Returns `None` if the read should miss.
+    async fn store_for_read(&self) -> Result<Option<&S>> {
+        if let Some(store) = self.once.get() {
+            return Ok(Some(store));
+        }
+        match self.recovery_read_policy {
+            RecoveryReadPolicy::Miss => Ok(None),
+            RecoveryReadPolicy::Recovering => Err(Error::Recovering),
+            RecoveryReadPolicy::Wait => {
+                // The sender is dropped without sending if the recovery fails.
+                let _ = self.ready.clone().wait_for(|ready| *ready).await;
+                Ok(self.once.get())
+            }
+        }
+    }
 }
 
 impl<K, V, S> Storage<K, V> for Lazy<K, V, S>
@@ -157,10 +264,10 @@ where
     V: StorageValue,
     S: Storage<K, V>,
 {
-    type Config = S::Config;
+    type Config = LazyStoreConfig<K, V, S>;
     type Writer = LazyStoreWriter<K, V, S>;
 
-    async fn open(config: S::Config) -> Result<Self> {
+    async fn open(config: LazyStoreConfig<K, V, S>) -> Result<Self> {
         let (store, task) = Self::with_handle(config);
         tokio::spawn(task);
         Ok(store)
@@ -191,6 +298,13 @@ where
         }
     }
 
+    async fn insert_batch(&self, entries: Vec<(Arc<K>, Arc<V>)>) -> Result<Vec<Option<CachedEntry<K, V>>>> {
+        match self.once.get() {
+            Some(store) => store.insert_batch(entries).await,
+            None => self.none.insert_batch(entries).await,
+        }
+    }
+
     fn exists<Q>(&self, key: &Q) -> Result<bool>
     where
         K: Borrow<Q>,
@@ -202,17 +316,58 @@ where
         }
     }
 
+    fn touch<Q>(&self, key: &Q) -> Result<bool>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        match self.once.get() {
+            Some(store) => store.touch(key),
+            None => self.none.touch(key),
+        }
+    }
+
+    fn recent_inserts(&self, n: usize) -> Vec<(Arc<K>, Sequence)> {
+        match self.once.get() {
+            Some(store) => store.recent_inserts(n),
+            None => self.none.recent_inserts(n),
+        }
+    }
+
     async fn get<Q>(&self, key: &Q) -> Result<Option<CachedEntry<K, V>>>
     where
         K: Borrow<Q>,
         Q: Hash + Eq + ?Sized + Send + Sync + 'static + Clone,
     {
-        match self.once.get() {
+        match self.store_for_read().await? {
             Some(store) => store.get(key).await,
             None => self.none.get(key).await,
         }
     }
 
+    async fn get_stream<Q, W>(&self, key: &Q, writer: W) -> Result<bool>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + Send + Sync + 'static + Clone,
+        W: AsyncWrite + Send + Unpin + 'static,
+    {
+        match self.store_for_read().await? {
+            Some(store) => store.get_stream(key, writer).await,
+            None => self.none.get_stream(key, writer).await,
+        }
+    }
+
+    async fn get_range<Q>(&self, key: &Q, range: Range<usize>) -> Result<Option<Vec<u8>>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + Send + Sync + 'static + Clone,
+    {
+        match self.store_for_read().await? {
+            Some(store) => store.get_range(key, range).await,
+            None => self.none.get_range(key, range).await,
+        }
+    }
+
     fn remove<Q>(&self, key: &Q) -> Result<bool>
     where
         K: Borrow<Q>,
@@ -230,17 +385,102 @@ where
             None => self.none.clear(),
         }
     }
+
+    async fn snapshot_catalog(&self, path: impl AsRef<Path> + Send) -> Result<()> {
+        match self.once.get() {
+            Some(store) => store.snapshot_catalog(path).await,
+            None => self.none.snapshot_catalog(path).await,
+        }
+    }
+
+    fn entries_since(&self, sequence: Sequence) -> BoxStream<'static, Result<(Arc<K>, Arc<V>, Sequence)>> {
+        match self.once.get() {
+            Some(store) => store.entries_since(sequence),
+            None => self.none.entries_since(sequence),
+        }
+    }
+
+    fn scan(&self) -> BoxStream<'static, Result<(K, V)>> {
+        match self.once.get() {
+            Some(store) => store.scan(),
+            None => self.none.scan(),
+        }
+    }
+
+    fn barrier(&self) -> BoxFuture<'static, Result<()>> {
+        match self.once.get() {
+            Some(store) => store.barrier(),
+            None => self.none.barrier(),
+        }
+    }
+
+    fn drain(&self) -> BoxFuture<'static, Result<Vec<DrainedEntry<K>>>> {
+        match self.once.get() {
+            Some(store) => store.drain(),
+            None => self.none.drain(),
+        }
+    }
+
+    fn scrub(&self, remove_corrupted: bool) -> BoxFuture<'static, Result<ScrubReport<K>>> {
+        match self.once.get() {
+            Some(store) => store.scrub(remove_corrupted),
+            None => self.none.scrub(remove_corrupted),
+        }
+    }
+
+    fn compact(&self, strategy: CompactionStrategy) -> BoxFuture<'static, Result<CompactionReport>> {
+        match self.once.get() {
+            Some(store) => store.compact(strategy),
+            None => self.none.compact(strategy),
+        }
+    }
+
+    fn read_region_bytes(&self, region: RegionId) -> BoxFuture<'static, Result<Vec<u8>>> {
+        match self.once.get() {
+            Some(store) => store.read_region_bytes(region),
+            None => self.none.read_region_bytes(region),
+        }
+    }
+
+    fn apply_region_bytes(&self, region: RegionId, bytes: Vec<u8>) -> BoxFuture<'static, Result<()>> {
+        match self.once.get() {
+            Some(store) => store.apply_region_bytes(region, bytes),
+            None => self.none.apply_region_bytes(region, bytes),
+        }
+    }
+
+    fn shrink(&self, capacity: usize) -> BoxFuture<'static, Result<()>> {
+        match self.once.get() {
+            Some(store) => store.shrink(capacity),
+            None => self.none.shrink(capacity),
+        }
+    }
+
+    fn rebuild_region_filters(&self) -> BoxFuture<'static, Result<usize>> {
+        match self.once.get() {
+            Some(store) => store.rebuild_region_filters(),
+            None => self.none.rebuild_region_filters(),
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use std::path::PathBuf;
+    use std::{collections::HashMap, path::PathBuf, time::Duration};
 
     use foyer_memory::FifoConfig;
 
     use super::*;
     use crate::{
-        device::fs::FsDeviceConfig,
+        buffer::{FlushErrorPolicy, PaddingPolicy},
+        catalog::{CatalogRetryPolicy, WriteConflictPolicy},
+        device::{
+            executor::IoExecutor,
+            fs::{FsDeviceConfig, RegionOpenErrorPolicy},
+        },
+        flusher::{ChannelDropPolicy, QueueFullPolicy},
+        generic::ChecksumAlgorithm,
+        region::UnknownRegionVersionPolicy,
         storage::StorageExt,
         store::{FsStore, FsStoreConfig},
     };
@@ -261,6 +501,12 @@ mod tests {
                 file_size: 4 * MB,
                 align: 4096,
                 io_size: 4096 * KB,
+                io_executor: IoExecutor::default(),
+                on_region_open_error: RegionOpenErrorPolicy::default(),
+                write_io_size: None,
+                read_io_size: None,
+                extra_dirs: vec![],
+                direct_io: true,
             },
             catalog_shards: 1,
             admissions: vec![],
@@ -270,9 +516,52 @@ mod tests {
             recover_concurrency: 2,
             clean_region_threshold: 1,
             compression: crate::compress::Compression::None,
+            checksum: true,
+            recent_inserts_capacity: 0,
+            max_key_size: None,
+            region_compression: None,
+            intra_block_align: None,
+            catalog_snapshot_path: None,
+            reclaim_grace_period: Duration::ZERO,
+            compression_observer: None,
+            unknown_region_version_policy: UnknownRegionVersionPolicy::default(),
+            block_compression: None,
+            background_cpu_cores: None,
+            metrics_recorder: None,
+            write_conflict_policy: WriteConflictPolicy::default(),
+            catalog_ops_limit: None,
+            first_entry_offset: None,
+            verify_region_magic_on_read: false,
+            self_test_on_open: false,
+            scrub_throughput_limit: None,
+            write_coalescing_interval: None,
+            max_writing_regions: None,
+            size_padding: PaddingPolicy::default(),
+            store_key_hash_only: false,
+            zstd_level: 0,
+            max_concurrent_decompressions: None,
+            checksum_algorithm: ChecksumAlgorithm::default(),
+            key_prefix: None,
+            flush_error_policy: FlushErrorPolicy::default(),
+            admission_observer: None,
+            max_region_age: None,
+            channel_drop_policy: ChannelDropPolicy::default(),
+            prefix_compressions: HashMap::new(),
+            io_bytes_in_flight_limit: None,
+            flusher_queue_capacity: None,
+            queue_full_policy: QueueFullPolicy::default(),
+            flush_alignment: None,
+            adaptive_compression: false,
+            scan_read_ahead: 2,
+            verify_checksum_on_recover: false,
+            catalog_backend: None,
+            catalog_retry_policy: CatalogRetryPolicy::default(),
         };
 
-        let (store, handle) = Lazy::<u64, u64, FsStore<_, _>>::with_handle(config);
+        let (store, handle) = Lazy::<u64, u64, FsStore<_, _>>::with_handle(LazyStoreConfig {
+            store_config: config,
+            recovery_read_policy: RecoveryReadPolicy::Miss,
+        });
 
         assert!(store.insert(100, 100).await.unwrap().is_none());
 
@@ -293,6 +582,12 @@ mod tests {
                 file_size: 4 * MB,
                 align: 4096,
                 io_size: 4096 * KB,
+                io_executor: IoExecutor::default(),
+                on_region_open_error: RegionOpenErrorPolicy::default(),
+                write_io_size: None,
+                read_io_size: None,
+                extra_dirs: vec![],
+                direct_io: true,
             },
             catalog_shards: 1,
             admissions: vec![],
@@ -302,15 +597,161 @@ mod tests {
             recover_concurrency: 2,
             clean_region_threshold: 1,
             compression: crate::compress::Compression::None,
+            checksum: true,
+            recent_inserts_capacity: 0,
+            max_key_size: None,
+            region_compression: None,
+            intra_block_align: None,
+            catalog_snapshot_path: None,
+            reclaim_grace_period: Duration::ZERO,
+            compression_observer: None,
+            unknown_region_version_policy: UnknownRegionVersionPolicy::default(),
+            block_compression: None,
+            background_cpu_cores: None,
+            metrics_recorder: None,
+            write_conflict_policy: WriteConflictPolicy::default(),
+            catalog_ops_limit: None,
+            first_entry_offset: None,
+            verify_region_magic_on_read: false,
+            self_test_on_open: false,
+            scrub_throughput_limit: None,
+            write_coalescing_interval: None,
+            max_writing_regions: None,
+            size_padding: PaddingPolicy::default(),
+            store_key_hash_only: false,
+            zstd_level: 0,
+            max_concurrent_decompressions: None,
+            checksum_algorithm: ChecksumAlgorithm::default(),
+            key_prefix: None,
+            flush_error_policy: FlushErrorPolicy::default(),
+            admission_observer: None,
+            max_region_age: None,
+            channel_drop_policy: ChannelDropPolicy::default(),
+            prefix_compressions: HashMap::new(),
+            io_bytes_in_flight_limit: None,
+            flusher_queue_capacity: None,
+            queue_full_policy: QueueFullPolicy::default(),
+            flush_alignment: None,
+            adaptive_compression: false,
+            scan_read_ahead: 2,
+            verify_checksum_on_recover: false,
+            catalog_backend: None,
+            catalog_retry_policy: CatalogRetryPolicy::default(),
+        };
+
+        let (store, handle) = Lazy::<u64, u64, FsStore<_, _>>::with_handle(LazyStoreConfig {
+            store_config: config,
+            recovery_read_policy: RecoveryReadPolicy::Miss,
+        });
+
+        assert!(store.get(&100).await.unwrap().is_none());
+
+        handle.await.unwrap().unwrap();
+
+        assert_eq!(store.get(&100).await.unwrap().unwrap().value(), &100);
+        store.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_recovery_read_policy() {
+        let tempdir = tempfile::tempdir().unwrap();
+
+        let config = |recovery_read_policy| LazyStoreConfig {
+            store_config: FsStoreConfig {
+                name: "".to_string(),
+                eviction_config: FifoConfig {}.into(),
+                device_config: FsDeviceConfig {
+                    dir: PathBuf::from(tempdir.path()),
+                    capacity: 16 * MB,
+                    file_size: 4 * MB,
+                    align: 4096,
+                    io_size: 4096 * KB,
+                    io_executor: IoExecutor::default(),
+                    on_region_open_error: RegionOpenErrorPolicy::default(),
+                    write_io_size: None,
+                    read_io_size: None,
+                    extra_dirs: vec![],
+                    direct_io: true,
+                },
+                catalog_shards: 1,
+                admissions: vec![],
+                reinsertions: vec![],
+                flushers: 1,
+                reclaimers: 1,
+                recover_concurrency: 2,
+                clean_region_threshold: 1,
+                compression: crate::compress::Compression::None,
+                checksum: true,
+                recent_inserts_capacity: 0,
+                max_key_size: None,
+                region_compression: None,
+                intra_block_align: None,
+                catalog_snapshot_path: None,
+                reclaim_grace_period: Duration::ZERO,
+                compression_observer: None,
+                unknown_region_version_policy: UnknownRegionVersionPolicy::default(),
+                block_compression: None,
+                background_cpu_cores: None,
+                metrics_recorder: None,
+                write_conflict_policy: WriteConflictPolicy::default(),
+                catalog_ops_limit: None,
+                first_entry_offset: None,
+                verify_region_magic_on_read: false,
+                self_test_on_open: false,
+                scrub_throughput_limit: None,
+                write_coalescing_interval: None,
+                max_writing_regions: None,
+                size_padding: PaddingPolicy::default(),
+                store_key_hash_only: false,
+                zstd_level: 0,
+                max_concurrent_decompressions: None,
+                checksum_algorithm: ChecksumAlgorithm::default(),
+                key_prefix: None,
+                flush_error_policy: FlushErrorPolicy::default(),
+                admission_observer: None,
+                max_region_age: None,
+                channel_drop_policy: ChannelDropPolicy::default(),
+                prefix_compressions: HashMap::new(),
+                io_bytes_in_flight_limit: None,
+                flusher_queue_capacity: None,
+                queue_full_policy: QueueFullPolicy::default(),
+                flush_alignment: None,
+                adaptive_compression: false,
+                scan_read_ahead: 2,
+                verify_checksum_on_recover: false,
+                catalog_backend: None,
+                catalog_retry_policy: CatalogRetryPolicy::default(),
+            },
+            recovery_read_policy,
         };
 
-        let (store, handle) = Lazy::<u64, u64, FsStore<_, _>>::with_handle(config);
+        let (store, handle) = Lazy::<u64, u64, FsStore<_, _>>::with_handle(config(RecoveryReadPolicy::Miss));
+        handle.await.unwrap().unwrap();
+        assert!(store.insert(100, 100).await.unwrap().is_some());
+        store.close().await.unwrap();
+        drop(store);
+
+        // The recovery task doesn't run before the test task yields, so the reads below are issued during recovery.
 
+        let (store, handle) = Lazy::<u64, u64, FsStore<_, _>>::with_handle(config(RecoveryReadPolicy::Miss));
         assert!(store.get(&100).await.unwrap().is_none());
+        handle.await.unwrap().unwrap();
+        assert_eq!(store.get(&100).await.unwrap().unwrap().value(), &100);
+        store.close().await.unwrap();
+        drop(store);
 
+        let (store, handle) = Lazy::<u64, u64, FsStore<_, _>>::with_handle(config(RecoveryReadPolicy::Recovering));
+        assert!(matches!(store.get(&100).await, Err(Error::Recovering)));
         handle.await.unwrap().unwrap();
+        assert_eq!(store.get(&100).await.unwrap().unwrap().value(), &100);
+        store.close().await.unwrap();
+        drop(store);
 
+        let (store, handle) = Lazy::<u64, u64, FsStore<_, _>>::with_handle(config(RecoveryReadPolicy::Wait));
+        assert!(!store.is_ready());
         assert_eq!(store.get(&100).await.unwrap().unwrap().value(), &100);
+        assert!(store.is_ready());
+        handle.await.unwrap().unwrap();
         store.close().await.unwrap();
     }
 }
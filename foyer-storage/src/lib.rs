@@ -13,22 +13,27 @@
 //  limitations under the License.
 
 mod admission;
+mod block;
 mod buffer;
 mod catalog;
 mod compress;
+mod cpu_budget;
 mod device;
 mod error;
 mod flusher;
 mod generic;
+mod io_limit;
 mod judge;
 mod lazy;
 mod metrics;
+mod migrate;
 mod none;
 mod reclaimer;
 mod region;
 mod region_manager;
 mod reinsertion;
 mod runtime;
+mod snapshot;
 mod storage;
 mod store;
 
@@ -12,16 +12,38 @@
 //  See the License for the specific language governing permissions and
 //  limitations under the License.
 
-use std::sync::OnceLock;
+//! Metrics of the storage engine.
+//!
+//! Metrics are recorded through a [`MetricsRecorder`], so they can be exported to different backends, e.g. the
+//! `metrics` crate facade or OpenTelemetry. With the `prometheus` feature, metrics are recorded to the Prometheus
+//! registry set by `set_metrics_registry` with `PrometheusMetricsRecorder` by default, and the metrics of a store can
+//! be registered to another registry with `Metrics::register`. Otherwise, metrics are only kept by the store. Either
+//! way, the current values can be serialized to JSON with `Metrics::to_json`.
 
+use std::{
+    collections::HashMap,
+    fmt::{Debug, Write},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, OnceLock,
+    },
+    time::Instant,
+};
+
+#[cfg(feature = "prometheus")]
+use itertools::Itertools;
+use parking_lot::Mutex;
+#[cfg(feature = "prometheus")]
 use prometheus::{
-    core::{AtomicU64, GenericGauge, GenericGaugeVec},
-    exponential_buckets, opts, register_histogram_vec_with_registry, register_int_counter_vec_with_registry,
-    register_int_gauge_vec_with_registry, Histogram, HistogramVec, IntCounter, IntCounterVec, IntGaugeVec, Registry,
+    core::{AtomicU64 as PrometheusAtomicU64, Collector, Desc, GenericGaugeVec},
+    opts, proto, register_counter_vec_with_registry, register_histogram_vec_with_registry,
+    register_int_counter_vec_with_registry, register_int_gauge_vec_with_registry, CounterVec, HistogramVec,
+    IntCounterVec, IntGaugeVec, Registry,
 };
-type UintGaugeVec = GenericGaugeVec<AtomicU64>;
-type UintGauge = GenericGauge<AtomicU64>;
+#[cfg(feature = "prometheus")]
+type UintGaugeVec = GenericGaugeVec<PrometheusAtomicU64>;
 
+#[cfg(feature = "prometheus")]
 macro_rules! register_gauge_vec {
     ($TYPE:ident, $OPTS:expr, $LABELS_NAMES:expr, $REGISTRY:expr $(,)?) => {{
         let gauge_vec = $TYPE::new($OPTS, $LABELS_NAMES).unwrap();
@@ -29,6 +51,7 @@ macro_rules! register_gauge_vec {
     }};
 }
 
+#[cfg(feature = "prometheus")]
 macro_rules! register_uint_gauge_vec_with_registry {
     ($OPTS:expr, $LABELS_NAMES:expr, $REGISTRY:expr $(,)?) => {{
         register_gauge_vec!(UintGaugeVec, $OPTS, $LABELS_NAMES, $REGISTRY)
@@ -39,6 +62,84 @@ macro_rules! register_uint_gauge_vec_with_registry {
     }};
 }
 
+pub const OP_DURATION: &str = "foyer_storage_op_duration";
+pub const SLOW_OP_DURATION: &str = "foyer_storage_slow_op_duration";
+pub const OP_BYTES: &str = "foyer_storage_op_bytes";
+pub const TOTAL_BYTES: &str = "foyer_storage_total_bytes";
+pub const ENTRY_BYTES: &str = "foyer_storage_entry_bytes";
+pub const INNER_OP_DURATION: &str = "foyer_storage_inner_op_duration";
+pub const BLOCKING_OPS_INFLIGHT: &str = "foyer_storage_blocking_ops_inflight";
+pub const BACKGROUND_CPU_SECONDS: &str = "foyer_storage_background_cpu_seconds";
+pub const CATALOG_OPS: &str = "foyer_storage_catalog_ops";
+pub const DECOMPRESSION_QUEUE_DEPTH: &str = "foyer_storage_decompression_queue_depth";
+pub const FLUSHER_QUEUE_DEPTH: &str = "foyer_storage_flusher_queue_depth";
+pub const RECOVER_ENTRIES: &str = "foyer_storage_recover_entries";
+
+/// Help text of the metric.
+#[cfg(feature = "prometheus")]
+fn help(name: &str) -> &'static str {
+    match name {
+        OP_DURATION => "foyer storage op duration",
+        SLOW_OP_DURATION => "foyer storage slow op duration",
+        OP_BYTES => "foyer storage op bytes",
+        TOTAL_BYTES => "foyer storage total bytes",
+        ENTRY_BYTES => "foyer storage entry bytes",
+        INNER_OP_DURATION => "foyer storage inner op duration",
+        BLOCKING_OPS_INFLIGHT => "foyer storage in-flight blocking ops",
+        BACKGROUND_CPU_SECONDS => "foyer storage cpu seconds consumed by background tasks",
+        CATALOG_OPS => "foyer storage catalog mutations",
+        DECOMPRESSION_QUEUE_DEPTH => "foyer storage gets waiting for decompression",
+        FLUSHER_QUEUE_DEPTH => "foyer storage entries queued to the flushers",
+        RECOVER_ENTRIES => "foyer storage entries scanned on recovery",
+        _ => "",
+    }
+}
+
+/// Upper bounds of the buckets of the histogram.
+fn buckets(name: &str) -> Vec<f64> {
+    match name {
+        OP_DURATION => vec![0.0001, 0.001, 0.005, 0.01, 0.02, 0.05, 0.075, 0.1, 0.25, 0.5, 0.75, 1.0],
+        SLOW_OP_DURATION => vec![0.01, 0.1, 0.5, 0.77, 1.0, 2.5, 5.0, 7.5, 10.0],
+        // Exponential buckets from 1 with factor 2.
+        ENTRY_BYTES => (0..32).map(|i| 2f64.powi(i)).collect(),
+        INNER_OP_DURATION => vec![
+            0.0001, 0.01, 0.02, 0.05, 0.075, 0.1, 0.25, 0.5, 0.75, 1.0, 2.5, 5.0, 10.0,
+        ],
+        _ => vec![],
+    }
+}
+
+/// Identity of a metric, which is the metric name with the label pairs.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct MetricKey {
+    pub name: &'static str,
+    pub labels: Vec<(&'static str, String)>,
+}
+
+impl MetricKey {
+    pub fn new(name: &'static str, labels: &[(&'static str, &str)]) -> Self {
+        Self {
+            name,
+            labels: labels.iter().map(|(k, v)| (*k, v.to_string())).collect(),
+        }
+    }
+}
+
+/// Backend of the metrics.
+///
+/// The storage engine keeps the values of counters and gauges by itself, so a recorder only needs to export them.
+pub trait MetricsRecorder: Send + Sync + Debug + 'static {
+    /// Increase the counter by `value`.
+    fn increment_counter(&self, key: &MetricKey, value: f64);
+
+    /// Record `value` to the histogram.
+    fn observe_histogram(&self, key: &MetricKey, value: f64);
+
+    /// Set the gauge to `value`.
+    fn set_gauge(&self, key: &MetricKey, value: f64);
+}
+
+#[cfg(feature = "prometheus")]
 static REGISTRY: OnceLock<Registry> = OnceLock::new();
 
 /// Set metrics registry for `foyer`.
@@ -46,24 +147,69 @@ static REGISTRY: OnceLock<Registry> = OnceLock::new();
 /// Metrics registry must be set before `open`.
 ///
 /// Return `true` if set succeeds.
+#[cfg(feature = "prometheus")]
 pub fn set_metrics_registry(registry: Registry) -> bool {
     REGISTRY.set(registry).is_ok()
 }
 
+#[cfg(feature = "prometheus")]
 pub fn get_metrics_registry() -> &'static Registry {
     REGISTRY.get_or_init(|| prometheus::default_registry().clone())
 }
 
-// TODO(MrCroxx): Use `LazyLock` after `lazy_cell` is stable.
-// /// Multiple foyer instance will share the same global metrics with different label `foyer` name.
-// pub static METRICS: LazyLock<GlobalMetrics> = LazyLock::new(GlobalMetrics::default);
+static RECORDER: OnceLock<Arc<dyn MetricsRecorder>> = OnceLock::new();
 
-lazy_static::lazy_static! {
-    pub static ref METRICS: GlobalMetrics = GlobalMetrics::default();
+/// Set the default metrics recorder for `foyer`, which is used if no recorder is given in the store config.
+///
+/// Metrics recorder must be set before `open`.
+///
+/// Return `true` if set succeeds.
+pub fn set_metrics_recorder(recorder: Arc<dyn MetricsRecorder>) -> bool {
+    RECORDER.set(recorder).is_ok()
 }
 
+/// Get the default metrics recorder if not set, which is a `PrometheusMetricsRecorder` on the registry of
+/// `get_metrics_registry` with the `prometheus` feature, or a [`NoopMetricsRecorder`] without.
+pub fn get_metrics_recorder() -> &'static Arc<dyn MetricsRecorder> {
+    #[cfg(feature = "prometheus")]
+    let recorder = || Arc::new(PrometheusMetricsRecorder::new(get_metrics_registry())) as Arc<dyn MetricsRecorder>;
+    #[cfg(not(feature = "prometheus"))]
+    let recorder = || Arc::new(NoopMetricsRecorder) as Arc<dyn MetricsRecorder>;
+    RECORDER.get_or_init(recorder)
+}
+
+/// Gauge of the in-flight blocking ops of the executors named `executor`, recorded by the default metrics recorder.
+///
+/// Executors with the same name share the gauge.
+pub fn blocking_ops_inflight(executor: &str) -> Gauge {
+    static GAUGES: OnceLock<Mutex<HashMap<String, Gauge>>> = OnceLock::new();
+
+    GAUGES
+        .get_or_init(|| Mutex::new(HashMap::new()))
+        .lock()
+        .entry(executor.to_string())
+        .or_insert_with(|| Gauge::new(get_metrics_recorder(), BLOCKING_OPS_INFLIGHT, &[("executor", executor)]))
+        .clone()
+}
+
+/// Metrics recorder that exports nothing, the metrics are only kept by the store.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct NoopMetricsRecorder;
+
+impl MetricsRecorder for NoopMetricsRecorder {
+    fn increment_counter(&self, _: &MetricKey, _: f64) {}
+
+    fn observe_histogram(&self, _: &MetricKey, _: f64) {}
+
+    fn set_gauge(&self, _: &MetricKey, _: f64) {}
+}
+
+/// Metrics recorder that records metrics to a Prometheus registry.
+///
+/// Multiple foyer instance will share the same metrics with different label `foyer` name.
+#[cfg(feature = "prometheus")]
 #[derive(Debug)]
-pub struct GlobalMetrics {
+pub struct PrometheusMetricsRecorder {
     op_duration: HistogramVec,
     slow_op_duration: HistogramVec,
     op_bytes: IntCounterVec,
@@ -73,64 +219,61 @@ pub struct GlobalMetrics {
 
     inner_op_duration: HistogramVec,
     _inner_bytes: IntGaugeVec,
-}
 
-impl Default for GlobalMetrics {
-    fn default() -> Self {
-        Self::new(get_metrics_registry())
-    }
+    blocking_ops_inflight: IntGaugeVec,
+
+    background_cpu_seconds: CounterVec,
+
+    catalog_ops: IntCounterVec,
+
+    decompression_queue_depth: UintGaugeVec,
+    flusher_queue_depth: UintGaugeVec,
+
+    recover_entries: IntCounterVec,
 }
 
-impl GlobalMetrics {
+#[cfg(feature = "prometheus")]
+impl PrometheusMetricsRecorder {
     pub fn new(registry: &Registry) -> Self {
         let op_duration = register_histogram_vec_with_registry!(
-            "foyer_storage_op_duration",
-            "foyer storage op duration",
+            OP_DURATION,
+            help(OP_DURATION),
             &["foyer", "op", "extra"],
-            vec![0.0001, 0.001, 0.005, 0.01, 0.02, 0.05, 0.075, 0.1, 0.25, 0.5, 0.75, 1.0],
+            buckets(OP_DURATION),
             registry,
         )
         .unwrap();
 
         let slow_op_duration = register_histogram_vec_with_registry!(
-            "foyer_storage_slow_op_duration",
-            "foyer storage slow op duration",
+            SLOW_OP_DURATION,
+            help(SLOW_OP_DURATION),
             &["foyer", "op", "extra"],
-            vec![0.01, 0.1, 0.5, 0.77, 1.0, 2.5, 5.0, 7.5, 10.0],
+            buckets(SLOW_OP_DURATION),
             registry,
         )
         .unwrap();
 
-        let op_bytes = register_int_counter_vec_with_registry!(
-            "foyer_storage_op_bytes",
-            "foyer storage op bytes",
-            &["foyer", "op", "extra"],
-            registry,
-        )
-        .unwrap();
+        let op_bytes =
+            register_int_counter_vec_with_registry!(OP_BYTES, help(OP_BYTES), &["foyer", "op", "extra"], registry,)
+                .unwrap();
 
-        let total_bytes = register_uint_gauge_vec_with_registry!(
-            "foyer_storage_total_bytes",
-            "foyer storage total bytes",
-            &["foyer"],
-            registry,
-        )
-        .unwrap();
+        let total_bytes =
+            register_uint_gauge_vec_with_registry!(TOTAL_BYTES, help(TOTAL_BYTES), &["foyer"], registry,).unwrap();
 
         let entry_bytes = register_histogram_vec_with_registry!(
-            "foyer_storage_entry_bytes",
-            "foyer storage entry bytes",
+            ENTRY_BYTES,
+            help(ENTRY_BYTES),
             &["foyer", "op", "extra"],
-            exponential_buckets(1.0, 2.0, 32).unwrap(),
+            buckets(ENTRY_BYTES),
             registry,
         )
         .unwrap();
 
         let inner_op_duration = register_histogram_vec_with_registry!(
-            "foyer_storage_inner_op_duration",
-            "foyer storage inner op duration",
+            INNER_OP_DURATION,
+            help(INNER_OP_DURATION),
             &["foyer", "op", "extra"],
-            vec![0.0001, 0.01, 0.02, 0.05, 0.075, 0.1, 0.25, 0.5, 0.75, 1.0, 2.5, 5.0, 10.0],
+            buckets(INNER_OP_DURATION),
             registry,
         )
         .unwrap();
@@ -143,6 +286,49 @@ impl GlobalMetrics {
         )
         .unwrap();
 
+        let blocking_ops_inflight = register_int_gauge_vec_with_registry!(
+            BLOCKING_OPS_INFLIGHT,
+            help(BLOCKING_OPS_INFLIGHT),
+            &["executor"],
+            registry,
+        )
+        .unwrap();
+
+        let background_cpu_seconds = register_counter_vec_with_registry!(
+            BACKGROUND_CPU_SECONDS,
+            help(BACKGROUND_CPU_SECONDS),
+            &["foyer", "task"],
+            registry,
+        )
+        .unwrap();
+
+        let catalog_ops =
+            register_int_counter_vec_with_registry!(CATALOG_OPS, help(CATALOG_OPS), &["foyer", "op"], registry,)
+                .unwrap();
+
+        let decompression_queue_depth = register_uint_gauge_vec_with_registry!(
+            DECOMPRESSION_QUEUE_DEPTH,
+            help(DECOMPRESSION_QUEUE_DEPTH),
+            &["foyer"],
+            registry,
+        )
+        .unwrap();
+        let flusher_queue_depth = register_uint_gauge_vec_with_registry!(
+            FLUSHER_QUEUE_DEPTH,
+            help(FLUSHER_QUEUE_DEPTH),
+            &["foyer"],
+            registry,
+        )
+        .unwrap();
+
+        let recover_entries = register_int_counter_vec_with_registry!(
+            RECOVER_ENTRIES,
+            help(RECOVER_ENTRIES),
+            &["foyer", "result"],
+            registry,
+        )
+        .unwrap();
+
         Self {
             op_duration,
             slow_op_duration,
@@ -153,11 +339,238 @@ impl GlobalMetrics {
 
             inner_op_duration,
             _inner_bytes: inner_bytes,
+
+            blocking_ops_inflight,
+
+            background_cpu_seconds,
+
+            catalog_ops,
+
+            decompression_queue_depth,
+            flusher_queue_depth,
+
+            recover_entries,
+        }
+    }
+
+    /// Label values in the order of the label names.
+    fn with_label_values<R>(key: &MetricKey, f: impl FnOnce(&[&str]) -> R) -> R {
+        const MAX_LABELS: usize = 3;
+        assert!(key.labels.len() <= MAX_LABELS);
+
+        let mut values = [""; MAX_LABELS];
+        for (value, (_, v)) in values.iter_mut().zip(key.labels.iter()) {
+            *value = v;
+        }
+        f(&values[..key.labels.len()])
+    }
+}
+
+#[cfg(feature = "prometheus")]
+impl MetricsRecorder for PrometheusMetricsRecorder {
+    fn increment_counter(&self, key: &MetricKey, value: f64) {
+        Self::with_label_values(key, |values| match key.name {
+            OP_BYTES => self.op_bytes.with_label_values(values).inc_by(value as u64),
+            BACKGROUND_CPU_SECONDS => self.background_cpu_seconds.with_label_values(values).inc_by(value),
+            CATALOG_OPS => self.catalog_ops.with_label_values(values).inc_by(value as u64),
+            RECOVER_ENTRIES => self.recover_entries.with_label_values(values).inc_by(value as u64),
+            _ => {}
+        })
+    }
+
+    fn observe_histogram(&self, key: &MetricKey, value: f64) {
+        Self::with_label_values(key, |values| match key.name {
+            OP_DURATION => self.op_duration.with_label_values(values).observe(value),
+            SLOW_OP_DURATION => self.slow_op_duration.with_label_values(values).observe(value),
+            ENTRY_BYTES => self.entry_bytes.with_label_values(values).observe(value),
+            INNER_OP_DURATION => self.inner_op_duration.with_label_values(values).observe(value),
+            _ => {}
+        })
+    }
+
+    fn set_gauge(&self, key: &MetricKey, value: f64) {
+        Self::with_label_values(key, |values| match key.name {
+            TOTAL_BYTES => self.total_bytes.with_label_values(values).set(value as u64),
+            BLOCKING_OPS_INFLIGHT => self.blocking_ops_inflight.with_label_values(values).set(value as i64),
+            DECOMPRESSION_QUEUE_DEPTH => self
+                .decompression_queue_depth
+                .with_label_values(values)
+                .set(value as u64),
+            FLUSHER_QUEUE_DEPTH => self.flusher_queue_depth.with_label_values(values).set(value as u64),
+            _ => {}
+        })
+    }
+}
+
+#[derive(Debug)]
+struct MetricInner {
+    recorder: Arc<dyn MetricsRecorder>,
+    key: MetricKey,
+    /// Current value of counters and gauges, in `f64` bits for float counters.
+    value: AtomicU64,
+}
+
+impl MetricInner {
+    fn new(recorder: &Arc<dyn MetricsRecorder>, name: &'static str, labels: &[(&'static str, &str)]) -> Arc<Self> {
+        Arc::new(Self {
+            recorder: recorder.clone(),
+            key: MetricKey::new(name, labels),
+            value: AtomicU64::new(0),
+        })
+    }
+}
+
+/// Integer counter.
+#[derive(Debug, Clone)]
+pub struct Counter(Arc<MetricInner>);
+
+impl Counter {
+    pub fn new(recorder: &Arc<dyn MetricsRecorder>, name: &'static str, labels: &[(&'static str, &str)]) -> Self {
+        Self(MetricInner::new(recorder, name, labels))
+    }
+
+    pub fn inc_by(&self, v: u64) {
+        self.0.value.fetch_add(v, Ordering::Relaxed);
+        self.0.recorder.increment_counter(&self.0.key, v as f64);
+    }
+
+    pub fn get(&self) -> u64 {
+        self.0.value.load(Ordering::Relaxed)
+    }
+}
+
+/// Float counter.
+#[derive(Debug, Clone)]
+pub struct FloatCounter(Arc<MetricInner>);
+
+impl FloatCounter {
+    pub fn new(recorder: &Arc<dyn MetricsRecorder>, name: &'static str, labels: &[(&'static str, &str)]) -> Self {
+        Self(MetricInner::new(recorder, name, labels))
+    }
+
+    pub fn inc_by(&self, v: f64) {
+        let _ = self.0.value.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |bits| {
+            Some((f64::from_bits(bits) + v).to_bits())
+        });
+        self.0.recorder.increment_counter(&self.0.key, v);
+    }
+
+    pub fn get(&self) -> f64 {
+        f64::from_bits(self.0.value.load(Ordering::Relaxed))
+    }
+}
+
+/// Unsigned integer gauge.
+#[derive(Debug, Clone)]
+pub struct Gauge(Arc<MetricInner>);
+
+impl Gauge {
+    pub fn new(recorder: &Arc<dyn MetricsRecorder>, name: &'static str, labels: &[(&'static str, &str)]) -> Self {
+        Self(MetricInner::new(recorder, name, labels))
+    }
+
+    pub fn set(&self, v: u64) {
+        self.0.value.store(v, Ordering::Relaxed);
+        self.0.recorder.set_gauge(&self.0.key, v as f64);
+    }
+
+    pub fn add(&self, v: u64) {
+        let value = self.0.value.fetch_add(v, Ordering::Relaxed).wrapping_add(v);
+        self.0.recorder.set_gauge(&self.0.key, value as f64);
+    }
+
+    pub fn sub(&self, v: u64) {
+        let value = self.0.value.fetch_sub(v, Ordering::Relaxed).wrapping_sub(v);
+        self.0.recorder.set_gauge(&self.0.key, value as f64);
+    }
+
+    pub fn inc(&self) {
+        self.add(1)
+    }
+
+    pub fn dec(&self) {
+        self.sub(1)
+    }
+
+    pub fn get(&self) -> u64 {
+        self.0.value.load(Ordering::Relaxed)
+    }
+}
+
+#[derive(Debug)]
+struct HistogramState {
+    /// Upper bounds of the buckets.
+    bounds: Vec<f64>,
+    /// Count of the observations of each bucket, not cumulative, the last one is the `+Inf` bucket.
+    counts: Vec<AtomicU64>,
+    /// Sum of the observations, in `f64` bits.
+    sum: AtomicU64,
+}
+
+#[derive(Debug, Clone)]
+pub struct Histogram {
+    inner: Arc<MetricInner>,
+    state: Arc<HistogramState>,
+}
+
+impl Histogram {
+    pub fn new(recorder: &Arc<dyn MetricsRecorder>, name: &'static str, labels: &[(&'static str, &str)]) -> Self {
+        let bounds = buckets(name);
+        let counts = (0..=bounds.len()).map(|_| AtomicU64::new(0)).collect();
+        Self {
+            inner: MetricInner::new(recorder, name, labels),
+            state: Arc::new(HistogramState {
+                bounds,
+                counts,
+                sum: AtomicU64::new(0),
+            }),
         }
     }
 
-    pub fn foyer(&self, name: &str) -> Metrics {
-        Metrics::new(self, name)
+    pub fn observe(&self, v: f64) {
+        let bucket = self.state.bounds.partition_point(|bound| *bound < v);
+        self.state.counts[bucket].fetch_add(1, Ordering::Relaxed);
+        let _ = self
+            .state
+            .sum
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |bits| {
+                Some((f64::from_bits(bits) + v).to_bits())
+            });
+        self.inner.recorder.observe_histogram(&self.inner.key, v);
+    }
+
+    /// Count of the observations.
+    pub fn count(&self) -> u64 {
+        self.state
+            .counts
+            .iter()
+            .map(|count| count.load(Ordering::Relaxed))
+            .sum()
+    }
+
+    /// Sum of the observations.
+    pub fn sum(&self) -> f64 {
+        f64::from_bits(self.state.sum.load(Ordering::Relaxed))
+    }
+
+    /// Start a timer that records the elapsed seconds on drop.
+    pub fn start_timer(&self) -> HistogramTimer {
+        HistogramTimer {
+            histogram: self.clone(),
+            start: Instant::now(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct HistogramTimer {
+    histogram: Histogram,
+    start: Instant,
+}
+
+impl Drop for HistogramTimer {
+    fn drop(&mut self) {
+        self.histogram.observe(self.start.elapsed().as_secs_f64());
     }
 }
 
@@ -171,13 +584,14 @@ pub struct Metrics {
     pub op_duration_remove: Histogram,
     pub slow_op_duration_reclaim: Histogram,
 
-    pub op_bytes_insert: IntCounter,
-    pub op_bytes_get: IntCounter,
-    pub op_bytes_flush: IntCounter,
-    pub op_bytes_reclaim: IntCounter,
-    pub op_bytes_reinsert: IntCounter,
+    pub op_bytes_insert: Counter,
+    pub op_bytes_get: Counter,
+    pub op_bytes_flush: Counter,
+    pub op_bytes_reclaim: Counter,
+    pub op_bytes_reinsert: Counter,
+    pub op_bytes_recover: Counter,
 
-    pub total_bytes: UintGauge,
+    pub total_bytes: Gauge,
 
     pub insert_entry_bytes: Histogram,
 
@@ -187,75 +601,458 @@ pub struct Metrics {
     pub inner_op_duration_update_catalog: Histogram,
     pub inner_op_duration_entry_flush: Histogram,
     pub inner_op_duration_flusher_handle: Histogram,
+    pub inner_op_duration_throttle_catalog: Histogram,
+
+    pub flush_queue_lag: Histogram,
+
+    pub background_cpu_seconds_flush: FloatCounter,
+    pub background_cpu_seconds_reclaim: FloatCounter,
+
+    pub catalog_ops_insert: Counter,
+    pub catalog_ops_remove: Counter,
+    pub catalog_ops_expire: Counter,
+    pub catalog_ops_persist_failed: Counter,
+
+    pub decompression_queue_depth: Gauge,
+    pub flusher_queue_depth: Gauge,
+
+    pub recover_entries_recovered: Counter,
+    pub recover_entries_corrupted: Counter,
+    pub recover_entries_checksum_mismatch: Counter,
 }
 
 impl Metrics {
-    pub fn new(global: &GlobalMetrics, foyer: &str) -> Self {
-        let op_duration_insert_inserted = global.op_duration.with_label_values(&[foyer, "insert", "inserted"]);
-        let op_duration_insert_filtered = global.op_duration.with_label_values(&[foyer, "insert", "filtered"]);
-        let op_duration_insert_dropped = global.op_duration.with_label_values(&[foyer, "insert", "dropped"]);
-        let op_duration_get_hit = global.op_duration.with_label_values(&[foyer, "get", "hit"]);
-        let op_duration_get_miss = global.op_duration.with_label_values(&[foyer, "get", "miss"]);
-        let op_duration_remove = global.op_duration.with_label_values(&[foyer, "remove", ""]);
-        let slow_op_duration_reclaim = global.slow_op_duration.with_label_values(&[foyer, "reclaim", ""]);
-
-        let op_bytes_insert = global.op_bytes.with_label_values(&[foyer, "insert", ""]);
-        let op_bytes_get = global.op_bytes.with_label_values(&[foyer, "get", ""]);
-        let op_bytes_flush = global.op_bytes.with_label_values(&[foyer, "flush", ""]);
-        let op_bytes_reclaim = global.op_bytes.with_label_values(&[foyer, "reclaim", ""]);
-        let op_bytes_reinsert = global.op_bytes.with_label_values(&[foyer, "reinsert", ""]);
-
-        let total_bytes = global.total_bytes.with_label_values(&[foyer]);
-
-        let insert_entry_bytes = global.entry_bytes.with_label_values(&[foyer, "insert", ""]);
-
-        let inner_op_duration_acquire_clean_region =
-            global
-                .inner_op_duration
-                .with_label_values(&[foyer, "acquire_clean_region", ""]);
-        let inner_op_duration_acquire_clean_buffer =
-            global
-                .inner_op_duration
-                .with_label_values(&[foyer, "acquire_clean_buffer", ""]);
-        let inner_op_duration_wait_ring_buffer =
-            global
-                .inner_op_duration
-                .with_label_values(&[foyer, "wait_ring_buffer", ""]);
-        let inner_op_duration_update_catalog =
-            global
-                .inner_op_duration
-                .with_label_values(&[foyer, "update_catalog", ""]);
-        let inner_op_duration_entry_flush = global.inner_op_duration.with_label_values(&[foyer, "entry_flush", ""]);
-        let inner_op_duration_flusher_handle =
-            global
-                .inner_op_duration
-                .with_label_values(&[foyer, "flusher_handle", ""]);
+    pub fn new(recorder: &Arc<dyn MetricsRecorder>, foyer: &str) -> Self {
+        let op_duration =
+            |op, extra| Histogram::new(recorder, OP_DURATION, &[("foyer", foyer), ("op", op), ("extra", extra)]);
+        let op_bytes = |op| Counter::new(recorder, OP_BYTES, &[("foyer", foyer), ("op", op), ("extra", "")]);
+        let inner_op_duration = |op| {
+            Histogram::new(
+                recorder,
+                INNER_OP_DURATION,
+                &[("foyer", foyer), ("op", op), ("extra", "")],
+            )
+        };
+        let background_cpu_seconds =
+            |task| FloatCounter::new(recorder, BACKGROUND_CPU_SECONDS, &[("foyer", foyer), ("task", task)]);
+        let catalog_ops = |op| Counter::new(recorder, CATALOG_OPS, &[("foyer", foyer), ("op", op)]);
+        let recover_entries = |result| Counter::new(recorder, RECOVER_ENTRIES, &[("foyer", foyer), ("result", result)]);
 
         Self {
-            op_duration_insert_inserted,
-            op_duration_insert_filtered,
-            op_duration_insert_dropped,
-            op_duration_get_hit,
-            op_duration_get_miss,
-            op_duration_remove,
-            slow_op_duration_reclaim,
-
-            op_bytes_insert,
-            op_bytes_get,
-            op_bytes_flush,
-            op_bytes_reclaim,
-            op_bytes_reinsert,
+            op_duration_insert_inserted: op_duration("insert", "inserted"),
+            op_duration_insert_filtered: op_duration("insert", "filtered"),
+            op_duration_insert_dropped: op_duration("insert", "dropped"),
+            op_duration_get_hit: op_duration("get", "hit"),
+            op_duration_get_miss: op_duration("get", "miss"),
+            op_duration_remove: op_duration("remove", ""),
+            slow_op_duration_reclaim: Histogram::new(
+                recorder,
+                SLOW_OP_DURATION,
+                &[("foyer", foyer), ("op", "reclaim"), ("extra", "")],
+            ),
 
-            total_bytes,
+            op_bytes_insert: op_bytes("insert"),
+            op_bytes_get: op_bytes("get"),
+            op_bytes_flush: op_bytes("flush"),
+            op_bytes_reclaim: op_bytes("reclaim"),
+            op_bytes_reinsert: op_bytes("reinsert"),
+            op_bytes_recover: op_bytes("recover"),
+
+            total_bytes: Gauge::new(recorder, TOTAL_BYTES, &[("foyer", foyer)]),
+
+            insert_entry_bytes: Histogram::new(
+                recorder,
+                ENTRY_BYTES,
+                &[("foyer", foyer), ("op", "insert"), ("extra", "")],
+            ),
+
+            inner_op_duration_acquire_clean_region: inner_op_duration("acquire_clean_region"),
+            inner_op_duration_acquire_clean_buffer: inner_op_duration("acquire_clean_buffer"),
+            inner_op_duration_wait_ring_buffer: inner_op_duration("wait_ring_buffer"),
+            inner_op_duration_update_catalog: inner_op_duration("update_catalog"),
+            inner_op_duration_entry_flush: inner_op_duration("entry_flush"),
+            inner_op_duration_flusher_handle: inner_op_duration("flusher_handle"),
+            inner_op_duration_throttle_catalog: inner_op_duration("throttle_catalog"),
+
+            flush_queue_lag: inner_op_duration("flush_queue_lag"),
+
+            background_cpu_seconds_flush: background_cpu_seconds("flush"),
+            background_cpu_seconds_reclaim: background_cpu_seconds("reclaim"),
+
+            catalog_ops_insert: catalog_ops("insert"),
+            catalog_ops_remove: catalog_ops("remove"),
+            catalog_ops_expire: catalog_ops("expire"),
+            catalog_ops_persist_failed: catalog_ops("persist_failed"),
+
+            decompression_queue_depth: Gauge::new(recorder, DECOMPRESSION_QUEUE_DEPTH, &[("foyer", foyer)]),
+            flusher_queue_depth: Gauge::new(recorder, FLUSHER_QUEUE_DEPTH, &[("foyer", foyer)]),
+
+            recover_entries_recovered: recover_entries("recovered"),
+            recover_entries_corrupted: recover_entries("corrupted"),
+            recover_entries_checksum_mismatch: recover_entries("checksum_mismatch"),
+        }
+    }
 
-            insert_entry_bytes,
+    /// Serialize the current values of the metrics to a JSON object, e.g. for ad-hoc diagnostics without a metrics
+    /// backend.
+    ///
+    /// The metrics are grouped by the metric names, each with its labels and value. Histograms are summarized by the
+    /// count and the sum of the observations. The values are loaded one by one, so the snapshot is not atomic with
+    /// concurrent operations.
+    pub fn to_json(&self) -> String {
+        let mut families: Vec<(&'static str, Vec<MetricRef>)> = vec![];
+        for metric in self.metric_refs() {
+            let name = metric.inner().key.name;
+            match families.iter_mut().find(|(n, _)| *n == name) {
+                Some((_, family)) => family.push(metric),
+                None => families.push((name, vec![metric])),
+            }
+        }
+
+        let mut json = String::from("{");
+        for (i, (name, family)) in families.iter().enumerate() {
+            if i > 0 {
+                json.push(',');
+            }
+            write_json_str(&mut json, name);
+            json.push_str(":[");
+            for (j, metric) in family.iter().enumerate() {
+                if j > 0 {
+                    json.push(',');
+                }
+                json.push_str("{\"labels\":{");
+                for (k, (label, value)) in metric.inner().key.labels.iter().enumerate() {
+                    if k > 0 {
+                        json.push(',');
+                    }
+                    write_json_str(&mut json, label);
+                    json.push(':');
+                    write_json_str(&mut json, value);
+                }
+                json.push('}');
+                match metric {
+                    MetricRef::Counter(counter) => write!(json, ",\"value\":{}", counter.get()).unwrap(),
+                    MetricRef::FloatCounter(counter) => {
+                        json.push_str(",\"value\":");
+                        write_json_f64(&mut json, counter.get());
+                    }
+                    MetricRef::Gauge(gauge) => write!(json, ",\"value\":{}", gauge.get()).unwrap(),
+                    MetricRef::Histogram(histogram) => {
+                        write!(json, ",\"count\":{},\"sum\":", histogram.count()).unwrap();
+                        write_json_f64(&mut json, histogram.sum());
+                    }
+                }
+                json.push('}');
+            }
+            json.push(']');
+        }
+        json.push('}');
+        json
+    }
+
+    fn metric_refs(&self) -> Vec<MetricRef> {
+        vec![
+            MetricRef::Histogram(self.op_duration_insert_inserted.clone()),
+            MetricRef::Histogram(self.op_duration_insert_filtered.clone()),
+            MetricRef::Histogram(self.op_duration_insert_dropped.clone()),
+            MetricRef::Histogram(self.op_duration_get_hit.clone()),
+            MetricRef::Histogram(self.op_duration_get_miss.clone()),
+            MetricRef::Histogram(self.op_duration_remove.clone()),
+            MetricRef::Histogram(self.slow_op_duration_reclaim.clone()),
+            MetricRef::Counter(self.op_bytes_insert.clone()),
+            MetricRef::Counter(self.op_bytes_get.clone()),
+            MetricRef::Counter(self.op_bytes_flush.clone()),
+            MetricRef::Counter(self.op_bytes_reclaim.clone()),
+            MetricRef::Counter(self.op_bytes_reinsert.clone()),
+            MetricRef::Counter(self.op_bytes_recover.clone()),
+            MetricRef::Gauge(self.total_bytes.clone()),
+            MetricRef::Histogram(self.insert_entry_bytes.clone()),
+            MetricRef::Histogram(self.inner_op_duration_acquire_clean_region.clone()),
+            MetricRef::Histogram(self.inner_op_duration_acquire_clean_buffer.clone()),
+            MetricRef::Histogram(self.inner_op_duration_wait_ring_buffer.clone()),
+            MetricRef::Histogram(self.inner_op_duration_update_catalog.clone()),
+            MetricRef::Histogram(self.inner_op_duration_entry_flush.clone()),
+            MetricRef::Histogram(self.inner_op_duration_flusher_handle.clone()),
+            MetricRef::Histogram(self.inner_op_duration_throttle_catalog.clone()),
+            MetricRef::Histogram(self.flush_queue_lag.clone()),
+            MetricRef::FloatCounter(self.background_cpu_seconds_flush.clone()),
+            MetricRef::FloatCounter(self.background_cpu_seconds_reclaim.clone()),
+            MetricRef::Counter(self.catalog_ops_insert.clone()),
+            MetricRef::Counter(self.catalog_ops_remove.clone()),
+            MetricRef::Counter(self.catalog_ops_expire.clone()),
+            MetricRef::Counter(self.catalog_ops_persist_failed.clone()),
+            MetricRef::Gauge(self.decompression_queue_depth.clone()),
+            MetricRef::Gauge(self.flusher_queue_depth.clone()),
+            MetricRef::Counter(self.recover_entries_recovered.clone()),
+            MetricRef::Counter(self.recover_entries_corrupted.clone()),
+            MetricRef::Counter(self.recover_entries_checksum_mismatch.clone()),
+        ]
+    }
+}
+
+fn write_json_str(json: &mut String, s: &str) {
+    json.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            c if c.is_control() => write!(json, "\\u{:04x}", c as u32).unwrap(),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+}
+
+/// JSON has no representation of the non-finite numbers, they are written as `null`.
+fn write_json_f64(json: &mut String, v: f64) {
+    if v.is_finite() {
+        write!(json, "{}", v).unwrap();
+    } else {
+        json.push_str("null");
+    }
+}
+
+#[cfg(feature = "prometheus")]
+impl Metrics {
+    /// Register the metrics to the Prometheus `registry`.
+    ///
+    /// The metric names are prefixed with `{namespace}_` unless `namespace` is empty, and the metrics of different
+    /// stores are distinguished by the label `foyer`. The values are read from the metrics when the registry is
+    /// gathered, regardless of the metrics recorder.
+    pub fn register(&self, registry: &Registry, namespace: &str) -> prometheus::Result<()> {
+        let metrics = self.metric_refs();
+        registry.register(Box::new(MetricsCollector::new(namespace, metrics)?))
+    }
+}
+
+#[derive(Debug, Clone)]
+enum MetricRef {
+    Counter(Counter),
+    FloatCounter(FloatCounter),
+    Gauge(Gauge),
+    Histogram(Histogram),
+}
+
+impl MetricRef {
+    fn inner(&self) -> &MetricInner {
+        match self {
+            MetricRef::Counter(counter) => &counter.0,
+            MetricRef::FloatCounter(counter) => &counter.0,
+            MetricRef::Gauge(gauge) => &gauge.0,
+            MetricRef::Histogram(histogram) => &histogram.inner,
+        }
+    }
+}
+
+#[cfg(feature = "prometheus")]
+impl MetricRef {
+    fn metric_type(&self) -> proto::MetricType {
+        match self {
+            MetricRef::Counter(_) | MetricRef::FloatCounter(_) => proto::MetricType::COUNTER,
+            MetricRef::Gauge(_) => proto::MetricType::GAUGE,
+            MetricRef::Histogram(_) => proto::MetricType::HISTOGRAM,
+        }
+    }
+
+    fn collect(&self) -> proto::Metric {
+        let mut metric = proto::Metric::default();
+        let labels = self
+            .inner()
+            .key
+            .labels
+            .iter()
+            .map(|(name, value)| {
+                let mut label = proto::LabelPair::default();
+                label.set_name(name.to_string());
+                label.set_value(value.clone());
+                label
+            })
+            .collect_vec();
+        metric.set_label(labels.into());
 
-            inner_op_duration_acquire_clean_region,
-            inner_op_duration_acquire_clean_buffer,
-            inner_op_duration_wait_ring_buffer,
-            inner_op_duration_update_catalog,
-            inner_op_duration_entry_flush,
-            inner_op_duration_flusher_handle,
+        match self {
+            MetricRef::Counter(counter) => {
+                let mut c = proto::Counter::default();
+                c.set_value(counter.get() as f64);
+                metric.set_counter(c);
+            }
+            MetricRef::FloatCounter(counter) => {
+                let mut c = proto::Counter::default();
+                c.set_value(counter.get());
+                metric.set_counter(c);
+            }
+            MetricRef::Gauge(gauge) => {
+                let mut g = proto::Gauge::default();
+                g.set_value(gauge.get() as f64);
+                metric.set_gauge(g);
+            }
+            MetricRef::Histogram(histogram) => {
+                let state = &histogram.state;
+                let mut cumulative = 0;
+                let buckets = state
+                    .bounds
+                    .iter()
+                    .zip(state.counts.iter())
+                    .map(|(bound, count)| {
+                        cumulative += count.load(Ordering::Relaxed);
+                        let mut bucket = proto::Bucket::default();
+                        bucket.set_upper_bound(*bound);
+                        bucket.set_cumulative_count(cumulative);
+                        bucket
+                    })
+                    .collect_vec();
+                let mut h = proto::Histogram::default();
+                h.set_sample_count(cumulative + state.counts.last().unwrap().load(Ordering::Relaxed));
+                h.set_sample_sum(histogram.sum());
+                h.set_bucket(buckets.into());
+                metric.set_histogram(h);
+            }
         }
+
+        metric
+    }
+}
+
+/// Collector that reads the metrics of a store when the registry is gathered.
+#[cfg(feature = "prometheus")]
+#[derive(Debug)]
+struct MetricsCollector {
+    descs: Vec<Desc>,
+    /// Metrics grouped by the metric names, in the same order as `descs`.
+    families: Vec<Vec<MetricRef>>,
+}
+
+#[cfg(feature = "prometheus")]
+impl MetricsCollector {
+    fn new(namespace: &str, metrics: Vec<MetricRef>) -> prometheus::Result<Self> {
+        let mut families: Vec<Vec<MetricRef>> = vec![];
+        for metric in metrics {
+            match families
+                .iter_mut()
+                .find(|family| family[0].inner().key.name == metric.inner().key.name)
+            {
+                Some(family) => family.push(metric),
+                None => families.push(vec![metric]),
+            }
+        }
+
+        let descs = families
+            .iter()
+            .map(|family| {
+                let key = &family[0].inner().key;
+                let name = if namespace.is_empty() {
+                    key.name.to_string()
+                } else {
+                    format!("{}_{}", namespace, key.name)
+                };
+                // The store name is a constant label, so the collectors of different stores don't conflict.
+                let (consts, variables): (Vec<_>, Vec<_>) = key.labels.iter().partition(|(label, _)| *label == "foyer");
+                Desc::new(
+                    name,
+                    help(key.name).to_string(),
+                    variables.into_iter().map(|(label, _)| label.to_string()).collect(),
+                    consts
+                        .into_iter()
+                        .map(|(label, value)| (label.to_string(), value.clone()))
+                        .collect(),
+                )
+            })
+            .collect::<prometheus::Result<Vec<_>>>()?;
+
+        Ok(Self { descs, families })
+    }
+}
+
+#[cfg(feature = "prometheus")]
+impl Collector for MetricsCollector {
+    fn desc(&self) -> Vec<&Desc> {
+        self.descs.iter().collect()
+    }
+
+    fn collect(&self) -> Vec<proto::MetricFamily> {
+        self.descs
+            .iter()
+            .zip(self.families.iter())
+            .map(|(desc, family)| {
+                let mut mf = proto::MetricFamily::default();
+                mf.set_name(desc.fq_name.clone());
+                mf.set_help(desc.help.clone());
+                mf.set_field_type(family[0].metric_type());
+                mf.set_metric(family.iter().map(|metric| metric.collect()).collect_vec().into());
+                mf
+            })
+            .collect()
+    }
+}
+
+#[cfg(all(test, feature = "prometheus"))]
+mod tests {
+    use super::*;
+
+    fn family<'a>(families: &'a [proto::MetricFamily], name: &str) -> &'a proto::MetricFamily {
+        families.iter().find(|family| family.get_name() == name).unwrap()
+    }
+
+    #[test]
+    fn test_register() {
+        let recorder: Arc<dyn MetricsRecorder> = Arc::new(NoopMetricsRecorder);
+        let registry = Registry::new();
+
+        let a = Metrics::new(&recorder, "a");
+        let b = Metrics::new(&recorder, "b");
+        a.register(&registry, "test").unwrap();
+        // The metrics of different stores can be registered to the same registry.
+        b.register(&registry, "test").unwrap();
+        // But not the same store twice.
+        assert!(a.register(&registry, "test").is_err());
+
+        a.op_bytes_flush.inc_by(4096);
+        a.total_bytes.set(1024);
+        a.op_duration_get_hit.observe(0.002);
+        a.op_duration_get_hit.observe(0.2);
+        a.op_duration_get_miss.observe(2.0);
+
+        let families = registry.gather();
+
+        let op_bytes = family(&families, "test_foyer_storage_op_bytes");
+        let flush = op_bytes
+            .get_metric()
+            .iter()
+            .find(|metric| {
+                let labels = metric.get_label();
+                labels.iter().any(|l| l.get_name() == "foyer" && l.get_value() == "a")
+                    && labels.iter().any(|l| l.get_name() == "op" && l.get_value() == "flush")
+            })
+            .unwrap();
+        assert_eq!(flush.get_counter().get_value(), 4096.0);
+
+        let total_bytes = family(&families, "test_foyer_storage_total_bytes");
+        assert_eq!(total_bytes.get_metric().len(), 2);
+
+        let op_duration = family(&families, "test_foyer_storage_op_duration");
+        let hit = op_duration
+            .get_metric()
+            .iter()
+            .find(|metric| {
+                let labels = metric.get_label();
+                labels.iter().any(|l| l.get_name() == "foyer" && l.get_value() == "a")
+                    && labels.iter().any(|l| l.get_name() == "extra" && l.get_value() == "hit")
+            })
+            .unwrap()
+            .get_histogram();
+        assert_eq!(hit.get_sample_count(), 2);
+        assert!((hit.get_sample_sum() - 0.202).abs() < 1e-9);
+        // 0.002 falls into the bucket of 0.005, and 0.2 into the bucket of 0.25.
+        let cumulative = |bound: f64| {
+            hit.get_bucket()
+                .iter()
+                .find(|bucket| bucket.get_upper_bound() == bound)
+                .unwrap()
+                .get_cumulative_count()
+        };
+        assert_eq!(cumulative(0.001), 0);
+        assert_eq!(cumulative(0.005), 1);
+        assert_eq!(cumulative(0.25), 2);
+        assert_eq!(a.op_duration_get_miss.count(), 1);
     }
 }
@@ -0,0 +1,696 @@
+//  Copyright 2024 Foyer Project Authors
+//
+//  Licensed under the Apache License, Version 2.0 (the "License");
+//  you may not use this file except in compliance with the License.
+//  You may obtain a copy of the License at
+//
+//  http://www.apache.org/licenses/LICENSE-2.0
+//
+//  Unless required by applicable law or agreed to in writing, software
+//  distributed under the License is distributed on an "AS IS" BASIS,
+//  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//  See the License for the specific language governing permissions and
+//  limitations under the License.
+
+use std::{
+    borrow::Borrow,
+    fmt::Debug,
+    hash::Hash,
+    ops::Range,
+    path::Path,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::SystemTime,
+};
+
+use ahash::RandomState;
+use foyer_common::code::{StorageKey, StorageValue};
+use futures::{future::BoxFuture, stream::BoxStream};
+use itertools::Itertools;
+use parking_lot::RwLock;
+use tokio::{io::AsyncWrite, sync::Mutex};
+
+use crate::{
+    catalog::Sequence,
+    compress::Compression,
+    device::Device,
+    error::Result,
+    generic::{GenericStore, GenericStoreConfig, GenericStoreWriter},
+    region::RegionId,
+    storage::{CachedEntry, CompactionReport, CompactionStrategy, DrainedEntry, ScrubReport, Storage, StorageWriter},
+};
+
+const DEFAULT_MIGRATE_LOCK_SHARDS: usize = 64;
+
+pub struct MigrateStoreConfig<K, V, D>
+where
+    K: StorageKey,
+    V: StorageValue,
+    D: Device,
+{
+    /// Config of the store on the old device to migrate from.
+    pub from: GenericStoreConfig<K, V, D>,
+
+    /// Config of the store on the new device to migrate to.
+    pub to: GenericStoreConfig<K, V, D>,
+}
+
+impl<K, V, D> Debug for MigrateStoreConfig<K, V, D>
+where
+    K: StorageKey,
+    V: StorageValue,
+    D: Device,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MigrateStoreConfig")
+            .field("from", &self.from)
+            .field("to", &self.to)
+            .finish()
+    }
+}
+
+impl<K, V, D> Clone for MigrateStoreConfig<K, V, D>
+where
+    K: StorageKey,
+    V: StorageValue,
+    D: Device,
+{
+    fn clone(&self) -> Self {
+        Self {
+            from: self.from.clone(),
+            to: self.to.clone(),
+        }
+    }
+}
+
+/// [`MigrateStore`] migrates a live store from an old device to a new device online.
+///
+/// During the migration:
+///
+/// - New entries are always written to the new device.
+/// - Reads check both devices. Entries hit on the old device are migrated to the new device on the fly.
+/// - [`MigrateStore::migrate_region`] migrates the entries of the old device region by region with a migration
+///   cursor.
+///
+/// The old device is closed and detached after all its entries are migrated.
+pub struct MigrateStore<K, V, D>
+where
+    K: StorageKey,
+    V: StorageValue,
+    D: Device,
+{
+    inner: Arc<MigrateStoreInner<K, V, D>>,
+}
+
+impl<K, V, D> Clone for MigrateStore<K, V, D>
+where
+    K: StorageKey,
+    V: StorageValue,
+    D: Device,
+{
+    fn clone(&self) -> Self {
+        Self {
+            inner: Arc::clone(&self.inner),
+        }
+    }
+}
+
+struct MigrateStoreInner<K, V, D>
+where
+    K: StorageKey,
+    V: StorageValue,
+    D: Device,
+{
+    /// The store on the old device, `None` after detached.
+    from: RwLock<Option<GenericStore<K, V, D>>>,
+    /// The store on the new device.
+    to: GenericStore<K, V, D>,
+
+    /// Next region of the old device to migrate.
+    cursor: AtomicUsize,
+
+    /// Serialize writers and migrations of the same key, to prevent the migrated old value from overwriting the new
+    /// value.
+    locks: Vec<Mutex<()>>,
+    hash_builder: RandomState,
+}
+
+impl<K, V, D> MigrateStore<K, V, D>
+where
+    K: StorageKey,
+    V: StorageValue,
+    D: Device,
+{
+    /// Attach the store on the new device `to` to the live store on the old device `from`.
+    pub fn new(from: GenericStore<K, V, D>, to: GenericStore<K, V, D>) -> Self {
+        let inner = MigrateStoreInner {
+            from: RwLock::new(Some(from)),
+            to,
+            cursor: AtomicUsize::new(0),
+            locks: (0..DEFAULT_MIGRATE_LOCK_SHARDS).map(|_| Mutex::new(())).collect_vec(),
+            hash_builder: RandomState::default(),
+        };
+        Self { inner: Arc::new(inner) }
+    }
+
+    /// Next region of the old device to migrate.
+    pub fn cursor(&self) -> usize {
+        self.inner.cursor.load(Ordering::Relaxed)
+    }
+
+    /// Returns `true` if all entries are migrated and the old device is detached.
+    pub fn is_migrated(&self) -> bool {
+        self.inner.from.read().is_none()
+    }
+
+    /// Migrate the entries of the old device region at the migration cursor, then advance the cursor.
+    ///
+    /// After the cursor passes the last region, the old device is detached if it is empty. Otherwise (e.g. entries
+    /// were still in the flush buffer while their region was migrated), the cursor restarts from the first region.
+    ///
+    /// Returns `false` if the migration is finished.
+    pub async fn migrate_region(&self) -> Result<bool> {
+        let Some(from) = self.from() else {
+            return Ok(false);
+        };
+
+        let region = self.inner.cursor.fetch_add(1, Ordering::Relaxed);
+
+        if region >= from.device().regions() {
+            if !from.catalog().is_empty() {
+                self.inner.cursor.store(0, Ordering::Relaxed);
+                return Ok(true);
+            }
+            self.detach().await?;
+            return Ok(false);
+        }
+
+        let keys = from.catalog().region_keys(&(region as RegionId));
+        for key in keys {
+            self.migrate_key(&from, key.as_ref()).await?;
+        }
+
+        Ok(true)
+    }
+
+    /// Migrate all entries of the old device, then detach it.
+    pub async fn migrate(&self) -> Result<()> {
+        while self.migrate_region().await? {
+            // Yield to serve foreground operations between regions.
+            tokio::task::yield_now().await;
+        }
+        Ok(())
+    }
+
+    fn from(&self) -> Option<GenericStore<K, V, D>> {
+        self.inner.from.read().clone()
+    }
+
+    async fn detach(&self) -> Result<()> {
+        let from = self.inner.from.write().take();
+        if let Some(from) = from {
+            tracing::info!("[migrate]: all entries are migrated, detach the old device");
+            from.close().await?;
+        }
+        Ok(())
+    }
+
+    fn lock<Q>(&self, key: &Q) -> &Mutex<()>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        &self.inner.locks[self.shard(key)]
+    }
+
+    fn shard<Q>(&self, key: &Q) -> usize
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let hash = self.inner.hash_builder.hash_one(key);
+        hash as usize % self.inner.locks.len()
+    }
+
+    /// Move the entry of `key` from the old device to the new device.
+    ///
+    /// Returns the migrated entry, or `None` if the entry is no longer on the old device.
+    async fn migrate_key<Q>(&self, from: &GenericStore<K, V, D>, key: &Q) -> Result<Option<CachedEntry<K, V>>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized + Sync,
+    {
+        let _guard = self.lock(key).lock().await;
+
+        let entry = match from.get(key).await? {
+            Some(entry) => entry,
+            None => return Ok(None),
+        };
+
+        // The new device always holds the newer value.
+        if self.inner.to.exists(key)? {
+            from.remove(key)?;
+            return Ok(None);
+        }
+
+        let expire_at = from.catalog().get(key).and_then(|item| item.expire_at());
+        let (entry_key, value) = entry.to_arc();
+        let mut writer = self.inner.to.writer(entry_key.clone());
+        writer.force();
+        if let Some(expire_at) = expire_at {
+            writer.set_expire_at(expire_at);
+        }
+        writer.finish(value.clone()).await?;
+
+        // The entry is removed concurrently, remove the migrated entry as well.
+        if !from.remove(key)? {
+            self.inner.to.remove(key)?;
+            return Ok(None);
+        }
+
+        Ok(Some(CachedEntry::Shared { key: entry_key, value }))
+    }
+}
+
+pub struct MigrateStoreWriter<K, V, D>
+where
+    K: StorageKey,
+    V: StorageValue,
+    D: Device,
+{
+    store: MigrateStore<K, V, D>,
+    writer: GenericStoreWriter<K, V, D>,
+}
+
+impl<K, V, D> Debug for MigrateStoreWriter<K, V, D>
+where
+    K: StorageKey + Debug,
+    V: StorageValue,
+    D: Device,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MigrateStoreWriter")
+            .field("writer", &self.writer)
+            .finish()
+    }
+}
+
+impl<K, V, D> StorageWriter<K, V> for MigrateStoreWriter<K, V, D>
+where
+    K: StorageKey,
+    V: StorageValue,
+    D: Device,
+{
+    fn key(&self) -> &K {
+        self.writer.key()
+    }
+
+    fn judge(&mut self) -> bool {
+        self.writer.judge()
+    }
+
+    fn force(&mut self) {
+        self.writer.force()
+    }
+
+    async fn finish<AV>(self, value: AV) -> Result<Option<CachedEntry<K, V>>>
+    where
+        AV: Into<Arc<V>> + Send + 'static,
+    {
+        let _guard = self.store.lock(self.writer.key()).lock().await;
+
+        // Remove the stale entry on the old device before writing the new one.
+        if let Some(from) = self.store.from() {
+            from.remove(self.writer.key())?;
+        }
+
+        self.writer.finish(value).await
+    }
+
+    fn compression(&self) -> Compression {
+        self.writer.compression()
+    }
+
+    fn set_compression(&mut self, compression: Compression) {
+        self.writer.set_compression(compression)
+    }
+
+    fn expire_at(&self) -> Option<SystemTime> {
+        self.writer.expire_at()
+    }
+
+    fn set_expire_at(&mut self, expire_at: SystemTime) {
+        self.writer.set_expire_at(expire_at)
+    }
+}
+
+impl<K, V, D> Storage<K, V> for MigrateStore<K, V, D>
+where
+    K: StorageKey,
+    V: StorageValue,
+    D: Device,
+{
+    type Config = MigrateStoreConfig<K, V, D>;
+    type Writer = MigrateStoreWriter<K, V, D>;
+
+    async fn open(config: Self::Config) -> Result<Self> {
+        let from = GenericStore::open(config.from).await?;
+        let to = GenericStore::open(config.to).await?;
+        Ok(Self::new(from, to))
+    }
+
+    fn is_ready(&self) -> bool {
+        true
+    }
+
+    async fn close(&self) -> Result<()> {
+        if let Some(from) = self.from() {
+            from.close().await?;
+        }
+        self.inner.to.close().await
+    }
+
+    fn writer<AK>(&self, key: AK) -> Self::Writer
+    where
+        AK: Into<Arc<K>> + Send + 'static,
+    {
+        MigrateStoreWriter {
+            store: self.clone(),
+            writer: self.inner.to.writer(key),
+        }
+    }
+
+    async fn insert_batch(&self, entries: Vec<(Arc<K>, Arc<V>)>) -> Result<Vec<Option<CachedEntry<K, V>>>> {
+        // Lock the shards in order, so that concurrent batches never deadlock.
+        let shards = entries
+            .iter()
+            .map(|(key, _)| self.shard(key.as_ref()))
+            .sorted()
+            .dedup()
+            .collect_vec();
+        let mut guards = Vec::with_capacity(shards.len());
+        for shard in shards {
+            guards.push(self.inner.locks[shard].lock().await);
+        }
+
+        // Remove the stale entries on the old device before writing the new ones.
+        if let Some(from) = self.from() {
+            for (key, _) in entries.iter() {
+                from.remove(key.as_ref())?;
+            }
+        }
+
+        self.inner.to.insert_batch(entries).await
+    }
+
+    fn exists<Q>(&self, key: &Q) -> Result<bool>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        if let Some(from) = self.from() {
+            if from.exists(key)? {
+                return Ok(true);
+            }
+        }
+        self.inner.to.exists(key)
+    }
+
+    fn touch<Q>(&self, key: &Q) -> Result<bool>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        if let Some(from) = self.from() {
+            if from.touch(key)? {
+                return Ok(true);
+            }
+        }
+        self.inner.to.touch(key)
+    }
+
+    fn recent_inserts(&self, n: usize) -> Vec<(Arc<K>, Sequence)> {
+        self.inner.to.recent_inserts(n)
+    }
+
+    async fn get<Q>(&self, key: &Q) -> Result<Option<CachedEntry<K, V>>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + Send + Sync + 'static + Clone,
+    {
+        // Check the old device first, for an entry is always inserted into the new device before it is removed from
+        // the old device while migrating.
+        if let Some(from) = self.from() {
+            if from.exists(key)? {
+                if let Some(entry) = self.migrate_key(&from, key).await? {
+                    return Ok(Some(entry));
+                }
+            }
+        }
+        self.inner.to.get(key).await
+    }
+
+    async fn get_stream<Q, W>(&self, key: &Q, writer: W) -> Result<bool>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + Send + Sync + 'static + Clone,
+        W: AsyncWrite + Send + Unpin + 'static,
+    {
+        if let Some(from) = self.from() {
+            if from.exists(key)? {
+                self.migrate_key(&from, key).await?;
+            }
+        }
+        self.inner.to.get_stream(key, writer).await
+    }
+
+    async fn get_range<Q>(&self, key: &Q, range: Range<usize>) -> Result<Option<Vec<u8>>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + Send + Sync + 'static + Clone,
+    {
+        if let Some(from) = self.from() {
+            if from.exists(key)? {
+                self.migrate_key(&from, key).await?;
+            }
+        }
+        self.inner.to.get_range(key, range).await
+    }
+
+    fn remove<Q>(&self, key: &Q) -> Result<bool>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let mut res = false;
+        if let Some(from) = self.from() {
+            res |= from.remove(key)?;
+        }
+        res |= self.inner.to.remove(key)?;
+        Ok(res)
+    }
+
+    fn clear(&self) -> Result<()> {
+        if let Some(from) = self.from() {
+            from.clear()?;
+        }
+        self.inner.to.clear()
+    }
+
+    /// Only the catalog of the target store is exported.
+    async fn snapshot_catalog(&self, path: impl AsRef<Path> + Send) -> Result<()> {
+        self.inner.to.snapshot_catalog(path).await
+    }
+
+    /// Only the entries of the target store are streamed.
+    fn entries_since(&self, sequence: Sequence) -> BoxStream<'static, Result<(Arc<K>, Arc<V>, Sequence)>> {
+        self.inner.to.entries_since(sequence)
+    }
+
+    /// Only the entries of the target store are scanned.
+    fn scan(&self) -> BoxStream<'static, Result<(K, V)>> {
+        self.inner.to.scan()
+    }
+
+    /// Entries are only inserted into the target store, so the barrier is only placed on it.
+    fn barrier(&self) -> BoxFuture<'static, Result<()>> {
+        self.inner.to.barrier()
+    }
+
+    /// Entries are only inserted into the target store, so only the target store is drained.
+    fn drain(&self) -> BoxFuture<'static, Result<Vec<DrainedEntry<K>>>> {
+        self.inner.to.drain()
+    }
+
+    fn scrub(&self, remove_corrupted: bool) -> BoxFuture<'static, Result<ScrubReport<K>>> {
+        self.inner.to.scrub(remove_corrupted)
+    }
+
+    fn compact(&self, strategy: CompactionStrategy) -> BoxFuture<'static, Result<CompactionReport>> {
+        self.inner.to.compact(strategy)
+    }
+
+    fn read_region_bytes(&self, region: RegionId) -> BoxFuture<'static, Result<Vec<u8>>> {
+        self.inner.to.read_region_bytes(region)
+    }
+
+    fn apply_region_bytes(&self, region: RegionId, bytes: Vec<u8>) -> BoxFuture<'static, Result<()>> {
+        self.inner.to.apply_region_bytes(region, bytes)
+    }
+
+    fn shrink(&self, capacity: usize) -> BoxFuture<'static, Result<()>> {
+        self.inner.to.shrink(capacity)
+    }
+
+    /// Only the regions of the target store are rebuilt.
+    fn rebuild_region_filters(&self) -> BoxFuture<'static, Result<usize>> {
+        self.inner.to.rebuild_region_filters()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, path::PathBuf, time::Duration};
+
+    use foyer_memory::FifoConfig;
+
+    use super::*;
+    use crate::{
+        buffer::{FlushErrorPolicy, PaddingPolicy},
+        catalog::{CatalogRetryPolicy, WriteConflictPolicy},
+        device::{
+            executor::IoExecutor,
+            fs::{FsDevice, FsDeviceConfig, RegionOpenErrorPolicy},
+        },
+        flusher::{ChannelDropPolicy, QueueFullPolicy},
+        generic::ChecksumAlgorithm,
+        region::UnknownRegionVersionPolicy,
+        storage::StorageExt,
+    };
+
+    const KB: usize = 1024;
+    const MB: usize = 1024 * 1024;
+
+    fn config_for_test(dir: impl Into<PathBuf>, capacity: usize) -> GenericStoreConfig<u64, Vec<u8>, FsDevice> {
+        GenericStoreConfig {
+            name: "".to_string(),
+            eviction_config: FifoConfig {}.into(),
+            device_config: FsDeviceConfig {
+                dir: dir.into(),
+                capacity,
+                file_size: 4 * MB,
+                align: 4 * KB,
+                io_size: 4 * KB,
+                io_executor: IoExecutor::default(),
+                on_region_open_error: RegionOpenErrorPolicy::default(),
+                write_io_size: None,
+                read_io_size: None,
+                extra_dirs: vec![],
+                direct_io: true,
+            },
+            catalog_shards: 1,
+            admissions: vec![],
+            reinsertions: vec![],
+            flushers: 1,
+            reclaimers: 1,
+            recover_concurrency: 2,
+            clean_region_threshold: 1,
+            compression: Compression::None,
+            checksum: true,
+            recent_inserts_capacity: 0,
+            max_key_size: None,
+            region_compression: None,
+            intra_block_align: None,
+            catalog_snapshot_path: None,
+            reclaim_grace_period: Duration::ZERO,
+            compression_observer: None,
+            unknown_region_version_policy: UnknownRegionVersionPolicy::default(),
+            block_compression: None,
+            background_cpu_cores: None,
+            metrics_recorder: None,
+            write_conflict_policy: WriteConflictPolicy::default(),
+            catalog_ops_limit: None,
+            first_entry_offset: None,
+            verify_region_magic_on_read: false,
+            self_test_on_open: false,
+            scrub_throughput_limit: None,
+            write_coalescing_interval: None,
+            max_writing_regions: None,
+            size_padding: PaddingPolicy::default(),
+            store_key_hash_only: false,
+            zstd_level: 0,
+            max_concurrent_decompressions: None,
+            checksum_algorithm: ChecksumAlgorithm::default(),
+            key_prefix: None,
+            flush_error_policy: FlushErrorPolicy::default(),
+            admission_observer: None,
+            max_region_age: None,
+            channel_drop_policy: ChannelDropPolicy::default(),
+            prefix_compressions: HashMap::new(),
+            io_bytes_in_flight_limit: None,
+            flusher_queue_capacity: None,
+            queue_full_policy: QueueFullPolicy::default(),
+            flush_alignment: None,
+            adaptive_compression: false,
+            scan_read_ahead: 2,
+            verify_checksum_on_recover: false,
+            catalog_backend: None,
+            catalog_retry_policy: CatalogRetryPolicy::default(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_migrate_store() {
+        let from_dir = tempfile::tempdir().unwrap();
+        let to_dir = tempfile::tempdir().unwrap();
+
+        let from = GenericStore::open(config_for_test(from_dir.path(), 16 * MB))
+            .await
+            .unwrap();
+        for i in 0..64 {
+            from.insert(i, vec![i as u8; 16 * KB]).await.unwrap();
+        }
+        let to = GenericStore::open(config_for_test(to_dir.path(), 32 * MB))
+            .await
+            .unwrap();
+
+        let store = MigrateStore::new(from.clone(), to.clone());
+
+        // New entries are written to the new device.
+        store.insert(64, vec![64; 16 * KB]).await.unwrap();
+        assert!(to.exists(&64).unwrap());
+        assert!(!from.exists(&64).unwrap());
+
+        // Serve gets concurrently while migrating.
+        let readers = (0..4)
+            .map(|_| {
+                let store = store.clone();
+                tokio::spawn(async move {
+                    for _ in 0..4 {
+                        for i in 0..=64 {
+                            let entry = store.get(&i).await.unwrap().unwrap();
+                            assert_eq!(entry.value(), &vec![i as u8; 16 * KB]);
+                        }
+                        tokio::task::yield_now().await;
+                    }
+                })
+            })
+            .collect_vec();
+
+        store.migrate().await.unwrap();
+        for reader in readers {
+            reader.await.unwrap();
+        }
+
+        assert!(store.is_migrated());
+        assert!(from.catalog().is_empty());
+        for i in 0..=64 {
+            assert!(to.exists(&i).unwrap());
+            assert_eq!(store.get(&i).await.unwrap().unwrap().value(), &vec![i as u8; 16 * KB]);
+        }
+
+        store.close().await.unwrap();
+    }
+}
@@ -12,14 +12,18 @@
 //  See the License for the specific language governing permissions and
 //  limitations under the License.
 
-use std::{borrow::Borrow, hash::Hash, marker::PhantomData, sync::Arc};
+use std::{borrow::Borrow, hash::Hash, marker::PhantomData, ops::Range, path::Path, sync::Arc, time::SystemTime};
 
 use foyer_common::code::{StorageKey, StorageValue};
+use futures::{future::BoxFuture, stream::BoxStream, FutureExt, StreamExt};
+use tokio::io::AsyncWrite;
 
 use crate::{
+    catalog::Sequence,
     compress::Compression,
-    error::Result,
-    storage::{CachedEntry, Storage, StorageWriter},
+    error::{Error, Result},
+    region::RegionId,
+    storage::{CachedEntry, CompactionReport, CompactionStrategy, DrainedEntry, ScrubReport, Storage, StorageWriter},
 };
 
 #[derive(Debug)]
@@ -72,6 +76,12 @@ where
     }
 
     fn set_compression(&mut self, _: Compression) {}
+
+    fn expire_at(&self) -> Option<SystemTime> {
+        None
+    }
+
+    fn set_expire_at(&mut self, _: SystemTime) {}
 }
 
 #[derive(Debug)]
@@ -112,6 +122,10 @@ impl<K: StorageKey, V: StorageValue> Storage<K, V> for NoneStore<K, V> {
         NoneStoreWriter::new(key)
     }
 
+    async fn insert_batch(&self, entries: Vec<(Arc<K>, Arc<V>)>) -> Result<Vec<Option<CachedEntry<K, V>>>> {
+        Ok(entries.iter().map(|_| None).collect())
+    }
+
     fn exists<Q>(&self, _: &Q) -> Result<bool>
     where
         K: Borrow<Q>,
@@ -120,6 +134,18 @@ impl<K: StorageKey, V: StorageValue> Storage<K, V> for NoneStore<K, V> {
         Ok(false)
     }
 
+    fn touch<Q>(&self, _: &Q) -> Result<bool>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        Ok(false)
+    }
+
+    fn recent_inserts(&self, _: usize) -> Vec<(Arc<K>, Sequence)> {
+        vec![]
+    }
+
     async fn get<Q>(&self, _: &Q) -> Result<Option<CachedEntry<K, V>>>
     where
         K: Borrow<Q>,
@@ -128,6 +154,23 @@ impl<K: StorageKey, V: StorageValue> Storage<K, V> for NoneStore<K, V> {
         Ok(None)
     }
 
+    async fn get_stream<Q, W>(&self, _: &Q, _: W) -> Result<bool>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+        W: AsyncWrite + Send + Unpin + 'static,
+    {
+        Ok(false)
+    }
+
+    async fn get_range<Q>(&self, _: &Q, _: Range<usize>) -> Result<Option<Vec<u8>>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        Ok(None)
+    }
+
     fn remove<Q>(&self, _: &Q) -> Result<bool>
     where
         K: Borrow<Q>,
@@ -139,4 +182,48 @@ impl<K: StorageKey, V: StorageValue> Storage<K, V> for NoneStore<K, V> {
     fn clear(&self) -> Result<()> {
         Ok(())
     }
+
+    async fn snapshot_catalog(&self, _: impl AsRef<Path> + Send) -> Result<()> {
+        Ok(())
+    }
+
+    fn entries_since(&self, _: Sequence) -> BoxStream<'static, Result<(Arc<K>, Arc<V>, Sequence)>> {
+        futures::stream::empty().boxed()
+    }
+
+    fn scan(&self) -> BoxStream<'static, Result<(K, V)>> {
+        futures::stream::empty().boxed()
+    }
+
+    fn barrier(&self) -> BoxFuture<'static, Result<()>> {
+        futures::future::ok(()).boxed()
+    }
+
+    fn drain(&self) -> BoxFuture<'static, Result<Vec<DrainedEntry<K>>>> {
+        futures::future::ok(vec![]).boxed()
+    }
+
+    fn scrub(&self, _: bool) -> BoxFuture<'static, Result<ScrubReport<K>>> {
+        futures::future::ok(ScrubReport::default()).boxed()
+    }
+
+    fn compact(&self, _: CompactionStrategy) -> BoxFuture<'static, Result<CompactionReport>> {
+        futures::future::ok(CompactionReport::default()).boxed()
+    }
+
+    fn read_region_bytes(&self, region: RegionId) -> BoxFuture<'static, Result<Vec<u8>>> {
+        futures::future::err(Error::RegionNotSealed { region }).boxed()
+    }
+
+    fn apply_region_bytes(&self, _: RegionId, _: Vec<u8>) -> BoxFuture<'static, Result<()>> {
+        futures::future::ok(()).boxed()
+    }
+
+    fn shrink(&self, _: usize) -> BoxFuture<'static, Result<()>> {
+        futures::future::ok(()).boxed()
+    }
+
+    fn rebuild_region_filters(&self) -> BoxFuture<'static, Result<usize>> {
+        futures::future::ok(0).boxed()
+    }
 }
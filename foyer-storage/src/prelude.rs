@@ -13,16 +13,50 @@
 //  limitations under the License.
 
 pub use crate::{
-    admission::{rated_ticket::RatedTicketAdmissionPolicy, AdmissionContext, AdmissionPolicy},
-    compress::Compression,
-    device::fs::{FsDeviceConfig, FsDeviceConfigBuilder},
+    admission::{
+        rated_ticket::RatedTicketAdmissionPolicy, AdmissionContext, AdmissionDecision, AdmissionObserver,
+        AdmissionPolicy,
+    },
+    buffer::{FlushErrorPolicy, PaddingPolicy},
+    catalog::{CatalogBackend, CatalogRecord, CatalogRetryPolicy, Sequence, WriteConflictPolicy},
+    compress::{BlockCompression, Compression, CompressionDecision, CompressionObserver},
+    device::{
+        executor::{BlockingExecutor, BlockingJob, IoExecutor, ThreadPoolExecutor, TokioBlockingExecutor},
+        fs::{ConfigError, FsDeviceConfig, FsDeviceConfigBuilder, RegionOpenErrorPolicy},
+        mem::{MemDevice, MemDeviceConfig},
+    },
     error::{Error, Result},
-    metrics::{get_metrics_registry, set_metrics_registry},
+    flusher::{ChannelDropPolicy, QueueFullPolicy},
+    generic::{checksum, ChecksumAlgorithm, KeyPrefix},
+    lazy::{LazyStoreConfig, RecoveryReadPolicy},
+    metrics::{get_metrics_recorder, set_metrics_recorder, MetricKey, Metrics, MetricsRecorder, NoopMetricsRecorder},
+    migrate::{MigrateStore, MigrateStoreConfig, MigrateStoreWriter},
+    region::UnknownRegionVersionPolicy,
     reinsertion::{
         exist::ExistReinsertionPolicy, rated_ticket::RatedTicketReinsertionPolicy, ReinsertionContext,
         ReinsertionPolicy,
     },
     runtime::{RuntimeConfig, RuntimeConfigBuilder, RuntimeStoreConfig},
-    storage::{AsyncStorageExt, CachedEntry, ForceStorageExt, Storage, StorageExt, StorageWriter},
-    store::{DeviceConfig, FsStoreConfig, Store, StoreBuilder, StoreConfig, StoreWriter},
+    snapshot::{
+        read_catalog_snapshot, CatalogSnapshot, SnapshotEntry, CATALOG_SNAPSHOT_MAGIC, CATALOG_SNAPSHOT_VERSION,
+    },
+    storage::{
+        AsyncStorageExt, CachedEntry, CompactionReport, CompactionStrategy, CorruptedEntry, DrainedEntry,
+        ForceStorageExt, ScrubReport, Storage, StorageExt, StorageWriter,
+    },
+    store::{
+        DeviceConfig, FsMigrateStore, FsMigrateStoreConfig, FsStoreConfig, Store, StoreBuilder, StoreConfig,
+        StoreWriter,
+    },
 };
+
+#[cfg(all(feature = "mmap", unix))]
+pub use crate::device::mmap::{MmapDevice, MmapDeviceConfig, MmapSlice};
+#[cfg(feature = "object-store")]
+pub use crate::device::object::{ObjectStoreDevice, ObjectStoreDeviceConfig};
+#[cfg(all(feature = "raw", target_os = "linux"))]
+pub use crate::device::raw::{RawDevice, RawDeviceConfig};
+#[cfg(all(feature = "io_uring", target_os = "linux"))]
+pub use crate::device::uring::{IoUringDevice, IoUringDeviceConfig};
+#[cfg(feature = "prometheus")]
+pub use crate::metrics::{get_metrics_registry, set_metrics_registry, PrometheusMetricsRecorder};
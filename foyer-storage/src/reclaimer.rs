@@ -13,8 +13,9 @@
 //  limitations under the License.
 
 use std::{
+    collections::HashMap,
     sync::{atomic::Ordering, Arc},
-    time::Duration,
+    time::{Duration, SystemTime},
 };
 
 use bytes::BufMut;
@@ -23,6 +24,7 @@ use foyer_common::code::{StorageKey, StorageValue};
 use tokio::sync::broadcast;
 
 use crate::{
+    cpu_budget::CpuBudgetHandle,
     device::Device,
     error::Result,
     generic::{GenericStore, RegionEntryIter},
@@ -46,6 +48,8 @@ where
 
     metrics: Arc<Metrics>,
 
+    cpu_budget: CpuBudgetHandle,
+
     stop_rx: broadcast::Receiver<()>,
 }
 
@@ -60,6 +64,7 @@ where
         store: GenericStore<K, V, D>,
         region_manager: Arc<RegionManager<D>>,
         metrics: Arc<Metrics>,
+        cpu_budget: CpuBudgetHandle,
         stop_rx: broadcast::Receiver<()>,
     ) -> Self {
         Self {
@@ -67,6 +72,7 @@ where
             store,
             region_manager,
             metrics,
+            cpu_budget,
             stop_rx,
         }
     }
@@ -104,6 +110,9 @@ where
 
         let region = self.region_manager.region(&region_id);
 
+        // Keep the indices of the region readable during the grace period.
+        self.region_manager.wait_reclaim_grace_period().await;
+
         // step 1: drop indices
         let indices = self.store.catalog().take_region(&region_id);
 
@@ -118,23 +127,43 @@ where
             }
         }
 
+        // The expire at is kept by the reinserted entries, and the expired ones are not reinserted.
+        let expire_ats: HashMap<_, _> = indices
+            .iter()
+            .filter_map(|(key, item)| item.expire_at().map(|expire_at| (key.clone(), expire_at)))
+            .collect();
+
         // step 2: do reinsertion
         let reinsert = || {
             let region = region.clone();
             let metrics = self.metrics.clone();
             let reinsertions = self.store.reinsertions().clone();
+            let checksum = self.store.is_checksum_enabled();
+            let cpu_budget = self.cpu_budget.clone();
 
             tracing::info!("[reclaimer] begin reinsertion, region: {}", region_id);
 
             async move {
-                let mut iter = match RegionEntryIter::<K, V, D>::open(region).await {
+                let mut iter = match RegionEntryIter::<K, V, D>::open(region, checksum).await {
                     Ok(Some(iter)) => iter,
                     Ok(None) => return Ok(true),
                     Err(e) => return Err(e),
                 };
 
-                while let Some((key, value, len)) = iter.next_kv().await? {
+                loop {
+                    // Decompression and checksum of the entries consume the cpu budget.
+                    let guard = cpu_budget.acquire().await;
+                    let Some((key, value, len)) = iter.next_kv().await? else {
+                        break;
+                    };
+                    guard.release().await;
+
                     let key = Arc::new(key);
+                    let expire_at = expire_ats.get(&key).copied();
+                    if expire_at.is_some_and(|expire_at| expire_at <= SystemTime::now()) {
+                        continue;
+                    }
+
                     // Judge by reinsertion policy.
                     let mut judges = Judges::new(reinsertions.len());
                     for (index, reinsertion) in reinsertions.iter().enumerate() {
@@ -147,6 +176,9 @@ where
 
                     let mut writer = self.store.writer(key);
                     writer.set_skippable();
+                    if let Some(expire_at) = expire_at {
+                        writer.set_expire_at(expire_at);
+                    }
 
                     if !writer.judge() {
                         continue;
@@ -186,9 +218,10 @@ where
         (&mut buf[..]).put_slice(&vec![0; align]);
         let (res, _buf) = region.device().write(buf, .., region_id, 0).await;
         res?;
+        region.advance_generation();
 
-        // step 4: send clean region
-        self.region_manager.clean_regions().release(region_id);
+        // step 4: discard region and send clean region
+        self.region_manager.release_reclaimed(region_id).await;
 
         tracing::info!("[reclaimer] finish reclaim task, region: {}", region_id);
 
@@ -200,3 +233,267 @@ where
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, path::PathBuf};
+
+    use foyer_memory::FifoConfig;
+
+    use super::*;
+    use crate::{
+        buffer::{FlushErrorPolicy, PaddingPolicy},
+        catalog::{CatalogRetryPolicy, Index, WriteConflictPolicy},
+        compress::Compression,
+        cpu_budget::CpuBudget,
+        device::{
+            executor::IoExecutor,
+            fs::{FsDevice, FsDeviceConfig, RegionOpenErrorPolicy},
+        },
+        error::Error,
+        flusher::{ChannelDropPolicy, QueueFullPolicy},
+        generic::{ChecksumAlgorithm, GenericStoreConfig},
+        metrics::get_metrics_recorder,
+        region::{RegionHeader, UnknownRegionVersionPolicy},
+        storage::StorageExt,
+    };
+
+    type TestStore = GenericStore<u64, Vec<u8>, FsDevice>;
+
+    #[tokio::test]
+    async fn test_reclaim_grace_period() {
+        const KB: usize = 1024;
+        const MB: usize = 1024 * 1024;
+
+        let tempdir = tempfile::tempdir().unwrap();
+
+        let config = GenericStoreConfig {
+            name: "".to_string(),
+            eviction_config: FifoConfig {}.into(),
+            device_config: FsDeviceConfig {
+                dir: PathBuf::from(tempdir.path()),
+                capacity: 16 * MB,
+                file_size: 4 * MB,
+                align: 4 * KB,
+                io_size: 16 * KB,
+                io_executor: IoExecutor::default(),
+                on_region_open_error: RegionOpenErrorPolicy::default(),
+                write_io_size: None,
+                read_io_size: None,
+                extra_dirs: vec![],
+                direct_io: true,
+            },
+            catalog_shards: 1,
+            admissions: vec![],
+            reinsertions: vec![],
+            flushers: 1,
+            reclaimers: 0,
+            recover_concurrency: 2,
+            clean_region_threshold: 1,
+            compression: Compression::None,
+            checksum: true,
+            recent_inserts_capacity: 0,
+            max_key_size: None,
+            region_compression: None,
+            intra_block_align: None,
+            catalog_snapshot_path: None,
+            reclaim_grace_period: Duration::from_millis(500),
+            compression_observer: None,
+            unknown_region_version_policy: UnknownRegionVersionPolicy::default(),
+            block_compression: None,
+            background_cpu_cores: None,
+            metrics_recorder: None,
+            write_conflict_policy: WriteConflictPolicy::default(),
+            catalog_ops_limit: None,
+            first_entry_offset: None,
+            verify_region_magic_on_read: false,
+            self_test_on_open: false,
+            scrub_throughput_limit: None,
+            write_coalescing_interval: None,
+            max_writing_regions: None,
+            size_padding: PaddingPolicy::default(),
+            store_key_hash_only: false,
+            zstd_level: 0,
+            max_concurrent_decompressions: None,
+            checksum_algorithm: ChecksumAlgorithm::default(),
+            key_prefix: None,
+            flush_error_policy: FlushErrorPolicy::default(),
+            admission_observer: None,
+            max_region_age: None,
+            channel_drop_policy: ChannelDropPolicy::default(),
+            prefix_compressions: HashMap::new(),
+            io_bytes_in_flight_limit: None,
+            flusher_queue_capacity: None,
+            queue_full_policy: QueueFullPolicy::default(),
+            flush_alignment: None,
+            adaptive_compression: false,
+            scan_read_ahead: 2,
+            verify_checksum_on_recover: false,
+            catalog_backend: None,
+            catalog_retry_policy: CatalogRetryPolicy::default(),
+        };
+
+        let store = <TestStore as Storage<_, _>>::open(config).await.unwrap();
+        store.insert(0, vec![0; 16 * KB]).await.unwrap();
+        store.close().await.unwrap();
+
+        let Index::Region { view } = store.catalog().get(&0).unwrap().consume().1 else {
+            panic!("entry must be indexed by region after flushed");
+        };
+        let region_id = *view.id();
+        drop(view);
+        store.region_manager().eviction_push(region_id);
+
+        let metrics = Arc::new(Metrics::new(get_metrics_recorder(), ""));
+        let (_stop_tx, stop_rx) = broadcast::channel(1);
+        let reclaimer = Reclaimer::new(
+            16,
+            store.clone(),
+            store.region_manager().clone(),
+            metrics.clone(),
+            CpuBudgetHandle::new(
+                Arc::new(CpuBudget::new(None)),
+                metrics.background_cpu_seconds_reclaim.clone(),
+            ),
+            stop_rx,
+        );
+        let handle = tokio::spawn(async move { reclaimer.handle().await.unwrap() });
+
+        // The region is popped for reclamation, but a slow reader can still read it within the grace period.
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(!handle.is_finished());
+        assert_eq!(store.get(&0).await.unwrap().unwrap().value(), &vec![0; 16 * KB]);
+
+        handle.await.unwrap();
+        assert!(store.get(&0).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_region_generation() {
+        const KB: usize = 1024;
+        const MB: usize = 1024 * 1024;
+
+        let tempdir = tempfile::tempdir().unwrap();
+
+        let config = GenericStoreConfig {
+            name: "".to_string(),
+            eviction_config: FifoConfig {}.into(),
+            device_config: FsDeviceConfig {
+                dir: PathBuf::from(tempdir.path()),
+                capacity: 16 * MB,
+                file_size: 4 * MB,
+                align: 4 * KB,
+                io_size: 16 * KB,
+                io_executor: IoExecutor::default(),
+                on_region_open_error: RegionOpenErrorPolicy::default(),
+                write_io_size: None,
+                read_io_size: None,
+                extra_dirs: vec![],
+                direct_io: true,
+            },
+            catalog_shards: 1,
+            admissions: vec![],
+            reinsertions: vec![],
+            flushers: 1,
+            reclaimers: 0,
+            recover_concurrency: 2,
+            clean_region_threshold: 1,
+            compression: Compression::None,
+            checksum: true,
+            recent_inserts_capacity: 0,
+            max_key_size: None,
+            region_compression: None,
+            intra_block_align: None,
+            catalog_snapshot_path: None,
+            reclaim_grace_period: Duration::ZERO,
+            compression_observer: None,
+            unknown_region_version_policy: UnknownRegionVersionPolicy::default(),
+            block_compression: None,
+            background_cpu_cores: None,
+            metrics_recorder: None,
+            write_conflict_policy: WriteConflictPolicy::default(),
+            catalog_ops_limit: None,
+            first_entry_offset: None,
+            verify_region_magic_on_read: false,
+            self_test_on_open: false,
+            scrub_throughput_limit: None,
+            write_coalescing_interval: None,
+            max_writing_regions: None,
+            size_padding: PaddingPolicy::default(),
+            store_key_hash_only: false,
+            zstd_level: 0,
+            max_concurrent_decompressions: None,
+            checksum_algorithm: ChecksumAlgorithm::default(),
+            key_prefix: None,
+            flush_error_policy: FlushErrorPolicy::default(),
+            admission_observer: None,
+            max_region_age: None,
+            channel_drop_policy: ChannelDropPolicy::default(),
+            prefix_compressions: HashMap::new(),
+            io_bytes_in_flight_limit: None,
+            flusher_queue_capacity: None,
+            queue_full_policy: QueueFullPolicy::default(),
+            flush_alignment: None,
+            adaptive_compression: false,
+            scan_read_ahead: 2,
+            verify_checksum_on_recover: false,
+            catalog_backend: None,
+            catalog_retry_policy: CatalogRetryPolicy::default(),
+        };
+
+        let store = <TestStore as Storage<_, _>>::open(config.clone()).await.unwrap();
+        store.insert(0, vec![0; 16 * KB]).await.unwrap();
+        store.close().await.unwrap();
+
+        let Index::Region { view } = store.catalog().get(&0).unwrap().consume().1 else {
+            panic!("entry must be indexed by region after flushed");
+        };
+        let region_id = *view.id();
+        let region = store.region_manager().region(&region_id).clone();
+        // The flusher advances the generation of the region it claims.
+        assert_eq!(*view.generation(), 1);
+        assert!(region.load(view.clone()).await.unwrap().is_some());
+
+        // The generation is recorded in the region header.
+        let header = region.load_range(0..4 * KB).await.unwrap().unwrap();
+        assert_eq!(RegionHeader::read(header.as_ref()).unwrap().generation, 1);
+
+        // The generation is restored from the region header after the store is reopened.
+        let reopened = <TestStore as Storage<_, _>>::open(config).await.unwrap();
+        assert_eq!(reopened.region_generation(region_id), 1);
+        reopened.close().await.unwrap();
+
+        // Recycle the region while a reference to it is still kept, and overwrite it.
+        assert_eq!(region.advance_generation(), 2);
+        let buf = region.device().io_buffer(16 * KB, 16 * KB);
+        let (res, _buf) = region.device().write(buf, .., region_id, *view.offset() as usize).await;
+        res.unwrap();
+
+        // The stale view is treated as a miss.
+        match region.validate(&view) {
+            Err(Error::StaleRegionView { region, view, current }) => {
+                assert_eq!((region, view, current), (region_id, 1, 2));
+            }
+            res => panic!("expected stale region view, got: {:?}", res),
+        }
+        assert!(region.load(view).await.unwrap().is_none());
+
+        // The reclaimer advances the generation of the reclaimed region.
+        store.region_manager().eviction_push(region_id);
+        let metrics = Arc::new(Metrics::new(get_metrics_recorder(), ""));
+        let (_stop_tx, stop_rx) = broadcast::channel(1);
+        let reclaimer = Reclaimer::new(
+            16,
+            store.clone(),
+            store.region_manager().clone(),
+            metrics.clone(),
+            CpuBudgetHandle::new(
+                Arc::new(CpuBudget::new(None)),
+                metrics.background_cpu_seconds_reclaim.clone(),
+            ),
+            stop_rx,
+        );
+        reclaimer.handle().await.unwrap();
+        assert_eq!(store.region_generation(region_id), 3);
+    }
+}
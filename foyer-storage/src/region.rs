@@ -17,48 +17,123 @@ use std::{
     fmt::Debug,
     ops::Range,
     sync::{
-        atomic::{AtomicUsize, Ordering},
+        atomic::{AtomicU64, AtomicUsize, Ordering},
         Arc,
     },
 };
 
 use allocator_api2::vec::Vec as VecA;
 use bytes::{Buf, BufMut};
-use foyer_common::range::RangeBoundsExt;
-use parking_lot::Mutex;
+use foyer_common::{
+    bits::{align_down, align_up, is_aligned},
+    range::RangeBoundsExt,
+};
+use parking_lot::{Mutex, RwLock};
 use tokio::sync::oneshot;
 
 use crate::{
+    buffer::PaddingPolicy,
+    compress::Compression,
     device::{BufferAllocator, Device, DeviceExt},
-    error::Result,
+    error::{Error, Result},
+    io_limit::IoBytesLimiter,
 };
 
 pub type RegionId = u32;
 
 pub const REGION_MAGIC: u64 = 0x19970327;
 
-#[derive(Debug)]
+/// Marks that the entries of the region are compressed per entry.
+const REGION_COMPRESSION_PER_ENTRY: u8 = u8::MAX;
+
+/// Encoded length of the region header fields before the key filter bytes, including the key filter length.
+const REGION_HEADER_FIXED_LEN: usize = 8 + 8 + 1 + 4 + 4 + 1 + 4 + 8 + 4;
+
+/// Count of the bits set in the key filter for each key hash.
+const KEY_FILTER_HASHES: u64 = 4;
+
+/// Bloom filter of the key hashes of the entries in a region, see [`RegionHeader::filter`].
+///
+/// A key hash inserted is always reported as maybe contained, a key hash not inserted may be reported as maybe
+/// contained as well.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyFilter {
+    bits: Vec<u8>,
+}
+
+impl KeyFilter {
+    /// Create an empty filter of `len` bytes.
+    pub fn new(len: usize) -> Self {
+        assert!(len > 0, "key filter length must be > 0");
+        Self { bits: vec![0; len] }
+    }
+
+    pub fn from_bytes(bits: Vec<u8>) -> Self {
+        Self { bits }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bits
+    }
+
+    pub fn insert(&mut self, hash: u128) {
+        for bit in self.positions(hash) {
+            self.bits[bit / 8] |= 1 << (bit % 8);
+        }
+    }
+
+    /// Return `false` only if the key hash is never inserted.
+    pub fn may_contain(&self, hash: u128) -> bool {
+        self.positions(hash)
+            .all(|bit| self.bits[bit / 8] & (1 << (bit % 8)) != 0)
+    }
+
+    /// Positions of the bits of the key hash, by double hashing on the two halves of the key hash.
+    fn positions(&self, hash: u128) -> impl Iterator<Item = usize> {
+        let (h1, h2) = (hash as u64, (hash >> 64) as u64);
+        let len = self.bits.len() as u64 * 8;
+        (0..KEY_FILTER_HASHES).map(move |i| (h1.wrapping_add(i.wrapping_mul(h2)) % len) as usize)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Version {
     V1,
+    /// V2 records the region level compression and the intra block align in the region header.
+    V2,
+    /// V3 records the offset of the first entry in the region header.
+    V3,
+    /// V4 records the size padding policy of the values in the region header.
+    V4,
+    /// V5 records the flush alignment in the region header.
+    V5,
+    /// V6 records the generation of the region in the region header.
+    V6,
+    /// V7 records the key filter of the region in the region header.
+    V7,
 }
 
 impl Version {
     pub fn latest() -> Self {
-        Self::V1
+        Self::V7
     }
 
-    pub fn to_u64(&self) -> u64 {
+    pub fn to_u64(self) -> u64 {
         match self {
             Version::V1 => 1,
+            Version::V2 => 2,
+            Version::V3 => 3,
+            Version::V4 => 4,
+            Version::V5 => 5,
+            Version::V6 => 6,
+            Version::V7 => 7,
         }
     }
 }
 
 impl From<Version> for u64 {
     fn from(value: Version) -> Self {
-        match value {
-            Version::V1 => 1,
-        }
+        value.to_u64()
     }
 }
 
@@ -68,23 +143,81 @@ impl TryFrom<u64> for Version {
     fn try_from(value: u64) -> std::result::Result<Self, Self::Error> {
         match value {
             1 => Ok(Self::V1),
+            2 => Ok(Self::V2),
+            3 => Ok(Self::V3),
+            4 => Ok(Self::V4),
+            5 => Ok(Self::V5),
+            6 => Ok(Self::V6),
+            7 => Ok(Self::V7),
             v => Err(anyhow::anyhow!("invalid region format version: {}", v)),
         }
     }
 }
 
+/// Policy for the regions with an unknown (newer) format version found during recovery, e.g. after a downgrade.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum UnknownRegionVersionPolicy {
+    /// Skip the region with a warning and reuse it as a clean region.
+    #[default]
+    SkipWithWarning,
+    /// Abort opening the store.
+    Abort,
+}
+
 #[derive(Debug)]
 pub struct RegionHeader {
     /// magic number to decide a valid region
     pub magic: u64,
     /// format version
     pub version: Version,
+    /// compression applied to all entries of the region, `None` if compressed per entry
+    pub compression: Option<Compression>,
+    /// alignment of entries packed in a device block, `None` if entries are aligned to device align
+    pub intra_block_align: Option<u32>,
+    /// offset of the first entry, `None` if the first entry follows the region header in the next device block
+    pub first_entry_offset: Option<u32>,
+    /// padding applied to the values of the region
+    pub size_padding: PaddingPolicy,
+    /// alignment of the flushes, `None` if flushes are aligned to device align
+    ///
+    /// The gap between the last entry of a flush and the next flush alignment boundary is zero padding.
+    pub flush_alignment: Option<u32>,
+    /// generation of the region when it is claimed by the flush buffer, see [`Region::generation`]
+    pub generation: u64,
+    /// filter of the key hashes of the region entries, `None` if not built yet
+    ///
+    /// The filter is built by [`crate::storage::Storage::rebuild_region_filters`] after the region is sealed, and takes
+    /// the rest of the first device block of the region. It is loaded on recovery, and the lookups skip the device
+    /// read of the keys it rejects.
+    pub filter: Option<KeyFilter>,
 }
 
 impl RegionHeader {
     pub fn write(&self, mut buf: &mut [u8]) {
         buf.put_u64(self.magic);
         buf.put_u64(self.version.to_u64());
+        buf.put_u8(
+            self.compression
+                .map(|compression| compression.to_u8())
+                .unwrap_or(REGION_COMPRESSION_PER_ENTRY),
+        );
+        buf.put_u32(self.intra_block_align.unwrap_or(0));
+        buf.put_u32(self.first_entry_offset.unwrap_or(0));
+        buf.put_u8(self.size_padding.to_u8());
+        buf.put_u32(self.flush_alignment.unwrap_or(0));
+        buf.put_u64(self.generation);
+        match self.filter.as_ref() {
+            Some(filter) => {
+                buf.put_u32(filter.as_bytes().len() as u32);
+                buf.put_slice(filter.as_bytes());
+            }
+            None => buf.put_u32(0),
+        }
+    }
+
+    /// Max bytes of the key filter held by the region header in the first device block of `align`.
+    pub fn filter_capacity(align: usize) -> usize {
+        align.saturating_sub(REGION_HEADER_FIXED_LEN)
     }
 
     pub fn read(mut buf: &[u8]) -> std::result::Result<Self, anyhow::Error> {
@@ -97,7 +230,82 @@ impl RegionHeader {
             ));
         }
         let version = buf.get_u64().try_into()?;
-        Ok(Self { magic, version })
+        let (compression, intra_block_align) = match version {
+            Version::V1 => (None, None),
+            Version::V2 | Version::V3 | Version::V4 | Version::V5 | Version::V6 | Version::V7 => {
+                let compression = match buf.get_u8() {
+                    REGION_COMPRESSION_PER_ENTRY => None,
+                    v => Some(Compression::try_from(v)?),
+                };
+                let intra_block_align = match buf.get_u32() {
+                    0 => None,
+                    v => Some(v),
+                };
+                (compression, intra_block_align)
+            }
+        };
+        let first_entry_offset = match version {
+            Version::V1 | Version::V2 => None,
+            Version::V3 | Version::V4 | Version::V5 | Version::V6 | Version::V7 => match buf.get_u32() {
+                0 => None,
+                v => Some(v),
+            },
+        };
+        let size_padding = match version {
+            Version::V1 | Version::V2 | Version::V3 => PaddingPolicy::None,
+            Version::V4 | Version::V5 | Version::V6 | Version::V7 => PaddingPolicy::try_from(buf.get_u8())?,
+        };
+        let flush_alignment = match version {
+            Version::V1 | Version::V2 | Version::V3 | Version::V4 => None,
+            Version::V5 | Version::V6 | Version::V7 => match buf.get_u32() {
+                0 => None,
+                v => Some(v),
+            },
+        };
+        let generation = match version {
+            Version::V1 | Version::V2 | Version::V3 | Version::V4 | Version::V5 => 0,
+            Version::V6 | Version::V7 => buf.get_u64(),
+        };
+        let filter = match version {
+            Version::V1 | Version::V2 | Version::V3 | Version::V4 | Version::V5 | Version::V6 => None,
+            Version::V7 => match buf.get_u32() as usize {
+                0 => None,
+                len if len > buf.remaining() => {
+                    return Err(anyhow::anyhow!(
+                        "key filter length {} exceeds the region header, remaining: {}",
+                        len,
+                        buf.remaining()
+                    ))
+                }
+                len => Some(KeyFilter::from_bytes(buf[..len].to_vec())),
+            },
+        };
+        Ok(Self {
+            magic,
+            version,
+            compression,
+            intra_block_align,
+            first_entry_offset,
+            size_padding,
+            flush_alignment,
+            generation,
+            filter,
+        })
+    }
+
+    /// Return `true` if the region header starts with the region magic, without parsing the rest of it.
+    pub fn verify_magic(mut buf: &[u8]) -> bool {
+        buf.get_u64() == REGION_MAGIC
+    }
+
+    /// Return the raw format version if the region header is valid but its version is newer than
+    /// [`Version::latest()`], which can not be read by the current build.
+    pub fn unknown_version(mut buf: &[u8]) -> Option<u64> {
+        if buf.get_u64() != REGION_MAGIC {
+            return None;
+        }
+        let version = buf.get_u64();
+        (version > Version::latest().to_u64()).then_some(version)
     }
 }
 
@@ -122,20 +330,36 @@ where
 
     device: D,
 
+    /// Limit of the total bytes of the in-flight device I/Os, shared with the flush buffers.
+    io_limiter: IoBytesLimiter,
+
     refs: Arc<AtomicUsize>,
+
+    /// Incremented each time the region is claimed by a flusher or reclaimed, the views of the previous generations
+    /// are stale.
+    ///
+    /// The generation is recorded in the region header on claim, and restored from it after the store is reopened.
+    generation: Arc<AtomicU64>,
+
+    /// Key filter of the entries of the current generation, loaded from the region header on recovery or set when it
+    /// is rebuilt, `None` if not built.
+    filter: Arc<RwLock<Option<Arc<KeyFilter>>>>,
 }
 
 impl<D> Region<D>
 where
     D: Device,
 {
-    pub fn new(id: RegionId, device: D) -> Self {
+    pub fn new(id: RegionId, device: D, io_limiter: IoBytesLimiter) -> Self {
         let inner = RegionInner { waits: BTreeMap::new() };
         Self {
             id,
             inner: Arc::new(Mutex::new(inner)),
             device,
+            io_limiter,
             refs: Arc::new(AtomicUsize::default()),
+            generation: Arc::new(AtomicU64::default()),
+            filter: Arc::new(RwLock::new(None)),
         }
     }
 
@@ -145,6 +369,7 @@ where
             id: self.id,
             offset,
             len,
+            generation: self.generation(),
             refs: Arc::clone(&self.refs),
         }
     }
@@ -153,14 +378,85 @@ where
         &self.refs
     }
 
+    /// Current generation of the region.
+    pub fn generation(&self) -> u64 {
+        self.generation.load(Ordering::Acquire)
+    }
+
+    /// Advance the generation after the region is reclaimed or when it is claimed, before it is reused.
+    ///
+    /// Returns the new generation.
+    pub fn advance_generation(&self) -> u64 {
+        // The key filter only covers the entries of the previous generation.
+        *self.filter.write() = None;
+        self.generation.fetch_add(1, Ordering::AcqRel) + 1
+    }
+
+    /// Key filter of the entries of the region, `None` if not built.
+    pub fn filter(&self) -> Option<Arc<KeyFilter>> {
+        self.filter.read().clone()
+    }
+
+    /// Set the key filter built from all the entries of the current generation.
+    pub fn set_filter(&self, filter: Option<KeyFilter>) {
+        *self.filter.write() = filter.map(Arc::new);
+    }
+
+    /// Restore the generation recorded in the region header on recovery, before any view of the region is taken.
+    pub fn recover_generation(&self, generation: u64) {
+        self.generation.fetch_max(generation, Ordering::AcqRel);
+    }
+
+    /// Return [`Error::StaleRegionView`] if the view is taken before the region is reclaimed.
+    pub fn validate(&self, view: &RegionView) -> Result<()> {
+        let current = self.generation();
+        if view.generation != current {
+            return Err(Error::StaleRegionView {
+                region: self.id,
+                view: view.generation,
+                current,
+            });
+        }
+        Ok(())
+    }
+
     /// Load region data by view from device.
+    ///
+    /// If the view is not aligned to the device align (entries packed in a block), the aligned range is loaded and
+    /// the data of the view is copied out.
+    ///
+    /// Returns `None` if the view is stale, i.e. the region is reclaimed or reused after the view is taken, even
+    /// during the load.
     // TODO(MrCroxx): use `expect` after `lint_reasons` is stable.
     #[allow(clippy::type_complexity)]
     #[tracing::instrument(skip(self, view))]
     pub async fn load(&self, view: RegionView) -> Result<Option<Arc<VecA<u8, D::IoBufferAllocator>>>> {
-        let res = self
-            .load_range(view.offset as usize..view.offset as usize + view.len as usize)
-            .await;
+        if self.validate(&view).is_err() {
+            return Ok(None);
+        }
+
+        let align = self.device.align();
+        let start = view.offset as usize;
+        let end = start + view.len as usize;
+
+        let res = if is_aligned(align, start) && is_aligned(align, end) {
+            self.load_range(start..end).await
+        } else {
+            let align_start = align_down(align, start);
+            let align_end = align_up(align, end);
+            match self.load_range(align_start..align_end).await {
+                Ok(Some(slice)) => {
+                    let mut buf = self.device.io_buffer(0, end - start);
+                    buf.extend_from_slice(&slice[start - align_start..end - align_start]);
+                    Ok(Some(Arc::new(buf)))
+                }
+                res => res,
+            }
+        };
+        // The region may be reclaimed during the load.
+        if self.validate(&view).is_err() {
+            return Ok(None);
+        }
         // drop view after load finish
         drop(view);
         res
@@ -197,10 +493,41 @@ where
             return rx.await.map_err(anyhow::Error::from)?.map(Some);
         }
 
+        /// Notify the joined loads on drop, in case the load is cancelled while reading, e.g. by a timeout.
+        /// Otherwise the joined loads and the following loads of the same range would wait forever.
+        struct Loading<'a, D: Device> {
+            region: &'a Region<D>,
+            range: (usize, usize),
+            finished: bool,
+        }
+
+        impl<D: Device> Drop for Loading<'_, D> {
+            fn drop(&mut self) {
+                if self.finished {
+                    return;
+                }
+                if let Some(txs) = self.region.inner.lock().waits.remove(&self.range) {
+                    for tx in txs {
+                        let _ = tx.send(Err(anyhow::anyhow!("cancelled by previous load").into()));
+                    }
+                }
+            }
+        }
+
         // otherwise, read from device
         let region = self.id;
 
-        let buf = match self.device.load(region, range.start..range.end).await {
+        let mut loading = Loading {
+            region: self,
+            range: (range.start, range.end),
+            finished: false,
+        };
+        let permit = self.io_limiter.acquire(range.size().unwrap()).await;
+        let res = self.device.load(region, range.start..range.end).await;
+        drop(permit);
+        loading.finished = true;
+
+        let buf = match res {
             Err(e) => {
                 self.cleanup(range.start, range.end)?;
                 return Err(e.into());
@@ -243,6 +570,7 @@ pub struct RegionView {
     id: RegionId,
     offset: u32,
     len: u32,
+    generation: u64,
     refs: Arc<AtomicUsize>,
 }
 
@@ -253,6 +581,7 @@ impl Clone for RegionView {
             id: self.id,
             offset: self.offset,
             len: self.len,
+            generation: self.generation,
             refs: Arc::clone(&self.refs),
         }
     }
@@ -277,7 +606,31 @@ impl RegionView {
         &self.len
     }
 
+    /// Generation of the region when the view is taken, see [`Region::validate`].
+    pub fn generation(&self) -> &u64 {
+        &self.generation
+    }
+
     pub fn refs(&self) -> &Arc<AtomicUsize> {
         &self.refs
     }
+
+    /// View of the sub-range of the view, `range` is relative to the offset of the view.
+    ///
+    /// The sub-view holds a reference to the region as the view does, which is useful to read only a part of an entry.
+    pub fn slice(&self, range: Range<u32>) -> RegionView {
+        assert!(
+            range.start <= range.end && range.end <= self.len,
+            "range {range:?} out of the view len {}",
+            self.len
+        );
+        self.refs.fetch_add(1, Ordering::SeqCst);
+        Self {
+            id: self.id,
+            offset: self.offset + range.start,
+            len: range.end - range.start,
+            generation: self.generation,
+            refs: Arc::clone(&self.refs),
+        }
+    }
 }
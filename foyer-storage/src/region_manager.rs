@@ -12,15 +12,26 @@
 //  See the License for the specific language governing permissions and
 //  limitations under the License.
 
-use std::fmt::Debug;
+use std::{
+    collections::HashSet,
+    fmt::Debug,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
 
 use foyer_common::async_queue::AsyncQueue;
 use foyer_memory::{Cache, CacheBuilder, EvictionConfig};
 
 use itertools::Itertools;
+use parking_lot::Mutex;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
 
 use crate::{
     device::Device,
+    io_limit::IoBytesLimiter,
     region::{Region, RegionId},
 };
 
@@ -32,8 +43,35 @@ where
     clean_regions: AsyncQueue<RegionId>,
 
     regions: Vec<Region<D>>,
+    /// Count of the regions in use, the trailing regions beyond are retired by [`RegionManager::retire`].
+    active: AtomicUsize,
 
     eviction: Cache<RegionId, ()>,
+    /// Regions with entries dropped on failed catalog backend inserts, reclaimed before the other evictable regions.
+    orphaned: Mutex<HashSet<RegionId>>,
+
+    /// Duration that a popped evictable region remains readable before it is physically reclaimed.
+    reclaim_grace_period: Duration,
+
+    /// Limit of the regions being written concurrently by the flushers, `None` if unlimited.
+    writing_limit: Option<Arc<Semaphore>>,
+    /// Count of the regions being written.
+    writing: Arc<AtomicUsize>,
+}
+
+/// Held by a flusher while it is writing its region.
+///
+/// See [`RegionManager::acquire_writing`].
+#[derive(Debug)]
+pub struct WritingRegionGuard {
+    _permit: Option<OwnedSemaphorePermit>,
+    writing: Arc<AtomicUsize>,
+}
+
+impl Drop for WritingRegionGuard {
+    fn drop(&mut self) {
+        self.writing.fetch_sub(1, Ordering::Relaxed);
+    }
 }
 
 impl<D> Debug for RegionManager<D>
@@ -44,7 +82,12 @@ where
         f.debug_struct("RegionManager")
             .field("clean_regions", &self.clean_regions)
             .field("regions", &self.regions.len())
+            .field("active", &self.active)
             .field("eviction", &self.eviction)
+            .field("orphaned", &self.orphaned)
+            .field("reclaim_grace_period", &self.reclaim_grace_period)
+            .field("writing_limit", &self.writing_limit)
+            .field("writing", &self.writing)
             .finish()
     }
 }
@@ -53,7 +96,22 @@ impl<D> RegionManager<D>
 where
     D: Device,
 {
-    pub fn new(region_count: usize, eviction_config: EvictionConfig, device: D) -> Self {
+    pub fn new(
+        region_count: usize,
+        eviction_config: EvictionConfig,
+        reclaim_grace_period: Duration,
+        max_writing_regions: Option<usize>,
+        device: D,
+        io_limiter: IoBytesLimiter,
+    ) -> Self {
+        if let Some(max_writing_regions) = max_writing_regions {
+            assert!(
+                max_writing_regions > 0,
+                "max writing regions must be > 0, given: {}",
+                max_writing_regions
+            );
+        }
+
         let clean_regions = AsyncQueue::new();
 
         let eviction = CacheBuilder::new(region_count)
@@ -63,13 +121,18 @@ where
             .build();
 
         let regions = (0..region_count as RegionId)
-            .map(|id| Region::new(id, device.clone()))
+            .map(|id| Region::new(id, device.clone(), io_limiter.clone()))
             .collect_vec();
 
         Self {
             clean_regions,
             regions,
+            active: AtomicUsize::new(region_count),
             eviction,
+            orphaned: Mutex::new(HashSet::new()),
+            reclaim_grace_period,
+            writing_limit: max_writing_regions.map(|limit| Arc::new(Semaphore::new(limit))),
+            writing: Arc::new(AtomicUsize::new(0)),
         }
     }
 
@@ -86,11 +149,106 @@ where
         &self.clean_regions
     }
 
+    /// Discard the content of the reclaimed region on the device and mark it clean.
+    ///
+    /// Discarding is only a hint to the device, the region is marked clean even if it fails.
+    pub async fn release_reclaimed(&self, id: RegionId) {
+        if let Err(e) = self.region(&id).device().discard(id).await {
+            tracing::warn!("[region manager] discard region {} error: {}", id, e);
+        }
+        // The retired region is dropped rather than marked clean.
+        if self.is_retired(id) {
+            return;
+        }
+        self.clean_regions.release(id);
+    }
+
+    /// Count of the regions in use.
+    pub fn active_regions(&self) -> usize {
+        self.active.load(Ordering::Acquire)
+    }
+
+    /// Retire the regions from `regions` on, they are never marked clean again, so the flushers only acquire the
+    /// regions in use since.
+    ///
+    /// The retired regions being written or evictable are left to the caller.
+    pub fn retire(&self, regions: usize) {
+        let active = self.active.fetch_min(regions, Ordering::AcqRel);
+        for id in regions..active {
+            self.clean_regions.remove(&(id as RegionId));
+        }
+    }
+
+    /// A region is retired if it is beyond the regions in use, see [`RegionManager::retire`].
+    pub fn is_retired(&self, id: RegionId) -> bool {
+        id as usize >= self.active_regions()
+    }
+
     pub fn eviction_push(&self, region_id: RegionId) {
         self.eviction.insert(region_id, ());
     }
 
+    /// Pop an evictable region, the sealed orphaned regions are popped first.
     pub fn eviction_pop(&self) -> Option<RegionId> {
-        self.eviction.pop().map(|entry| *entry.key())
+        let mut orphaned = self.orphaned.lock();
+        if let Some(id) = orphaned.iter().copied().find(|id| self.eviction.contains(id)) {
+            orphaned.remove(&id);
+            self.eviction.remove(&id);
+            return Some(id);
+        }
+        let id = self.eviction.pop().map(|entry| *entry.key())?;
+        orphaned.remove(&id);
+        Some(id)
+    }
+
+    /// Remove the region from the evictable regions, return `true` if it was evictable.
+    pub fn eviction_remove(&self, region_id: RegionId) -> bool {
+        let removed = self.eviction.remove(&region_id).is_some();
+        if removed {
+            self.orphaned.lock().remove(&region_id);
+        }
+        removed
+    }
+
+    /// Mark the region orphaned if some of its entries are dropped on failed catalog backend inserts, so it is
+    /// reclaimed before the other evictable regions once sealed.
+    pub fn mark_orphaned(&self, region_id: RegionId) {
+        self.orphaned.lock().insert(region_id);
+    }
+
+    /// A region is sealed if it is fully written and waits in the evictable regions to be reclaimed.
+    pub fn is_sealed(&self, region_id: RegionId) -> bool {
+        self.eviction.contains(&region_id)
+    }
+
+    /// Wait for the reclaim grace period after an evictable region is popped.
+    ///
+    /// The region remains readable during the grace period, which gives slow readers time to finish before the region
+    /// is physically reclaimed.
+    pub async fn wait_reclaim_grace_period(&self) {
+        if !self.reclaim_grace_period.is_zero() {
+            tokio::time::sleep(self.reclaim_grace_period).await;
+        }
+    }
+
+    /// Acquire the permission to write a region, waiting if the max writing regions is reached.
+    ///
+    /// The region is counted as being written until the returned guard is dropped.
+    pub async fn acquire_writing(&self) -> WritingRegionGuard {
+        let permit = match self.writing_limit.as_ref() {
+            Some(limit) => Some(limit.clone().acquire_owned().await.unwrap()),
+            None => None,
+        };
+        self.writing.fetch_add(1, Ordering::Relaxed);
+        WritingRegionGuard {
+            _permit: permit,
+            writing: self.writing.clone(),
+        }
+    }
+
+    /// Count of the regions being written by the flushers.
+    #[cfg(test)]
+    pub fn writing_regions(&self) -> usize {
+        self.writing.load(Ordering::Relaxed)
     }
 }
@@ -12,17 +12,23 @@
 //  See the License for the specific language governing permissions and
 //  limitations under the License.
 
-use std::{borrow::Borrow, fmt::Debug, hash::Hash, marker::PhantomData, sync::Arc};
+use std::{
+    borrow::Borrow, fmt::Debug, hash::Hash, marker::PhantomData, ops::Range, path::Path, sync::Arc, time::SystemTime,
+};
 
 use foyer_common::{
     code::{StorageKey, StorageValue},
     runtime::BackgroundShutdownRuntime,
 };
+use futures::{future::BoxFuture, stream::BoxStream, FutureExt};
+use tokio::io::AsyncWrite;
 
 use crate::{
+    catalog::Sequence,
     compress::Compression,
     error::Result,
-    storage::{CachedEntry, Storage, StorageWriter},
+    region::RegionId,
+    storage::{CachedEntry, CompactionReport, CompactionStrategy, DrainedEntry, ScrubReport, Storage, StorageWriter},
 };
 
 pub struct RuntimeConfigBuilder {
@@ -152,6 +158,14 @@ where
     fn set_compression(&mut self, compression: Compression) {
         self.writer.set_compression(compression)
     }
+
+    fn expire_at(&self) -> Option<SystemTime> {
+        self.writer.expire_at()
+    }
+
+    fn set_expire_at(&mut self, expire_at: SystemTime) {
+        self.writer.set_expire_at(expire_at)
+    }
 }
 
 #[derive(Debug)]
@@ -181,6 +195,18 @@ where
     }
 }
 
+impl<K, V, S> Runtime<K, V, S>
+where
+    K: StorageKey,
+    V: StorageValue,
+    S: Storage<K, V>,
+{
+    /// The store running on the runtime.
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+}
+
 impl<K, V, S> Storage<K, V> for Runtime<K, V, S>
 where
     K: StorageKey,
@@ -231,6 +257,14 @@ where
         }
     }
 
+    async fn insert_batch(&self, entries: Vec<(Arc<K>, Arc<V>)>) -> Result<Vec<Option<CachedEntry<K, V>>>> {
+        let store = self.store.clone();
+        self.runtime
+            .spawn(async move { store.insert_batch(entries).await })
+            .await
+            .unwrap()
+    }
+
     fn exists<Q>(&self, key: &Q) -> crate::error::Result<bool>
     where
         K: Borrow<Q>,
@@ -239,6 +273,18 @@ where
         self.store.exists(key)
     }
 
+    fn touch<Q>(&self, key: &Q) -> crate::error::Result<bool>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.store.touch(key)
+    }
+
+    fn recent_inserts(&self, n: usize) -> Vec<(Arc<K>, Sequence)> {
+        self.store.recent_inserts(n)
+    }
+
     async fn get<Q>(&self, key: &Q) -> Result<Option<CachedEntry<K, V>>>
     where
         K: Borrow<Q>,
@@ -249,6 +295,33 @@ where
         self.runtime.spawn(async move { store.get(&key).await }).await.unwrap()
     }
 
+    async fn get_stream<Q, W>(&self, key: &Q, writer: W) -> Result<bool>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + Send + Sync + 'static + Clone,
+        W: AsyncWrite + Send + Unpin + 'static,
+    {
+        let store = self.store.clone();
+        let key = key.clone();
+        self.runtime
+            .spawn(async move { store.get_stream(&key, writer).await })
+            .await
+            .unwrap()
+    }
+
+    async fn get_range<Q>(&self, key: &Q, range: Range<usize>) -> Result<Option<Vec<u8>>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + Send + Sync + 'static + Clone,
+    {
+        let store = self.store.clone();
+        let key = key.clone();
+        self.runtime
+            .spawn(async move { store.get_range(&key, range).await })
+            .await
+            .unwrap()
+    }
+
     fn remove<Q>(&self, key: &Q) -> crate::error::Result<bool>
     where
         K: Borrow<Q>,
@@ -260,4 +333,69 @@ where
     fn clear(&self) -> crate::error::Result<()> {
         self.store.clear()
     }
+
+    async fn snapshot_catalog(&self, path: impl AsRef<Path> + Send) -> Result<()> {
+        let store = self.store.clone();
+        let path = path.as_ref().to_path_buf();
+        self.runtime
+            .spawn(async move { store.snapshot_catalog(path).await })
+            .await
+            .unwrap()
+    }
+
+    fn entries_since(&self, sequence: Sequence) -> BoxStream<'static, Result<(Arc<K>, Arc<V>, Sequence)>> {
+        self.store.entries_since(sequence)
+    }
+
+    fn scan(&self) -> BoxStream<'static, Result<(K, V)>> {
+        self.store.scan()
+    }
+
+    fn barrier(&self) -> BoxFuture<'static, Result<()>> {
+        let runtime = self.runtime.clone();
+        let barrier = self.store.barrier();
+        async move { runtime.spawn(barrier).await.unwrap() }.boxed()
+    }
+
+    fn drain(&self) -> BoxFuture<'static, Result<Vec<DrainedEntry<K>>>> {
+        let runtime = self.runtime.clone();
+        let drain = self.store.drain();
+        async move { runtime.spawn(drain).await.unwrap() }.boxed()
+    }
+
+    fn scrub(&self, remove_corrupted: bool) -> BoxFuture<'static, Result<ScrubReport<K>>> {
+        let runtime = self.runtime.clone();
+        let scrub = self.store.scrub(remove_corrupted);
+        async move { runtime.spawn(scrub).await.unwrap() }.boxed()
+    }
+
+    fn compact(&self, strategy: CompactionStrategy) -> BoxFuture<'static, Result<CompactionReport>> {
+        let runtime = self.runtime.clone();
+        let compact = self.store.compact(strategy);
+        async move { runtime.spawn(compact).await.unwrap() }.boxed()
+    }
+
+    fn read_region_bytes(&self, region: RegionId) -> BoxFuture<'static, Result<Vec<u8>>> {
+        let runtime = self.runtime.clone();
+        let read = self.store.read_region_bytes(region);
+        async move { runtime.spawn(read).await.unwrap() }.boxed()
+    }
+
+    fn apply_region_bytes(&self, region: RegionId, bytes: Vec<u8>) -> BoxFuture<'static, Result<()>> {
+        let runtime = self.runtime.clone();
+        let apply = self.store.apply_region_bytes(region, bytes);
+        async move { runtime.spawn(apply).await.unwrap() }.boxed()
+    }
+
+    fn shrink(&self, capacity: usize) -> BoxFuture<'static, Result<()>> {
+        let runtime = self.runtime.clone();
+        let shrink = self.store.shrink(capacity);
+        async move { runtime.spawn(shrink).await.unwrap() }.boxed()
+    }
+
+    fn rebuild_region_filters(&self) -> BoxFuture<'static, Result<usize>> {
+        let runtime = self.runtime.clone();
+        let rebuild = self.store.rebuild_region_filters();
+        async move { runtime.spawn(rebuild).await.unwrap() }.boxed()
+    }
 }
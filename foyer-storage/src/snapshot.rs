@@ -0,0 +1,171 @@
+//  Copyright 2024 Foyer Project Authors
+//
+//  Licensed under the Apache License, Version 2.0 (the "License");
+//  you may not use this file except in compliance with the License.
+//  You may obtain a copy of the License at
+//
+//  http://www.apache.org/licenses/LICENSE-2.0
+//
+//  Unless required by applicable law or agreed to in writing, software
+//  distributed under the License is distributed on an "AS IS" BASIS,
+//  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//  See the License for the specific language governing permissions and
+//  limitations under the License.
+
+//! Catalog snapshot, which allows the store to recover the catalog without scanning all regions.
+//!
+//! The snapshot can also be parsed by [`read_catalog_snapshot`] without opening the store, e.g. to build an index of
+//! key to location offline.
+//!
+//! # Format
+//!
+//! The snapshot is [bincode](https://docs.rs/bincode/1)-encoded with the default options, i.e. integers are
+//! little-endian with fixed width, a `Vec` is prefixed with its length as `u64`, an `Option` is a `u8` tag of `0` for
+//! `None` or `1` for `Some` followed by the value, and a `SystemTime` is the seconds as `u64` and the nanoseconds as
+//! `u32` since the unix epoch. The fields are, in order:
+//!
+//! - `magic: u64`: [`CATALOG_SNAPSHOT_MAGIC`], the bytes of `b"foyercat"`.
+//! - `version: u32`: [`CATALOG_SNAPSHOT_VERSION`], bumped on any incompatible change of the following fields.
+//! - `fingerprints: Vec<Option<u64>>`: the fingerprint of each region, which is the sequence of the first entry of the
+//!   region, or `None` if the region is clean. A region is rewritten after reclaimed, so the fingerprint changes if the
+//!   region is rewritten after the snapshot is taken.
+//! - `entries: Vec<(K, u64, u32, u32, u32, Option<u32>, Option<SystemTime>)>`: `(key, sequence, region, offset, len,
+//!   block index, expire at)` of each entry indexed by region, see [`SnapshotEntry`]. The key is bincode-encoded as
+//!   it is in the regions.
+
+use std::{
+    fs::{self, File},
+    io::{BufReader, BufWriter, ErrorKind, Write},
+    path::Path,
+    sync::Arc,
+    time::SystemTime,
+};
+
+use anyhow::anyhow;
+use foyer_common::code::StorageKey;
+use itertools::Itertools;
+
+use crate::{buffer::BufferError, catalog::Sequence, error::Result, region::RegionId};
+
+/// Magic of the catalog snapshot, the bytes of `b"foyercat"` in little-endian.
+pub const CATALOG_SNAPSHOT_MAGIC: u64 = u64::from_le_bytes(*b"foyercat");
+
+/// Version of the catalog snapshot format.
+pub const CATALOG_SNAPSHOT_VERSION: u32 = 3;
+
+/// Entry record as encoded in the snapshot.
+type RawSnapshotEntry<K> = (K, Sequence, RegionId, u32, u32, Option<u32>, Option<SystemTime>);
+
+/// Location and metadata of an entry recorded in the catalog snapshot.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SnapshotEntry<K> {
+    pub key: K,
+    pub sequence: Sequence,
+    /// Region the entry is written to.
+    pub region: RegionId,
+    /// Offset of the entry in the region.
+    pub offset: u32,
+    /// Aligned length of the entry in the region.
+    ///
+    /// If the entry is compressed in a block, the offset and the length are of the block.
+    pub len: u32,
+    /// Index of the entry in the block, `None` unless the entry is compressed in a block.
+    pub block_index: Option<u32>,
+    /// `None` unless the entry is inserted with a ttl.
+    pub expire_at: Option<SystemTime>,
+}
+
+impl<K> From<RawSnapshotEntry<K>> for SnapshotEntry<K> {
+    fn from((key, sequence, region, offset, len, block_index, expire_at): RawSnapshotEntry<K>) -> Self {
+        Self {
+            key,
+            sequence,
+            region,
+            offset,
+            len,
+            block_index,
+            expire_at,
+        }
+    }
+}
+
+/// Catalog snapshot parsed by [`read_catalog_snapshot`].
+#[derive(Debug)]
+pub struct CatalogSnapshot<K> {
+    /// Format version of the snapshot.
+    pub version: u32,
+    /// Fingerprint of each region, `None` if the region is clean.
+    pub fingerprints: Vec<Option<Sequence>>,
+    pub entries: Vec<SnapshotEntry<K>>,
+}
+
+/// Write the catalog snapshot to `path` atomically.
+pub fn write<K>(path: &Path, fingerprints: &[Option<Sequence>], entries: &[RawSnapshotEntry<Arc<K>>]) -> Result<()>
+where
+    K: StorageKey,
+{
+    let entries = entries
+        .iter()
+        .map(|(key, sequence, region, offset, len, index, expire_at)| {
+            (key.as_ref(), *sequence, *region, *offset, *len, *index, *expire_at)
+        })
+        .collect_vec();
+
+    // Write to a temporary file then rename it, so a crash while writing never leaves a broken snapshot.
+    let tmp = path.with_extension("tmp");
+    {
+        let mut writer = BufWriter::new(File::create(&tmp).map_err(BufferError::from)?);
+        bincode::serialize_into(
+            &mut writer,
+            &(CATALOG_SNAPSHOT_MAGIC, CATALOG_SNAPSHOT_VERSION, fingerprints, entries),
+        )
+        .map_err(BufferError::from)?;
+        writer.flush().map_err(BufferError::from)?;
+        writer.get_ref().sync_all().map_err(BufferError::from)?;
+    }
+    fs::rename(&tmp, path).map_err(BufferError::from)?;
+
+    Ok(())
+}
+
+/// Read the catalog snapshot from `path`, with `K` the key type of the store that takes the snapshot.
+///
+/// Returns `None` if the snapshot does not exist.
+pub fn read_catalog_snapshot<K>(path: impl AsRef<Path>) -> Result<Option<CatalogSnapshot<K>>>
+where
+    K: StorageKey,
+{
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(BufferError::from(e).into()),
+    };
+
+    let mut reader = BufReader::new(file);
+    let magic: u64 = bincode::deserialize_from(&mut reader).map_err(BufferError::from)?;
+    if magic != CATALOG_SNAPSHOT_MAGIC {
+        return Err(anyhow!(
+            "catalog snapshot magic mismatch, expected: {}, got: {}",
+            CATALOG_SNAPSHOT_MAGIC,
+            magic
+        )
+        .into());
+    }
+    let version: u32 = bincode::deserialize_from(&mut reader).map_err(BufferError::from)?;
+    if version > CATALOG_SNAPSHOT_VERSION {
+        return Err(anyhow!(
+            "catalog snapshot version {} is newer than the supported version {}",
+            version,
+            CATALOG_SNAPSHOT_VERSION
+        )
+        .into());
+    }
+    let (fingerprints, entries): (Vec<Option<Sequence>>, Vec<RawSnapshotEntry<K>>) =
+        bincode::deserialize_from(&mut reader).map_err(BufferError::from)?;
+
+    Ok(Some(CatalogSnapshot {
+        version,
+        fingerprints,
+        entries: entries.into_iter().map(SnapshotEntry::from).collect(),
+    }))
+}
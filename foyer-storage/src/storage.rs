@@ -12,12 +12,78 @@
 //  See the License for the specific language governing permissions and
 //  limitations under the License.
 
-use std::{borrow::Borrow, fmt::Debug, hash::Hash, ops::Deref, sync::Arc};
+use std::{
+    borrow::Borrow,
+    fmt::Debug,
+    hash::Hash,
+    ops::{Deref, Range},
+    path::Path,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
 
 use foyer_common::code::{StorageKey, StorageValue};
-use futures::Future;
+use futures::{future::BoxFuture, stream::BoxStream, Future};
+use tokio::io::AsyncWrite;
+
+use crate::{
+    catalog::Sequence,
+    compress::Compression,
+    error::{Error, Result},
+    region::RegionId,
+};
+
+/// Entry written during a drain, see [`Storage::drain`].
+///
+/// `(key, sequence, region, offset)`
+pub type DrainedEntry<K> = (Arc<K>, Sequence, RegionId, usize);
+
+/// Entry with a mismatched checksum found by a scrub, see [`Storage::scrub`].
+///
+/// `(key, sequence, region, offset)`
+pub type CorruptedEntry<K> = (Arc<K>, Sequence, RegionId, usize);
+
+/// Result of [`Storage::scrub`].
+#[derive(Debug)]
+pub struct ScrubReport<K> {
+    /// Count of the scrubbed regions.
+    pub regions: usize,
+    /// Count of the verified entries.
+    pub entries: usize,
+    /// Entries with mismatched checksums, ordered by location.
+    pub corrupted: Vec<CorruptedEntry<K>>,
+}
+
+impl<K> Default for ScrubReport<K> {
+    fn default() -> Self {
+        Self {
+            regions: 0,
+            entries: 0,
+            corrupted: vec![],
+        }
+    }
+}
+
+/// Strategy of [`Storage::compact`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompactionStrategy {
+    /// Rewrite the live entries that are indexed in the regions longer than `max_age` ago into fresh regions, oldest
+    /// first, so that all data has a similar age and a few ancient regions don't pin stale entries indefinitely.
+    ///
+    /// The age of an entry restarts when it is rewritten or recovered after the store is reopened.
+    AgeBalance { max_age: Duration },
+}
 
-use crate::{compress::Compression, error::Result};
+/// Result of [`Storage::compact`].
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct CompactionReport {
+    /// Count of the entries selected by the strategy.
+    pub selected: usize,
+    /// Count of the entries rewritten into fresh regions.
+    pub relocated: usize,
+    /// Count of the selected entries that are overwritten, removed or not rewritten, e.g. no clean region available.
+    pub skipped: usize,
+}
 
 #[derive(Debug)]
 pub enum CachedEntry<K, V>
@@ -104,6 +170,11 @@ where
 
     fn set_compression(&mut self, compression: Compression);
 
+    /// Time after which the entry is treated as a miss, `None` means the entry never expires.
+    fn expire_at(&self) -> Option<SystemTime>;
+
+    fn set_expire_at(&mut self, expire_at: SystemTime);
+
     fn finish<AV>(self, value: AV) -> impl Future<Output = Result<Option<CachedEntry<K, V>>>> + Send
     where
         AV: Into<Arc<V>> + Send + 'static;
@@ -117,6 +188,9 @@ where
     type Config: Send + Clone + Debug;
     type Writer: StorageWriter<K, V>;
 
+    /// Open the storage, the entries persisted by the previous runs are recovered before it returns.
+    ///
+    /// The recovery scans the regions and rebuilds the catalog, there is no separate call to recover the storage.
     #[must_use]
     fn open(config: Self::Config) -> impl Future<Output = Result<Self>> + Send;
 
@@ -129,23 +203,169 @@ where
     where
         AK: Into<Arc<K>> + Send + 'static;
 
+    /// Insert the entries in order, the later entry of the same key wins.
+    ///
+    /// The admitted entries are sent to the flushers together, so that a flusher writes them in a row and updates the
+    /// catalog once. Returns the cached entry of each given entry in order, or `None` if it is not inserted.
+    #[must_use]
+    fn insert_batch(
+        &self,
+        entries: Vec<(Arc<K>, Arc<V>)>,
+    ) -> impl Future<Output = Result<Vec<Option<CachedEntry<K, V>>>>> + Send;
+
     fn exists<Q>(&self, key: &Q) -> Result<bool>
     where
         K: Borrow<Q>,
         Q: Hash + Eq + ?Sized;
 
+    /// Whether the key is cached, answered by the in-memory catalog without reading or decompressing from the device.
+    ///
+    /// Only the latest entry of a key is indexed, and the entries of an evicted region are unindexed before the region
+    /// is reused, so neither a superseded entry nor an evicted entry counts. Nor does an expired entry. An error of
+    /// [`Storage::exists`] is treated as absent.
+    fn contains<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.exists(key).unwrap_or(false)
+    }
+
+    /// Register an access to the key without reading its value, and return whether the key exists.
+    ///
+    /// The access updates the eviction recency of the region that holds the entry.
+    fn touch<Q>(&self, key: &Q) -> Result<bool>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized;
+
+    /// Return the last (at most) `n` inserted keys with their sequences, from the oldest to the newest.
+    ///
+    /// The result is bounded by the recent inserts capacity of the store, which is disabled by default. It is only a
+    /// lightweight diagnostic and keys may have been evicted or removed since.
+    fn recent_inserts(&self, n: usize) -> Vec<(Arc<K>, Sequence)>;
+
     #[must_use]
     fn get<Q>(&self, key: &Q) -> impl Future<Output = Result<Option<CachedEntry<K, V>>>> + Send
     where
         K: Borrow<Q>,
         Q: Hash + Eq + ?Sized + Send + Sync + 'static + Clone;
 
+    /// Stream the serialized value of `key` into `writer`.
+    ///
+    /// The entry is read from the device in chunks and the value is piped into `writer` chunk by chunk instead of being
+    /// materialized in memory as a whole, which is useful for very large values. A compressed value is collected in
+    /// its compressed form before it is decompressed chunk by chunk. With checksum enabled, the entry is read twice to
+    /// verify the checksum before anything is written.
+    ///
+    /// Returns `true` if the entry is found.
+    #[must_use]
+    fn get_stream<Q, W>(&self, key: &Q, writer: W) -> impl Future<Output = Result<bool>> + Send
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + Send + Sync + 'static + Clone,
+        W: AsyncWrite + Send + Unpin + 'static;
+
+    /// Get the bytes within `range` of the serialized value of `key`.
+    ///
+    /// `range` is clamped to the serialized value len. If the value is stored uncompressed, only the aligned part of
+    /// the entry covering `range` is read from the device, otherwise the whole value is read and decompressed. The
+    /// checksum is not verified for the partial reads.
+    ///
+    /// Returns `None` if the entry is not found.
+    #[must_use]
+    fn get_range<Q>(&self, key: &Q, range: Range<usize>) -> impl Future<Output = Result<Option<Vec<u8>>>> + Send
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + Send + Sync + 'static + Clone;
+
     fn remove<Q>(&self, key: &Q) -> Result<bool>
     where
         K: Borrow<Q>,
         Q: Hash + Eq + ?Sized;
 
     fn clear(&self) -> Result<()>;
+
+    /// Export the catalog as a standalone snapshot to `path`.
+    ///
+    /// The snapshot records `(key, sequence, region, offset, len)` of each entry that has been flushed to a region. A
+    /// store opened with the catalog snapshot path recovers the catalog from a valid snapshot without a full region
+    /// scan.
+    #[must_use]
+    fn snapshot_catalog(&self, path: impl AsRef<Path> + Send) -> impl Future<Output = Result<()>> + Send;
+
+    /// Stream the entries written with sequence greater than `sequence`, in sequence order.
+    ///
+    /// Only the latest version of each key is yielded. The entries to yield are decided when the stream is created,
+    /// entries that are overwritten or removed while streaming are skipped. The sequence of the last yielded entry can
+    /// be used as the watermark of the next call, e.g. for a change data capture feed.
+    #[allow(clippy::type_complexity)]
+    fn entries_since(&self, sequence: Sequence) -> BoxStream<'static, Result<(Arc<K>, Arc<V>, Sequence)>>;
+
+    /// Stream all live entries of the store, region by region.
+    ///
+    /// Only the entries still indexed by the catalog are yielded, the overwritten and removed ones left in the regions
+    /// are skipped. The entries not flushed yet are not yielded, place a [`Storage::barrier`] before scanning to
+    /// include them. An entry that fails to be decoded, e.g. on checksum mismatch, is yielded as an error and the scan
+    /// goes on.
+    fn scan(&self) -> BoxStream<'static, Result<(K, V)>>;
+
+    /// Place a write-order durability barrier.
+    ///
+    /// The returned future resolves after all entries inserted before the barrier are written and synced to the
+    /// device. Entries inserted after the barrier are not written before that, so they never become durable ahead of
+    /// the prior ones. It is weaker than syncing each entry, but much cheaper.
+    ///
+    /// The barrier is placed when called rather than when polled. The following writes are held until the future
+    /// resolves or is dropped.
+    fn barrier(&self) -> BoxFuture<'static, Result<()>>;
+
+    /// Stop accepting writes, flush all pending entries and sync them to the device, e.g. for a handoff to another
+    /// process.
+    ///
+    /// The returned future resolves with the entries written since the drain started, ordered by sequence. Inserts
+    /// are rejected since the drain is called rather than polled, and remain rejected after it resolves.
+    fn drain(&self) -> BoxFuture<'static, Result<Vec<DrainedEntry<K>>>>;
+
+    /// Walk all regions and verify the checksums of the indexed entries, e.g. for a background scrubbing task.
+    ///
+    /// The reads are throttled by the scrub throughput limit of the store, if any. If `remove_corrupted` is `true`, the
+    /// corrupted entries are removed, so the following reads miss rather than fail.
+    fn scrub(&self, remove_corrupted: bool) -> BoxFuture<'static, Result<ScrubReport<K>>>;
+
+    /// Rewrite the live entries selected by the compaction `strategy` into fresh regions.
+    ///
+    /// The rewrites go through the flushers as the insertions do, but are skipped rather than waiting for a clean
+    /// region, so the compaction doesn't block the foreground insertions.
+    fn compact(&self, strategy: CompactionStrategy) -> BoxFuture<'static, Result<CompactionReport>>;
+
+    /// Read the raw bytes of the whole sealed region, aligned to the device, for replicating it to a standby.
+    ///
+    /// Fail with [`Error::RegionNotSealed`] if the region is clean or still being written.
+    fn read_region_bytes(&self, region: RegionId) -> BoxFuture<'static, Result<Vec<u8>>>;
+
+    /// Apply the region bytes read by [`Storage::read_region_bytes`] from the primary to the same region of the
+    /// standby.
+    ///
+    /// The previous content of the region is dropped, then the region is scanned to index the replicated entries, an
+    /// entry newer in the standby wins over the replicated one. The region must be clean or sealed on the standby.
+    fn apply_region_bytes(&self, region: RegionId, bytes: Vec<u8>) -> BoxFuture<'static, Result<()>>;
+
+    /// Shrink the store to `capacity`, e.g. to reclaim the disk space after a traffic decline.
+    ///
+    /// The live entries in the trailing regions beyond `capacity` are relocated into the leading regions, then the
+    /// trailing regions are truncated from the device. The store must be reopened with the shrunk capacity.
+    ///
+    /// `capacity` must be a multiplier of the region size. Fail with [`Error::ShrinkLiveDataExceeded`] if the live
+    /// entries exceed `capacity`.
+    fn shrink(&self, capacity: usize) -> BoxFuture<'static, Result<()>>;
+
+    /// Rebuild the key filters of the sealed regions from their entries, e.g. after a filter is corrupted or on the
+    /// regions written before the filters are introduced.
+    ///
+    /// Only the first device block of each region, which holds the region header and its key filter, is rewritten,
+    /// the entries are left in place. Returns the count of the rebuilt regions.
+    fn rebuild_region_filters(&self) -> BoxFuture<'static, Result<usize>>;
 }
 
 pub trait StorageExt<K, V>: Storage<K, V>
@@ -153,6 +373,29 @@ where
     K: StorageKey,
     V: StorageValue,
 {
+    /// Get the value of the key, or fail with [`Error::Timeout`] if the read doesn't complete within `timeout`.
+    ///
+    /// The read is cancelled on timeout. The I/O buffer is owned by the device operation, so it is still reclaimed
+    /// after the in-flight I/O completes.
+    #[must_use]
+    #[tracing::instrument(skip_all)]
+    fn get_with_timeout<Q>(
+        &self,
+        key: &Q,
+        timeout: Duration,
+    ) -> impl Future<Output = Result<Option<CachedEntry<K, V>>>> + Send
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + Send + Sync + 'static + Clone,
+    {
+        let get = self.get(key);
+        async move {
+            tokio::time::timeout(timeout, get)
+                .await
+                .map_err(|_| Error::Timeout(timeout))?
+        }
+    }
+
     #[must_use]
     #[tracing::instrument(skip_all)]
     fn insert<AK, AV>(&self, key: AK, value: AV) -> impl Future<Output = Result<Option<CachedEntry<K, V>>>> + Send
@@ -163,6 +406,26 @@ where
         self.writer(key).finish(value)
     }
 
+    /// Insert the entry which is treated as a miss after `ttl`.
+    ///
+    /// The expired entry is removed lazily on access, or skipped on flush if it expires before written.
+    #[must_use]
+    #[tracing::instrument(skip_all)]
+    fn insert_with_ttl<AK, AV>(
+        &self,
+        key: AK,
+        value: AV,
+        ttl: Duration,
+    ) -> impl Future<Output = Result<Option<CachedEntry<K, V>>>> + Send
+    where
+        AK: Into<Arc<K>> + Send + 'static,
+        AV: Into<Arc<V>> + Send + 'static,
+    {
+        let mut writer = self.writer(key);
+        writer.set_expire_at(SystemTime::now() + ttl);
+        writer.finish(value)
+    }
+
     #[must_use]
     #[tracing::instrument(skip_all)]
     fn insert_if_not_exists<AK, AV>(&self, key: AK, value: AV) -> impl Future<Output = Result<bool>> + Send
@@ -347,6 +610,32 @@ where
             future.await;
         });
     }
+
+    /// Like [`AsyncStorageExt::insert_if_not_exists_async_with_callback`], but the entry bypasses the admission
+    /// policies.
+    fn insert_if_not_exists_force_async_with_callback<AK, AV, F, FU>(&self, key: AK, value: AV, f: F)
+    where
+        AK: Into<Arc<K>> + Send + 'static,
+        AV: Into<Arc<V>> + Send + 'static,
+        F: FnOnce(Result<bool>) -> FU + Send + 'static,
+        FU: Future<Output = ()> + Send + 'static,
+    {
+        let store = self.clone();
+        let key = key.into();
+        tokio::spawn(async move {
+            let res = match store.exists(&key) {
+                Ok(true) => Ok(false),
+                Ok(false) => {
+                    let mut writer = store.writer(key);
+                    writer.force();
+                    writer.finish(value).await.map(|res| res.is_some())
+                }
+                Err(e) => Err(e),
+            };
+            let future = f(res);
+            future.await;
+        });
+    }
 }
 
 impl<K, V, S> AsyncStorageExt<K, V> for S
@@ -457,14 +746,22 @@ where
 mod tests {
     //! storage interface test
 
-    use std::{path::Path, sync::Arc, time::Duration};
+    use std::{collections::HashMap, path::Path, sync::Arc, time::Duration};
 
     use foyer_memory::FifoConfig;
     use tokio::sync::Barrier;
 
     use super::*;
     use crate::{
-        device::fs::FsDeviceConfig,
+        buffer::{FlushErrorPolicy, PaddingPolicy},
+        catalog::{CatalogRetryPolicy, WriteConflictPolicy},
+        device::{
+            executor::IoExecutor,
+            fs::{FsDeviceConfig, RegionOpenErrorPolicy},
+        },
+        flusher::{ChannelDropPolicy, QueueFullPolicy},
+        generic::ChecksumAlgorithm,
+        region::UnknownRegionVersionPolicy,
         store::{FsStore, FsStoreConfig},
     };
 
@@ -481,6 +778,12 @@ mod tests {
                 file_size: MB,
                 align: 4 * KB,
                 io_size: 4 * KB,
+                io_executor: IoExecutor::default(),
+                on_region_open_error: RegionOpenErrorPolicy::default(),
+                write_io_size: None,
+                read_io_size: None,
+                extra_dirs: vec![],
+                direct_io: true,
             },
             catalog_shards: 1,
             admissions: vec![],
@@ -490,6 +793,46 @@ mod tests {
             clean_region_threshold: 1,
             recover_concurrency: 2,
             compression: Compression::None,
+            checksum: true,
+            recent_inserts_capacity: 0,
+            max_key_size: None,
+            region_compression: None,
+            intra_block_align: None,
+            catalog_snapshot_path: None,
+            reclaim_grace_period: Duration::ZERO,
+            compression_observer: None,
+            unknown_region_version_policy: UnknownRegionVersionPolicy::default(),
+            block_compression: None,
+            background_cpu_cores: None,
+            metrics_recorder: None,
+            write_conflict_policy: WriteConflictPolicy::default(),
+            catalog_ops_limit: None,
+            first_entry_offset: None,
+            verify_region_magic_on_read: false,
+            self_test_on_open: false,
+            scrub_throughput_limit: None,
+            write_coalescing_interval: None,
+            max_writing_regions: None,
+            size_padding: PaddingPolicy::default(),
+            store_key_hash_only: false,
+            zstd_level: 0,
+            max_concurrent_decompressions: None,
+            checksum_algorithm: ChecksumAlgorithm::default(),
+            key_prefix: None,
+            flush_error_policy: FlushErrorPolicy::default(),
+            admission_observer: None,
+            max_region_age: None,
+            channel_drop_policy: ChannelDropPolicy::default(),
+            prefix_compressions: HashMap::new(),
+            io_bytes_in_flight_limit: None,
+            flusher_queue_capacity: None,
+            queue_full_policy: QueueFullPolicy::default(),
+            flush_alignment: None,
+            adaptive_compression: false,
+            scan_read_ahead: 2,
+            verify_checksum_on_recover: false,
+            catalog_backend: None,
+            catalog_retry_policy: CatalogRetryPolicy::default(),
         }
     }
 
@@ -14,24 +14,44 @@
 
 use foyer_common::code::{StorageKey, StorageValue};
 use foyer_memory::{EvictionConfig, LfuConfig};
-use std::{borrow::Borrow, fmt::Debug, hash::Hash, sync::Arc};
+use futures::{future::BoxFuture, stream::BoxStream};
+use std::{
+    borrow::Borrow,
+    collections::HashMap,
+    fmt::Debug,
+    hash::Hash,
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+use tokio::io::AsyncWrite;
 
 use crate::{
-    compress::Compression,
+    buffer::{FlushErrorPolicy, PaddingPolicy},
+    catalog::{CatalogBackend, CatalogRetryPolicy, Sequence, WriteConflictPolicy},
+    compress::{BlockCompression, Compression, CompressionObserver},
     device::fs::FsDevice,
     error::Result,
-    generic::{GenericStore, GenericStoreConfig, GenericStoreWriter},
-    lazy::{Lazy, LazyStoreWriter},
+    flusher::{ChannelDropPolicy, QueueFullPolicy},
+    generic::{ChecksumAlgorithm, GenericStore, GenericStoreConfig, GenericStoreWriter, KeyPrefix},
+    lazy::{Lazy, LazyStoreConfig, LazyStoreWriter, RecoveryReadPolicy},
+    metrics::{Metrics, MetricsRecorder},
+    migrate::{MigrateStore, MigrateStoreConfig},
     none::{NoneStore, NoneStoreWriter},
+    region::{RegionId, UnknownRegionVersionPolicy},
     runtime::{Runtime, RuntimeStoreConfig, RuntimeStoreWriter},
-    storage::{CachedEntry, Storage, StorageWriter},
-    AdmissionPolicy, FsDeviceConfig, ReinsertionPolicy, RuntimeConfig,
+    storage::{CachedEntry, CompactionReport, CompactionStrategy, DrainedEntry, ScrubReport, Storage, StorageWriter},
+    AdmissionObserver, AdmissionPolicy, FsDeviceConfig, ReinsertionPolicy, RuntimeConfig,
 };
 
 pub type FsStore<K, V> = GenericStore<K, V, FsDevice>;
 pub type FsStoreConfig<K, V> = GenericStoreConfig<K, V, FsDevice>;
 pub type FsStoreWriter<K, V> = GenericStoreWriter<K, V, FsDevice>;
 
+pub type FsMigrateStore<K, V> = MigrateStore<K, V, FsDevice>;
+pub type FsMigrateStoreConfig<K, V> = MigrateStoreConfig<K, V, FsDevice>;
+
 #[derive(Debug, Clone)]
 pub enum DeviceConfig {
     None,
@@ -60,7 +80,48 @@ where
     clean_region_threshold: Option<usize>,
     recover_concurrency: usize,
     compression: Compression,
+    checksum: bool,
+    recent_inserts_capacity: usize,
+    max_key_size: Option<usize>,
+    region_compression: Option<Compression>,
+    intra_block_align: Option<usize>,
+    catalog_snapshot_path: Option<PathBuf>,
+    reclaim_grace_period: Duration,
+    compression_observer: Option<Arc<dyn CompressionObserver>>,
+    unknown_region_version_policy: UnknownRegionVersionPolicy,
+    block_compression: Option<BlockCompression>,
+    background_cpu_cores: Option<f64>,
+    metrics_recorder: Option<Arc<dyn MetricsRecorder>>,
+    write_conflict_policy: WriteConflictPolicy,
+    catalog_ops_limit: Option<f64>,
+    first_entry_offset: Option<usize>,
+    verify_region_magic_on_read: bool,
+    self_test_on_open: bool,
+    scrub_throughput_limit: Option<usize>,
+    write_coalescing_interval: Option<Duration>,
+    max_writing_regions: Option<usize>,
+    size_padding: PaddingPolicy,
+    store_key_hash_only: bool,
+    zstd_level: i32,
+    max_concurrent_decompressions: Option<usize>,
+    checksum_algorithm: ChecksumAlgorithm,
+    key_prefix: Option<Arc<dyn KeyPrefix<K>>>,
+    flush_error_policy: FlushErrorPolicy,
+    admission_observer: Option<Arc<dyn AdmissionObserver<Key = K>>>,
+    max_region_age: Option<Duration>,
+    channel_drop_policy: ChannelDropPolicy,
+    prefix_compressions: HashMap<u64, Compression>,
+    io_bytes_in_flight_limit: Option<usize>,
+    flusher_queue_capacity: Option<usize>,
+    queue_full_policy: QueueFullPolicy,
+    flush_alignment: Option<usize>,
+    adaptive_compression: bool,
+    scan_read_ahead: usize,
+    verify_checksum_on_recover: bool,
+    catalog_backend: Option<Arc<dyn CatalogBackend<K>>>,
+    catalog_retry_policy: CatalogRetryPolicy,
     lazy: bool,
+    recovery_read_policy: RecoveryReadPolicy,
     runtime_config: Option<RuntimeConfig>,
 }
 
@@ -98,8 +159,49 @@ where
             clean_region_threshold: None,
             recover_concurrency: 8,
             compression: Compression::None,
+            checksum: true,
+            recent_inserts_capacity: 0,
+            max_key_size: None,
+            region_compression: None,
+            intra_block_align: None,
+            catalog_snapshot_path: None,
+            reclaim_grace_period: Duration::ZERO,
+            compression_observer: None,
+            unknown_region_version_policy: UnknownRegionVersionPolicy::default(),
+            block_compression: None,
+            background_cpu_cores: None,
+            metrics_recorder: None,
+            write_conflict_policy: WriteConflictPolicy::default(),
+            catalog_ops_limit: None,
+            first_entry_offset: None,
+            verify_region_magic_on_read: false,
+            self_test_on_open: false,
+            scrub_throughput_limit: None,
+            write_coalescing_interval: None,
+            max_writing_regions: None,
+            size_padding: PaddingPolicy::default(),
+            store_key_hash_only: false,
+            zstd_level: 0,
+            max_concurrent_decompressions: None,
+            checksum_algorithm: ChecksumAlgorithm::default(),
+            key_prefix: None,
+            flush_error_policy: FlushErrorPolicy::default(),
+            admission_observer: None,
+            max_region_age: None,
+            channel_drop_policy: ChannelDropPolicy::default(),
+            prefix_compressions: HashMap::new(),
+            io_bytes_in_flight_limit: None,
+            flusher_queue_capacity: None,
+            queue_full_policy: QueueFullPolicy::default(),
+            flush_alignment: None,
+            adaptive_compression: false,
+            scan_read_ahead: 2,
+            verify_checksum_on_recover: false,
+            catalog_backend: None,
+            catalog_retry_policy: CatalogRetryPolicy::default(),
             runtime_config: None,
             lazy: false,
+            recovery_read_policy: RecoveryReadPolicy::default(),
         }
     }
 }
@@ -145,6 +247,14 @@ where
         self
     }
 
+    /// Set the observer of the admission decisions made by each admission policy.
+    ///
+    /// Default: `None`.
+    pub fn with_admission_observer(mut self, observer: Arc<dyn AdmissionObserver<Key = K>>) -> Self {
+        self.admission_observer = Some(observer);
+        self
+    }
+
     /// Push a new reinsertion policy in order.
     pub fn with_reinsertion_policy(mut self, reinsertion: Arc<dyn ReinsertionPolicy<Key = K, Value = V>>) -> Self {
         self.reinsertions.push(reinsertion);
@@ -173,7 +283,9 @@ where
         self
     }
 
-    /// Concurrency of recovery.
+    /// Count of regions scanned concurrently during recovery, must be > 0.
+    ///
+    /// The default recover concurrency is 8.
     pub fn with_recover_concurrency(mut self, recover_concurrency: usize) -> Self {
         self.recover_concurrency = recover_concurrency;
         self
@@ -185,6 +297,388 @@ where
         self
     }
 
+    /// Enable checksum for entries.
+    ///
+    /// If disabled, checksum is neither calculated on write nor verified on read.
+    ///
+    /// WARNING: Disabling checksum is unsafe for durability, corrupted entries can NOT be detected. Only disable it
+    /// for ephemeral caches on trusted devices.
+    ///
+    /// Default: `true`.
+    pub fn with_checksum(mut self, checksum: bool) -> Self {
+        self.checksum = checksum;
+        self
+    }
+
+    /// Capacity of the ring buffer that records the recent inserted keys and sequences, which can be read with
+    /// [`Storage::recent_inserts`] for diagnosis.
+    ///
+    /// Default: `0` (disabled).
+    pub fn with_recent_inserts_capacity(mut self, recent_inserts_capacity: usize) -> Self {
+        self.recent_inserts_capacity = recent_inserts_capacity;
+        self
+    }
+
+    /// Max serialized size of a key.
+    ///
+    /// Inserting a key larger than the limit fails with [`crate::Error::KeyTooLarge`] before the entry is written.
+    ///
+    /// Default: unlimited.
+    pub fn with_max_key_size(mut self, max_key_size: usize) -> Self {
+        self.max_key_size = Some(max_key_size);
+        self
+    }
+
+    /// Compression algorithm applied to all entries of a region, recorded in the region header.
+    ///
+    /// If set, it overrides the compression of each entry written into the region.
+    ///
+    /// Default: compress per entry.
+    pub fn with_region_compression(mut self, compression: Compression) -> Self {
+        self.region_compression = Some(compression);
+        self
+    }
+
+    /// Pack entries in device blocks, aligned to `intra_block_align` instead of the device align.
+    ///
+    /// `intra_block_align` must be a power of 2 between the entry header size and the device align.
+    ///
+    /// Default: each entry is aligned to the device align.
+    pub fn with_intra_block_align(mut self, intra_block_align: usize) -> Self {
+        self.intra_block_align = Some(intra_block_align);
+        self
+    }
+
+    /// Recover the catalog from the snapshot at `path` on open, which is taken by [`Storage::snapshot_catalog`].
+    ///
+    /// Falls back to scanning all regions if the snapshot is missing or stale.
+    ///
+    /// Default: recover by scanning all regions.
+    pub fn with_catalog_snapshot_path(mut self, path: impl AsRef<Path>) -> Self {
+        self.catalog_snapshot_path = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Set the duration that an evictable region remains readable before it is physically reclaimed.
+    ///
+    /// The grace period gives slow readers time to finish reading the region.
+    ///
+    /// Default: reclaim immediately.
+    pub fn with_reclaim_grace_period(mut self, reclaim_grace_period: Duration) -> Self {
+        self.reclaim_grace_period = reclaim_grace_period;
+        self
+    }
+
+    /// Set the observer of the compression decision of each written entry.
+    ///
+    /// Default: no observer.
+    pub fn with_compression_observer(mut self, observer: Arc<dyn CompressionObserver>) -> Self {
+        self.compression_observer = Some(observer);
+        self
+    }
+
+    /// Set the policy for the regions with an unknown (newer) format version found during recovery.
+    ///
+    /// Default: [`UnknownRegionVersionPolicy::SkipWithWarning`].
+    pub fn with_unknown_region_version_policy(mut self, policy: UnknownRegionVersionPolicy) -> Self {
+        self.unknown_region_version_policy = policy;
+        self
+    }
+
+    /// Compress consecutive entries together as blocks.
+    ///
+    /// Block compression exploits the redundancy across entries, e.g. many similar small records. The compression of
+    /// each entry is ignored, but the region compression still overrides the block compression if set.
+    ///
+    /// Default: disabled.
+    pub fn with_block_compression(mut self, block_compression: BlockCompression) -> Self {
+        self.block_compression = Some(block_compression);
+        self
+    }
+
+    /// Limit the CPU cores consumed by the background tasks, e.g. compression and checksum on flush, and
+    /// decompression on reclamation.
+    ///
+    /// The budget can be fractional, e.g. `0.5` means half a core.
+    ///
+    /// Default: unlimited.
+    pub fn with_background_cpu_cores(mut self, cores: f64) -> Self {
+        self.background_cpu_cores = Some(cores);
+        self
+    }
+
+    /// Set the metrics recorder of the store, e.g. to export metrics with a backend other than Prometheus.
+    ///
+    /// Default: the recorder set by [`crate::set_metrics_recorder`], or a Prometheus recorder if not set.
+    pub fn with_metrics_recorder(mut self, recorder: Arc<dyn MetricsRecorder>) -> Self {
+        self.metrics_recorder = Some(recorder);
+        self
+    }
+
+    /// Set the policy to decide the winner when the same key is inserted concurrently.
+    ///
+    /// Default: [`WriteConflictPolicy::LastWriterWins`].
+    pub fn with_write_conflict_policy(mut self, policy: WriteConflictPolicy) -> Self {
+        self.write_conflict_policy = policy;
+        self
+    }
+
+    /// Limit the rate of the catalog mutations issued by the inserts, in ops per second.
+    ///
+    /// Inserts exceeding the rate are delayed, which protects the catalog from a flood of tiny inserts.
+    ///
+    /// Default: unlimited.
+    pub fn with_catalog_ops_limit(mut self, ops_per_sec: f64) -> Self {
+        self.catalog_ops_limit = Some(ops_per_sec);
+        self
+    }
+
+    /// Reserve the space between the region header and the first entry of each region, the first entry is placed at
+    /// `first_entry_offset`.
+    ///
+    /// `first_entry_offset` must be aligned to the device align, and in `[device align, region size)`.
+    ///
+    /// Default: the first entry is placed right after the region header, at the device align.
+    pub fn with_first_entry_offset(mut self, first_entry_offset: usize) -> Self {
+        self.first_entry_offset = Some(first_entry_offset);
+        self
+    }
+
+    /// Verify the region header magic before reading entries from a region, to detect the region clobbered by other
+    /// processes on shared storage.
+    ///
+    /// The magic is verified on the first read of a region, and re-verified periodically.
+    ///
+    /// Default: `false`.
+    pub fn with_verify_region_magic_on_read(mut self, verify_region_magic_on_read: bool) -> Self {
+        self.verify_region_magic_on_read = verify_region_magic_on_read;
+        self
+    }
+
+    /// Write an aligned block to the device, read it back and verify it on open, before trusting the device.
+    ///
+    /// The open fails with a descriptive error if the round-trip fails, e.g. the filesystem rejects direct I/O.
+    ///
+    /// Default: `false`.
+    pub fn with_self_test_on_open(mut self, self_test_on_open: bool) -> Self {
+        self.self_test_on_open = self_test_on_open;
+        self
+    }
+
+    /// Set the max bytes per second read by [`Storage::scrub`], to keep the scrub from interfering with the foreground
+    /// reads.
+    ///
+    /// Default: unlimited.
+    pub fn with_scrub_throughput_limit(mut self, scrub_throughput_limit: usize) -> Self {
+        self.scrub_throughput_limit = Some(scrub_throughput_limit);
+        self
+    }
+
+    /// Coalesce the writes of update-heavy keys, only the newest entry of each key is written per interval.
+    ///
+    /// Default: disabled, the entries are written as soon as inserted.
+    pub fn with_write_coalescing_interval(mut self, write_coalescing_interval: Duration) -> Self {
+        self.write_coalescing_interval = Some(write_coalescing_interval);
+        self
+    }
+
+    /// Limit the count of the regions being written concurrently by the flushers, to keep the writes concentrated.
+    ///
+    /// Default: unlimited, each flusher writes its own region.
+    pub fn with_max_writing_regions(mut self, max_writing_regions: usize) -> Self {
+        self.max_writing_regions = Some(max_writing_regions);
+        self
+    }
+
+    /// Seal the region being written after it has been written for `max_region_age`, even if it is not full.
+    ///
+    /// Default: disabled, the regions are only rotated when full.
+    pub fn with_max_region_age(mut self, max_region_age: Duration) -> Self {
+        self.max_region_age = Some(max_region_age);
+        self
+    }
+
+    /// Pad the stored values by the padding policy, so that their stored sizes don't reveal the exact value lengths.
+    ///
+    /// Default: [`PaddingPolicy::None`].
+    pub fn with_size_padding(mut self, size_padding: PaddingPolicy) -> Self {
+        self.size_padding = size_padding;
+        self
+    }
+
+    /// Store a 128-bit hash of the key on disk instead of the full key, to shrink the overhead of long keys.
+    ///
+    /// The full keys can't be rebuilt from the stored hashes, so the entries are lost after a restart unless the
+    /// catalog is recovered from a snapshot. Not supported with block compression.
+    ///
+    /// Default: `false`.
+    pub fn with_store_key_hash_only(mut self, store_key_hash_only: bool) -> Self {
+        self.store_key_hash_only = store_key_hash_only;
+        self
+    }
+
+    /// Set the level of [`Compression::Zstd`], e.g. `1` for hot data or `19` for cold data with a better ratio.
+    ///
+    /// The level is not needed for decompression, so it can be changed across restarts.
+    ///
+    /// Default: `0`, which means the zstd default level.
+    pub fn with_zstd_level(mut self, zstd_level: i32) -> Self {
+        self.zstd_level = zstd_level;
+        self
+    }
+
+    /// Limit the count of the concurrent decompressions on the get path, the excess gets wait for a while instead of
+    /// thrashing the CPU.
+    ///
+    /// Default: unlimited.
+    pub fn with_max_concurrent_decompressions(mut self, max_concurrent_decompressions: usize) -> Self {
+        self.max_concurrent_decompressions = Some(max_concurrent_decompressions);
+        self
+    }
+
+    /// Set the algorithm of the entry checksums, e.g. [`ChecksumAlgorithm::Crc32c`] for the hardware acceleration, or
+    /// [`ChecksumAlgorithm::XxHash3`] for the faster validation of large values.
+    ///
+    /// The algorithm is recorded in each entry, so it can be changed across restarts.
+    ///
+    /// Default: [`ChecksumAlgorithm::XxHash64`].
+    pub fn with_checksum_algorithm(mut self, checksum_algorithm: ChecksumAlgorithm) -> Self {
+        self.checksum_algorithm = checksum_algorithm;
+        self
+    }
+
+    /// Set the function to extract the prefix of a key, e.g. the tenant id, to group the entries for locality.
+    ///
+    /// Entries with the same prefix are preferentially written into the same regions, which benefits the read-ahead of
+    /// prefix scans. The prefixes are spread over the flushers by `prefix % flushers`, so the prefix groups are
+    /// separated from each other up to the flusher count.
+    ///
+    /// Default: no grouping.
+    pub fn with_key_prefix(mut self, key_prefix: impl KeyPrefix<K>) -> Self {
+        self.key_prefix = Some(Arc::new(key_prefix));
+        self
+    }
+
+    /// Set the behavior of the flushers when writing to the device fails.
+    ///
+    /// For a best-effort cache, [`FlushErrorPolicy::Drop`] keeps the store serving at the cost of the dropped entries.
+    /// On a device with failing regions, e.g. bad sectors, [`FlushErrorPolicy::Relocate`] moves the entries to other
+    /// regions instead.
+    ///
+    /// Default: [`FlushErrorPolicy::Abort`].
+    pub fn with_flush_error_policy(mut self, flush_error_policy: FlushErrorPolicy) -> Self {
+        self.flush_error_policy = flush_error_policy;
+        self
+    }
+
+    /// Set the behavior of the flushers when the entry channel is closed while the store is not being closed.
+    ///
+    /// With [`ChannelDropPolicy::Unhealthy`], the store logs an error and reports itself unhealthy.
+    ///
+    /// Default: [`ChannelDropPolicy::Unhealthy`].
+    pub fn with_channel_drop_policy(mut self, channel_drop_policy: ChannelDropPolicy) -> Self {
+        self.channel_drop_policy = channel_drop_policy;
+        self
+    }
+
+    /// Set the compression of the entries whose key prefix is `prefix`, which overrides the compression set by
+    /// [`StoreBuilder::with_compression`] for them.
+    ///
+    /// The prefix of a key is extracted by the function set by [`StoreBuilder::with_key_prefix`], so it takes no effect
+    /// if the key prefix is not set.
+    pub fn with_prefix_compression(mut self, prefix: u64, compression: Compression) -> Self {
+        self.prefix_compressions.insert(prefix, compression);
+        self
+    }
+
+    /// Limit the total bytes of the in-flight device reads and writes, the excess I/Os wait for the others to complete.
+    ///
+    /// It bounds the memory held by the pending io buffers and the queue depth of the device on large flushes.
+    ///
+    /// Default: unlimited.
+    pub fn with_io_bytes_in_flight_limit(mut self, io_bytes_in_flight_limit: usize) -> Self {
+        self.io_bytes_in_flight_limit = Some(io_bytes_in_flight_limit);
+        self
+    }
+
+    /// Bound the count of the entries queued to the flushers, the excess inserts are handled by the queue full policy.
+    ///
+    /// It bounds the memory held by the queued entries on a burst of inserts that the device can't keep up with.
+    ///
+    /// Default: unbounded.
+    pub fn with_flusher_queue_capacity(mut self, flusher_queue_capacity: usize) -> Self {
+        self.flusher_queue_capacity = Some(flusher_queue_capacity);
+        self
+    }
+
+    /// Set the behavior of the inserts when the flusher queue is full.
+    ///
+    /// Default: [`QueueFullPolicy::Block`].
+    pub fn with_queue_full_policy(mut self, queue_full_policy: QueueFullPolicy) -> Self {
+        self.queue_full_policy = queue_full_policy;
+        self
+    }
+
+    /// Align the flushes to the regions to `flush_alignment`, e.g. the physical erase block size of the device.
+    ///
+    /// Each flush is padded up to the alignment, which trades some space for the write patterns that reduce the write
+    /// amplification of some SSDs. It must be a power of 2 no less than the device align that divides the region size.
+    ///
+    /// Default: aligned to the device align.
+    pub fn with_flush_alignment(mut self, flush_alignment: usize) -> Self {
+        self.flush_alignment = Some(flush_alignment);
+        self
+    }
+
+    /// Write the value uncompressed if the compression doesn't make it smaller, which saves the space and the
+    /// decompression of the incompressible values.
+    ///
+    /// The entry header records the algorithm actually applied, so the entries are read as usual.
+    ///
+    /// Default: `false`.
+    pub fn with_adaptive_compression(mut self, adaptive_compression: bool) -> Self {
+        self.adaptive_compression = adaptive_compression;
+        self
+    }
+
+    /// Set the count of the regions read ahead by [`Storage::scan`] while the current region is parsed, which keeps
+    /// the device busy during a full scan. `0` reads the regions one at a time.
+    ///
+    /// Each region read ahead holds an io buffer of the region size.
+    ///
+    /// Default: `2`.
+    pub fn with_scan_read_ahead(mut self, regions: usize) -> Self {
+        self.scan_read_ahead = regions;
+        self
+    }
+
+    /// Verify the checksums of the entries while the catalog is recovered on open. The entries that fail are skipped.
+    ///
+    /// The values are read in full for the verification, which slows down the recovery.
+    ///
+    /// Default: `false`.
+    pub fn with_verify_checksum_on_recover(mut self, verify_checksum_on_recover: bool) -> Self {
+        self.verify_checksum_on_recover = verify_checksum_on_recover;
+        self
+    }
+
+    /// Set the backend that persists the index of the flushed entries besides the in-memory catalog.
+    ///
+    /// A flushed entry is indexed only after the backend accepts it, the failed inserts are retried by the catalog
+    /// retry policy.
+    pub fn with_catalog_backend(mut self, backend: Arc<dyn CatalogBackend<K>>) -> Self {
+        self.catalog_backend = Some(backend);
+        self
+    }
+
+    /// Set the retry of the failed catalog backend inserts on flush.
+    ///
+    /// Default: 3 retries, the backoff starts from 10ms.
+    pub fn with_catalog_retry_policy(mut self, policy: CatalogRetryPolicy) -> Self {
+        self.catalog_retry_policy = policy;
+        self
+    }
+
     /// Enable a dedicated tokio runtime for the store with a runtime config.
     ///
     /// If not given, the store will use the user's runtime.
@@ -201,6 +695,14 @@ where
         self
     }
 
+    /// Set the behavior of reads before the lazy recovery finishes, only works if lazy recovery is enabled.
+    ///
+    /// Default: [`RecoveryReadPolicy::Miss`].
+    pub fn with_recovery_read_policy(mut self, recovery_read_policy: RecoveryReadPolicy) -> Self {
+        self.recovery_read_policy = recovery_read_policy;
+        self
+    }
+
     /// Build and return the [`StoreConfig`] only.
     pub fn build_config(self) -> StoreConfig<K, V> {
         let clean_region_threshold = self.clean_region_threshold.unwrap_or(self.reclaimers);
@@ -219,19 +721,102 @@ where
                 clean_region_threshold,
                 recover_concurrency: self.recover_concurrency,
                 compression: self.compression,
+                checksum: self.checksum,
+                recent_inserts_capacity: self.recent_inserts_capacity,
+                max_key_size: self.max_key_size,
+                region_compression: self.region_compression,
+                intra_block_align: self.intra_block_align,
+                catalog_snapshot_path: self.catalog_snapshot_path.clone(),
+                reclaim_grace_period: self.reclaim_grace_period,
+                compression_observer: self.compression_observer.clone(),
+                unknown_region_version_policy: self.unknown_region_version_policy,
+                block_compression: self.block_compression,
+                background_cpu_cores: self.background_cpu_cores,
+                metrics_recorder: self.metrics_recorder.clone(),
+                write_conflict_policy: self.write_conflict_policy,
+                catalog_ops_limit: self.catalog_ops_limit,
+                first_entry_offset: self.first_entry_offset,
+                verify_region_magic_on_read: self.verify_region_magic_on_read,
+                self_test_on_open: self.self_test_on_open,
+                scrub_throughput_limit: self.scrub_throughput_limit,
+                write_coalescing_interval: self.write_coalescing_interval,
+                max_writing_regions: self.max_writing_regions,
+                size_padding: self.size_padding,
+                store_key_hash_only: self.store_key_hash_only,
+                zstd_level: self.zstd_level,
+                max_concurrent_decompressions: self.max_concurrent_decompressions,
+                checksum_algorithm: self.checksum_algorithm,
+                key_prefix: self.key_prefix.clone(),
+                flush_error_policy: self.flush_error_policy,
+                admission_observer: self.admission_observer.clone(),
+                max_region_age: self.max_region_age,
+                channel_drop_policy: self.channel_drop_policy,
+                prefix_compressions: self.prefix_compressions.clone(),
+                io_bytes_in_flight_limit: self.io_bytes_in_flight_limit,
+                flusher_queue_capacity: self.flusher_queue_capacity,
+                queue_full_policy: self.queue_full_policy,
+                flush_alignment: self.flush_alignment,
+                adaptive_compression: self.adaptive_compression,
+                scan_read_ahead: self.scan_read_ahead,
+                verify_checksum_on_recover: self.verify_checksum_on_recover,
+                catalog_backend: self.catalog_backend.clone(),
+                catalog_retry_policy: self.catalog_retry_policy,
             }),
-            (DeviceConfig::Fs(device_config), None, true) => StoreConfig::LazyFs(FsStoreConfig {
-                name: self.name,
-                eviction_config: self.eviction_config,
-                device_config,
-                catalog_shards: self.catalog_shards,
-                admissions: self.admissions,
-                reinsertions: self.reinsertions,
-                flushers: self.flushers,
-                reclaimers: self.reclaimers,
-                clean_region_threshold,
-                recover_concurrency: self.recover_concurrency,
-                compression: self.compression,
+            (DeviceConfig::Fs(device_config), None, true) => StoreConfig::LazyFs(LazyStoreConfig {
+                store_config: FsStoreConfig {
+                    name: self.name,
+                    eviction_config: self.eviction_config,
+                    device_config,
+                    catalog_shards: self.catalog_shards,
+                    admissions: self.admissions,
+                    reinsertions: self.reinsertions,
+                    flushers: self.flushers,
+                    reclaimers: self.reclaimers,
+                    clean_region_threshold,
+                    recover_concurrency: self.recover_concurrency,
+                    compression: self.compression,
+                    checksum: self.checksum,
+                    recent_inserts_capacity: self.recent_inserts_capacity,
+                    max_key_size: self.max_key_size,
+                    region_compression: self.region_compression,
+                    intra_block_align: self.intra_block_align,
+                    catalog_snapshot_path: self.catalog_snapshot_path.clone(),
+                    reclaim_grace_period: self.reclaim_grace_period,
+                    compression_observer: self.compression_observer.clone(),
+                    unknown_region_version_policy: self.unknown_region_version_policy,
+                    block_compression: self.block_compression,
+                    background_cpu_cores: self.background_cpu_cores,
+                    metrics_recorder: self.metrics_recorder.clone(),
+                    write_conflict_policy: self.write_conflict_policy,
+                    catalog_ops_limit: self.catalog_ops_limit,
+                    first_entry_offset: self.first_entry_offset,
+                    verify_region_magic_on_read: self.verify_region_magic_on_read,
+                    self_test_on_open: self.self_test_on_open,
+                    scrub_throughput_limit: self.scrub_throughput_limit,
+                    write_coalescing_interval: self.write_coalescing_interval,
+                    max_writing_regions: self.max_writing_regions,
+                    size_padding: self.size_padding,
+                    store_key_hash_only: self.store_key_hash_only,
+                    zstd_level: self.zstd_level,
+                    max_concurrent_decompressions: self.max_concurrent_decompressions,
+                    checksum_algorithm: self.checksum_algorithm,
+                    key_prefix: self.key_prefix.clone(),
+                    flush_error_policy: self.flush_error_policy,
+                    admission_observer: self.admission_observer.clone(),
+                    max_region_age: self.max_region_age,
+                    channel_drop_policy: self.channel_drop_policy,
+                    prefix_compressions: self.prefix_compressions.clone(),
+                    io_bytes_in_flight_limit: self.io_bytes_in_flight_limit,
+                    flusher_queue_capacity: self.flusher_queue_capacity,
+                    queue_full_policy: self.queue_full_policy,
+                    flush_alignment: self.flush_alignment,
+                    adaptive_compression: self.adaptive_compression,
+                    scan_read_ahead: self.scan_read_ahead,
+                    verify_checksum_on_recover: self.verify_checksum_on_recover,
+                    catalog_backend: self.catalog_backend.clone(),
+                    catalog_retry_policy: self.catalog_retry_policy,
+                },
+                recovery_read_policy: self.recovery_read_policy,
             }),
             (DeviceConfig::Fs(device_config), Some(runtime_config), true) => {
                 StoreConfig::RuntimeFs(RuntimeStoreConfig {
@@ -247,24 +832,107 @@ where
                         clean_region_threshold,
                         recover_concurrency: self.recover_concurrency,
                         compression: self.compression,
+                        checksum: self.checksum,
+                        recent_inserts_capacity: self.recent_inserts_capacity,
+                        max_key_size: self.max_key_size,
+                        region_compression: self.region_compression,
+                        intra_block_align: self.intra_block_align,
+                        catalog_snapshot_path: self.catalog_snapshot_path.clone(),
+                        reclaim_grace_period: self.reclaim_grace_period,
+                        compression_observer: self.compression_observer.clone(),
+                        unknown_region_version_policy: self.unknown_region_version_policy,
+                        block_compression: self.block_compression,
+                        background_cpu_cores: self.background_cpu_cores,
+                        metrics_recorder: self.metrics_recorder.clone(),
+                        write_conflict_policy: self.write_conflict_policy,
+                        catalog_ops_limit: self.catalog_ops_limit,
+                        first_entry_offset: self.first_entry_offset,
+                        verify_region_magic_on_read: self.verify_region_magic_on_read,
+                        self_test_on_open: self.self_test_on_open,
+                        scrub_throughput_limit: self.scrub_throughput_limit,
+                        write_coalescing_interval: self.write_coalescing_interval,
+                        max_writing_regions: self.max_writing_regions,
+                        size_padding: self.size_padding,
+                        store_key_hash_only: self.store_key_hash_only,
+                        zstd_level: self.zstd_level,
+                        max_concurrent_decompressions: self.max_concurrent_decompressions,
+                        checksum_algorithm: self.checksum_algorithm,
+                        key_prefix: self.key_prefix.clone(),
+                        flush_error_policy: self.flush_error_policy,
+                        admission_observer: self.admission_observer.clone(),
+                        max_region_age: self.max_region_age,
+                        channel_drop_policy: self.channel_drop_policy,
+                        prefix_compressions: self.prefix_compressions.clone(),
+                        io_bytes_in_flight_limit: self.io_bytes_in_flight_limit,
+                        flusher_queue_capacity: self.flusher_queue_capacity,
+                        queue_full_policy: self.queue_full_policy,
+                        flush_alignment: self.flush_alignment,
+                        adaptive_compression: self.adaptive_compression,
+                        scan_read_ahead: self.scan_read_ahead,
+                        verify_checksum_on_recover: self.verify_checksum_on_recover,
+                        catalog_backend: self.catalog_backend.clone(),
+                        catalog_retry_policy: self.catalog_retry_policy,
                     },
                     runtime_config,
                 })
             }
             (DeviceConfig::Fs(device_config), Some(runtime_config), false) => {
                 StoreConfig::RuntimeLazyFs(RuntimeStoreConfig {
-                    store_config: FsStoreConfig {
-                        name: self.name,
-                        eviction_config: self.eviction_config,
-                        device_config,
-                        catalog_shards: self.catalog_shards,
-                        admissions: self.admissions,
-                        reinsertions: self.reinsertions,
-                        flushers: self.flushers,
-                        reclaimers: self.reclaimers,
-                        clean_region_threshold,
-                        recover_concurrency: self.recover_concurrency,
-                        compression: self.compression,
+                    store_config: LazyStoreConfig {
+                        store_config: FsStoreConfig {
+                            name: self.name,
+                            eviction_config: self.eviction_config,
+                            device_config,
+                            catalog_shards: self.catalog_shards,
+                            admissions: self.admissions,
+                            reinsertions: self.reinsertions,
+                            flushers: self.flushers,
+                            reclaimers: self.reclaimers,
+                            clean_region_threshold,
+                            recover_concurrency: self.recover_concurrency,
+                            compression: self.compression,
+                            checksum: self.checksum,
+                            recent_inserts_capacity: self.recent_inserts_capacity,
+                            max_key_size: self.max_key_size,
+                            region_compression: self.region_compression,
+                            intra_block_align: self.intra_block_align,
+                            catalog_snapshot_path: self.catalog_snapshot_path.clone(),
+                            reclaim_grace_period: self.reclaim_grace_period,
+                            compression_observer: self.compression_observer.clone(),
+                            unknown_region_version_policy: self.unknown_region_version_policy,
+                            block_compression: self.block_compression,
+                            background_cpu_cores: self.background_cpu_cores,
+                            metrics_recorder: self.metrics_recorder.clone(),
+                            write_conflict_policy: self.write_conflict_policy,
+                            catalog_ops_limit: self.catalog_ops_limit,
+                            first_entry_offset: self.first_entry_offset,
+                            verify_region_magic_on_read: self.verify_region_magic_on_read,
+                            self_test_on_open: self.self_test_on_open,
+                            scrub_throughput_limit: self.scrub_throughput_limit,
+                            write_coalescing_interval: self.write_coalescing_interval,
+                            max_writing_regions: self.max_writing_regions,
+                            size_padding: self.size_padding,
+                            store_key_hash_only: self.store_key_hash_only,
+                            zstd_level: self.zstd_level,
+                            max_concurrent_decompressions: self.max_concurrent_decompressions,
+                            checksum_algorithm: self.checksum_algorithm,
+                            key_prefix: self.key_prefix.clone(),
+                            flush_error_policy: self.flush_error_policy,
+                            admission_observer: self.admission_observer.clone(),
+                            max_region_age: self.max_region_age,
+                            channel_drop_policy: self.channel_drop_policy,
+                            prefix_compressions: self.prefix_compressions.clone(),
+                            io_bytes_in_flight_limit: self.io_bytes_in_flight_limit,
+                            flusher_queue_capacity: self.flusher_queue_capacity,
+                            queue_full_policy: self.queue_full_policy,
+                            flush_alignment: self.flush_alignment,
+                            adaptive_compression: self.adaptive_compression,
+                            scan_read_ahead: self.scan_read_ahead,
+                            verify_checksum_on_recover: self.verify_checksum_on_recover,
+                            catalog_backend: self.catalog_backend.clone(),
+                            catalog_retry_policy: self.catalog_retry_policy,
+                        },
+                        recovery_read_policy: self.recovery_read_policy,
                     },
                     runtime_config,
                 })
@@ -288,7 +956,7 @@ where
     None,
 
     Fs(FsStoreConfig<K, V>),
-    LazyFs(FsStoreConfig<K, V>),
+    LazyFs(LazyStoreConfig<K, V, FsStore<K, V>>),
     RuntimeFs(RuntimeStoreConfig<K, V, FsStore<K, V>>),
     RuntimeLazyFs(RuntimeStoreConfig<K, V, Lazy<K, V, FsStore<K, V>>>),
 }
@@ -454,6 +1122,26 @@ where
         }
     }
 
+    fn expire_at(&self) -> Option<SystemTime> {
+        match self {
+            StoreWriter::None(writer) => writer.expire_at(),
+            StoreWriter::Fs(writer) => writer.expire_at(),
+            StoreWriter::LazyFs(writer) => writer.expire_at(),
+            StoreWriter::RuntimeFs(writer) => writer.expire_at(),
+            StoreWriter::RuntimeLazyFs(writer) => writer.expire_at(),
+        }
+    }
+
+    fn set_expire_at(&mut self, expire_at: SystemTime) {
+        match self {
+            StoreWriter::None(writer) => writer.set_expire_at(expire_at),
+            StoreWriter::Fs(writer) => writer.set_expire_at(expire_at),
+            StoreWriter::LazyFs(writer) => writer.set_expire_at(expire_at),
+            StoreWriter::RuntimeFs(writer) => writer.set_expire_at(expire_at),
+            StoreWriter::RuntimeLazyFs(writer) => writer.set_expire_at(expire_at),
+        }
+    }
+
     async fn finish<AV>(self, value: AV) -> Result<Option<CachedEntry<K, V>>>
     where
         AV: Into<Arc<V>> + Send + 'static,
@@ -468,6 +1156,23 @@ where
     }
 }
 
+impl<K, V> Store<K, V>
+where
+    K: StorageKey,
+    V: StorageValue,
+{
+    /// Metrics of the store, `None` for the none store or a lazy store that is not recovered yet.
+    pub fn metrics(&self) -> Option<&Arc<Metrics>> {
+        match self {
+            Store::None(_) => None,
+            Store::Fs(store) => Some(store.metrics()),
+            Store::LazyFs(store) => store.store().map(|store| store.metrics()),
+            Store::RuntimeFs(store) => Some(store.store().metrics()),
+            Store::RuntimeLazyFs(store) => store.store().store().map(|store| store.metrics()),
+        }
+    }
+}
+
 impl<K, V> Storage<K, V> for Store<K, V>
 where
     K: StorageKey,
@@ -520,6 +1225,16 @@ where
         }
     }
 
+    async fn insert_batch(&self, entries: Vec<(Arc<K>, Arc<V>)>) -> Result<Vec<Option<CachedEntry<K, V>>>> {
+        match self {
+            Store::None(store) => store.insert_batch(entries).await,
+            Store::Fs(store) => store.insert_batch(entries).await,
+            Store::LazyFs(store) => store.insert_batch(entries).await,
+            Store::RuntimeFs(store) => store.insert_batch(entries).await,
+            Store::RuntimeLazyFs(store) => store.insert_batch(entries).await,
+        }
+    }
+
     fn exists<Q>(&self, key: &Q) -> Result<bool>
     where
         K: Borrow<Q>,
@@ -534,6 +1249,30 @@ where
         }
     }
 
+    fn touch<Q>(&self, key: &Q) -> Result<bool>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        match self {
+            Store::None(store) => store.touch(key),
+            Store::Fs(store) => store.touch(key),
+            Store::LazyFs(store) => store.touch(key),
+            Store::RuntimeFs(store) => store.touch(key),
+            Store::RuntimeLazyFs(store) => store.touch(key),
+        }
+    }
+
+    fn recent_inserts(&self, n: usize) -> Vec<(Arc<K>, Sequence)> {
+        match self {
+            Store::None(store) => store.recent_inserts(n),
+            Store::Fs(store) => store.recent_inserts(n),
+            Store::LazyFs(store) => store.recent_inserts(n),
+            Store::RuntimeFs(store) => store.recent_inserts(n),
+            Store::RuntimeLazyFs(store) => store.recent_inserts(n),
+        }
+    }
+
     async fn get<Q>(&self, key: &Q) -> Result<Option<CachedEntry<K, V>>>
     where
         K: Borrow<Q>,
@@ -548,6 +1287,35 @@ where
         }
     }
 
+    async fn get_stream<Q, W>(&self, key: &Q, writer: W) -> Result<bool>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + Send + Sync + 'static + Clone,
+        W: AsyncWrite + Send + Unpin + 'static,
+    {
+        match self {
+            Store::None(store) => store.get_stream(key, writer).await,
+            Store::Fs(store) => store.get_stream(key, writer).await,
+            Store::LazyFs(store) => store.get_stream(key, writer).await,
+            Store::RuntimeFs(store) => store.get_stream(key, writer).await,
+            Store::RuntimeLazyFs(store) => store.get_stream(key, writer).await,
+        }
+    }
+
+    async fn get_range<Q>(&self, key: &Q, range: Range<usize>) -> Result<Option<Vec<u8>>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + Send + Sync + 'static + Clone,
+    {
+        match self {
+            Store::None(store) => store.get_range(key, range).await,
+            Store::Fs(store) => store.get_range(key, range).await,
+            Store::LazyFs(store) => store.get_range(key, range).await,
+            Store::RuntimeFs(store) => store.get_range(key, range).await,
+            Store::RuntimeLazyFs(store) => store.get_range(key, range).await,
+        }
+    }
+
     fn remove<Q>(&self, key: &Q) -> Result<bool>
     where
         K: Borrow<Q>,
@@ -571,4 +1339,114 @@ where
             Store::RuntimeLazyFs(store) => store.clear(),
         }
     }
+
+    async fn snapshot_catalog(&self, path: impl AsRef<Path> + Send) -> Result<()> {
+        match self {
+            Store::None(store) => store.snapshot_catalog(path).await,
+            Store::Fs(store) => store.snapshot_catalog(path).await,
+            Store::LazyFs(store) => store.snapshot_catalog(path).await,
+            Store::RuntimeFs(store) => store.snapshot_catalog(path).await,
+            Store::RuntimeLazyFs(store) => store.snapshot_catalog(path).await,
+        }
+    }
+
+    fn entries_since(&self, sequence: Sequence) -> BoxStream<'static, Result<(Arc<K>, Arc<V>, Sequence)>> {
+        match self {
+            Store::None(store) => store.entries_since(sequence),
+            Store::Fs(store) => store.entries_since(sequence),
+            Store::LazyFs(store) => store.entries_since(sequence),
+            Store::RuntimeFs(store) => store.entries_since(sequence),
+            Store::RuntimeLazyFs(store) => store.entries_since(sequence),
+        }
+    }
+
+    fn scan(&self) -> BoxStream<'static, Result<(K, V)>> {
+        match self {
+            Store::None(store) => store.scan(),
+            Store::Fs(store) => store.scan(),
+            Store::LazyFs(store) => store.scan(),
+            Store::RuntimeFs(store) => store.scan(),
+            Store::RuntimeLazyFs(store) => store.scan(),
+        }
+    }
+
+    fn barrier(&self) -> BoxFuture<'static, Result<()>> {
+        match self {
+            Store::None(store) => store.barrier(),
+            Store::Fs(store) => store.barrier(),
+            Store::LazyFs(store) => store.barrier(),
+            Store::RuntimeFs(store) => store.barrier(),
+            Store::RuntimeLazyFs(store) => store.barrier(),
+        }
+    }
+
+    fn drain(&self) -> BoxFuture<'static, Result<Vec<DrainedEntry<K>>>> {
+        match self {
+            Store::None(store) => store.drain(),
+            Store::Fs(store) => store.drain(),
+            Store::LazyFs(store) => store.drain(),
+            Store::RuntimeFs(store) => store.drain(),
+            Store::RuntimeLazyFs(store) => store.drain(),
+        }
+    }
+
+    fn scrub(&self, remove_corrupted: bool) -> BoxFuture<'static, Result<ScrubReport<K>>> {
+        match self {
+            Store::None(store) => store.scrub(remove_corrupted),
+            Store::Fs(store) => store.scrub(remove_corrupted),
+            Store::LazyFs(store) => store.scrub(remove_corrupted),
+            Store::RuntimeFs(store) => store.scrub(remove_corrupted),
+            Store::RuntimeLazyFs(store) => store.scrub(remove_corrupted),
+        }
+    }
+
+    fn compact(&self, strategy: CompactionStrategy) -> BoxFuture<'static, Result<CompactionReport>> {
+        match self {
+            Store::None(store) => store.compact(strategy),
+            Store::Fs(store) => store.compact(strategy),
+            Store::LazyFs(store) => store.compact(strategy),
+            Store::RuntimeFs(store) => store.compact(strategy),
+            Store::RuntimeLazyFs(store) => store.compact(strategy),
+        }
+    }
+
+    fn read_region_bytes(&self, region: RegionId) -> BoxFuture<'static, Result<Vec<u8>>> {
+        match self {
+            Store::None(store) => store.read_region_bytes(region),
+            Store::Fs(store) => store.read_region_bytes(region),
+            Store::LazyFs(store) => store.read_region_bytes(region),
+            Store::RuntimeFs(store) => store.read_region_bytes(region),
+            Store::RuntimeLazyFs(store) => store.read_region_bytes(region),
+        }
+    }
+
+    fn apply_region_bytes(&self, region: RegionId, bytes: Vec<u8>) -> BoxFuture<'static, Result<()>> {
+        match self {
+            Store::None(store) => store.apply_region_bytes(region, bytes),
+            Store::Fs(store) => store.apply_region_bytes(region, bytes),
+            Store::LazyFs(store) => store.apply_region_bytes(region, bytes),
+            Store::RuntimeFs(store) => store.apply_region_bytes(region, bytes),
+            Store::RuntimeLazyFs(store) => store.apply_region_bytes(region, bytes),
+        }
+    }
+
+    fn shrink(&self, capacity: usize) -> BoxFuture<'static, Result<()>> {
+        match self {
+            Store::None(store) => store.shrink(capacity),
+            Store::Fs(store) => store.shrink(capacity),
+            Store::LazyFs(store) => store.shrink(capacity),
+            Store::RuntimeFs(store) => store.shrink(capacity),
+            Store::RuntimeLazyFs(store) => store.shrink(capacity),
+        }
+    }
+
+    fn rebuild_region_filters(&self) -> BoxFuture<'static, Result<usize>> {
+        match self {
+            Store::None(store) => store.rebuild_region_filters(),
+            Store::Fs(store) => store.rebuild_region_filters(),
+            Store::LazyFs(store) => store.rebuild_region_filters(),
+            Store::RuntimeFs(store) => store.rebuild_region_filters(),
+            Store::RuntimeLazyFs(store) => store.rebuild_region_filters(),
+        }
+    }
 }
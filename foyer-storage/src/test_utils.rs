@@ -12,13 +12,30 @@
 //  See the License for the specific language governing permissions and
 //  limitations under the License.s
 
-use std::{collections::HashSet, marker::PhantomData, sync::Arc};
+use std::{
+    collections::{HashMap, HashSet},
+    marker::PhantomData,
+    ops::Range,
+    sync::Arc,
+    time::Duration,
+};
 
-use foyer_common::code::{StorageKey, StorageValue};
+use foyer_common::{
+    bits,
+    code::{StorageKey, StorageValue},
+};
+use foyer_memory::FifoConfig;
 use parking_lot::Mutex;
 
 use crate::{
     admission::{AdmissionContext, AdmissionPolicy},
+    buffer::{FlushErrorPolicy, PaddingPolicy},
+    catalog::{CatalogRetryPolicy, WriteConflictPolicy},
+    compress::Compression,
+    device::{Device, DeviceResult},
+    flusher::{ChannelDropPolicy, QueueFullPolicy},
+    generic::{ChecksumAlgorithm, GenericStoreConfig},
+    region::{RegionId, UnknownRegionVersionPolicy},
     reinsertion::{ReinsertionContext, ReinsertionPolicy},
 };
 
@@ -119,3 +136,110 @@ where
         false
     }
 }
+
+/// Read the raw bytes of the `range` of the region, regardless of the alignment of the device.
+///
+/// The bytes not written yet are read as zeros.
+pub async fn inspect<D>(device: &D, region: RegionId, range: Range<usize>) -> DeviceResult<Vec<u8>>
+where
+    D: Device,
+{
+    let align = device.align();
+    let start = bits::align_down(align, range.start);
+    let end = bits::align_up(align, range.end);
+
+    let mut buf = device.io_buffer(0, end - start);
+    buf.resize(end - start, 0);
+    let (res, buf) = device.read(buf, .., region, start).await;
+    res?;
+
+    Ok(buf[range.start - start..range.end - start].to_vec())
+}
+
+/// Write raw bytes to the region at `offset`, regardless of the alignment of the device.
+///
+/// The aligned blocks covering the bytes are read, patched and written back, so the bytes around are kept. It is
+/// useful to craft the on-device state before a crash for recovery tests, e.g. a torn entry or a corrupted region
+/// header.
+pub async fn inject<D>(device: &D, region: RegionId, offset: usize, bytes: &[u8]) -> DeviceResult<()>
+where
+    D: Device,
+{
+    let align = device.align();
+    let start = bits::align_down(align, offset);
+    let end = bits::align_up(align, offset + bytes.len());
+
+    let mut buf = device.io_buffer(0, end - start);
+    buf.resize(end - start, 0);
+    let (res, mut buf) = device.read(buf, .., region, start).await;
+    res?;
+
+    buf[offset - start..offset - start + bytes.len()].copy_from_slice(bytes);
+    let (res, _) = device.write(buf, .., region, start).await;
+    res?;
+
+    Ok(())
+}
+
+/// The store config on the device with the default options, to be overridden by the tests, e.g.
+/// `GenericStoreConfig { flushers: 2, ..base_config(device_config) }`.
+pub fn base_config<K, V, D>(device_config: D::Config) -> GenericStoreConfig<K, V, D>
+where
+    K: StorageKey,
+    V: StorageValue,
+    D: Device,
+{
+    GenericStoreConfig {
+        name: "".to_string(),
+        eviction_config: FifoConfig {}.into(),
+        device_config,
+        catalog_shards: 1,
+        admissions: vec![],
+        reinsertions: vec![],
+        flushers: 1,
+        reclaimers: 0,
+        recover_concurrency: 2,
+        clean_region_threshold: 1,
+        compression: Compression::None,
+        checksum: true,
+        recent_inserts_capacity: 0,
+        max_key_size: None,
+        region_compression: None,
+        intra_block_align: None,
+        catalog_snapshot_path: None,
+        reclaim_grace_period: Duration::ZERO,
+        compression_observer: None,
+        unknown_region_version_policy: UnknownRegionVersionPolicy::default(),
+        block_compression: None,
+        background_cpu_cores: None,
+        metrics_recorder: None,
+        write_conflict_policy: WriteConflictPolicy::default(),
+        catalog_ops_limit: None,
+        first_entry_offset: None,
+        verify_region_magic_on_read: false,
+        self_test_on_open: false,
+        scrub_throughput_limit: None,
+        write_coalescing_interval: None,
+        max_writing_regions: None,
+        size_padding: PaddingPolicy::default(),
+        store_key_hash_only: false,
+        zstd_level: 0,
+        max_concurrent_decompressions: None,
+        checksum_algorithm: ChecksumAlgorithm::default(),
+        key_prefix: None,
+        flush_error_policy: FlushErrorPolicy::default(),
+        admission_observer: None,
+        max_region_age: None,
+        channel_drop_policy: ChannelDropPolicy::default(),
+        prefix_compressions: HashMap::new(),
+        io_bytes_in_flight_limit: None,
+        flusher_queue_capacity: None,
+        queue_full_policy: QueueFullPolicy::default(),
+        flush_alignment: None,
+        adaptive_compression: false,
+        scan_read_ahead: 2,
+        verify_checksum_on_recover: false,
+        catalog_backend: None,
+        catalog_retry_policy: CatalogRetryPolicy::default(),
+    }
+}
@@ -15,12 +15,14 @@
 // TODO(MrCroxx): use `expect` after `lint_reasons` is stable.
 #![allow(clippy::identity_op)]
 
-use std::{path::PathBuf, sync::Arc, time::Duration};
+use std::{collections::HashMap, path::PathBuf, sync::Arc, time::Duration};
 
 use foyer_memory::FifoConfig;
 use foyer_storage::{
-    test_utils::JudgeRecorder, Compression, FsDeviceConfig, FsStoreConfig, RuntimeConfigBuilder, RuntimeStoreConfig,
-    Storage, StorageExt, Store, StoreConfig,
+    test_utils::JudgeRecorder, CatalogRetryPolicy, ChannelDropPolicy, ChecksumAlgorithm, Compression, FlushErrorPolicy,
+    FsDeviceConfig, FsStoreConfig, IoExecutor, LazyStoreConfig, PaddingPolicy, QueueFullPolicy, RecoveryReadPolicy,
+    RegionOpenErrorPolicy, RuntimeConfigBuilder, RuntimeStoreConfig, Storage, StorageExt, Store, StoreConfig,
+    UnknownRegionVersionPolicy, WriteConflictPolicy,
 };
 
 const KB: usize = 1024;
@@ -106,6 +108,12 @@ async fn test_fs_store() {
             file_size: 1 * MB,
             align: 4 * KB,
             io_size: 4 * KB,
+            io_executor: IoExecutor::default(),
+            on_region_open_error: RegionOpenErrorPolicy::default(),
+            write_io_size: None,
+            read_io_size: None,
+            extra_dirs: vec![],
+            direct_io: true,
         },
         catalog_shards: 1,
         admissions: vec![recorder.clone()],
@@ -115,6 +123,46 @@ async fn test_fs_store() {
         clean_region_threshold: 1,
         recover_concurrency: 2,
         compression: Compression::None,
+        checksum: true,
+        recent_inserts_capacity: 0,
+        max_key_size: None,
+        region_compression: None,
+        intra_block_align: None,
+        catalog_snapshot_path: None,
+        reclaim_grace_period: Duration::ZERO,
+        compression_observer: None,
+        unknown_region_version_policy: UnknownRegionVersionPolicy::default(),
+        block_compression: None,
+        background_cpu_cores: None,
+        metrics_recorder: None,
+        write_conflict_policy: WriteConflictPolicy::default(),
+        catalog_ops_limit: None,
+        first_entry_offset: None,
+        verify_region_magic_on_read: false,
+        self_test_on_open: false,
+        scrub_throughput_limit: None,
+        write_coalescing_interval: None,
+        max_writing_regions: None,
+        size_padding: PaddingPolicy::default(),
+        store_key_hash_only: false,
+        zstd_level: 0,
+        max_concurrent_decompressions: None,
+        checksum_algorithm: ChecksumAlgorithm::default(),
+        key_prefix: None,
+        flush_error_policy: FlushErrorPolicy::default(),
+        admission_observer: None,
+        max_region_age: None,
+        channel_drop_policy: ChannelDropPolicy::default(),
+        prefix_compressions: HashMap::new(),
+        io_bytes_in_flight_limit: None,
+        flusher_queue_capacity: None,
+        queue_full_policy: QueueFullPolicy::default(),
+        flush_alignment: None,
+        adaptive_compression: false,
+        scan_read_ahead: 2,
+        verify_checksum_on_recover: false,
+        catalog_backend: None,
+        catalog_retry_policy: CatalogRetryPolicy::default(),
     });
 
     test_store(config, recorder).await;
@@ -133,6 +181,12 @@ async fn test_fs_store_zstd() {
             file_size: 1 * MB,
             align: 4 * KB,
             io_size: 4 * KB,
+            io_executor: IoExecutor::default(),
+            on_region_open_error: RegionOpenErrorPolicy::default(),
+            write_io_size: None,
+            read_io_size: None,
+            extra_dirs: vec![],
+            direct_io: true,
         },
         catalog_shards: 1,
         admissions: vec![recorder.clone()],
@@ -142,6 +196,46 @@ async fn test_fs_store_zstd() {
         clean_region_threshold: 1,
         recover_concurrency: 2,
         compression: Compression::Zstd,
+        checksum: true,
+        recent_inserts_capacity: 0,
+        max_key_size: None,
+        region_compression: None,
+        intra_block_align: None,
+        catalog_snapshot_path: None,
+        reclaim_grace_period: Duration::ZERO,
+        compression_observer: None,
+        unknown_region_version_policy: UnknownRegionVersionPolicy::default(),
+        block_compression: None,
+        background_cpu_cores: None,
+        metrics_recorder: None,
+        write_conflict_policy: WriteConflictPolicy::default(),
+        catalog_ops_limit: None,
+        first_entry_offset: None,
+        verify_region_magic_on_read: false,
+        self_test_on_open: false,
+        scrub_throughput_limit: None,
+        write_coalescing_interval: None,
+        max_writing_regions: None,
+        size_padding: PaddingPolicy::default(),
+        store_key_hash_only: false,
+        zstd_level: 0,
+        max_concurrent_decompressions: None,
+        checksum_algorithm: ChecksumAlgorithm::default(),
+        key_prefix: None,
+        flush_error_policy: FlushErrorPolicy::default(),
+        admission_observer: None,
+        max_region_age: None,
+        channel_drop_policy: ChannelDropPolicy::default(),
+        prefix_compressions: HashMap::new(),
+        io_bytes_in_flight_limit: None,
+        flusher_queue_capacity: None,
+        queue_full_policy: QueueFullPolicy::default(),
+        flush_alignment: None,
+        adaptive_compression: false,
+        scan_read_ahead: 2,
+        verify_checksum_on_recover: false,
+        catalog_backend: None,
+        catalog_retry_policy: CatalogRetryPolicy::default(),
     });
 
     test_store(config, recorder).await;
@@ -160,6 +254,12 @@ async fn test_fs_store_lz4() {
             file_size: 1 * MB,
             align: 4 * KB,
             io_size: 4 * KB,
+            io_executor: IoExecutor::default(),
+            on_region_open_error: RegionOpenErrorPolicy::default(),
+            write_io_size: None,
+            read_io_size: None,
+            extra_dirs: vec![],
+            direct_io: true,
         },
         catalog_shards: 1,
         admissions: vec![recorder.clone()],
@@ -169,16 +269,56 @@ async fn test_fs_store_lz4() {
         clean_region_threshold: 1,
         recover_concurrency: 2,
         compression: Compression::Lz4,
+        checksum: true,
+        recent_inserts_capacity: 0,
+        max_key_size: None,
+        region_compression: None,
+        intra_block_align: None,
+        catalog_snapshot_path: None,
+        reclaim_grace_period: Duration::ZERO,
+        compression_observer: None,
+        unknown_region_version_policy: UnknownRegionVersionPolicy::default(),
+        block_compression: None,
+        background_cpu_cores: None,
+        metrics_recorder: None,
+        write_conflict_policy: WriteConflictPolicy::default(),
+        catalog_ops_limit: None,
+        first_entry_offset: None,
+        verify_region_magic_on_read: false,
+        self_test_on_open: false,
+        scrub_throughput_limit: None,
+        write_coalescing_interval: None,
+        max_writing_regions: None,
+        size_padding: PaddingPolicy::default(),
+        store_key_hash_only: false,
+        zstd_level: 0,
+        max_concurrent_decompressions: None,
+        checksum_algorithm: ChecksumAlgorithm::default(),
+        key_prefix: None,
+        flush_error_policy: FlushErrorPolicy::default(),
+        admission_observer: None,
+        max_region_age: None,
+        channel_drop_policy: ChannelDropPolicy::default(),
+        prefix_compressions: HashMap::new(),
+        io_bytes_in_flight_limit: None,
+        flusher_queue_capacity: None,
+        queue_full_policy: QueueFullPolicy::default(),
+        flush_alignment: None,
+        adaptive_compression: false,
+        scan_read_ahead: 2,
+        verify_checksum_on_recover: false,
+        catalog_backend: None,
+        catalog_retry_policy: CatalogRetryPolicy::default(),
     });
 
     test_store(config, recorder).await;
 }
 
 #[tokio::test]
-async fn test_lazy_fs_store() {
+async fn test_fs_store_snappy() {
     let tempdir = tempfile::tempdir().unwrap();
     let recorder = Arc::new(JudgeRecorder::default());
-    let config = StoreConfig::LazyFs(FsStoreConfig {
+    let config = StoreConfig::Fs(FsStoreConfig {
         name: "".to_string(),
         eviction_config: FifoConfig {}.into(),
         device_config: FsDeviceConfig {
@@ -187,6 +327,12 @@ async fn test_lazy_fs_store() {
             file_size: 1 * MB,
             align: 4 * KB,
             io_size: 4 * KB,
+            io_executor: IoExecutor::default(),
+            on_region_open_error: RegionOpenErrorPolicy::default(),
+            write_io_size: None,
+            read_io_size: None,
+            extra_dirs: vec![],
+            direct_io: true,
         },
         catalog_shards: 1,
         admissions: vec![recorder.clone()],
@@ -195,17 +341,57 @@ async fn test_lazy_fs_store() {
         reclaimers: 1,
         clean_region_threshold: 1,
         recover_concurrency: 2,
-        compression: Compression::None,
+        compression: Compression::Snappy,
+        checksum: true,
+        recent_inserts_capacity: 0,
+        max_key_size: None,
+        region_compression: None,
+        intra_block_align: None,
+        catalog_snapshot_path: None,
+        reclaim_grace_period: Duration::ZERO,
+        compression_observer: None,
+        unknown_region_version_policy: UnknownRegionVersionPolicy::default(),
+        block_compression: None,
+        background_cpu_cores: None,
+        metrics_recorder: None,
+        write_conflict_policy: WriteConflictPolicy::default(),
+        catalog_ops_limit: None,
+        first_entry_offset: None,
+        verify_region_magic_on_read: false,
+        self_test_on_open: false,
+        scrub_throughput_limit: None,
+        write_coalescing_interval: None,
+        max_writing_regions: None,
+        size_padding: PaddingPolicy::default(),
+        store_key_hash_only: false,
+        zstd_level: 0,
+        max_concurrent_decompressions: None,
+        checksum_algorithm: ChecksumAlgorithm::default(),
+        key_prefix: None,
+        flush_error_policy: FlushErrorPolicy::default(),
+        admission_observer: None,
+        max_region_age: None,
+        channel_drop_policy: ChannelDropPolicy::default(),
+        prefix_compressions: HashMap::new(),
+        io_bytes_in_flight_limit: None,
+        flusher_queue_capacity: None,
+        queue_full_policy: QueueFullPolicy::default(),
+        flush_alignment: None,
+        adaptive_compression: false,
+        scan_read_ahead: 2,
+        verify_checksum_on_recover: false,
+        catalog_backend: None,
+        catalog_retry_policy: CatalogRetryPolicy::default(),
     });
 
     test_store(config, recorder).await;
 }
 
 #[tokio::test]
-async fn test_runtime_fs_store() {
+async fn test_lazy_fs_store() {
     let tempdir = tempfile::tempdir().unwrap();
     let recorder = Arc::new(JudgeRecorder::default());
-    let config = StoreConfig::RuntimeFs(RuntimeStoreConfig {
+    let config = StoreConfig::LazyFs(LazyStoreConfig {
         store_config: FsStoreConfig {
             name: "".to_string(),
             eviction_config: FifoConfig {}.into(),
@@ -215,6 +401,12 @@ async fn test_runtime_fs_store() {
                 file_size: 1 * MB,
                 align: 4 * KB,
                 io_size: 4 * KB,
+                io_executor: IoExecutor::default(),
+                on_region_open_error: RegionOpenErrorPolicy::default(),
+                write_io_size: None,
+                read_io_size: None,
+                extra_dirs: vec![],
+                direct_io: true,
             },
             catalog_shards: 1,
             admissions: vec![recorder.clone()],
@@ -224,18 +416,58 @@ async fn test_runtime_fs_store() {
             clean_region_threshold: 1,
             recover_concurrency: 2,
             compression: Compression::None,
+            checksum: true,
+            recent_inserts_capacity: 0,
+            max_key_size: None,
+            region_compression: None,
+            intra_block_align: None,
+            catalog_snapshot_path: None,
+            reclaim_grace_period: Duration::ZERO,
+            compression_observer: None,
+            unknown_region_version_policy: UnknownRegionVersionPolicy::default(),
+            block_compression: None,
+            background_cpu_cores: None,
+            metrics_recorder: None,
+            write_conflict_policy: WriteConflictPolicy::default(),
+            catalog_ops_limit: None,
+            first_entry_offset: None,
+            verify_region_magic_on_read: false,
+            self_test_on_open: false,
+            scrub_throughput_limit: None,
+            write_coalescing_interval: None,
+            max_writing_regions: None,
+            size_padding: PaddingPolicy::default(),
+            store_key_hash_only: false,
+            zstd_level: 0,
+            max_concurrent_decompressions: None,
+            checksum_algorithm: ChecksumAlgorithm::default(),
+            key_prefix: None,
+            flush_error_policy: FlushErrorPolicy::default(),
+            admission_observer: None,
+            max_region_age: None,
+            channel_drop_policy: ChannelDropPolicy::default(),
+            prefix_compressions: HashMap::new(),
+            io_bytes_in_flight_limit: None,
+            flusher_queue_capacity: None,
+            queue_full_policy: QueueFullPolicy::default(),
+            flush_alignment: None,
+            adaptive_compression: false,
+            scan_read_ahead: 2,
+            verify_checksum_on_recover: false,
+            catalog_backend: None,
+            catalog_retry_policy: CatalogRetryPolicy::default(),
         },
-        runtime_config: RuntimeConfigBuilder::new().build(),
+        recovery_read_policy: RecoveryReadPolicy::default(),
     });
 
     test_store(config, recorder).await;
 }
 
 #[tokio::test]
-async fn test_runtime_lazy_fs_store() {
+async fn test_runtime_fs_store() {
     let tempdir = tempfile::tempdir().unwrap();
     let recorder = Arc::new(JudgeRecorder::default());
-    let config = StoreConfig::RuntimeLazyFs(RuntimeStoreConfig {
+    let config = StoreConfig::RuntimeFs(RuntimeStoreConfig {
         store_config: FsStoreConfig {
             name: "".to_string(),
             eviction_config: FifoConfig {}.into(),
@@ -245,6 +477,12 @@ async fn test_runtime_lazy_fs_store() {
                 file_size: 1 * MB,
                 align: 4 * KB,
                 io_size: 4 * KB,
+                io_executor: IoExecutor::default(),
+                on_region_open_error: RegionOpenErrorPolicy::default(),
+                write_io_size: None,
+                read_io_size: None,
+                extra_dirs: vec![],
+                direct_io: true,
             },
             catalog_shards: 1,
             admissions: vec![recorder.clone()],
@@ -254,6 +492,125 @@ async fn test_runtime_lazy_fs_store() {
             clean_region_threshold: 1,
             recover_concurrency: 2,
             compression: Compression::None,
+            checksum: true,
+            recent_inserts_capacity: 0,
+            max_key_size: None,
+            region_compression: None,
+            intra_block_align: None,
+            catalog_snapshot_path: None,
+            reclaim_grace_period: Duration::ZERO,
+            compression_observer: None,
+            unknown_region_version_policy: UnknownRegionVersionPolicy::default(),
+            block_compression: None,
+            background_cpu_cores: None,
+            metrics_recorder: None,
+            write_conflict_policy: WriteConflictPolicy::default(),
+            catalog_ops_limit: None,
+            first_entry_offset: None,
+            verify_region_magic_on_read: false,
+            self_test_on_open: false,
+            scrub_throughput_limit: None,
+            write_coalescing_interval: None,
+            max_writing_regions: None,
+            size_padding: PaddingPolicy::default(),
+            store_key_hash_only: false,
+            zstd_level: 0,
+            max_concurrent_decompressions: None,
+            checksum_algorithm: ChecksumAlgorithm::default(),
+            key_prefix: None,
+            flush_error_policy: FlushErrorPolicy::default(),
+            admission_observer: None,
+            max_region_age: None,
+            channel_drop_policy: ChannelDropPolicy::default(),
+            prefix_compressions: HashMap::new(),
+            io_bytes_in_flight_limit: None,
+            flusher_queue_capacity: None,
+            queue_full_policy: QueueFullPolicy::default(),
+            flush_alignment: None,
+            adaptive_compression: false,
+            scan_read_ahead: 2,
+            verify_checksum_on_recover: false,
+            catalog_backend: None,
+            catalog_retry_policy: CatalogRetryPolicy::default(),
+        },
+        runtime_config: RuntimeConfigBuilder::new().build(),
+    });
+
+    test_store(config, recorder).await;
+}
+
+#[tokio::test]
+async fn test_runtime_lazy_fs_store() {
+    let tempdir = tempfile::tempdir().unwrap();
+    let recorder = Arc::new(JudgeRecorder::default());
+    let config = StoreConfig::RuntimeLazyFs(RuntimeStoreConfig {
+        store_config: LazyStoreConfig {
+            store_config: FsStoreConfig {
+                name: "".to_string(),
+                eviction_config: FifoConfig {}.into(),
+                device_config: FsDeviceConfig {
+                    dir: PathBuf::from(tempdir.path()),
+                    capacity: 4 * MB,
+                    file_size: 1 * MB,
+                    align: 4 * KB,
+                    io_size: 4 * KB,
+                    io_executor: IoExecutor::default(),
+                    on_region_open_error: RegionOpenErrorPolicy::default(),
+                    write_io_size: None,
+                    read_io_size: None,
+                    extra_dirs: vec![],
+                    direct_io: true,
+                },
+                catalog_shards: 1,
+                admissions: vec![recorder.clone()],
+                reinsertions: vec![recorder.clone()],
+                flushers: 1,
+                reclaimers: 1,
+                clean_region_threshold: 1,
+                recover_concurrency: 2,
+                compression: Compression::None,
+                checksum: true,
+                recent_inserts_capacity: 0,
+                max_key_size: None,
+                region_compression: None,
+                intra_block_align: None,
+                catalog_snapshot_path: None,
+                reclaim_grace_period: Duration::ZERO,
+                compression_observer: None,
+                unknown_region_version_policy: UnknownRegionVersionPolicy::default(),
+                block_compression: None,
+                background_cpu_cores: None,
+                metrics_recorder: None,
+                write_conflict_policy: WriteConflictPolicy::default(),
+                catalog_ops_limit: None,
+                first_entry_offset: None,
+                verify_region_magic_on_read: false,
+                self_test_on_open: false,
+                scrub_throughput_limit: None,
+                write_coalescing_interval: None,
+                max_writing_regions: None,
+                size_padding: PaddingPolicy::default(),
+                store_key_hash_only: false,
+                zstd_level: 0,
+                max_concurrent_decompressions: None,
+                checksum_algorithm: ChecksumAlgorithm::default(),
+                key_prefix: None,
+                flush_error_policy: FlushErrorPolicy::default(),
+                admission_observer: None,
+                max_region_age: None,
+                channel_drop_policy: ChannelDropPolicy::default(),
+                prefix_compressions: HashMap::new(),
+                io_bytes_in_flight_limit: None,
+                flusher_queue_capacity: None,
+                queue_full_policy: QueueFullPolicy::default(),
+                flush_alignment: None,
+                adaptive_compression: false,
+                scan_read_ahead: 2,
+                verify_checksum_on_recover: false,
+                catalog_backend: None,
+                catalog_retry_policy: CatalogRetryPolicy::default(),
+            },
+            recovery_read_policy: RecoveryReadPolicy::default(),
         },
         runtime_config: RuntimeConfigBuilder::new().build(),
     });
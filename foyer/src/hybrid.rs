@@ -14,20 +14,217 @@
 
 use std::{
     borrow::Borrow,
+    fs::{self, File},
     future::Future,
     hash::{BuildHasher, Hash},
-    sync::{Arc, OnceLock},
+    io::{BufReader, BufWriter, ErrorKind, Write},
+    panic::AssertUnwindSafe,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc, OnceLock,
+    },
+    time::{Duration, Instant, SystemTime},
 };
 
 use ahash::RandomState;
+use anyhow::anyhow;
 use foyer_common::code::{StorageKey, StorageValue};
 use foyer_memory::{
-    Cache, CacheBuilder, CacheContext, CacheEntry, CacheEventListener, Entry, EvictionConfig, Weighter,
+    Cache, CacheBuilder, CacheContext, CacheEntry, CacheEventListener, CacheFull, CachePriority, Entry, EvictionConfig,
+    InsertMode, ReleasedEntry, ShouldEvict, Weighter,
 };
 use foyer_storage::{
-    AdmissionPolicy, AsyncStorageExt, Compression, DeviceConfig, ReinsertionPolicy, RuntimeConfig, Storage, Store,
-    StoreBuilder,
+    AdmissionObserver, AdmissionPolicy, AsyncStorageExt, BlockCompression, CachedEntry, CatalogBackend,
+    CatalogRetryPolicy, ChannelDropPolicy, ChecksumAlgorithm, Compression, CompressionObserver, DeviceConfig,
+    FlushErrorPolicy, KeyPrefix, MetricsRecorder, PaddingPolicy, QueueFullPolicy, RecoveryReadPolicy,
+    ReinsertionPolicy, RuntimeConfig, Storage, StorageExt, StorageWriter, Store, StoreBuilder,
+    UnknownRegionVersionPolicy, WriteConflictPolicy,
 };
+use futures::{stream, FutureExt, Stream, StreamExt};
+
+/// Default concurrency of the storage reads of [`HybridCache::get_batch`].
+pub const DEFAULT_GET_BATCH_CONCURRENCY: usize = 16;
+
+/// Magic of the memory tier snapshot file, see [`HybridCachePersistence::Snapshot`].
+const MEMORY_SNAPSHOT_MAGIC: u64 = 0x20240712;
+
+/// Count of the locks that serialize the updates of [`HybridCache::update`] by key.
+const UPDATE_LOCK_SHARDS: usize = 64;
+
+/// Locks that serialize the updates of the same key, the keys are mapped to the locks by their hash.
+#[derive(Debug)]
+struct UpdateLocks {
+    hash_builder: RandomState,
+    locks: Box<[tokio::sync::Mutex<()>]>,
+}
+
+impl Default for UpdateLocks {
+    fn default() -> Self {
+        Self {
+            hash_builder: RandomState::default(),
+            locks: (0..UPDATE_LOCK_SHARDS).map(|_| tokio::sync::Mutex::new(())).collect(),
+        }
+    }
+}
+
+impl UpdateLocks {
+    async fn lock<K: Hash>(&self, key: &K) -> tokio::sync::MutexGuard<'_, ()> {
+        let index = self.hash_builder.hash_one(key) as usize % self.locks.len();
+        self.locks[index].lock().await
+    }
+}
+
+/// Count of the key hashes tracked by [`PromotionPolicy::OnSecondAccess`].
+const PROMOTION_HISTORY_SLOTS: usize = 4096;
+
+/// Policy of promoting the entries found in the storage tier to the memory tier on lookups.
+///
+/// It applies to [`HybridCache::get`], [`HybridCache::get_with_timeout`] and [`HybridCache::get_batch`]. The entries
+/// computed by [`HybridCache::entry`] or explicitly promoted by [`HybridCache::touch_and_promote`] are always cached.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PromotionPolicy {
+    /// Promote the entry on each storage tier hit.
+    #[default]
+    Always,
+    /// Never promote, the entry is returned without being inserted into the memory tier or evicting others.
+    Never,
+    /// Promote the entry on its second storage tier hit, so the entries read only once don't evict the others.
+    ///
+    /// The first hits are tracked by the key hashes in a fixed size table, so a first hit can be forgotten if another
+    /// key takes its slot.
+    OnSecondAccess,
+}
+
+/// Decides whether to promote an entry found in the storage tier by the [`PromotionPolicy`].
+#[derive(Debug)]
+struct Promotion {
+    policy: PromotionPolicy,
+    hash_builder: RandomState,
+    /// hashes of the keys hit once in the storage tier, `0` marks an empty slot
+    history: Box<[AtomicU64]>,
+}
+
+impl Promotion {
+    fn new(policy: PromotionPolicy) -> Self {
+        let slots = match policy {
+            PromotionPolicy::OnSecondAccess => PROMOTION_HISTORY_SLOTS,
+            PromotionPolicy::Always | PromotionPolicy::Never => 0,
+        };
+        Self {
+            policy,
+            hash_builder: RandomState::default(),
+            history: (0..slots).map(|_| AtomicU64::new(0)).collect(),
+        }
+    }
+
+    fn judge<K: Hash>(&self, key: &K) -> bool {
+        match self.policy {
+            PromotionPolicy::Always => true,
+            PromotionPolicy::Never => false,
+            PromotionPolicy::OnSecondAccess => {
+                let hash = self.hash_builder.hash_one(key).max(1);
+                let slot = &self.history[hash as usize % self.history.len()];
+                // Promote on the second hit and forget the key, otherwise record the first hit.
+                if slot
+                    .compare_exchange(hash, 0, Ordering::AcqRel, Ordering::Relaxed)
+                    .is_ok()
+                {
+                    return true;
+                }
+                slot.store(hash, Ordering::Relaxed);
+                false
+            }
+        }
+    }
+}
+
+/// How the hybrid cache persists the entries of the memory tier.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum HybridCachePersistence {
+    /// Entries evicted from the memory tier are continuously demoted to the storage tier.
+    #[default]
+    Continuous,
+    /// Entries stay in memory only during operation. The whole memory tier is written to the snapshot file at `path`
+    /// on [`HybridCache::shutdown`], and loaded from it on build to warm the cache.
+    ///
+    /// The storage tier is disabled in this mode, so there are no continuous flushes. The snapshot file is removed
+    /// after loaded, so a crash without graceful shutdown never restores stale entries.
+    Snapshot { path: PathBuf },
+}
+
+/// Metrics of the entries flowing between the memory tier and the storage tier.
+#[derive(Debug, Default)]
+pub struct HybridCacheMetrics {
+    /// entries promoted from the storage tier to the memory tier
+    pub promoted: AtomicUsize,
+    /// entries demoted from the memory tier to the storage tier
+    pub demoted: AtomicUsize,
+    /// lookups served by the memory tier
+    pub memory_hit: AtomicUsize,
+    /// lookups missed by the memory tier
+    pub memory_miss: AtomicUsize,
+    /// lookups served by the storage tier
+    pub storage_hit: AtomicUsize,
+    /// lookups missed by both tiers, including the decode errors
+    pub storage_miss: AtomicUsize,
+    /// lookups whose entry is read from the storage tier but fails to decode, e.g. checksum
+    /// mismatch
+    pub storage_miss_decode_error: AtomicUsize,
+}
+
+/// Snapshot of [`HybridCacheMetrics`], see [`HybridCacheMetrics::stats`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct HybridCacheStats {
+    pub promoted: usize,
+    pub demoted: usize,
+    pub memory_hit: usize,
+    pub memory_miss: usize,
+    pub storage_hit: usize,
+    pub storage_miss: usize,
+    pub storage_miss_decode_error: usize,
+}
+
+impl HybridCacheMetrics {
+    /// Take a snapshot of the counters.
+    ///
+    /// The counters are loaded one by one, so the snapshot is not atomic with concurrent operations.
+    pub fn stats(&self) -> HybridCacheStats {
+        HybridCacheStats {
+            promoted: self.promoted.load(Ordering::Relaxed),
+            demoted: self.demoted.load(Ordering::Relaxed),
+            memory_hit: self.memory_hit.load(Ordering::Relaxed),
+            memory_miss: self.memory_miss.load(Ordering::Relaxed),
+            storage_hit: self.storage_hit.load(Ordering::Relaxed),
+            storage_miss: self.storage_miss.load(Ordering::Relaxed),
+            storage_miss_decode_error: self.storage_miss_decode_error.load(Ordering::Relaxed),
+        }
+    }
+
+    fn record_memory(&self, hit: bool) {
+        match hit {
+            true => self.memory_hit.fetch_add(1, Ordering::Relaxed),
+            false => self.memory_miss.fetch_add(1, Ordering::Relaxed),
+        };
+    }
+
+    fn record_storage<T>(&self, res: &foyer_storage::Result<Option<T>>) {
+        match res {
+            Ok(Some(_)) => {
+                self.storage_hit.fetch_add(1, Ordering::Relaxed);
+            }
+            Ok(None) => {
+                self.storage_miss.fetch_add(1, Ordering::Relaxed);
+            }
+            Err(e) => {
+                self.storage_miss.fetch_add(1, Ordering::Relaxed);
+                if matches!(e, foyer_storage::Error::EntryDecode(_)) {
+                    self.storage_miss_decode_error.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+        }
+    }
+}
 
 struct HybridCacheEventListenerInner<K, V>
 where
@@ -35,6 +232,7 @@ where
     V: StorageValue,
 {
     store: OnceLock<Store<K, V>>,
+    metrics: Arc<HybridCacheMetrics>,
 }
 
 pub struct HybridCacheEventListener<K, V>
@@ -52,7 +250,10 @@ where
 {
     fn default() -> Self {
         Self {
-            inner: Arc::new(HybridCacheEventListenerInner { store: OnceLock::new() }),
+            inner: Arc::new(HybridCacheEventListenerInner {
+                store: OnceLock::new(),
+                metrics: Arc::new(HybridCacheMetrics::default()),
+            }),
         }
     }
 }
@@ -74,9 +275,76 @@ where
     K: StorageKey,
     V: StorageValue,
 {
-    fn on_release(&self, key: Arc<K>, value: Arc<V>, _context: CacheContext, _weight: usize) {
+    fn on_release(&self, key: Arc<K>, value: Arc<V>, context: CacheContext, weight: usize) {
+        self.on_release_with_priority(key, value, context, CachePriority::default(), weight)
+    }
+
+    fn on_release_with_priority(
+        &self,
+        key: Arc<K>,
+        value: Arc<V>,
+        _context: CacheContext,
+        priority: CachePriority,
+        _weight: usize,
+    ) {
+        self.demote(key, value, priority, None)
+    }
+
+    fn on_release_entry(&self, entry: ReleasedEntry<K, V>) {
+        // Expired entries are dropped instead of demoted, the rest keep the remaining ttl in the storage tier.
+        let expire_at = match entry.expire_at {
+            Some(expire_at) => match expire_at.checked_duration_since(Instant::now()) {
+                Some(ttl) if !ttl.is_zero() => Some(SystemTime::now() + ttl),
+                _ => return,
+            },
+            None => None,
+        };
+        self.demote(entry.key, entry.value, entry.priority, expire_at)
+    }
+}
+
+impl<K, V> HybridCacheEventListener<K, V>
+where
+    K: StorageKey,
+    V: StorageValue,
+{
+    fn demote(&self, key: Arc<K>, value: Arc<V>, priority: CachePriority, expire_at: Option<SystemTime>) {
         // TODO(MrCroxx): Return read handle to block following request of the key and clear with callback?
-        unsafe { self.inner.store.get().unwrap_unchecked() }.insert_if_not_exists_async(key, value)
+        let metrics = self.inner.metrics.clone();
+        let callback = |res: foyer_storage::Result<bool>| async move {
+            if let Ok(true) = res {
+                metrics.demoted.fetch_add(1, Ordering::Relaxed);
+            }
+        };
+        let store = unsafe { self.inner.store.get().unwrap_unchecked() };
+
+        if let Some(expire_at) = expire_at {
+            let store = store.clone();
+            tokio::spawn(async move {
+                let res = match store.exists(&key) {
+                    Ok(true) => Ok(false),
+                    Ok(false) => {
+                        let mut writer = store.writer(key);
+                        if priority == CachePriority::High {
+                            writer.force();
+                        }
+                        writer.set_expire_at(expire_at);
+                        writer.finish(value).await.map(|res| res.is_some())
+                    }
+                    Err(e) => Err(e),
+                };
+                callback(res).await;
+            });
+            return;
+        }
+
+        match priority {
+            // High priority entries bypass the admission policies of the storage tier.
+            CachePriority::High => store.insert_if_not_exists_force_async_with_callback(key, value, callback),
+            CachePriority::Normal | CachePriority::Low => {
+                store.insert_if_not_exists_async_with_callback(key, value, callback)
+            }
+        }
     }
 }
 
@@ -93,6 +361,10 @@ impl HybridCacheBuilder {
         Self
     }
 
+    /// Configure the memory tier with its `capacity`.
+    ///
+    /// The memory tier capacity is measured by the weighter (count of entries by default), and it is independent from
+    /// the storage tier capacity, which is configured by the device config in the storage phase.
     pub fn memory<K, V>(self, capacity: usize) -> HybridCacheBuilderPhaseMemory<K, V, RandomState>
     where
         K: StorageKey,
@@ -177,11 +449,69 @@ where
         }
     }
 
+    /// Set in-memory cache eviction veto.
+    ///
+    /// If `should_evict` returns `false` for an eviction candidate, the candidate is skipped and the next one is
+    /// evicted. If all candidates are vetoed, they will be evicted anyway to release space.
+    pub fn with_should_evict(self, should_evict: impl ShouldEvict<K, V>) -> Self {
+        let builder = self.builder.with_should_evict(should_evict);
+        HybridCacheBuilderPhaseMemory {
+            builder,
+            listener: self.listener,
+        }
+    }
+
+    /// Set in-memory cache insert mode.
+    ///
+    /// With [`InsertMode::NoEvict`], inserting a new key into a full in-memory cache is rejected instead of evicting
+    /// other entries to the disk cache.
+    pub fn with_insert_mode(self, insert_mode: InsertMode) -> Self {
+        let builder = self.builder.with_insert_mode(insert_mode);
+        HybridCacheBuilderPhaseMemory {
+            builder,
+            listener: self.listener,
+        }
+    }
+
+    /// Spread the expiry of the entries inserted with the same ttl over `[ttl, ttl * (1 + ttl_jitter)]`.
+    ///
+    /// See [`CacheBuilder::with_ttl_jitter`].
+    pub fn with_ttl_jitter(self, ttl_jitter: f64) -> Self {
+        let builder = self.builder.with_ttl_jitter(ttl_jitter);
+        HybridCacheBuilderPhaseMemory {
+            builder,
+            listener: self.listener,
+        }
+    }
+
+    /// Serve an expired entry if refetching it fails, until it has been expired for `max_staleness`.
+    ///
+    /// See [`CacheBuilder::with_serve_stale_on_error`].
+    pub fn with_serve_stale_on_error(self, max_staleness: Duration) -> Self {
+        let builder = self.builder.with_serve_stale_on_error(max_staleness);
+        HybridCacheBuilderPhaseMemory {
+            builder,
+            listener: self.listener,
+        }
+    }
+
+    /// Deliver the entries released by the in-memory cache to the disk cache in batches of `count` entries, or after
+    /// `interval` since the batch is started, whichever comes first.
+    pub fn with_release_batch(self, count: usize, interval: Duration) -> Self {
+        let builder = self.builder.with_release_batch(count, interval);
+        HybridCacheBuilderPhaseMemory {
+            builder,
+            listener: self.listener,
+        }
+    }
+
     pub fn storage(self) -> HybridCacheBuilderPhaseStorage<K, V, S> {
         HybridCacheBuilderPhaseStorage {
             listener: self.listener,
             cache: self.builder.build(),
             builder: StoreBuilder::new(),
+            persistence: HybridCachePersistence::default(),
+            promotion_policy: PromotionPolicy::default(),
         }
     }
 }
@@ -195,6 +525,8 @@ where
     listener: HybridCacheEventListener<K, V>,
     cache: Cache<K, V, HybridCacheEventListener<K, V>, S>,
     builder: StoreBuilder<K, V>,
+    persistence: HybridCachePersistence,
+    promotion_policy: PromotionPolicy,
 }
 
 impl<K, V, S> HybridCacheBuilderPhaseStorage<K, V, S>
@@ -212,6 +544,8 @@ where
             listener: self.listener,
             cache: self.cache,
             builder,
+            persistence: self.persistence,
+            promotion_policy: self.promotion_policy,
         }
     }
 
@@ -224,6 +558,8 @@ where
             listener: self.listener,
             cache: self.cache,
             builder,
+            persistence: self.persistence,
+            promotion_policy: self.promotion_policy,
         }
     }
 
@@ -236,6 +572,8 @@ where
             listener: self.listener,
             cache: self.cache,
             builder,
+            persistence: self.persistence,
+            promotion_policy: self.promotion_policy,
         }
     }
 
@@ -246,6 +584,8 @@ where
             listener: self.listener,
             cache: self.cache,
             builder,
+            persistence: self.persistence,
+            promotion_policy: self.promotion_policy,
         }
     }
 
@@ -256,6 +596,20 @@ where
             listener: self.listener,
             cache: self.cache,
             builder,
+            persistence: self.persistence,
+            promotion_policy: self.promotion_policy,
+        }
+    }
+
+    /// Set the observer of the admission decisions made by each admission policy.
+    pub fn with_admission_observer(self, observer: Arc<dyn AdmissionObserver<Key = K>>) -> Self {
+        let builder = self.builder.with_admission_observer(observer);
+        Self {
+            listener: self.listener,
+            cache: self.cache,
+            builder,
+            persistence: self.persistence,
+            promotion_policy: self.promotion_policy,
         }
     }
 
@@ -266,6 +620,8 @@ where
             listener: self.listener,
             cache: self.cache,
             builder,
+            persistence: self.persistence,
+            promotion_policy: self.promotion_policy,
         }
     }
 
@@ -276,6 +632,8 @@ where
             listener: self.listener,
             cache: self.cache,
             builder,
+            persistence: self.persistence,
+            promotion_policy: self.promotion_policy,
         }
     }
 
@@ -286,6 +644,8 @@ where
             listener: self.listener,
             cache: self.cache,
             builder,
+            persistence: self.persistence,
+            promotion_policy: self.promotion_policy,
         }
     }
 
@@ -300,16 +660,22 @@ where
             listener: self.listener,
             cache: self.cache,
             builder,
+            persistence: self.persistence,
+            promotion_policy: self.promotion_policy,
         }
     }
 
-    /// Concurrency of recovery.
+    /// Count of regions scanned concurrently during recovery, must be > 0.
+    ///
+    /// The default recover concurrency is 8.
     pub fn with_recover_concurrency(self, recover_concurrency: usize) -> Self {
         let builder = self.builder.with_recover_concurrency(recover_concurrency);
         Self {
             listener: self.listener,
             cache: self.cache,
             builder,
+            persistence: self.persistence,
+            promotion_policy: self.promotion_policy,
         }
     }
 
@@ -320,131 +686,1149 @@ where
             listener: self.listener,
             cache: self.cache,
             builder,
+            persistence: self.persistence,
+            promotion_policy: self.promotion_policy,
         }
     }
 
-    /// Enable a dedicated tokio runtime for the store with a runtime config.
+    /// Enable checksum for entries.
     ///
-    /// If not given, the store will use the user's runtime.
-    pub fn with_runtime_config(self, runtime_config: RuntimeConfig) -> Self {
-        let builder = self.builder.with_runtime_config(runtime_config);
+    /// WARNING: Disabling checksum is unsafe for durability, corrupted entries can NOT be detected. Only disable it
+    /// for ephemeral caches on trusted devices.
+    ///
+    /// Default: `true`.
+    pub fn with_checksum(self, checksum: bool) -> Self {
+        let builder = self.builder.with_checksum(checksum);
         Self {
             listener: self.listener,
             cache: self.cache,
             builder,
+            persistence: self.persistence,
+            promotion_policy: self.promotion_policy,
         }
     }
 
-    /// Decide if lazy reocvery feature is enabled.
+    /// Capacity of the ring buffer that records the recent inserted keys and sequences for diagnosis.
     ///
-    /// If enabled, the opening for the store will return immediately, but always return `None` before recovery is finished.
-    pub fn with_lazy(self, lazy: bool) -> Self {
-        let builder = self.builder.with_lazy(lazy);
+    /// Default: `0` (disabled).
+    pub fn with_recent_inserts_capacity(self, recent_inserts_capacity: usize) -> Self {
+        let builder = self.builder.with_recent_inserts_capacity(recent_inserts_capacity);
         Self {
             listener: self.listener,
             cache: self.cache,
             builder,
+            persistence: self.persistence,
+            promotion_policy: self.promotion_policy,
         }
     }
 
-    pub async fn build(self) -> anyhow::Result<HybridCache<K, V, S>> {
-        let store = self.builder.build().await?;
-        self.listener.inner.store.set(store.clone()).unwrap();
-        Ok(HybridCache {
+    /// Max serialized size of a key, larger keys are rejected on insertion.
+    ///
+    /// Default: unlimited.
+    pub fn with_max_key_size(self, max_key_size: usize) -> Self {
+        let builder = self.builder.with_max_key_size(max_key_size);
+        Self {
+            listener: self.listener,
             cache: self.cache,
-            store,
-        })
+            builder,
+            persistence: self.persistence,
+            promotion_policy: self.promotion_policy,
+        }
     }
-}
-
-pub type HybridCacheEntry<K, V, S> = CacheEntry<K, V, HybridCacheEventListener<K, V>, S>;
-
-pub struct HybridCache<K, V, S = RandomState>
-where
-    K: StorageKey,
-    V: StorageValue,
-    S: BuildHasher + Send + Sync + 'static,
-{
-    cache: Cache<K, V, HybridCacheEventListener<K, V>, S>,
-    store: Store<K, V>,
-}
 
-impl<K, V, S> HybridCache<K, V, S>
-where
-    K: StorageKey,
-    V: StorageValue,
-    S: BuildHasher + Send + Sync + 'static,
-{
-    pub fn cache(&self) -> &Cache<K, V, HybridCacheEventListener<K, V>, S> {
-        &self.cache
+    /// Compression algorithm applied to all entries of a region, which overrides the compression of each entry.
+    ///
+    /// Default: compress per entry.
+    pub fn with_region_compression(self, compression: Compression) -> Self {
+        let builder = self.builder.with_region_compression(compression);
+        Self {
+            listener: self.listener,
+            cache: self.cache,
+            builder,
+            persistence: self.persistence,
+            promotion_policy: self.promotion_policy,
+        }
     }
 
-    pub fn store(&self) -> &Store<K, V> {
-        &self.store
+    /// Pack entries in device blocks, aligned to `intra_block_align` instead of the device align.
+    ///
+    /// Default: each entry is aligned to the device align.
+    pub fn with_intra_block_align(self, intra_block_align: usize) -> Self {
+        let builder = self.builder.with_intra_block_align(intra_block_align);
+        Self {
+            listener: self.listener,
+            cache: self.cache,
+            builder,
+            persistence: self.persistence,
+            promotion_policy: self.promotion_policy,
+        }
     }
 
-    pub fn insert<AK, AV>(&self, key: AK, value: AV) -> HybridCacheEntry<K, V, S>
-    where
-        AK: Into<Arc<K>> + Send + 'static,
-        AV: Into<Arc<V>> + Send + 'static,
-    {
-        self.cache.insert(key, value)
+    /// Recover the catalog from the snapshot at `path` on open, falls back to scanning all regions if the snapshot is
+    /// missing or stale.
+    ///
+    /// Default: recover by scanning all regions.
+    pub fn with_catalog_snapshot_path(self, path: impl AsRef<Path>) -> Self {
+        let builder = self.builder.with_catalog_snapshot_path(path);
+        Self {
+            listener: self.listener,
+            cache: self.cache,
+            builder,
+            persistence: self.persistence,
+            promotion_policy: self.promotion_policy,
+        }
     }
 
-    pub fn insert_with_context<AK, AV>(&self, key: AK, value: AV, context: CacheContext) -> HybridCacheEntry<K, V, S>
-    where
-        AK: Into<Arc<K>> + Send + 'static,
-        AV: Into<Arc<V>> + Send + 'static,
-    {
-        self.cache.insert_with_context(key, value, context)
+    /// Set the duration that an evictable region remains readable before it is physically reclaimed.
+    ///
+    /// Default: reclaim immediately.
+    pub fn with_reclaim_grace_period(self, reclaim_grace_period: Duration) -> Self {
+        let builder = self.builder.with_reclaim_grace_period(reclaim_grace_period);
+        Self {
+            listener: self.listener,
+            cache: self.cache,
+            builder,
+            persistence: self.persistence,
+            promotion_policy: self.promotion_policy,
+        }
     }
 
-    pub async fn get<Q>(&self, key: &Q) -> anyhow::Result<Option<HybridCacheEntry<K, V, S>>>
-    where
-        K: Borrow<Q>,
-        Q: Hash + Eq + ?Sized + Send + Sync + 'static + Clone,
-    {
-        if let Some(entry) = self.cache.get(key) {
-            return Ok(Some(entry));
+    /// Set the observer of the compression decision of each written entry.
+    ///
+    /// Default: no observer.
+    pub fn with_compression_observer(self, observer: Arc<dyn CompressionObserver>) -> Self {
+        let builder = self.builder.with_compression_observer(observer);
+        Self {
+            listener: self.listener,
+            cache: self.cache,
+            builder,
+            persistence: self.persistence,
+            promotion_policy: self.promotion_policy,
         }
-        if let Some(entry) = self.store.get(key).await? {
-            let (key, value) = entry.to_arc();
-            return Ok(Some(self.cache.insert(key, value)));
+    }
+
+    /// Set the policy for the regions with an unknown (newer) format version found during recovery.
+    ///
+    /// Default: [`UnknownRegionVersionPolicy::SkipWithWarning`].
+    pub fn with_unknown_region_version_policy(self, policy: UnknownRegionVersionPolicy) -> Self {
+        let builder = self.builder.with_unknown_region_version_policy(policy);
+        Self {
+            listener: self.listener,
+            cache: self.cache,
+            builder,
+            persistence: self.persistence,
+            promotion_policy: self.promotion_policy,
         }
-        Ok(None)
     }
 
-    pub fn remove<Q>(&self, key: &Q) -> anyhow::Result<bool>
-    where
-        K: Borrow<Q>,
-        Q: Hash + Eq + ?Sized,
-    {
-        let cache = self.cache.remove(key).is_some();
-        let store = self.store.remove(key)?;
-        Ok(cache || store)
+    /// Compress consecutive entries together as blocks.
+    ///
+    /// Block compression exploits the redundancy across entries, e.g. many similar small records. The compression of
+    /// each entry is ignored, but the region compression still overrides the block compression if set.
+    ///
+    /// Default: disabled.
+    pub fn with_block_compression(self, block_compression: BlockCompression) -> Self {
+        let builder = self.builder.with_block_compression(block_compression);
+        Self {
+            listener: self.listener,
+            cache: self.cache,
+            builder,
+            persistence: self.persistence,
+            promotion_policy: self.promotion_policy,
+        }
     }
 
-    pub fn contains<Q>(&self, key: &Q) -> anyhow::Result<bool>
-    where
-        K: Borrow<Q>,
-        Q: Hash + Eq + ?Sized,
-    {
-        Ok(self.cache.contains(key) || self.store.exists(key)?)
+    /// Limit the CPU cores consumed by the background tasks, e.g. compression and checksum on flush, and
+    /// decompression on reclamation.
+    ///
+    /// The budget can be fractional, e.g. `0.5` means half a core.
+    ///
+    /// Default: unlimited.
+    pub fn with_background_cpu_cores(self, cores: f64) -> Self {
+        let builder = self.builder.with_background_cpu_cores(cores);
+        Self {
+            listener: self.listener,
+            cache: self.cache,
+            builder,
+            persistence: self.persistence,
+            promotion_policy: self.promotion_policy,
+        }
     }
 
-    pub fn clear(&self) -> anyhow::Result<()> {
-        self.cache.clear();
-        self.store.clear()?;
-        Ok(())
+    /// Set the metrics recorder of the store, e.g. to export metrics with a backend other than Prometheus.
+    ///
+    /// Default: the recorder set by `set_metrics_recorder`, or a Prometheus recorder if not set.
+    pub fn with_metrics_recorder(self, recorder: Arc<dyn MetricsRecorder>) -> Self {
+        let builder = self.builder.with_metrics_recorder(recorder);
+        Self {
+            listener: self.listener,
+            cache: self.cache,
+            builder,
+            persistence: self.persistence,
+            promotion_policy: self.promotion_policy,
+        }
     }
-}
 
-pub type HybridEntry<K, V, S> = Entry<K, V, anyhow::Error, HybridCacheEventListener<K, V>, S>;
+    /// Set the policy to decide the winner when the same key is inserted concurrently.
+    ///
+    /// Default: [`WriteConflictPolicy::LastWriterWins`].
+    pub fn with_write_conflict_policy(self, policy: WriteConflictPolicy) -> Self {
+        let builder = self.builder.with_write_conflict_policy(policy);
+        Self {
+            listener: self.listener,
+            cache: self.cache,
+            builder,
+            persistence: self.persistence,
+            promotion_policy: self.promotion_policy,
+        }
+    }
 
-impl<K, V, S> HybridCache<K, V, S>
-where
-    K: StorageKey + Clone,
-    V: StorageValue,
+    /// Limit the rate of the catalog mutations issued by the inserts, in ops per second.
+    ///
+    /// Inserts exceeding the rate are delayed, which protects the catalog from a flood of tiny inserts.
+    ///
+    /// Default: unlimited.
+    pub fn with_catalog_ops_limit(self, ops_per_sec: f64) -> Self {
+        let builder = self.builder.with_catalog_ops_limit(ops_per_sec);
+        Self {
+            listener: self.listener,
+            cache: self.cache,
+            builder,
+            persistence: self.persistence,
+            promotion_policy: self.promotion_policy,
+        }
+    }
+
+    /// Reserve the space between the region header and the first entry of each region, the first entry is placed at
+    /// `first_entry_offset`.
+    ///
+    /// `first_entry_offset` must be aligned to the device align, and in `[device align, region size)`.
+    ///
+    /// Default: the first entry is placed right after the region header, at the device align.
+    pub fn with_first_entry_offset(self, first_entry_offset: usize) -> Self {
+        let builder = self.builder.with_first_entry_offset(first_entry_offset);
+        Self {
+            listener: self.listener,
+            cache: self.cache,
+            builder,
+            persistence: self.persistence,
+            promotion_policy: self.promotion_policy,
+        }
+    }
+
+    /// Verify the region header magic before reading entries from a region, to detect the region clobbered by other
+    /// processes on shared storage.
+    ///
+    /// The magic is verified on the first read of a region, and re-verified periodically.
+    ///
+    /// Default: `false`.
+    pub fn with_verify_region_magic_on_read(self, verify_region_magic_on_read: bool) -> Self {
+        let builder = self
+            .builder
+            .with_verify_region_magic_on_read(verify_region_magic_on_read);
+        Self {
+            listener: self.listener,
+            cache: self.cache,
+            builder,
+            persistence: self.persistence,
+            promotion_policy: self.promotion_policy,
+        }
+    }
+
+    /// Write an aligned block to the device, read it back and verify it on open, before trusting the device.
+    ///
+    /// The open fails with a descriptive error if the round-trip fails, e.g. the filesystem rejects direct I/O.
+    ///
+    /// Default: `false`.
+    pub fn with_self_test_on_open(self, self_test_on_open: bool) -> Self {
+        let builder = self.builder.with_self_test_on_open(self_test_on_open);
+        Self {
+            listener: self.listener,
+            cache: self.cache,
+            builder,
+            persistence: self.persistence,
+            promotion_policy: self.promotion_policy,
+        }
+    }
+
+    /// Set the max bytes per second read by the storage scrub, to keep the scrub from interfering with the foreground
+    /// reads.
+    ///
+    /// Default: unlimited.
+    pub fn with_scrub_throughput_limit(self, scrub_throughput_limit: usize) -> Self {
+        let builder = self.builder.with_scrub_throughput_limit(scrub_throughput_limit);
+        Self {
+            listener: self.listener,
+            cache: self.cache,
+            builder,
+            persistence: self.persistence,
+            promotion_policy: self.promotion_policy,
+        }
+    }
+
+    /// Coalesce the writes of update-heavy keys, only the newest entry of each key is written per interval.
+    ///
+    /// Default: disabled, the entries are written as soon as inserted.
+    pub fn with_write_coalescing_interval(self, write_coalescing_interval: Duration) -> Self {
+        let builder = self.builder.with_write_coalescing_interval(write_coalescing_interval);
+        Self {
+            listener: self.listener,
+            cache: self.cache,
+            builder,
+            persistence: self.persistence,
+            promotion_policy: self.promotion_policy,
+        }
+    }
+
+    /// Seal the region being written after it has been written for `max_region_age`, even if it is not full.
+    ///
+    /// Default: disabled, the regions are only rotated when full.
+    pub fn with_max_region_age(self, max_region_age: Duration) -> Self {
+        let builder = self.builder.with_max_region_age(max_region_age);
+        Self {
+            listener: self.listener,
+            cache: self.cache,
+            builder,
+            persistence: self.persistence,
+            promotion_policy: self.promotion_policy,
+        }
+    }
+
+    /// Limit the count of the regions being written concurrently by the flushers, to keep the writes concentrated.
+    ///
+    /// Default: unlimited, each flusher writes its own region.
+    pub fn with_max_writing_regions(self, max_writing_regions: usize) -> Self {
+        let builder = self.builder.with_max_writing_regions(max_writing_regions);
+        Self {
+            listener: self.listener,
+            cache: self.cache,
+            builder,
+            persistence: self.persistence,
+            promotion_policy: self.promotion_policy,
+        }
+    }
+
+    /// Pad the stored values by the padding policy, so that their stored sizes don't reveal the exact value lengths.
+    ///
+    /// Default: [`PaddingPolicy::None`].
+    pub fn with_size_padding(self, size_padding: PaddingPolicy) -> Self {
+        let builder = self.builder.with_size_padding(size_padding);
+        Self {
+            listener: self.listener,
+            cache: self.cache,
+            builder,
+            persistence: self.persistence,
+            promotion_policy: self.promotion_policy,
+        }
+    }
+
+    /// Store a 128-bit hash of the key on disk instead of the full key, to shrink the overhead of long keys.
+    ///
+    /// The full keys can't be rebuilt from the stored hashes, so the entries are lost after a restart unless the
+    /// catalog is recovered from a snapshot. Not supported with block compression.
+    ///
+    /// Default: `false`.
+    pub fn with_store_key_hash_only(self, store_key_hash_only: bool) -> Self {
+        let builder = self.builder.with_store_key_hash_only(store_key_hash_only);
+        Self {
+            listener: self.listener,
+            cache: self.cache,
+            builder,
+            persistence: self.persistence,
+            promotion_policy: self.promotion_policy,
+        }
+    }
+
+    /// Set the level of [`Compression::Zstd`], e.g. `1` for hot data or `19` for cold data with a better ratio.
+    ///
+    /// The level is not needed for decompression, so it can be changed across restarts.
+    ///
+    /// Default: `0`, which means the zstd default level.
+    pub fn with_zstd_level(self, zstd_level: i32) -> Self {
+        let builder = self.builder.with_zstd_level(zstd_level);
+        Self {
+            listener: self.listener,
+            cache: self.cache,
+            builder,
+            persistence: self.persistence,
+            promotion_policy: self.promotion_policy,
+        }
+    }
+
+    /// Limit the count of the concurrent decompressions on the get path, the excess gets wait for a while instead of
+    /// thrashing the CPU.
+    ///
+    /// Default: unlimited.
+    pub fn with_max_concurrent_decompressions(self, max_concurrent_decompressions: usize) -> Self {
+        let builder = self
+            .builder
+            .with_max_concurrent_decompressions(max_concurrent_decompressions);
+        Self {
+            listener: self.listener,
+            cache: self.cache,
+            builder,
+            persistence: self.persistence,
+            promotion_policy: self.promotion_policy,
+        }
+    }
+
+    /// Set the algorithm of the entry checksums, e.g. [`ChecksumAlgorithm::Crc32c`] for the hardware acceleration, or
+    /// [`ChecksumAlgorithm::XxHash3`] for the faster validation of large values.
+    ///
+    /// The algorithm is recorded in each entry, so it can be changed across restarts.
+    ///
+    /// Default: [`ChecksumAlgorithm::XxHash64`].
+    pub fn with_checksum_algorithm(self, checksum_algorithm: ChecksumAlgorithm) -> Self {
+        let builder = self.builder.with_checksum_algorithm(checksum_algorithm);
+        Self {
+            listener: self.listener,
+            cache: self.cache,
+            builder,
+            persistence: self.persistence,
+            promotion_policy: self.promotion_policy,
+        }
+    }
+
+    /// Set the function to extract the prefix of a key, e.g. the tenant id, to group the entries for locality.
+    ///
+    /// Entries with the same prefix are preferentially written into the same regions, which benefits the read-ahead of
+    /// prefix scans. The prefixes are spread over the flushers by `prefix % flushers`, so the prefix groups are
+    /// separated from each other up to the flusher count.
+    ///
+    /// Default: no grouping.
+    pub fn with_key_prefix(self, key_prefix: impl KeyPrefix<K>) -> Self {
+        let builder = self.builder.with_key_prefix(key_prefix);
+        Self {
+            listener: self.listener,
+            cache: self.cache,
+            builder,
+            persistence: self.persistence,
+            promotion_policy: self.promotion_policy,
+        }
+    }
+
+    /// Set the behavior of the flushers when writing to the device fails.
+    ///
+    /// For a best-effort cache, [`FlushErrorPolicy::Drop`] keeps the store serving at the cost of the dropped entries.
+    ///
+    /// Default: [`FlushErrorPolicy::Abort`].
+    pub fn with_flush_error_policy(self, flush_error_policy: FlushErrorPolicy) -> Self {
+        let builder = self.builder.with_flush_error_policy(flush_error_policy);
+        Self {
+            listener: self.listener,
+            cache: self.cache,
+            builder,
+            persistence: self.persistence,
+            promotion_policy: self.promotion_policy,
+        }
+    }
+
+    /// Set the behavior of the flushers when the entry channel is closed while the store is not being closed.
+    ///
+    /// With [`ChannelDropPolicy::Unhealthy`], the store logs an error and reports itself unhealthy.
+    ///
+    /// Default: [`ChannelDropPolicy::Unhealthy`].
+    pub fn with_channel_drop_policy(self, channel_drop_policy: ChannelDropPolicy) -> Self {
+        let builder = self.builder.with_channel_drop_policy(channel_drop_policy);
+        Self {
+            listener: self.listener,
+            cache: self.cache,
+            builder,
+            persistence: self.persistence,
+            promotion_policy: self.promotion_policy,
+        }
+    }
+
+    /// Set the compression of the entries whose key prefix is `prefix`, which overrides the compression set by
+    /// [`HybridCacheBuilderPhaseStorage::with_compression`] for them.
+    ///
+    /// The prefix of a key is extracted by the function set by [`HybridCacheBuilderPhaseStorage::with_key_prefix`], so
+    /// it takes no effect if the key prefix is not set.
+    pub fn with_prefix_compression(self, prefix: u64, compression: Compression) -> Self {
+        let builder = self.builder.with_prefix_compression(prefix, compression);
+        Self {
+            listener: self.listener,
+            cache: self.cache,
+            builder,
+            persistence: self.persistence,
+            promotion_policy: self.promotion_policy,
+        }
+    }
+
+    /// Limit the total bytes of the in-flight device reads and writes, the excess I/Os wait for the others to complete.
+    ///
+    /// It bounds the memory held by the pending io buffers and the queue depth of the device on large flushes.
+    ///
+    /// Default: unlimited.
+    pub fn with_io_bytes_in_flight_limit(self, io_bytes_in_flight_limit: usize) -> Self {
+        let builder = self.builder.with_io_bytes_in_flight_limit(io_bytes_in_flight_limit);
+        Self {
+            listener: self.listener,
+            cache: self.cache,
+            builder,
+            persistence: self.persistence,
+            promotion_policy: self.promotion_policy,
+        }
+    }
+
+    /// Bound the count of the entries queued to the flushers, the excess inserts are handled by the queue full policy.
+    ///
+    /// It bounds the memory held by the queued entries on a burst of inserts that the device can't keep up with.
+    ///
+    /// Default: unbounded.
+    pub fn with_flusher_queue_capacity(self, flusher_queue_capacity: usize) -> Self {
+        let builder = self.builder.with_flusher_queue_capacity(flusher_queue_capacity);
+        Self {
+            listener: self.listener,
+            cache: self.cache,
+            builder,
+            persistence: self.persistence,
+            promotion_policy: self.promotion_policy,
+        }
+    }
+
+    /// Set the behavior of the inserts when the flusher queue is full.
+    ///
+    /// With [`QueueFullPolicy::Reject`], the inserts fail with [`foyer_storage::Error::QueueFull`].
+    ///
+    /// Default: [`QueueFullPolicy::Block`].
+    pub fn with_queue_full_policy(self, queue_full_policy: QueueFullPolicy) -> Self {
+        let builder = self.builder.with_queue_full_policy(queue_full_policy);
+        Self {
+            listener: self.listener,
+            cache: self.cache,
+            builder,
+            persistence: self.persistence,
+            promotion_policy: self.promotion_policy,
+        }
+    }
+
+    /// Align the flushes to the regions to `flush_alignment`, e.g. the physical erase block size of the device.
+    ///
+    /// Each flush is padded up to the alignment, which trades some space for the write patterns that reduce the write
+    /// amplification of some SSDs. It must be a power of 2 no less than the device align that divides the region size.
+    ///
+    /// Default: aligned to the device align.
+    pub fn with_flush_alignment(self, flush_alignment: usize) -> Self {
+        let builder = self.builder.with_flush_alignment(flush_alignment);
+        Self {
+            listener: self.listener,
+            cache: self.cache,
+            builder,
+            persistence: self.persistence,
+            promotion_policy: self.promotion_policy,
+        }
+    }
+
+    /// Write the value uncompressed if the compression doesn't make it smaller, which saves the space and the
+    /// decompression of the incompressible values.
+    ///
+    /// The entry header records the algorithm actually applied, so the entries are read as usual.
+    ///
+    /// Default: `false`.
+    pub fn with_adaptive_compression(self, adaptive_compression: bool) -> Self {
+        let builder = self.builder.with_adaptive_compression(adaptive_compression);
+        Self {
+            listener: self.listener,
+            cache: self.cache,
+            builder,
+            persistence: self.persistence,
+            promotion_policy: self.promotion_policy,
+        }
+    }
+
+    /// Set the count of the regions read ahead by the storage scans while the current region is parsed, which keeps
+    /// the device busy during a full scan. `0` reads the regions one at a time.
+    ///
+    /// Each region read ahead holds an io buffer of the region size.
+    ///
+    /// Default: `2`.
+    pub fn with_scan_read_ahead(self, regions: usize) -> Self {
+        let builder = self.builder.with_scan_read_ahead(regions);
+        Self {
+            listener: self.listener,
+            cache: self.cache,
+            builder,
+            persistence: self.persistence,
+            promotion_policy: self.promotion_policy,
+        }
+    }
+
+    /// Verify the checksums of the entries while the catalog is recovered on open. The entries that fail are skipped.
+    ///
+    /// The values are read in full for the verification, which slows down the recovery.
+    ///
+    /// Default: `false`.
+    pub fn with_verify_checksum_on_recover(self, verify_checksum_on_recover: bool) -> Self {
+        let builder = self.builder.with_verify_checksum_on_recover(verify_checksum_on_recover);
+        Self {
+            listener: self.listener,
+            cache: self.cache,
+            builder,
+            persistence: self.persistence,
+            promotion_policy: self.promotion_policy,
+        }
+    }
+
+    /// Set the backend that persists the index of the flushed entries besides the in-memory catalog.
+    ///
+    /// A flushed entry is indexed only after the backend accepts it, the failed inserts are retried by the catalog
+    /// retry policy.
+    pub fn with_catalog_backend(self, backend: Arc<dyn CatalogBackend<K>>) -> Self {
+        let builder = self.builder.with_catalog_backend(backend);
+        Self {
+            listener: self.listener,
+            cache: self.cache,
+            builder,
+            persistence: self.persistence,
+            promotion_policy: self.promotion_policy,
+        }
+    }
+
+    /// Set the retry of the failed catalog backend inserts on flush.
+    ///
+    /// Default: 3 retries, the backoff starts from 10ms.
+    pub fn with_catalog_retry_policy(self, policy: CatalogRetryPolicy) -> Self {
+        let builder = self.builder.with_catalog_retry_policy(policy);
+        Self {
+            listener: self.listener,
+            cache: self.cache,
+            builder,
+            persistence: self.persistence,
+            promotion_policy: self.promotion_policy,
+        }
+    }
+
+    /// Enable a dedicated tokio runtime for the store with a runtime config.
+    ///
+    /// If not given, the store will use the user's runtime.
+    pub fn with_runtime_config(self, runtime_config: RuntimeConfig) -> Self {
+        let builder = self.builder.with_runtime_config(runtime_config);
+        Self {
+            listener: self.listener,
+            cache: self.cache,
+            builder,
+            persistence: self.persistence,
+            promotion_policy: self.promotion_policy,
+        }
+    }
+
+    /// Decide if lazy reocvery feature is enabled.
+    ///
+    /// If enabled, the opening for the store will return immediately, but always return `None` before recovery is finished.
+    pub fn with_lazy(self, lazy: bool) -> Self {
+        let builder = self.builder.with_lazy(lazy);
+        Self {
+            listener: self.listener,
+            cache: self.cache,
+            builder,
+            persistence: self.persistence,
+            promotion_policy: self.promotion_policy,
+        }
+    }
+
+    /// Set the behavior of reads before the lazy recovery finishes, only works if lazy recovery is enabled.
+    ///
+    /// Default: [`RecoveryReadPolicy::Miss`].
+    pub fn with_recovery_read_policy(self, recovery_read_policy: RecoveryReadPolicy) -> Self {
+        let builder = self.builder.with_recovery_read_policy(recovery_read_policy);
+        Self {
+            listener: self.listener,
+            cache: self.cache,
+            builder,
+            persistence: self.persistence,
+            promotion_policy: self.promotion_policy,
+        }
+    }
+
+    /// Set how the entries of the memory tier are persisted.
+    ///
+    /// Default: [`HybridCachePersistence::Continuous`].
+    pub fn with_persistence(self, persistence: HybridCachePersistence) -> Self {
+        Self {
+            listener: self.listener,
+            cache: self.cache,
+            builder: self.builder,
+            persistence,
+            promotion_policy: self.promotion_policy,
+        }
+    }
+
+    /// Set whether to promote the entries found in the storage tier to the memory tier on lookups.
+    ///
+    /// Default: [`PromotionPolicy::Always`].
+    pub fn with_promotion_policy(self, promotion_policy: PromotionPolicy) -> Self {
+        Self {
+            listener: self.listener,
+            cache: self.cache,
+            builder: self.builder,
+            persistence: self.persistence,
+            promotion_policy,
+        }
+    }
+
+    pub async fn build(self) -> anyhow::Result<HybridCache<K, V, S>> {
+        let builder = match self.persistence {
+            HybridCachePersistence::Continuous => self.builder,
+            HybridCachePersistence::Snapshot { .. } => self.builder.with_device_config(DeviceConfig::None),
+        };
+        let store = builder.build().await?;
+        self.listener.inner.store.set(store.clone()).unwrap();
+
+        if let HybridCachePersistence::Snapshot { path } = &self.persistence {
+            let path = path.clone();
+            let entries = tokio::task::spawn_blocking(move || read_memory_snapshot::<K, V>(&path)).await??;
+            // Entries are snapshotted from the coldest to the hottest, insert them in the same order.
+            for (key, value) in entries.unwrap_or_default() {
+                self.cache.insert(key, value);
+            }
+        }
+
+        Ok(HybridCache {
+            cache: self.cache,
+            store,
+            metrics: self.listener.inner.metrics.clone(),
+            persistence: self.persistence,
+            promotion: Promotion::new(self.promotion_policy),
+            update_locks: UpdateLocks::default(),
+        })
+    }
+}
+
+pub type HybridCacheEntry<K, V, S> = CacheEntry<K, V, HybridCacheEventListener<K, V>, S>;
+
+pub struct HybridCache<K, V, S = RandomState>
+where
+    K: StorageKey,
+    V: StorageValue,
+    S: BuildHasher + Send + Sync + 'static,
+{
+    cache: Cache<K, V, HybridCacheEventListener<K, V>, S>,
+    store: Store<K, V>,
+    metrics: Arc<HybridCacheMetrics>,
+    persistence: HybridCachePersistence,
+    promotion: Promotion,
+    update_locks: UpdateLocks,
+}
+
+impl<K, V, S> HybridCache<K, V, S>
+where
+    K: StorageKey,
+    V: StorageValue,
+    S: BuildHasher + Send + Sync + 'static,
+{
+    pub fn cache(&self) -> &Cache<K, V, HybridCacheEventListener<K, V>, S> {
+        &self.cache
+    }
+
+    pub fn store(&self) -> &Store<K, V> {
+        &self.store
+    }
+
+    pub fn metrics(&self) -> &HybridCacheMetrics {
+        &self.metrics
+    }
+
+    /// Serialize the metrics of both tiers to a JSON object, e.g. for ad-hoc diagnostics without a metrics backend.
+    ///
+    /// The object has the fields `memory` with the usage and the counters of the memory tier, `hybrid` with the
+    /// [`HybridCacheStats`], and `storage` with the metrics of the storage tier, see
+    /// [`foyer_storage::Metrics::to_json`], or `null` if the storage tier is disabled or still recovering.
+    pub fn metrics_json(&self) -> String {
+        let memory = self.cache.metrics();
+        let load = |counter: &AtomicUsize| counter.load(Ordering::Relaxed);
+        let stats = self.metrics.stats();
+        format!(
+            concat!(
+                "{{",
+                r#""memory":{{"capacity":{},"usage":{},"insert":{},"replace":{},"reject":{},"hit":{},"miss":{},"#,
+                r#""fetch":{},"queue":{},"remove":{},"expire":{},"evict":{},"reinsert":{},"release":{}}},"#,
+                r#""hybrid":{{"promoted":{},"demoted":{},"memory_hit":{},"memory_miss":{},"storage_hit":{},"#,
+                r#""storage_miss":{},"storage_miss_decode_error":{}}},"#,
+                r#""storage":{}"#,
+                "}}"
+            ),
+            self.cache.capacity(),
+            self.cache.usage(),
+            load(&memory.insert),
+            load(&memory.replace),
+            load(&memory.reject),
+            load(&memory.hit),
+            load(&memory.miss),
+            load(&memory.fetch),
+            load(&memory.queue),
+            load(&memory.remove),
+            load(&memory.expire),
+            load(&memory.evict),
+            load(&memory.reinsert),
+            load(&memory.release),
+            stats.promoted,
+            stats.demoted,
+            stats.memory_hit,
+            stats.memory_miss,
+            stats.storage_hit,
+            stats.storage_miss,
+            stats.storage_miss_decode_error,
+            self.store
+                .metrics()
+                .map(|metrics| metrics.to_json())
+                .unwrap_or_else(|| "null".to_string()),
+        )
+    }
+
+    pub fn insert<AK, AV>(&self, key: AK, value: AV) -> HybridCacheEntry<K, V, S>
+    where
+        AK: Into<Arc<K>> + Send + 'static,
+        AV: Into<Arc<V>> + Send + 'static,
+    {
+        self.cache.insert(key, value)
+    }
+
+    pub fn insert_with_context<AK, AV>(&self, key: AK, value: AV, context: CacheContext) -> HybridCacheEntry<K, V, S>
+    where
+        AK: Into<Arc<K>> + Send + 'static,
+        AV: Into<Arc<V>> + Send + 'static,
+    {
+        self.cache.insert_with_context(key, value, context)
+    }
+
+    /// Insert a new entry into the memory cache with the given priority.
+    ///
+    /// High priority entries survive eviction pressure of the memory cache longer, and bypass the admission policies
+    /// when they are demoted to the storage tier. See [`CachePriority`] for details.
+    pub fn insert_with_priority<AK, AV>(&self, key: AK, value: AV, priority: CachePriority) -> HybridCacheEntry<K, V, S>
+    where
+        AK: Into<Arc<K>> + Send + 'static,
+        AV: Into<Arc<V>> + Send + 'static,
+    {
+        self.cache.insert_with_priority(key, value, priority)
+    }
+
+    /// Insert a new entry into the memory cache, which is treated as a miss after `ttl`.
+    ///
+    /// The entry keeps the remaining ttl when it is demoted to the storage tier, and it is dropped instead if it has
+    /// expired.
+    // TODO(MrCroxx): The storage tier entries are promoted without ttl, carry the expire at with the cached entry.
+    pub fn insert_with_ttl<AK, AV>(&self, key: AK, value: AV, ttl: Duration) -> HybridCacheEntry<K, V, S>
+    where
+        AK: Into<Arc<K>> + Send + 'static,
+        AV: Into<Arc<V>> + Send + 'static,
+    {
+        self.cache.insert_with_ttl(key, value, ttl)
+    }
+
+    /// Insert a new entry into the memory cache, and write it through to the disk cache with the given `compression`
+    /// instead of the configured one, e.g. [`Compression::None`] for the values that are already compressed.
+    ///
+    /// The entry is still judged by the admission policies. If it is rejected, it is demoted with the configured
+    /// compression later.
+    pub async fn insert_with_compression<AK, AV>(
+        &self,
+        key: AK,
+        value: AV,
+        compression: Compression,
+    ) -> anyhow::Result<HybridCacheEntry<K, V, S>>
+    where
+        AK: Into<Arc<K>> + Send + 'static,
+        AV: Into<Arc<V>> + Send + 'static,
+    {
+        let key: Arc<K> = key.into();
+        let value: Arc<V> = value.into();
+        let entry = self.cache.insert(key.clone(), value.clone());
+        let mut writer = self.store.writer(key);
+        writer.set_compression(compression);
+        writer.finish(value).await?;
+        Ok(entry)
+    }
+
+    /// Insert a new entry into the memory cache.
+    ///
+    /// Return [`CacheFull`] if the entry is rejected in [`InsertMode::NoEvict`] mode.
+    pub fn try_insert<AK, AV>(&self, key: AK, value: AV) -> std::result::Result<HybridCacheEntry<K, V, S>, CacheFull>
+    where
+        AK: Into<Arc<K>> + Send + 'static,
+        AV: Into<Arc<V>> + Send + 'static,
+    {
+        self.cache.try_insert(key, value)
+    }
+
+    /// Insert a new entry into the memory cache, and return the entry evicted from the memory cache to make room for
+    /// it. The returned entry is not demoted to the disk cache.
+    pub fn insert_and_get_evicted<AK, AV>(&self, key: AK, value: AV) -> Option<(Arc<K>, Arc<V>)>
+    where
+        AK: Into<Arc<K>> + Send + 'static,
+        AV: Into<Arc<V>> + Send + 'static,
+    {
+        self.cache.insert_and_get_evicted(key, value)
+    }
+
+    /// Insert the entries into the memory cache in order, and write them through to the disk cache in a batch.
+    ///
+    /// The later entry of the same key wins on both tiers. Returns the inserted memory cache entries in order.
+    pub async fn insert_batch<AK, AV>(
+        &self,
+        entries: impl IntoIterator<Item = (AK, AV)>,
+    ) -> anyhow::Result<Vec<HybridCacheEntry<K, V, S>>>
+    where
+        AK: Into<Arc<K>> + Send + 'static,
+        AV: Into<Arc<V>> + Send + 'static,
+    {
+        let entries: Vec<(Arc<K>, Arc<V>)> = entries
+            .into_iter()
+            .map(|(key, value)| (key.into(), value.into()))
+            .collect();
+        let res = entries
+            .iter()
+            .map(|(key, value)| self.cache.insert(key.clone(), value.clone()))
+            .collect();
+        self.store.insert_batch(entries).await?;
+        Ok(res)
+    }
+
+    pub async fn get<Q>(&self, key: &Q) -> anyhow::Result<Option<HybridCacheEntry<K, V, S>>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized + Send + Sync + 'static + Clone,
+    {
+        if let Some(entry) = self.get_memory(key) {
+            return Ok(Some(entry));
+        }
+        match self.get_storage(self.store.get(key)).await? {
+            Some((key, value)) => Ok(Some(self.promote(key, value))),
+            None => Ok(None),
+        }
+    }
+
+    /// Get the value of the key like [`HybridCache::get`], but fail with a timeout error if the storage read doesn't
+    /// complete within `timeout`.
+    ///
+    /// The memory tier is not bounded by the timeout.
+    pub async fn get_with_timeout<Q>(
+        &self,
+        key: &Q,
+        timeout: Duration,
+    ) -> anyhow::Result<Option<HybridCacheEntry<K, V, S>>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + Send + Sync + 'static + Clone,
+    {
+        if let Some(entry) = self.get_memory(key) {
+            return Ok(Some(entry));
+        }
+        match self.get_storage(self.store.get_with_timeout(key, timeout)).await? {
+            Some((key, value)) => Ok(Some(self.promote(key, value))),
+            None => Ok(None),
+        }
+    }
+
+    /// Get the entry from the memory tier, and record the memory hit or miss.
+    fn get_memory<Q>(&self, key: &Q) -> Option<HybridCacheEntry<K, V, S>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let entry = self.cache.get(key);
+        self.metrics.record_memory(entry.is_some());
+        entry
+    }
+
+    /// Get the entry from the storage tier with `read`, and record the storage hit or miss.
+    async fn get_storage(
+        &self,
+        read: impl Future<Output = foyer_storage::Result<Option<CachedEntry<K, V>>>>,
+    ) -> foyer_storage::Result<Option<(Arc<K>, Arc<V>)>> {
+        let res = read.await;
+        self.metrics.record_storage(&res);
+        res.map(|entry| entry.map(|entry| entry.to_arc()))
+    }
+
+    /// Insert the entry found in the storage tier into the memory tier if the promotion policy allows, otherwise
+    /// return it without caching it.
+    fn promote(&self, key: Arc<K>, value: Arc<V>) -> HybridCacheEntry<K, V, S> {
+        if !self.promotion.judge(key.as_ref()) {
+            return self.cache.deposit(key, value);
+        }
+        self.metrics.promoted.fetch_add(1, Ordering::Relaxed);
+        self.cache.insert(key, value)
+    }
+
+    pub fn remove<Q>(&self, key: &Q) -> anyhow::Result<bool>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let cache = self.cache.remove(key).is_some();
+        let store = self.store.remove(key)?;
+        Ok(cache || store)
+    }
+
+    /// Whether the key is cached in the memory tier or the storage tier.
+    ///
+    /// The storage tier is checked by its in-memory catalog, the value is never read from the device.
+    pub fn contains<Q>(&self, key: &Q) -> anyhow::Result<bool>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        Ok(self.cache.contains(key) || self.store.exists(key)?)
+    }
+
+    /// Register an access to the key without reading its value, and return whether the key exists.
+    ///
+    /// The access renews the recency of the entry in the memory tier, or of the region holding it in the storage tier.
+    /// The entry is not promoted from the storage tier, see [`HybridCache::touch_and_promote`].
+    pub fn touch<Q>(&self, key: &Q) -> anyhow::Result<bool>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        if self.cache.touch(key) {
+            return Ok(true);
+        }
+        Ok(self.store.touch(key)?)
+    }
+
+    /// Register an access to the key like [`HybridCache::touch`], and promote the entry to the memory tier if it lives
+    /// only in the storage tier.
+    ///
+    /// The value is never returned to the caller, but it must be read from the storage tier to be promoted.
+    pub async fn touch_and_promote<Q>(&self, key: &Q) -> anyhow::Result<bool>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + Send + Sync + 'static + Clone,
+    {
+        let touched = self.cache.touch(key);
+        self.metrics.record_memory(touched);
+        if touched {
+            return Ok(true);
+        }
+        if let Some((key, value)) = self.get_storage(self.store.get(key)).await? {
+            self.metrics.promoted.fetch_add(1, Ordering::Relaxed);
+            self.cache.insert(key, value);
+            return Ok(true);
+        }
+        Ok(false)
+    }
+
+    /// Locate the tiers where the key lives, without reading the value.
+    pub fn locate<Q>(&self, key: &Q) -> anyhow::Result<KeyLocation>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        Ok(KeyLocation::new(self.cache.contains(key), self.store.exists(key)?))
+    }
+
+    pub fn clear(&self) -> anyhow::Result<()> {
+        self.cache.clear();
+        self.store.clear()?;
+        Ok(())
+    }
+
+    /// Shutdown the hybrid cache gracefully.
+    ///
+    /// With [`HybridCachePersistence::Snapshot`], the memory tier is drained and written to the snapshot file. Entries
+    /// still referenced by the caller are not evictable, and are not included in the snapshot.
+    pub async fn shutdown(&self) -> anyhow::Result<()> {
+        if let HybridCachePersistence::Snapshot { path } = &self.persistence {
+            let mut entries = vec![];
+            while let Some(entry) = self.cache.pop() {
+                entries.push(entry);
+            }
+            let path = path.clone();
+            tokio::task::spawn_blocking(move || {
+                let entries = entries
+                    .iter()
+                    .map(|entry| (entry.key(), entry.value()))
+                    .collect::<Vec<_>>();
+                write_memory_snapshot::<K, V>(&path, &entries)
+            })
+            .await??;
+        }
+        self.store.close().await?;
+        Ok(())
+    }
+}
+
+/// Write the entries of the memory tier to `path` atomically.
+fn write_memory_snapshot<K, V>(path: &Path, entries: &[(&K, &V)]) -> anyhow::Result<()>
+where
+    K: StorageKey,
+    V: StorageValue,
+{
+    // Write to a temporary file then rename it, so a crash while writing never leaves a broken snapshot.
+    let tmp = path.with_extension("tmp");
+    {
+        let mut writer = BufWriter::new(File::create(&tmp)?);
+        bincode::serialize_into(&mut writer, &(MEMORY_SNAPSHOT_MAGIC, entries))?;
+        writer.flush()?;
+        writer.get_ref().sync_all()?;
+    }
+    fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+/// Read the entries of the memory tier from `path` and remove the snapshot.
+///
+/// Returns `None` if the snapshot does not exist.
+fn read_memory_snapshot<K, V>(path: &Path) -> anyhow::Result<Option<Vec<(K, V)>>>
+where
+    K: StorageKey,
+    V: StorageValue,
+{
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+
+    let (magic, entries): (u64, Vec<(K, V)>) = bincode::deserialize_from(BufReader::new(file))?;
+    if magic != MEMORY_SNAPSHOT_MAGIC {
+        return Err(anyhow!(
+            "memory snapshot magic mismatch, expected: {}, got: {}",
+            MEMORY_SNAPSHOT_MAGIC,
+            magic
+        ));
+    }
+    fs::remove_file(path)?;
+
+    Ok(Some(entries))
+}
+
+/// The tiers where a key of the hybrid cache lives, see [`HybridCache::locate`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyLocation {
+    Nowhere,
+    Memory,
+    Storage,
+    Both,
+}
+
+impl KeyLocation {
+    pub fn new(memory: bool, storage: bool) -> Self {
+        match (memory, storage) {
+            (false, false) => Self::Nowhere,
+            (true, false) => Self::Memory,
+            (false, true) => Self::Storage,
+            (true, true) => Self::Both,
+        }
+    }
+
+    pub fn in_memory(&self) -> bool {
+        matches!(self, Self::Memory | Self::Both)
+    }
+
+    pub fn in_storage(&self) -> bool {
+        matches!(self, Self::Storage | Self::Both)
+    }
+}
+
+pub type HybridEntry<K, V, S> = Entry<K, V, anyhow::Error, HybridCacheEventListener<K, V>, S>;
+
+impl<K, V, S> HybridCache<K, V, S>
+where
+    K: StorageKey + Clone,
+    V: StorageValue,
     S: BuildHasher + Send + Sync + 'static,
 {
     pub fn entry<AK, AV, F, FU>(&self, key: AK, f: F) -> HybridEntry<K, V, S>
@@ -456,8 +1840,10 @@ where
     {
         let key: Arc<K> = key.into();
         let store = self.store.clone();
+        let metrics = self.metrics.clone();
         self.cache.entry(key.clone(), || async move {
             if let Some(entry) = store.get(&key).await.map_err(anyhow::Error::from)? {
+                metrics.promoted.fetch_add(1, Ordering::Relaxed);
                 return Ok((entry.to_arc().1, CacheContext::default()));
             }
             f().await
@@ -465,4 +1851,963 @@ where
                 .map_err(anyhow::Error::from)
         })
     }
+
+    /// Get the entry of `key`, or compute its value with `f` on miss of both tiers.
+    ///
+    /// Concurrent callers of the same missing key share a single computation and all receive the resulting entry. The
+    /// computed value is inserted into the memory cache and written to the disk cache.
+    ///
+    /// The computation is spawned, so cancelling a caller doesn't cancel it for the others. If `f` fails or panics, the
+    /// callers waiting for it receive an error and the next call computes the value again.
+    pub async fn get_or_insert_with<AK, AV, F, FU>(&self, key: AK, f: F) -> anyhow::Result<HybridCacheEntry<K, V, S>>
+    where
+        AK: Into<Arc<K>> + Send + 'static,
+        AV: Into<Arc<V>> + Send + 'static,
+        F: FnOnce() -> FU + Send + 'static,
+        FU: Future<Output = anyhow::Result<AV>> + Send + 'static,
+    {
+        let key: Arc<K> = key.into();
+        let store = self.store.clone();
+        let metrics = self.metrics.clone();
+        self.cache
+            .entry(key.clone(), || async move {
+                if let Some(entry) = store.get(&key).await.map_err(anyhow::Error::from)? {
+                    metrics.promoted.fetch_add(1, Ordering::Relaxed);
+                    return Ok((entry.to_arc().1, CacheContext::default()));
+                }
+                // Turn a panic into an error, so the key is released for the following calls.
+                let value: Arc<V> = AssertUnwindSafe(async move { f().await })
+                    .catch_unwind()
+                    .await
+                    .map_err(|_| anyhow!("computation of the value panicked"))??
+                    .into();
+                store.insert(key, value.clone()).await?;
+                Ok((value, CacheContext::default()))
+            })
+            .await
+    }
+
+    /// Atomically update the value of `key` with `f`, which takes the current value from either tier and returns the
+    /// new value, or `None` to remove the entry.
+    ///
+    /// The updates of the same key are serialized, so concurrent updates don't lose writes. The plain inserts and
+    /// removes are not serialized with them. The new value is written through to the disk cache bypassing the
+    /// admission policies, so the disk cache never serves the outdated value.
+    ///
+    /// Returns the updated memory cache entry, or `None` if the entry is removed.
+    pub async fn update<AK, F>(&self, key: AK, f: F) -> anyhow::Result<Option<HybridCacheEntry<K, V, S>>>
+    where
+        AK: Into<Arc<K>> + Send + 'static,
+        F: FnOnce(Option<V>) -> Option<V>,
+        V: Clone,
+    {
+        let key: Arc<K> = key.into();
+        let _guard = self.update_locks.lock(key.as_ref()).await;
+
+        let current = self.get(key.as_ref()).await?.map(|entry| entry.value().clone());
+        let Some(value) = f(current) else {
+            self.remove(key.as_ref())?;
+            return Ok(None);
+        };
+
+        // Write the disk cache first, so the outdated value released from the memory cache is not demoted.
+        let value = Arc::new(value);
+        let mut writer = self.store.writer(key.clone());
+        writer.force();
+        if writer.finish(value.clone()).await?.is_none() {
+            self.store.remove(key.as_ref())?;
+        }
+        Ok(Some(self.cache.insert(key, value)))
+    }
+
+    /// Get the entries of `keys` in batch.
+    ///
+    /// Memory hits are yielded immediately. Then the storage reads of the other keys are issued concurrently with
+    /// the concurrency of [`DEFAULT_GET_BATCH_CONCURRENCY`], and are yielded as they complete.
+    #[allow(clippy::type_complexity)]
+    pub fn get_batch(
+        &self,
+        keys: impl IntoIterator<Item = K>,
+    ) -> impl Stream<Item = (K, anyhow::Result<Option<HybridCacheEntry<K, V, S>>>)> + '_ {
+        self.get_batch_with_concurrency(keys, DEFAULT_GET_BATCH_CONCURRENCY)
+    }
+
+    /// Get the entries of `keys` in batch, with at most `concurrency` storage reads in flight.
+    ///
+    /// See [`HybridCache::get_batch`].
+    #[allow(clippy::type_complexity)]
+    pub fn get_batch_with_concurrency(
+        &self,
+        keys: impl IntoIterator<Item = K>,
+        concurrency: usize,
+    ) -> impl Stream<Item = (K, anyhow::Result<Option<HybridCacheEntry<K, V, S>>>)> + '_ {
+        assert!(concurrency > 0, "concurrency of batch get must be > 0");
+
+        let mut hits = vec![];
+        let mut misses = vec![];
+        for key in keys {
+            match self.get_memory(&key) {
+                Some(entry) => hits.push((key, Ok(Some(entry)))),
+                None => misses.push(key),
+            }
+        }
+
+        let reads = stream::iter(misses)
+            .map(move |key| async move {
+                let res = match self.get_storage(self.store.get(&key)).await {
+                    Ok(Some((k, value))) => Ok(Some(self.promote(k, value))),
+                    Ok(None) => Ok(None),
+                    Err(e) => Err(e.into()),
+                };
+                (key, res)
+            })
+            .buffer_unordered(concurrency);
+
+        stream::iter(hits).chain(reads)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use foyer_memory::{FifoConfig, LruConfig};
+    use foyer_storage::{
+        AdmissionContext, BlockingExecutor, BlockingJob, CompressionDecision, FsDeviceConfigBuilder, IoExecutor,
+    };
+
+    use super::*;
+
+    const KB: usize = 1024;
+    const MB: usize = 1024 * 1024;
+
+    #[tokio::test]
+    async fn test_tier_capacity() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let hybrid: HybridCache<u64, Vec<u8>> = HybridCacheBuilder::new()
+            .memory(4)
+            .with_shards(1)
+            .with_eviction_config(FifoConfig {})
+            .storage()
+            .with_device_config(
+                FsDeviceConfigBuilder::new(dir.path())
+                    .with_capacity(16 * MB)
+                    .with_file_size(MB)
+                    .with_align(4 * KB)
+                    .with_io_size(16 * KB)
+                    .build(),
+            )
+            .build()
+            .await
+            .unwrap();
+
+        for i in 0..16 {
+            hybrid.insert(i, vec![i as u8; KB]);
+        }
+        assert_eq!(hybrid.cache().usage(), 4);
+
+        // Wait for the overflowed entries to be demoted to the storage tier.
+        for i in 0..12 {
+            while !hybrid.store().exists(&i).unwrap() {
+                tokio::time::sleep(Duration::from_millis(1)).await;
+            }
+        }
+        assert_eq!(hybrid.metrics().demoted.load(Ordering::Relaxed), 12);
+
+        // The demoted entries are still retrievable, and are promoted to the memory tier.
+        for i in 0..12 {
+            assert_eq!(hybrid.get(&i).await.unwrap().unwrap().value(), &vec![i as u8; KB]);
+        }
+        assert_eq!(hybrid.metrics().promoted.load(Ordering::Relaxed), 12);
+        assert_eq!(hybrid.cache().usage(), 4);
+
+        hybrid.store().close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_tier_stats() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let hybrid: HybridCache<u64, Vec<u8>> = HybridCacheBuilder::new()
+            .memory(4)
+            .with_shards(1)
+            .with_eviction_config(FifoConfig {})
+            .storage()
+            .with_device_config(
+                FsDeviceConfigBuilder::new(dir.path())
+                    .with_capacity(16 * MB)
+                    .with_file_size(MB)
+                    .with_align(4 * KB)
+                    .with_io_size(16 * KB)
+                    .build(),
+            )
+            .build()
+            .await
+            .unwrap();
+
+        for i in 0..8 {
+            hybrid.insert(i, vec![i as u8; KB]);
+        }
+        // Wait for the overflowed entries to be demoted to the storage tier.
+        for i in 0..4 {
+            while !hybrid.store().exists(&i).unwrap() {
+                tokio::time::sleep(Duration::from_millis(1)).await;
+            }
+        }
+        assert_eq!(hybrid.metrics().stats().demoted, 4);
+
+        // Served by the memory tier.
+        for i in 4..8 {
+            assert_eq!(hybrid.get(&i).await.unwrap().unwrap().value(), &vec![i as u8; KB]);
+        }
+        // Served by the storage tier.
+        assert_eq!(hybrid.get(&0).await.unwrap().unwrap().value(), &vec![0; KB]);
+        // Missed by both tiers.
+        assert!(hybrid.get(&42).await.unwrap().is_none());
+
+        let stats = hybrid.metrics().stats();
+        assert_eq!(
+            stats,
+            HybridCacheStats {
+                memory_hit: 4,
+                memory_miss: 2,
+                storage_hit: 1,
+                storage_miss: 1,
+                storage_miss_decode_error: 0,
+                ..stats
+            }
+        );
+        assert_eq!(stats.promoted, 1);
+
+        // The batch and timeout lookups are recorded per tier as well.
+        let mut results = hybrid.get_batch([5, 6, 1, 43]).collect::<Vec<_>>().await;
+        results.sort_by_key(|(key, _)| *key);
+        let found = results
+            .into_iter()
+            .map(|(key, res)| (key, res.unwrap().is_some()))
+            .collect::<Vec<_>>();
+        assert_eq!(found, vec![(1, true), (5, true), (6, true), (43, false)]);
+        assert!(hybrid
+            .get_with_timeout(&2, Duration::from_secs(10))
+            .await
+            .unwrap()
+            .is_some());
+
+        let stats = hybrid.metrics().stats();
+        assert_eq!(
+            stats,
+            HybridCacheStats {
+                memory_hit: 6,
+                memory_miss: 5,
+                storage_hit: 3,
+                storage_miss: 2,
+                storage_miss_decode_error: 0,
+                ..stats
+            }
+        );
+
+        hybrid.store().close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_locate() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let hybrid: HybridCache<u64, Vec<u8>> = HybridCacheBuilder::new()
+            .memory(4)
+            .with_shards(1)
+            .with_eviction_config(FifoConfig {})
+            .storage()
+            .with_device_config(
+                FsDeviceConfigBuilder::new(dir.path())
+                    .with_capacity(16 * MB)
+                    .with_file_size(MB)
+                    .with_align(4 * KB)
+                    .with_io_size(16 * KB)
+                    .build(),
+            )
+            .build()
+            .await
+            .unwrap();
+
+        for i in 0..8 {
+            hybrid.insert(i, vec![i as u8; KB]);
+        }
+        for i in 0..4 {
+            while !hybrid.store().exists(&i).unwrap() {
+                tokio::time::sleep(Duration::from_millis(1)).await;
+            }
+        }
+
+        for i in 0..4 {
+            assert_eq!(hybrid.locate(&i).unwrap(), KeyLocation::Storage);
+        }
+        for i in 4..8 {
+            assert_eq!(hybrid.locate(&i).unwrap(), KeyLocation::Memory);
+        }
+        assert_eq!(hybrid.locate(&8).unwrap(), KeyLocation::Nowhere);
+
+        // The promoted entry is kept in the storage tier.
+        hybrid.get(&0).await.unwrap().unwrap();
+        let location = hybrid.locate(&0).unwrap();
+        assert_eq!(location, KeyLocation::Both);
+        assert!(location.in_memory() && location.in_storage());
+
+        hybrid.store().close().await.unwrap();
+    }
+
+    #[derive(Debug)]
+    struct RejectAll;
+
+    impl AdmissionPolicy for RejectAll {
+        type Key = u64;
+        type Value = Vec<u8>;
+
+        fn init(&self, _: AdmissionContext<u64, Vec<u8>>) {}
+
+        fn judge(&self, _: &Arc<u64>) -> bool {
+            false
+        }
+    }
+
+    #[tokio::test]
+    async fn test_priority() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let hybrid: HybridCache<u64, Vec<u8>> = HybridCacheBuilder::new()
+            .memory(4)
+            .with_shards(1)
+            .with_eviction_config(FifoConfig {})
+            .storage()
+            .with_device_config(
+                FsDeviceConfigBuilder::new(dir.path())
+                    .with_capacity(16 * MB)
+                    .with_file_size(MB)
+                    .with_align(4 * KB)
+                    .with_io_size(16 * KB)
+                    .build(),
+            )
+            .with_admission_policy(Arc::new(RejectAll))
+            .build()
+            .await
+            .unwrap();
+
+        // Odd keys are of high priority, even keys are of low priority.
+        for i in 0..16 {
+            let priority = if i % 2 == 1 {
+                CachePriority::High
+            } else {
+                CachePriority::Low
+            };
+            hybrid.insert_with_priority(i, vec![i as u8; KB], priority);
+        }
+        assert_eq!(hybrid.cache().usage(), 4);
+
+        // High priority entries are retained preferentially by the memory tier.
+        let (high, low): (Vec<_>, Vec<_>) = (0..16).filter(|i| hybrid.cache().contains(i)).partition(|i| i % 2 == 1);
+        assert!(high.len() > low.len(), "high: {:?}, low: {:?}", high, low);
+
+        // Evicted high priority entries bypass the admission policy, while the low priority ones are rejected.
+        let demoted = (0..16).filter(|i| i % 2 == 1 && !high.contains(i)).collect::<Vec<_>>();
+        while hybrid.metrics().demoted.load(Ordering::Relaxed) < demoted.len() {
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+        for i in demoted.iter() {
+            assert!(hybrid.store().exists(i).unwrap());
+        }
+        for i in (0..16).filter(|i| i % 2 == 0) {
+            assert!(!hybrid.store().exists(&i).unwrap());
+        }
+
+        hybrid.store().close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_ttl_demotion() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let hybrid: HybridCache<u64, Vec<u8>> = HybridCacheBuilder::new()
+            .memory(1)
+            .with_shards(1)
+            .with_eviction_config(FifoConfig {})
+            .storage()
+            .with_device_config(
+                FsDeviceConfigBuilder::new(dir.path())
+                    .with_capacity(16 * MB)
+                    .with_file_size(MB)
+                    .with_align(4 * KB)
+                    .with_io_size(16 * KB)
+                    .build(),
+            )
+            .build()
+            .await
+            .unwrap();
+
+        // The entry with the remaining ttl is demoted.
+        hybrid.insert_with_ttl(1, vec![1; KB], Duration::from_secs(3600));
+        hybrid.insert(2, vec![2; KB]);
+        while hybrid.metrics().demoted.load(Ordering::Relaxed) < 1 {
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+        assert!(hybrid.store().exists(&1).unwrap());
+
+        // The expired entry is dropped instead of demoted.
+        hybrid.insert_with_ttl(3, vec![3; KB], Duration::from_millis(1));
+        while hybrid.metrics().demoted.load(Ordering::Relaxed) < 2 {
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        hybrid.insert(4, vec![4; KB]);
+        assert!(!hybrid.store().exists(&3).unwrap());
+        assert!(hybrid.get(&3).await.unwrap().is_none());
+
+        hybrid.store().close().await.unwrap();
+    }
+
+    /// Blocking executor that slows down each io and records the count and the peak count of concurrent ios.
+    #[derive(Debug, Default)]
+    struct SlowExecutor {
+        jobs: Arc<AtomicUsize>,
+        running: Arc<AtomicUsize>,
+        peak: Arc<AtomicUsize>,
+    }
+
+    impl BlockingExecutor for SlowExecutor {
+        fn name(&self) -> &str {
+            "slow"
+        }
+
+        fn execute(&self, job: BlockingJob) {
+            self.jobs.fetch_add(1, Ordering::SeqCst);
+            let running = self.running.clone();
+            let peak = self.peak.clone();
+            tokio::task::spawn_blocking(move || {
+                let current = running.fetch_add(1, Ordering::SeqCst) + 1;
+                peak.fetch_max(current, Ordering::SeqCst);
+                std::thread::sleep(Duration::from_millis(10));
+                job();
+                running.fetch_sub(1, Ordering::SeqCst);
+            });
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_get_batch() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let executor = Arc::new(SlowExecutor::default());
+
+        let hybrid: HybridCache<u64, Vec<u8>> = HybridCacheBuilder::new()
+            .memory(4)
+            .with_shards(1)
+            .with_eviction_config(FifoConfig {})
+            .storage()
+            .with_device_config(
+                FsDeviceConfigBuilder::new(dir.path())
+                    .with_capacity(16 * MB)
+                    .with_file_size(MB)
+                    .with_align(4 * KB)
+                    .with_io_size(4 * KB)
+                    .with_io_executor(IoExecutor::custom(executor.clone()))
+                    .build(),
+            )
+            .build()
+            .await
+            .unwrap();
+
+        for i in 0..20 {
+            hybrid.insert(i, vec![i as u8; 3 * KB]);
+        }
+        // Wait for the overflowed entries to be flushed to the device.
+        for i in 0..16 {
+            loop {
+                if let Some(entry) = hybrid.store().get(&i).await.unwrap() {
+                    if matches!(entry, CachedEntry::Owned { .. }) {
+                        break;
+                    }
+                }
+                tokio::time::sleep(Duration::from_millis(1)).await;
+            }
+        }
+        executor.peak.store(0, Ordering::SeqCst);
+
+        // 16..20 are memory hits, 0..16 are disk hits, 100..104 are misses.
+        let keys = (0..20).chain(100..104).collect::<Vec<_>>();
+        let res: Vec<_> = hybrid.get_batch(keys).collect().await;
+
+        assert_eq!(res.len(), 24);
+        // Memory hits are yielded first.
+        for (key, entry) in &res[..4] {
+            assert!((16..20).contains(key));
+            assert_eq!(
+                entry.as_ref().unwrap().as_ref().unwrap().value(),
+                &vec![*key as u8; 3 * KB]
+            );
+        }
+        for (key, entry) in res {
+            let entry = entry.unwrap();
+            if key < 20 {
+                assert_eq!(entry.unwrap().value(), &vec![key as u8; 3 * KB]);
+            } else {
+                assert!(entry.is_none());
+            }
+        }
+
+        // Disk reads are issued concurrently.
+        assert!(executor.peak.load(Ordering::SeqCst) > 1);
+
+        hybrid.store().close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_insert_batch() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let hybrid: HybridCache<u64, Vec<u8>> = HybridCacheBuilder::new()
+            .memory(16)
+            .with_shards(1)
+            .with_eviction_config(FifoConfig {})
+            .storage()
+            .with_device_config(
+                FsDeviceConfigBuilder::new(dir.path())
+                    .with_capacity(16 * MB)
+                    .with_file_size(MB)
+                    .with_align(4 * KB)
+                    .with_io_size(16 * KB)
+                    .build(),
+            )
+            .build()
+            .await
+            .unwrap();
+
+        // The later entry of key 0 wins.
+        let entries = (0..8u64).map(|i| (i, vec![i as u8; KB])).chain([(0, vec![100; KB])]);
+        let res = hybrid.insert_batch(entries).await.unwrap();
+        assert_eq!(res.len(), 9);
+        for (i, entry) in res[..8].iter().enumerate() {
+            assert_eq!(*entry.key(), i as u64);
+            assert_eq!(entry.value(), &vec![i as u8; KB]);
+        }
+        assert_eq!(res[8].value(), &vec![100; KB]);
+
+        assert_eq!(hybrid.get(&0).await.unwrap().unwrap().value(), &vec![100; KB]);
+        for i in 0..8u64 {
+            let value = if i == 0 { vec![100; KB] } else { vec![i as u8; KB] };
+            let entry = hybrid.store().get(&i).await.unwrap().unwrap();
+            assert_eq!(entry.to_arc().1.as_ref(), &value);
+        }
+
+        hybrid.store().close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_insert_with_compression() {
+        #[derive(Debug, Default)]
+        struct Decisions(std::sync::Mutex<Vec<CompressionDecision>>);
+
+        impl CompressionObserver for Decisions {
+            fn on_decision(&self, decision: CompressionDecision) {
+                self.0.lock().unwrap().push(decision);
+            }
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        let decisions = Arc::new(Decisions::default());
+
+        let hybrid: HybridCache<u64, Vec<u8>> = HybridCacheBuilder::new()
+            .memory(16)
+            .with_shards(1)
+            .with_eviction_config(FifoConfig {})
+            .storage()
+            .with_device_config(
+                FsDeviceConfigBuilder::new(dir.path())
+                    .with_capacity(16 * MB)
+                    .with_file_size(MB)
+                    .with_align(4 * KB)
+                    .with_io_size(16 * KB)
+                    .build(),
+            )
+            .with_compression(Compression::Lz4)
+            .with_compression_observer(decisions.clone())
+            .build()
+            .await
+            .unwrap();
+
+        let compressions = [
+            Compression::None,
+            Compression::Zstd,
+            Compression::Lz4,
+            Compression::None,
+        ];
+        for (i, compression) in compressions.iter().enumerate() {
+            let value = vec![i as u8; (i + 1) * KB];
+            let entry = hybrid
+                .insert_with_compression(i as u64, value.clone(), *compression)
+                .await
+                .unwrap();
+            assert_eq!(entry.value(), &value);
+        }
+        hybrid.store().barrier().await.unwrap();
+
+        // The values are of different sizes, sort the decisions by the size to match them with the inserts.
+        let mut decisions = decisions.0.lock().unwrap().drain(..).collect::<Vec<_>>();
+        decisions.sort_by_key(|decision| decision.original_size);
+        assert_eq!(decisions.len(), compressions.len());
+        for (i, (decision, compression)) in decisions.iter().zip(compressions.iter()).enumerate() {
+            assert_eq!(decision.requested, *compression);
+            assert_eq!(decision.chosen, *compression);
+            let entry = hybrid.store().get(&(i as u64)).await.unwrap().unwrap();
+            assert_eq!(entry.to_arc().1.as_ref(), &vec![i as u8; (i + 1) * KB]);
+        }
+
+        hybrid.store().close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_promotion_policy() {
+        for (policy, promoted_on) in [
+            (PromotionPolicy::Always, Some(1)),
+            (PromotionPolicy::OnSecondAccess, Some(2)),
+            (PromotionPolicy::Never, None),
+        ] {
+            let dir = tempfile::tempdir().unwrap();
+
+            let hybrid: HybridCache<u64, Vec<u8>> = HybridCacheBuilder::new()
+                .memory(4)
+                .with_shards(1)
+                .with_eviction_config(FifoConfig {})
+                .storage()
+                .with_device_config(
+                    FsDeviceConfigBuilder::new(dir.path())
+                        .with_capacity(16 * MB)
+                        .with_file_size(MB)
+                        .with_align(4 * KB)
+                        .with_io_size(16 * KB)
+                        .build(),
+                )
+                .with_promotion_policy(policy)
+                .build()
+                .await
+                .unwrap();
+
+            // The entry lives only in the storage tier.
+            hybrid.store().insert(0, vec![0; KB]).await.unwrap();
+            hybrid.store().barrier().await.unwrap();
+
+            for access in 1..=3 {
+                let entry = hybrid.get(&0).await.unwrap().unwrap();
+                assert_eq!(entry.value(), &vec![0; KB]);
+                drop(entry);
+                let promoted = promoted_on.is_some_and(|promoted_on| access >= promoted_on);
+                assert_eq!(
+                    hybrid.cache().contains(&0),
+                    promoted,
+                    "policy: {policy:?}, access: {access}"
+                );
+            }
+            let expected = if promoted_on.is_some() { 1 } else { 0 };
+            assert_eq!(hybrid.metrics().promoted.load(Ordering::Relaxed), expected);
+            assert_eq!(hybrid.cache().usage(), expected);
+
+            hybrid.store().close().await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_metrics_json() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let hybrid: HybridCache<u64, Vec<u8>> = HybridCacheBuilder::new()
+            .memory(4)
+            .with_shards(1)
+            .with_eviction_config(FifoConfig {})
+            .storage()
+            .with_device_config(
+                FsDeviceConfigBuilder::new(dir.path())
+                    .with_capacity(16 * MB)
+                    .with_file_size(MB)
+                    .with_align(4 * KB)
+                    .with_io_size(16 * KB)
+                    .build(),
+            )
+            .build()
+            .await
+            .unwrap();
+
+        hybrid.store().insert(0, vec![0; KB]).await.unwrap();
+        hybrid.store().barrier().await.unwrap();
+        hybrid.insert(1, vec![1; KB]);
+
+        assert!(hybrid.get(&1).await.unwrap().is_some());
+        assert!(hybrid.get(&0).await.unwrap().is_some());
+        assert!(hybrid.get(&2).await.unwrap().is_none());
+
+        let json = hybrid.metrics_json();
+        assert!(json.starts_with(r#"{"memory":{"capacity":4,"#));
+        for field in [
+            r#""hit":1,"#,
+            r#""miss":2,"#,
+            r#""hybrid":{"promoted":1,"demoted":0,"memory_hit":1,"memory_miss":2,"storage_hit":1,"storage_miss":1,"#,
+            r#""storage":{"foyer_storage_op_duration":[{"labels":{"foyer":"foyer","op":"insert","extra":"inserted"},"#,
+            r#""labels":{"foyer":"foyer","op":"get","extra":"hit"},"count":1,"#,
+            r#""foyer_storage_total_bytes":[{"labels":{"foyer":"foyer"},"value":"#,
+        ] {
+            assert!(json.contains(field), "{field} is missing in {json}");
+        }
+        let flush = hybrid.store().metrics().unwrap().op_bytes_flush.get();
+        assert!(flush >= KB as u64);
+        let field = format!(r#"{{"labels":{{"foyer":"foyer","op":"flush","extra":""}},"value":{flush}}}"#);
+        assert!(json.contains(&field), "{field} is missing in {json}");
+        // The braces of the objects are balanced.
+        assert_eq!(json.matches('{').count(), json.matches('}').count());
+        assert!(json.ends_with("}}"));
+
+        hybrid.store().close().await.unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_update() {
+        const TASKS: u64 = 16;
+        const UPDATES: u64 = 32;
+
+        let dir = tempfile::tempdir().unwrap();
+
+        // With a single entry in the memory tier, the counters evict each other and are read from the storage tier.
+        let hybrid: HybridCache<u64, u64> = HybridCacheBuilder::new()
+            .memory(1)
+            .with_shards(1)
+            .with_eviction_config(FifoConfig {})
+            .storage()
+            .with_device_config(
+                FsDeviceConfigBuilder::new(dir.path())
+                    .with_capacity(16 * MB)
+                    .with_file_size(MB)
+                    .with_align(4 * KB)
+                    .with_io_size(16 * KB)
+                    .build(),
+            )
+            .build()
+            .await
+            .unwrap();
+        let hybrid = Arc::new(hybrid);
+
+        let handles = (0..TASKS)
+            .map(|task| {
+                let hybrid = hybrid.clone();
+                tokio::spawn(async move {
+                    for _ in 0..UPDATES {
+                        let key = task % 2;
+                        let entry = hybrid.update(key, |v| Some(v.unwrap_or(0) + 1)).await.unwrap().unwrap();
+                        assert!(*entry.value() >= 1);
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        for handle in handles {
+            handle.await.unwrap();
+        }
+
+        for key in 0..2 {
+            assert_eq!(*hybrid.get(&key).await.unwrap().unwrap().value(), TASKS / 2 * UPDATES);
+            let entry = hybrid.store().get(&key).await.unwrap().unwrap();
+            assert_eq!(*entry.to_arc().1, TASKS / 2 * UPDATES);
+        }
+
+        // Returning `None` removes the entry from both tiers.
+        assert!(hybrid.update(0, |_| None).await.unwrap().is_none());
+        assert!(hybrid.get(&0).await.unwrap().is_none());
+        assert!(!hybrid.store().exists(&0).unwrap());
+
+        hybrid.store().close().await.unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_get_or_insert_with() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let hybrid: HybridCache<u64, Vec<u8>> = HybridCacheBuilder::new()
+            .memory(16)
+            .with_shards(1)
+            .with_eviction_config(FifoConfig {})
+            .storage()
+            .with_device_config(
+                FsDeviceConfigBuilder::new(dir.path())
+                    .with_capacity(16 * MB)
+                    .with_file_size(MB)
+                    .with_align(4 * KB)
+                    .with_io_size(16 * KB)
+                    .build(),
+            )
+            .build()
+            .await
+            .unwrap();
+        let hybrid = Arc::new(hybrid);
+
+        let computations = Arc::new(AtomicUsize::new(0));
+        let compute = |computations: Arc<AtomicUsize>| {
+            move || async move {
+                computations.fetch_add(1, Ordering::SeqCst);
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                Ok(vec![1; KB])
+            }
+        };
+
+        // Concurrent callers share a single computation.
+        let handles = (0..8)
+            .map(|_| {
+                let hybrid = hybrid.clone();
+                let f = compute(computations.clone());
+                tokio::spawn(async move { hybrid.get_or_insert_with(1, f).await })
+            })
+            .collect::<Vec<_>>();
+        for handle in handles {
+            assert_eq!(handle.await.unwrap().unwrap().value(), &vec![1; KB]);
+        }
+        assert_eq!(computations.load(Ordering::SeqCst), 1);
+        // The value is written to both tiers.
+        assert!(hybrid.cache().contains(&1));
+        assert!(hybrid.store().exists(&1).unwrap());
+
+        // Cancelling the caller that starts the computation doesn't cancel it for the others.
+        let first = tokio::spawn({
+            let hybrid = hybrid.clone();
+            let f = compute(computations.clone());
+            async move { hybrid.get_or_insert_with(2, f).await }
+        });
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        let second = tokio::spawn({
+            let hybrid = hybrid.clone();
+            let f = compute(computations.clone());
+            async move { hybrid.get_or_insert_with(2, f).await }
+        });
+        first.abort();
+        assert_eq!(second.await.unwrap().unwrap().value(), &vec![1; KB]);
+        assert_eq!(computations.load(Ordering::SeqCst), 2);
+
+        // A panicked computation doesn't poison the key.
+        let res = hybrid
+            .get_or_insert_with(3, || async move {
+                if true {
+                    panic!("injected panic");
+                }
+                Ok(vec![3; KB])
+            })
+            .await;
+        assert!(res.is_err());
+        let res = hybrid
+            .get_or_insert_with(3, || async move { Err::<Vec<u8>, _>(anyhow!("injected error")) })
+            .await;
+        assert!(res.is_err());
+        let entry = hybrid
+            .get_or_insert_with(3, || async move { Ok(vec![3; KB]) })
+            .await
+            .unwrap();
+        assert_eq!(entry.value(), &vec![3; KB]);
+
+        hybrid.store().close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_persistence() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("memory.snapshot");
+
+        let open = || async {
+            HybridCacheBuilder::new()
+                .memory(64)
+                .with_shards(1)
+                .with_eviction_config(FifoConfig {})
+                .storage()
+                .with_device_config(
+                    FsDeviceConfigBuilder::new(dir.path())
+                        .with_capacity(16 * MB)
+                        .with_file_size(MB)
+                        .with_align(4 * KB)
+                        .with_io_size(16 * KB)
+                        .build(),
+                )
+                .with_persistence(HybridCachePersistence::Snapshot { path: path.clone() })
+                .build()
+                .await
+                .unwrap()
+        };
+
+        let hybrid: HybridCache<u64, Vec<u8>> = open().await;
+        for i in 0..64 {
+            hybrid.insert(i, vec![i as u8; KB]);
+        }
+        // Inserts stay in memory, nothing is flushed to the storage tier.
+        assert!(!hybrid.store().exists(&0).unwrap());
+        assert!(!path.exists());
+
+        hybrid.shutdown().await.unwrap();
+        assert!(path.exists());
+        drop(hybrid);
+
+        let hybrid: HybridCache<u64, Vec<u8>> = open().await;
+        assert_eq!(hybrid.cache().usage(), 64);
+        for i in 0..64 {
+            assert_eq!(hybrid.cache().get(&i).unwrap().value(), &vec![i as u8; KB]);
+        }
+        // The snapshot is consumed on load.
+        assert!(!path.exists());
+        hybrid.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_touch() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let executor = Arc::new(SlowExecutor::default());
+
+        let hybrid: HybridCache<u64, Vec<u8>> = HybridCacheBuilder::new()
+            .memory(4)
+            .with_shards(1)
+            .with_eviction_config(LruConfig {
+                high_priority_pool_ratio: 0.0,
+            })
+            .storage()
+            .with_device_config(
+                FsDeviceConfigBuilder::new(dir.path())
+                    .with_capacity(16 * MB)
+                    .with_file_size(MB)
+                    .with_align(4 * KB)
+                    .with_io_size(16 * KB)
+                    .with_io_executor(IoExecutor::custom(executor.clone()))
+                    .build(),
+            )
+            .build()
+            .await
+            .unwrap();
+
+        for i in 0..4 {
+            hybrid.insert(i, vec![i as u8; KB]);
+        }
+
+        // Touching the least recently used key spares it from the next eviction.
+        assert!(hybrid.touch(&0).unwrap());
+        hybrid.insert(4, vec![4; KB]);
+        assert!(hybrid.cache().contains(&0));
+        assert!(!hybrid.cache().contains(&1));
+
+        // Wait for the evicted entry to be demoted to the storage tier.
+        while !hybrid.store().exists(&1).unwrap() {
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+        hybrid.store().barrier().await.unwrap();
+
+        // Touching an entry in the storage tier reads nothing from the device.
+        let jobs = executor.jobs.load(Ordering::SeqCst);
+        assert!(hybrid.touch(&1).unwrap());
+        assert!(!hybrid.touch(&100).unwrap());
+        assert_eq!(executor.jobs.load(Ordering::SeqCst), jobs);
+        assert!(!hybrid.cache().contains(&1));
+        assert_eq!(hybrid.metrics().promoted.load(Ordering::Relaxed), 0);
+
+        assert!(hybrid.touch_and_promote(&1).await.unwrap());
+        assert!(hybrid.cache().contains(&1));
+        assert_eq!(hybrid.metrics().promoted.load(Ordering::Relaxed), 1);
+        assert!(!hybrid.touch_and_promote(&100).await.unwrap());
+
+        hybrid.store().close().await.unwrap();
+    }
 }
@@ -17,5 +17,8 @@ use foyer_storage as storage;
 
 mod hybrid;
 
+#[cfg(feature = "testing")]
+pub mod testing;
+
 mod prelude;
 pub use prelude::*;
@@ -16,16 +16,19 @@ use crate::memory;
 use crate::storage;
 
 use ahash::RandomState;
-pub use memory::{CacheContext, EvictionConfig, FifoConfig, LfuConfig, LruConfig, S3FifoConfig};
+pub use memory::{
+    CacheContext, CacheFull, CachePriority, EvictionConfig, FifoConfig, InsertMode, LfuConfig, LruConfig, S3FifoConfig,
+};
 pub use storage::{
-    AdmissionContext, AdmissionPolicy, Compression, ExistReinsertionPolicy, FsDeviceConfig, FsDeviceConfigBuilder,
-    RatedTicketAdmissionPolicy, RatedTicketReinsertionPolicy, ReinsertionContext, ReinsertionPolicy,
-    RuntimeConfigBuilder, Storage, StorageExt,
+    AdmissionContext, AdmissionDecision, AdmissionObserver, AdmissionPolicy, ChecksumAlgorithm, Compression,
+    ExistReinsertionPolicy, FsDeviceConfig, FsDeviceConfigBuilder, IoExecutor, KeyPrefix, RatedTicketAdmissionPolicy,
+    RatedTicketReinsertionPolicy, ReinsertionContext, ReinsertionPolicy, RuntimeConfigBuilder, Storage, StorageExt,
 };
 
 pub type Cache<K, V, S = RandomState> = memory::Cache<K, V, memory::DefaultCacheEventListener<K, V>, S>;
 pub type CacheBuilder<K, V, S> = memory::CacheBuilder<K, V, memory::DefaultCacheEventListener<K, V>, S>;
 
 pub use crate::hybrid::{
-    HybridCache, HybridCacheBuilder, HybridCacheBuilderPhaseMemory, HybridCacheBuilderPhaseStorage,
+    HybridCache, HybridCacheBuilder, HybridCacheBuilderPhaseMemory, HybridCacheBuilderPhaseStorage, HybridCacheMetrics,
+    HybridCachePersistence, HybridCacheStats, KeyLocation, PromotionPolicy,
 };
@@ -0,0 +1,363 @@
+//  Copyright 2024 Foyer Project Authors
+//
+//  Licensed under the Apache License, Version 2.0 (the "License");
+//  you may not use this file except in compliance with the License.
+//  You may obtain a copy of the License at
+//
+//  http://www.apache.org/licenses/LICENSE-2.0
+//
+//  Unless required by applicable law or agreed to in writing, software
+//  distributed under the License is distributed on an "AS IS" BASIS,
+//  WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//  See the License for the specific language governing permissions and
+//  limitations under the License.
+
+//! Utilities for tuning and regression testing with synthetic workloads.
+//!
+//! [`WorkloadGenerator`] generates reads and writes with configurable key distribution, value size distribution and
+//! read/write mix, drives a [`WorkloadTarget`] (e.g. an in-memory cache or a hybrid cache) with them, and reports the
+//! hit ratio and the latencies.
+
+use std::{
+    future::Future,
+    hash::BuildHasher,
+    time::{Duration, Instant},
+};
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::{memory, memory::CacheEventListener, HybridCache};
+
+/// Distribution of the generated keys.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum KeyDistribution {
+    /// Keys are uniformly distributed in `[0, keys)`.
+    Uniform { keys: u64 },
+    /// Keys are distributed in `[0, keys)` following Zipf's law, key `0` is the hottest.
+    ///
+    /// `exponent` must be positive, larger exponent means more skewed.
+    Zipf { keys: u64, exponent: f64 },
+}
+
+/// Distribution of the generated value sizes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValueSizeDistribution {
+    /// All values are of the same size.
+    Fixed(usize),
+    /// Value sizes are uniformly distributed in `[min, max]`.
+    Uniform { min: usize, max: usize },
+}
+
+/// Operation generated by [`WorkloadGenerator`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operation {
+    Get { key: u64 },
+    Insert { key: u64, value_size: usize },
+}
+
+/// Cache that can be driven by [`WorkloadGenerator`].
+pub trait WorkloadTarget: Send + Sync {
+    /// Get the value of the key, return `true` on hit.
+    fn get(&self, key: u64) -> impl Future<Output = anyhow::Result<bool>> + Send;
+
+    /// Insert the value of the key.
+    fn insert(&self, key: u64, value: Vec<u8>);
+}
+
+impl<L, S> WorkloadTarget for memory::Cache<u64, Vec<u8>, L, S>
+where
+    L: CacheEventListener<u64, Vec<u8>>,
+    S: BuildHasher + Send + Sync + 'static,
+{
+    async fn get(&self, key: u64) -> anyhow::Result<bool> {
+        Ok(memory::Cache::get(self, &key).is_some())
+    }
+
+    fn insert(&self, key: u64, value: Vec<u8>) {
+        memory::Cache::insert(self, key, value);
+    }
+}
+
+impl<S> WorkloadTarget for HybridCache<u64, Vec<u8>, S>
+where
+    S: BuildHasher + Send + Sync + 'static,
+{
+    async fn get(&self, key: u64) -> anyhow::Result<bool> {
+        Ok(HybridCache::get(self, &key).await?.is_some())
+    }
+
+    fn insert(&self, key: u64, value: Vec<u8>) {
+        HybridCache::insert(self, key, value);
+    }
+}
+
+/// Latency statistics of an operation kind.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LatencyStats {
+    pub mean: Duration,
+    pub p50: Duration,
+    pub p90: Duration,
+    pub p99: Duration,
+    pub max: Duration,
+}
+
+impl LatencyStats {
+    fn new(mut latencies: Vec<Duration>) -> Self {
+        if latencies.is_empty() {
+            return Self::default();
+        }
+        latencies.sort_unstable();
+        let percentile = |p: f64| latencies[((latencies.len() - 1) as f64 * p).round() as usize];
+        Self {
+            mean: latencies.iter().sum::<Duration>() / latencies.len() as u32,
+            p50: percentile(0.5),
+            p90: percentile(0.9),
+            p99: percentile(0.99),
+            max: *latencies.last().unwrap(),
+        }
+    }
+}
+
+/// Report of a workload run by [`WorkloadGenerator::drive`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WorkloadReport {
+    /// Count of the gets.
+    pub gets: usize,
+    /// Count of the gets that hit.
+    pub hits: usize,
+    /// Count of the inserts, including the fills on get misses.
+    pub inserts: usize,
+    /// Latency of the gets.
+    pub get_latency: LatencyStats,
+    /// Latency of the inserts.
+    pub insert_latency: LatencyStats,
+}
+
+impl WorkloadReport {
+    /// Ratio of the gets that hit, `0` if there is no get.
+    pub fn hit_ratio(&self) -> f64 {
+        if self.gets == 0 {
+            return 0.0;
+        }
+        self.hits as f64 / self.gets as f64
+    }
+}
+
+/// Synthetic workload generator.
+///
+/// The generated workload is deterministic with the same config and seed.
+#[derive(Debug, Clone)]
+pub struct WorkloadGenerator {
+    key_distribution: KeyDistribution,
+    value_size_distribution: ValueSizeDistribution,
+    read_ratio: f64,
+    fill_on_miss: bool,
+
+    /// Cumulative weights of the zipf keys, the key is drawn by searching a uniform sample in them.
+    zipf: Option<Vec<f64>>,
+    rng: StdRng,
+}
+
+impl WorkloadGenerator {
+    const DEFAULT_VALUE_SIZE: usize = 1024;
+    const DEFAULT_READ_RATIO: f64 = 0.9;
+    const DEFAULT_SEED: u64 = 0;
+
+    pub fn new(key_distribution: KeyDistribution) -> Self {
+        let zipf = match key_distribution {
+            KeyDistribution::Uniform { keys } => {
+                assert!(keys > 0, "uniform key distribution requires at least 1 key");
+                None
+            }
+            KeyDistribution::Zipf { keys, exponent } => {
+                assert!(keys > 0, "zipf key distribution requires at least 1 key");
+                assert!(exponent > 0.0, "zipf exponent must be > 0, given: {exponent}");
+                let weights = (1..=keys)
+                    .scan(0.0, |sum, k| {
+                        *sum += 1.0 / (k as f64).powf(exponent);
+                        Some(*sum)
+                    })
+                    .collect();
+                Some(weights)
+            }
+        };
+        Self {
+            key_distribution,
+            value_size_distribution: ValueSizeDistribution::Fixed(Self::DEFAULT_VALUE_SIZE),
+            read_ratio: Self::DEFAULT_READ_RATIO,
+            fill_on_miss: true,
+            zipf,
+            rng: StdRng::seed_from_u64(Self::DEFAULT_SEED),
+        }
+    }
+
+    /// Set the value size distribution.
+    ///
+    /// Default: fixed 1 KiB.
+    pub fn with_value_size_distribution(mut self, value_size_distribution: ValueSizeDistribution) -> Self {
+        if let ValueSizeDistribution::Uniform { min, max } = value_size_distribution {
+            assert!(min <= max, "min value size ({min}) must be <= max value size ({max})");
+        }
+        self.value_size_distribution = value_size_distribution;
+        self
+    }
+
+    /// Set the ratio of the gets among the generated operations, the rest are inserts.
+    ///
+    /// Default: `0.9`.
+    pub fn with_read_ratio(mut self, read_ratio: f64) -> Self {
+        assert!(
+            (0.0..=1.0).contains(&read_ratio),
+            "read ratio must be in [0, 1], given: {read_ratio}"
+        );
+        self.read_ratio = read_ratio;
+        self
+    }
+
+    /// Decide if the missed key of a get is inserted, like a read-through cache.
+    ///
+    /// Default: `true`.
+    pub fn with_fill_on_miss(mut self, fill_on_miss: bool) -> Self {
+        self.fill_on_miss = fill_on_miss;
+        self
+    }
+
+    /// Set the seed of the random generator.
+    ///
+    /// Default: `0`.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.rng = StdRng::seed_from_u64(seed);
+        self
+    }
+
+    /// Generate the next key.
+    pub fn next_key(&mut self) -> u64 {
+        match self.key_distribution {
+            KeyDistribution::Uniform { keys } => self.rng.gen_range(0..keys),
+            KeyDistribution::Zipf { keys, .. } => {
+                let weights = self.zipf.as_ref().unwrap();
+                let x = self.rng.gen::<f64>() * weights[keys as usize - 1];
+                weights.partition_point(|&sum| sum < x) as u64
+            }
+        }
+    }
+
+    /// Generate the next value size.
+    pub fn next_value_size(&mut self) -> usize {
+        match self.value_size_distribution {
+            ValueSizeDistribution::Fixed(size) => size,
+            ValueSizeDistribution::Uniform { min, max } => self.rng.gen_range(min..=max),
+        }
+    }
+
+    /// Generate the next operation.
+    pub fn next_operation(&mut self) -> Operation {
+        let key = self.next_key();
+        if self.rng.gen_bool(self.read_ratio) {
+            Operation::Get { key }
+        } else {
+            let value_size = self.next_value_size();
+            Operation::Insert { key, value_size }
+        }
+    }
+
+    /// Drive the target with `operations` generated operations and report the hit ratio and the latencies.
+    pub async fn drive<T>(&mut self, target: &T, operations: usize) -> anyhow::Result<WorkloadReport>
+    where
+        T: WorkloadTarget,
+    {
+        let mut report = WorkloadReport::default();
+        let mut get_latencies = Vec::with_capacity(operations);
+        let mut insert_latencies = Vec::with_capacity(operations);
+
+        let mut insert = |target: &T, key: u64, value_size: usize, report: &mut WorkloadReport| {
+            let now = Instant::now();
+            target.insert(key, vec![key as u8; value_size]);
+            insert_latencies.push(now.elapsed());
+            report.inserts += 1;
+        };
+
+        for _ in 0..operations {
+            match self.next_operation() {
+                Operation::Get { key } => {
+                    let now = Instant::now();
+                    let hit = target.get(key).await?;
+                    get_latencies.push(now.elapsed());
+                    report.gets += 1;
+                    if hit {
+                        report.hits += 1;
+                    } else if self.fill_on_miss {
+                        let value_size = self.next_value_size();
+                        insert(target, key, value_size, &mut report);
+                    }
+                }
+                Operation::Insert { key, value_size } => insert(target, key, value_size, &mut report),
+            }
+        }
+
+        report.get_latency = LatencyStats::new(get_latencies);
+        report.insert_latency = LatencyStats::new(insert_latencies);
+
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CacheBuilder, LruConfig};
+
+    const KEYS: u64 = 10_000;
+    const CAPACITY: usize = 100;
+    const OPERATIONS: usize = 100_000;
+
+    fn cache() -> crate::Cache<u64, Vec<u8>> {
+        CacheBuilder::new(CAPACITY)
+            .with_shards(1)
+            .with_eviction_config(LruConfig {
+                high_priority_pool_ratio: 0.0,
+            })
+            .build()
+    }
+
+    #[tokio::test]
+    async fn test_workload_generator_zipf() {
+        let mut generator = WorkloadGenerator::new(KeyDistribution::Zipf {
+            keys: KEYS,
+            exponent: 1.0,
+        })
+        .with_value_size_distribution(ValueSizeDistribution::Uniform { min: 16, max: 64 })
+        .with_read_ratio(1.0);
+
+        let report = generator.drive(&cache(), OPERATIONS).await.unwrap();
+        assert_eq!(report.gets, OPERATIONS);
+        assert_eq!(report.inserts, report.gets - report.hits);
+        assert!(report.get_latency.p50 <= report.get_latency.p99);
+        assert!(report.get_latency.p99 <= report.get_latency.max);
+
+        // A 1% LRU cache hits roughly 40% of a Zipf(1.0) workload.
+        let zipf = report.hit_ratio();
+        assert!((0.25..0.55).contains(&zipf), "hit ratio: {zipf}");
+
+        // The same cache hits roughly 1% of a uniform workload.
+        let mut generator = WorkloadGenerator::new(KeyDistribution::Uniform { keys: KEYS }).with_read_ratio(1.0);
+        let report = generator.drive(&cache(), OPERATIONS).await.unwrap();
+        let uniform = report.hit_ratio();
+        assert!(uniform < 0.05, "hit ratio: {uniform}");
+    }
+
+    #[test]
+    fn test_workload_generator_deterministic() {
+        let generate = |seed| {
+            let mut generator = WorkloadGenerator::new(KeyDistribution::Zipf {
+                keys: KEYS,
+                exponent: 1.2,
+            })
+            .with_read_ratio(0.5)
+            .with_seed(seed);
+            (0..100).map(|_| generator.next_operation()).collect::<Vec<_>>()
+        };
+        assert_eq!(generate(42), generate(42));
+        assert_ne!(generate(42), generate(43));
+    }
+}